@@ -0,0 +1,136 @@
+// Measure building and laying out the box trees of deep and wide synthetic
+// documents, from about ten thousand to a hundred thousand nodes.
+// Run with `cargo bench`, or `cargo bench --features parallel` to also
+// compare laying out on one thread and on several threads.
+
+use std::collections::HashMap;
+
+use criterion::{
+    BenchmarkId,
+    Criterion,
+    SamplingMode,
+    criterion_group,
+    criterion_main,
+};
+use simple_browser_rs::{
+    css_parser::CSSParser,
+    dom::{Node, NodeId},
+    html_parser::HTMLParser,
+    layout::{Dimensions, layout_tree_with_metrics},
+    style::{MediaContext, apply},
+    text::MonospaceMetrics,
+};
+
+// The number of children of each section. Sections nest as deeply as each
+// document size asks for.
+const WIDTH: usize = 6;
+const DEPTHS: [usize; 2] = [4, 5];
+
+// Build nested sections, whose innermost ones hold paragraphs of text.
+fn section(html: &mut String, depth: usize) {
+    html.push_str("<section>");
+    for i in 0..WIDTH {
+        if depth == 0 {
+            html.push_str("<p>");
+            for word in 0..40 {
+                html.push_str(&format!("word{} ", word * i));
+            }
+            html.push_str("</p>");
+        } else {
+            section(html, depth - 1);
+        }
+    }
+    html.push_str("</section>");
+}
+
+fn document(depth: usize) -> Node {
+    let mut html = String::new();
+    section(&mut html, depth);
+    HTMLParser::parse(html)
+}
+
+fn count_nodes(node: &Node) -> usize {
+    1 + node.children.iter().map(count_nodes).sum::<usize>()
+}
+
+fn viewport(width: f32) -> Dimensions {
+    let mut viewport = Dimensions::default();
+    viewport.content.width = width;
+    viewport.content.height = 600.0;
+    viewport
+}
+
+fn bench_layout(c: &mut Criterion) {
+    let stylesheets = [CSSParser::parse(
+        "section { display: block; padding: 2px; margin: 4px 0; } \
+         p { margin: 8px 0; }"
+            .to_string(),
+    )];
+    // No images, so that only text and boxes are laid out.
+    let images: HashMap<NodeId, (f32, f32)> = HashMap::new();
+
+    let mut group = c.benchmark_group("layout");
+    // A run of the largest document takes long, so only a few are taken.
+    group.sampling_mode(SamplingMode::Flat).sample_size(10);
+    for depth in DEPTHS {
+        let root = document(depth);
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let nodes = count_nodes(&root);
+
+        // Building the box tree and laying it out for the first time.
+        group.bench_with_input(
+            BenchmarkId::new("build_and_layout", nodes),
+            &styled,
+            |b, styled| {
+                b.iter(|| {
+                    layout_tree_with_metrics(
+                        styled,
+                        viewport(800.0),
+                        &MonospaceMetrics,
+                        &images,
+                    )
+                })
+            },
+        );
+
+        // Laying out a built tree again at another width, which no box can
+        // skip.
+        let mut layout_root = layout_tree_with_metrics(
+            &styled,
+            viewport(800.0),
+            &MonospaceMetrics,
+            &images,
+        );
+        let mut width = 800.0;
+        group.bench_function(BenchmarkId::new("relayout", nodes), |b| {
+            b.iter(|| {
+                width = if width == 800.0 { 640.0 } else { 800.0 };
+                layout_root.relayout_with_metrics(
+                    viewport(width),
+                    &MonospaceMetrics,
+                    &images,
+                );
+            })
+        });
+
+        #[cfg(feature = "parallel")]
+        group.bench_with_input(
+            BenchmarkId::new("build_and_layout_parallel", nodes),
+            &styled,
+            |b, styled| {
+                b.iter(|| {
+                    simple_browser_rs::layout::layout_tree_parallel(
+                        styled,
+                        viewport(800.0),
+                        &MonospaceMetrics,
+                        &images,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_layout);
+criterion_main!(benches);
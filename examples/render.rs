@@ -0,0 +1,85 @@
+// Render an HTML document and a stylesheet to a PNG image of the viewport.
+// Run with `cargo run --example render --features png -- page.html
+// style.css out.png`. With the `fonts` feature, a font file can follow, which
+// all text is drawn in. The image is rendered for a high-resolution display
+// if `DEVICE_PIXEL_RATIO` is set, such as to 2 for an image twice as large.
+
+use std::{env, fs, fs::File, io::BufWriter};
+
+#[cfg(feature = "fonts")]
+use simple_browser_rs::{
+    computed::FontStyle,
+    layout::{NoImages, layout_tree_with_metrics},
+    painting::paint_with_fonts,
+};
+use simple_browser_rs::{
+    css_parser::CSSParser,
+    html_parser::HTMLParser,
+    layout::Dimensions,
+    painting::Images,
+    style::{MediaContext, apply},
+};
+#[cfg(not(feature = "fonts"))]
+use simple_browser_rs::{layout::layout_tree, painting::paint};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (html, css, output) = match args.as_slice() {
+        [_, html, css, output, ..] => (html, css, output),
+        _ => panic!("Usage: render <html> <css> <png> [font]"),
+    };
+    let read = |path: &str| {
+        fs::read_to_string(path).unwrap_or_else(|error| {
+            panic!("Failed to read {}: {}", path, error)
+        })
+    };
+
+    let root = HTMLParser::parse(read(html));
+    let stylesheets = [CSSParser::parse(read(css))];
+    let device_pixel_ratio =
+        env::var("DEVICE_PIXEL_RATIO").map_or(1.0, |ratio| {
+            ratio.parse().unwrap_or_else(|error| {
+                panic!("Invalid DEVICE_PIXEL_RATIO {}: {}", ratio, error)
+            })
+        });
+    let media = MediaContext {
+        device_pixel_ratio,
+        ..MediaContext::default()
+    };
+    let styled = apply(&root, &stylesheets, &media);
+
+    let mut viewport = Dimensions::default();
+    viewport.content.width = media.viewport_width;
+    viewport.content.height = media.viewport_height;
+    #[cfg(not(feature = "fonts"))]
+    let canvas = paint(
+        &layout_tree(&styled, viewport),
+        viewport.content,
+        &Images::new(),
+        device_pixel_ratio,
+    );
+    #[cfg(feature = "fonts")]
+    let canvas = {
+        let mut fonts = simple_browser_rs::font::Fonts::new();
+        if let Some(path) = args.get(4) {
+            let data = fs::read(path).unwrap_or_else(|error| {
+                panic!("Failed to read {}: {}", path, error)
+            });
+            fonts.add_font("serif", 400, FontStyle::Normal, data);
+        }
+        let layout_root =
+            layout_tree_with_metrics(&styled, viewport, &fonts, &NoImages);
+        paint_with_fonts(
+            &layout_root,
+            viewport.content,
+            &Images::new(),
+            device_pixel_ratio,
+            &fonts,
+        )
+    };
+
+    let file = File::create(output).unwrap_or_else(|error| {
+        panic!("Failed to create {}: {}", output, error)
+    });
+    canvas.write_png(BufWriter::new(file)).unwrap();
+}
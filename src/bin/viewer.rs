@@ -0,0 +1,572 @@
+// Open an HTML document with a stylesheet in a window. The page is laid out
+// again whenever the window is resized, and scrolls with the mouse wheel and
+// the keyboard. Only the parts of the window that look different are
+// painted again after scrolling within the page. The mouse wheel scrolls the
+// innermost scroll container under the cursor that can still scroll, and the
+// page otherwise. The page is laid out in CSS pixels and painted in the
+// pixels of the display, of which there are more on a high-resolution one.
+// Tab and Shift+Tab move the focus through links and form controls, which
+// are drawn with a focus ring and scrolled into view.
+// With the `wgpu` feature, the whole window is drawn on the GPU in each
+// frame instead. Run with `cargo run --features viewer --bin viewer --
+// page.html style.css`, optionally followed by a font file that all text is
+// drawn in.
+
+#[cfg(not(feature = "wgpu"))]
+use std::num::NonZeroU32;
+use std::{collections::HashMap, env, fs, sync::Arc};
+
+#[cfg(not(feature = "wgpu"))]
+use simple_browser_rs::painting::{Canvas, damage, repaint_with_fonts};
+use simple_browser_rs::{
+    computed::FontStyle,
+    css_parser::{CSSParser, StyleSheet},
+    document::Document,
+    dom::{Node, NodeId},
+    font::Fonts,
+    html_parser::HTMLParser,
+    layout::{Dimensions, LayoutBox, Rectangle, layout_tree_with_metrics},
+    painting::{DisplayList, Images, build_display_list, hit_test},
+    style::{ElementStateProvider, MediaContext, StyleCache, restyle},
+};
+#[cfg(feature = "wgpu")]
+use simple_browser_rs::{gpu::GpuRenderer, painting::device_transform};
+#[cfg(not(feature = "wgpu"))]
+use softbuffer::{Context, Rect, Surface};
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, KeyEvent, MouseScrollDelta, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::{Window, WindowId},
+};
+
+// How far a notch of the mouse wheel or an arrow key scrolls.
+const LINE_HEIGHT: f32 = 40.0;
+
+// A document with its stylesheets, as it was last laid out.
+struct Page {
+    document: Document,
+    stylesheets: Vec<StyleSheet>,
+    fonts: Fonts,
+    images: Images,
+    display_list: DisplayList,
+    // The height of the laid out page, which is how far it scrolls.
+    height: f32,
+    // The size of the viewport that the page was laid out in, in CSS pixels.
+    viewport: (f32, f32),
+    // How many pixels of the display a CSS pixel covers.
+    device_pixel_ratio: f32,
+    // How far the scroll containers of the page are scrolled, which is kept
+    // when the page is laid out again.
+    scroll_offsets: HashMap<NodeId, (f32, f32)>,
+    // The focused element, with its border box as it was laid out.
+    focused: Option<(NodeId, Rectangle)>,
+}
+
+// The states of the elements of a page, where only the focus changes.
+struct PageState(Option<NodeId>);
+
+impl ElementStateProvider for PageState {
+    fn is_focused(&self, node: &Node) -> bool {
+        self.0 == Some(node.node_id)
+    }
+}
+
+impl Page {
+    // Style the page for a viewport of a size in device pixels, which media
+    // queries match against, and lay it out in it.
+    fn layout(&mut self, width: f32, height: f32, device_pixel_ratio: f32) {
+        self.device_pixel_ratio = device_pixel_ratio;
+        self.viewport =
+            (width / device_pixel_ratio, height / device_pixel_ratio);
+        self.scroll_at((0.0, 0.0), 0.0, 0.0);
+    }
+
+    // Lay the page out again, and scroll the innermost scroll container
+    // under a point that moves by a distance. Returns whether one moved.
+    fn scroll_at(&mut self, point: (f32, f32), dx: f32, dy: f32) -> bool {
+        let (width, height) = self.viewport;
+        let media = MediaContext {
+            viewport_width: width,
+            viewport_height: height,
+            device_pixel_ratio: self.device_pixel_ratio,
+            ..MediaContext::default()
+        };
+        let focused = self.focused.map(|(node, _)| node);
+        let styled = restyle(
+            &self.document,
+            &self.stylesheets,
+            &media,
+            &mut StyleCache::default(),
+            &[],
+            &PageState(focused),
+        );
+        let mut viewport = Dimensions::default();
+        viewport.content.width = width;
+        viewport.content.height = height;
+        let mut layout_root = layout_tree_with_metrics(
+            &styled,
+            viewport,
+            &self.fonts,
+            &self.images,
+        );
+        for (&node, &(x, y)) in &self.scroll_offsets {
+            layout_root.scroll_by(node, x, y);
+        }
+
+        let mut scrolled = false;
+        if dx != 0.0 || dy != 0.0 {
+            for node in hit_test(&layout_root, point) {
+                if layout_root.scroll_by(node, dx, dy) {
+                    if let Some(offset) = scroll_offset(&layout_root, node) {
+                        self.scroll_offsets.insert(node, offset);
+                    }
+                    scrolled = true;
+                    break;
+                }
+            }
+        }
+        self.height = layout_root.dimensions().margin_box().height;
+        self.display_list = build_display_list(&layout_root, &self.images);
+        self.focused = focused.map(|node| {
+            (node, border_box(&layout_root, node).unwrap_or_default())
+        });
+        scrolled
+    }
+
+    // Move the focus to the next element that can be focused, or the
+    // previous one, wrapping around at the ends, and lay the page out
+    // again. Returns the border box of the newly focused element.
+    fn move_focus(&mut self, backward: bool) -> Option<Rectangle> {
+        let elements = self.document.root.focusable_elements();
+        let current = self.focused.and_then(|(node, _)| {
+            elements.iter().position(|&element| element == node)
+        });
+        let count = elements.len();
+        let next = match current {
+            None if backward => count.checked_sub(1),
+            None => (count > 0).then_some(0),
+            Some(i) if backward => Some((i + count - 1) % count),
+            Some(i) => Some((i + 1) % count),
+        };
+        self.focused = next.map(|i| (elements[i], Rectangle::default()));
+        self.scroll_at((0.0, 0.0), 0.0, 0.0);
+        self.focused.map(|(_, rect)| rect)
+    }
+}
+
+// Get the border box of the first box of a node.
+fn border_box(layout_box: &LayoutBox, node: NodeId) -> Option<Rectangle> {
+    if layout_box.node_id() == Some(node) {
+        return Some(layout_box.dimensions().border_box());
+    }
+    layout_box
+        .children()
+        .iter()
+        .find_map(|child| border_box(child, node))
+}
+
+// Get how far the scroll container of a node is scrolled.
+fn scroll_offset(layout_box: &LayoutBox, node: NodeId) -> Option<(f32, f32)> {
+    if layout_box.node_id() == Some(node) && layout_box.clip_rect().is_some() {
+        return Some(layout_box.scroll_offset());
+    }
+    layout_box
+        .children()
+        .iter()
+        .find_map(|child| scroll_offset(child, node))
+}
+
+#[cfg(not(feature = "wgpu"))]
+type WindowSurface = Surface<Arc<Window>, Arc<Window>>;
+
+// A surface of the window that is drawn on with a renderer on the GPU.
+#[cfg(feature = "wgpu")]
+struct WindowSurface {
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    renderer: GpuRenderer,
+}
+
+#[cfg(not(feature = "wgpu"))]
+fn create_surface(window: Arc<Window>, _: &Fonts) -> WindowSurface {
+    let context =
+        Context::new(window.clone()).expect("Failed to create a context");
+    Surface::new(&context, window).expect("Failed to create a surface")
+}
+
+// Colors are blended without converting them from sRGB, like on a canvas,
+// so the surface is in a format that doesn't convert them either.
+#[cfg(feature = "wgpu")]
+fn create_surface(window: Arc<Window>, fonts: &Fonts) -> WindowSurface {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let surface = instance
+        .create_surface(window.clone())
+        .expect("Failed to create a surface");
+    let adapter = pollster::block_on(instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        },
+    ))
+    .expect("Failed to find a GPU");
+    let (device, queue) = pollster::block_on(
+        adapter.request_device(&wgpu::DeviceDescriptor::default()),
+    )
+    .expect("Failed to create a device");
+    let size = window.inner_size();
+    let mut config = surface
+        .get_default_config(&adapter, size.width.max(1), size.height.max(1))
+        .expect("Failed to configure the surface");
+    let capabilities = surface.get_capabilities(&adapter);
+    if let Some(format) =
+        capabilities.formats.iter().find(|format| !format.is_srgb())
+    {
+        config.format = *format;
+    }
+    surface.configure(&device, &config);
+    let mut renderer = GpuRenderer::new(device, queue, config.format);
+    renderer.fonts = Some(fonts.clone());
+    WindowSurface {
+        surface,
+        config,
+        renderer,
+    }
+}
+
+struct Viewer {
+    title: String,
+    page: Page,
+    window: Option<(Arc<Window>, WindowSurface)>,
+    // How far the page is scrolled down, in CSS pixels.
+    scroll: f32,
+    // Where the cursor is in the window, in CSS pixels.
+    cursor: (f32, f32),
+    // The modifier keys that are held down.
+    modifiers: ModifiersState,
+    // What is in the window, with the display list that it was painted
+    // from and how far the page was scrolled then.
+    #[cfg(not(feature = "wgpu"))]
+    painted: Option<(Canvas, DisplayList, f32)>,
+}
+
+impl Viewer {
+    // Scroll the page by a distance, but not past its top or bottom.
+    fn scroll_by(&mut self, dy: f32) {
+        let Some((window, _)) = &self.window else {
+            return;
+        };
+        let viewport_height = self.page.viewport.1;
+        let max = (self.page.height - viewport_height).max(0.0);
+        let scroll = (self.scroll + dy).clamp(0.0, max);
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            window.request_redraw();
+        }
+    }
+
+    // Scroll the page as little as possible for a box to be in view, with
+    // its top in view if it is taller than the window.
+    fn scroll_into_view(&mut self, rect: Rectangle) {
+        let viewport_height = self.page.viewport.1;
+        if rect.y + rect.height > self.scroll + viewport_height {
+            self.scroll_by(
+                rect.y + rect.height - viewport_height - self.scroll,
+            );
+        }
+        if rect.y < self.scroll {
+            self.scroll_by(rect.y - self.scroll);
+        }
+    }
+
+    #[cfg(not(feature = "wgpu"))]
+    fn redraw(&mut self) {
+        let Some((window, surface)) = &mut self.window else {
+            return;
+        };
+        let size = window.inner_size();
+        let (Some(width), Some(height)) =
+            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+        else {
+            return;
+        };
+        surface.resize(width, height).expect("Failed to resize");
+
+        // The window is painted again where the page changed, unless it was
+        // resized or the page was scrolled, which moves all of it.
+        let bounds = Rectangle {
+            x: 0.0,
+            y: self.scroll,
+            width: self.page.viewport.0,
+            height: self.page.viewport.1,
+        };
+        let (mut canvas, damaged) = match self.painted.take() {
+            Some((canvas, list, scroll))
+                if (canvas.width, canvas.height)
+                    == (size.width as usize, size.height as usize)
+                    && scroll == self.scroll =>
+            {
+                (canvas, damage(&list, &self.page.display_list))
+            }
+            _ => (
+                Canvas::new(size.width as usize, size.height as usize),
+                vec![bounds],
+            ),
+        };
+        let painted = repaint_with_fonts(
+            &mut canvas,
+            bounds,
+            &self.page.display_list,
+            &self.page.images,
+            self.page.device_pixel_ratio,
+            &self.page.fonts,
+            &damaged,
+        );
+
+        let mut buffer = surface.buffer_mut().expect("Failed to get buffer");
+        for (pixel, color) in buffer.iter_mut().zip(&canvas.pixels) {
+            *pixel =
+                (color.r as u32) << 16 | (color.g as u32) << 8 | color.b as u32;
+        }
+        let rects: Vec<Rect> = painted
+            .iter()
+            .filter_map(|rect| {
+                Some(Rect {
+                    x: rect.x as u32,
+                    y: rect.y as u32,
+                    width: NonZeroU32::new(rect.width as u32)?,
+                    height: NonZeroU32::new(rect.height as u32)?,
+                })
+            })
+            .collect();
+        // The whole window is presented when it asks to be drawn again
+        // without anything having changed, such as after being uncovered.
+        if rects.is_empty() {
+            buffer.present().expect("Failed to present");
+        } else {
+            buffer
+                .present_with_damage(&rects)
+                .expect("Failed to present");
+        }
+        self.painted =
+            Some((canvas, self.page.display_list.clone(), self.scroll));
+    }
+
+    // The whole display list is drawn in each frame, which the GPU does
+    // quickly enough to scroll smoothly through large pages.
+    #[cfg(feature = "wgpu")]
+    fn redraw(&mut self) {
+        let Some((window, surface)) = &mut self.window else {
+            return;
+        };
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        let device = &surface.renderer.device;
+        if (surface.config.width, surface.config.height)
+            != (size.width, size.height)
+        {
+            surface.config.width = size.width;
+            surface.config.height = size.height;
+            surface.surface.configure(device, &surface.config);
+        }
+        // A surface that is lost or out of date is configured again, and
+        // drawn in the next frame.
+        let Ok(texture) = surface.surface.get_current_texture() else {
+            surface.surface.configure(device, &surface.config);
+            window.request_redraw();
+            return;
+        };
+        let view = texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let bounds = Rectangle {
+            x: 0.0,
+            y: self.scroll,
+            width: self.page.viewport.0,
+            height: self.page.viewport.1,
+        };
+        surface.renderer.render(
+            &self.page.display_list,
+            &self.page.images,
+            device_transform(bounds, self.page.device_pixel_ratio),
+            &view,
+            (size.width, size.height),
+        );
+        window.pre_present_notify();
+        texture.present();
+    }
+}
+
+impl ApplicationHandler for Viewer {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        let attributes = Window::default_attributes().with_title(&self.title);
+        let window = Arc::new(
+            event_loop
+                .create_window(attributes)
+                .expect("Failed to create a window"),
+        );
+        let surface = create_surface(window.clone(), &self.page.fonts);
+        let size = window.inner_size();
+        self.page.layout(
+            size.width as f32,
+            size.height as f32,
+            window.scale_factor() as f32,
+        );
+        self.window = Some((window, surface));
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        _: WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(size) => {
+                let ratio = self.page.device_pixel_ratio;
+                self.page
+                    .layout(size.width as f32, size.height as f32, ratio);
+                // Keep the scroll position within the page.
+                self.scroll_by(0.0);
+                if let Some((window, _)) = &self.window {
+                    window.request_redraw();
+                }
+            }
+            // The page is laid out again for the new ratio, and again if the
+            // window is resized to keep its size in CSS pixels.
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                if let Some((window, _)) = &self.window {
+                    let size = window.inner_size();
+                    self.page.layout(
+                        size.width as f32,
+                        size.height as f32,
+                        scale_factor as f32,
+                    );
+                    window.request_redraw();
+                }
+                // What is in the window was painted at the old ratio.
+                #[cfg(not(feature = "wgpu"))]
+                {
+                    self.painted = None;
+                }
+                self.scroll_by(0.0);
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let ratio = self.page.device_pixel_ratio;
+                self.cursor =
+                    (position.x as f32 / ratio, position.y as f32 / ratio);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (dx, dy) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        (x * LINE_HEIGHT, y * LINE_HEIGHT)
+                    }
+                    MouseScrollDelta::PixelDelta(position) => {
+                        let ratio = self.page.device_pixel_ratio;
+                        (position.x as f32 / ratio, position.y as f32 / ratio)
+                    }
+                };
+                // The wheel scrolls the content up when it moves away from
+                // the user, which is a positive delta.
+                let point = (self.cursor.0, self.cursor.1 + self.scroll);
+                if self.page.scroll_at(point, -dx, -dy) {
+                    if let Some((window, _)) = &self.window {
+                        window.request_redraw();
+                    }
+                } else {
+                    self.scroll_by(-dy);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: Key::Named(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let page = self.page.viewport.1 * 0.9;
+                match key {
+                    NamedKey::ArrowDown => self.scroll_by(LINE_HEIGHT),
+                    NamedKey::ArrowUp => self.scroll_by(-LINE_HEIGHT),
+                    NamedKey::PageDown | NamedKey::Space => {
+                        self.scroll_by(page)
+                    }
+                    NamedKey::PageUp => self.scroll_by(-page),
+                    NamedKey::Home => self.scroll_by(-self.scroll),
+                    NamedKey::End => self.scroll_by(self.page.height),
+                    NamedKey::Tab => {
+                        let backward = self.modifiers.shift_key();
+                        if let Some(rect) = self.page.move_focus(backward) {
+                            self.scroll_into_view(rect);
+                        }
+                        if let Some((window, _)) = &self.window {
+                            window.request_redraw();
+                        }
+                    }
+                    NamedKey::Escape => event_loop.exit(),
+                    _ => {}
+                }
+            }
+            WindowEvent::RedrawRequested => self.redraw(),
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (html, css) = match args.as_slice() {
+        [_, html, css, ..] => (html, css),
+        _ => panic!("Usage: viewer <html> <css> [font]"),
+    };
+    let read = |path: &str| {
+        fs::read_to_string(path).unwrap_or_else(|error| {
+            panic!("Failed to read {}: {}", path, error)
+        })
+    };
+    let mut fonts = Fonts::new();
+    if let Some(path) = args.get(3) {
+        let data = fs::read(path).unwrap_or_else(|error| {
+            panic!("Failed to read {}: {}", path, error)
+        });
+        fonts.add_font("serif", 400, FontStyle::Normal, data);
+    }
+
+    let mut viewer = Viewer {
+        title: html.clone(),
+        page: Page {
+            document: Document::new(HTMLParser::parse(read(html))),
+            stylesheets: vec![CSSParser::parse(read(css))],
+            fonts,
+            images: Images::new(),
+            display_list: Vec::new(),
+            height: 0.0,
+            viewport: (0.0, 0.0),
+            device_pixel_ratio: 1.0,
+            scroll_offsets: HashMap::new(),
+            focused: None,
+        },
+        window: None,
+        scroll: 0.0,
+        cursor: (0.0, 0.0),
+        modifiers: ModifiersState::empty(),
+        #[cfg(not(feature = "wgpu"))]
+        painted: None,
+    };
+    let event_loop = EventLoop::new().expect("Failed to create an event loop");
+    event_loop
+        .run_app(&mut viewer)
+        .expect("Failed to run the viewer");
+}
@@ -0,0 +1,82 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// The number of bits of a hash used to index the counters.
+const KEY_SIZE: usize = 12;
+const ARRAY_SIZE: usize = 1 << KEY_SIZE;
+const KEY_MASK: u32 = (1 << KEY_SIZE) - 1;
+
+// A counting Bloom filter, which supports removing items as well as adding
+// them. It may report that an item is present when it is not, but never
+// the other way around.
+// Each item sets two counters, indexed by the low and high bits of its hash.
+#[derive(Clone)]
+pub struct BloomFilter {
+    counters: Box<[u8; ARRAY_SIZE]>,
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        Self {
+            counters: Box::new([0; ARRAY_SIZE]),
+        }
+    }
+
+    // Add an item by its hash.
+    pub fn insert_hash(&mut self, hash: u32) {
+        for index in indices(hash) {
+            // A saturated counter is never changed again, because its true
+            // count is lost.
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    // Remove an item by its hash. The item must have been added before.
+    pub fn remove_hash(&mut self, hash: u32) {
+        for index in indices(hash) {
+            let counter = &mut self.counters[index];
+            if *counter != u8::MAX {
+                *counter -= 1;
+            }
+        }
+    }
+
+    // Check if an item might have been added. `false` is always correct.
+    #[inline]
+    pub fn might_contain_hash(&self, hash: u32) -> bool {
+        indices(hash).iter().all(|&index| self.counters[index] != 0)
+    }
+
+    // Remove all items.
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for BloomFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let used = self.counters.iter().filter(|&&c| c != 0).count();
+        f.debug_struct("BloomFilter").field("used", &used).finish()
+    }
+}
+
+// Hash an item for a Bloom filter.
+pub fn hash<T: Hash + ?Sized>(item: &T) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    item.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+#[inline]
+fn indices(hash: u32) -> [usize; 2] {
+    [
+        (hash & KEY_MASK) as usize,
+        ((hash >> KEY_SIZE) & KEY_MASK) as usize,
+    ]
+}
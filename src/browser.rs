@@ -0,0 +1,568 @@
+// A browser loads a page from a URL and runs it through the whole pipeline:
+//
+// ResourceLoader::load -> net::decode_html -> HTMLParser::parse_document
+//     -> stylesheets and images -> style::restyle -> layout
+//     -> painting::build_display_list
+//
+// The stylesheets of `<style>` elements and `<link rel="stylesheet">`
+// elements cascade in the order that they appear in the document. Those
+// that fail to load are skipped. All resources are loaded with the resource
+// loader of the browser, concurrently; see `pipeline::PageLoad`.
+//
+// The page keeps its document, so that it can be styled and laid out again
+// for another viewport.
+//
+// A browser is a session that navigates from page to page, with a history
+// of the pages that it visited. Going back or forward in the history loads
+// a page again, from the cache of the loader if it has one.
+
+use std::{io, sync::Arc};
+
+use crate::{
+    css_parser::StyleSheet,
+    document::Document,
+    image_cache::ImageCache,
+    layout::{Dimensions, LayoutBox, layout_tree_with_metrics},
+    loader::{ResourceLoader, SchemeLoader},
+    painting::{DisplayList, build_display_list},
+    pipeline::PageLoad,
+    style::{MediaContext, StaticElementState, StyleCache, restyle},
+    text::MonospaceMetrics,
+    url::Url,
+};
+
+// A page that was loaded from a URL.
+pub struct Page {
+    // The URL of the document after redirects, which relative URLs in it
+    // are resolved against.
+    pub url: Url,
+    // The status of the response that the document came with.
+    pub status: u16,
+    pub document: Document,
+    // The author stylesheets of the page.
+    pub stylesheets: Vec<StyleSheet>,
+    // The images of the `<img>` elements of the page that were loaded.
+    pub images: ImageCache,
+    // What the page shows in the viewport of the browser, as it was last
+    // laid out.
+    pub display_list: DisplayList,
+}
+
+impl Page {
+    // Style the page and lay it out in a viewport of a width and a height,
+    // and call a function with the layout tree.
+    pub fn with_layout<R>(
+        &self,
+        (width, height): (f32, f32),
+        f: impl FnOnce(&LayoutBox) -> R,
+    ) -> R {
+        let media = MediaContext {
+            viewport_width: width,
+            viewport_height: height,
+            ..MediaContext::default()
+        };
+        let styled = restyle(
+            &self.document,
+            &self.stylesheets,
+            &media,
+            &mut StyleCache::default(),
+            &[],
+            &StaticElementState,
+        );
+        let mut viewport = Dimensions::default();
+        viewport.content.width = width;
+        viewport.content.height = height;
+        f(&layout_tree_with_metrics(
+            &styled,
+            viewport,
+            &MonospaceMetrics,
+            &self.images,
+        ))
+    }
+
+    // Lay the page out again in a viewport, and update what it shows.
+    pub fn layout(&mut self, viewport: (f32, f32)) {
+        self.display_list = self.with_layout(viewport, |layout_root| {
+            build_display_list(layout_root, &self.images)
+        });
+    }
+}
+
+// Loads pages and lays them out in a viewport.
+pub struct Browser {
+    // The width and the height of the viewport, in CSS pixels.
+    pub viewport: (f32, f32),
+    loader: Arc<dyn ResourceLoader + Send + Sync>,
+    page: Option<Page>,
+    // The URLs of the pages that were visited, and the index of the current
+    // one among them.
+    history: Vec<Url>,
+    index: usize,
+}
+
+impl Browser {
+    // Create a browser that loads the schemes of `SchemeLoader::default`.
+    pub fn new(viewport: (f32, f32)) -> Self {
+        Self::with_loader(viewport, SchemeLoader::default())
+    }
+
+    // Create a browser that loads all resources with a loader.
+    pub fn with_loader(
+        viewport: (f32, f32),
+        loader: impl ResourceLoader + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            viewport,
+            loader: Arc::new(loader),
+            page: None,
+            history: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // The page that was loaded last, if any.
+    #[inline]
+    pub fn page(&self) -> Option<&Page> {
+        self.page.as_ref()
+    }
+
+    // The document of the current page, if any.
+    pub fn document(&self) -> Option<&Document> {
+        self.page.as_ref().map(|page| &page.document)
+    }
+
+    // Style the current page and lay it out in the viewport, and call a
+    // function with the layout tree. Returns `None` without a page.
+    pub fn with_layout<R>(&self, f: impl FnOnce(&LayoutBox) -> R) -> Option<R> {
+        Some(self.page.as_ref()?.with_layout(self.viewport, f))
+    }
+
+    // Resize the viewport, and lay the current page out again in it.
+    pub fn resize(&mut self, viewport: (f32, f32)) {
+        self.viewport = viewport;
+        if let Some(page) = &mut self.page {
+            page.layout(viewport);
+        }
+    }
+
+    // The URLs of the pages that were visited, from the first one.
+    #[inline]
+    pub fn history(&self) -> &[Url] {
+        &self.history
+    }
+
+    // The index of the current page in the history.
+    #[inline]
+    pub fn history_index(&self) -> usize {
+        self.index
+    }
+
+    #[inline]
+    pub fn can_go_back(&self) -> bool {
+        self.index > 0
+    }
+
+    #[inline]
+    pub fn can_go_forward(&self) -> bool {
+        self.index + 1 < self.history.len()
+    }
+
+    // Load the document at a URL in place of the current page, and lay it
+    // out. A relative URL is resolved against the URL of the current page.
+    // The page is added to the history after the current one, in place of
+    // the pages that were forward of it. Pages that come with error
+    // statuses are shown like others, and pages that fail to load leave the
+    // current page and the history as they were.
+    pub fn navigate(&mut self, url: &str) -> io::Result<&Page> {
+        let resolved = match &self.page {
+            Some(page) => page.url.join(url),
+            None => Url::parse(url),
+        };
+        let url = resolved.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid URL {}", url),
+            )
+        })?;
+        let page = self.fetch_page(url)?;
+        if self.page.is_some() {
+            self.history.truncate(self.index + 1);
+            self.index += 1;
+        }
+        self.history.push(page.url.clone());
+        Ok(self.page.insert(page))
+    }
+
+    // Load the document at a URL in place of the current page, like
+    // `navigate`.
+    #[inline]
+    pub fn load(&mut self, url: &str) -> io::Result<&Page> {
+        self.navigate(url)
+    }
+
+    // Load the current page again.
+    pub fn reload(&mut self) -> io::Result<&Page> {
+        let Some(url) = self.history.get(self.index).cloned() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No page to reload",
+            ));
+        };
+        let page = self.fetch_page(url)?;
+        Ok(self.page.insert(page))
+    }
+
+    // Load the page before the current one in the history. Returns `None`
+    // if there is none.
+    pub fn back(&mut self) -> Option<io::Result<&Page>> {
+        self.can_go_back().then(|| self.go_to(self.index - 1))
+    }
+
+    // Load the page after the current one in the history. Returns `None`
+    // if there is none.
+    pub fn forward(&mut self) -> Option<io::Result<&Page>> {
+        self.can_go_forward().then(|| self.go_to(self.index + 1))
+    }
+
+    // Load the page at an index of the history, and make it the current
+    // one if it loads.
+    fn go_to(&mut self, index: usize) -> io::Result<&Page> {
+        let page = self.fetch_page(self.history[index].clone())?;
+        self.index = index;
+        Ok(self.page.insert(page))
+    }
+
+    fn fetch_page(&self, url: Url) -> io::Result<Page> {
+        PageLoad::start(Arc::clone(&self.loader), url, self.viewport).wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        css_parser::Color,
+        dom::Node,
+        layout::BoxMap,
+        loader::{MemoryLoader, Resource},
+        net::{serve, serve_paths},
+        painting::{DisplayCommand, ImageSource},
+        style::query_selector,
+    };
+
+    #[test]
+    fn test_load() {
+        let (url, _) = serve(vec![
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: text/html; charset=windows-1252\r\n\
+              Content-Length: 52\r\n\r\n\
+              <!DOCTYPE html><html><body><p>caf\xe9</p></body></html>"
+                .to_vec(),
+        ]);
+        let mut browser = Browser::new((100.0, 50.0));
+        assert!(browser.page().is_none());
+        let page = browser.load(&format!("{}/a/./", url)).unwrap();
+
+        assert_eq!(page.status, 200);
+        assert_eq!(page.url.to_string(), format!("{}/a/", url));
+        assert_eq!(page.document.url, Some(page.url.to_string()));
+        assert_eq!(page.document.root.text_content(), "caf\u{e9}");
+        assert_eq!(
+            page.display_list,
+            page.with_layout((100.0, 50.0), |layout_root| {
+                build_display_list(layout_root, &page.images)
+            })
+        );
+        assert!(page.with_layout((100.0, 50.0), |layout_root| {
+            layout_root.dimensions().content.width == 100.0
+        }));
+        assert!(browser.page().is_some());
+
+        // Without a page, relative URLs can't be resolved.
+        assert_eq!(
+            Browser::new((100.0, 50.0))
+                .load("page.html")
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_load_stylesheets() {
+        let html = "<html><head>\
+                    <link rel=\"stylesheet\" href=\"../css/a.css\"></link>\
+                    <style>p { color: #00ff00; height: 10px; }</style>\
+                    <link rel=\"alternate stylesheet\" href=\"b.css\"></link>\
+                    <link rel=\"stylesheet\" href=\"missing.css\"></link>\
+                    </head><body><p>a</p></body></html>";
+        let css = "p { color: #ff0000; height: 20px; margin: 0; }";
+        // The stylesheets are requested at the same time.
+        let (url, requests) = serve_paths(vec![
+            (
+                "/pages/index.html",
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    html.len(),
+                    html
+                )
+                .into_bytes(),
+            ),
+            (
+                "/css/a.css",
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/css\r\n\
+                     Content-Length: {}\r\n\r\n{}",
+                    css.len(),
+                    css
+                )
+                .into_bytes(),
+            ),
+            (
+                "/pages/missing.css",
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 1\r\n\r\np"
+                    .to_vec(),
+            ),
+        ]);
+        let mut browser = Browser::new((100.0, 50.0));
+        let page = browser.load(&format!("{}/pages/index.html", url)).unwrap();
+
+        // Links are resolved against the page, and the alternate one isn't
+        // loaded.
+        let mut paths: Vec<String> = requests
+            .iter()
+            .map(|request| request.split(' ').nth(1).unwrap().to_string())
+            .collect();
+        paths[1..].sort();
+        assert_eq!(
+            paths,
+            ["/pages/index.html", "/css/a.css", "/pages/missing.css"]
+        );
+
+        // The stylesheets cascade in document order, so the `<style>`
+        // element wins over the link before it.
+        assert_eq!(page.stylesheets.len(), 2);
+        let p = query_selector(&page.document.root, "p").unwrap();
+        let height = page.with_layout((100.0, 50.0), |layout_root| {
+            BoxMap::new(layout_root)
+                .get(p.node_id)
+                .unwrap()
+                .dimensions()
+                .content
+                .height
+        });
+        assert_eq!(height, 10.0);
+        assert!(page.display_list.iter().any(|command| matches!(
+            command,
+            DisplayCommand::Text(run) if run.color == Color { r: 0, g: 255, b: 0, a: 255 }
+        )));
+    }
+
+    #[test]
+    fn test_unparsable_pages_are_errors() {
+        // Pages as they are written, with void elements and attributes
+        // without values, which the parser can't handle.
+        let pages = [
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+             <title>Page</title></head><body></body></html>",
+            "<html><body><p>a<br>b</p></body></html>",
+            "<html><body><form><input disabled></form></body></html>",
+        ];
+        let (url, _) = serve(
+            pages
+                .iter()
+                .map(|html| {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        html.len(),
+                        html
+                    )
+                    .into_bytes()
+                })
+                .collect(),
+        );
+        let mut browser = Browser::new((100.0, 50.0));
+        for _ in pages {
+            let error = browser.load(&format!("{}/", url)).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+        assert!(browser.page().is_none());
+    }
+
+    #[test]
+    fn test_custom_loader() {
+        let mut memory = MemoryLoader::new();
+        memory.insert(
+            "app://bundle/index.html",
+            Some("text/html"),
+            b"<html><head>\
+              <link rel=\"stylesheet\" href=\"style.css\"></link>\
+              <link rel=\"stylesheet\" \
+              href=\"data:text/css,p%20{height:%2030px}\"></link>\
+              </head><body><p>a</p></body></html>",
+        );
+        memory.insert(
+            "app://bundle/style.css",
+            Some("text/css"),
+            b"p { height: 20px; margin: 0; }",
+        );
+        let mut loader = SchemeLoader::default();
+        loader.register("app", memory);
+        let mut browser = Browser::with_loader((100.0, 50.0), loader);
+        let page = browser.load("app://bundle/index.html").unwrap();
+
+        assert_eq!(page.stylesheets.len(), 2);
+        let p = query_selector(&page.document.root, "p").unwrap();
+        let height = page.with_layout((100.0, 50.0), |layout_root| {
+            BoxMap::new(layout_root)
+                .get(p.node_id)
+                .unwrap()
+                .dimensions()
+                .content
+                .height
+        });
+        assert_eq!(height, 30.0);
+
+        assert_eq!(
+            browser
+                .load("app://bundle/missing.html")
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_data_urls() {
+        // A page with its stylesheet and images inlined loads without a
+        // network.
+        let svg = "data:image/svg+xml,%3Csvg%20width=%2220%22%20\
+                   height=%2210%22%3E%3C/svg%3E";
+        let html = format!(
+            "<html><head>\
+             <link rel=\"stylesheet\" \
+             href=\"data:text/css;base64,ZGl2IHsgaGVpZ2h0OiA0MHB4OyB9\"></link>\
+             <style>div {{ background-image: url({}); }}</style>\
+             </head><body><div><img src=\"{}\"></img></div></body></html>",
+            svg, svg
+        );
+        // The percent signs of the inner URLs are encoded again.
+        let url = format!(
+            "data:text/html,{}",
+            crate::url::percent_encode(&html.replace('%', "%25"))
+        );
+        let mut browser = Browser::new((100.0, 50.0));
+        let page = browser.load(&url).unwrap();
+
+        assert_eq!(page.stylesheets.len(), 2);
+        assert!(page.images.images().get_svg(svg).is_some());
+        let img = query_selector(&page.document.root, "img").unwrap();
+        let div = query_selector(&page.document.root, "div").unwrap();
+        let heights = page.with_layout((100.0, 50.0), |layout_root| {
+            let boxes = BoxMap::new(layout_root);
+            let height = |node: &Node| {
+                boxes.get(node.node_id).unwrap().dimensions().content.height
+            };
+            (height(img), height(div))
+        });
+        assert_eq!(heights, (10.0, 40.0));
+        assert!(page.display_list.iter().any(|command| matches!(
+            command,
+            DisplayCommand::BackgroundImage { image: ImageSource::Url(url), .. }
+                if url == svg
+        )));
+    }
+
+    // Loads resources from memory, counting the loads.
+    struct CountingLoader {
+        memory: MemoryLoader,
+        loads: Arc<AtomicUsize>,
+    }
+
+    impl ResourceLoader for CountingLoader {
+        fn load(&self, url: &Url) -> io::Result<Resource> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            self.memory.load(url)
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut memory = MemoryLoader::new();
+        for name in ["a", "b", "c"] {
+            memory.insert(
+                &format!("app://site/{}.html", name),
+                Some("text/html"),
+                format!("<html><body><p>{}</p></body></html>", name).as_bytes(),
+            );
+        }
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut browser = Browser::with_loader(
+            (100.0, 50.0),
+            CountingLoader {
+                memory,
+                loads: Arc::clone(&loads),
+            },
+        );
+        let text =
+            |browser: &Browser| browser.document().unwrap().root.text_content();
+        assert!(browser.reload().is_err());
+        assert!(browser.back().is_none());
+
+        // Relative URLs are resolved against the current page.
+        browser.navigate("app://site/a.html").unwrap();
+        browser.navigate("b.html").unwrap();
+        browser.navigate("/c.html").unwrap();
+        assert_eq!(text(&browser), "c");
+        let history: Vec<String> =
+            browser.history().iter().map(Url::to_string).collect();
+        assert_eq!(
+            history,
+            [
+                "app://site/a.html",
+                "app://site/b.html",
+                "app://site/c.html"
+            ]
+        );
+
+        assert_eq!(browser.back().unwrap().unwrap().url.path(), "/b.html");
+        browser.back().unwrap().unwrap();
+        assert_eq!(text(&browser), "a");
+        assert!(!browser.can_go_back());
+        assert!(browser.back().is_none());
+        browser.forward().unwrap().unwrap();
+        assert_eq!(browser.history_index(), 1);
+        assert_eq!(text(&browser), "b");
+
+        // A page that fails to load changes nothing, and a new page
+        // replaces the ones forward of the current one.
+        assert!(browser.navigate("missing.html").is_err());
+        assert_eq!(
+            (browser.history_index(), text(&browser).as_str()),
+            (1, "b")
+        );
+        browser.navigate("a.html").unwrap();
+        assert_eq!(browser.history().len(), 3);
+        assert_eq!(browser.history_index(), 2);
+        assert!(!browser.can_go_forward());
+
+        let before = loads.load(Ordering::SeqCst);
+        assert_eq!(browser.reload().unwrap().url.path(), "/a.html");
+        assert_eq!(loads.load(Ordering::SeqCst), before + 1);
+
+        // The layout tree of the current page is laid out in the viewport.
+        browser.resize((200.0, 50.0));
+        assert_eq!(
+            browser.with_layout(|layout_root| layout_root
+                .dimensions()
+                .content
+                .width),
+            Some(200.0)
+        );
+    }
+}
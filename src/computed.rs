@@ -0,0 +1,2796 @@
+use crate::{
+    css_parser::{Color, Unit, Value},
+    style::{Display, PropertyMap},
+};
+
+// The initial font size in pixels.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+// The line height factor used for `line-height: normal`.
+const NORMAL_LINE_HEIGHT: f32 = 1.2;
+
+// A length that may be left for layout to determine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthOrAuto {
+    Auto,
+    Px(f32),
+    // A percentage of a size of the containing block, which layout
+    // resolves.
+    Percent(f32),
+    // The intrinsic widths of the content, which only widths can be.
+    // Layout resolves them, and they count as `auto` otherwise.
+    // SEE: https://www.w3.org/TR/css-sizing-3/#sizing-values
+    MinContent,
+    MaxContent,
+    FitContent,
+}
+
+impl LengthOrAuto {
+    // Get the length in pixels, treating `auto` as zero. Percentages have
+    // to be resolved first, and count as zero otherwise.
+    #[inline]
+    pub fn to_px(self) -> f32 {
+        match self {
+            LengthOrAuto::Px(px) => px,
+            _ => 0.0,
+        }
+    }
+
+    // Resolve a percentage against `basis`. Without a basis, such as the
+    // height of a containing block that depends on its content, it
+    // behaves as `auto`.
+    #[inline]
+    pub fn resolve(self, basis: Option<f32>) -> Self {
+        match (self, basis) {
+            (LengthOrAuto::Percent(percent), Some(basis)) => {
+                LengthOrAuto::Px(percent / 100.0 * basis)
+            }
+            (LengthOrAuto::Percent(_), None) => LengthOrAuto::Auto,
+            (length, _) => length,
+        }
+    }
+}
+
+// A length that may be a percentage of a size of the containing block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthPercentage {
+    Px(f32),
+    Percent(f32),
+}
+
+impl Default for LengthPercentage {
+    fn default() -> Self {
+        LengthPercentage::Px(0.0)
+    }
+}
+
+impl LengthPercentage {
+    // Get the length in pixels, resolving a percentage against `basis`.
+    #[inline]
+    pub fn to_px(self, basis: f32) -> f32 {
+        match self {
+            LengthPercentage::Px(px) => px,
+            LengthPercentage::Percent(percent) => percent / 100.0 * basis,
+        }
+    }
+
+    // Get the length in pixels, unless it is a percentage without a basis.
+    #[inline]
+    pub fn resolve(self, basis: Option<f32>) -> Option<f32> {
+        match (self, basis) {
+            (LengthPercentage::Percent(_), None) => None,
+            (length, basis) => Some(length.to_px(basis.unwrap_or(0.0))),
+        }
+    }
+
+    #[inline]
+    pub fn is_zero(self) -> bool {
+        matches!(
+            self,
+            LengthPercentage::Px(0.0) | LengthPercentage::Percent(0.0)
+        )
+    }
+}
+
+// Values for the four sides of a box.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sides<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+// Values for the four corners of a box.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Corners<T> {
+    pub top_left: T,
+    pub top_right: T,
+    pub bottom_right: T,
+    pub bottom_left: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Visible,
+    // The box is laid out, but not painted.
+    Hidden,
+    // Like `hidden`, except for table rows and columns.
+    Collapse,
+}
+
+// Whether content may break onto the next page between or inside boxes,
+// from the weakest to the strongest request.
+// SEE: https://drafts.csswg.org/css-break/#break-between
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Break {
+    Auto,
+    Avoid,
+    // A forced page break, which is never allowed inside a box.
+    Page,
+}
+
+// The line style of a border.
+// SEE: https://www.w3.org/TR/CSS22/box.html#border-style-properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+    None,
+    // Like `none`, except that it wins over other styles when table borders
+    // collapse.
+    Hidden,
+    Dotted,
+    Dashed,
+    Solid,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+impl BorderStyle {
+    // Check if the border is drawn, and so takes up its width.
+    #[inline]
+    pub fn is_visible(self) -> bool {
+        !matches!(self, BorderStyle::None | BorderStyle::Hidden)
+    }
+}
+
+// The line style of an outline, which is that of a border, or `auto` for a
+// focus ring that the user agent draws in its own way.
+// SEE: https://drafts.csswg.org/css-ui/#outline-style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineStyle {
+    Auto,
+    Border(BorderStyle),
+}
+
+impl OutlineStyle {
+    #[inline]
+    pub fn is_visible(self) -> bool {
+        match self {
+            OutlineStyle::Auto => true,
+            OutlineStyle::Border(style) => style.is_visible(),
+        }
+    }
+}
+
+// A 2D transform function of the `transform` property. Angles are in
+// radians, and positive ones turn clockwise.
+// SEE: https://drafts.csswg.org/css-transforms/#two-d-transform-functions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformFunction {
+    // A translation, whose percentages are of the size of the border box.
+    Translate(LengthPercentage, LengthPercentage),
+    Scale(f32, f32),
+    Rotate(f32),
+    Skew(f32, f32),
+    // The matrix `[a b c d e f]` of `matrix(a, b, c, d, e, f)`.
+    Matrix([f32; 6]),
+}
+
+// A background image, which is either loaded from a URL or generated.
+// SEE: https://drafts.csswg.org/css-images/#typedef-image
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundImage {
+    // A URL as it is written.
+    Url(String),
+    LinearGradient {
+        direction: GradientDirection,
+        stops: Vec<ColorStop>,
+    },
+    RadialGradient {
+        shape: RadialShape,
+        size: RadialSize,
+        // The center of the gradient in the box.
+        position: (LengthPercentage, LengthPercentage),
+        stops: Vec<ColorStop>,
+    },
+}
+
+// The direction that a linear gradient runs in.
+// SEE: https://drafts.csswg.org/css-images/#linear-gradient-syntax
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientDirection {
+    // An angle in radians, clockwise from upward.
+    Angle(f32),
+    // Toward a corner, such as `to top right`, whose angle depends on the
+    // size of the box.
+    Corner { right: bool, bottom: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadialShape {
+    Circle,
+    Ellipse,
+}
+
+// The size of the ending shape of a radial gradient.
+// SEE: https://drafts.csswg.org/css-images/#valdef-radial-gradient-radial-size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadialSize {
+    ClosestSide,
+    FarthestSide,
+    ClosestCorner,
+    FarthestCorner,
+    // The horizontal and vertical radii, which are equal for circles.
+    Radii(LengthPercentage, LengthPercentage),
+}
+
+// A color of a gradient, and where it is on the gradient line, if given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub color: Color,
+    pub position: Option<LengthPercentage>,
+}
+
+// A shadow of a box, which is a copy of its border box that is offset,
+// grown by the spread distance, and blurred.
+// SEE: https://drafts.csswg.org/css-backgrounds/#box-shadow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxShadow {
+    pub offset: (f32, f32),
+    // The blur radius, which is twice the standard deviation of the
+    // Gaussian blur.
+    pub blur: f32,
+    pub spread: f32,
+    pub color: Color,
+    // Whether the shadow is cast inside of the padding box instead of
+    // outside of the border box.
+    pub inset: bool,
+}
+
+// Whether a background image is repeated along an axis.
+// SEE: https://drafts.csswg.org/css-backgrounds/#background-repeat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundRepeat {
+    Repeat,
+    NoRepeat,
+}
+
+// The size of a background image.
+// SEE: https://drafts.csswg.org/css-backgrounds/#background-size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundSize {
+    // Scale the image to the largest size that fits in the background
+    // positioning area.
+    Contain,
+    // Scale the image to the smallest size that covers the area.
+    Cover,
+    // The width and height, where `None` is `auto`.
+    Size(Option<LengthPercentage>, Option<LengthPercentage>),
+}
+
+// How content that overflows the padding box of a box is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Visible,
+    // Clipped, and only scrollable by scripts.
+    Hidden,
+    // Clipped, and scrollable with scrollbars that are always shown.
+    Scroll,
+    // Clipped, and scrollable with scrollbars that are shown when needed.
+    Auto,
+}
+
+impl Overflow {
+    // Check if overflowing content is clipped to the padding box.
+    #[inline]
+    pub fn clips(self) -> bool {
+        self != Overflow::Visible
+    }
+}
+
+// How an inline-level box is aligned vertically within its line box.
+// SEE: https://drafts.csswg.org/css2/#propdef-vertical-align
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerticalAlign {
+    // The baseline of the box is aligned with the baseline of its parent.
+    Baseline,
+    // The top of the box is aligned with the top of the line box.
+    Top,
+    // The middle of the box is aligned with the baseline of its parent plus
+    // half its x-height.
+    Middle,
+    // The bottom of the box is aligned with the bottom of the line box.
+    Bottom,
+}
+
+// The inline base direction, which sets where lines start and which side
+// over-constrained margins are resolved on.
+// SEE: https://www.w3.org/TR/CSS22/visuren.html#direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+// How the content of a line box is aligned within it.
+// SEE: https://drafts.csswg.org/css-text/#text-align-property
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    // Aligned with the start edge of the line, in the inline base
+    // direction.
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+    // Stretched to fill the line, except on the last line of a block and
+    // before forced breaks, which are aligned with the start.
+    Justify,
+}
+
+// How white space inside text is collapsed and where lines may wrap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhiteSpace {
+    Normal,
+    // Like `normal`, but lines never wrap.
+    Nowrap,
+    // Spaces and newlines are preserved, and lines never wrap.
+    Pre,
+    // Like `pre`, but lines wrap.
+    PreWrap,
+    // Newlines are preserved, but other white space collapses.
+    PreLine,
+}
+
+impl WhiteSpace {
+    // Check if runs of spaces and tabs collapse into a single space.
+    #[inline]
+    pub fn collapses_spaces(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine
+        )
+    }
+
+    // Check if newlines force line breaks.
+    #[inline]
+    pub fn preserves_newlines(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine
+        )
+    }
+
+    // Check if lines may wrap at spaces.
+    #[inline]
+    pub fn wraps(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::PreWrap | WhiteSpace::PreLine
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Static,
+    // Offset from its position in normal flow.
+    Relative,
+    // Taken out of normal flow, and placed against the nearest positioned
+    // ancestor.
+    Absolute,
+    // Like `absolute`, but placed against the viewport.
+    Fixed,
+}
+
+impl Position {
+    // Check if boxes are taken out of normal flow.
+    #[inline]
+    pub fn is_out_of_flow(self) -> bool {
+        matches!(self, Position::Absolute | Position::Fixed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Float {
+    None,
+    Left,
+    Right,
+}
+
+// The sides of preceding floats that a box is moved below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clear {
+    None,
+    Left,
+    Right,
+    Both,
+}
+
+impl Clear {
+    // Check if the box is moved below floats on the given side.
+    #[inline]
+    pub fn clears(self, side: Float) -> bool {
+        matches!(
+            (self, side),
+            (Clear::Left | Clear::Both, Float::Left)
+                | (Clear::Right | Clear::Both, Float::Right)
+        )
+    }
+}
+
+// The algorithm that sizes the columns of a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableLayout {
+    // Columns are sized to fit the content of all cells.
+    Auto,
+    // Columns are sized by the first row, and the width of the table.
+    Fixed,
+}
+
+// The size of a grid track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackSize {
+    Px(f32),
+    // A percentage of the size of the grid container.
+    Percent(f32),
+    // A share of the space left over by the other tracks.
+    Fr(f32),
+    Auto,
+}
+
+// The line that a grid item starts or ends at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridLine {
+    // Placed automatically.
+    Auto,
+    // A line number, where negative numbers count from the end of the
+    // explicit grid.
+    Line(i32),
+    // A number of tracks from the other line of the item.
+    Span(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FontStyle {
+    Normal,
+    Italic,
+}
+
+// The kind of marker of a list item.
+// SEE: https://drafts.csswg.org/css-lists/#text-markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListStyleType {
+    None,
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+impl ListStyleType {
+    // Get the text of the marker of the list item with the given ordinal,
+    // followed by its suffix. Ordinals that a counter style can't represent
+    // fall back to decimal.
+    // SEE: https://drafts.csswg.org/css-counter-styles/#simple-numeric
+    pub fn marker(self, ordinal: i32) -> Option<String> {
+        let text = match self {
+            ListStyleType::None => return None,
+            ListStyleType::Disc => return Some("\u{2022} ".to_string()),
+            ListStyleType::Circle => return Some("\u{25e6} ".to_string()),
+            ListStyleType::Square => return Some("\u{25aa} ".to_string()),
+            ListStyleType::LowerAlpha | ListStyleType::UpperAlpha
+                if ordinal > 0 =>
+            {
+                // Alphabetic numbering has no zero: z is followed by aa.
+                let mut letters = Vec::new();
+                let mut n = ordinal as u32;
+                while n > 0 {
+                    n -= 1;
+                    letters.push(char::from(b'a' + (n % 26) as u8));
+                    n /= 26;
+                }
+                letters.iter().rev().collect()
+            }
+            ListStyleType::LowerRoman | ListStyleType::UpperRoman
+                if (1..4000).contains(&ordinal) =>
+            {
+                const NUMERALS: [(i32, &str); 13] = [
+                    (1000, "m"),
+                    (900, "cm"),
+                    (500, "d"),
+                    (400, "cd"),
+                    (100, "c"),
+                    (90, "xc"),
+                    (50, "l"),
+                    (40, "xl"),
+                    (10, "x"),
+                    (9, "ix"),
+                    (5, "v"),
+                    (4, "iv"),
+                    (1, "i"),
+                ];
+                let mut n = ordinal;
+                let mut text = String::new();
+                for (value, numeral) in NUMERALS {
+                    while n >= value {
+                        text.push_str(numeral);
+                        n -= value;
+                    }
+                }
+                text
+            }
+            _ => ordinal.to_string(),
+        };
+        let text = match self {
+            ListStyleType::UpperAlpha | ListStyleType::UpperRoman => {
+                text.to_ascii_uppercase()
+            }
+            _ => text,
+        };
+        Some(format!("{}. ", text))
+    }
+}
+
+// Whether the marker of a list item is placed outside its content box, or
+// as the first inline box of its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListStylePosition {
+    Outside,
+    Inside,
+}
+
+// The lines that decorate text, which any combination of may be drawn.
+// SEE: https://drafts.csswg.org/css-text-decor/#text-decoration-line-property
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextDecorationLine {
+    pub underline: bool,
+    pub overline: bool,
+    pub line_through: bool,
+}
+
+impl TextDecorationLine {
+    #[inline]
+    pub fn is_none(self) -> bool {
+        !(self.underline || self.overline || self.line_through)
+    }
+}
+
+// The decoration of a box, which is drawn across the text of the box and
+// its in-flow descendants in the color of the box.
+// SEE: https://drafts.csswg.org/css-text-decor/#line-decoration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextDecoration {
+    pub line: TextDecorationLine,
+    pub color: Color,
+}
+
+// The computed values of the properties used by layout and painting.
+// Computing them once per node spares later stages from looking properties
+// up by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    pub display: Display,
+    pub position: Position,
+    // The `top`, `right`, `bottom`, and `left` offsets of positioned boxes.
+    pub offset: Sides<LengthOrAuto>,
+    // The stack level of a positioned box. `None` is `auto`.
+    pub z_index: Option<i32>,
+    pub float: Float,
+    pub clear: Clear,
+    pub width: LengthOrAuto,
+    pub height: LengthOrAuto,
+    // The bounds that the used width and height are clamped to. `None` is
+    // the `none` value of the maximums.
+    pub min_width: LengthPercentage,
+    pub max_width: Option<LengthPercentage>,
+    pub min_height: LengthPercentage,
+    pub max_height: Option<LengthPercentage>,
+    pub margin: Sides<LengthOrAuto>,
+    pub padding: Sides<LengthPercentage>,
+    // The widths of the borders, which are zero for the sides whose
+    // border style is `none` or `hidden`.
+    pub border_width: Sides<f32>,
+    pub border_style: Sides<BorderStyle>,
+    pub border_color: Sides<Color>,
+    // The horizontal and vertical radii of the corners of the border box.
+    pub border_radius: Corners<(LengthPercentage, LengthPercentage)>,
+    // The outline, which is drawn outside of the border box at an offset
+    // without taking up space. Its width is zero unless its style is
+    // visible.
+    // SEE: https://drafts.csswg.org/css-ui/#outline-props
+    pub outline_width: f32,
+    pub outline_style: OutlineStyle,
+    pub outline_color: Color,
+    pub outline_offset: f32,
+    pub color: Color,
+    pub background_color: Color,
+    pub background_image: Option<BackgroundImage>,
+    // Whether the background image repeats horizontally and vertically.
+    pub background_repeat: (BackgroundRepeat, BackgroundRepeat),
+    // The offset of the background image in the padding box. A percentage
+    // aligns that point of the image with the same point of the box.
+    pub background_position: (LengthPercentage, LengthPercentage),
+    pub background_size: BackgroundSize,
+    pub box_shadow: Vec<BoxShadow>,
+    pub font_size: f32,
+    // The line height in pixels.
+    pub line_height: f32,
+    // The factor of the font size that `line_height` was computed from, if
+    // any. Descendants inherit the factor rather than the absolute value.
+    line_height_factor: Option<f32>,
+    pub font_weight: u16,
+    pub font_style: FontStyle,
+    pub font_family: String,
+    pub white_space: WhiteSpace,
+    pub vertical_align: VerticalAlign,
+    pub direction: Direction,
+    pub text_align: TextAlign,
+    pub list_style_type: ListStyleType,
+    pub list_style_position: ListStylePosition,
+    pub text_decoration_line: TextDecorationLine,
+    pub text_decoration_color: Color,
+    // The decorations of the box and the ancestors that propagate them to
+    // it, from the outermost one, which its text is drawn with.
+    pub text_decorations: Vec<TextDecoration>,
+    pub grid_template_columns: Vec<TrackSize>,
+    pub grid_template_rows: Vec<TrackSize>,
+    // The start and end lines of a grid item.
+    pub grid_column: (GridLine, GridLine),
+    pub grid_row: (GridLine, GridLine),
+    pub row_gap: f32,
+    // The gap between grid columns or the columns of a multi-column
+    // container, or `None` for `normal`, which is 1em in multi-column
+    // containers and zero elsewhere.
+    pub column_gap: Option<f32>,
+    // The number and the minimum width of the columns of a multi-column
+    // container, or `None` for `auto`. A box with either of them is a
+    // multi-column container.
+    // SEE: https://drafts.csswg.org/css-multicol/#the-number-and-width-of-columns
+    pub column_count: Option<u32>,
+    pub column_width: Option<f32>,
+    // The horizontal and vertical spacing between the cells of a table.
+    pub border_spacing: (f32, f32),
+    pub table_layout: TableLayout,
+    pub visibility: Visibility,
+    pub overflow_x: Overflow,
+    pub overflow_y: Overflow,
+    // The opacity of the box and its descendants as a group, between 0 and
+    // 1.
+    pub opacity: f32,
+    // The transform functions that are applied to the box from left to
+    // right, which are none if it is empty, and the point they are applied
+    // around, relative to the border box.
+    pub transform: Vec<TransformFunction>,
+    pub transform_origin: (LengthPercentage, LengthPercentage),
+    pub break_before: Break,
+    pub break_after: Break,
+    pub break_inside: Break,
+    // The minimum number of lines of a block left at the bottom of a page,
+    // and moved to the top of the next one.
+    pub orphans: u32,
+    pub widows: u32,
+}
+
+impl Default for ComputedStyle {
+    // The initial values of all properties.
+    fn default() -> Self {
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        Self {
+            display: Display::Inline,
+            position: Position::Static,
+            offset: Sides {
+                top: LengthOrAuto::Auto,
+                right: LengthOrAuto::Auto,
+                bottom: LengthOrAuto::Auto,
+                left: LengthOrAuto::Auto,
+            },
+            z_index: None,
+            float: Float::None,
+            clear: Clear::None,
+            width: LengthOrAuto::Auto,
+            height: LengthOrAuto::Auto,
+            min_width: LengthPercentage::Px(0.0),
+            max_width: None,
+            min_height: LengthPercentage::Px(0.0),
+            max_height: None,
+            margin: Sides {
+                top: LengthOrAuto::Px(0.0),
+                right: LengthOrAuto::Px(0.0),
+                bottom: LengthOrAuto::Px(0.0),
+                left: LengthOrAuto::Px(0.0),
+            },
+            padding: Sides::default(),
+            border_width: Sides::default(),
+            border_style: Sides {
+                top: BorderStyle::None,
+                right: BorderStyle::None,
+                bottom: BorderStyle::None,
+                left: BorderStyle::None,
+            },
+            border_color: Sides {
+                top: black,
+                right: black,
+                bottom: black,
+                left: black,
+            },
+            border_radius: Corners::default(),
+            outline_width: 0.0,
+            outline_style: OutlineStyle::Border(BorderStyle::None),
+            outline_color: black,
+            outline_offset: 0.0,
+            color: black,
+            background_color: Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+            background_image: None,
+            background_repeat: (
+                BackgroundRepeat::Repeat,
+                BackgroundRepeat::Repeat,
+            ),
+            background_position: (
+                LengthPercentage::Percent(0.0),
+                LengthPercentage::Percent(0.0),
+            ),
+            background_size: BackgroundSize::Size(None, None),
+            box_shadow: Vec::new(),
+            font_size: DEFAULT_FONT_SIZE,
+            line_height: DEFAULT_FONT_SIZE * NORMAL_LINE_HEIGHT,
+            line_height_factor: Some(NORMAL_LINE_HEIGHT),
+            font_weight: 400,
+            font_style: FontStyle::Normal,
+            font_family: "serif".to_string(),
+            white_space: WhiteSpace::Normal,
+            vertical_align: VerticalAlign::Baseline,
+            direction: Direction::Ltr,
+            text_align: TextAlign::Start,
+            list_style_type: ListStyleType::Disc,
+            list_style_position: ListStylePosition::Outside,
+            text_decoration_line: TextDecorationLine::default(),
+            text_decoration_color: black,
+            text_decorations: Vec::new(),
+            grid_template_columns: Vec::new(),
+            grid_template_rows: Vec::new(),
+            grid_column: (GridLine::Auto, GridLine::Auto),
+            grid_row: (GridLine::Auto, GridLine::Auto),
+            row_gap: 0.0,
+            column_gap: None,
+            column_count: None,
+            column_width: None,
+            border_spacing: (0.0, 0.0),
+            table_layout: TableLayout::Auto,
+            visibility: Visibility::Visible,
+            overflow_x: Overflow::Visible,
+            overflow_y: Overflow::Visible,
+            opacity: 1.0,
+            transform: Vec::new(),
+            transform_origin: (
+                LengthPercentage::Percent(50.0),
+                LengthPercentage::Percent(50.0),
+            ),
+            break_before: Break::Auto,
+            break_after: Break::Auto,
+            break_inside: Break::Auto,
+            orphans: 2,
+            widows: 2,
+        }
+    }
+}
+
+impl ComputedStyle {
+    // Check if the box is painted. Hidden boxes still take up space in
+    // layout, and their descendants may be visible again.
+    #[inline]
+    pub fn is_visible(&self) -> bool {
+        self.visibility == Visibility::Visible
+    }
+
+    // Check if the box is a scroll container, which clips its content and
+    // lets it be scrolled.
+    #[inline]
+    pub fn is_scroll_container(&self) -> bool {
+        self.overflow_x.clips() || self.overflow_y.clips()
+    }
+
+    // Compute the style of a node from its specified values.
+    // Inherited properties fall back to the parent's computed values, and
+    // relative lengths are resolved to pixels, so that layout only ever sees
+    // absolute lengths. `root_font_size` is the font size of the root
+    // element, or the initial font size when computing the root itself,
+    // and `viewport` is the width and height of the viewport.
+    //
+    // This is the computed value stage between the specified values of the
+    // cascade and the used values of layout, which resolves percentages
+    // and `auto` against the containing block.
+    pub fn compute(
+        values: &PropertyMap,
+        parent: Option<&ComputedStyle>,
+        root_font_size: f32,
+        viewport: (f32, f32),
+    ) -> Self {
+        let initial = ComputedStyle {
+            font_size: root_font_size,
+            line_height: root_font_size * NORMAL_LINE_HEIGHT,
+            ..ComputedStyle::default()
+        };
+        let inherited = parent.unwrap_or(&initial);
+        let specified = Specified(values);
+
+        // The color is resolved first, because `currentColor` in the other
+        // properties refers to it. In `color` itself, it refers to the
+        // inherited color.
+        let color = match css_wide_keyword(values.get("color")) {
+            Some("initial") => initial.color,
+            _ => resolve_color(specified.get("color"), inherited.color)
+                .unwrap_or(inherited.color),
+        };
+
+        // The font size is resolved next, because `em` lengths of the other
+        // properties are relative to it.
+        let font_size = match css_wide_keyword(values.get("font-size")) {
+            Some("initial") => initial.font_size,
+            _ => font_size(
+                specified.get("font-size"),
+                inherited.font_size,
+                root_font_size,
+                viewport,
+            ),
+        };
+        let metrics = FontMetrics {
+            font_size,
+            root_font_size,
+            viewport,
+        };
+        let inherited_line_height = match inherited.line_height_factor {
+            Some(factor) => (factor * font_size, Some(factor)),
+            None => (inherited.line_height, None),
+        };
+        let (line_height, line_height_factor) = match specified
+            .get("line-height")
+        {
+            Some(Value::Number(factor)) => (factor * font_size, Some(*factor)),
+            Some(Value::Keyword(k)) if k == "normal" => {
+                (NORMAL_LINE_HEIGHT * font_size, Some(NORMAL_LINE_HEIGHT))
+            }
+            Some(Value::Length(percent, Unit::Percent)) => {
+                (percent / 100.0 * font_size, None)
+            }
+            Some(value) => metrics
+                .resolve(value)
+                .map_or(inherited_line_height, |px| (px, None)),
+            None => inherited_line_height,
+        };
+        let length_or_auto = |value: Option<&Value>, default| match value {
+            Some(Value::Keyword(k)) if k == "auto" => LengthOrAuto::Auto,
+            Some(Value::Length(percent, Unit::Percent)) => {
+                LengthOrAuto::Percent(*percent)
+            }
+            Some(value) => {
+                metrics.resolve(value).map_or(default, LengthOrAuto::Px)
+            }
+            None => default,
+        };
+        let length = |value: Option<&Value>| {
+            value
+                .and_then(|value| metrics.resolve(value))
+                .unwrap_or(0.0)
+        };
+        let length_percentage = |value: Option<&Value>| match value {
+            Some(Value::Length(percent, Unit::Percent)) => {
+                Some(LengthPercentage::Percent(*percent))
+            }
+            Some(value) => metrics.resolve(value).map(LengthPercentage::Px),
+            None => None,
+        };
+
+        let position = match specified.get("position") {
+            Some(Value::Keyword(k)) if k == "relative" => Position::Relative,
+            Some(Value::Keyword(k)) if k == "absolute" => Position::Absolute,
+            Some(Value::Keyword(k)) if k == "fixed" => Position::Fixed,
+            _ => Position::Static,
+        };
+        // Boxes out of normal flow don't float.
+        let float = match specified.get("float") {
+            _ if position.is_out_of_flow() => Float::None,
+            Some(Value::Keyword(k)) if k == "left" => Float::Left,
+            Some(Value::Keyword(k)) if k == "right" => Float::Right,
+            _ => Float::None,
+        };
+        // Floats and boxes out of normal flow are laid out as blocks.
+        let display = if float != Float::None || position.is_out_of_flow() {
+            display(&specified).blockified()
+        } else {
+            display(&specified)
+        };
+        let offset =
+            |name| length_or_auto(specified.get(name), LengthOrAuto::Auto);
+
+        let mut style = Self {
+            display,
+            position,
+            offset: Sides {
+                top: offset("top"),
+                right: offset("right"),
+                bottom: offset("bottom"),
+                left: offset("left"),
+            },
+            z_index: match specified.get("z-index") {
+                Some(Value::Number(z)) if z.fract() == 0.0 => Some(*z as i32),
+                _ => None,
+            },
+            float,
+            clear: match specified.get("clear") {
+                Some(Value::Keyword(k)) if k == "left" => Clear::Left,
+                Some(Value::Keyword(k)) if k == "right" => Clear::Right,
+                Some(Value::Keyword(k)) if k == "both" => Clear::Both,
+                _ => Clear::None,
+            },
+            width: match specified.get("width") {
+                Some(Value::Keyword(k)) if k == "min-content" => {
+                    LengthOrAuto::MinContent
+                }
+                Some(Value::Keyword(k)) if k == "max-content" => {
+                    LengthOrAuto::MaxContent
+                }
+                Some(Value::Keyword(k)) if k == "fit-content" => {
+                    LengthOrAuto::FitContent
+                }
+                value => length_or_auto(value, initial.width),
+            },
+            // Heights don't depend on the intrinsic widths, so they are
+            // `auto` with these keywords.
+            height: match specified.get("height") {
+                Some(Value::Keyword(k))
+                    if matches!(
+                        k.as_str(),
+                        "min-content" | "max-content" | "fit-content"
+                    ) =>
+                {
+                    LengthOrAuto::Auto
+                }
+                value => length_or_auto(value, initial.height),
+            },
+            // `auto` minimums are zero outside of flex and grid items.
+            min_width: length_percentage(specified.get("min-width"))
+                .unwrap_or_default(),
+            max_width: length_percentage(specified.get("max-width")),
+            min_height: length_percentage(specified.get("min-height"))
+                .unwrap_or_default(),
+            max_height: length_percentage(specified.get("max-height")),
+            margin: sides(&specified, "margin", "", |value| {
+                length_or_auto(value, LengthOrAuto::Px(0.0))
+            }),
+            padding: sides(&specified, "padding", "", |value| {
+                length_percentage(value).unwrap_or_default()
+            }),
+            border_width: sides(&specified, "border", "-width", |value| {
+                border_width(value, metrics)
+            }),
+            border_style: sides(&specified, "border", "-style", border_style),
+            // The initial value of the border colors is `currentColor`.
+            border_color: sides(&specified, "border", "-color", |value| {
+                resolve_color(value, color).unwrap_or(color)
+            }),
+            // A single radius is both the horizontal and vertical one.
+            border_radius: corners(&specified, "border", "-radius", |value| {
+                let radius = |value: &Value| {
+                    length_percentage(Some(value)).unwrap_or_default()
+                };
+                match value {
+                    Some(Value::List(radii)) => match radii.as_slice() {
+                        [horizontal, vertical] => {
+                            (radius(horizontal), radius(vertical))
+                        }
+                        _ => Default::default(),
+                    },
+                    Some(value) => (radius(value), radius(value)),
+                    None => Default::default(),
+                }
+            }),
+            outline_width: border_width(
+                specified.get("outline-width"),
+                metrics,
+            ),
+            outline_style: match specified.get("outline-style") {
+                Some(Value::Keyword(k)) if k == "auto" => OutlineStyle::Auto,
+                value => OutlineStyle::Border(border_style(value)),
+            },
+            // The initial value of the outline color is `currentColor`.
+            outline_color: resolve_color(specified.get("outline-color"), color)
+                .unwrap_or(color),
+            outline_offset: specified
+                .get("outline-offset")
+                .and_then(|value| metrics.resolve(value))
+                .unwrap_or(0.0),
+            background_color: resolve_color(
+                specified.get("background-color"),
+                color,
+            )
+            .unwrap_or(initial.background_color),
+            background_image: background_image(
+                specified.get("background-image"),
+                color,
+                metrics,
+            ),
+            background_repeat: background_repeat(
+                specified.get("background-repeat"),
+            ),
+            background_position: position_value(
+                specified.get("background-position"),
+                metrics,
+            )
+            .unwrap_or(initial.background_position),
+            background_size: background_size(
+                specified.get("background-size"),
+                metrics,
+            ),
+            box_shadow: box_shadow(specified.get("box-shadow"), color, metrics)
+                .into_iter()
+                .collect(),
+            color,
+            font_size,
+            line_height,
+            line_height_factor,
+            font_weight: match specified.get("font-weight") {
+                Some(Value::Keyword(k)) if k == "bold" => 700,
+                Some(Value::Keyword(k)) if k == "normal" => 400,
+                Some(Value::Number(weight)) => *weight as u16,
+                _ => inherited.font_weight,
+            },
+            font_style: match specified.get("font-style") {
+                Some(Value::Keyword(k)) if k == "italic" || k == "oblique" => {
+                    FontStyle::Italic
+                }
+                Some(Value::Keyword(k)) if k == "normal" => FontStyle::Normal,
+                _ => inherited.font_style,
+            },
+            font_family: match specified.get("font-family") {
+                Some(Value::Keyword(family)) => family.clone(),
+                _ => inherited.font_family.clone(),
+            },
+            white_space: match specified.get("white-space") {
+                Some(Value::Keyword(k)) => match k.as_str() {
+                    "normal" => WhiteSpace::Normal,
+                    "nowrap" => WhiteSpace::Nowrap,
+                    "pre" => WhiteSpace::Pre,
+                    "pre-wrap" => WhiteSpace::PreWrap,
+                    "pre-line" => WhiteSpace::PreLine,
+                    _ => inherited.white_space,
+                },
+                _ => inherited.white_space,
+            },
+            vertical_align: vertical_align(specified.get("vertical-align")),
+            direction: match specified.get("direction") {
+                Some(Value::Keyword(k)) if k == "ltr" => Direction::Ltr,
+                Some(Value::Keyword(k)) if k == "rtl" => Direction::Rtl,
+                _ => inherited.direction,
+            },
+            text_align: match specified.get("text-align") {
+                Some(Value::Keyword(k)) => match k.as_str() {
+                    "start" => TextAlign::Start,
+                    "end" => TextAlign::End,
+                    "left" => TextAlign::Left,
+                    "right" => TextAlign::Right,
+                    "center" => TextAlign::Center,
+                    "justify" => TextAlign::Justify,
+                    _ => inherited.text_align,
+                },
+                _ => inherited.text_align,
+            },
+            list_style_type: match specified.get("list-style-type") {
+                Some(Value::Keyword(k)) => match k.as_str() {
+                    "none" => ListStyleType::None,
+                    "disc" => ListStyleType::Disc,
+                    "circle" => ListStyleType::Circle,
+                    "square" => ListStyleType::Square,
+                    "decimal" => ListStyleType::Decimal,
+                    "lower-alpha" | "lower-latin" => ListStyleType::LowerAlpha,
+                    "upper-alpha" | "upper-latin" => ListStyleType::UpperAlpha,
+                    "lower-roman" => ListStyleType::LowerRoman,
+                    "upper-roman" => ListStyleType::UpperRoman,
+                    _ => inherited.list_style_type,
+                },
+                _ => inherited.list_style_type,
+            },
+            list_style_position: match specified.get("list-style-position") {
+                Some(Value::Keyword(k)) if k == "outside" => {
+                    ListStylePosition::Outside
+                }
+                Some(Value::Keyword(k)) if k == "inside" => {
+                    ListStylePosition::Inside
+                }
+                _ => inherited.list_style_position,
+            },
+            text_decoration_line: text_decoration_line(
+                specified.get("text-decoration-line"),
+            ),
+            // The initial value of the decoration color is `currentColor`.
+            text_decoration_color: resolve_color(
+                specified.get("text-decoration-color"),
+                color,
+            )
+            .unwrap_or(color),
+            text_decorations: Vec::new(),
+            grid_template_columns: track_list(
+                specified.get("grid-template-columns"),
+                metrics,
+            ),
+            grid_template_rows: track_list(
+                specified.get("grid-template-rows"),
+                metrics,
+            ),
+            grid_column: (
+                grid_line(specified.get("grid-column-start")),
+                grid_line(specified.get("grid-column-end")),
+            ),
+            grid_row: (
+                grid_line(specified.get("grid-row-start")),
+                grid_line(specified.get("grid-row-end")),
+            ),
+            // TODO: Resolve percentage gaps against the grid container.
+            row_gap: length(specified.get("row-gap")),
+            column_gap: specified
+                .get("column-gap")
+                .and_then(|value| metrics.resolve(value)),
+            column_count: match specified.get("column-count") {
+                Some(Value::Number(n)) if *n >= 1.0 && n.fract() == 0.0 => {
+                    Some(*n as u32)
+                }
+                _ => None,
+            },
+            column_width: specified
+                .get("column-width")
+                .and_then(|value| metrics.resolve(value))
+                .filter(|&width| width > 0.0),
+            border_spacing: match specified.get("border-spacing") {
+                Some(Value::List(values)) => match values.as_slice() {
+                    [horizontal, vertical] => {
+                        match (
+                            metrics.resolve(horizontal),
+                            metrics.resolve(vertical),
+                        ) {
+                            (Some(horizontal), Some(vertical)) => {
+                                (horizontal, vertical)
+                            }
+                            _ => inherited.border_spacing,
+                        }
+                    }
+                    _ => inherited.border_spacing,
+                },
+                Some(value) => metrics
+                    .resolve(value)
+                    .map_or(inherited.border_spacing, |spacing| {
+                        (spacing, spacing)
+                    }),
+                None => inherited.border_spacing,
+            },
+            table_layout: match specified.get("table-layout") {
+                Some(Value::Keyword(k)) if k == "fixed" => TableLayout::Fixed,
+                _ => TableLayout::Auto,
+            },
+            visibility: match specified.get("visibility") {
+                Some(Value::Keyword(k)) if k == "visible" => {
+                    Visibility::Visible
+                }
+                Some(Value::Keyword(k)) if k == "hidden" => Visibility::Hidden,
+                Some(Value::Keyword(k)) if k == "collapse" => {
+                    Visibility::Collapse
+                }
+                _ => inherited.visibility,
+            },
+            overflow_x: overflow(specified.get("overflow-x")),
+            overflow_y: overflow(specified.get("overflow-y")),
+            opacity: match specified.get("opacity") {
+                Some(Value::Number(opacity)) => opacity.clamp(0.0, 1.0),
+                Some(Value::Length(percent, Unit::Percent)) => {
+                    (percent / 100.0).clamp(0.0, 1.0)
+                }
+                _ => initial.opacity,
+            },
+            transform: transform_list(specified.get("transform"), metrics),
+            transform_origin: position_value(
+                specified.get("transform-origin"),
+                metrics,
+            )
+            .unwrap_or((
+                LengthPercentage::Percent(50.0),
+                LengthPercentage::Percent(50.0),
+            )),
+            break_before: break_between(specified.get("break-before")),
+            break_after: break_between(specified.get("break-after")),
+            break_inside: match specified.get("break-inside") {
+                Some(Value::Keyword(k)) if k.starts_with("avoid") => {
+                    Break::Avoid
+                }
+                _ => Break::Auto,
+            },
+            orphans: match specified.get("orphans") {
+                Some(Value::Number(n)) if *n >= 1.0 => *n as u32,
+                _ => inherited.orphans,
+            },
+            widows: match specified.get("widows") {
+                Some(Value::Number(n)) if *n >= 1.0 => *n as u32,
+                _ => inherited.widows,
+            },
+        };
+
+        // Without a value, inherited properties take the parent's value and
+        // the others their initial value, which is also what `unset` means.
+        // `inherit` and `initial` only differ from that for the other kind
+        // of property.
+        for (name, value) in values {
+            match css_wide_keyword(Some(value)) {
+                Some("inherit") => style.copy_property(name, inherited),
+                Some("initial") => {
+                    let initial = ComputedStyle {
+                        font_size: style.font_size,
+                        line_height: style.font_size * NORMAL_LINE_HEIGHT,
+                        ..ComputedStyle::default()
+                    };
+                    style.copy_property(name, &initial);
+                }
+                _ => {}
+            }
+        }
+
+        // Decorations propagate to the in-flow content of a box, but not to
+        // atomic inlines, floats, and boxes out of normal flow, which are
+        // decorated by their own decorations only.
+        // SEE: https://drafts.csswg.org/css-text-decor/#line-decoration
+        let atomic_inline = matches!(
+            style.display,
+            Display::InlineBlock
+                | Display::InlineFlex
+                | Display::InlineGrid
+                | Display::InlineTable
+        );
+        let propagated = !atomic_inline
+            && style.float == Float::None
+            && !style.position.is_out_of_flow();
+        if let Some(parent) = parent.filter(|_| propagated) {
+            style.text_decorations = parent.text_decorations.clone();
+        }
+        if !style.text_decoration_line.is_none() {
+            style.text_decorations.push(TextDecoration {
+                line: style.text_decoration_line,
+                color: style.text_decoration_color,
+            });
+        }
+
+        // A border without a style has no width, whatever width it is
+        // given.
+        let Sides {
+            top,
+            right,
+            bottom,
+            left,
+        } = style.border_style;
+        for (width, border_style) in [
+            (&mut style.border_width.top, top),
+            (&mut style.border_width.right, right),
+            (&mut style.border_width.bottom, bottom),
+            (&mut style.border_width.left, left),
+        ] {
+            if !border_style.is_visible() {
+                *width = 0.0;
+            }
+        }
+        if !style.outline_style.is_visible() {
+            style.outline_width = 0.0;
+        }
+
+        // A box can't clip in one direction only, so `visible` beside a
+        // value that clips is `auto`.
+        // SEE: https://drafts.csswg.org/css-overflow-3/#overflow-control
+        if style.overflow_x.clips() != style.overflow_y.clips() {
+            for overflow in [&mut style.overflow_x, &mut style.overflow_y] {
+                if *overflow == Overflow::Visible {
+                    *overflow = Overflow::Auto;
+                }
+            }
+        }
+        style
+    }
+
+    // Round the border widths down to whole device pixels, so that borders
+    // are drawn crisp, but keep a border at least one device pixel wide.
+    // SEE: https://drafts.csswg.org/css-values-4/#snap-a-length-as-a-border-width
+    pub fn snap_border_widths(&mut self, device_pixel_ratio: f32) {
+        let Sides {
+            top,
+            right,
+            bottom,
+            left,
+        } = &mut self.border_width;
+        for width in [top, right, bottom, left] {
+            let device = *width * device_pixel_ratio;
+            if device > 0.0 {
+                *width = device.floor().max(1.0) / device_pixel_ratio;
+            }
+        }
+    }
+
+    // Compute the style of an anonymous box, which has no specified values
+    // and so inherits from its parent or takes the initial values. Without
+    // values, no lengths are resolved against the viewport.
+    pub fn anonymous(parent: &ComputedStyle, display: Display) -> Self {
+        Self {
+            display,
+            ..Self::compute(
+                &PropertyMap::new(),
+                Some(parent),
+                parent.font_size,
+                (0.0, 0.0),
+            )
+        }
+    }
+
+    // Copy the computed value of a property from another style.
+    // `color` and `font-size` are resolved up front, because other values
+    // depend on them.
+    fn copy_property(&mut self, name: &str, source: &ComputedStyle) {
+        match name {
+            "display" => self.display = source.display,
+            "position" => self.position = source.position,
+            "top" => self.offset.top = source.offset.top,
+            "z-index" => self.z_index = source.z_index,
+            "right" => self.offset.right = source.offset.right,
+            "bottom" => self.offset.bottom = source.offset.bottom,
+            "left" => self.offset.left = source.offset.left,
+            "float" => self.float = source.float,
+            "clear" => self.clear = source.clear,
+            "width" => self.width = source.width,
+            "height" => self.height = source.height,
+            "min-width" => self.min_width = source.min_width,
+            "max-width" => self.max_width = source.max_width,
+            "min-height" => self.min_height = source.min_height,
+            "max-height" => self.max_height = source.max_height,
+            "background-color" => {
+                self.background_color = source.background_color
+            }
+            "background-image" => {
+                self.background_image = source.background_image.clone()
+            }
+            "background-repeat" => {
+                self.background_repeat = source.background_repeat
+            }
+            "background-position" => {
+                self.background_position = source.background_position
+            }
+            "background-size" => self.background_size = source.background_size,
+            "box-shadow" => self.box_shadow.clone_from(&source.box_shadow),
+            "line-height" => {
+                self.line_height_factor = source.line_height_factor;
+                self.line_height = source
+                    .line_height_factor
+                    .map_or(source.line_height, |factor| {
+                        factor * self.font_size
+                    });
+            }
+            "font-weight" => self.font_weight = source.font_weight,
+            "font-style" => self.font_style = source.font_style,
+            "font-family" => self.font_family = source.font_family.clone(),
+            "white-space" => self.white_space = source.white_space,
+            "grid-template-columns" => {
+                self.grid_template_columns =
+                    source.grid_template_columns.clone()
+            }
+            "grid-template-rows" => {
+                self.grid_template_rows = source.grid_template_rows.clone()
+            }
+            "grid-column-start" => self.grid_column.0 = source.grid_column.0,
+            "grid-column-end" => self.grid_column.1 = source.grid_column.1,
+            "grid-row-start" => self.grid_row.0 = source.grid_row.0,
+            "grid-row-end" => self.grid_row.1 = source.grid_row.1,
+            "row-gap" => self.row_gap = source.row_gap,
+            "column-gap" => self.column_gap = source.column_gap,
+            "column-count" => self.column_count = source.column_count,
+            "column-width" => self.column_width = source.column_width,
+            "border-spacing" => self.border_spacing = source.border_spacing,
+            "table-layout" => self.table_layout = source.table_layout,
+            "visibility" => self.visibility = source.visibility,
+            "vertical-align" => self.vertical_align = source.vertical_align,
+            "direction" => self.direction = source.direction,
+            "text-align" => self.text_align = source.text_align,
+            "list-style-type" => self.list_style_type = source.list_style_type,
+            "list-style-position" => {
+                self.list_style_position = source.list_style_position
+            }
+            "outline-width" => self.outline_width = source.outline_width,
+            "outline-style" => self.outline_style = source.outline_style,
+            "outline-color" => self.outline_color = source.outline_color,
+            "outline-offset" => self.outline_offset = source.outline_offset,
+            "text-decoration-line" => {
+                self.text_decoration_line = source.text_decoration_line
+            }
+            "text-decoration-color" => {
+                self.text_decoration_color = source.text_decoration_color
+            }
+            "overflow-x" => self.overflow_x = source.overflow_x,
+            "overflow-y" => self.overflow_y = source.overflow_y,
+            "opacity" => self.opacity = source.opacity,
+            "transform" => self.transform = source.transform.clone(),
+            "transform-origin" => {
+                self.transform_origin = source.transform_origin
+            }
+            "break-before" => self.break_before = source.break_before,
+            "break-after" => self.break_after = source.break_after,
+            "break-inside" => self.break_inside = source.break_inside,
+            "orphans" => self.orphans = source.orphans,
+            "widows" => self.widows = source.widows,
+            _ => {
+                if let Some(side) = name.strip_prefix("margin-") {
+                    copy_side(&mut self.margin, &source.margin, side);
+                } else if let Some(side) = name.strip_prefix("padding-") {
+                    copy_side(&mut self.padding, &source.padding, side);
+                } else if let Some(side) = name
+                    .strip_prefix("border-")
+                    .and_then(|name| name.strip_suffix("-width"))
+                {
+                    copy_side(
+                        &mut self.border_width,
+                        &source.border_width,
+                        side,
+                    );
+                } else if let Some(side) = name
+                    .strip_prefix("border-")
+                    .and_then(|name| name.strip_suffix("-style"))
+                {
+                    copy_side(
+                        &mut self.border_style,
+                        &source.border_style,
+                        side,
+                    );
+                } else if let Some(corner) = name
+                    .strip_prefix("border-")
+                    .and_then(|name| name.strip_suffix("-radius"))
+                {
+                    let (target, source) =
+                        (&mut self.border_radius, &source.border_radius);
+                    match corner {
+                        "top-left" => target.top_left = source.top_left,
+                        "top-right" => target.top_right = source.top_right,
+                        "bottom-right" => {
+                            target.bottom_right = source.bottom_right
+                        }
+                        "bottom-left" => {
+                            target.bottom_left = source.bottom_left
+                        }
+                        _ => {}
+                    }
+                } else if let Some(side) = name
+                    .strip_prefix("border-")
+                    .and_then(|name| name.strip_suffix("-color"))
+                {
+                    copy_side(
+                        &mut self.border_color,
+                        &source.border_color,
+                        side,
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Copy the value of one side, such as `top`.
+fn copy_side<T: Copy>(target: &mut Sides<T>, source: &Sides<T>, side: &str) {
+    match side {
+        "top" => target.top = source.top,
+        "right" => target.right = source.right,
+        "bottom" => target.bottom = source.bottom,
+        "left" => target.left = source.left,
+        _ => {}
+    }
+}
+
+// The specified values with the CSS-wide keywords left out, so that
+// properties with them fall back like unspecified ones.
+struct Specified<'v>(&'v PropertyMap);
+
+impl<'v> Specified<'v> {
+    #[inline]
+    fn get(&self, name: &str) -> Option<&'v Value> {
+        self.0
+            .get(name)
+            .filter(|value| css_wide_keyword(Some(value)).is_none())
+    }
+}
+
+// Get the CSS-wide keyword of a value, if it is one.
+pub fn css_wide_keyword(value: Option<&Value>) -> Option<&str> {
+    match value {
+        Some(Value::Keyword(k))
+            if matches!(k.as_str(), "inherit" | "initial" | "unset") =>
+        {
+            Some(k)
+        }
+        _ => None,
+    }
+}
+
+// The font sizes and the viewport size that relative lengths are resolved
+// against.
+#[derive(Debug, Clone, Copy)]
+struct FontMetrics {
+    font_size: f32,
+    root_font_size: f32,
+    viewport: (f32, f32),
+}
+
+impl FontMetrics {
+    // Resolve a length to pixels.
+    // Return `None` for percentages and values that are not lengths.
+    fn resolve(&self, value: &Value) -> Option<f32> {
+        match *value {
+            Value::Length(px, Unit::Px) => Some(px),
+            Value::Length(em, Unit::Em) => Some(em * self.font_size),
+            Value::Length(rem, Unit::Rem) => Some(rem * self.root_font_size),
+            Value::Length(length, unit) => {
+                viewport_length(length, unit, self.viewport)
+            }
+            // Zero is the only length allowed without a unit.
+            Value::Number(0.0) => Some(0.0),
+            _ => None,
+        }
+    }
+}
+
+// Compute a border width, where the keywords are the widths of typical
+// thin, medium, and thick lines. The initial width is `medium`.
+// SEE: https://www.w3.org/TR/CSS22/box.html#border-width-properties
+fn border_width(value: Option<&Value>, metrics: FontMetrics) -> f32 {
+    match value {
+        Some(Value::Keyword(k)) if k == "thin" => 1.0,
+        Some(Value::Keyword(k)) if k == "thick" => 5.0,
+        Some(value) => metrics
+            .resolve(value)
+            .filter(|width| *width >= 0.0)
+            .unwrap_or(3.0),
+        None => 3.0,
+    }
+}
+
+// Compute a border style, which is `none` unless it is a keyword of one.
+fn border_style(value: Option<&Value>) -> BorderStyle {
+    match value {
+        Some(Value::Keyword(k)) => match k.as_str() {
+            "hidden" => BorderStyle::Hidden,
+            "dotted" => BorderStyle::Dotted,
+            "dashed" => BorderStyle::Dashed,
+            "solid" => BorderStyle::Solid,
+            "double" => BorderStyle::Double,
+            "groove" => BorderStyle::Groove,
+            "ridge" => BorderStyle::Ridge,
+            "inset" => BorderStyle::Inset,
+            "outset" => BorderStyle::Outset,
+            _ => BorderStyle::None,
+        },
+        _ => BorderStyle::None,
+    }
+}
+
+// Compute `text-decoration-line`, which is `none` or a list of lines.
+fn text_decoration_line(value: Option<&Value>) -> TextDecorationLine {
+    let values = match value {
+        Some(Value::List(values)) => values.as_slice(),
+        Some(value) => std::slice::from_ref(value),
+        None => &[],
+    };
+    let mut line = TextDecorationLine::default();
+    for value in values {
+        match value {
+            Value::Keyword(k) if k == "underline" => line.underline = true,
+            Value::Keyword(k) if k == "overline" => line.overline = true,
+            Value::Keyword(k) if k == "line-through" => {
+                line.line_through = true
+            }
+            _ => {}
+        }
+    }
+    line
+}
+
+// Resolve a color value, replacing `currentColor` with `current_color`.
+// Return `None` for values that are not colors.
+fn resolve_color(value: Option<&Value>, current_color: Color) -> Option<Color> {
+    match value {
+        Some(Value::ColorValue(color)) => Some(*color),
+        Some(Value::Keyword(k)) if k.eq_ignore_ascii_case("currentcolor") => {
+            Some(current_color)
+        }
+        _ => None,
+    }
+}
+
+// Resolve a viewport-relative length to pixels.
+// Return `None` for other units.
+fn viewport_length(
+    length: f32,
+    unit: Unit,
+    viewport: (f32, f32),
+) -> Option<f32> {
+    let (width, height) = viewport;
+    let basis = match unit {
+        Unit::Vw => width,
+        Unit::Vh => height,
+        Unit::Vmin => width.min(height),
+        Unit::Vmax => width.max(height),
+        _ => return None,
+    };
+    Some(length / 100.0 * basis)
+}
+
+// Compute the font size from the parent's font size.
+fn font_size(
+    value: Option<&Value>,
+    parent: f32,
+    root: f32,
+    viewport: (f32, f32),
+) -> f32 {
+    match value {
+        Some(Value::Length(px, Unit::Px)) => *px,
+        Some(Value::Length(em, Unit::Em)) => em * parent,
+        Some(Value::Length(rem, Unit::Rem)) => rem * root,
+        Some(Value::Length(percent, Unit::Percent)) => percent / 100.0 * parent,
+        Some(&Value::Length(length, unit)) => {
+            viewport_length(length, unit, viewport).unwrap_or(parent)
+        }
+        Some(Value::Keyword(k)) => match k.as_str() {
+            "xx-small" => root * 0.6,
+            "x-small" => root * 0.75,
+            "small" => root * 0.89,
+            "medium" => root,
+            "large" => root * 1.2,
+            "x-large" => root * 1.5,
+            "xx-large" => root * 2.0,
+            "smaller" => parent / 1.2,
+            "larger" => parent * 1.2,
+            _ => parent,
+        },
+        _ => parent,
+    }
+}
+
+// Compute a list of grid track sizes, such as `100px 1fr 2fr`.
+// TODO: Support `repeat()` and `minmax()`.
+fn track_list(value: Option<&Value>, metrics: FontMetrics) -> Vec<TrackSize> {
+    let track = |value: &Value| match *value {
+        Value::Length(fr, Unit::Fr) => TrackSize::Fr(fr),
+        Value::Length(percent, Unit::Percent) => TrackSize::Percent(percent),
+        ref value => metrics
+            .resolve(value)
+            .map_or(TrackSize::Auto, TrackSize::Px),
+    };
+    match value {
+        Some(Value::List(values)) => values.iter().map(track).collect(),
+        Some(Value::Keyword(k)) if k == "none" => Vec::new(),
+        Some(value) => vec![track(value)],
+        None => Vec::new(),
+    }
+}
+
+// Compute a list of transform functions, such as
+// `translate(10px, 50%) rotate(45deg)`. A list with an invalid function is
+// invalid as a whole, and so is `none`.
+fn transform_list(
+    value: Option<&Value>,
+    metrics: FontMetrics,
+) -> Vec<TransformFunction> {
+    let length_percentage = |value: &Value| match *value {
+        Value::Length(percent, Unit::Percent) => {
+            Some(LengthPercentage::Percent(percent))
+        }
+        ref value => metrics.resolve(value).map(LengthPercentage::Px),
+    };
+    let number = |value: &Value| match *value {
+        Value::Number(number) => Some(number),
+        _ => None,
+    };
+    let function = |value: &Value| {
+        let Value::Function(name, arguments) = value else {
+            return None;
+        };
+        let zero = LengthPercentage::Px(0.0);
+        let function = match (name.as_str(), arguments.as_slice()) {
+            ("translate", [x]) => {
+                TransformFunction::Translate(length_percentage(x)?, zero)
+            }
+            ("translate", [x, y]) => TransformFunction::Translate(
+                length_percentage(x)?,
+                length_percentage(y)?,
+            ),
+            ("translatex", [x]) => {
+                TransformFunction::Translate(length_percentage(x)?, zero)
+            }
+            ("translatey", [y]) => {
+                TransformFunction::Translate(zero, length_percentage(y)?)
+            }
+            ("scale", [s]) => TransformFunction::Scale(number(s)?, number(s)?),
+            ("scale", [x, y]) => {
+                TransformFunction::Scale(number(x)?, number(y)?)
+            }
+            ("scalex", [x]) => TransformFunction::Scale(number(x)?, 1.0),
+            ("scaley", [y]) => TransformFunction::Scale(1.0, number(y)?),
+            ("rotate", [angle]) => TransformFunction::Rotate(radians(angle)?),
+            ("skew", [x]) => TransformFunction::Skew(radians(x)?, 0.0),
+            ("skew", [x, y]) => {
+                TransformFunction::Skew(radians(x)?, radians(y)?)
+            }
+            ("skewx", [x]) => TransformFunction::Skew(radians(x)?, 0.0),
+            ("skewy", [y]) => TransformFunction::Skew(0.0, radians(y)?),
+            ("matrix", [a, b, c, d, e, f]) => TransformFunction::Matrix([
+                number(a)?,
+                number(b)?,
+                number(c)?,
+                number(d)?,
+                number(e)?,
+                number(f)?,
+            ]),
+            _ => return None,
+        };
+        Some(function)
+    };
+    match value {
+        Some(Value::List(values)) => {
+            values.iter().map(function).collect::<Option<_>>()
+        }
+        Some(value) => function(value).map(|function| vec![function]),
+        None => None,
+    }
+    .unwrap_or_default()
+}
+
+// Compute an angle in radians. Zero is the only angle allowed without a
+// unit.
+fn radians(value: &Value) -> Option<f32> {
+    use std::f32::consts::PI;
+    match *value {
+        Value::Length(deg, Unit::Deg) => Some(deg.to_radians()),
+        Value::Length(rad, Unit::Rad) => Some(rad),
+        Value::Length(grad, Unit::Grad) => Some(grad / 200.0 * PI),
+        Value::Length(turn, Unit::Turn) => Some(turn * 2.0 * PI),
+        Value::Number(0.0) => Some(0.0),
+        _ => None,
+    }
+}
+
+// Compute a position, such as the transform origin or the background
+// position, like `left top` or `10px 50%`. With a single value, the other
+// one is `center`.
+fn position_value(
+    value: Option<&Value>,
+    metrics: FontMetrics,
+) -> Option<(LengthPercentage, LengthPercentage)> {
+    let center = LengthPercentage::Percent(50.0);
+    let offset = |value: &Value| match *value {
+        Value::Keyword(ref k) => match k.as_str() {
+            "left" | "top" => Some(LengthPercentage::Percent(0.0)),
+            "center" => Some(center),
+            "right" | "bottom" => Some(LengthPercentage::Percent(100.0)),
+            _ => None,
+        },
+        Value::Length(percent, Unit::Percent) => {
+            Some(LengthPercentage::Percent(percent))
+        }
+        ref value => metrics.resolve(value).map(LengthPercentage::Px),
+    };
+    let is_vertical = |value: &Value| matches!(value, Value::Keyword(k) if k == "top" || k == "bottom");
+    let is_horizontal = |value: &Value| matches!(value, Value::Keyword(k) if k == "left" || k == "right");
+    match value {
+        // Keywords may be given in either order, such as `top left`.
+        Some(Value::List(values)) => match values.as_slice() {
+            [y, x] if is_vertical(y) || is_horizontal(x) => {
+                offset(x).zip(offset(y))
+            }
+            [x, y] => offset(x).zip(offset(y)),
+            _ => None,
+        },
+        Some(y) if is_vertical(y) => offset(y).map(|y| (center, y)),
+        Some(x) => offset(x).map(|x| (x, center)),
+        None => None,
+    }
+}
+
+// Compute a background image, which is a URL or a gradient.
+fn background_image(
+    value: Option<&Value>,
+    current_color: Color,
+    metrics: FontMetrics,
+) -> Option<BackgroundImage> {
+    let (name, arguments) = match value? {
+        Value::Url(url) => return Some(BackgroundImage::Url(url.clone())),
+        Value::Function(name, arguments) => (name, arguments),
+        _ => return None,
+    };
+    // The commas between the arguments are dropped, so the color stops
+    // start at the first color.
+    let first_stop = arguments.iter().position(|value| {
+        resolve_color(Some(value), current_color).is_some()
+    })?;
+    let (preamble, stops) = arguments.split_at(first_stop);
+    let stops = color_stops(stops, current_color, metrics)?;
+    match name.to_ascii_lowercase().as_str() {
+        "linear-gradient" => Some(BackgroundImage::LinearGradient {
+            direction: gradient_direction(preamble)?,
+            stops,
+        }),
+        "radial-gradient" => {
+            let (shape, size, position) = radial_shape(preamble, metrics)?;
+            Some(BackgroundImage::RadialGradient {
+                shape,
+                size,
+                position,
+                stops,
+            })
+        }
+        _ => None,
+    }
+}
+
+// Compute the direction of a linear gradient, such as `45deg` or
+// `to top right`. The initial direction is `to bottom`.
+fn gradient_direction(values: &[Value]) -> Option<GradientDirection> {
+    use std::f32::consts::PI;
+    let keyword = |value: &Value| match value {
+        Value::Keyword(k) => Some(k.to_ascii_lowercase()),
+        _ => None,
+    };
+    let sides = match values {
+        [] => return Some(GradientDirection::Angle(PI)),
+        [angle] => return radians(angle).map(GradientDirection::Angle),
+        [to, sides @ ..] if keyword(to)? == "to" => {
+            sides.iter().map(keyword).collect::<Option<Vec<_>>>()?
+        }
+        _ => return None,
+    };
+    let is_vertical = |side: &str| side == "top" || side == "bottom";
+    match sides.as_slice() {
+        [side] => {
+            let angle = match side.as_str() {
+                "top" => 0.0,
+                "right" => 0.5,
+                "bottom" => 1.0,
+                "left" => 1.5,
+                _ => return None,
+            };
+            Some(GradientDirection::Angle(angle * PI))
+        }
+        [a, b] if is_vertical(a) != is_vertical(b) => {
+            let (x, y) = if is_vertical(a) { (b, a) } else { (a, b) };
+            if !matches!(x.as_str(), "left" | "right") {
+                return None;
+            }
+            Some(GradientDirection::Corner {
+                right: x == "right",
+                bottom: y == "bottom",
+            })
+        }
+        _ => None,
+    }
+}
+
+// Compute the shape, size, and center of a radial gradient, such as
+// `circle closest-side at left top`. A single length makes a circle.
+fn radial_shape(
+    values: &[Value],
+    metrics: FontMetrics,
+) -> Option<(
+    RadialShape,
+    RadialSize,
+    (LengthPercentage, LengthPercentage),
+)> {
+    let center = LengthPercentage::Percent(50.0);
+    let at = values.iter().position(
+        |value| matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case("at")),
+    );
+    let (values, position) = match at {
+        Some(at) => {
+            let position = match &values[at + 1..] {
+                [value] => position_value(Some(value), metrics)?,
+                values => position_value(
+                    Some(&Value::List(values.to_vec())),
+                    metrics,
+                )?,
+            };
+            (&values[..at], position)
+        }
+        None => (values, (center, center)),
+    };
+
+    let length = |value: &Value| match *value {
+        Value::Length(percent, Unit::Percent) => {
+            Some(LengthPercentage::Percent(percent))
+        }
+        ref value => metrics.resolve(value).map(LengthPercentage::Px),
+    };
+    let mut shape = None;
+    let mut size = None;
+    let mut radii = Vec::new();
+    for value in values {
+        match value {
+            Value::Keyword(k) => match k.to_ascii_lowercase().as_str() {
+                "circle" => shape = Some(RadialShape::Circle),
+                "ellipse" => shape = Some(RadialShape::Ellipse),
+                "closest-side" => size = Some(RadialSize::ClosestSide),
+                "farthest-side" => size = Some(RadialSize::FarthestSide),
+                "closest-corner" => size = Some(RadialSize::ClosestCorner),
+                "farthest-corner" => size = Some(RadialSize::FarthestCorner),
+                _ => return None,
+            },
+            value => radii.push(length(value)?),
+        }
+    }
+    let shape = match (shape, radii.len()) {
+        (Some(shape), _) => shape,
+        (None, 1) => RadialShape::Circle,
+        (None, _) => RadialShape::Ellipse,
+    };
+    let size = match (shape, radii.as_slice(), size) {
+        (_, [], size) => size.unwrap_or(RadialSize::FarthestCorner),
+        // A circle can't be sized with a percentage.
+        (RadialShape::Circle, [LengthPercentage::Px(radius)], None) => {
+            RadialSize::Radii(
+                LengthPercentage::Px(*radius),
+                LengthPercentage::Px(*radius),
+            )
+        }
+        (RadialShape::Ellipse, [x, y], None) => RadialSize::Radii(*x, *y),
+        _ => return None,
+    };
+    Some((shape, size, position))
+}
+
+// Compute the color stops of a gradient, such as `#ff0000 10% #0000ff`. A
+// color with two positions is two stops of the same color.
+// TODO: Support transition hints, which can't be told apart from the second
+// position of a stop without the commas between the stops.
+fn color_stops(
+    values: &[Value],
+    current_color: Color,
+    metrics: FontMetrics,
+) -> Option<Vec<ColorStop>> {
+    let length = |value: &Value| match *value {
+        Value::Length(percent, Unit::Percent) => {
+            Some(LengthPercentage::Percent(percent))
+        }
+        ref value => metrics.resolve(value).map(LengthPercentage::Px),
+    };
+    let mut stops: Vec<ColorStop> = Vec::new();
+    let mut positions = 0;
+    for value in values {
+        if let Some(color) = resolve_color(Some(value), current_color) {
+            stops.push(ColorStop {
+                color,
+                position: None,
+            });
+            positions = 0;
+            continue;
+        }
+        let position = length(value)?;
+        let last = stops.last_mut()?;
+        match positions {
+            0 => last.position = Some(position),
+            1 => {
+                let color = last.color;
+                stops.push(ColorStop {
+                    color,
+                    position: Some(position),
+                });
+            }
+            _ => return None,
+        }
+        positions += 1;
+    }
+    (stops.len() >= 2).then_some(stops)
+}
+
+// Compute whether a background image repeats horizontally and vertically.
+// `space` and `round` are treated as `repeat`.
+fn background_repeat(
+    value: Option<&Value>,
+) -> (BackgroundRepeat, BackgroundRepeat) {
+    let repeat = |value: &Value| match value {
+        Value::Keyword(k) if k == "no-repeat" => BackgroundRepeat::NoRepeat,
+        _ => BackgroundRepeat::Repeat,
+    };
+    match value {
+        Some(Value::Keyword(k)) if k == "repeat-x" => {
+            (BackgroundRepeat::Repeat, BackgroundRepeat::NoRepeat)
+        }
+        Some(Value::Keyword(k)) if k == "repeat-y" => {
+            (BackgroundRepeat::NoRepeat, BackgroundRepeat::Repeat)
+        }
+        Some(Value::List(values)) if values.len() == 2 => {
+            (repeat(&values[0]), repeat(&values[1]))
+        }
+        Some(value) => (repeat(value), repeat(value)),
+        None => (BackgroundRepeat::Repeat, BackgroundRepeat::Repeat),
+    }
+}
+
+// Compute a box shadow, such as `2px 2px 4px #000000` or `inset 0 0 4px`.
+// The color defaults to `currentColor`, and the blur and spread to zero.
+// TODO: Support lists of shadows, which are separated by commas.
+fn box_shadow(
+    value: Option<&Value>,
+    current_color: Color,
+    metrics: FontMetrics,
+) -> Option<BoxShadow> {
+    let values = match value? {
+        Value::List(values) => values.as_slice(),
+        _ => return None,
+    };
+    let mut lengths = Vec::new();
+    let mut color = None;
+    let mut inset = false;
+    for value in values {
+        match value {
+            Value::Keyword(k) if k.eq_ignore_ascii_case("inset") && !inset => {
+                inset = true;
+            }
+            value => match resolve_color(Some(value), current_color) {
+                Some(value) if color.is_none() => color = Some(value),
+                Some(_) => return None,
+                None => lengths.push(metrics.resolve(value)?),
+            },
+        }
+    }
+    let (offset, blur, spread) = match *lengths.as_slice() {
+        [x, y] => ((x, y), 0.0, 0.0),
+        [x, y, blur] => ((x, y), blur, 0.0),
+        [x, y, blur, spread] => ((x, y), blur, spread),
+        _ => return None,
+    };
+    if blur < 0.0 {
+        return None;
+    }
+    Some(BoxShadow {
+        offset,
+        blur,
+        spread,
+        color: color.unwrap_or(current_color),
+        inset,
+    })
+}
+
+// Compute the size of a background image, such as `cover` or `50% auto`.
+// With a single value, the height is `auto`.
+fn background_size(
+    value: Option<&Value>,
+    metrics: FontMetrics,
+) -> BackgroundSize {
+    let length = |value: &Value| match *value {
+        Value::Length(percent, Unit::Percent) => {
+            Some(LengthPercentage::Percent(percent))
+        }
+        ref value => metrics.resolve(value).map(LengthPercentage::Px),
+    };
+    match value {
+        Some(Value::Keyword(k)) if k == "contain" => BackgroundSize::Contain,
+        Some(Value::Keyword(k)) if k == "cover" => BackgroundSize::Cover,
+        Some(Value::List(values)) if values.len() == 2 => {
+            BackgroundSize::Size(length(&values[0]), length(&values[1]))
+        }
+        Some(value) => BackgroundSize::Size(length(value), None),
+        None => BackgroundSize::Size(None, None),
+    }
+}
+
+// Compute a grid line, such as `2`, `-1`, or `span 2`.
+fn grid_line(value: Option<&Value>) -> GridLine {
+    match value {
+        Some(Value::Number(line)) if *line != 0.0 => {
+            GridLine::Line(*line as i32)
+        }
+        Some(Value::List(values)) => match values.as_slice() {
+            [Value::Keyword(k), Value::Number(span)]
+            | [Value::Number(span), Value::Keyword(k)]
+                if k == "span" && *span >= 1.0 =>
+            {
+                GridLine::Span(*span as u32)
+            }
+            _ => GridLine::Auto,
+        },
+        _ => GridLine::Auto,
+    }
+}
+
+// Compute a `vertical-align` value. Other alignments, such as lengths and
+// `text-top`, are treated as `baseline`.
+fn vertical_align(value: Option<&Value>) -> VerticalAlign {
+    match value {
+        Some(Value::Keyword(k)) if k == "top" => VerticalAlign::Top,
+        Some(Value::Keyword(k)) if k == "middle" => VerticalAlign::Middle,
+        Some(Value::Keyword(k)) if k == "bottom" => VerticalAlign::Bottom,
+        _ => VerticalAlign::Baseline,
+    }
+}
+
+// Compute a `break-before` or `break-after` value. Breaks to a left or a
+// right page are forced page breaks, and column breaks are ignored.
+fn break_between(value: Option<&Value>) -> Break {
+    match value {
+        Some(Value::Keyword(k)) => match k.as_str() {
+            "page" | "left" | "right" | "recto" | "verso" => Break::Page,
+            "avoid" | "avoid-page" => Break::Avoid,
+            _ => Break::Auto,
+        },
+        _ => Break::Auto,
+    }
+}
+
+// Compute an `overflow-x` or `overflow-y` value.
+fn overflow(value: Option<&Value>) -> Overflow {
+    match value {
+        Some(Value::Keyword(k)) if k == "hidden" => Overflow::Hidden,
+        Some(Value::Keyword(k)) if k == "scroll" => Overflow::Scroll,
+        Some(Value::Keyword(k)) if k == "auto" => Overflow::Auto,
+        _ => Overflow::Visible,
+    }
+}
+
+// Compute the display value.
+fn display(values: &Specified) -> Display {
+    match values.get("display") {
+        Some(Value::Keyword(keyword)) => {
+            Display::from_keyword(keyword).unwrap_or(Display::Inline)
+        }
+        _ => Display::Inline,
+    }
+}
+
+// Compute a property for each side, such as `margin-top`.
+// Shorthands are already expanded to these longhands by the cascade.
+fn sides<T>(
+    values: &Specified,
+    prefix: &str,
+    suffix: &str,
+    compute: impl Fn(Option<&Value>) -> T,
+) -> Sides<T> {
+    let side = |name: &str| {
+        compute(values.get(&format!("{}-{}{}", prefix, name, suffix)))
+    };
+    Sides {
+        top: side("top"),
+        right: side("right"),
+        bottom: side("bottom"),
+        left: side("left"),
+    }
+}
+
+// Compute the values of the four corners, such as those of
+// `border-top-left-radius` and the like.
+fn corners<T>(
+    values: &Specified,
+    prefix: &str,
+    suffix: &str,
+    compute: impl Fn(Option<&Value>) -> T,
+) -> Corners<T> {
+    let corner = |name: &str| {
+        compute(values.get(&format!("{}-{}{}", prefix, name, suffix)))
+    };
+    Corners {
+        top_left: corner("top-left"),
+        top_right: corner("top-right"),
+        bottom_right: corner("bottom-right"),
+        bottom_left: corner("bottom-left"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: (f32, f32) = (800.0, 600.0);
+
+    fn values(declarations: &[(&str, Value)]) -> PropertyMap {
+        declarations
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn keyword(keyword: &str) -> Value {
+        Value::Keyword(keyword.to_string())
+    }
+
+    fn px(px: f32) -> Value {
+        Value::Length(px, Unit::Px)
+    }
+
+    fn red() -> Color {
+        Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        }
+    }
+
+    #[test]
+    fn test_inherited_and_non_inherited_properties() {
+        let parent = ComputedStyle::compute(
+            &values(&[
+                ("color", Value::ColorValue(red())),
+                ("font-weight", keyword("bold")),
+                ("display", keyword("block")),
+                ("padding-top", px(5.0)),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let child = ComputedStyle::compute(
+            &values(&[]),
+            Some(&parent),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(child.color, red());
+        assert_eq!(child.font_weight, 700);
+        assert_eq!(child.display, Display::Inline);
+        assert_eq!(child.padding.top, LengthPercentage::Px(0.0));
+    }
+
+    #[test]
+    fn test_relative_lengths_resolve_to_pixels() {
+        let root = ComputedStyle::compute(
+            &values(&[("font-size", Value::Length(20.0, Unit::Px))]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let child = ComputedStyle::compute(
+            &values(&[
+                ("font-size", Value::Length(150.0, Unit::Percent)),
+                ("margin-left", Value::Length(2.0, Unit::Em)),
+                ("padding-left", Value::Length(1.0, Unit::Rem)),
+                ("width", keyword("auto")),
+            ]),
+            Some(&root),
+            root.font_size,
+            VIEWPORT,
+        );
+        assert_eq!(child.font_size, 30.0);
+        // `em` is relative to the element's own font size.
+        assert_eq!(child.margin.left, LengthOrAuto::Px(60.0));
+        assert_eq!(child.padding.left, LengthPercentage::Px(20.0));
+        assert_eq!(child.width, LengthOrAuto::Auto);
+    }
+
+    #[test]
+    fn test_viewport_lengths_resolve_to_pixels() {
+        let style = ComputedStyle::compute(
+            &values(&[
+                ("font-size", Value::Length(2.0, Unit::Vw)),
+                ("height", Value::Length(50.0, Unit::Vh)),
+                ("min-width", Value::Length(10.0, Unit::Vmin)),
+                ("max-width", Value::Length(10.0, Unit::Vmax)),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(style.font_size, 16.0);
+        assert_eq!(style.height, LengthOrAuto::Px(300.0));
+        assert_eq!(style.min_width, LengthPercentage::Px(60.0));
+        assert_eq!(style.max_width, Some(LengthPercentage::Px(80.0)));
+    }
+
+    #[test]
+    fn test_resize_restyles_viewport_lengths() {
+        use crate::{
+            css_parser::CSSParser,
+            dom::NodeId,
+            html_parser::HTMLParser,
+            style::{
+                MediaContext,
+                StaticElementState,
+                StyleEngine,
+                StyledNode,
+                query_selector,
+            },
+        };
+
+        fn find<'a>(
+            styled: &'a StyledNode<'a>,
+            id: NodeId,
+        ) -> Option<&'a StyledNode<'a>> {
+            if styled.node.node_id == id {
+                return Some(styled);
+            }
+            styled.children.iter().find_map(|child| find(child, id))
+        }
+
+        let document = HTMLParser::parse_document(
+            "<html><body><p><span>a</span></p><div></div></body></html>"
+                .to_string(),
+        );
+        let mut engine = StyleEngine::new(
+            vec![CSSParser::parse(
+                "p { height: 50vh; font-size: 2vw; } div { height: 10px; }"
+                    .to_string(),
+            )],
+            MediaContext {
+                viewport_width: 800.0,
+                viewport_height: 600.0,
+                ..MediaContext::default()
+            },
+        );
+        let p = query_selector(&document.root, "p").unwrap().node_id;
+        let span = query_selector(&document.root, "span").unwrap().node_id;
+        engine.restyle(&document, &[], &StaticElementState);
+
+        // Only the element with viewport units is restyled, and its
+        // descendants inherit the new lengths.
+        let dirty_roots = engine.set_viewport(&document, 400.0, 300.0);
+        assert_eq!(dirty_roots, [p]);
+        let styled =
+            engine.restyle(&document, &dirty_roots, &StaticElementState);
+        let p = find(&styled, p).unwrap();
+        assert_eq!(p.style.height, LengthOrAuto::Px(150.0));
+        assert_eq!(p.style.font_size, 8.0);
+        assert_eq!(find(&styled, span).unwrap().style.font_size, 8.0);
+
+        // The same size restyles nothing.
+        assert!(engine.set_viewport(&document, 400.0, 300.0).is_empty());
+    }
+
+    #[test]
+    fn test_line_height_factor_is_inherited() {
+        let parent = ComputedStyle::compute(
+            &values(&[("line-height", Value::Number(1.5))]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let child = ComputedStyle::compute(
+            &values(&[("font-size", px(20.0))]),
+            Some(&parent),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(parent.line_height, 24.0);
+        assert_eq!(child.line_height, 30.0);
+
+        // A length is inherited as an absolute value.
+        let parent = ComputedStyle::compute(
+            &values(&[("line-height", Value::Length(2.0, Unit::Em))]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let child = ComputedStyle::compute(
+            &values(&[("font-size", px(20.0))]),
+            Some(&parent),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(child.line_height, 32.0);
+    }
+
+    #[test]
+    fn test_css_wide_keywords() {
+        let parent = ComputedStyle::compute(
+            &values(&[
+                ("color", Value::ColorValue(red())),
+                ("display", keyword("block")),
+                ("margin-top", px(10.0)),
+                ("font-style", keyword("italic")),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let child = ComputedStyle::compute(
+            &values(&[
+                ("color", keyword("initial")),
+                ("display", keyword("inherit")),
+                ("margin-top", keyword("inherit")),
+                ("font-style", keyword("unset")),
+                ("border-top-color", keyword("currentcolor")),
+            ]),
+            Some(&parent),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(child.color, ComputedStyle::default().color);
+        assert_eq!(child.border_color.top, child.color);
+        assert_eq!(child.display, Display::Block);
+        assert_eq!(child.margin.top, LengthOrAuto::Px(10.0));
+        assert_eq!(child.font_style, FontStyle::Italic);
+    }
+
+    #[test]
+    fn test_visibility_is_inherited_and_opacity_is_not() {
+        let parent = ComputedStyle::compute(
+            &values(&[
+                ("visibility", keyword("hidden")),
+                ("opacity", Value::Number(0.5)),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let child = ComputedStyle::compute(
+            &values(&[]),
+            Some(&parent),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        let visible = ComputedStyle::compute(
+            &values(&[
+                ("visibility", keyword("visible")),
+                ("opacity", Value::Number(2.0)),
+            ]),
+            Some(&parent),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(parent.opacity, 0.5);
+        assert!(!child.is_visible());
+        assert_eq!(child.opacity, 1.0);
+        assert!(visible.is_visible());
+        assert_eq!(visible.opacity, 1.0);
+    }
+
+    #[test]
+    fn test_border_widths_depend_on_style() {
+        let style = ComputedStyle::compute(
+            &values(&[
+                ("border-top-width", keyword("thick")),
+                ("border-top-style", keyword("solid")),
+                ("border-right-style", keyword("double")),
+                ("border-bottom-width", px(4.0)),
+                ("border-left-width", keyword("thin")),
+                ("border-left-style", keyword("hidden")),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        // The initial width is `medium`, and sides without a visible style
+        // have no width.
+        let width = style.border_width;
+        assert_eq!(
+            (width.top, width.right, width.bottom, width.left),
+            (5.0, 3.0, 0.0, 0.0)
+        );
+        assert_eq!(style.border_style.right, BorderStyle::Double);
+        assert_eq!(style.border_style.bottom, BorderStyle::None);
+    }
+
+    #[test]
+    fn test_outline() {
+        let style = ComputedStyle::compute(
+            &values(&[
+                ("outline-width", keyword("thin")),
+                ("outline-style", keyword("dashed")),
+                ("outline-offset", px(2.0)),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(style.outline_width, 1.0);
+        assert_eq!(
+            style.outline_style,
+            OutlineStyle::Border(BorderStyle::Dashed)
+        );
+        assert_eq!(style.outline_color, style.color);
+        assert_eq!(style.outline_offset, 2.0);
+
+        // Like a border, an outline without a style has no width.
+        let style = ComputedStyle::compute(
+            &values(&[("outline-width", px(4.0))]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(style.outline_width, 0.0);
+        let style = ComputedStyle::compute(
+            &values(&[("outline-style", keyword("auto"))]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(style.outline_style, OutlineStyle::Auto);
+        assert_eq!(style.outline_width, 3.0);
+    }
+
+    #[test]
+    fn test_transform_functions() {
+        let function = |name: &str, arguments: &[Value]| {
+            Value::Function(name.to_string(), arguments.to_vec())
+        };
+        let style = ComputedStyle::compute(
+            &values(&[
+                (
+                    "transform",
+                    Value::List(vec![
+                        function(
+                            "translatex",
+                            &[Value::Length(10.0, Unit::Percent)],
+                        ),
+                        function("rotate", &[Value::Length(0.5, Unit::Turn)]),
+                        function("scale", &[Value::Number(2.0)]),
+                    ]),
+                ),
+                (
+                    "transform-origin",
+                    Value::List(vec![keyword("top"), keyword("left")]),
+                ),
+            ]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(
+            style.transform,
+            [
+                TransformFunction::Translate(
+                    LengthPercentage::Percent(10.0),
+                    LengthPercentage::Px(0.0)
+                ),
+                TransformFunction::Rotate(std::f32::consts::PI),
+                TransformFunction::Scale(2.0, 2.0),
+            ]
+        );
+        assert_eq!(
+            style.transform_origin,
+            (
+                LengthPercentage::Percent(0.0),
+                LengthPercentage::Percent(0.0)
+            )
+        );
+
+        // A list with an invalid function is ignored as a whole.
+        let invalid = ComputedStyle::compute(
+            &values(&[(
+                "transform",
+                Value::List(vec![
+                    function("scale", &[Value::Number(2.0)]),
+                    function("rotate", &[px(10.0)]),
+                ]),
+            )]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert!(invalid.transform.is_empty());
+    }
+
+    #[test]
+    fn test_gradients() {
+        let gradient = |name: &str, arguments: &[Value]| {
+            let style = ComputedStyle::compute(
+                &values(&[(
+                    "background-image",
+                    Value::Function(name.to_string(), arguments.to_vec()),
+                )]),
+                None,
+                DEFAULT_FONT_SIZE,
+                VIEWPORT,
+            );
+            style.background_image
+        };
+        let color = Value::ColorValue(red());
+        let stop = |position: Option<LengthPercentage>| ColorStop {
+            color: red(),
+            position,
+        };
+
+        // A color with two positions is two stops.
+        assert_eq!(
+            gradient(
+                "linear-gradient",
+                &[
+                    keyword("to"),
+                    keyword("top"),
+                    keyword("right"),
+                    color.clone(),
+                    Value::Length(10.0, Unit::Percent),
+                    px(20.0),
+                    color.clone(),
+                ]
+            ),
+            Some(BackgroundImage::LinearGradient {
+                direction: GradientDirection::Corner {
+                    right: true,
+                    bottom: false,
+                },
+                stops: vec![
+                    stop(Some(LengthPercentage::Percent(10.0))),
+                    stop(Some(LengthPercentage::Px(20.0))),
+                    stop(None),
+                ],
+            })
+        );
+        assert_eq!(
+            gradient(
+                "linear-gradient",
+                &[keyword("to"), keyword("left"), color.clone(), color.clone()]
+            ),
+            Some(BackgroundImage::LinearGradient {
+                direction: GradientDirection::Angle(1.5 * std::f32::consts::PI),
+                stops: vec![stop(None), stop(None)],
+            })
+        );
+        // A single length makes a circle.
+        assert_eq!(
+            gradient(
+                "radial-gradient",
+                &[
+                    px(10.0),
+                    keyword("at"),
+                    keyword("left"),
+                    color.clone(),
+                    color.clone(),
+                ]
+            ),
+            Some(BackgroundImage::RadialGradient {
+                shape: RadialShape::Circle,
+                size: RadialSize::Radii(
+                    LengthPercentage::Px(10.0),
+                    LengthPercentage::Px(10.0)
+                ),
+                position: (
+                    LengthPercentage::Percent(0.0),
+                    LengthPercentage::Percent(50.0)
+                ),
+                stops: vec![stop(None), stop(None)],
+            })
+        );
+
+        // Gradients need two colors, and circles can't have percentages.
+        assert_eq!(
+            gradient("linear-gradient", &[color.clone(), px(10.0)]),
+            None
+        );
+        assert_eq!(
+            gradient(
+                "radial-gradient",
+                &[
+                    keyword("circle"),
+                    Value::Length(10.0, Unit::Percent),
+                    color.clone(),
+                    color.clone(),
+                ]
+            ),
+            None
+        );
+        assert_eq!(
+            gradient("linear-gradient", &[keyword("to"), color.clone(), color]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_box_shadow() {
+        let shadow = |shadow: Vec<Value>| {
+            let style = ComputedStyle::compute(
+                &values(&[
+                    ("color", Value::ColorValue(red())),
+                    ("box-shadow", Value::List(shadow)),
+                ]),
+                None,
+                DEFAULT_FONT_SIZE,
+                VIEWPORT,
+            );
+            style.box_shadow
+        };
+        let black = Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+
+        assert_eq!(
+            shadow(vec![
+                Value::ColorValue(black),
+                px(1.0),
+                Value::Length(1.0, Unit::Em),
+                px(4.0),
+                px(-2.0),
+            ]),
+            [BoxShadow {
+                offset: (1.0, DEFAULT_FONT_SIZE),
+                blur: 4.0,
+                spread: -2.0,
+                color: black,
+                inset: false,
+            }]
+        );
+        // The color defaults to the color of the text.
+        assert_eq!(
+            shadow(vec![px(1.0), Value::Number(0.0), keyword("inset")]),
+            [BoxShadow {
+                offset: (1.0, 0.0),
+                blur: 0.0,
+                spread: 0.0,
+                color: red(),
+                inset: true,
+            }]
+        );
+        // Blurs can't be negative, and two lengths are needed.
+        assert!(shadow(vec![px(1.0), px(1.0), px(-1.0)]).is_empty());
+        assert!(shadow(vec![px(1.0), Value::ColorValue(black)]).is_empty());
+    }
+
+    #[test]
+    fn test_overflow_clips_in_both_directions() {
+        let style = ComputedStyle::compute(
+            &values(&[("overflow-x", keyword("hidden"))]),
+            None,
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert_eq!(
+            (style.overflow_x, style.overflow_y),
+            (Overflow::Hidden, Overflow::Auto)
+        );
+        assert!(style.is_scroll_container());
+        let child = ComputedStyle::compute(
+            &values(&[]),
+            Some(&style),
+            DEFAULT_FONT_SIZE,
+            VIEWPORT,
+        );
+        assert!(!child.is_scroll_container());
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let markers = |list_style_type: ListStyleType| -> Vec<_> {
+            [1, 4, 28, 1994]
+                .map(|ordinal| list_style_type.marker(ordinal).unwrap())
+                .to_vec()
+        };
+        assert_eq!(
+            markers(ListStyleType::Decimal),
+            ["1. ", "4. ", "28. ", "1994. "]
+        );
+        assert_eq!(
+            markers(ListStyleType::LowerAlpha),
+            ["a. ", "d. ", "ab. ", "bxr. "]
+        );
+        assert_eq!(
+            markers(ListStyleType::UpperRoman),
+            ["I. ", "IV. ", "XXVIII. ", "MCMXCIV. "]
+        );
+        // Ordinals out of the range of a style fall back to decimal.
+        assert_eq!(ListStyleType::LowerRoman.marker(0).unwrap(), "0. ");
+        assert_eq!(ListStyleType::Disc.marker(3).unwrap(), "\u{2022} ");
+        assert_eq!(ListStyleType::None.marker(3), None);
+    }
+}
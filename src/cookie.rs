@@ -0,0 +1,343 @@
+// The cookie jar keeps the cookies that servers set with `Set-Cookie`
+// headers, and gives the `Cookie` header of each request the cookies that
+// belong to its URL:
+//
+// - A cookie without a `Domain` is sent to its host only, and one with a
+//   `Domain` to that domain and its subdomains, if it set the cookie.
+// - A cookie is sent to its `Path` and the paths below it, which default to the
+//   directory of the URL that set it.
+// - A cookie expires at its `Max-Age` or `Expires`, or lasts as long as the jar
+//   does without either.
+// - A `Secure` cookie is set and sent over HTTPS only.
+//
+// Public suffixes are not known, so a server can't set a cookie for a
+// top-level domain such as `com`, but can for a domain such as `co.uk`.
+// SEE: https://httpwg.org/specs/rfc6265.html
+
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::url::Url;
+
+// The longest that a cookie is kept, which is about 400 days as browsers
+// limit it to.
+// SEE: https://httpwg.org/http-extensions/draft-ietf-httpbis-rfc6265bis.html#name-the-max-age-attribute
+const MAX_AGE: Duration = Duration::from_secs(400 * 24 * 60 * 60);
+
+// A cookie that a server set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    // The lowercase domain of the cookie, without a leading dot.
+    pub domain: String,
+    // Whether the cookie is only sent to the host that is its domain, and
+    // not to its subdomains.
+    pub host_only: bool,
+    pub path: String,
+    // When the cookie expires, or `None` if it lasts as long as the jar.
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+impl Cookie {
+    // Parse the value of a `Set-Cookie` header of a response from a URL.
+    // Returns `None` if the cookie is invalid or doesn't belong to the URL.
+    // SEE: https://httpwg.org/specs/rfc6265.html#storage-model
+    pub fn parse(set_cookie: &str, url: &Url, now: SystemTime) -> Option<Self> {
+        let host = url.host()?;
+        let mut parts = set_cookie.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Self {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: host.to_string(),
+            host_only: true,
+            path: default_path(url.path()).to_string(),
+            expires: None,
+            secure: false,
+            http_only: false,
+        };
+        let mut max_age = None;
+        for attribute in parts {
+            let (name, value) = match attribute.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Ok(expires) = httpdate::parse_http_date(value) {
+                        cookie.expires = Some(expires);
+                    }
+                }
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(seconds);
+                    }
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.');
+                    cookie.domain = domain.to_ascii_lowercase();
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => {
+                    cookie.path = value.to_string();
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        // `Max-Age` wins over `Expires`, and a cookie that is not older than
+        // zero seconds has expired. Longer ages than are kept are clamped,
+        // so that they don't overflow the time.
+        if let Some(seconds) = max_age {
+            cookie.expires = match u64::try_from(seconds) {
+                Ok(seconds) if seconds > 0 => {
+                    now.checked_add(Duration::from_secs(seconds).min(MAX_AGE))
+                }
+                _ => Some(UNIX_EPOCH),
+            };
+        }
+        // A cookie for a top-level domain is rejected, unless it is the host
+        // itself, such as `localhost`, which is the only one that gets it.
+        if !cookie.host_only && !cookie.domain.contains('.') {
+            if cookie.domain != host {
+                return None;
+            }
+            cookie.host_only = true;
+        }
+        if !domain_matches(host, &cookie.domain)
+            || (cookie.secure && url.scheme() != "https")
+        {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    // Check if the cookie is sent with a request for a URL.
+    // SEE: https://httpwg.org/specs/rfc6265.html#cookie
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host() else {
+            return false;
+        };
+        let domain = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(host, &self.domain)
+        };
+        domain
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+    }
+
+    // Check if the cookie has expired at a time.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+// The cookies of the responses that were received, which can be shared by
+// the threads that send requests.
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Store the cookie of a `Set-Cookie` header of a response from a URL,
+    // in place of the one of the same name, domain, and path. A cookie that
+    // has expired removes that one.
+    pub fn set(&self, url: &Url, set_cookie: &str) {
+        let now = SystemTime::now();
+        let Some(cookie) = Cookie::parse(set_cookie, url, now) else {
+            return;
+        };
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|stored| {
+            (&stored.name, &stored.domain, &stored.path)
+                != (&cookie.name, &cookie.domain, &cookie.path)
+        });
+        if !cookie.is_expired(now) {
+            cookies.push(cookie);
+        }
+    }
+
+    // Get the value of the `Cookie` header of a request for a URL, if any
+    // cookies are sent with it. Cookies with longer paths come first.
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let now = SystemTime::now();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+        let mut matching: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(url))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // The sort is stable, so cookies of the same path stay in the order
+        // that they were set.
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    // Get the cookies that have not expired.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        let cookies = self.cookies.lock().unwrap();
+        cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    // Remove all cookies.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+}
+
+// Get the directory of the path of a URL, which is the default path of the
+// cookies that it sets.
+// SEE: https://httpwg.org/specs/rfc6265.html#cookie-path
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(end) => &path[..end],
+    }
+}
+
+// Check if a host is a domain or one of its subdomains. IP addresses only
+// match themselves.
+// SEE: https://httpwg.org/specs/rfc6265.html#cookie-domain
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<std::net::IpAddr>().is_err()
+            && !host.starts_with('['))
+}
+
+// Check if a path is the path of a cookie, or below it.
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/')
+                || path[cookie_path.len()..].starts_with('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cookie() {
+        let now = SystemTime::now();
+        let url = Url::parse("https://www.example.com/a/b.html").unwrap();
+        let parse = |set_cookie| Cookie::parse(set_cookie, &url, now);
+
+        let cookie = parse("id = 1 ").unwrap();
+        assert_eq!((cookie.name.as_str(), cookie.value.as_str()), ("id", "1"));
+        assert_eq!(cookie.domain, "www.example.com");
+        assert!(cookie.host_only);
+        assert_eq!(cookie.path, "/a");
+        assert_eq!(cookie.expires, None);
+
+        let cookie = parse(
+            "id=1; Domain=.Example.com; Path=/; Secure; HttpOnly; \
+             Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+        )
+        .unwrap();
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+        assert_eq!(cookie.path, "/");
+        assert!(cookie.secure && cookie.http_only);
+        assert!(cookie.is_expired(now));
+        assert_eq!(
+            parse("id=1; Max-Age=60; Expires=Wed, 21 Oct 2015 07:28:00 GMT")
+                .unwrap()
+                .expires,
+            Some(now + Duration::from_secs(60))
+        );
+        assert!(parse("id=1; Max-Age=0").unwrap().is_expired(now));
+        assert!(parse("id=1; Max-Age=-5").unwrap().is_expired(now));
+        // Huge ages are clamped instead of overflowing the time.
+        assert_eq!(
+            parse("id=1; Max-Age=9223372036854775807").unwrap().expires,
+            Some(now + MAX_AGE)
+        );
+
+        // Cookies for other domains, and secure cookies over HTTP, are
+        // rejected.
+        assert_eq!(parse("id=1; Domain=other.com"), None);
+        assert_eq!(parse("id=1; Domain=ww.example.com"), None);
+        assert_eq!(parse("id"), None);
+        // Cookies for top-level domains are rejected, and are host-only
+        // cookies for hosts that are top-level domains.
+        assert_eq!(parse("id=1; Domain=com"), None);
+        assert_eq!(parse("id=1; Domain=.COM"), None);
+        let localhost = Url::parse("http://localhost/").unwrap();
+        let cookie =
+            Cookie::parse("id=1; Domain=localhost", &localhost, now).unwrap();
+        assert_eq!(cookie.domain, "localhost");
+        assert!(cookie.host_only);
+        let http = Url::parse("http://www.example.com/").unwrap();
+        assert_eq!(Cookie::parse("id=1; Secure", &http, now), None);
+    }
+
+    #[test]
+    fn test_cookie_jar() {
+        let jar = CookieJar::new();
+        let set = |url: &str, set_cookie| {
+            jar.set(&Url::parse(url).unwrap(), set_cookie)
+        };
+        let header = |url: &str| jar.header(&Url::parse(url).unwrap());
+
+        set("https://example.com/", "a=1; Domain=example.com");
+        set("https://example.com/docs/", "b=2; Path=/docs");
+        set("https://example.com/", "c=3; Secure");
+        set("https://example.com/", "d=4");
+
+        assert_eq!(
+            header("https://example.com/").as_deref(),
+            Some("a=1; c=3; d=4")
+        );
+        assert_eq!(
+            header("https://example.com/docs/page").as_deref(),
+            Some("b=2; a=1; c=3; d=4")
+        );
+        assert_eq!(
+            header("http://example.com/docsx").as_deref(),
+            Some("a=1; d=4")
+        );
+        // Host-only cookies are not sent to subdomains.
+        assert_eq!(header("https://www.example.com/").as_deref(), Some("a=1"));
+        assert_eq!(header("https://example.org/"), None);
+
+        // A cookie replaces the one of the same name, domain, and path, and
+        // an expired one removes it.
+        set("https://example.com/", "d=5");
+        set("https://example.com/", "a=0; Domain=example.com; Max-Age=0");
+        assert_eq!(header("https://example.com/").as_deref(), Some("c=3; d=5"));
+        assert_eq!(jar.cookies().len(), 3);
+        jar.clear();
+        assert_eq!(header("https://example.com/"), None);
+    }
+}
@@ -1,18 +1,73 @@
+use std::{cmp::Reverse, fmt};
+
 // TODO: Support CSS3.
 #[derive(Debug)]
 pub struct StyleSheet {
     pub rules: Vec<Rule>,
+    pub origin: Origin,
+}
+
+// Where a stylesheet comes from, which decides its precedence in the
+// cascade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
 }
 
 #[derive(Debug)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    // The condition of the enclosing `@media` rule, if any. Rules inside
+    // `@media` are kept in source order with the other rules, so that the
+    // cascade doesn't depend on whether their condition matches.
+    pub media: Option<MediaQueryList>,
+}
+
+// A comma-separated list of media queries, which matches if any query
+// matches.
+pub type MediaQueryList = Vec<MediaQuery>;
+
+// A media query, such as `screen and (min-width: 600px)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    pub negated: bool,
+    // `None` matches all media types.
+    pub media_type: Option<String>,
+    pub features: Vec<MediaFeature>,
+}
+
+// A media feature test, such as `(min-width: 600px)` or `(color)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaFeature {
+    pub name: String,
+    pub value: Option<Value>,
 }
 
 #[derive(Debug)]
 pub enum Selector {
     Simple(SimpleSelector),
+    // Simple selectors joined by combinators, such as `ul > li a`.
+    Complex(ComplexSelector),
+}
+
+#[derive(Debug)]
+pub struct ComplexSelector {
+    // The simple selector that the element itself must match.
+    pub subject: SimpleSelector,
+    // The simple selectors that its ancestors must match, from right to
+    // left, each with the combinator on its right.
+    pub ancestors: Vec<(Combinator, SimpleSelector)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    // `a b`
+    Descendant,
+    // `a > b`
+    Child,
 }
 
 #[derive(Debug)]
@@ -20,17 +75,70 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    // The position of the element among its sibling elements, counting
+    // from 1, from `:nth-child(2)`. Only integers are supported.
+    // SEE: https://drafts.csswg.org/selectors/#nth-child-pseudo
+    pub nth_child: Option<usize>,
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+// A pseudo-class that matches elements by their dynamic state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoClass {
+    Hover,
+    Focus,
+    Active,
+    Visited,
+    Checked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoElement {
+    Before,
+    After,
+    // The marker box of a list item, which is generated for every list
+    // item, and not matched by selectors.
+    Marker,
 }
 
 pub type Specificity = (usize, usize, usize);
 
 impl Selector {
     // Calculate the specificity of a selector.
+    // The specificity of a complex selector is the sum of the specificities
+    // of its simple selectors.
+    pub fn specificity(&self) -> Specificity {
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Complex(ref complex) => complex
+                .ancestors
+                .iter()
+                .map(|(_, simple)| simple.specificity())
+                .fold(complex.subject.specificity(), |(a, b, c), (x, y, z)| {
+                    (a + x, b + y, c + z)
+                }),
+        }
+    }
+
+    // Get the simple selector that the element itself must match.
+    pub fn subject(&self) -> &SimpleSelector {
+        match *self {
+            Selector::Simple(ref simple) => simple,
+            Selector::Complex(ref complex) => &complex.subject,
+        }
+    }
+}
+
+impl SimpleSelector {
+    // Calculate the specificity of a simple selector.
     pub fn specificity(&self) -> Specificity {
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
+        let a = self.id.iter().count();
+        let b = self.class.len()
+            + self.pseudo_classes.len()
+            + self.nth_child.iter().count();
+        let c =
+            self.tag_name.iter().count() + self.pseudo_element.iter().count();
         (a, b, c)
     }
 }
@@ -39,13 +147,26 @@ impl Selector {
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    // A number without a unit, such as a line-height factor.
+    Number(f32),
     ColorValue(Color),
+    // A quoted string, such as the value of `content`.
+    String(String),
+    // Component values separated by whitespace, such as the value of
+    // `margin: 10px 20px`.
+    List(Vec<Value>),
+    // A function and its arguments, such as `translate(10px, 20px)`. The
+    // commas between the arguments are dropped.
+    Function(String, Vec<Value>),
+    // A URL, such as of `background-image: url("a.png")`, as it is written.
+    Url(String),
     // TODO: Add more value types.
 }
 
@@ -59,13 +180,96 @@ impl Value {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// Serialize a value back to CSS text.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Keyword(keyword) => f.write_str(keyword),
+            Value::Length(length, unit) => {
+                let unit = match unit {
+                    Unit::Px => "px",
+                    Unit::Em => "em",
+                    Unit::Rem => "rem",
+                    Unit::Percent => "%",
+                    Unit::Fr => "fr",
+                    Unit::Vw => "vw",
+                    Unit::Vh => "vh",
+                    Unit::Vmin => "vmin",
+                    Unit::Vmax => "vmax",
+                    Unit::Deg => "deg",
+                    Unit::Rad => "rad",
+                    Unit::Grad => "grad",
+                    Unit::Turn => "turn",
+                    Unit::Dppx => "dppx",
+                    Unit::Dpi => "dpi",
+                };
+                write!(f, "{}{}", length, unit)
+            }
+            Value::Number(number) => write!(f, "{}", number),
+            Value::ColorValue(Color { r, g, b, a: 255 }) => {
+                write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+            }
+            Value::ColorValue(Color { r, g, b, a }) => {
+                write!(f, "rgba({}, {}, {}, {})", r, g, b, *a as f32 / 255.0)
+            }
+            Value::String(string) => write!(f, "{:?}", string),
+            Value::List(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+            Value::Function(name, arguments) => {
+                write!(f, "{}(", name)?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                f.write_str(")")
+            }
+            Value::Url(url) => write!(f, "url({:?})", url),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Unit {
     Px,
+    // Relative to the font size of the element.
+    Em,
+    // Relative to the font size of the root element.
+    Rem,
+    Percent,
+    // A fraction of the free space in a grid container.
+    Fr,
+    // Percentages of the width, the height, and the smaller or larger
+    // dimension of the viewport.
+    // SEE: https://www.w3.org/TR/css-values-3/#viewport-relative-lengths
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    // Angles, of which a full turn is 360 degrees, 2π radians, 400
+    // gradians, or one turn.
+    Deg,
+    Rad,
+    Grad,
+    Turn,
+    // Resolutions, in dots per pixel, of which `x` is an alias, and in
+    // dots per inch, of which there are 96 per pixel.
+    // SEE: https://www.w3.org/TR/css-values-4/#resolution
+    Dppx,
+    Dpi,
     // TODO: Add more units.
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -80,7 +284,53 @@ pub struct CSSParser {
 }
 
 impl CSSParser {
-    // Parse rules.
+    // Parse an author stylesheet.
+    #[inline]
+    pub fn parse(source: String) -> StyleSheet {
+        Self::parse_with_origin(source, Origin::Author)
+    }
+
+    // Parse a stylesheet of the given origin.
+    pub fn parse_with_origin(source: String, origin: Origin) -> StyleSheet {
+        StyleSheet {
+            rules: Self {
+                pos: 0,
+                input: source,
+            }
+            .parse_rules(),
+            origin,
+        }
+    }
+
+    // Parse the declarations of a `style` attribute, such as
+    // `color: red; margin: 0`. Invalid declarations are skipped up to the
+    // next semicolon, like in stylesheets.
+    // SEE: https://drafts.csswg.org/css-syntax/#consume-a-list-of-declarations
+    pub fn parse_declaration_list(source: String) -> Vec<Declaration> {
+        let mut parser = Self {
+            pos: 0,
+            input: source,
+        };
+        let mut declarations = Vec::new();
+        loop {
+            parser.consume_whitespace();
+            if parser.is_eof() {
+                break;
+            }
+            let start = parser.pos;
+            match parser.parse_declaration() {
+                Some(declaration) => declarations.push(declaration),
+                None => {
+                    parser.pos = start;
+                    parser.skip_declaration(false);
+                }
+            }
+        }
+        declarations
+    }
+
+    // Parse rules. Invalid rules are skipped, along with their blocks.
+    // SEE: https://drafts.csswg.org/css-syntax/#consume-a-list-of-rules
     fn parse_rules(&mut self) -> Vec<Rule> {
         let mut rules = Vec::new();
         loop {
@@ -88,44 +338,231 @@ impl CSSParser {
             if self.is_eof() {
                 break;
             }
-            rules.push(self.parse_rule());
+            let start = self.pos;
+            let at_rule = self.next_char() == '@';
+            let parsed = if at_rule {
+                self.parse_at_rule()
+            } else {
+                self.parse_rule(None).map(|rule| vec![rule])
+            };
+            match parsed {
+                Some(parsed) => rules.extend(parsed),
+                None => {
+                    self.pos = start;
+                    self.skip_rule(at_rule);
+                    // A `}` without a block to close is skipped alone.
+                    if self.pos == start {
+                        self.consume_char();
+                    }
+                }
+            }
         }
         rules
     }
 
-    // Parse a rule.
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
-        }
+    // Parse a rule. Returns `None` if the rule is invalid.
+    fn parse_rule(&mut self, media: Option<MediaQueryList>) -> Option<Rule> {
+        Some(Rule {
+            selectors: self.parse_selectors()?,
+            declarations: self.parse_declarations()?,
+            media,
+        })
     }
 
-    // Parse selectors.
-    fn parse_selectors(&mut self) -> Vec<Selector> {
-        let mut selectors = Vec::new();
+    // Parse an at-rule, returning the rules inside it. Returns `None` if
+    // the at-rule is invalid or unsupported.
+    // TODO: Support more at-rules.
+    fn parse_at_rule(&mut self) -> Option<Vec<Rule>> {
+        self.expect_char('@');
+        let name = self.parse_identifier();
+        if !name.eq_ignore_ascii_case("media") {
+            return None;
+        }
+        let media = self.parse_media_query_list()?;
+        self.expect_char('{');
+        let mut rules = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
             self.consume_whitespace();
+            // The end of the stylesheet closes the block.
+            if self.is_eof() {
+                break;
+            }
+            if self.next_char() == '}' {
+                self.consume_char();
+                break;
+            }
+            let start = self.pos;
+            match self.parse_rule(Some(media.clone())) {
+                Some(rule) => rules.push(rule),
+                None => {
+                    self.pos = start;
+                    self.skip_rule(false);
+                }
+            }
+        }
+        Some(rules)
+    }
+
+    // Skip an invalid rule, up to and including the end of its block, or
+    // the semicolon that ends an at-rule without one. A `}` that closes an
+    // outer block ends the rule, and is not skipped.
+    fn skip_rule(&mut self, at_rule: bool) {
+        let mut depth = 0;
+        while !self.is_eof() {
             match self.next_char() {
+                '"' | '\'' => {
+                    if self.parse_string().is_none() {
+                        return;
+                    }
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.consume_char();
+                        return;
+                    }
+                }
+                ';' if at_rule && depth == 0 => {
+                    self.consume_char();
+                    return;
+                }
+                _ => {}
+            }
+            self.consume_char();
+        }
+    }
+
+    // Parse a media query list, up to the opening brace.
+    fn parse_media_query_list(&mut self) -> Option<MediaQueryList> {
+        let mut queries = Vec::new();
+        loop {
+            queries.push(self.parse_media_query()?);
+            match self.peek_char()? {
+                ',' => {
+                    self.consume_char();
+                }
+                '{' => break,
+                _ => return None,
+            }
+        }
+        Some(queries)
+    }
+
+    // Parse a media query.
+    fn parse_media_query(&mut self) -> Option<MediaQuery> {
+        let mut query = MediaQuery {
+            negated: false,
+            media_type: None,
+            features: Vec::new(),
+        };
+        loop {
+            self.consume_whitespace();
+            match self.peek_char()? {
+                '(' => query.features.push(self.parse_media_feature()?),
+                ',' | '{' => break,
+                _ => {
+                    let keyword = self.parse_identifier().to_ascii_lowercase();
+                    match keyword.as_str() {
+                        "" => return None,
+                        "and" | "only" => {}
+                        "not" => query.negated = true,
+                        _ => query.media_type = Some(keyword),
+                    }
+                }
+            }
+        }
+        Some(query)
+    }
+
+    // Parse a media feature in parentheses.
+    fn parse_media_feature(&mut self) -> Option<MediaFeature> {
+        self.expect_char('(');
+        self.consume_whitespace();
+        let name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace();
+        let value = if self.peek_char()? == ':' {
+            self.consume_char();
+            self.consume_whitespace();
+            let value = self.parse_component_value()?;
+            self.consume_whitespace();
+            Some(value)
+        } else {
+            None
+        };
+        self.consume_expected_char(')')?;
+        Some(MediaFeature { name, value })
+    }
+
+    // Parse selectors. Returns `None` if any of them is invalid, which
+    // makes the whole rule invalid.
+    // SEE: https://drafts.csswg.org/selectors/#invalid
+    fn parse_selectors(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            selectors.push(self.parse_selector()?);
+            match self.peek_char()? {
                 ',' => {
                     self.consume_char();
                     self.consume_whitespace();
                 }
                 '{' => break,
-                c => panic!("Unexpected character: {}", c),
+                _ => return None,
             }
         }
-        selectors.sort_by_key(|s| s.specificity());
-        selectors
+        // Store the selectors from most- to least-specific.
+        selectors.sort_by_key(|s| Reverse(s.specificity()));
+        Some(selectors)
+    }
+
+    // Parse a selector, which is a chain of simple selectors joined by
+    // combinators.
+    fn parse_selector(&mut self) -> Option<Selector> {
+        let mut simple_selectors = vec![self.parse_simple_selector()?];
+        let mut combinators = Vec::new();
+        loop {
+            let start = self.pos;
+            self.consume_whitespace();
+            let combinator = match self.peek_char()? {
+                '>' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    Combinator::Child
+                }
+                ',' | '{' => break,
+                _ if self.pos > start => Combinator::Descendant,
+                _ => return None,
+            };
+            combinators.push(combinator);
+            simple_selectors.push(self.parse_simple_selector()?);
+        }
+
+        let subject = simple_selectors.pop().unwrap();
+        if combinators.is_empty() {
+            return Some(Selector::Simple(subject));
+        }
+        Some(Selector::Complex(ComplexSelector {
+            subject,
+            ancestors: combinators
+                .into_iter()
+                .zip(simple_selectors)
+                .rev()
+                .collect(),
+        }))
     }
 
-    // Parse a simple selector.
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    // Parse a simple selector. Returns `None` if it has an unsupported
+    // pseudo-class or pseudo-element.
+    fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
         let mut selector = SimpleSelector {
             tag_name: None,
             id: None,
             class: Vec::new(),
+            pseudo_classes: Vec::new(),
+            nth_child: None,
+            pseudo_element: None,
         };
         while !self.is_eof() {
             match self.next_char() {
@@ -140,93 +577,389 @@ impl CSSParser {
                 '*' => {
                     self.consume_char();
                 }
+                ':' => {
+                    self.consume_char();
+                    let double_colon = self.peek_char() == Some(':');
+                    if double_colon {
+                        self.consume_char();
+                    }
+                    let name = self.parse_identifier();
+                    // Pseudo-elements may also be written with a single
+                    // colon for compatibility with CSS2.
+                    match name.to_ascii_lowercase().as_str() {
+                        "before" => {
+                            selector.pseudo_element =
+                                Some(PseudoElement::Before)
+                        }
+                        "after" => {
+                            selector.pseudo_element = Some(PseudoElement::After)
+                        }
+                        _ if double_colon => return None,
+                        "hover" => {
+                            selector.pseudo_classes.push(PseudoClass::Hover)
+                        }
+                        "focus" => {
+                            selector.pseudo_classes.push(PseudoClass::Focus)
+                        }
+                        "active" => {
+                            selector.pseudo_classes.push(PseudoClass::Active)
+                        }
+                        "visited" => {
+                            selector.pseudo_classes.push(PseudoClass::Visited)
+                        }
+                        "checked" => {
+                            selector.pseudo_classes.push(PseudoClass::Checked)
+                        }
+                        "nth-child" => {
+                            self.consume_expected_char('(')?;
+                            self.consume_whitespace();
+                            let index = self
+                                .consume_chars_while(|c| c.is_ascii_digit())
+                                .parse()
+                                .ok()?;
+                            self.consume_whitespace();
+                            self.consume_expected_char(')')?;
+                            selector.nth_child = Some(index);
+                        }
+                        _ => return None,
+                    }
+                }
                 c if valid_identifier_char(c) => {
                     selector.tag_name = Some(self.parse_identifier());
                 }
                 _ => break,
             }
         }
-        selector
+        Some(selector)
     }
 
-    // Parse declarations.
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        self.expect_char('{');
+    // Parse declarations. Invalid declarations are skipped up to the next
+    // semicolon, or the end of the block. Returns `None` if there is no
+    // block.
+    fn parse_declarations(&mut self) -> Option<Vec<Declaration>> {
+        self.consume_expected_char('{')?;
         let mut declarations = Vec::new();
         loop {
             self.consume_whitespace();
+            // The end of the stylesheet closes the block.
+            if self.is_eof() {
+                break;
+            }
             if self.next_char() == '}' {
                 self.consume_char();
                 break;
             }
-            declarations.push(self.parse_declaration());
+            let start = self.pos;
+            match self.parse_declaration() {
+                Some(declaration) => declarations.push(declaration),
+                None => {
+                    self.pos = start;
+                    self.skip_declaration(true);
+                }
+            }
         }
-        declarations
+        Some(declarations)
     }
 
-    // Parse a declaration.
-    fn parse_declaration(&mut self) -> Declaration {
+    // Parse a declaration, and the semicolon after it, if any. The
+    // semicolon is optional after the last declaration of a block. Returns
+    // `None` if the declaration is invalid.
+    fn parse_declaration(&mut self) -> Option<Declaration> {
         let name = self.parse_identifier();
+        if name.is_empty() {
+            return None;
+        }
         self.consume_whitespace();
-        self.expect_char(':');
+        self.consume_expected_char(':')?;
         self.consume_whitespace();
-        let value = self.parse_value();
+        let value = self.parse_value()?;
         self.consume_whitespace();
-        self.expect_char(';');
+        let important = self.parse_important()?;
+        if !self.is_eof() && self.next_char() != '}' {
+            self.consume_expected_char(';')?;
+        }
+        Some(Declaration {
+            name,
+            value,
+            important,
+        })
+    }
 
-        Declaration { name, value }
+    // Skip an invalid declaration, up to and including the next semicolon
+    // that is not in a string, parentheses or a block. In a block, a `}`
+    // that closes it ends the declaration too, and is not skipped.
+    fn skip_declaration(&mut self, in_block: bool) {
+        let mut depth = 0;
+        while !self.is_eof() {
+            match self.next_char() {
+                '"' | '\'' => {
+                    // An unterminated string ends the declaration list.
+                    if self.parse_string().is_none() {
+                        return;
+                    }
+                    continue;
+                }
+                '(' | '{' => depth += 1,
+                ')' | '}' if depth > 0 => depth -= 1,
+                '}' if in_block => return,
+                ';' if depth == 0 => {
+                    self.consume_char();
+                    return;
+                }
+                _ => {}
+            }
+            self.consume_char();
+        }
+    }
+
+    // Parse an optional `!important` annotation. Returns `None` if the
+    // annotation is not `!important`.
+    fn parse_important(&mut self) -> Option<bool> {
+        if self.is_eof() || self.next_char() != '!' {
+            return Some(false);
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        let keyword = self.parse_identifier();
+        if !keyword.eq_ignore_ascii_case("important") {
+            return None;
+        }
+        self.consume_whitespace();
+        Some(true)
+    }
+
+    // Parse the value of a declaration, which may be a list of component
+    // values separated by whitespace, such as `10px 20px`. Returns `None`
+    // if the value is empty or invalid.
+    fn parse_value(&mut self) -> Option<Value> {
+        let mut values = Vec::new();
+        loop {
+            let start = self.pos;
+            values.push(self.parse_component_value()?);
+            if self.pos == start {
+                return None;
+            }
+            self.consume_whitespace();
+            if self.is_eof() || matches!(self.next_char(), ';' | '}' | '!') {
+                break;
+            }
+        }
+        if values.len() == 1 {
+            Some(values.remove(0))
+        } else {
+            Some(Value::List(values))
+        }
+    }
+
+    // Parse a single component value.
+    fn parse_component_value(&mut self) -> Option<Value> {
+        if self.is_eof() {
+            return None;
+        }
+        let value = match self.next_char() {
+            '0'..='9' | '.' => self.parse_length()?,
+            '-' if self.input[self.pos + 1..]
+                .starts_with(|c: char| c.is_ascii_digit() || c == '.') =>
+            {
+                self.parse_length()?
+            }
+            '#' => self.parse_color()?,
+            '"' | '\'' => Value::String(self.parse_string()?),
+            // A delimiter, such as in `grid-column: 1 / 3`.
+            '/' => {
+                self.consume_char();
+                Value::Keyword("/".to_string())
+            }
+            _ => {
+                let name = self.parse_identifier();
+                if !self.is_eof()
+                    && self.next_char() == '('
+                    && name.eq_ignore_ascii_case("url")
+                {
+                    return self.parse_url().map(Value::Url);
+                }
+                if !self.is_eof() && self.next_char() == '(' {
+                    let arguments = self.parse_arguments()?;
+                    return Some(Value::Function(name, arguments));
+                }
+                Value::Keyword(name)
+            }
+        };
+        Some(value)
+    }
+
+    // Parse the parenthesized URL of `url()`, which is either quoted or
+    // ends at the closing parenthesis.
+    fn parse_url(&mut self) -> Option<String> {
+        self.consume_expected_char('(')?;
+        self.consume_whitespace();
+        let url = match self.input[self.pos..].chars().next()? {
+            '"' | '\'' => self.parse_string()?,
+            _ => self.consume_chars_while(|c| c != ')' && !c.is_whitespace()),
+        };
+        self.consume_whitespace();
+        self.consume_expected_char(')')?;
+        Some(url)
     }
 
-    // Parse a value.
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+    // Parse the arguments of a function, which are separated by commas or
+    // whitespace.
+    fn parse_arguments(&mut self) -> Option<Vec<Value>> {
+        self.consume_expected_char('(')?;
+        let mut arguments = Vec::new();
+        loop {
+            self.consume_whitespace();
+            match self.input[self.pos..].chars().next()? {
+                ')' => break,
+                ',' => {
+                    self.consume_char();
+                }
+                _ => {
+                    let start = self.pos;
+                    arguments.push(self.parse_component_value()?);
+                    if self.pos == start {
+                        return None;
+                    }
+                }
+            }
         }
+        self.consume_expected_char(')')?;
+        Some(arguments)
     }
 
-    // Parse a length value.
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    // Parse a length value, or a number if it has no unit.
+    fn parse_length(&mut self) -> Option<Value> {
+        let value = self.parse_float()?;
+        if self.is_eof()
+            || !matches!(self.next_char(), '%' | 'a'..='z' | 'A'..='Z')
+        {
+            return Some(Value::Number(value));
+        }
+        Some(Value::Length(value, self.parse_unit()?))
     }
 
     // Parse a float value.
-    fn parse_float(&mut self) -> f32 {
-        self.consume_chars_while(|c| matches!(c, '0'..='9' | '.'))
+    fn parse_float(&mut self) -> Option<f32> {
+        let sign = if self.next_char() == '-' {
+            self.consume_char();
+            -1.0
+        } else {
+            1.0
+        };
+        let digits: f32 = self
+            .consume_chars_while(|c| matches!(c, '0'..='9' | '.'))
             .parse()
-            .unwrap()
+            .ok()?;
+        Some(sign * digits)
     }
 
     // Parse a unit value.
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("Unrecognized unit."),
+    fn parse_unit(&mut self) -> Option<Unit> {
+        if self.next_char() == '%' {
+            self.consume_char();
+            return Some(Unit::Percent);
         }
+        let unit = match &*self.parse_identifier().to_ascii_lowercase() {
+            "px" => Unit::Px,
+            "em" => Unit::Em,
+            "rem" => Unit::Rem,
+            "fr" => Unit::Fr,
+            "vw" => Unit::Vw,
+            "vh" => Unit::Vh,
+            "vmin" => Unit::Vmin,
+            "vmax" => Unit::Vmax,
+            "deg" => Unit::Deg,
+            "rad" => Unit::Rad,
+            "grad" => Unit::Grad,
+            "turn" => Unit::Turn,
+            "dppx" | "x" => Unit::Dppx,
+            "dpi" => Unit::Dpi,
+            _ => return None,
+        };
+        Some(unit)
     }
 
     // Parse a color value.
-    fn parse_color(&mut self) -> Value {
-        self.expect_char('#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
+    fn parse_color(&mut self) -> Option<Value> {
+        self.consume_expected_char('#')?;
+        Some(Value::ColorValue(Color {
+            r: self.parse_hex_pair()?,
+            g: self.parse_hex_pair()?,
+            b: self.parse_hex_pair()?,
             a: 255,
-        })
+        }))
     }
 
     // Parse two hexadecimal digits.
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
+    fn parse_hex_pair(&mut self) -> Option<u8> {
+        let s = self.input.get(self.pos..self.pos + 2)?;
+        let value = u8::from_str_radix(s, 16).ok()?;
         self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+        Some(value)
+    }
+
+    // Parse a quoted string. A backslash escapes the next character.
+    // Returns `None` if the string is not terminated.
+    fn parse_string(&mut self) -> Option<String> {
+        let quote = self.consume_char();
+        let mut result = String::new();
+        loop {
+            if self.is_eof() {
+                return None;
+            }
+            match self.consume_char() {
+                c if c == quote => break,
+                '\\' if !self.is_eof() => result.push(self.consume_char()),
+                c => result.push(c),
+            }
+        }
+        Some(result)
     }
 
-    // Parse an identifier.
+    // Parse an identifier. A backslash escapes the next character, or a
+    // code point of up to six hex digits, which one whitespace may follow,
+    // like `escape_identifier` writes them.
+    // SEE: https://drafts.csswg.org/css-syntax/#consume-escaped-code-point
     fn parse_identifier(&mut self) -> String {
-        self.consume_chars_while(valid_identifier_char)
+        let mut result = String::new();
+        while !self.is_eof() {
+            match self.next_char() {
+                '\\' => {
+                    self.consume_char();
+                    // A backslash at the end or before a newline escapes
+                    // nothing.
+                    if self.is_eof() || self.next_char() == '\n' {
+                        self.pos -= 1;
+                        break;
+                    }
+                    let mut hex = String::new();
+                    while hex.len() < 6
+                        && !self.is_eof()
+                        && self.next_char().is_ascii_hexdigit()
+                    {
+                        hex.push(self.consume_char());
+                    }
+                    if hex.is_empty() {
+                        result.push(self.consume_char());
+                        continue;
+                    }
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .filter(|&c| c != '\0')
+                        .unwrap_or('\u{FFFD}');
+                    result.push(c);
+                    if !self.is_eof() && self.next_char().is_whitespace() {
+                        self.consume_char();
+                    }
+                }
+                c if valid_identifier_char(c) => {
+                    result.push(self.consume_char())
+                }
+                _ => break,
+            }
+        }
+        result
     }
 
     // Consume characters while the condition is true.
@@ -260,6 +993,21 @@ impl CSSParser {
         self.input[self.pos..].chars().next().unwrap()
     }
 
+    // Read the next character, if there is one, without consuming it.
+    #[inline]
+    fn peek_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    // If the exact character is next, consume it. Otherwise, return `None`.
+    fn consume_expected_char(&mut self, c: char) -> Option<()> {
+        if self.is_eof() || self.next_char() != c {
+            return None;
+        }
+        self.consume_char();
+        Some(())
+    }
+
     // If the exact character is found, consume it.
     // Otherwise, panic.
     fn expect_char(&mut self, c: char) {
@@ -282,3 +1030,146 @@ impl CSSParser {
 fn valid_identifier_char(c: char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '\u{00A0}'..='\u{10FFFF}')
 }
+
+// Escape a string so that it can be used as an identifier in a selector.
+// SEE: https://drafts.csswg.org/cssom/#serialize-an-identifier
+pub fn escape_identifier(s: &str) -> String {
+    let mut result = String::new();
+    let first = s.chars().next();
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '\0' => result.push('\u{FFFD}'),
+            '\u{1}'..='\u{1F}' | '\u{7F}' => {
+                result.push_str(&format!("\\{:x} ", c as u32))
+            }
+            '0'..='9' if i == 0 || (i == 1 && first == Some('-')) => {
+                result.push_str(&format!("\\{:x} ", c as u32))
+            }
+            '-' if i == 0 && s.len() == 1 => result.push_str("\\-"),
+            c if c >= '\u{80}'
+                || c.is_ascii_alphanumeric()
+                || c == '-'
+                || c == '_' =>
+            {
+                result.push(c)
+            }
+            c => {
+                result.push('\\');
+                result.push(c);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declaration_names(css: &str) -> Vec<String> {
+        CSSParser::parse(css.to_string())
+            .rules
+            .iter()
+            .map(|rule| {
+                let names: Vec<&str> = rule
+                    .declarations
+                    .iter()
+                    .map(|declaration| declaration.name.as_str())
+                    .collect();
+                names.join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_rules_are_skipped() {
+        // Unsupported at-rules are skipped up to their semicolon, or with
+        // their block.
+        assert_eq!(
+            declaration_names(
+                "@import url(\"a;b.css\") screen; p { color: red } \
+                 @font-face { src: url(a.woff); } p { margin: 0 }"
+            ),
+            ["color", "margin"]
+        );
+        // Rules with unsupported selectors are skipped with their block,
+        // even inside `@media`.
+        assert_eq!(
+            declaration_names(
+                "a[href] { color: red; } li:nth-child(2n) { b: { c } } \
+                 p::marker { color: red } \
+                 @media screen { a[href] { color: red } p { margin: 0 } } \
+                 p { padding: 0 }"
+            ),
+            ["margin", "padding"]
+        );
+        // An invalid media query skips the whole `@media` rule.
+        assert_eq!(
+            declaration_names(
+                "@media screen, ! { p { margin: 0 } } p { padding: 0 }"
+            ),
+            ["padding"]
+        );
+        // Invalid declarations are skipped up to the next semicolon, or
+        // the end of their block.
+        assert_eq!(
+            declaration_names(
+                "p { color: red !default; margin: 0; width: } \
+                 p { : 0; padding: 0 }"
+            ),
+            ["margin", "padding"]
+        );
+        // Stray braces and unterminated stylesheets don't panic.
+        assert_eq!(declaration_names("} p { margin: 0 } }"), ["margin"]);
+        assert_eq!(declaration_names("p { margin: 0"), ["margin"]);
+        assert_eq!(
+            declaration_names("@media screen { p { margin: 0"),
+            ["margin"]
+        );
+        assert!(declaration_names("p, ").is_empty());
+        assert!(declaration_names("p:").is_empty());
+    }
+
+    #[test]
+    fn test_escape_identifier() {
+        assert_eq!(escape_identifier("main"), "main");
+        assert_eq!(escape_identifier("a-b_c\u{e9}"), "a-b_c\u{e9}");
+        // A digit can't start an identifier, even after a hyphen.
+        assert_eq!(escape_identifier("1a"), "\\31 a");
+        assert_eq!(escape_identifier("-1a"), "-\\31 a");
+        assert_eq!(escape_identifier("a1"), "a1");
+        assert_eq!(escape_identifier("--1"), "--1");
+        // A lone hyphen isn't an identifier, but it can start one.
+        assert_eq!(escape_identifier("-"), "\\-");
+        assert_eq!(escape_identifier("-a"), "-a");
+        // Control characters are escaped as code points, and NUL is
+        // replaced.
+        assert_eq!(escape_identifier("a\tb\u{7f}"), "a\\9 b\\7f ");
+        assert_eq!(escape_identifier("a\0b"), "a\u{fffd}b");
+        assert_eq!(escape_identifier("a.b#c d"), "a\\.b\\#c\\ d");
+
+        // The escaped identifiers parse back to the same ids.
+        for id in ["1a", "-1a", "-", "a\tb\u{7f}", "a.b#c d", "\u{e9}t\u{e9}"] {
+            let css = format!("#{} {{}}", escape_identifier(id));
+            let stylesheet = CSSParser::parse(css);
+            let selector = stylesheet.rules[0].selectors[0].subject();
+            assert_eq!(selector.id.as_deref(), Some(id));
+        }
+    }
+
+    #[test]
+    fn test_nth_child() {
+        let stylesheet =
+            CSSParser::parse("li:nth-child( 2 ), :nth-child(10) a {}".into());
+        let selectors = &stylesheet.rules[0].selectors;
+        assert_eq!(selectors[0].subject().nth_child, Some(2));
+        assert_eq!(selectors[0].specificity(), (0, 1, 1));
+        assert_eq!(selectors[1].subject().nth_child, None);
+        // Other arguments are not supported.
+        for css in
+            [":nth-child(odd) {}", ":nth-child(2n+1) {}", ":nth-child {}"]
+        {
+            assert!(CSSParser::parse(css.into()).rules.is_empty(), "{}", css);
+        }
+    }
+}
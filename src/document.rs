@@ -0,0 +1,1038 @@
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    fmt,
+    mem,
+};
+
+use crate::{
+    dom::{ElementData, Node, NodeId, NodeType},
+    event::{Event, EventListeners, ListenerId},
+    url,
+};
+
+// Callbacks and user data must be thread-safe, so that a document can be
+// parsed on a worker thread and handed over to a render thread.
+
+// A callback invoked when the children of a node change.
+type ChildListCallback = Box<dyn FnMut(NodeId) + Send + Sync>;
+
+// A callback invoked when an attribute of an element changes.
+type AttributeCallback = Box<dyn FnMut(NodeId, &str) + Send + Sync>;
+
+// A callback invoked when the content of a text node changes.
+type TextCallback = Box<dyn FnMut(NodeId) + Send + Sync>;
+
+// Embedder state attached to a node.
+type UserData = Box<dyn Any + Send + Sync>;
+
+// Where a stylesheet of a document comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StylesheetSource {
+    // The text of a `<style>` element.
+    Inline(String),
+    // The URL of a `<link rel="stylesheet">` element, resolved against the
+    // base URL of the document.
+    Link(String),
+}
+
+// The position of a node relative to another node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentPosition {
+    // Both are the same node.
+    Same,
+    // The other node is an ancestor.
+    Contains,
+    // The other node is a descendant.
+    ContainedBy,
+    // The other node comes earlier in tree order.
+    Preceding,
+    // The other node comes later in tree order.
+    Following,
+    // At least one of the nodes is not in the document.
+    Disconnected,
+}
+
+// The rendering mode of a document, decided by its doctype. Legacy pages
+// without a standards doctype are rendered in quirks mode, which emulates
+// the behaviors of old browsers.
+// SEE: https://quirks.spec.whatwg.org/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuirksMode {
+    #[default]
+    NoQuirks,
+    // Only the line height calculation differs from no-quirks mode.
+    LimitedQuirks,
+    Quirks,
+}
+
+// Size statistics of a document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    pub element_count: usize,
+    pub text_count: usize,
+    pub comment_count: usize,
+    pub attribute_count: usize,
+    // The number of nodes on the longest path from the root down, which
+    // bounds the recursion of the tree walks.
+    pub depth: usize,
+    // The total length of text content in bytes.
+    pub text_bytes: usize,
+    // An estimate of the heap memory owned by the tree in bytes.
+    // Allocator overhead and hash table control bytes are not included.
+    pub heap_bytes: usize,
+}
+
+impl DocumentStats {
+    // The total number of nodes.
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.element_count + self.text_count + self.comment_count
+    }
+
+    // Add the statistics of a subtree whose root is at a depth, counting
+    // from 1.
+    fn collect(&mut self, node: &Node, depth: usize) {
+        self.depth = self.depth.max(depth);
+        self.heap_bytes += node.children.capacity() * mem::size_of::<Node>();
+        match node.node_type {
+            NodeType::Element(ref elem) => {
+                self.element_count += 1;
+                self.attribute_count += elem.attributes.len();
+                self.heap_bytes += elem.tag_name.capacity()
+                    + elem.attributes.capacity()
+                        * mem::size_of::<(String, String)>()
+                    + elem
+                        .attributes
+                        .iter()
+                        .map(|(name, value)| name.capacity() + value.capacity())
+                        .sum::<usize>();
+            }
+            NodeType::Text(ref text) => {
+                self.text_count += 1;
+                self.text_bytes += text.len();
+                self.heap_bytes += text.capacity();
+            }
+            NodeType::Comment(ref comment) => {
+                self.comment_count += 1;
+                self.heap_bytes += comment.capacity();
+            }
+        }
+        for child in &node.children {
+            self.collect(child, depth + 1);
+        }
+    }
+}
+
+// A DOM tree together with the state shared by the whole document.
+// All mutations should go through the document so that registered
+// observers are notified.
+pub struct Document {
+    pub root: Node,
+    // The URL the document was loaded from, if any.
+    pub url: Option<String>,
+    pub quirks_mode: QuirksMode,
+    child_list_callbacks: Vec<ChildListCallback>,
+    attribute_callbacks: Vec<AttributeCallback>,
+    text_callbacks: Vec<TextCallback>,
+    // Embedder state attached to nodes, keyed by node and value type.
+    user_data: HashMap<(NodeId, TypeId), UserData>,
+    element_index: ElementIndex,
+    event_listeners: EventListeners,
+    // Nodes whose subtrees need to be restyled.
+    style_dirty: HashSet<NodeId>,
+}
+
+// An inverted index from ids and classes to elements, kept up to date by
+// the mutation methods of `Document`.
+#[derive(Debug, Default)]
+struct ElementIndex {
+    ids: HashMap<String, HashSet<NodeId>>,
+    classes: HashMap<String, HashSet<NodeId>>,
+}
+
+impl ElementIndex {
+    // Add all elements of a subtree.
+    fn insert_subtree(&mut self, node: &Node) {
+        if let NodeType::Element(ref elem) = node.node_type {
+            self.insert_element(node.node_id, elem);
+        }
+        for child in &node.children {
+            self.insert_subtree(child);
+        }
+    }
+
+    // Remove all elements of a subtree.
+    fn remove_subtree(&mut self, node: &Node) {
+        if let NodeType::Element(ref elem) = node.node_type {
+            self.remove_element(node.node_id, elem);
+        }
+        for child in &node.children {
+            self.remove_subtree(child);
+        }
+    }
+
+    // Add an element under its id and classes.
+    fn insert_element(&mut self, id: NodeId, elem: &ElementData) {
+        if let Some(element_id) = elem.id() {
+            self.ids.entry(element_id.clone()).or_default().insert(id);
+        }
+        for class in elem.classes() {
+            self.classes
+                .entry(class.to_string())
+                .or_default()
+                .insert(id);
+        }
+    }
+
+    // Remove an element from under its id and classes.
+    fn remove_element(&mut self, id: NodeId, elem: &ElementData) {
+        if let Some(element_id) = elem.id() {
+            remove_from_bucket(&mut self.ids, element_id, id);
+        }
+        for class in elem.classes() {
+            remove_from_bucket(&mut self.classes, class, id);
+        }
+    }
+}
+
+// Remove a node from an index bucket, dropping the bucket if it is empty.
+fn remove_from_bucket(
+    buckets: &mut HashMap<String, HashSet<NodeId>>,
+    key: &str,
+    id: NodeId,
+) {
+    if let Some(bucket) = buckets.get_mut(key) {
+        bucket.remove(&id);
+        if bucket.is_empty() {
+            buckets.remove(key);
+        }
+    }
+}
+
+impl Document {
+    // Create a document from a parsed root node.
+    pub fn new(root: Node) -> Self {
+        let mut element_index = ElementIndex::default();
+        element_index.insert_subtree(&root);
+        Self {
+            root,
+            url: None,
+            quirks_mode: QuirksMode::NoQuirks,
+            child_list_callbacks: Vec::new(),
+            attribute_callbacks: Vec::new(),
+            text_callbacks: Vec::new(),
+            user_data: HashMap::new(),
+            element_index,
+            event_listeners: EventListeners::default(),
+            style_dirty: HashSet::new(),
+        }
+    }
+
+    // Find a node by id.
+    #[inline]
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.root.find(id)
+    }
+
+    // Get the first element with the given id in tree order.
+    pub fn get_element_by_id(&self, element_id: &str) -> Option<&Node> {
+        let bucket = self.element_index.ids.get(element_id)?;
+        match bucket.len() {
+            1 => self.node(*bucket.iter().next().unwrap()),
+            _ => first_in_tree_order(&self.root, bucket),
+        }
+    }
+
+    // Get the elements with the given class in tree order.
+    pub fn get_elements_by_class_name(&self, class: &str) -> Vec<&Node> {
+        let mut nodes = Vec::new();
+        if let Some(bucket) = self.element_index.classes.get(class) {
+            collect_in_tree_order(&self.root, bucket, &mut nodes);
+        }
+        nodes
+    }
+
+    // Get the base URL used to resolve relative URLs.
+    // The first `<base>` with an `href` takes precedence over the document
+    // URL.
+    // SEE: https://html.spec.whatwg.org/multipage/urls-and-fetching.html#document-base-url
+    pub fn base_url(&self) -> Option<String> {
+        let base_href = find_attribute(&self.root, "base", "href");
+        match (base_href, &self.url) {
+            (Some(href), Some(document_url)) => {
+                Some(url::resolve(document_url, href))
+            }
+            (Some(href), None) => Some(href.clone()),
+            (None, document_url) => document_url.clone(),
+        }
+    }
+
+    // Get the value of a URL attribute of an element as an absolute URL.
+    // Without a base URL, only values that are already absolute are
+    // returned.
+    pub fn resolved_url(&self, id: NodeId, attribute: &str) -> Option<String> {
+        let NodeType::Element(ref elem) = self.node(id)?.node_type else {
+            return None;
+        };
+        match self.base_url() {
+            Some(base) => elem.resolved_url(attribute, &base),
+            None => elem
+                .attributes
+                .get(attribute)
+                .filter(|value| url::is_absolute(value))
+                .cloned(),
+        }
+    }
+
+    // Get the stylesheets of the document in tree order, which is the order
+    // that they cascade in. Links whose URL can't be resolved, and
+    // alternate stylesheets, are left out.
+    // SEE: https://html.spec.whatwg.org/multipage/links.html#link-type-stylesheet
+    pub fn stylesheet_sources(&self) -> Vec<StylesheetSource> {
+        let mut sources = Vec::new();
+        self.collect_stylesheet_sources(&self.root, &mut sources);
+        sources
+    }
+
+    fn collect_stylesheet_sources(
+        &self,
+        node: &Node,
+        sources: &mut Vec<StylesheetSource>,
+    ) {
+        let NodeType::Element(ref elem) = node.node_type else {
+            return;
+        };
+        match elem.tag_name.as_str() {
+            "style" => {
+                sources.push(StylesheetSource::Inline(node.text_content()));
+            }
+            "link" => {
+                let rel = elem.attributes.get("rel").map_or("", String::as_str);
+                let has = |link_type: &str| {
+                    rel.split_ascii_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case(link_type))
+                };
+                if has("stylesheet") && !has("alternate") {
+                    if let Some(url) = self.resolved_url(node.node_id, "href") {
+                        sources.push(StylesheetSource::Link(url));
+                    }
+                }
+            }
+            _ => {}
+        }
+        for child in &node.children {
+            self.collect_stylesheet_sources(child, sources);
+        }
+    }
+
+    // Collect size statistics of the tree.
+    pub fn stats(&self) -> DocumentStats {
+        let mut stats = DocumentStats::default();
+        stats.collect(&self.root, 1);
+        stats
+    }
+
+    // Get the index of a node among its siblings.
+    // Return `None` for the root or a node not in the document.
+    pub fn index(&self, id: NodeId) -> Option<usize> {
+        self.root.path_to(id)?.last().copied()
+    }
+
+    // Get the position of `other` relative to `node`.
+    pub fn compare_document_position(
+        &self,
+        node: NodeId,
+        other: NodeId,
+    ) -> DocumentPosition {
+        let (Some(node_path), Some(other_path)) =
+            (self.root.path_to(node), self.root.path_to(other))
+        else {
+            return DocumentPosition::Disconnected;
+        };
+        if node_path == other_path {
+            DocumentPosition::Same
+        } else if node_path.starts_with(&other_path) {
+            DocumentPosition::Contains
+        } else if other_path.starts_with(&node_path) {
+            DocumentPosition::ContainedBy
+        } else if other_path < node_path {
+            DocumentPosition::Preceding
+        } else {
+            DocumentPosition::Following
+        }
+    }
+
+    // Register a callback for child insertions and removals.
+    // The callback receives the id of the parent node.
+    pub fn on_child_list_change(
+        &mut self,
+        callback: impl FnMut(NodeId) + Send + Sync + 'static,
+    ) {
+        self.child_list_callbacks.push(Box::new(callback));
+    }
+
+    // Register a callback for attribute changes.
+    // The callback receives the id of the element and the attribute name.
+    pub fn on_attribute_change(
+        &mut self,
+        callback: impl FnMut(NodeId, &str) + Send + Sync + 'static,
+    ) {
+        self.attribute_callbacks.push(Box::new(callback));
+    }
+
+    // Register a callback for text content changes.
+    // The callback receives the id of the text node.
+    pub fn on_text_change(
+        &mut self,
+        callback: impl FnMut(NodeId) + Send + Sync + 'static,
+    ) {
+        self.text_callbacks.push(Box::new(callback));
+    }
+
+    // Append a child to the given parent, returning the id of the child.
+    // Return `None` if the parent does not exist.
+    pub fn append_child(
+        &mut self,
+        parent: NodeId,
+        child: Node,
+    ) -> Option<NodeId> {
+        let len = self.root.find(parent)?.children.len();
+        self.insert_child(parent, len, child)
+    }
+
+    // Insert a child at the given index of the parent's children.
+    // Return `None` if the parent does not exist.
+    pub fn insert_child(
+        &mut self,
+        parent: NodeId,
+        index: usize,
+        child: Node,
+    ) -> Option<NodeId> {
+        let child_id = child.node_id;
+        let parent_node = self.root.find_mut(parent)?;
+        let index = index.min(parent_node.children.len());
+        self.element_index.insert_subtree(&child);
+        parent_node.children.insert(index, child);
+        self.notify_child_list_change(parent);
+        Some(child_id)
+    }
+
+    // Remove a child from the given parent, returning the detached node.
+    pub fn remove_child(
+        &mut self,
+        parent: NodeId,
+        child: NodeId,
+    ) -> Option<Node> {
+        let parent_node = self.root.find_mut(parent)?;
+        let index = parent_node
+            .children
+            .iter()
+            .position(|node| node.node_id == child)?;
+        let removed = parent_node.children.remove(index);
+        self.element_index.remove_subtree(&removed);
+        self.notify_child_list_change(parent);
+        Some(removed)
+    }
+
+    // Detach a node together with its subtree, wherever it is in the tree.
+    // The root cannot be detached.
+    pub fn detach(&mut self, id: NodeId) -> Option<Node> {
+        let parent = self.root.parent_of(id)?.node_id;
+        self.remove_child(parent, id)
+    }
+
+    // Set an attribute on an element. Observers are not notified if the
+    // attribute already had the value.
+    // Return `false` without notifying if the node is not an element.
+    pub fn set_attribute(
+        &mut self,
+        id: NodeId,
+        name: &str,
+        value: String,
+    ) -> bool {
+        let Some(Node {
+            node_type: NodeType::Element(elem),
+            ..
+        }) = self.root.find_mut(id)
+        else {
+            return false;
+        };
+        if elem.attributes.get(name) == Some(&value) {
+            return true;
+        }
+        self.element_index.remove_element(id, elem);
+        elem.attributes.insert(name.to_string(), value);
+        self.element_index.insert_element(id, elem);
+        self.notify_attribute_change(id, name);
+        true
+    }
+
+    // Remove an attribute from an element, returning the removed value.
+    pub fn remove_attribute(
+        &mut self,
+        id: NodeId,
+        name: &str,
+    ) -> Option<String> {
+        let NodeType::Element(ref mut elem) = self.root.find_mut(id)?.node_type
+        else {
+            return None;
+        };
+        if !elem.attributes.contains_key(name) {
+            return None;
+        }
+        self.element_index.remove_element(id, elem);
+        let removed = elem.attributes.remove(name)?;
+        self.element_index.insert_element(id, elem);
+        self.notify_attribute_change(id, name);
+        Some(removed)
+    }
+
+    // Replace the content of a text node, returning the previous content.
+    // Observers are not notified if the content is the same.
+    pub fn set_text(&mut self, id: NodeId, text: String) -> Option<String> {
+        let NodeType::Text(ref mut content) = self.root.find_mut(id)?.node_type
+        else {
+            return None;
+        };
+        let changed = *content != text;
+        let previous = mem::replace(content, text);
+        if changed {
+            self.notify_text_change(id);
+        }
+        Some(previous)
+    }
+
+    // Register an event listener on a node, invoked during the capturing
+    // phase if `capture` is true and the bubbling phase otherwise.
+    pub fn add_event_listener(
+        &mut self,
+        node: NodeId,
+        event_type: &str,
+        capture: bool,
+        callback: impl FnMut(&mut Event) + Send + Sync + 'static,
+    ) -> ListenerId {
+        self.event_listeners
+            .add(node, event_type, capture, Box::new(callback))
+    }
+
+    // Unregister an event listener, returning whether it was found.
+    pub fn remove_event_listener(
+        &mut self,
+        node: NodeId,
+        listener: ListenerId,
+    ) -> bool {
+        self.event_listeners.remove(node, listener)
+    }
+
+    // Dispatch an event to a node, propagating it from the root down to the
+    // node and back up if it bubbles.
+    // Return `false` if a listener canceled the default action.
+    pub fn dispatch_event(
+        &mut self,
+        target: NodeId,
+        event: &mut Event,
+    ) -> bool {
+        let Some(path) = self.root.path_to(target) else {
+            return true;
+        };
+        let mut node = &self.root;
+        let mut ids = vec![node.node_id];
+        for i in path {
+            node = &node.children[i];
+            ids.push(node.node_id);
+        }
+        self.event_listeners.dispatch(&ids, event);
+        !event.default_prevented()
+    }
+
+    // Attach a value of type `T` to a node, returning the previous one.
+    // Each node holds at most one value per type, so independent embedders
+    // can store their own state without conflicts. The value is kept even if
+    // the node is detached, so it survives moving the node around, until
+    // `purge_user_data` drops it.
+    pub fn set_user_data<T: Any + Send + Sync>(
+        &mut self,
+        id: NodeId,
+        value: T,
+    ) -> Option<T> {
+        self.user_data
+            .insert((id, TypeId::of::<T>()), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    // Get the value of type `T` attached to a node.
+    pub fn user_data<T: Any + Send + Sync>(&self, id: NodeId) -> Option<&T> {
+        self.user_data
+            .get(&(id, TypeId::of::<T>()))
+            .and_then(|value| value.downcast_ref())
+    }
+
+    // Get the value of type `T` attached to a node, mutably.
+    pub fn user_data_mut<T: Any + Send + Sync>(
+        &mut self,
+        id: NodeId,
+    ) -> Option<&mut T> {
+        self.user_data
+            .get_mut(&(id, TypeId::of::<T>()))
+            .and_then(|value| value.downcast_mut())
+    }
+
+    // Detach the value of type `T` from a node.
+    pub fn remove_user_data<T: Any + Send + Sync>(
+        &mut self,
+        id: NodeId,
+    ) -> Option<T> {
+        self.user_data
+            .remove(&(id, TypeId::of::<T>()))
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    // Drop the values attached to nodes that are no longer in the document,
+    // such as nodes that were removed for good. Nodes that are being moved
+    // must be inserted again first to keep their values.
+    pub fn purge_user_data(&mut self) {
+        let mut ids = HashSet::new();
+        collect_ids(&self.root, &mut ids);
+        self.user_data.retain(|(id, _), _| ids.contains(id));
+    }
+
+    // Mark the subtree of a node as needing restyle, for example after its
+    // pseudo-class state changed.
+    #[inline]
+    pub fn invalidate_style(&mut self, id: NodeId) {
+        self.style_dirty.insert(id);
+    }
+
+    // Take the roots of the subtrees that need restyle, clearing the dirty
+    // bits. Nodes inside another dirty subtree or no longer in the document
+    // are left out.
+    pub fn take_style_dirty_roots(&mut self) -> Vec<NodeId> {
+        let dirty = mem::take(&mut self.style_dirty);
+        dirty
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let Some(path) = self.root.path_to(id) else {
+                    return false;
+                };
+                let mut node = &self.root;
+                for i in path {
+                    if dirty.contains(&node.node_id) {
+                        return false;
+                    }
+                    node = &node.children[i];
+                }
+                true
+            })
+            .collect()
+    }
+
+    // Notify observers that the children of a node changed.
+    fn notify_child_list_change(&mut self, parent: NodeId) {
+        self.style_dirty.insert(parent);
+        for callback in &mut self.child_list_callbacks {
+            callback(parent);
+        }
+    }
+
+    // Notify observers that an attribute of an element changed.
+    fn notify_attribute_change(&mut self, id: NodeId, name: &str) {
+        self.style_dirty.insert(id);
+        for callback in &mut self.attribute_callbacks {
+            callback(id, name);
+        }
+    }
+
+    // Notify observers that the content of a text node changed.
+    fn notify_text_change(&mut self, id: NodeId) {
+        for callback in &mut self.text_callbacks {
+            callback(id);
+        }
+    }
+}
+
+// Find the value of an attribute of the first element with the given tag
+// name that has it, in tree order.
+fn find_attribute<'a>(
+    node: &'a Node,
+    tag_name: &str,
+    attribute: &str,
+) -> Option<&'a String> {
+    if let NodeType::Element(ref elem) = node.node_type {
+        if elem.tag_name == tag_name {
+            if let Some(value) = elem.attributes.get(attribute) {
+                return Some(value);
+            }
+        }
+    }
+    node.children
+        .iter()
+        .find_map(|child| find_attribute(child, tag_name, attribute))
+}
+
+// Collect the ids of the nodes of a subtree.
+fn collect_ids(node: &Node, ids: &mut HashSet<NodeId>) {
+    ids.insert(node.node_id);
+    for child in &node.children {
+        collect_ids(child, ids);
+    }
+}
+
+// Find the first node of a set in tree order.
+fn first_in_tree_order<'a>(
+    node: &'a Node,
+    ids: &HashSet<NodeId>,
+) -> Option<&'a Node> {
+    if ids.contains(&node.node_id) {
+        return Some(node);
+    }
+    node.children
+        .iter()
+        .find_map(|child| first_in_tree_order(child, ids))
+}
+
+// Collect the nodes of a set in tree order.
+fn collect_in_tree_order<'a>(
+    node: &'a Node,
+    ids: &HashSet<NodeId>,
+    nodes: &mut Vec<&'a Node>,
+) {
+    if ids.contains(&node.node_id) {
+        nodes.push(node);
+    }
+    for child in &node.children {
+        collect_in_tree_order(child, ids, nodes);
+    }
+}
+
+// Assert at compile time that documents can be sent across threads.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Node>();
+    assert_send_sync::<Document>();
+};
+
+impl fmt::Debug for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Document")
+            .field("root", &self.root)
+            .field("url", &self.url)
+            .field("quirks_mode", &self.quirks_mode)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::html_parser::HTMLParser;
+
+    fn parse(html: &str) -> Document {
+        Document::new(HTMLParser::parse(html.to_string()))
+    }
+
+    // Get the id of the element with an id attribute.
+    fn id(document: &Document, element_id: &str) -> NodeId {
+        document.get_element_by_id(element_id).unwrap().node_id
+    }
+
+    #[test]
+    fn test_base_url() {
+        let parse_with_url = |html: &str| {
+            let mut document = HTMLParser::parse_document(html.to_string());
+            document.url = Some("http://example.com/a/b.html".to_string());
+            document
+        };
+        assert_eq!(
+            parse_with_url("<html></html>").base_url().as_deref(),
+            Some("http://example.com/a/b.html")
+        );
+        // A `<base>` without an `href` is skipped.
+        assert_eq!(
+            parse_with_url(
+                "<html><head><base target=\"_top\"></base>\
+                 <base href=\"/c/\"></base><base href=\"/d/\"></base>\
+                 </head></html>"
+            )
+            .base_url()
+            .as_deref(),
+            Some("http://example.com/c/")
+        );
+    }
+
+    #[test]
+    fn test_mutation_callbacks() {
+        let mut document = parse("<div id=\"a\"><p id=\"b\">c</p></div>");
+        let (a, b) = (id(&document, "a"), id(&document, "b"));
+        let text = document.node(b).unwrap().children[0].node_id;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let child_list_log = Arc::clone(&log);
+        document.on_child_list_change(move |parent| {
+            child_list_log
+                .lock()
+                .unwrap()
+                .push(("children", parent, None));
+        });
+        let attribute_log = Arc::clone(&log);
+        document.on_attribute_change(move |id, name| {
+            let name = Some(name.to_string());
+            attribute_log.lock().unwrap().push(("attribute", id, name));
+        });
+        let text_log = Arc::clone(&log);
+        document.on_text_change(move |id| {
+            text_log.lock().unwrap().push(("text", id, None));
+        });
+        let take = || mem::take(&mut *log.lock().unwrap());
+
+        let span =
+            Node::new_by_element("span".to_string(), HashMap::new(), vec![]);
+        let span = document.insert_child(a, 0, span).unwrap();
+        let em = Node::new_by_element("em".to_string(), HashMap::new(), vec![]);
+        document.append_child(b, em).unwrap();
+        document.remove_child(a, span).unwrap();
+        assert_eq!(
+            take(),
+            [
+                ("children", a, None),
+                ("children", b, None),
+                ("children", a, None)
+            ]
+        );
+
+        assert!(document.set_attribute(b, "title", "d".to_string()));
+        assert_eq!(
+            document.remove_attribute(b, "title"),
+            Some("d".to_string())
+        );
+        assert_eq!(
+            document.set_text(text, "e".to_string()),
+            Some("c".to_string())
+        );
+        let title = Some("title".to_string());
+        assert_eq!(
+            take(),
+            [
+                ("attribute", b, title.clone()),
+                ("attribute", b, title),
+                ("text", text, None)
+            ]
+        );
+
+        // Mutations that change nothing, or fail, don't notify.
+        assert!(document.set_attribute(b, "id", "b".to_string()));
+        assert_eq!(document.remove_attribute(b, "title"), None);
+        assert_eq!(
+            document.set_text(text, "e".to_string()),
+            Some("e".to_string())
+        );
+        assert!(document.remove_child(a, span).is_none());
+        assert!(document.remove_child(b, a).is_none());
+        assert!(!document.set_attribute(text, "id", "f".to_string()));
+        assert_eq!(document.set_text(b, "f".to_string()), None);
+        let orphan = Node::new_by_text("g".to_string());
+        assert!(document.append_child(span, orphan).is_none());
+        assert!(take().is_empty());
+    }
+
+    #[test]
+    fn test_element_index() {
+        let mut document = parse(
+            "<div id=\"root\"><p id=\"a\" class=\"x y\">a</p>\
+             <section><p id=\"b\" class=\"y\">b</p></section>\
+             <p id=\"c\" class=\"x\">c</p></div>",
+        );
+        let (a, b, c) =
+            (id(&document, "a"), id(&document, "b"), id(&document, "c"));
+        let class = |document: &Document, class| -> Vec<NodeId> {
+            document
+                .get_elements_by_class_name(class)
+                .iter()
+                .map(|node| node.node_id)
+                .collect()
+        };
+        // Elements are returned in document order, not in the order that
+        // they were indexed in.
+        assert_eq!(class(&document, "x"), [a, c]);
+        assert_eq!(class(&document, "y"), [a, b]);
+        assert!(class(&document, "z").is_empty());
+
+        // Changing the id or the classes moves an element between buckets.
+        document.set_attribute(c, "class", "y z".to_string());
+        assert_eq!(class(&document, "x"), [a]);
+        assert_eq!(class(&document, "y"), [a, b, c]);
+        assert_eq!(class(&document, "z"), [c]);
+        document.set_attribute(c, "id", "d".to_string());
+        assert!(document.get_element_by_id("c").is_none());
+        assert_eq!(id(&document, "d"), c);
+        // Two elements with the same id are found in document order.
+        document.set_attribute(a, "id", "d".to_string());
+        assert_eq!(id(&document, "d"), a);
+
+        document.remove_attribute(a, "id");
+        assert_eq!(id(&document, "d"), c);
+        document.remove_attribute(a, "class");
+        assert_eq!(class(&document, "y"), [b, c]);
+        assert!(class(&document, "x").is_empty());
+
+        // Removing an element removes its subtree from the index.
+        let section = document.root.parent_of(b).unwrap().node_id;
+        document.remove_child(id(&document, "root"), section);
+        assert!(document.get_element_by_id("b").is_none());
+        assert_eq!(class(&document, "y"), [c]);
+    }
+
+    #[test]
+    fn test_user_data() {
+        let mut document =
+            parse("<div id=\"a\"><p id=\"b\"><em id=\"c\">c</em></p></div>");
+        let (a, b, c) =
+            (id(&document, "a"), id(&document, "b"), id(&document, "c"));
+        assert_eq!(document.set_user_data(b, 1_u32), None);
+        assert_eq!(document.set_user_data(b, 2_u32), Some(1));
+        document.set_user_data(b, "b");
+        document.set_user_data(c, 3_u32);
+        *document.user_data_mut::<u32>(c).unwrap() += 1;
+        assert_eq!(document.user_data::<u32>(b), Some(&2));
+        assert_eq!(document.user_data::<&str>(b), Some(&"b"));
+        assert_eq!(document.user_data::<u32>(c), Some(&4));
+        assert_eq!(document.user_data::<u64>(b), None);
+
+        // The values of a moved subtree survive a purge.
+        let p = document.detach(b).unwrap();
+        document.insert_child(a, 0, p).unwrap();
+        document.purge_user_data();
+        assert_eq!(document.user_data::<u32>(b), Some(&2));
+        assert_eq!(document.user_data::<u32>(c), Some(&4));
+
+        // The values of a removed subtree are dropped by a purge.
+        document.detach(b).unwrap();
+        assert_eq!(document.user_data::<u32>(c), Some(&4));
+        document.purge_user_data();
+        assert_eq!(document.user_data::<u32>(b), None);
+        assert_eq!(document.user_data::<&str>(b), None);
+        assert_eq!(document.user_data::<u32>(c), None);
+        assert_eq!(document.remove_user_data::<u32>(c), None);
+        assert!(document.user_data.is_empty());
+
+        document.set_user_data(a, 5_u32);
+        assert_eq!(document.remove_user_data::<u32>(a), Some(5));
+        assert!(document.user_data.is_empty());
+    }
+
+    #[test]
+    fn test_document_position() {
+        let mut document = parse(
+            "<div id=\"a\"><p id=\"b\"><em id=\"c\">c</em></p>\
+             <p id=\"d\">d</p></div>",
+        );
+        let [a, b, c, d] =
+            ["a", "b", "c", "d"].map(|element_id| id(&document, element_id));
+        let text = document.node(d).unwrap().children[0].node_id;
+
+        assert_eq!(document.index(a), None);
+        assert_eq!(document.index(b), Some(0));
+        assert_eq!(document.index(c), Some(0));
+        assert_eq!(document.index(d), Some(1));
+        assert_eq!(document.index(text), Some(0));
+
+        use DocumentPosition::*;
+        let position =
+            |node, other| document.compare_document_position(node, other);
+        assert_eq!(position(b, b), Same);
+        assert_eq!(position(c, a), Contains);
+        assert_eq!(position(c, b), Contains);
+        assert_eq!(position(a, c), ContainedBy);
+        assert_eq!(position(d, b), Preceding);
+        assert_eq!(position(d, c), Preceding);
+        assert_eq!(position(c, d), Following);
+        assert_eq!(position(c, text), Following);
+        assert_eq!(position(text, d), Contains);
+
+        // Nodes that are not in the document are disconnected from all.
+        let detached = document.detach(b).unwrap();
+        assert_eq!(document.index(b), None);
+        assert_eq!(document.index(d), Some(0));
+        for (node, other) in [(a, c), (c, a), (b, c), (c, c)] {
+            assert_eq!(
+                document.compare_document_position(node, other),
+                Disconnected
+            );
+        }
+        let orphan = Node::new_by_text("e".to_string());
+        assert_eq!(
+            document.compare_document_position(a, orphan.node_id),
+            Disconnected
+        );
+        assert!(detached.contains(c));
+    }
+
+    #[test]
+    fn test_stats() {
+        let document = parse(
+            "<div id=\"a\" class=\"b\"><p>cd<em>e</em></p><!--f-->\
+             <ul><li><a href=\"g\">hi</a></li></ul></div>",
+        );
+        let stats = document.stats();
+        assert_eq!(stats.element_count, 6);
+        assert_eq!(stats.text_count, 3);
+        assert_eq!(stats.comment_count, 1);
+        assert_eq!(stats.node_count(), 10);
+        assert_eq!(stats.attribute_count, 3);
+        assert_eq!(stats.text_bytes, 5);
+        // `div > ul > li > a > text`
+        assert_eq!(stats.depth, 5);
+        assert!(stats.heap_bytes >= stats.text_bytes);
+
+        let document = Document::new(Node::new_by_text(String::new()));
+        let stats = document.stats();
+        assert_eq!(
+            (stats.text_count, stats.depth, stats.text_bytes),
+            (1, 1, 0)
+        );
+        assert_eq!(stats.node_count(), 1);
+    }
+
+    #[test]
+    fn test_detach() {
+        let mut document = parse(
+            "<div id=\"a\"><section id=\"b\"><p id=\"c\" class=\"x\">c</p>\
+             </section><p id=\"d\" class=\"x\">d</p></div>",
+        );
+        let [a, b, c, d] =
+            ["a", "b", "c", "d"].map(|element_id| id(&document, element_id));
+
+        // The subtree leaves the tree and the index.
+        let section = document.detach(b).unwrap();
+        assert_eq!(section.node_id, b);
+        assert!(document.node(b).is_none());
+        assert!(document.node(c).is_none());
+        assert!(document.get_element_by_id("b").is_none());
+        assert!(document.get_element_by_id("c").is_none());
+        let class: Vec<NodeId> = document
+            .get_elements_by_class_name("x")
+            .iter()
+            .map(|node| node.node_id)
+            .collect();
+        assert_eq!(class, [d]);
+        assert!(document.detach(b).is_none());
+        // The root can't be detached.
+        assert!(document.detach(a).is_none());
+
+        // It can be inserted again elsewhere, with its ids.
+        document.append_child(d, section).unwrap();
+        assert_eq!(document.root.parent_of(b).unwrap().node_id, d);
+        assert_eq!(id(&document, "c"), c);
+        let class: Vec<NodeId> = document
+            .get_elements_by_class_name("x")
+            .iter()
+            .map(|node| node.node_id)
+            .collect();
+        assert_eq!(class, [d, c]);
+        assert_eq!(document.root.text_content(), "dc");
+    }
+}
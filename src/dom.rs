@@ -1,7 +1,26 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{css_parser::escape_identifier, url};
+
+// A unique identifier of a node, stable across tree mutations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(usize);
+
+impl NodeId {
+    // Allocate a new unique id.
+    fn next() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 #[derive(Debug)]
 pub struct Node {
+    pub node_id: NodeId,
     pub children: Vec<Node>,
     pub node_type: NodeType,
 }
@@ -10,11 +29,21 @@ impl Node {
     // Create a node by given text.
     pub fn new_by_text(text: String) -> Self {
         Self {
+            node_id: NodeId::next(),
             children: vec![],
             node_type: NodeType::Text(text),
         }
     }
 
+    // Create a node by given comment.
+    pub fn new_by_comment(comment: String) -> Self {
+        Self {
+            node_id: NodeId::next(),
+            children: vec![],
+            node_type: NodeType::Comment(comment),
+        }
+    }
+
     // Create a node by given element data.
     pub fn new_by_element(
         tag_name: String,
@@ -22,6 +51,7 @@ impl Node {
         children: Vec<Node>,
     ) -> Self {
         Self {
+            node_id: NodeId::next(),
             children,
             node_type: NodeType::Element(ElementData {
                 tag_name,
@@ -29,11 +59,171 @@ impl Node {
             }),
         }
     }
+
+    // Merge adjacent text nodes and remove empty ones, recursively.
+    pub fn normalize(&mut self) {
+        let mut children: Vec<Node> = Vec::with_capacity(self.children.len());
+        for mut child in std::mem::take(&mut self.children) {
+            child.normalize();
+            if let NodeType::Text(ref text) = child.node_type {
+                if text.is_empty() {
+                    continue;
+                }
+                if let Some(Node {
+                    node_type: NodeType::Text(prev),
+                    ..
+                }) = children.last_mut()
+                {
+                    prev.push_str(text);
+                    continue;
+                }
+            }
+            children.push(child);
+        }
+        self.children = children;
+    }
+
+    // Find a node by id in this subtree.
+    pub fn find(&self, id: NodeId) -> Option<&Node> {
+        if self.node_id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    // Get the concatenated text of all text nodes in this subtree.
+    pub fn text_content(&self) -> String {
+        match self.node_type {
+            NodeType::Text(ref text) => text.clone(),
+            NodeType::Comment(_) => String::new(),
+            NodeType::Element(_) => {
+                self.children.iter().map(Node::text_content).collect()
+            }
+        }
+    }
+
+    // Check if a node is this node or one of its descendants.
+    #[inline]
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.find(id).is_some()
+    }
+
+    // Find the parent of a node in this subtree.
+    pub fn parent_of(&self, id: NodeId) -> Option<&Node> {
+        if self.children.iter().any(|child| child.node_id == id) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.parent_of(id))
+    }
+
+    // Build a selector that uniquely identifies a descendant, such as
+    // `html > body > div#main > p:nth-child(2)`.
+    // Return `None` if the node is not an element in this subtree.
+    pub fn css_path(&self, id: NodeId) -> Option<String> {
+        let path = self.path_to(id)?;
+        let mut node = self;
+        let mut steps = vec![node.css_step(None)?];
+        for i in path {
+            let parent = node;
+            node = &parent.children[i];
+            steps.push(node.css_step(Some(parent))?);
+        }
+        Some(steps.join(" > "))
+    }
+
+    // Build the selector for this element alone, disambiguated among the
+    // children of its parent.
+    fn css_step(&self, parent: Option<&Node>) -> Option<String> {
+        let NodeType::Element(ref elem) = self.node_type else {
+            return None;
+        };
+        let mut step = escape_identifier(&elem.tag_name);
+        if let Some(id) = elem.id() {
+            step.push('#');
+            step.push_str(&escape_identifier(id));
+            return Some(step);
+        }
+
+        let Some(parent) = parent else {
+            return Some(step);
+        };
+        let siblings: Vec<&Node> = parent
+            .children
+            .iter()
+            .filter(|child| matches!(child.node_type, NodeType::Element(_)))
+            .collect();
+        let has_same_tag_sibling = siblings.iter().any(|sibling| {
+            sibling.node_id != self.node_id
+                && matches!(
+                    sibling.node_type,
+                    NodeType::Element(ref other) if other.tag_name == elem.tag_name
+                )
+        });
+        if has_same_tag_sibling {
+            let index = siblings
+                .iter()
+                .position(|sibling| sibling.node_id == self.node_id)?;
+            step.push_str(&format!(":nth-child({})", index + 1));
+        }
+        Some(step)
+    }
+
+    // Get the child indices leading from this node to the given node.
+    // The path to this node itself is empty.
+    pub fn path_to(&self, id: NodeId) -> Option<Vec<usize>> {
+        if self.node_id == id {
+            return Some(Vec::new());
+        }
+        self.children.iter().enumerate().find_map(|(i, child)| {
+            let mut path = child.path_to(id)?;
+            path.insert(0, i);
+            Some(path)
+        })
+    }
+
+    // Find a node by id in this subtree, mutably.
+    pub fn find_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        if self.node_id == id {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_mut(id))
+    }
+
+    // Get the elements in this subtree that the Tab key moves the focus
+    // through, in that order: those with a positive `tabindex` in
+    // ascending order first, and then the others in tree order.
+    // SEE: https://html.spec.whatwg.org/multipage/interaction.html#sequential-focus-navigation
+    pub fn focusable_elements(&self) -> Vec<NodeId> {
+        let mut elements = Vec::new();
+        self.collect_focusable(&mut elements);
+        // The sort is stable, so elements of the same index stay in tree
+        // order.
+        elements.sort_by_key(
+            |&(index, _)| if index > 0 { index } else { i32::MAX },
+        );
+        elements.into_iter().map(|(_, id)| id).collect()
+    }
+
+    fn collect_focusable(&self, elements: &mut Vec<(i32, NodeId)>) {
+        if let NodeType::Element(ref elem) = self.node_type {
+            if let Some(index) = elem.tab_index() {
+                if index >= 0 {
+                    elements.push((index, self.node_id));
+                }
+            }
+        }
+        for child in &self.children {
+            child.collect_focusable(elements);
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum NodeType {
     Text(String),
+    Comment(String),
     Element(ElementData),
 }
 
@@ -54,8 +244,271 @@ impl ElementData {
     // Get classes.
     pub fn classes(&self) -> HashSet<&str> {
         match self.attributes.get("class") {
-            Some(classes) => classes.split(' ').collect(),
+            Some(classes) => classes.split_ascii_whitespace().collect(),
             None => HashSet::new(),
         }
     }
+
+    // Get the tab index of an element, which is `None` if it can't be
+    // focused. Links, buttons, and form controls can be by default, and
+    // other elements if they have a `tabindex` attribute. Elements whose
+    // tab index is negative are focused by clicking, but not by the Tab
+    // key.
+    // SEE: https://html.spec.whatwg.org/multipage/interaction.html#attr-tabindex
+    pub fn tab_index(&self) -> Option<i32> {
+        if let Some(index) = self.attributes.get("tabindex") {
+            if let Ok(index) = index.trim().parse() {
+                return Some(index);
+            }
+        }
+        let focusable = match self.tag_name.as_str() {
+            "a" => self.attributes.contains_key("href"),
+            "input" => self
+                .attributes
+                .get("type")
+                .is_none_or(|kind| !kind.eq_ignore_ascii_case("hidden")),
+            "button" | "select" | "textarea" => true,
+            _ => false,
+        };
+        (focusable && !self.attributes.contains_key("disabled")).then_some(0)
+    }
+
+    // Get the value of a URL attribute such as `href` or `src`, resolved
+    // against the given base URL.
+    pub fn resolved_url(&self, attribute: &str, base: &str) -> Option<String> {
+        self.attributes
+            .get(attribute)
+            .map(|reference| url::resolve(base, reference))
+    }
+}
+
+// A point in the tree, at an offset in a node, which counts bytes in the
+// text of a text node and children in other nodes.
+// SEE: https://dom.spec.whatwg.org/#concept-range-bp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundaryPoint {
+    pub node: NodeId,
+    pub offset: usize,
+}
+
+impl BoundaryPoint {
+    // Get the position of the point in tree order, as a path to a node and
+    // an offset in its text. A point in another node is before its child
+    // at the offset.
+    fn position(&self, root: &Node) -> Option<(Vec<usize>, usize)> {
+        let mut path = root.path_to(self.node)?;
+        match root.find(self.node)?.node_type {
+            NodeType::Text(_) => Some((path, self.offset)),
+            _ => {
+                path.push(self.offset);
+                Some((path, 0))
+            }
+        }
+    }
+}
+
+// The content of a tree between two boundary points, such as the text
+// that the user selected. The start is not after the end.
+// SEE: https://dom.spec.whatwg.org/#concept-range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: BoundaryPoint,
+    pub end: BoundaryPoint,
+}
+
+impl Range {
+    // Get the bytes of the text of each text node that are in the range.
+    // Nothing is in a range whose points are not in the tree.
+    pub fn text_ranges(
+        &self,
+        root: &Node,
+    ) -> HashMap<NodeId, std::ops::Range<usize>> {
+        let mut ranges = HashMap::new();
+        if let (Some(start), Some(end)) =
+            (self.start.position(root), self.end.position(root))
+        {
+            collect_text_ranges(
+                root,
+                &mut Vec::new(),
+                &start,
+                &end,
+                &mut ranges,
+            );
+        }
+        ranges
+    }
+}
+
+fn collect_text_ranges(
+    node: &Node,
+    path: &mut Vec<usize>,
+    start: &(Vec<usize>, usize),
+    end: &(Vec<usize>, usize),
+    ranges: &mut HashMap<NodeId, std::ops::Range<usize>>,
+) {
+    if let NodeType::Text(ref text) = node.node_type {
+        let from = match start.0.cmp(path) {
+            std::cmp::Ordering::Less => 0,
+            std::cmp::Ordering::Equal => start.1.min(text.len()),
+            std::cmp::Ordering::Greater => return,
+        };
+        let to = match end.0.cmp(path) {
+            std::cmp::Ordering::Greater => text.len(),
+            std::cmp::Ordering::Equal => end.1.min(text.len()),
+            std::cmp::Ordering::Less => return,
+        };
+        if from < to {
+            ranges.insert(node.node_id, from..to);
+        }
+        return;
+    }
+    for (i, child) in node.children.iter().enumerate() {
+        path.push(i);
+        collect_text_ranges(child, path, start, end, ranges);
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{html_parser::HTMLParser, style::query_selector};
+
+    fn parse(html: &str) -> Node {
+        HTMLParser::parse(html.to_string())
+    }
+
+    // Get the ids of the elements of a subtree in tree order.
+    fn element_ids(node: &Node, ids: &mut Vec<NodeId>) {
+        if let NodeType::Element(_) = node.node_type {
+            ids.push(node.node_id);
+        }
+        for child in &node.children {
+            element_ids(child, ids);
+        }
+    }
+
+    #[test]
+    fn test_css_path() {
+        let root = parse(
+            "<html><body><div id=\"main\"><p>a</p>text<span>b</span>\
+             <p>c</p></div><div><p>d</p></div></body></html>",
+        );
+        let path = |selector| {
+            let id = query_selector(&root, selector).unwrap().node_id;
+            root.css_path(id).unwrap()
+        };
+        assert_eq!(path("html"), "html");
+        // Elements with an id need no position, and only siblings of the
+        // same tag are told apart by their position among the elements.
+        assert_eq!(path("#main"), "html > body > div#main");
+        assert_eq!(path("#main > span"), "html > body > div#main > span");
+        assert_eq!(
+            path("#main > p"),
+            "html > body > div#main > p:nth-child(1)"
+        );
+        assert_eq!(
+            path("#main > p:nth-child(3)"),
+            "html > body > div#main > p:nth-child(3)"
+        );
+        assert_eq!(
+            path("div:nth-child(2) p"),
+            "html > body > div:nth-child(2) > p"
+        );
+        // Text isn't an element, and neither is a node of another tree.
+        let text = &root.children[0].children[0].children[1];
+        assert!(matches!(text.node_type, NodeType::Text(_)));
+        assert!(root.css_path(text.node_id).is_none());
+        assert!(root.css_path(parse("<p></p>").node_id).is_none());
+    }
+
+    #[test]
+    fn test_css_path_round_trip() {
+        // Each element is selected by its path, even with ids that must be
+        // escaped.
+        let root = parse(
+            "<html><body><ul><li>a</li><li id=\"1st\">b</li><li>c</li></ul>\
+             <ul><li class=\"x\">d</li><li class=\"x\">e</li></ul>\
+             <div id=\"a b\"><p>f</p><p>g</p></div>\
+             <div id=\"-\"><span>h</span></div></body></html>",
+        );
+        let mut ids = Vec::new();
+        element_ids(&root, &mut ids);
+        assert_eq!(ids.len(), 14);
+        for id in ids {
+            let path = root.css_path(id).unwrap();
+            let found = query_selector(&root, &path).map(|node| node.node_id);
+            assert_eq!(found, Some(id), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let root = parse("<div><p><em>a</em></p><p>b</p></div>");
+        let p = &root.children[0];
+        let em = &p.children[0];
+        let text = &em.children[0];
+        // A node contains itself and its descendants.
+        assert!(root.contains(root.node_id));
+        assert!(root.contains(text.node_id));
+        assert!(p.contains(p.node_id));
+        assert!(p.contains(em.node_id));
+        assert!(p.contains(text.node_id));
+        // But not its ancestors, siblings, or nodes of other trees.
+        assert!(!em.contains(p.node_id));
+        assert!(!p.contains(root.children[1].node_id));
+        assert!(!text.contains(em.node_id));
+        assert!(!root.contains(parse("<p></p>").node_id));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let text = |text: &str| Node::new_by_text(text.to_string());
+        let element = |tag_name: &str, children| {
+            Node::new_by_element(tag_name.to_string(), HashMap::new(), children)
+        };
+        let mut root = element(
+            "div",
+            vec![
+                text(""),
+                text("a"),
+                text(""),
+                text("b"),
+                element("p", vec![text("c"), text(""), text("d")]),
+                text("e"),
+                Node::new_by_comment("f".to_string()),
+                text("g"),
+                text("h"),
+                element("p", vec![text("")]),
+            ],
+        );
+        let first = root.children[1].node_id;
+        root.normalize();
+
+        // Adjacent text is merged into the first node, empty text is
+        // dropped, and comments keep text apart.
+        let children: Vec<String> = root
+            .children
+            .iter()
+            .map(|child| match child.node_type {
+                NodeType::Text(ref text) => text.clone(),
+                NodeType::Comment(_) => "<!---->".to_string(),
+                NodeType::Element(_) => {
+                    format!("<p>{}</p>", child.children.len())
+                }
+            })
+            .collect();
+        assert_eq!(
+            children,
+            ["ab", "<p>1</p>", "e", "<!---->", "gh", "<p>0</p>"]
+        );
+        assert_eq!(root.children[0].node_id, first);
+        // Children are normalized too.
+        assert_eq!(root.children[1].children[0].text_content(), "cd");
+        assert_eq!(root.text_content(), "abcdegh");
+
+        // Normalizing again changes nothing.
+        root.normalize();
+        assert_eq!(root.children.len(), 6);
+    }
 }
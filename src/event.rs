@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use crate::dom::NodeId;
+
+// The phase of event propagation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventPhase {
+    None,
+    Capturing,
+    AtTarget,
+    Bubbling,
+}
+
+// An event dispatched through the tree, such as a click or a key press.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub event_type: String,
+    pub bubbles: bool,
+    // Extra data such as a key name or pointer position, as the frontend
+    // sees fit.
+    pub detail: HashMap<String, String>,
+    target: Option<NodeId>,
+    current_target: Option<NodeId>,
+    phase: EventPhase,
+    propagation_stopped: bool,
+    default_prevented: bool,
+}
+
+impl Event {
+    // Create an event that has not been dispatched yet.
+    pub fn new(event_type: &str, bubbles: bool) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            bubbles,
+            detail: HashMap::new(),
+            target: None,
+            current_target: None,
+            phase: EventPhase::None,
+            propagation_stopped: false,
+            default_prevented: false,
+        }
+    }
+
+    // Get the node the event was dispatched to.
+    #[inline]
+    pub fn target(&self) -> Option<NodeId> {
+        self.target
+    }
+
+    // Get the node whose listener is currently being invoked.
+    #[inline]
+    pub fn current_target(&self) -> Option<NodeId> {
+        self.current_target
+    }
+
+    // Get the current propagation phase.
+    #[inline]
+    pub fn phase(&self) -> EventPhase {
+        self.phase
+    }
+
+    // Stop the event from reaching further nodes. The remaining listeners
+    // of the current node are still invoked.
+    #[inline]
+    pub fn stop_propagation(&mut self) {
+        self.propagation_stopped = true;
+    }
+
+    // Cancel the default action of the event.
+    #[inline]
+    pub fn prevent_default(&mut self) {
+        self.default_prevented = true;
+    }
+
+    // Check if the default action of the event was canceled.
+    #[inline]
+    pub fn default_prevented(&self) -> bool {
+        self.default_prevented
+    }
+}
+
+// A handle to remove a registered listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(usize);
+
+// A callback invoked when an event reaches a node.
+type Listener = Box<dyn FnMut(&mut Event) + Send + Sync>;
+
+struct ListenerEntry {
+    id: ListenerId,
+    event_type: String,
+    capture: bool,
+    callback: Listener,
+}
+
+// Event listeners of all nodes in a document.
+#[derive(Default)]
+pub(crate) struct EventListeners {
+    entries: HashMap<NodeId, Vec<ListenerEntry>>,
+    next_id: usize,
+}
+
+impl EventListeners {
+    // Register a listener on a node.
+    pub(crate) fn add(
+        &mut self,
+        node: NodeId,
+        event_type: &str,
+        capture: bool,
+        callback: Listener,
+    ) -> ListenerId {
+        let id = ListenerId(self.next_id);
+        self.next_id += 1;
+        self.entries.entry(node).or_default().push(ListenerEntry {
+            id,
+            event_type: event_type.to_string(),
+            capture,
+            callback,
+        });
+        id
+    }
+
+    // Unregister a listener, returning whether it was found.
+    pub(crate) fn remove(&mut self, node: NodeId, id: ListenerId) -> bool {
+        let Some(entries) = self.entries.get_mut(&node) else {
+            return false;
+        };
+        let len = entries.len();
+        entries.retain(|entry| entry.id != id);
+        entries.len() != len
+    }
+
+    // Dispatch an event along a path of nodes from the root to the target.
+    pub(crate) fn dispatch(&mut self, path: &[NodeId], event: &mut Event) {
+        let Some((&target, ancestors)) = path.split_last() else {
+            return;
+        };
+        event.target = Some(target);
+
+        event.phase = EventPhase::Capturing;
+        for &node in ancestors {
+            self.invoke(node, event, |entry| entry.capture);
+            if event.propagation_stopped {
+                return self.finish(event);
+            }
+        }
+
+        // At the target, capturing listeners run before the others.
+        event.phase = EventPhase::AtTarget;
+        self.invoke(target, event, |entry| entry.capture);
+        self.invoke(target, event, |entry| !entry.capture);
+        if event.propagation_stopped || !event.bubbles {
+            return self.finish(event);
+        }
+
+        event.phase = EventPhase::Bubbling;
+        for &node in ancestors.iter().rev() {
+            self.invoke(node, event, |entry| !entry.capture);
+            if event.propagation_stopped {
+                break;
+            }
+        }
+        self.finish(event);
+    }
+
+    // Invoke the listeners of a node that accept the event.
+    fn invoke(
+        &mut self,
+        node: NodeId,
+        event: &mut Event,
+        filter: impl Fn(&ListenerEntry) -> bool,
+    ) {
+        let Some(entries) = self.entries.get_mut(&node) else {
+            return;
+        };
+        event.current_target = Some(node);
+        for entry in entries.iter_mut() {
+            if entry.event_type == event.event_type && filter(entry) {
+                (entry.callback)(event);
+            }
+        }
+    }
+
+    // Reset the dispatch state after the event has propagated.
+    fn finish(&self, event: &mut Event) {
+        event.phase = EventPhase::None;
+        event.current_target = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        document::Document,
+        html_parser::HTMLParser,
+        style::query_selector,
+    };
+
+    // A document of nested elements, and the ids of the outer, middle and
+    // inner ones.
+    fn nested() -> (Document, [NodeId; 3]) {
+        let document = Document::new(HTMLParser::parse(
+            "<div id=\"outer\"><p id=\"middle\"><span id=\"inner\">a</span>\
+             </p></div>"
+                .to_string(),
+        ));
+        let id = |selector| {
+            query_selector(&document.root, selector).unwrap().node_id
+        };
+        let ids = [id("#outer"), id("#middle"), id("#inner")];
+        (document, ids)
+    }
+
+    type Log = Arc<Mutex<Vec<(String, EventPhase, Option<NodeId>)>>>;
+
+    // Add listeners for both phases to each node that log the phase and
+    // the current target, under a name.
+    fn listen(document: &mut Document, ids: [NodeId; 3], log: &Log) {
+        for (id, name) in ids.into_iter().zip(["outer", "middle", "inner"]) {
+            for capture in [true, false] {
+                let log = Arc::clone(log);
+                document.add_event_listener(id, "click", capture, move |e| {
+                    log.lock().unwrap().push((
+                        name.to_string(),
+                        e.phase(),
+                        e.current_target(),
+                    ));
+                });
+            }
+        }
+    }
+
+    fn names(log: &Log) -> Vec<String> {
+        log.lock()
+            .unwrap()
+            .iter()
+            .map(|(name, phase, _)| format!("{} {:?}", name, phase))
+            .collect()
+    }
+
+    #[test]
+    fn test_propagation_order() {
+        let (mut document, ids) = nested();
+        let log = Log::default();
+        listen(&mut document, ids, &log);
+
+        let mut event = Event::new("click", true);
+        assert!(document.dispatch_event(ids[2], &mut event));
+        assert_eq!(
+            names(&log),
+            [
+                "outer Capturing",
+                "middle Capturing",
+                "inner AtTarget",
+                "inner AtTarget",
+                "middle Bubbling",
+                "outer Bubbling",
+            ]
+        );
+        let current_targets: Vec<Option<NodeId>> =
+            log.lock().unwrap().iter().map(|(_, _, id)| *id).collect();
+        assert_eq!(
+            current_targets,
+            [ids[0], ids[1], ids[2], ids[2], ids[1], ids[0]].map(Some)
+        );
+        // The dispatch state is reset afterwards, except for the target.
+        assert_eq!(event.target(), Some(ids[2]));
+        assert_eq!(event.current_target(), None);
+        assert_eq!(event.phase(), EventPhase::None);
+
+        // Listeners of other event types are not invoked.
+        log.lock().unwrap().clear();
+        document.dispatch_event(ids[2], &mut Event::new("keydown", true));
+        assert!(names(&log).is_empty());
+    }
+
+    #[test]
+    fn test_events_that_dont_bubble() {
+        let (mut document, ids) = nested();
+        let log = Log::default();
+        listen(&mut document, ids, &log);
+
+        document.dispatch_event(ids[2], &mut Event::new("click", false));
+        assert_eq!(
+            names(&log),
+            [
+                "outer Capturing",
+                "middle Capturing",
+                "inner AtTarget",
+                "inner AtTarget",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stop_propagation() {
+        // Stopping the propagation during capturing keeps the event from
+        // reaching the target, but the other listeners of the node run.
+        let (mut document, ids) = nested();
+        let log = Log::default();
+        document.add_event_listener(ids[1], "click", true, |event| {
+            event.stop_propagation()
+        });
+        listen(&mut document, ids, &log);
+        document.dispatch_event(ids[2], &mut Event::new("click", true));
+        assert_eq!(names(&log), ["outer Capturing", "middle Capturing"]);
+
+        // Stopping it at the target keeps it from bubbling.
+        let (mut document, ids) = nested();
+        let log = Log::default();
+        document.add_event_listener(ids[2], "click", false, |event| {
+            event.stop_propagation()
+        });
+        listen(&mut document, ids, &log);
+        document.dispatch_event(ids[2], &mut Event::new("click", true));
+        assert_eq!(
+            names(&log),
+            [
+                "outer Capturing",
+                "middle Capturing",
+                "inner AtTarget",
+                "inner AtTarget",
+            ]
+        );
+
+        // Stopping it while bubbling keeps it from the ancestors above.
+        let (mut document, ids) = nested();
+        let log = Log::default();
+        listen(&mut document, ids, &log);
+        document.add_event_listener(ids[1], "click", false, |event| {
+            event.stop_propagation()
+        });
+        document.dispatch_event(ids[2], &mut Event::new("click", true));
+        assert_eq!(names(&log)[4..], ["middle Bubbling"]);
+    }
+
+    #[test]
+    fn test_prevent_default() {
+        let (mut document, ids) = nested();
+        let mut event = Event::new("click", true);
+        assert!(document.dispatch_event(ids[2], &mut event));
+        assert!(!event.default_prevented());
+
+        // Preventing the default action doesn't stop the propagation.
+        let log = Log::default();
+        document.add_event_listener(ids[1], "click", true, |event| {
+            event.prevent_default()
+        });
+        listen(&mut document, ids, &log);
+        let mut event = Event::new("click", true);
+        assert!(!document.dispatch_event(ids[2], &mut event));
+        assert!(event.default_prevented());
+        assert_eq!(names(&log).len(), 6);
+
+        // Removed listeners are not invoked.
+        let (mut document, ids) = nested();
+        let listener =
+            document.add_event_listener(ids[0], "click", false, |event| {
+                event.prevent_default()
+            });
+        assert!(document.remove_event_listener(ids[0], listener));
+        assert!(!document.remove_event_listener(ids[0], listener));
+        assert!(
+            document.dispatch_event(ids[2], &mut Event::new("click", true))
+        );
+    }
+}
@@ -0,0 +1,295 @@
+// Fonts that text is measured in during layout and drawn with during
+// painting, so that glyphs land where inline layout placed them.
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont, point};
+
+use crate::{
+    computed::{ComputedStyle, FontStyle},
+    painting::TextRun,
+    text::{DecorationMetrics, FontMetrics, MonospaceMetrics, is_white_space},
+};
+
+// A font file registered for a family, weight, and style.
+#[derive(Debug, Clone)]
+struct Face {
+    // The family name in lowercase, such as "serif" or "dejavu sans".
+    family: String,
+    weight: u16,
+    style: FontStyle,
+    font: FontArc,
+    // The placement of decoration lines that the font gives, in ems, if it
+    // gives both the underline and the strikeout.
+    decorations: Option<DecorationMetrics>,
+}
+
+// The fonts that are available to a document.
+// Text whose family has no font falls back to the first font that was
+// added, and is measured as monospace text if there are no fonts at all.
+#[derive(Debug, Clone, Default)]
+pub struct Fonts {
+    faces: Vec<Face>,
+}
+
+impl Fonts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a TrueType or OpenType font for a family. Panics if the data is
+    // not a font.
+    pub fn add_font(
+        &mut self,
+        family: &str,
+        weight: u16,
+        style: FontStyle,
+        data: Vec<u8>,
+    ) {
+        let decorations = decoration_metrics(&data);
+        let font = FontArc::try_from_vec(data)
+            .unwrap_or_else(|error| panic!("Invalid font: {}", error));
+        self.faces.push(Face {
+            family: family.to_ascii_lowercase(),
+            weight,
+            style,
+            font,
+            decorations,
+        });
+    }
+
+    // Find the font of the family that matches the style best, preferring
+    // the same style over the closest weight.
+    // SEE: https://drafts.csswg.org/css-fonts/#font-style-matching
+    fn select(
+        &self,
+        family: &str,
+        weight: u16,
+        style: FontStyle,
+    ) -> Option<&Face> {
+        let in_family = self
+            .faces
+            .iter()
+            .filter(|face| face.family.eq_ignore_ascii_case(family));
+        in_family
+            .min_by_key(|face| {
+                (face.style != style, face.weight.abs_diff(weight))
+            })
+            .or_else(|| self.faces.first())
+    }
+
+    // Get the font of a style, scaled so that its em square is as tall as
+    // the font size.
+    fn scaled(
+        &self,
+        family: &str,
+        weight: u16,
+        style: FontStyle,
+        font_size: f32,
+    ) -> Option<impl ScaleFont<&FontArc>> {
+        let font = &self.select(family, weight, style)?.font;
+        let units_per_em = font.units_per_em().unwrap_or(1000.0);
+        let scale =
+            PxScale::from(font_size * font.height_unscaled() / units_per_em);
+        Some(font.as_scaled(scale))
+    }
+
+    fn scaled_for(
+        &self,
+        style: &ComputedStyle,
+    ) -> Option<impl ScaleFont<&FontArc>> {
+        self.scaled(
+            &style.font_family,
+            style.font_weight,
+            style.font_style,
+            style.font_size,
+        )
+    }
+
+    // Rasterize the glyphs of a run of text on its baseline, with the same
+    // advances that layout measured it with, and pass how much of each
+    // pixel they cover to `coverage`.
+    pub fn draw_glyphs(
+        &self,
+        run: &TextRun,
+        mut coverage: impl FnMut(i32, i32, f32),
+    ) {
+        let Some(font) = self.scaled(
+            &run.font_family,
+            run.font_weight,
+            run.font_style,
+            run.font_size,
+        ) else {
+            return;
+        };
+        let mut x = run.rect.x;
+        for c in run.text.chars() {
+            let advance = advance(&font, c);
+            if is_white_space(c) {
+                x += advance + run.word_spacing;
+                continue;
+            }
+            let glyph = font
+                .glyph_id(c)
+                .with_scale_and_position(font.scale(), point(x, run.baseline));
+            if let Some(outline) = font.font().outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, value| {
+                    coverage(
+                        bounds.min.x as i32 + gx as i32,
+                        bounds.min.y as i32 + gy as i32,
+                        value.min(1.0),
+                    );
+                });
+            }
+            x += advance;
+        }
+    }
+}
+
+// Read the position and thickness of underlines from the `post` table of
+// a font, and of strikeouts from its `OS/2` table, in ems. Overlines are
+// placed at the ascender.
+// SEE: https://learn.microsoft.com/en-us/typography/opentype/spec/post
+// SEE: https://learn.microsoft.com/en-us/typography/opentype/spec/os2#ystrikeoutsize
+fn decoration_metrics(data: &[u8]) -> Option<DecorationMetrics> {
+    let face = ttf_parser::Face::parse(data, 0).ok()?;
+    let units_per_em = f32::from(face.units_per_em());
+    let underline = face.underline_metrics()?;
+    let strikeout = face.strikeout_metrics()?;
+    let em = |units: i16| f32::from(units) / units_per_em;
+    Some(DecorationMetrics {
+        underline_offset: -em(underline.position),
+        underline_thickness: em(underline.thickness),
+        overline_offset: -em(face.ascender()),
+        line_through_offset: -em(strikeout.position),
+        line_through_thickness: em(strikeout.thickness),
+    })
+}
+
+// Get the advance width of a character. A tab advances as far as eight
+// spaces, like in the fallback metrics.
+fn advance<F: Font>(font: &impl ScaleFont<F>, c: char) -> f32 {
+    match c {
+        '\t' => font.h_advance(font.glyph_id(' ')) * 8.0,
+        _ => font.h_advance(font.glyph_id(c)),
+    }
+}
+
+impl FontMetrics for Fonts {
+    fn advance(&self, c: char, style: &ComputedStyle) -> f32 {
+        match self.scaled_for(style) {
+            Some(font) => advance(&font, c),
+            None => MonospaceMetrics.advance(c, style),
+        }
+    }
+
+    fn ascent(&self, style: &ComputedStyle) -> f32 {
+        match self.scaled_for(style) {
+            Some(font) => font.ascent(),
+            None => MonospaceMetrics.ascent(style),
+        }
+    }
+
+    fn descent(&self, style: &ComputedStyle) -> f32 {
+        match self.scaled_for(style) {
+            Some(font) => -font.descent(),
+            None => MonospaceMetrics.descent(style),
+        }
+    }
+
+    // Lines are kept at least a pixel thick, so that they don't vanish
+    // at small font sizes.
+    fn decoration_metrics(&self, style: &ComputedStyle) -> DecorationMetrics {
+        let face = self.select(
+            &style.font_family,
+            style.font_weight,
+            style.font_style,
+        );
+        let Some(em) = face.and_then(|face| face.decorations) else {
+            return MonospaceMetrics.decoration_metrics(style);
+        };
+        let size = style.font_size;
+        DecorationMetrics {
+            underline_offset: em.underline_offset * size,
+            underline_thickness: (em.underline_thickness * size).max(1.0),
+            overline_offset: em.overline_offset * size,
+            line_through_offset: em.line_through_offset * size,
+            line_through_thickness: (em.line_through_thickness * size).max(1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        html_parser::HTMLParser,
+        layout::{Dimensions, NoImages, Rectangle, layout_tree_with_metrics},
+        painting::paint_with_fonts,
+        style::{MediaContext, apply},
+    };
+
+    const FONT: &[u8] = include_bytes!("../testdata/fonts/DejaVuSansMono.ttf");
+
+    #[test]
+    fn test_draw_text() {
+        let mut fonts = Fonts::new();
+        fonts.add_font("monospace", 400, FontStyle::Normal, FONT.to_vec());
+
+        let root = HTMLParser::parse(
+            "<div><p class=\"red\">ab</p><p class=\"hidden\">ab</p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-family: monospace; font-size: 20px; \
+             line-height: 24px; } p { margin: 0; } \
+             .red { color: #ff0000; } .hidden { visibility: hidden; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 40.0;
+        let layout_root =
+            layout_tree_with_metrics(&styled, viewport, &fonts, &NoImages);
+
+        // Text is measured with the advances of the font, which are about 0.6em
+        // in DejaVu Sans Mono.
+        let line = &layout_root.children()[0].lines()[0];
+        let width = line.fragments()[0].rect().width;
+        assert!((width - 24.08).abs() < 0.01, "{}", width);
+
+        // Decoration lines are placed where the font says, and kept at least
+        // a pixel thick.
+        let metrics =
+            fonts.decoration_metrics(&line.fragments()[0].node().style);
+        assert!((metrics.underline_offset - 0.39).abs() < 0.01);
+        assert!((metrics.line_through_offset + 5.18).abs() < 0.01);
+        assert_eq!(metrics.underline_thickness, 1.0);
+
+        let canvas = paint_with_fonts(
+            &layout_root,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 40.0,
+                height: 48.0,
+            },
+            &crate::painting::Images::new(),
+            1.0,
+            &fonts,
+        );
+        let inked = |y0: usize, y1: usize| {
+            canvas.pixels[y0 * 40..y1 * 40]
+                .iter()
+                .filter(|pixel| (pixel.r, pixel.g, pixel.b) != (255, 255, 255))
+                .collect::<Vec<_>>()
+        };
+        // Glyphs are drawn in the color of the text, with antialiased
+        // edges blended over the background, and hidden text isn't drawn.
+        let first_line = inked(0, 24);
+        assert!(first_line.len() > 50);
+        assert!(first_line.iter().all(|pixel| pixel.r == 255));
+        assert!(first_line.iter().any(|pixel| pixel.g == 0));
+        assert!(inked(24, 48).is_empty());
+    }
+}
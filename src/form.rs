@@ -0,0 +1,280 @@
+use crate::dom::{ElementData, Node, NodeType};
+
+// Elements whose values can be submitted with a form.
+const LISTED_TAGS: [&str; 4] = ["button", "input", "select", "textarea"];
+
+// Input types that are never submitted by `serialize_form`, because they
+// are only submitted when they are the activated submitter or need files.
+const UNSUBMITTED_INPUT_TYPES: [&str; 5] =
+    ["button", "file", "image", "reset", "submit"];
+
+impl Node {
+    // Get the form controls in this subtree in tree order.
+    // Nested forms are not descended into.
+    pub fn elements(&self) -> Vec<&Node> {
+        let mut controls = Vec::new();
+        for child in &self.children {
+            collect_controls(child, &mut controls);
+        }
+        controls
+    }
+
+    // Get the current value of a form control.
+    // Return `None` if this node is not a form control or an option.
+    pub fn value(&self) -> Option<String> {
+        let elem = element(self)?;
+        match elem.tag_name.as_str() {
+            "input" => Some(match elem.attributes.get("value") {
+                Some(value) => value.clone(),
+                None if matches!(input_type(elem), "checkbox" | "radio") => {
+                    "on".to_string()
+                }
+                None => String::new(),
+            }),
+            "textarea" => Some(self.text_content()),
+            "select" => Some(
+                self.selected_option()
+                    .and_then(Node::value)
+                    .unwrap_or_default(),
+            ),
+            "option" => Some(
+                elem.attributes
+                    .get("value")
+                    .cloned()
+                    .unwrap_or_else(|| self.text_content().trim().to_string()),
+            ),
+            "button" => {
+                Some(elem.attributes.get("value").cloned().unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+
+    // Get the selected option of a `select` element.
+    // Like browsers, the last option marked `selected` wins, falling back to
+    // the first enabled option.
+    pub fn selected_option(&self) -> Option<&Node> {
+        let options = self.options();
+        options
+            .iter()
+            .rev()
+            .find(|option| has_attribute(option, "selected"))
+            .or_else(|| {
+                options
+                    .iter()
+                    .find(|option| !has_attribute(option, "disabled"))
+            })
+            .copied()
+    }
+
+    // Get the `option` elements of a `select`, including those in groups.
+    fn options(&self) -> Vec<&Node> {
+        let mut options = Vec::new();
+        for child in &self.children {
+            match element(child).map(|elem| elem.tag_name.as_str()) {
+                Some("option") => options.push(child),
+                Some("optgroup") => {
+                    options.extend(child.children.iter().filter(|node| {
+                        element(node)
+                            .is_some_and(|elem| elem.tag_name == "option")
+                    }))
+                }
+                _ => {}
+            }
+        }
+        options
+    }
+}
+
+// Build the name/value pairs submitted by a form, in tree order.
+// Disabled and unnamed controls, unchecked checkboxes and radio buttons,
+// and buttons are skipped.
+pub fn serialize_form(form: &Node) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    for control in form.elements() {
+        let Some(elem) = element(control) else {
+            continue;
+        };
+        let Some(name) = elem.attributes.get("name") else {
+            continue;
+        };
+        if name.is_empty() || elem.attributes.contains_key("disabled") {
+            continue;
+        }
+        match elem.tag_name.as_str() {
+            "button" => {}
+            "select" if elem.attributes.contains_key("multiple") => {
+                for option in control.options() {
+                    if has_attribute(option, "selected") {
+                        pairs.push((name.clone(), option.value().unwrap()));
+                    }
+                }
+            }
+            "input" => {
+                let input_type = input_type(elem);
+                let unchecked = matches!(input_type, "checkbox" | "radio")
+                    && !elem.attributes.contains_key("checked");
+                if !unchecked && !UNSUBMITTED_INPUT_TYPES.contains(&input_type)
+                {
+                    pairs.push((name.clone(), control.value().unwrap()));
+                }
+            }
+            _ => {
+                if let Some(value) = control.value() {
+                    pairs.push((name.clone(), value));
+                }
+            }
+        }
+    }
+    pairs
+}
+
+// Encode name/value pairs as `application/x-www-form-urlencoded`.
+pub fn encode_form_urlencoded(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            format!("{}={}", urlencode(name), urlencode(value))
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Percent-encode a string, with spaces as `+`.
+fn urlencode(s: &str) -> String {
+    let mut result = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'*'
+            | b'-'
+            | b'.'
+            | b'_' => result.push(byte as char),
+            b' ' => result.push('+'),
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+// Collect the form controls in a subtree.
+fn collect_controls<'a>(node: &'a Node, controls: &mut Vec<&'a Node>) {
+    match element(node).map(|elem| elem.tag_name.as_str()) {
+        Some("form") => return,
+        Some(tag_name) if LISTED_TAGS.contains(&tag_name) => {
+            controls.push(node);
+        }
+        _ => {}
+    }
+    for child in &node.children {
+        collect_controls(child, controls);
+    }
+}
+
+// Get the element data of a node, if it is an element.
+fn element(node: &Node) -> Option<&ElementData> {
+    match node.node_type {
+        NodeType::Element(ref elem) => Some(elem),
+        _ => None,
+    }
+}
+
+// Check if a node is an element with the given attribute.
+fn has_attribute(node: &Node, name: &str) -> bool {
+    element(node).is_some_and(|elem| elem.attributes.contains_key(name))
+}
+
+// Get the type of an input element, defaulting to `text`.
+fn input_type(elem: &ElementData) -> &str {
+    elem.attributes.get("type").map_or("text", String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{html_parser::HTMLParser, style::query_selector};
+
+    fn form() -> Node {
+        HTMLParser::parse(
+            "<form>\
+             <input name=\"q\" value=\"rust\"></input>\
+             <input type=\"checkbox\" name=\"c\" checked=\"\"></input>\
+             <input type=\"submit\" name=\"go\" value=\"Go\"></input>\
+             <div><textarea name=\"t\">some text</textarea></div>\
+             <select name=\"s\"><option>  first  </option>\
+             <optgroup><option value=\"2\" selected=\"\">second</option>\
+             <option selected=\"\">third</option></optgroup></select>\
+             <select name=\"f\"><option disabled=\"\">a</option>\
+             <option value=\"b\">B</option></select>\
+             <select name=\"e\"></select>\
+             <button name=\"b\">Press</button>\
+             <form><input name=\"nested\"></input></form>\
+             </form>"
+                .to_string(),
+        )
+    }
+
+    #[test]
+    fn test_elements() {
+        let form = form();
+        // The controls are in tree order, without those of nested forms.
+        let names: Vec<&str> = form
+            .elements()
+            .into_iter()
+            .map(|control| {
+                element(control).unwrap().attributes["name"].as_str()
+            })
+            .collect();
+        assert_eq!(names, ["q", "c", "go", "t", "s", "f", "e", "b"]);
+    }
+
+    #[test]
+    fn test_value() {
+        let form = form();
+        let value =
+            |selector: &str| query_selector(&form, selector).unwrap().value();
+        assert_eq!(value("input").as_deref(), Some("rust"));
+        // Elements that are not controls have no value.
+        assert_eq!(value("div").as_deref(), None);
+        assert_eq!(value("textarea").as_deref(), Some("some text"));
+        assert_eq!(value("button").as_deref(), Some(""));
+        // Checkboxes without a value have the value `on`, and selects
+        // without options an empty one.
+        let elements = form.elements();
+        let values: Vec<String> = elements
+            .iter()
+            .map(|control| control.value().unwrap())
+            .collect();
+        assert_eq!(
+            values,
+            ["rust", "on", "Go", "some text", "third", "b", "", ""]
+        );
+    }
+
+    #[test]
+    fn test_selected_option() {
+        let form = form();
+        let elements = form.elements();
+        let selected =
+            |control: &Node| control.selected_option().and_then(Node::value);
+        // The last selected option wins, including those in groups.
+        assert_eq!(selected(elements[4]).as_deref(), Some("third"));
+        // Without one, the first option that is not disabled is selected.
+        assert_eq!(selected(elements[5]).as_deref(), Some("b"));
+        assert_eq!(selected(elements[6]), None);
+        // The value of an option without a `value` is its trimmed text.
+        let first = elements[4].options()[0];
+        assert_eq!(first.value().as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn test_serialize_form() {
+        let pairs = serialize_form(&form());
+        assert_eq!(
+            encode_form_urlencoded(&pairs),
+            "q=rust&c=on&t=some+text&s=third&f=b&e="
+        );
+    }
+}
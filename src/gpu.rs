@@ -0,0 +1,1381 @@
+// A paint backend that draws display lists on the GPU with wgpu. A display
+// list is recorded on the CPU into one buffer of vertices, two triangles for
+// each quad, which is drawn with a draw call for each run of quads that
+// sample the same texture within the same scissor rectangle. Rectangles and
+// square borders are solid quads, text is drawn from an atlas that each run
+// is rasterized into once, and images are drawn from textures that are kept
+// for as long as they are drawn. Other shapes are rasterized on the CPU like
+// on a canvas, and drawn as textures of their own.
+// SEE: https://www.w3.org/TR/webgpu/
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    computed::{FontStyle, Sides},
+    css_parser::Color,
+    font::Fonts,
+    layout::{EdgeSizes, Rectangle, RoundedRect, Transform},
+    painting::{
+        Bitmap,
+        Canvas,
+        CanvasBackend,
+        DisplayCommand,
+        ImageSource,
+        Images,
+        PaintBackend,
+        TextRun,
+        paint_display_list,
+    },
+    svg::{FillRule, Path},
+};
+
+// The width and height of the atlas that text is rasterized into.
+pub const ATLAS_SIZE: u32 = 2048;
+
+// The size of the white block in the corner of the atlas, whose middle
+// solid quads sample.
+const WHITE_SIZE: u32 = 4;
+
+// Scales positions from device pixels to clip space, and multiplies the
+// color of each vertex by what its texture has where it is.
+const SHADER: &str = "
+@group(0) @binding(0) var<uniform> viewport: vec4<f32>;
+@group(1) @binding(0) var image: texture_2d<f32>;
+@group(1) @binding(1) var image_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        position.x / viewport.x * 2.0 - 1.0,
+        1.0 - position.y / viewport.y * 2.0,
+        0.0,
+        1.0,
+    );
+    out.uv = uv;
+    out.color = color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * textureSample(image, image_sampler, in.uv);
+}
+";
+
+// A corner of a triangle in device pixels, with the point of its texture
+// that it samples, and the color with premultiplied alpha that the sample
+// is multiplied by.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+// An image by the address of its bitmap and its size, which its texture is
+// kept under for as long as the image is drawn in each frame.
+pub type ImageKey = (usize, usize, usize);
+
+// A texture that quads are drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureId {
+    Atlas,
+    Image(ImageKey),
+    // A texture that is only drawn in one frame, by its index in the frame.
+    Scratch(usize),
+}
+
+// Vertices that are drawn with one draw call, from a texture, within a
+// rectangle of x, y, width, and height in device pixels, or else the whole
+// target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub texture: TextureId,
+    pub scissor: Option<[u32; 4]>,
+    pub vertices: Range<u32>,
+}
+
+// What a display list is drawn with, as it is uploaded to the GPU.
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    pub vertices: Vec<Vertex>,
+    pub batches: Vec<Batch>,
+    pub scratch: Vec<Bitmap>,
+    // The images that are drawn, with the bitmaps of those that have no
+    // textures yet.
+    pub images: HashMap<ImageKey, Option<Bitmap>>,
+}
+
+// Runs of text rasterized into a texture, white with the coverage of the
+// glyphs as alpha, on shelves that are each as tall as their tallest run.
+// The atlas is emptied before a frame once a run no longer fits in it.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    // The pixels in RGBA row by row, from the top left corner.
+    pub pixels: Vec<u8>,
+    // The rows that changed since the atlas was uploaded.
+    pub dirty: Option<Range<u32>>,
+    // The top of each shelf, its height, and where its next run goes.
+    shelves: Vec<(u32, u32, u32)>,
+    runs: HashMap<RunKey, AtlasRun>,
+    full: bool,
+}
+
+// What a run of text looks like, with how far its start and baseline are
+// from the pixel before them, in quarters of a pixel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RunKey {
+    text: String,
+    font_family: String,
+    font_weight: u16,
+    font_style: FontStyle,
+    font_size: u32,
+    word_spacing: u32,
+    offset: (u8, u8),
+}
+
+// Where a run is in the atlas, with the position of its top left corner
+// relative to the pixel that it starts at on its baseline. Runs without
+// glyphs are empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AtlasRun {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    left: i32,
+    top: i32,
+}
+
+impl Atlas {
+    pub fn new() -> Self {
+        let mut pixels = vec![0; (ATLAS_SIZE * ATLAS_SIZE * 4) as usize];
+        for y in 0..WHITE_SIZE {
+            let start = (y * ATLAS_SIZE * 4) as usize;
+            pixels[start..start + (WHITE_SIZE * 4) as usize].fill(255);
+        }
+        Self {
+            pixels,
+            dirty: Some(0..ATLAS_SIZE),
+            shelves: Vec::new(),
+            runs: HashMap::new(),
+            full: false,
+        }
+    }
+
+    // Find room for a run of a size, with a pixel between it and the others
+    // so that they don't bleed into each other.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        for (top, shelf_height, x) in &mut self.shelves {
+            if height <= *shelf_height && *x + width <= ATLAS_SIZE {
+                let position = (*x, *top);
+                *x += width + 1;
+                return Some(position);
+            }
+        }
+        let top = self
+            .shelves
+            .last()
+            .map_or(WHITE_SIZE + 1, |(top, height, _)| top + height + 1);
+        if top + height > ATLAS_SIZE || width > ATLAS_SIZE {
+            self.full = true;
+            return None;
+        }
+        self.shelves.push((top, height, width + 1));
+        Some((0, top))
+    }
+
+    // Rasterize a run whose start and baseline are within the first pixel,
+    // unless it doesn't fit.
+    fn insert(
+        &mut self,
+        fonts: &Fonts,
+        key: RunKey,
+        run: &TextRun,
+    ) -> Option<AtlasRun> {
+        let mut glyphs = Vec::new();
+        fonts.draw_glyphs(run, |x, y, coverage| glyphs.push((x, y, coverage)));
+        let (Some(left), Some(top), Some(right), Some(bottom)) = (
+            glyphs.iter().map(|glyph| glyph.0).min(),
+            glyphs.iter().map(|glyph| glyph.1).min(),
+            glyphs.iter().map(|glyph| glyph.0).max(),
+            glyphs.iter().map(|glyph| glyph.1).max(),
+        ) else {
+            let empty = AtlasRun {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+                left: 0,
+                top: 0,
+            };
+            self.runs.insert(key, empty);
+            return Some(empty);
+        };
+        let (width, height) =
+            ((right - left + 1) as u32, (bottom - top + 1) as u32);
+        let (x, y) = self.allocate(width, height)?;
+        for (gx, gy, coverage) in glyphs {
+            let px = x + (gx - left) as u32;
+            let py = y + (gy - top) as u32;
+            let i = ((py * ATLAS_SIZE + px) * 4) as usize;
+            let value = (self.pixels[i] as f32 / 255.0 + coverage).min(1.0);
+            self.pixels[i..i + 4].fill((value * 255.0).round() as u8);
+        }
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(y)..dirty.end.max(y + height),
+            None => y..y + height,
+        });
+        let entry = AtlasRun {
+            x,
+            y,
+            width,
+            height,
+            left,
+            top,
+        };
+        self.runs.insert(key, entry);
+        Some(entry)
+    }
+}
+
+impl Default for Atlas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The state that a push changes and the matching pop restores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct State {
+    transform: Transform,
+    scissor: Option<[u32; 4]>,
+    opacity: f32,
+    // How many of the clips that a scissor can't cut out are in effect.
+    clips: usize,
+}
+
+// The backend that records display lists into frames for a target of a
+// size in device pixels. Clips are scissor rectangles around them, and what
+// is drawn within rounded or rotated ones is rasterized with them. The
+// opacity of a layer is multiplied into what is drawn in it, which differs
+// from blending the layer as a whole where what is in it overlaps. Quads
+// outside of the target are left out. Text is only drawn with fonts.
+#[derive(Debug)]
+pub struct GpuBackend<'a> {
+    pub frame: Frame,
+    pub fonts: Option<&'a Fonts>,
+    atlas: &'a mut Atlas,
+    // The images that already have textures.
+    uploaded: HashSet<ImageKey>,
+    size: (u32, u32),
+    state: State,
+    saved: Vec<State>,
+    // The clips that a scissor can't cut out, with the transforms that
+    // they are in.
+    clips: Vec<(RoundedRect, Transform)>,
+}
+
+impl<'a> GpuBackend<'a> {
+    pub fn new(
+        atlas: &'a mut Atlas,
+        uploaded: HashSet<ImageKey>,
+        size: (u32, u32),
+    ) -> Self {
+        if atlas.full {
+            *atlas = Atlas::new();
+        }
+        Self {
+            frame: Frame::default(),
+            fonts: None,
+            atlas,
+            uploaded,
+            size,
+            state: State {
+                transform: Transform::IDENTITY,
+                scissor: None,
+                opacity: 1.0,
+                clips: 0,
+            },
+            saved: Vec::new(),
+            clips: Vec::new(),
+        }
+    }
+
+    // Get the area that is drawn in, as its left, top, right, and bottom.
+    fn visible(&self) -> [u32; 4] {
+        match self.state.scissor {
+            Some([x, y, width, height]) => [x, y, x + width, y + height],
+            None => [0, 0, self.size.0, self.size.1],
+        }
+    }
+
+    // Get the pixels around a rectangle through the transform in effect
+    // that are visible, as x, y, width, and height, unless there are none.
+    fn device_box(&self, rect: Rectangle) -> Option<[u32; 4]> {
+        let corners =
+            corners(rect).map(|point| self.state.transform.apply(point));
+        let [left, top, right, bottom] = self.visible();
+        let clamp = |value: f32, min: u32, max: u32| {
+            (value.max(min as f32) as u32).min(max)
+        };
+        let x0 = clamp(min(corners.map(|p| p.0)).floor(), left, right);
+        let y0 = clamp(min(corners.map(|p| p.1)).floor(), top, bottom);
+        let x1 = clamp(max(corners.map(|p| p.0)).ceil(), left, right);
+        let y1 = clamp(max(corners.map(|p| p.1)).ceil(), top, bottom);
+        (x0 < x1 && y0 < y1).then_some([x0, y0, x1 - x0, y1 - y0])
+    }
+
+    // Add a quad with corners in device pixels, from the top left one
+    // clockwise, and the points of its texture that they sample, to the
+    // last batch if it draws from the same texture within the same
+    // scissor rectangle. Quads that aren't visible are left out.
+    fn push_quad(
+        &mut self,
+        texture: TextureId,
+        corners: [(f32, f32); 4],
+        uvs: [(f32, f32); 4],
+        color: [f32; 4],
+    ) {
+        let [left, top, right, bottom] = self.visible();
+        if color[3] <= 0.0
+            || max(corners.map(|p| p.0)) <= left as f32
+            || min(corners.map(|p| p.0)) >= right as f32
+            || max(corners.map(|p| p.1)) <= top as f32
+            || min(corners.map(|p| p.1)) >= bottom as f32
+        {
+            return;
+        }
+        let start = self.frame.vertices.len() as u32;
+        for i in [0, 1, 2, 0, 2, 3] {
+            self.frame.vertices.push(Vertex {
+                position: [corners[i].0, corners[i].1],
+                uv: [uvs[i].0, uvs[i].1],
+                color,
+            });
+        }
+        let scissor = self.state.scissor;
+        match self.frame.batches.last_mut() {
+            Some(batch)
+                if batch.texture == texture && batch.scissor == scissor =>
+            {
+                batch.vertices.end = start + 6;
+            }
+            _ => self.frame.batches.push(Batch {
+                texture,
+                scissor,
+                vertices: start..start + 6,
+            }),
+        }
+    }
+
+    // Add a quad in the color of a polygon of four corners through the
+    // transform in effect.
+    fn push_solid(&mut self, color: Color, corners: [(f32, f32); 4]) {
+        let white = (
+            WHITE_SIZE as f32 / 2.0 / ATLAS_SIZE as f32,
+            WHITE_SIZE as f32 / 2.0 / ATLAS_SIZE as f32,
+        );
+        let transform = self.state.transform;
+        self.push_quad(
+            TextureId::Atlas,
+            corners.map(|point| transform.apply(point)),
+            [white; 4],
+            premultiplied(color, self.state.opacity),
+        );
+    }
+
+    // Rasterize what `draw` draws within `bounds` on a transparent canvas,
+    // like the CPU backend does, and draw it as a texture of its own.
+    fn rasterize(
+        &mut self,
+        bounds: Rectangle,
+        draw: impl FnOnce(&mut CanvasBackend),
+    ) {
+        let Some([x, y, width, height]) = self.device_box(bounds) else {
+            return;
+        };
+        let mut canvas = Canvas::new(width as usize, height as usize);
+        let mut backend = CanvasBackend::new(&mut canvas);
+        backend.fonts = self.fonts;
+        backend.push_layer(1.0);
+        // Each transform is pushed relative to the one before it, and the
+        // clips stay in effect when their transforms are replaced.
+        let origin = Transform::translate(-(x as f32), -(y as f32));
+        let mut previous = Transform::IDENTITY;
+        for (clip, transform) in &self.clips {
+            let transform = origin.multiply(*transform);
+            let Some(inverse) = previous.inverse() else {
+                return;
+            };
+            backend.push_transform(inverse.multiply(transform));
+            backend.push_clip(clip);
+            previous = transform;
+        }
+        let Some(inverse) = previous.inverse() else {
+            return;
+        };
+        backend.push_transform(
+            inverse.multiply(origin.multiply(self.state.transform)),
+        );
+        draw(&mut backend);
+        // The layer is left pushed, so the canvas holds its pixels.
+        self.frame.scratch.push(Bitmap {
+            width: width as usize,
+            height: height as usize,
+            pixels: canvas.pixels,
+        });
+        let texture = TextureId::Scratch(self.frame.scratch.len() - 1);
+        let rect = Rectangle {
+            x: x as f32,
+            y: y as f32,
+            width: width as f32,
+            height: height as f32,
+        };
+        self.push_quad(
+            texture,
+            corners(rect),
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            premultiplied(WHITE, self.state.opacity),
+        );
+    }
+
+    // Draw a run in device pixels from the atlas, rasterizing it into the
+    // atlas first unless it is there. Returns whether it fit.
+    fn draw_from_atlas(&mut self, fonts: &Fonts, run: &TextRun) -> bool {
+        let x = (run.rect.x * 4.0).round() / 4.0;
+        let baseline = (run.baseline * 4.0).round() / 4.0;
+        let (left, top) = (x.floor(), baseline.floor());
+        let key = RunKey {
+            text: run.text.clone(),
+            font_family: run.font_family.clone(),
+            font_weight: run.font_weight,
+            font_style: run.font_style,
+            font_size: run.font_size.to_bits(),
+            word_spacing: run.word_spacing.to_bits(),
+            offset: (((x - left) * 4.0) as u8, ((baseline - top) * 4.0) as u8),
+        };
+        let entry = match self.atlas.runs.get(&key) {
+            Some(entry) => *entry,
+            None => {
+                let mut local = run.clone();
+                local.rect.x = x - left;
+                local.baseline = baseline - top;
+                match self.atlas.insert(fonts, key, &local) {
+                    Some(entry) => entry,
+                    None => return false,
+                }
+            }
+        };
+        if entry.width == 0 {
+            return true;
+        }
+        let rect = Rectangle {
+            x: left + entry.left as f32,
+            y: top + entry.top as f32,
+            width: entry.width as f32,
+            height: entry.height as f32,
+        };
+        let uv = Rectangle {
+            x: entry.x as f32 / ATLAS_SIZE as f32,
+            y: entry.y as f32 / ATLAS_SIZE as f32,
+            width: entry.width as f32 / ATLAS_SIZE as f32,
+            height: entry.height as f32 / ATLAS_SIZE as f32,
+        };
+        self.push_quad(
+            TextureId::Atlas,
+            corners(rect),
+            corners(uv),
+            premultiplied(run.color, self.state.opacity),
+        );
+        true
+    }
+}
+
+impl PaintBackend for GpuBackend<'_> {
+    fn fill_rect(&mut self, color: Color, rect: Rectangle) {
+        if self.clips.is_empty() {
+            self.push_solid(color, corners(rect));
+        } else {
+            self.rasterize(rect, |backend| backend.fill_rect(color, rect));
+        }
+    }
+
+    fn fill_rounded_rect(&mut self, color: Color, rect: &RoundedRect) {
+        if !rect.is_rounded() {
+            self.fill_rect(color, rect.rect);
+            return;
+        }
+        self.rasterize(rect.rect, |backend| {
+            backend.fill_rounded_rect(color, rect);
+        });
+    }
+
+    // The sides of square borders are quads between their outer corners
+    // and inner ones, which divide them like `nearest_side` of the CPU
+    // backend does.
+    fn fill_border(
+        &mut self,
+        colors: &Sides<Color>,
+        rect: &RoundedRect,
+        widths: EdgeSizes,
+    ) {
+        if rect.is_rounded()
+            || !self.clips.is_empty()
+            || widths.left + widths.right > rect.rect.width
+            || widths.top + widths.bottom > rect.rect.height
+        {
+            self.rasterize(rect.rect, |backend| {
+                backend.fill_border(colors, rect, widths);
+            });
+            return;
+        }
+        let outer = corners(rect.rect);
+        let inner = corners(rect.shrunk_by(widths).rect);
+        let sides = [colors.top, colors.right, colors.bottom, colors.left];
+        let side_widths =
+            [widths.top, widths.right, widths.bottom, widths.left];
+        for (i, color) in sides.into_iter().enumerate() {
+            if side_widths[i] > 0.0 {
+                let j = (i + 1) % 4;
+                self.push_solid(
+                    color,
+                    [outer[i], outer[j], inner[j], inner[i]],
+                );
+            }
+        }
+    }
+
+    fn draw_shadow(
+        &mut self,
+        color: Color,
+        rect: &RoundedRect,
+        shadow: &RoundedRect,
+        blur: f32,
+    ) {
+        let margin = 3.0 * blur + 1.0;
+        let bounds = Rectangle {
+            x: shadow.rect.x - margin,
+            y: shadow.rect.y - margin,
+            width: shadow.rect.width + 2.0 * margin,
+            height: shadow.rect.height + 2.0 * margin,
+        };
+        self.rasterize(bounds, |backend| {
+            backend.draw_shadow(color, rect, shadow, blur);
+        });
+    }
+
+    // Text that is only moved and evenly scaled is drawn from the atlas,
+    // and other text, or text that doesn't fit in it, is rasterized.
+    fn draw_text(&mut self, run: &TextRun) {
+        if let Some(fonts) = self.fonts {
+            let margin = run.font_size;
+            let bounds = Rectangle {
+                x: run.rect.x - margin,
+                y: run.rect.y - margin,
+                width: run.rect.width + 2.0 * margin,
+                height: run.rect.height + 2.0 * margin,
+            };
+            if self.device_box(bounds).is_none() {
+                return;
+            }
+            let drawn = match run.transformed(self.state.transform) {
+                Some(run) if self.clips.is_empty() => {
+                    self.draw_from_atlas(fonts, &run)
+                }
+                _ => false,
+            };
+            if !drawn {
+                self.rasterize(bounds, |backend| backend.draw_text(run));
+            }
+        }
+    }
+
+    fn fill_path(&mut self, color: Color, path: &Path, fill_rule: FillRule) {
+        self.rasterize(path_bounds(path, 0.0), |backend| {
+            backend.fill_path(color, path, fill_rule);
+        });
+    }
+
+    fn stroke_path(&mut self, color: Color, path: &Path, width: f32) {
+        self.rasterize(path_bounds(path, width), |backend| {
+            backend.stroke_path(color, path, width);
+        });
+    }
+
+    // An image that isn't repeated or clipped by a rounded or rotated clip
+    // is a quad of the part of its tile within `rect`.
+    fn draw_image(
+        &mut self,
+        bitmap: &Bitmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        if repeat != (false, false) || !self.clips.is_empty() {
+            self.rasterize(rect, |backend| {
+                backend.draw_image(bitmap, rect, tile, repeat);
+            });
+            return;
+        }
+        let x0 = rect.x.max(tile.x);
+        let y0 = rect.y.max(tile.y);
+        let x1 = (rect.x + rect.width).min(tile.x + tile.width);
+        let y1 = (rect.y + rect.height).min(tile.y + tile.height);
+        if x0 >= x1 || y0 >= y1 || bitmap.width == 0 || bitmap.height == 0 {
+            return;
+        }
+        let key = (
+            bitmap as *const Bitmap as usize,
+            bitmap.width,
+            bitmap.height,
+        );
+        if !self.frame.images.contains_key(&key) {
+            let upload =
+                (!self.uploaded.contains(&key)).then(|| bitmap.clone());
+            self.frame.images.insert(key, upload);
+        }
+        let area = Rectangle {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        };
+        let uv = Rectangle {
+            x: (x0 - tile.x) / tile.width,
+            y: (y0 - tile.y) / tile.height,
+            width: (x1 - x0) / tile.width,
+            height: (y1 - y0) / tile.height,
+        };
+        let transform = self.state.transform;
+        self.push_quad(
+            TextureId::Image(key),
+            corners(area).map(|point| transform.apply(point)),
+            corners(uv),
+            premultiplied(WHITE, self.state.opacity),
+        );
+    }
+
+    fn draw_gradient(
+        &mut self,
+        gradient: &ImageSource,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        self.rasterize(rect, |backend| {
+            backend.draw_gradient(gradient, rect, tile, repeat);
+        });
+    }
+
+    fn push_clip(&mut self, clip: &RoundedRect) {
+        self.saved.push(self.state);
+        let [x, y, ..] = self.visible();
+        self.state.scissor =
+            Some(self.device_box(clip.rect).unwrap_or([x, y, 0, 0]));
+        let Transform { b, c, .. } = self.state.transform;
+        if clip.is_rounded() || b != 0.0 || c != 0.0 {
+            self.clips.push((*clip, self.state.transform));
+            self.state.clips = self.clips.len();
+        }
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.saved.push(self.state);
+        self.state.transform = self.state.transform.multiply(transform);
+    }
+
+    fn push_layer(&mut self, opacity: f32) {
+        self.saved.push(self.state);
+        self.state.opacity *= opacity;
+    }
+
+    fn pop(&mut self) {
+        self.state = self.saved.pop().expect("Unbalanced pops");
+        self.clips.truncate(self.state.clips);
+    }
+}
+
+const WHITE: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+};
+
+// Get the color of a vertex, with alpha premultiplied.
+fn premultiplied(color: Color, opacity: f32) -> [f32; 4] {
+    let alpha = color.a as f32 / 255.0 * opacity;
+    [
+        color.r as f32 / 255.0 * alpha,
+        color.g as f32 / 255.0 * alpha,
+        color.b as f32 / 255.0 * alpha,
+        alpha,
+    ]
+}
+
+// Get the corners of a rectangle from the top left one clockwise.
+fn corners(rect: Rectangle) -> [(f32, f32); 4] {
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+    [
+        (rect.x, rect.y),
+        (right, rect.y),
+        (right, bottom),
+        (rect.x, bottom),
+    ]
+}
+
+fn min(values: [f32; 4]) -> f32 {
+    values.into_iter().fold(f32::INFINITY, f32::min)
+}
+
+fn max(values: [f32; 4]) -> f32 {
+    values.into_iter().fold(f32::NEG_INFINITY, f32::max)
+}
+
+// Get the rectangle around the points of a path, grown by half of the width
+// that it is stroked with.
+fn path_bounds(path: &Path, width: f32) -> Rectangle {
+    let points = path.subpaths.iter().flat_map(|subpath| &subpath.points);
+    let (mut x0, mut y0) = (f32::INFINITY, f32::INFINITY);
+    let (mut x1, mut y1) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for &(x, y) in points {
+        (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+    }
+    if x0 > x1 {
+        return Rectangle::default();
+    }
+    let grow = width / 2.0 + 1.0;
+    Rectangle {
+        x: x0 - grow,
+        y: y0 - grow,
+        width: x1 - x0 + 2.0 * grow,
+        height: y1 - y0 + 2.0 * grow,
+    }
+}
+
+// Get the pixels of a bitmap in RGBA with premultiplied alpha.
+fn premultiplied_rgba(bitmap: &Bitmap) -> Vec<u8> {
+    bitmap
+        .pixels
+        .iter()
+        .flat_map(|color| {
+            let scale =
+                |value: u8| (value as u16 * color.a as u16).div_ceil(255) as u8;
+            [scale(color.r), scale(color.g), scale(color.b), color.a]
+        })
+        .collect()
+}
+
+// Draws display lists with a device into textures of a format, keeping the
+// atlas and the textures of images between frames.
+pub struct GpuRenderer {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub fonts: Option<Fonts>,
+    pub atlas: Atlas,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    // The size of the target in device pixels, and what binds it.
+    viewport: wgpu::Buffer,
+    viewport_group: wgpu::BindGroup,
+    atlas_texture: (wgpu::Texture, wgpu::BindGroup),
+    images: HashMap<ImageKey, (wgpu::Texture, wgpu::BindGroup)>,
+}
+
+impl GpuRenderer {
+    pub fn new(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("shader"),
+                source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+            });
+        let viewport_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("viewport"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let texture_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("texture"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                ],
+            });
+        let pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline"),
+                bind_group_layouts: &[&viewport_layout, &texture_layout],
+                push_constant_ranges: &[],
+            });
+        let pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: size_of::<Vertex>() as u64,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x2,
+                            1 => Float32x2,
+                            2 => Float32x4,
+                        ],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(
+                            wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+                        ),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            });
+        // Quads are drawn at whole pixels unless they are scaled, which
+        // samples the nearest pixels like the CPU backend does.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("sampler"),
+            ..Default::default()
+        });
+        let viewport = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("viewport"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let viewport_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("viewport"),
+                layout: &viewport_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: viewport.as_entire_binding(),
+                }],
+            });
+        let atlas_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("atlas"),
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let atlas_group =
+            bind_texture(&device, &texture_layout, &sampler, &atlas_texture);
+        Self {
+            device,
+            queue,
+            fonts: None,
+            atlas: Atlas::new(),
+            format,
+            pipeline,
+            texture_layout,
+            sampler,
+            viewport,
+            viewport_group,
+            atlas_texture: (atlas_texture, atlas_group),
+            images: HashMap::new(),
+        }
+    }
+
+    // Create a renderer for textures in RGBA without a window, unless there
+    // is no GPU to render with.
+    pub fn headless() -> Option<Self> {
+        pollster::block_on(async {
+            let instance =
+                wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions::default())
+                .await
+                .ok()?;
+            let (device, queue) = adapter
+                .request_device(&wgpu::DeviceDescriptor::default())
+                .await
+                .ok()?;
+            Some(Self::new(device, queue, wgpu::TextureFormat::Rgba8Unorm))
+        })
+    }
+
+    // Record a display list through a transform, such as from the page to
+    // device pixels, into a frame for a target of a size.
+    pub fn record(
+        &mut self,
+        list: &[DisplayCommand],
+        images: &Images,
+        transform: Transform,
+        size: (u32, u32),
+    ) -> Frame {
+        let uploaded = self.images.keys().copied().collect();
+        let mut backend = GpuBackend::new(&mut self.atlas, uploaded, size);
+        backend.fonts = self.fonts.as_ref();
+        backend.push_transform(transform);
+        paint_display_list(list, images, &mut backend);
+        backend.pop();
+        backend.frame
+    }
+
+    // Draw a display list through a transform on a target of a size, which
+    // is cleared to opaque white first, like a new canvas.
+    pub fn render(
+        &mut self,
+        list: &[DisplayCommand],
+        images: &Images,
+        transform: Transform,
+        target: &wgpu::TextureView,
+        (width, height): (u32, u32),
+    ) {
+        let frame = self.record(list, images, transform, (width, height));
+        let scratch = self.upload(&frame);
+
+        self.queue.write_buffer(
+            &self.viewport,
+            0,
+            bytemuck::cast_slice(&[width as f32, height as f32, 0.0, 0.0]),
+        );
+        let vertices = (!frame.vertices.is_empty()).then(|| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("vertices"),
+                    contents: bytemuck::cast_slice(&frame.vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass =
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("display list"),
+                    color_attachments: &[Some(
+                        wgpu::RenderPassColorAttachment {
+                            view: target,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        },
+                    )],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.viewport_group, &[]);
+            if let Some(vertices) = &vertices {
+                pass.set_vertex_buffer(0, vertices.slice(..));
+            }
+            for batch in &frame.batches {
+                let group = match batch.texture {
+                    TextureId::Atlas => &self.atlas_texture.1,
+                    TextureId::Image(key) => &self.images[&key].1,
+                    TextureId::Scratch(i) => &scratch[i].1,
+                };
+                pass.set_bind_group(1, group, &[]);
+                let [x, y, w, h] =
+                    batch.scissor.unwrap_or([0, 0, width, height]);
+                pass.set_scissor_rect(x, y, w, h);
+                pass.draw(batch.vertices.clone(), 0..1);
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+    }
+
+    // Upload the rows of the atlas that changed, and the textures of a
+    // frame. Textures of images that it doesn't draw are dropped.
+    fn upload(
+        &mut self,
+        frame: &Frame,
+    ) -> Vec<(wgpu::Texture, wgpu::BindGroup)> {
+        if let Some(rows) = self.atlas.dirty.take() {
+            let row = (ATLAS_SIZE * 4) as usize;
+            self.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.atlas_texture.0,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: rows.start,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &self.atlas.pixels
+                    [rows.start as usize * row..rows.end as usize * row],
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(ATLAS_SIZE * 4),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: ATLAS_SIZE,
+                    height: rows.end - rows.start,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        self.images.retain(|key, _| frame.images.contains_key(key));
+        for (key, bitmap) in &frame.images {
+            if let Some(bitmap) = bitmap {
+                let texture = self.texture(bitmap);
+                self.images.insert(*key, texture);
+            }
+        }
+        frame
+            .scratch
+            .iter()
+            .map(|bitmap| self.texture(bitmap))
+            .collect()
+    }
+
+    // Create a texture with the pixels of a bitmap, bound to be sampled.
+    fn texture(&self, bitmap: &Bitmap) -> (wgpu::Texture, wgpu::BindGroup) {
+        let texture = self.device.create_texture_with_data(
+            &self.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("image"),
+                size: wgpu::Extent3d {
+                    width: bitmap.width as u32,
+                    height: bitmap.height as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &premultiplied_rgba(bitmap),
+        );
+        let group = bind_texture(
+            &self.device,
+            &self.texture_layout,
+            &self.sampler,
+            &texture,
+        );
+        (texture, group)
+    }
+
+    // Draw a display list through a transform on a texture of a size, and
+    // read its pixels back into a canvas. Panics unless the renderer draws
+    // in RGBA, like the headless one.
+    pub fn render_to_canvas(
+        &mut self,
+        list: &[DisplayCommand],
+        images: &Images,
+        transform: Transform,
+        (width, height): (u32, u32),
+    ) -> Canvas {
+        assert_eq!(self.format, wgpu::TextureFormat::Rgba8Unorm);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render(list, images, transform, &view, (width, height));
+
+        // Rows are copied into the buffer at multiples of 256 bytes.
+        let row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (row * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit([encoder.finish()]);
+        buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .expect("Failed to read the texture");
+
+        let data = buffer.slice(..).get_mapped_range();
+        let mut canvas = Canvas::new(width as usize, height as usize);
+        for (y, line) in canvas.pixels.chunks_mut(width as usize).enumerate() {
+            let start = y * row as usize;
+            for (pixel, rgba) in line
+                .iter_mut()
+                .zip(data[start..start + width as usize * 4].chunks(4))
+            {
+                *pixel = Color {
+                    r: rgba[0],
+                    g: rgba[1],
+                    b: rgba[2],
+                    a: rgba[3],
+                };
+            }
+        }
+        canvas
+    }
+}
+
+fn bind_texture(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    texture: &wgpu::Texture,
+) -> wgpu::BindGroup {
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("texture"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        html_parser::HTMLParser,
+        layout::{Dimensions, NoImages, layout_tree, layout_tree_with_metrics},
+        painting::{build_display_list, paint_with_backend, paint_with_fonts},
+        style::{MediaContext, apply},
+    };
+
+    const FONT: &[u8] = include_bytes!("../testdata/fonts/DejaVuSansMono.ttf");
+
+    const BOUNDS: Rectangle = Rectangle {
+        x: 0.0,
+        y: 0.0,
+        width: 64.0,
+        height: 64.0,
+    };
+
+    #[test]
+    fn test_batches() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"><span></span></p>\
+             <p class=\"c\"></p><p class=\"d\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { width: 40px; } p { margin: 0; height: 8px; } \
+             .a { background-color: #ff0000; border: 2px solid #0000ff; } \
+             .b { background-color: #00ff00; overflow: hidden; } \
+             span { display: block; height: 20px; \
+             background-color: #000000; } \
+             .c { background-color: #ffff00; border-radius: 4px; } \
+             .d { background-color: #00ffff; margin-top: 100px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 64.0;
+        let layout_root = layout_tree(&styled, viewport);
+        let mut atlas = Atlas::new();
+        let mut backend = GpuBackend::new(&mut atlas, HashSet::new(), (64, 64));
+        paint_with_backend(
+            &layout_root,
+            BOUNDS,
+            &Images::new(),
+            1.0,
+            &mut backend,
+        );
+        let frame = backend.frame;
+
+        // Solid rectangles and square borders share a draw call until a
+        // clip or a shape that is rasterized on the CPU, and what is below
+        // the target is left out.
+        let batches: Vec<_> = frame
+            .batches
+            .iter()
+            .map(|batch| (batch.texture, batch.scissor, batch.vertices.len()))
+            .collect();
+        assert_eq!(
+            batches,
+            [
+                (TextureId::Atlas, None, 6 * 6),
+                (TextureId::Atlas, Some([0, 12, 40, 8]), 6),
+                (TextureId::Scratch(0), None, 6),
+            ]
+        );
+        assert_eq!(frame.scratch.len(), 1);
+        assert_eq!((frame.scratch[0].width, frame.scratch[0].height), (40, 8));
+        // The square border is four sides, each in the color of its side.
+        let blue = premultiplied(
+            Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255,
+            },
+            1.0,
+        );
+        assert_eq!(
+            frame.vertices[6..30]
+                .iter()
+                .filter(|vertex| vertex.color == blue)
+                .count(),
+            24
+        );
+    }
+
+    #[test]
+    fn test_gpu_renderer() {
+        // Without a GPU, such as on machines that run tests, there is
+        // nothing to render with.
+        let Some(mut renderer) = GpuRenderer::headless() else {
+            return;
+        };
+        let mut fonts = Fonts::new();
+        fonts.add_font("monospace", 400, FontStyle::Normal, FONT.to_vec());
+        renderer.fonts = Some(fonts.clone());
+        let root = HTMLParser::parse(
+            "<div class=\"a\"><p class=\"b\"></p><p class=\"c\"></p>\
+             <p>ab</p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 8px; width: 40px; height: 40px; \
+             background-color: #ffee88; border: 4px solid #3366cc; \
+             border-left-color: #cc3333; } \
+             p { margin: 0; height: 10px; } \
+             .b { background-image: linear-gradient(#ff0000, #0000ff); \
+             border-radius: 4px; } \
+             .c { background-color: #00ff00; opacity: 0.5; } \
+             div { font-family: monospace; font-size: 10px; \
+             color: #000080; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 64.0;
+        let layout_root =
+            layout_tree_with_metrics(&styled, viewport, &fonts, &NoImages);
+        let list = build_display_list(&layout_root, &Images::new());
+        let canvas = renderer.render_to_canvas(
+            &list,
+            &Images::new(),
+            Transform::IDENTITY,
+            (64, 64),
+        );
+        let expected =
+            paint_with_fonts(&layout_root, BOUNDS, &Images::new(), 1.0, &fonts);
+        // Only the edges of glyphs differ, whose coverage is rounded to
+        // bytes in the atlas and is at quarters of pixels.
+        let difference = canvas.difference(&expected);
+        assert!(difference.max_difference <= 16, "{:?}", difference);
+        // The text is drawn again from the atlas, which doesn't change.
+        assert!(renderer.atlas.dirty.is_none());
+        let again = renderer.render_to_canvas(
+            &list,
+            &Images::new(),
+            Transform::IDENTITY,
+            (64, 64),
+        );
+        assert!(renderer.atlas.dirty.is_none());
+        assert_eq!(again, canvas);
+    }
+}
@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 
-use crate::dom::{AttributeMap, Node};
+use crate::{
+    document::{Document, QuirksMode},
+    dom::{AttributeMap, Node},
+};
 
 // TODO: The following features are not implemented:
-// - Comments
-// - Doctype declarations
 // - Escaped characters (like &amp;) and CDATA sections
-// - Self-closing tags: <br/> or <br> with no closing tag
+// - Void elements with no closing tag: <br>
 // - Error handling (e.g. unbalanced or improperly nested tags)
 // - Namespaces and other XHTML syntax: <html:body>
 // - Character encoding detection
@@ -19,12 +20,31 @@ pub struct HTMLParser {
 
 impl HTMLParser {
     // Parse an HTML document, returning the root element.
+    // The doctype is skipped.
     pub fn parse(source: String) -> Node {
-        let mut nodes = Self {
+        let mut parser = Self {
             pos: 0,
             input: source,
-        }
-        .parse_nodes();
+        };
+        parser.parse_doctype();
+        parser.parse_root()
+    }
+
+    // Parse an HTML document, setting its quirks mode from the doctype.
+    pub fn parse_document(source: String) -> Document {
+        let mut parser = Self {
+            pos: 0,
+            input: source,
+        };
+        let quirks_mode = parser.parse_doctype();
+        let mut document = Document::new(parser.parse_root());
+        document.quirks_mode = quirks_mode;
+        document
+    }
+
+    // Parse the nodes of a document into a single root element.
+    fn parse_root(&mut self) -> Node {
+        let mut nodes = self.parse_nodes();
 
         // If the document contains a root element, return it.
         // Otherwise, create one.
@@ -34,6 +54,22 @@ impl HTMLParser {
         Node::new_by_element("html".to_string(), HashMap::new(), nodes)
     }
 
+    // Parse an optional doctype, returning the quirks mode it selects.
+    // Documents without a doctype are in quirks mode.
+    fn parse_doctype(&mut self) -> QuirksMode {
+        self.consume_whitespace();
+        let is_doctype = self.input[self.pos..]
+            .get(..9)
+            .is_some_and(|s| s.eq_ignore_ascii_case("<!doctype"));
+        if !is_doctype {
+            return QuirksMode::Quirks;
+        }
+        self.pos += 9;
+        let doctype = self.consume_chars_while(|c| c != '>');
+        self.expect(">");
+        quirks_mode(&doctype)
+    }
+
     // Parse nodes.
     fn parse_nodes(&mut self) -> Vec<Node> {
         let mut nodes = Vec::new();
@@ -49,7 +85,9 @@ impl HTMLParser {
 
     // Parse a single node.
     fn parse_node(&mut self) -> Node {
-        if self.starts_with("<") {
+        if self.starts_with("<!--") {
+            self.parse_comment()
+        } else if self.starts_with("<") {
             self.parse_element()
         } else {
             self.parse_text()
@@ -62,6 +100,12 @@ impl HTMLParser {
         self.expect("<");
         let tag_name = self.parse_name();
         let attributes = self.parse_attributes();
+        // A self-closing tag, such as the shapes of SVG images have, has
+        // no children and no closing tag.
+        if self.starts_with("/>") {
+            self.expect("/>");
+            return Node::new_by_element(tag_name, attributes, Vec::new());
+        }
         self.expect(">");
 
         // Children.
@@ -75,6 +119,18 @@ impl HTMLParser {
         Node::new_by_element(tag_name, attributes, children)
     }
 
+    // Parse a comment.
+    fn parse_comment(&mut self) -> Node {
+        self.expect("<!--");
+        let len = self.input[self.pos..].find("-->").unwrap_or_else(|| {
+            panic!("Unterminated comment at byte {}", self.pos)
+        });
+        let comment = self.input[self.pos..self.pos + len].to_string();
+        self.pos += len;
+        self.expect("-->");
+        Node::new_by_comment(comment)
+    }
+
     // Parse a text.
     fn parse_text(&mut self) -> Node {
         Node::new_by_text(self.consume_chars_while(|c| c != '<'))
@@ -85,7 +141,7 @@ impl HTMLParser {
         let mut attributes = AttributeMap::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
+            if self.next_char() == '>' || self.starts_with("/>") {
                 break;
             }
             let (name, value) = self.parse_attribute();
@@ -112,10 +168,10 @@ impl HTMLParser {
         value
     }
 
-    // Parse a tag or attribute name.
+    // Parse a tag or attribute name, such as `stroke-width`.
     fn parse_name(&mut self) -> String {
         self.consume_chars_while(
-            |c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'),
+            |c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-'),
         )
     }
 
@@ -175,3 +231,83 @@ impl HTMLParser {
         self.pos >= self.input.len()
     }
 }
+
+// The public identifiers of legacy doctypes that select quirks mode.
+// SEE: https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+const QUIRKY_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0",
+    "-//ietf//dtd html 3",
+    "-//ietf//dtd html level",
+    "-//ietf//dtd html strict",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer",
+    "-//netscape comm. corp.//dtd",
+    "-//o'reilly and associates//dtd html",
+    "-//softquad",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//sun microsystems corp.//dtd hotjava",
+    "-//w3c//dtd html 3",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html",
+];
+
+// Decide the quirks mode from the contents of a doctype, such as
+// ` html PUBLIC "-//W3C//DTD HTML 4.01//EN"`.
+fn quirks_mode(doctype: &str) -> QuirksMode {
+    let mut parts = doctype.split('"').map(str::trim);
+    let keywords = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let mut keywords = keywords.split_ascii_whitespace();
+    let name = keywords.next().unwrap_or_default();
+    let external = keywords.next().unwrap_or_default();
+    // Quoted identifiers are at the odd positions of the split.
+    let first_id = parts.next().map(str::to_ascii_lowercase);
+    let second_id = parts.nth(1).map(str::to_ascii_lowercase);
+    let (public_id, system_id) = match external {
+        "public" => (first_id, second_id),
+        "system" => (None, first_id),
+        _ => (None, None),
+    };
+
+    if name != "html"
+        || system_id.as_deref()
+            == Some(
+                "http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd",
+            )
+    {
+        return QuirksMode::Quirks;
+    }
+    let Some(public_id) = public_id else {
+        return QuirksMode::NoQuirks;
+    };
+    let html4_transitional = public_id
+        .starts_with("-//w3c//dtd html 4.01 frameset//")
+        || public_id.starts_with("-//w3c//dtd html 4.01 transitional//");
+    if matches!(
+        public_id.as_str(),
+        "-//w3o//dtd w3 html strict 3.0//en//"
+            | "-/w3c/dtd html 4.0 transitional/en"
+            | "html"
+    ) || QUIRKY_PUBLIC_ID_PREFIXES
+        .iter()
+        .any(|prefix| public_id.starts_with(prefix))
+        || (html4_transitional && system_id.is_none())
+    {
+        QuirksMode::Quirks
+    } else if html4_transitional
+        || public_id.starts_with("-//w3c//dtd xhtml 1.0 frameset//")
+        || public_id.starts_with("-//w3c//dtd xhtml 1.0 transitional//")
+    {
+        QuirksMode::LimitedQuirks
+    } else {
+        QuirksMode::NoQuirks
+    }
+}
@@ -0,0 +1,519 @@
+// The HTTP cache keeps responses on disk, keyed by the URLs that they were
+// requested with, so that loading a page again doesn't download all of its
+// resources again. A stored response is used as it is while it is fresh,
+// and revalidated with the server once it is stale, which answers with
+// `304 Not Modified` if it didn't change:
+//
+// - `Cache-Control: max-age`, `Expires`, or a tenth of the time since
+//   `Last-Modified` tell how long a response is fresh, in that order.
+// - `Cache-Control: no-cache` responses are revalidated every time, and
+//   `Cache-Control: no-store` and `Vary: *` responses are not stored.
+// - `ETag` and `Last-Modified` are sent back as `If-None-Match` and
+//   `If-Modified-Since` to revalidate a response.
+//
+// An offline cache never sends requests, and loads every response that it
+// stored, fresh or not, which makes a snapshot of the pages that were
+// loaded with it.
+// SEE: https://httpwg.org/specs/rfc9111.html
+
+use std::{
+    fs,
+    io,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    loader::{Resource, ResourceLoader},
+    net::{Client, Response},
+    url::Url,
+};
+
+// The statuses of responses that can be stored without explicit freshness.
+// SEE: https://httpwg.org/specs/rfc9110.html#overview.of.status.codes
+const HEURISTICALLY_CACHEABLE: [u16; 8] =
+    [200, 203, 204, 300, 301, 404, 405, 410];
+
+// A cache of responses in a directory, with a file for each URL.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    pub dir: PathBuf,
+    // Whether responses are only loaded from the cache.
+    pub offline: bool,
+    // The client that sends the requests, which the clones of the cache
+    // share.
+    pub client: Arc<Client>,
+}
+
+// A response as it was stored.
+struct Entry {
+    // The URL that the response was requested with.
+    url: String,
+    // When the response was received or last revalidated.
+    stored: SystemTime,
+    response: Response,
+}
+
+impl HttpCache {
+    // Create a cache in a directory, which is created when the first
+    // response is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            offline: false,
+            client: Arc::default(),
+        }
+    }
+
+    // Get the response for a URL, from the cache if it is fresh there, and
+    // from the network otherwise. See `Client::fetch`.
+    pub fn fetch(&self, url: &str) -> io::Result<Response> {
+        let entry = self.read(url);
+        if self.offline {
+            return entry.map(|entry| entry.response).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not in the cache", url),
+                )
+            });
+        }
+        let now = SystemTime::now();
+        let Some(mut entry) = entry else {
+            let response = self.client.fetch(url)?;
+            self.store(url, now, &response)?;
+            return Ok(response);
+        };
+        if is_fresh(&entry, now) {
+            return Ok(entry.response);
+        }
+
+        let etag = entry.response.header("etag");
+        let last_modified = entry.response.header("last-modified");
+        let validators: Vec<(&str, &str)> = etag
+            .map(|etag| ("If-None-Match", etag))
+            .into_iter()
+            .chain(last_modified.map(|date| ("If-Modified-Since", date)))
+            .collect();
+        let response = self.client.fetch_with_headers(url, &validators)?;
+        if response.status != 304 {
+            self.store(url, now, &response)?;
+            return Ok(response);
+        }
+        // The headers of a `304 Not Modified` response update the stored
+        // ones.
+        // SEE: https://httpwg.org/specs/rfc9111.html#freshening.responses
+        for (name, value) in response.headers {
+            if name == "content-length" {
+                continue;
+            }
+            entry.response.headers.retain(|(header, _)| *header != name);
+            entry.response.headers.push((name, value));
+        }
+        self.store(url, now, &entry.response)?;
+        Ok(entry.response)
+    }
+
+    // Remove every stored response.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    // The file of a URL, named by a hash of it.
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}", fnv1a(url.as_bytes())))
+    }
+
+    // Read the stored response for a URL, if there is one.
+    fn read(&self, url: &str) -> Option<Entry> {
+        let bytes = fs::read(self.path(url)).ok()?;
+        let entry = Entry::parse(&bytes)?;
+        // Two URLs can have the same hash.
+        (entry.url == url).then_some(entry)
+    }
+
+    // Store a response for a URL, unless it can't be stored.
+    fn store(
+        &self,
+        url: &str,
+        stored: SystemTime,
+        response: &Response,
+    ) -> io::Result<()> {
+        if !is_storable(response) {
+            return Ok(());
+        }
+        let entry = Entry {
+            url: url.to_string(),
+            stored,
+            response: response.clone(),
+        };
+        // The entry is written to a file of its own, and then renamed, so
+        // that other threads and processes that use the directory never
+        // read an entry that is partly written.
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(url);
+        let temp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, entry.serialize())?;
+        fs::rename(&temp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+}
+
+impl ResourceLoader for HttpCache {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        let response = self.fetch(&url.to_string())?;
+        Ok(Resource::from_response(url, response))
+    }
+}
+
+impl Entry {
+    // Serialize an entry as lines of the requested URL, the URL of the
+    // response, the time that it was stored, its status, and its headers,
+    // followed by an empty line and the body.
+    fn serialize(&self) -> Vec<u8> {
+        let stored = self
+            .stored
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut head = format!(
+            "{}\n{}\n{}\n{}\n",
+            self.url, self.response.url, stored, self.response.status
+        );
+        for (name, value) in &self.response.headers {
+            head.push_str(&format!("{}: {}\n", name, value));
+        }
+        head.push('\n');
+        [head.as_bytes(), &self.response.body].concat()
+    }
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let end = bytes.windows(2).position(|window| window == b"\n\n")?;
+        let head = std::str::from_utf8(&bytes[..end]).ok()?;
+        let mut lines = head.lines();
+        let url = lines.next()?.to_string();
+        let response_url = lines.next()?.to_string();
+        let stored = lines.next()?.parse().ok()?;
+        let status = lines.next()?.parse().ok()?;
+        let headers = lines
+            .map(|line| {
+                let (name, value) = line.split_once(": ")?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            url,
+            stored: UNIX_EPOCH.checked_add(Duration::from_secs(stored))?,
+            response: Response {
+                url: response_url,
+                status,
+                headers,
+                body: bytes[end + 2..].to_vec(),
+            },
+        })
+    }
+}
+
+// Get the directives of the `Cache-Control` headers of a response, with
+// lowercase names and their values, if any.
+fn cache_control(response: &Response) -> Vec<(String, Option<String>)> {
+    response
+        .headers
+        .iter()
+        .filter(|(name, _)| name == "cache-control")
+        .flat_map(|(_, value)| value.split(','))
+        .filter_map(|directive| {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => {
+                    (name, Some(value.trim().trim_matches('"')))
+                }
+                None => (directive, None),
+            };
+            let name = name.trim().to_ascii_lowercase();
+            (!name.is_empty()).then(|| (name, value.map(str::to_string)))
+        })
+        .collect()
+}
+
+// Check if a response can be stored.
+// SEE: https://httpwg.org/specs/rfc9111.html#response.cacheability
+fn is_storable(response: &Response) -> bool {
+    let directives = cache_control(response);
+    let has = |name: &str| directives.iter().any(|(n, _)| n == name);
+    let explicit = has("max-age") || response.header("expires").is_some();
+    !has("no-store")
+        && response.header("vary").map(str::trim) != Some("*")
+        && (HEURISTICALLY_CACHEABLE.contains(&response.status)
+            || (explicit && response.status != 206 && response.status != 304))
+}
+
+// Check if a stored response can be used without revalidating it.
+// SEE: https://httpwg.org/specs/rfc9111.html#expiration.model
+fn is_fresh(entry: &Entry, now: SystemTime) -> bool {
+    let directives = cache_control(&entry.response);
+    if directives.iter().any(|(name, _)| name == "no-cache") {
+        return false;
+    }
+    let header_date = |name: &str| {
+        entry
+            .response
+            .header(name)
+            .and_then(|date| httpdate::parse_http_date(date.trim()).ok())
+    };
+    let date = header_date("date").unwrap_or(entry.stored);
+    let max_age = directives
+        .iter()
+        .find(|(name, _)| name == "max-age")
+        .and_then(|(_, value)| delta_seconds(value.as_deref()?))
+        .map(Duration::from_secs);
+    let lifetime = max_age
+        .or_else(|| {
+            // An invalid date means that the response has expired.
+            let expires = entry.response.header("expires")?;
+            Some(
+                httpdate::parse_http_date(expires.trim())
+                    .ok()
+                    .and_then(|expires| expires.duration_since(date).ok())
+                    .unwrap_or_default(),
+            )
+        })
+        .or_else(|| {
+            let last_modified = header_date("last-modified")?;
+            Some(date.duration_since(last_modified).ok()? / 10)
+        })
+        .unwrap_or_default();
+
+    // The age is the age that the response had when it was received, plus
+    // the time that it spent in the cache since. An age too large to be
+    // represented means that the response is stale.
+    // SEE: https://httpwg.org/specs/rfc9111.html#age.calculations
+    let age = entry
+        .response
+        .header("age")
+        .and_then(delta_seconds)
+        .map(Duration::from_secs)
+        .unwrap_or_default()
+        .checked_add(now.duration_since(entry.stored).unwrap_or_default());
+    age.is_some_and(|age| lifetime > age)
+}
+
+// Parse a number of seconds, where a number too large to be represented
+// is the largest one that can be.
+// SEE: https://httpwg.org/specs/rfc9111.html#delta-seconds
+fn delta_seconds(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some(value.parse().unwrap_or(u64::MAX))
+}
+
+// Hash bytes with 64-bit FNV-1a, which is the same across runs and
+// versions of Rust, unlike the hasher of the standard library.
+// SEE: http://www.isthe.com/chongo/tech/comp/fnv/
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::serve;
+
+    fn response(headers: &str, body: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\n\r\n{}",
+            headers,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_http_cache() {
+        let (url, requests) = serve(vec![
+            response("Cache-Control: max-age=3600\r\n", "fresh"),
+            response("ETag: \"v1\"\r\nCache-Control: no-cache\r\n", "etag"),
+            b"HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nX-Test: a\r\n\
+              Content-Length: 0\r\n\r\n"
+                .to_vec(),
+            response(
+                "Last-Modified: Mon, 01 Jan 2024 00:00:00 GMT\r\n\
+                 Cache-Control: max-age=0\r\n",
+                "old",
+            ),
+            response("Cache-Control: max-age=0\r\n", "new"),
+            response("Cache-Control: no-store\r\n", "private"),
+        ]);
+        let dir = std::env::temp_dir().join("simple-browser-http-cache");
+        let cache = HttpCache::new(&dir);
+        cache.clear().unwrap();
+        let fetch = |cache: &HttpCache, path: &str| {
+            cache.fetch(&format!("{}{}", url, path))
+        };
+
+        // A fresh response is loaded from the cache.
+        for _ in 0..2 {
+            assert_eq!(fetch(&cache, "/fresh").unwrap().body, b"fresh");
+        }
+        // A stale response is revalidated, and updated by the answer.
+        assert_eq!(fetch(&cache, "/etag").unwrap().body, b"etag");
+        let response = fetch(&cache, "/etag").unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"etag");
+        assert_eq!(response.header("x-test"), Some("a"));
+        // A response that changed replaces the stored one.
+        assert_eq!(fetch(&cache, "/modified").unwrap().body, b"old");
+        assert_eq!(fetch(&cache, "/modified").unwrap().body, b"new");
+        assert_eq!(fetch(&cache, "/private").unwrap().body, b"private");
+        // Only the entries are left in the directory, without the files
+        // that they were written to.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+        let requests: Vec<String> = requests.iter().collect();
+        let paths: Vec<&str> = requests
+            .iter()
+            .map(|request| request.split(' ').nth(1).unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "/fresh",
+                "/etag",
+                "/etag",
+                "/modified",
+                "/modified",
+                "/private"
+            ]
+        );
+        assert!(requests[2].contains("if-none-match: \"v1\"\r\n"));
+        assert!(
+            requests[4].contains(
+                "if-modified-since: Mon, 01 Jan 2024 00:00:00 GMT\r\n"
+            )
+        );
+
+        // Offline, the stored responses are loaded whether they are fresh
+        // or not.
+        let offline = HttpCache {
+            offline: true,
+            ..HttpCache::new(&dir)
+        };
+        assert_eq!(fetch(&offline, "/fresh").unwrap().body, b"fresh");
+        assert_eq!(fetch(&offline, "/modified").unwrap().body, b"new");
+        let resource = offline
+            .load(&Url::parse(&format!("{}/etag", url)).unwrap())
+            .unwrap();
+        assert_eq!(resource.data, b"etag");
+        assert_eq!(
+            fetch(&offline, "/private").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn test_freshness() {
+        let now = SystemTime::now();
+        let entry = |headers: &[(&str, &str)], age: u64| Entry {
+            url: String::new(),
+            stored: now - Duration::from_secs(age),
+            response: Response {
+                url: String::new(),
+                status: 200,
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body: Vec::new(),
+            },
+        };
+        let date = httpdate::fmt_http_date(now - Duration::from_secs(100));
+        let is_fresh = |headers: &[(&str, &str)], age: u64| {
+            is_fresh(&entry(headers, age), now)
+        };
+
+        assert!(is_fresh(&[("cache-control", "max-age=60")], 59));
+        assert!(!is_fresh(&[("cache-control", "max-age=60")], 60));
+        assert!(!is_fresh(
+            &[("cache-control", "max-age=60"), ("age", "30")],
+            30
+        ));
+        assert!(!is_fresh(&[("cache-control", "no-cache, max-age=60")], 0));
+        // `max-age` wins over `Expires`.
+        assert!(!is_fresh(
+            &[("cache-control", "max-age=0"), ("expires", &date)],
+            0
+        ));
+        let expires = httpdate::fmt_http_date(now + Duration::from_secs(100));
+        assert!(is_fresh(&[("expires", &expires)], 10));
+        assert!(!is_fresh(&[("expires", "0")], 0));
+        // A response that was last modified 100 seconds before it was sent
+        // is fresh for 10 seconds.
+        let sent = httpdate::fmt_http_date(now);
+        let headers = [("date", sent.as_str()), ("last-modified", &date)];
+        assert!(is_fresh(&headers, 0));
+        assert!(!is_fresh(&[("last-modified", &date)], 11));
+        assert!(!is_fresh(&[], 0));
+        // Ages and lifetimes that overflow don't panic, and a response
+        // whose age overflows is stale.
+        let huge = "18446744073709551615";
+        assert!(!is_fresh(
+            &[("cache-control", "max-age=60"), ("age", huge)],
+            1
+        ));
+        let huger = "99999999999999999999999";
+        assert!(!is_fresh(
+            &[("cache-control", "max-age=60"), ("age", huger)],
+            0
+        ));
+        let max_age = format!("max-age={}", huge);
+        assert!(is_fresh(&[("cache-control", &max_age)], 10));
+        assert!(!is_fresh(&[("cache-control", &max_age), ("age", huge)], 10));
+
+        assert!(is_storable(&entry(&[], 0).response));
+        assert!(!is_storable(
+            &entry(&[("cache-control", "no-store")], 0).response
+        ));
+        assert!(!is_storable(&entry(&[("vary", "*")], 0).response));
+    }
+
+    #[test]
+    fn test_corrupt_entries_are_misses() {
+        let entry = |stored: &str| {
+            format!("/a\n/a\n{}\n200\nx-test: a\n\nbody", stored)
+        };
+        assert!(Entry::parse(entry("0").as_bytes()).is_some());
+        assert!(
+            Entry::parse(entry(&u64::MAX.to_string()).as_bytes()).is_none()
+        );
+        assert!(Entry::parse(entry("yesterday").as_bytes()).is_none());
+        assert!(Entry::parse(b"/a\n/a\n0\n200\nx-test").is_none());
+
+        // A corrupt entry is loaded from the network again.
+        let (url, _) = serve(vec![response("", "network")]);
+        let dir = std::env::temp_dir().join("simple-browser-corrupt-cache");
+        let cache = HttpCache::new(&dir);
+        cache.clear().unwrap();
+        let url = format!("{}/corrupt", url);
+        fs::create_dir_all(&dir).unwrap();
+        let stored = format!("{}\n{}\n{}\n200\n\ncached", url, url, u64::MAX);
+        fs::write(cache.path(&url), stored).unwrap();
+        assert_eq!(cache.fetch(&url).unwrap().body, b"network");
+    }
+}
@@ -0,0 +1,215 @@
+// The image cache decodes the bytes of images on other threads, so that a
+// document is laid out and painted while its images are decoded, and keeps
+// each decoded image once, shared with the images that paint it. An image
+// that isn't decoded yet has no natural size, so its element is laid out
+// again once it is.
+// SEE: https://html.spec.whatwg.org/multipage/images.html#decoding-images
+
+use std::{
+    collections::HashSet,
+    panic,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use crate::{
+    dom::Node,
+    layout::ImageSizes,
+    painting::{Bitmap, Images},
+    svg::Svg,
+};
+
+// An image as it came out of decoding.
+#[derive(Debug)]
+enum Decoded {
+    Bitmap(Bitmap),
+    Svg(Svg),
+    // The bytes are not an image in a format that can be decoded.
+    Broken,
+}
+
+// The images of a document by their URLs, as they are written in the
+// document, with the ones that are still being decoded.
+#[derive(Debug)]
+pub struct ImageCache {
+    images: Images,
+    pending: HashSet<String>,
+    broken: HashSet<String>,
+    sender: Sender<(String, Decoded)>,
+    receiver: Receiver<(String, Decoded)>,
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            images: Images::new(),
+            pending: HashSet::new(),
+            broken: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Start decoding the bytes of an image on another thread, unless the
+    // image at the URL is decoded or being decoded already.
+    pub fn load(&mut self, url: &str, data: Vec<u8>) {
+        if self.pending.contains(url)
+            || self.broken.contains(url)
+            || self.images.get(url).is_some()
+            || self.images.get_svg(url).is_some()
+        {
+            return;
+        }
+        self.pending.insert(url.to_string());
+        let sender = self.sender.clone();
+        let url = url.to_string();
+        thread::spawn(move || {
+            // An image that makes a decoder panic is broken like one that
+            // it rejects.
+            let decoded = panic::catch_unwind(|| decode(&data))
+                .unwrap_or(Decoded::Broken);
+            // The cache may be gone, in which case nobody wants the image.
+            let _ = sender.send((url, decoded));
+        });
+    }
+
+    // Keep the images that finished decoding, without waiting for the
+    // others. Returns their URLs, whose elements are to be laid out again.
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut loaded = Vec::new();
+        while let Ok((url, decoded)) = self.receiver.try_recv() {
+            self.finish(url, decoded, &mut loaded);
+        }
+        loaded
+    }
+
+    // Wait until all images are decoded, such as before taking a
+    // screenshot, and keep them. Returns the URLs like `poll`.
+    pub fn wait(&mut self) -> Vec<String> {
+        let mut loaded = self.poll();
+        while !self.pending.is_empty() {
+            let Ok((url, decoded)) = self.receiver.recv() else {
+                break;
+            };
+            self.finish(url, decoded, &mut loaded);
+        }
+        loaded
+    }
+
+    fn finish(
+        &mut self,
+        url: String,
+        decoded: Decoded,
+        loaded: &mut Vec<String>,
+    ) {
+        self.pending.remove(&url);
+        match decoded {
+            Decoded::Bitmap(bitmap) => self.images.insert(&url, bitmap),
+            Decoded::Svg(svg) => self.images.insert_svg(&url, svg),
+            Decoded::Broken => {
+                self.broken.insert(url);
+                return;
+            }
+        }
+        loaded.push(url);
+    }
+
+    // Check if the image at a URL is still being decoded.
+    pub fn is_pending(&self, url: &str) -> bool {
+        self.pending.contains(url)
+    }
+
+    // Check if the bytes of the image at a URL couldn't be decoded.
+    pub fn is_broken(&self, url: &str) -> bool {
+        self.broken.contains(url)
+    }
+
+    // Get the images that are decoded, which painting draws.
+    pub fn images(&self) -> &Images {
+        &self.images
+    }
+}
+
+impl ImageSizes for ImageCache {
+    fn natural_size(&self, node: &Node) -> Option<(f32, f32)> {
+        self.images.natural_size(node)
+    }
+
+    fn url_size(&self, url: &str) -> Option<(f32, f32)> {
+        self.images.url_size(url)
+    }
+}
+
+// Decode the bytes of an image, which is an SVG image if they are markup.
+fn decode(data: &[u8]) -> Decoded {
+    if let Some(bitmap) = Bitmap::decode(data) {
+        return Decoded::Bitmap(bitmap);
+    }
+    std::str::from_utf8(data)
+        .ok()
+        .filter(|source| source.trim_start().starts_with('<'))
+        .and_then(Svg::parse)
+        .map_or(Decoded::Broken, Decoded::Svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        html_parser::HTMLParser,
+        layout::{Dimensions, layout_tree_with_metrics},
+        style::{MediaContext, apply},
+        text::MonospaceMetrics,
+    };
+
+    #[test]
+    fn test_image_cache() {
+        let root = HTMLParser::parse(
+            "<div><img src=\"a.svg\"></img><img src=\"b.svg\"></img></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } img { display: block; }".to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        let heights = |cache: &ImageCache| {
+            let layout_root = layout_tree_with_metrics(
+                &styled,
+                viewport,
+                &MonospaceMetrics,
+                cache,
+            );
+            layout_root
+                .children()
+                .iter()
+                .map(|child| child.dimensions().content.height)
+                .collect::<Vec<_>>()
+        };
+
+        let mut cache = ImageCache::new();
+        cache.load("a.svg", b"<svg width=\"20\" height=\"10\"></svg>".to_vec());
+        cache.load("b.svg", b"\x00 not an image".to_vec());
+        assert_eq!(cache.wait(), ["a.svg"]);
+        assert!(!cache.is_pending("a.svg"));
+        assert!(cache.is_broken("b.svg"));
+        // A broken image is laid out at the default size of replaced
+        // elements, like one that isn't decoded yet.
+        assert_eq!(heights(&cache), [10.0, 150.0]);
+        assert!(cache.images().get_svg("a.svg").is_some());
+
+        // An image is decoded once.
+        cache.load("a.svg", b"<svg width=\"1\" height=\"1\"></svg>".to_vec());
+        assert!(!cache.is_pending("a.svg"));
+        assert!(cache.poll().is_empty());
+    }
+}
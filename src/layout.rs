@@ -1,16 +1,67 @@
+use std::{cell::OnceCell, collections::HashMap, fmt::Write, sync::Arc};
+
 use crate::{
-    css_parser::{Unit, Value},
+    computed::{
+        Break,
+        Clear,
+        ComputedStyle,
+        Corners,
+        Direction,
+        Float,
+        GridLine,
+        LengthOrAuto,
+        LengthPercentage,
+        ListStylePosition,
+        Position,
+        Sides,
+        TableLayout,
+        TextAlign,
+        TrackSize,
+        TransformFunction,
+        VerticalAlign,
+    },
+    css_parser::PseudoElement,
+    dom::{Node, NodeId, NodeType},
     style::{Display, StyledNode},
+    svg::Svg,
+    text::{
+        self,
+        DecorationMetrics,
+        FontMetrics,
+        MonospaceMetrics,
+        TextSegment,
+    },
 };
 
-// To keep the code simple, this code implments only normal flow.
-// TODO: Support floats, absolute positioning, and fixed positioning.
+// To keep the code simple, this code implments only normal flow, floats,
+// positioning, grids, and tables.
 
 #[derive(Debug)]
-struct LayoutBox<'a> {
+pub struct LayoutBox<'a> {
     dimensions: Dimensions,
     box_type: BoxType<'a>,
     children: Vec<LayoutBox<'a>>,
+    // The line boxes of the inline content of a block container.
+    lines: Vec<LineBox<'a>>,
+    // The position of the margin box in normal flow, which absolutely
+    // positioned boxes without offsets are placed at.
+    static_position: (f32, f32),
+    // The min-content and max-content widths of the content, which are
+    // measured once, and again only after the content changes.
+    measured_widths: OnceCell<(f32, f32)>,
+    // What has to be laid out again since the last layout.
+    dirty: Dirty,
+    // The inputs and results of the last layout in normal flow, which a
+    // clean box is moved to its new position with instead of being laid
+    // out again.
+    cache: Option<LayoutCache>,
+    // The matrix that maps the coordinates of the box to the page, which
+    // combines its transform and those of its ancestors. It is applied
+    // after layout, and is `None` if none of them is transformed.
+    transform: Option<Transform>,
+    // How far the content of a scroll container is scrolled, which is kept
+    // across layouts within the scrollable overflow.
+    scroll_offset: (f32, f32),
 }
 
 impl<'a> LayoutBox<'a> {
@@ -20,17 +71,187 @@ impl<'a> LayoutBox<'a> {
             dimensions: Default::default(),
             box_type,
             children: Vec::new(),
+            lines: Vec::new(),
+            static_position: (0.0, 0.0),
+            measured_widths: OnceCell::new(),
+            dirty: Dirty {
+                layout: true,
+                ..Default::default()
+            },
+            cache: None,
+            transform: None,
+            scroll_offset: (0.0, 0.0),
         }
     }
 
     // Get the style node associated with this layout box.
     fn get_style_node(&self) -> &'a StyledNode<'a> {
         match self.box_type {
-            BoxType::BlockNode(node) | BoxType::InlineNode(node) => node,
-            BoxType::AnonymousBlock => {
-                panic!("Anonymous block has no style node.")
+            BoxType::BlockNode(node)
+            | BoxType::InlineNode(node)
+            | BoxType::InlineBlockNode(node) => node,
+            BoxType::Anonymous(_) => {
+                panic!("Anonymous box has no style node.")
+            }
+        }
+    }
+
+    // Get the display type of the box.
+    #[inline]
+    fn display(&self) -> Display {
+        self.box_type.style().display
+    }
+
+    // Get the position and size of the box and its edges.
+    // Inline boxes are laid out as fragments on the lines of their block
+    // container, so their own dimensions are left empty.
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    // Get the computed style of the box.
+    pub fn style(&self) -> &ComputedStyle {
+        self.box_type.style()
+    }
+
+    // Get the style node that generated the box, unless it is an anonymous
+    // box.
+    pub fn style_node(&self) -> Option<&'a StyledNode<'a>> {
+        match self.box_type {
+            BoxType::BlockNode(node)
+            | BoxType::InlineNode(node)
+            | BoxType::InlineBlockNode(node) => Some(node),
+            BoxType::Anonymous(_) => None,
+        }
+    }
+
+    // Get the DOM node that generated the box, unless it is an anonymous
+    // box. The boxes of pseudo-elements belong to their element.
+    pub fn node_id(&self) -> Option<NodeId> {
+        self.style_node().map(|node| node.node.node_id)
+    }
+
+    pub fn children(&self) -> &[LayoutBox<'a>] {
+        &self.children
+    }
+
+    // Get the line boxes of the inline content, if the box is a block
+    // container.
+    pub fn lines(&self) -> &[LineBox<'a>] {
+        &self.lines
+    }
+
+    // Get the matrix that maps the laid out geometry of the box, and of
+    // the lines of a block container, to where it is drawn on the page.
+    pub fn transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    // Get how far the content of the box is scrolled to the right and down.
+    pub fn scroll_offset(&self) -> (f32, f32) {
+        self.scroll_offset
+    }
+
+    // Get how far the content of the box can be scrolled to the right and
+    // down, which is as far as its scrollable overflow reaches past its
+    // padding box, if it is a scroll container.
+    pub fn scroll_range(&self) -> (f32, f32) {
+        let Some(padding) = self.clip_rect() else {
+            return (0.0, 0.0);
+        };
+        let overflow = self.dimensions.scrollable_overflow;
+        (
+            (overflow.x + overflow.width - padding.x - padding.width).max(0.0),
+            (overflow.y + overflow.height - padding.y - padding.height)
+                .max(0.0),
+        )
+    }
+
+    // Scroll the scroll container of a node by a distance, as far as its
+    // content reaches, and move the boxes that it contains with it. Returns
+    // whether it moved. This is called on the root, which the transforms
+    // are updated from.
+    // SEE: https://drafts.csswg.org/cssom-view/#dom-element-scrollby
+    pub fn scroll_by(&mut self, node: NodeId, dx: f32, dy: f32) -> bool {
+        let Some(scroller) = self.find_scroll_container(node) else {
+            return false;
+        };
+        let (max_x, max_y) = scroller.scroll_range();
+        let (x, y) = scroller.scroll_offset;
+        let offset = ((x + dx).clamp(0.0, max_x), (y + dy).clamp(0.0, max_y));
+        if offset == scroller.scroll_offset {
+            return false;
+        }
+        scroller.scroll_offset = offset;
+        self.compute_transforms([None; 3]);
+        true
+    }
+
+    fn find_scroll_container(
+        &mut self,
+        node: NodeId,
+    ) -> Option<&mut LayoutBox<'a>> {
+        if self.node_id() == Some(node) && self.clip_rect().is_some() {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_scroll_container(node))
+    }
+
+    // Get the transformation of the box itself, relative to the coordinates
+    // that its parent is drawn in, if it is transformed.
+    pub fn own_transform(&self) -> Option<Transform> {
+        self.is_transformed().then(|| self.local_transform())
+    }
+
+    // Get the border box as it is drawn on the page, after transforms.
+    pub fn border_quad(&self) -> Quad {
+        Quad::new(self.dimensions.border_box(), self.transform)
+    }
+
+    // Get the border box with its corners rounded by `border-radius`.
+    // Radii that don't fit beside each other are scaled down together.
+    // SEE: https://drafts.csswg.org/css-backgrounds/#corner-overlap
+    pub fn rounded_border_box(&self) -> RoundedRect {
+        let rect = self.dimensions.border_box();
+        let radius = self.box_type.style().border_radius;
+        let resolve = |(x, y): (LengthPercentage, LengthPercentage)| {
+            (x.to_px(rect.width).max(0.0), y.to_px(rect.height).max(0.0))
+        };
+        let mut radii = Corners {
+            top_left: resolve(radius.top_left),
+            top_right: resolve(radius.top_right),
+            bottom_right: resolve(radius.bottom_right),
+            bottom_left: resolve(radius.bottom_left),
+        };
+        let scale = [
+            (rect.width, radii.top_left.0 + radii.top_right.0),
+            (rect.height, radii.top_right.1 + radii.bottom_right.1),
+            (rect.width, radii.bottom_left.0 + radii.bottom_right.0),
+            (rect.height, radii.top_left.1 + radii.bottom_left.1),
+        ]
+        .iter()
+        .filter(|(_, sum)| *sum > 0.0)
+        .map(|(length, sum)| length / sum)
+        .fold(1.0, f32::min);
+        if scale < 1.0 {
+            for radius in [
+                &mut radii.top_left,
+                &mut radii.top_right,
+                &mut radii.bottom_right,
+                &mut radii.bottom_left,
+            ] {
+                *radius = (radius.0 * scale, radius.1 * scale);
             }
         }
+        RoundedRect::new(rect, radii)
+    }
+
+    // Get the padding box with its corners rounded to follow the inner
+    // edge of the border.
+    pub fn rounded_padding_box(&self) -> RoundedRect {
+        self.rounded_border_box().shrunk_by(self.dimensions.border)
     }
 }
 
@@ -38,45 +259,199 @@ impl<'a> LayoutBox<'a> {
 enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
-    AnonymousBlock,
+    // An inline-level box whose content is laid out like a block.
+    InlineBlockNode(&'a StyledNode<'a>),
+    // A box that is generated to complete the box tree, such as a block
+    // around inline content or a table row around table cells. Its display
+    // type is given by its style.
+    Anonymous(Arc<ComputedStyle>),
+}
+
+impl BoxType<'_> {
+    // Get the computed style of the box.
+    // Taking it from the box type rather than the box lets callers modify
+    // the other fields of the box while holding it.
+    fn style(&self) -> &ComputedStyle {
+        match self {
+            BoxType::BlockNode(node)
+            | BoxType::InlineNode(node)
+            | BoxType::InlineBlockNode(node) => &node.style,
+            BoxType::Anonymous(style) => style,
+        }
+    }
+}
+
+// A line of inline content.
+#[derive(Debug)]
+pub struct LineBox<'a> {
+    rect: Rectangle,
+    // The position of the baseline that the content of the line is aligned
+    // on.
+    baseline: f32,
+    fragments: Vec<TextFragment<'a>>,
+}
+
+impl<'a> LineBox<'a> {
+    pub fn rect(&self) -> Rectangle {
+        self.rect
+    }
+
+    pub fn baseline(&self) -> f32 {
+        self.baseline
+    }
+
+    pub fn fragments(&self) -> &[TextFragment<'a>] {
+        &self.fragments
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.rect.x += dx;
+        self.rect.y += dy;
+        self.baseline += dy;
+        for fragment in &mut self.fragments {
+            fragment.rect.x += dx;
+            fragment.rect.y += dy;
+        }
+    }
+}
+
+// A place between lines or boxes where content may move to the next page.
+#[derive(Debug, Clone, Copy)]
+struct BreakPoint {
+    y: f32,
+    forced: bool,
+    avoid: bool,
+}
+
+// A piece of a text node on one line box. Text that wraps is split into a
+// fragment per line.
+#[derive(Debug)]
+pub struct TextFragment<'a> {
+    node: &'a StyledNode<'a>,
+    text: String,
+    rect: Rectangle,
+    // The extra width of each space of the text, by which justified lines
+    // are stretched to fill the line.
+    word_spacing: f32,
+    // The pieces that the text was pushed in, as the byte offsets in the
+    // text of the node where each starts, and the offsets in `text` that
+    // it starts at. Collapsed spaces are shorter in `text`.
+    pieces: Vec<(usize, usize)>,
+    // The placement of the lines that decorate the text in its font, if
+    // the node has decorations.
+    decoration_metrics: Option<DecorationMetrics>,
+}
+
+impl<'a> TextFragment<'a> {
+    // Get the text node that the fragment is a piece of.
+    pub fn node(&self) -> &'a StyledNode<'a> {
+        self.node
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn rect(&self) -> Rectangle {
+        self.rect
+    }
+
+    pub fn word_spacing(&self) -> f32 {
+        self.word_spacing
+    }
+
+    pub fn decoration_metrics(&self) -> Option<DecorationMetrics> {
+        self.decoration_metrics
+    }
+
+    // Get the part of the fragment where the text of its node between two
+    // byte offsets is drawn, such as the part of a selection on it. Return
+    // `None` if none of that text is in the fragment.
+    pub fn text_range_rect(
+        &self,
+        start: usize,
+        end: usize,
+        metrics: &dyn FontMetrics,
+    ) -> Option<Rectangle> {
+        let (start, end) = (self.text_index(start), self.text_index(end));
+        if start >= end {
+            return None;
+        }
+        let style = &self.node.style;
+        let x = |index: usize| {
+            let text = &self.text[..index];
+            let spaces = text.matches(' ').count() as f32;
+            metrics.measure(text, style) + self.word_spacing * spaces
+        };
+        Some(Rectangle {
+            x: self.rect.x + x(start),
+            width: x(end) - x(start),
+            ..self.rect
+        })
+    }
+
+    // Map a byte offset in the text of the node to an offset in `text`.
+    fn text_index(&self, offset: usize) -> usize {
+        let Some(i) =
+            self.pieces.iter().rposition(|&(start, _)| start <= offset)
+        else {
+            return 0;
+        };
+        let (start, index) = self.pieces[i];
+        let end = self
+            .pieces
+            .get(i + 1)
+            .map_or(self.text.len(), |piece| piece.1);
+        let mut index = (index + offset - start).min(end);
+        while !self.text.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
-struct Dimensions {
+pub struct Dimensions {
     // Position of the content area
-    content: Rectangle,
+    pub content: Rectangle,
 
     // Surrounding edges
-    padding: EdgeSizes,
-    border: EdgeSizes,
-    margin: EdgeSizes,
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+
+    // The area covered by the padding box and the content that overflows
+    // it, which a scroll container can be scrolled through.
+    // SEE: https://drafts.csswg.org/css-overflow-3/#scrollable
+    pub scrollable_overflow: Rectangle,
 }
 
 impl Dimensions {
     // The area covered by the content area plus its padding.
-    fn padding_box(self) -> Rectangle {
+    pub fn padding_box(self) -> Rectangle {
         self.content.expanded_by(self.padding)
     }
     // The area covered by the content area plus padding and borders.
-    fn border_box(self) -> Rectangle {
+    pub fn border_box(self) -> Rectangle {
         self.padding_box().expanded_by(self.border)
     }
     // The area covered by the content area plus padding, borders, and margin.
-    fn margin_box(self) -> Rectangle {
+    pub fn margin_box(self) -> Rectangle {
         self.border_box().expanded_by(self.margin)
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-struct Rectangle {
-    x: f32,
-    y: f32,
-    width: f32,
-    height: f32,
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rectangle {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
 }
 
 impl Rectangle {
-    fn expanded_by(self, edge: EdgeSizes) -> Self {
+    pub fn expanded_by(self, edge: EdgeSizes) -> Self {
         Self {
             x: self.x - edge.left,
             y: self.y - edge.top,
@@ -84,221 +459,5806 @@ impl Rectangle {
             height: self.height + edge.top + edge.bottom,
         }
     }
+
+    // Get the smallest rectangle that contains both rectangles.
+    pub fn union(self, other: Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    // Get the area that both rectangles cover, which is empty if they
+    // don't overlap.
+    pub fn intersection(self, other: Self) -> Self {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Self {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x)
+                .max(0.0),
+            height: ((self.y + self.height).min(other.y + other.height) - y)
+                .max(0.0),
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-struct EdgeSizes {
-    left: f32,
-    right: f32,
-    top: f32,
-    bottom: f32,
+// A 2D affine transformation, which maps a point (x, y) to
+// (ax + cy + e, bx + dy + f).
+// SEE: https://drafts.csswg.org/css-transforms/#mathematical-description
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
 }
 
-// Build a layout tree from the style tree.
-fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BoxType::BlockNode(style_node),
-        Display::Inline => BoxType::InlineNode(style_node),
-        Display::None => panic!("Root node has display: none."),
-    });
+impl Transform {
+    pub const IDENTITY: Self = Self::new([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    pub const fn new([a, b, c, d, e, f]: [f32; 6]) -> Self {
+        Self { a, b, c, d, e, f }
+    }
+
+    pub fn translate(x: f32, y: f32) -> Self {
+        Self::new([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Self::new([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
 
-    for child in &style_node.children {
-        match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root.children.push(build_layout_tree(child)),
-            Display::None => {}
+    // Get the matrix of a transform function of a box with a border box of
+    // `size`, which percentages of translations are of.
+    fn from_function(function: TransformFunction, size: Rectangle) -> Self {
+        match function {
+            TransformFunction::Translate(x, y) => {
+                Self::translate(x.to_px(size.width), y.to_px(size.height))
+            }
+            TransformFunction::Scale(x, y) => Self::scale(x, y),
+            TransformFunction::Rotate(angle) => {
+                let (sin, cos) = angle.sin_cos();
+                Self::new([cos, sin, -sin, cos, 0.0, 0.0])
+            }
+            TransformFunction::Skew(x, y) => {
+                Self::new([1.0, y.tan(), x.tan(), 1.0, 0.0, 0.0])
+            }
+            TransformFunction::Matrix(matrix) => Self::new(matrix),
         }
     }
 
-    root
+    // Get the transformation that applies `other` and then this one.
+    pub fn multiply(self, other: Self) -> Self {
+        Self {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    // Map a point.
+    pub fn apply(self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    // Get the transformation that undoes this one, unless it flattens the
+    // plane, such as `scale(0)`.
+    pub fn inverse(self) -> Option<Self> {
+        let determinant = self.a * self.d - self.b * self.c;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        Some(Self {
+            a: self.d / determinant,
+            b: -self.b / determinant,
+            c: -self.c / determinant,
+            d: self.a / determinant,
+            e: (self.c * self.f - self.d * self.e) / determinant,
+            f: (self.b * self.e - self.a * self.f) / determinant,
+        })
+    }
 }
 
-impl LayoutBox<'_> {
-    // Get inline container for the current box.
-    fn get_inline_container(&mut self) -> &mut Self {
-        match self.box_type {
-            BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
-            BoxType::BlockNode(_) => {
-                match self.children.last() {
-                    Some(&LayoutBox {
-                        box_type: BoxType::AnonymousBlock,
-                        ..
-                    }) => {}
-                    _ => self
-                        .children
-                        .push(LayoutBox::new(BoxType::AnonymousBlock)),
-                }
-                self.children.last_mut().unwrap()
-            }
+// A rectangle as it is drawn after transforms, given by its top left, top
+// right, bottom right, and bottom left corners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quad {
+    pub points: [(f32, f32); 4],
+}
+
+impl Quad {
+    pub fn new(rect: Rectangle, transform: Option<Transform>) -> Self {
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        let points = [
+            (rect.x, rect.y),
+            (right, rect.y),
+            (right, bottom),
+            (rect.x, bottom),
+        ];
+        Self {
+            points: transform.map_or(points, |transform| {
+                points.map(|point| transform.apply(point))
+            }),
         }
     }
 
-    fn layout(&mut self, containing_block: Dimensions) {
-        match self.box_type {
-            BoxType::BlockNode(_) => self.layout_block(containing_block),
-            BoxType::InlineNode(_) => {}  // TODO
-            BoxType::AnonymousBlock => {} // TODO
+    // Check if a point is inside the quad, including its edges, such as
+    // to hit test it. The quad of an affine transform is a parallelogram,
+    // and so convex.
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        // The point is on the same side of each edge, whichever way
+        // around the corners go.
+        let sides = [0, 1, 2, 3].map(|i| {
+            let (x0, y0) = self.points[i];
+            let (x1, y1) = self.points[(i + 1) % 4];
+            (x1 - x0) * (y - y0) - (y1 - y0) * (x - x0)
+        });
+        sides.iter().all(|side| *side >= 0.0)
+            || sides.iter().all(|side| *side <= 0.0)
+    }
+
+    // Get the smallest rectangle that contains the quad.
+    pub fn bounding_rect(&self) -> Rectangle {
+        let xs = self.points.map(|(x, _)| x);
+        let ys = self.points.map(|(_, y)| y);
+        let min =
+            |values: [f32; 4]| values.into_iter().fold(f32::INFINITY, f32::min);
+        let max = |values: [f32; 4]| {
+            values.into_iter().fold(f32::NEG_INFINITY, f32::max)
+        };
+        Rectangle {
+            x: min(xs),
+            y: min(ys),
+            width: max(xs) - min(xs),
+            height: max(ys) - min(ys),
         }
     }
+}
 
-    fn layout_block(&mut self, containing_block: Dimensions) {
-        // Child width can depend on parent width,
-        // so we need to calculate the box's width before laying out its children.
-        self.calc_block_width(containing_block);
+// A rectangle whose corners are rounded by the horizontal and vertical
+// radii of quarter ellipses.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoundedRect {
+    pub rect: Rectangle,
+    pub radii: Corners<(f32, f32)>,
+}
 
-        // Determine where the box is located within the containing block.
-        self.calc_block_position(containing_block);
+impl RoundedRect {
+    pub fn new(rect: Rectangle, radii: Corners<(f32, f32)>) -> Self {
+        Self { rect, radii }
+    }
 
-        // Recursively lay out the children of the box.
-        self.layout_block_children();
+    // Check if any corner is rounded.
+    pub fn is_rounded(&self) -> bool {
+        let Corners {
+            top_left,
+            top_right,
+            bottom_right,
+            bottom_left,
+        } = self.radii;
+        [top_left, top_right, bottom_right, bottom_left]
+            .iter()
+            .any(|&(x, y)| x > 0.0 && y > 0.0)
+    }
 
-        // Parent height can depend on child height,
-        // so we need to calculate the box's height after laying out its children.
-        self.calc_block_height();
+    // Get the rectangle shrunk by `edge`, such as the padding box inside
+    // the borders, whose radii shrink by as much.
+    // SEE: https://drafts.csswg.org/css-backgrounds/#corner-shaping
+    pub fn shrunk_by(self, edge: EdgeSizes) -> Self {
+        let shrink = |(x, y): (f32, f32), dx: f32, dy: f32| {
+            ((x - dx).max(0.0), (y - dy).max(0.0))
+        };
+        let radii = self.radii;
+        Self {
+            rect: Rectangle {
+                x: self.rect.x + edge.left,
+                y: self.rect.y + edge.top,
+                width: (self.rect.width - edge.left - edge.right).max(0.0),
+                height: (self.rect.height - edge.top - edge.bottom).max(0.0),
+            },
+            radii: Corners {
+                top_left: shrink(radii.top_left, edge.left, edge.top),
+                top_right: shrink(radii.top_right, edge.right, edge.top),
+                bottom_right: shrink(
+                    radii.bottom_right,
+                    edge.right,
+                    edge.bottom,
+                ),
+                bottom_left: shrink(radii.bottom_left, edge.left, edge.bottom),
+            },
+        }
     }
 
-    fn calc_block_width(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+    // Check if a point is inside the rectangle and not cut off by any of
+    // its corners.
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        let Rectangle {
+            x: left,
+            y: top,
+            width,
+            height,
+        } = self.rect;
+        let (right, bottom) = (left + width, top + height);
+        if x < left || y < top || x >= right || y >= bottom {
+            return false;
+        }
+        let radii = self.radii;
+        // Each corner, with the directions from it into the rectangle.
+        let corners = [
+            (radii.top_left, left, top, 1.0, 1.0),
+            (radii.top_right, right, top, -1.0, 1.0),
+            (radii.bottom_right, right, bottom, -1.0, -1.0),
+            (radii.bottom_left, left, bottom, 1.0, -1.0),
+        ];
+        corners
+            .iter()
+            .all(|&((rx, ry), corner_x, corner_y, sx, sy)| {
+                if rx <= 0.0 || ry <= 0.0 {
+                    return true;
+                }
+                // The offsets from the center of the ellipse, which are
+                // negative toward the corner.
+                let dx = (x - (corner_x + sx * rx)) * sx;
+                let dy = (y - (corner_y + sy * ry)) * sy;
+                dx >= 0.0
+                    || dy >= 0.0
+                    || (dx / rx).powi(2) + (dy / ry).powi(2) <= 1.0
+            })
+    }
+}
 
-        // `width` has initial value `auto`.
-        let auto = Value::Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or_else(|| auto.clone());
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EdgeSizes {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
 
-        let zero = Value::Length(0.0, Unit::Px);
+// Adjoining vertical margins, which collapse into a single margin that is
+// as wide as the largest positive margin plus the most negative one.
+// SEE: https://www.w3.org/TR/CSS22/box.html#collapsing-margins
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct CollapsedMargin {
+    positive: f32,
+    negative: f32,
+}
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+impl CollapsedMargin {
+    fn new(margin: f32) -> Self {
+        Self::default().adjoin(margin)
+    }
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+    fn adjoin(self, margin: f32) -> Self {
+        Self {
+            positive: self.positive.max(margin),
+            negative: self.negative.min(margin),
+        }
+    }
 
-        let border_left = style.lookup("border-left-width", "border", &zero);
-        let border_right = style.lookup("border-right-width", "border", &zero);
+    // Collapsing is idempotent, so margins that are already part of both
+    // are not counted twice.
+    fn collapse(self, other: Self) -> Self {
+        Self {
+            positive: self.positive.max(other.positive),
+            negative: self.negative.min(other.negative),
+        }
+    }
 
-        let total = sum([
-            &margin_left,
-            &margin_right,
-            &padding_left,
-            &padding_right,
-            &border_left,
-            &border_right,
-            &width,
-        ]
-        .iter()
-        .map(|v| v.to_px()));
+    fn resolve(self) -> f32 {
+        self.positive + self.negative
+    }
+}
 
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = Value::Length(0.0, Unit::Px);
-            }
-            if margin_right == auto {
-                margin_right = Value::Length(0.0, Unit::Px);
-            }
+// The parts of a box that have to be laid out again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Dirty {
+    // The box itself, along with all of its content.
+    layout: bool,
+    // Some of its descendants, which makes the box lay out its content
+    // again, but only the dirty parts of it from scratch.
+    children: bool,
+    // Only the position of the box, such as its relative offset, while its
+    // size and content stay the same.
+    position: bool,
+}
+
+impl Dirty {
+    #[inline]
+    fn is_clean(self) -> bool {
+        self == Dirty::default()
+    }
+}
+
+// A change to the document that the layout of the boxes of a node depends
+// on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    // The boxes have to be laid out again, such as when an image loads.
+    Layout,
+    // The boxes only move, such as when their relative offset changes.
+    Position,
+}
+
+impl Invalidation {
+    // Decide how a change of the computed style of a box affects its
+    // layout. Properties that are only painted, such as colors and
+    // opacity, don't affect it at all.
+    pub fn from_style_change(
+        old: &ComputedStyle,
+        new: &ComputedStyle,
+    ) -> Option<Self> {
+        let mut old = old.clone();
+        old.color = new.color;
+        old.background_color = new.background_color;
+        old.background_image.clone_from(&new.background_image);
+        old.background_repeat = new.background_repeat;
+        old.background_position = new.background_position;
+        old.background_size = new.background_size;
+        old.box_shadow.clone_from(&new.box_shadow);
+        old.border_color = new.border_color;
+        // A style that hides a border changes its computed width too.
+        old.border_style = new.border_style;
+        old.border_radius = new.border_radius;
+        old.visibility = new.visibility;
+        old.opacity = new.opacity;
+        old.z_index = new.z_index;
+        if old == *new {
+            return None;
+        }
+        old.offset = new.offset;
+        if old == *new && new.position == Position::Relative {
+            Some(Invalidation::Position)
+        } else {
+            Some(Invalidation::Layout)
         }
+    }
+}
 
-        let underflow = containing_block.content.width - total;
+// What the last layout of a box in normal flow depended on, and the margin
+// below it that it returned.
+#[derive(Clone, Copy, Debug)]
+struct LayoutCache {
+    containing_width: f32,
+    containing_height: Option<f32>,
+    direction: Direction,
+    above: Option<CollapsedMargin>,
+    below: CollapsedMargin,
+    // Whether any floats of the surrounding block formatting context were
+    // placed before or inside the box.
+    floats: bool,
+}
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
-            // If values are defined, adjust margin_right by the underflow.
-            (false, false, false) => {
-                margin_right =
-                    Value::Length(margin_right.to_px() + underflow, Unit::Px);
-            }
-            // If only margin_right is auto, set underflow to it.
-            (false, false, true) => {
-                margin_right = Value::Length(underflow, Unit::Px);
-            }
-            // If only margin_left is auto, set underflow to it.
-            (false, true, false) => {
-                margin_left = Value::Length(underflow, Unit::Px);
-            }
-            // If both margins are auto, set them to half of the underflow.
-            (false, true, true) => {
-                margin_left = Value::Length(underflow / 2.0, Unit::Px);
-                margin_right = Value::Length(underflow / 2.0, Unit::Px);
-            }
-            // If width is auto, any other auto values become 0.
-            (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Value::Length(underflow, Unit::Px);
-                }
-                if margin_right == auto {
-                    margin_right = Value::Length(underflow, Unit::Px);
-                }
+// The state of a layout pass that is shared between boxes.
+struct LayoutContext<'m> {
+    metrics: &'m dyn FontMetrics,
+    images: &'m dyn ImageSizes,
+    // The metrics and images again, if they can be shared between threads,
+    // which lets independent children be laid out in parallel.
+    #[cfg(feature = "parallel")]
+    parallel: Option<Parallel<'m>>,
+    // The floats of the innermost block formatting context.
+    floats: FloatContext,
+    // The height of the containing block, if it is known before its
+    // content is laid out, which percentage heights are resolved against.
+    containing_height: Option<f32>,
+    // The direction of the containing block, which decides the side that
+    // over-constrained margins are resolved on.
+    direction: Direction,
+}
 
-                if underflow >= 0.0 {
-                    // Expand width to fill the underflow.
-                    width = Value::Length(underflow, Unit::Px);
-                } else {
-                    // Width can't be negative, so adjust the margin_right instead.
-                    width = Value::Length(0.0, Unit::Px);
-                    margin_right = Value::Length(
-                        margin_right.to_px() + underflow,
-                        Unit::Px,
-                    );
-                }
-            }
+impl<'m> LayoutContext<'m> {
+    fn new(metrics: &'m dyn FontMetrics) -> Self {
+        Self {
+            metrics,
+            images: &NoImages,
+            #[cfg(feature = "parallel")]
+            parallel: None,
+            floats: FloatContext::default(),
+            containing_height: None,
+            direction: Direction::Ltr,
         }
+    }
+}
 
-        let d = &mut self.dimensions;
-        d.content.width = width.to_px();
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+#[cfg(feature = "parallel")]
+#[derive(Clone, Copy)]
+struct Parallel<'m> {
+    metrics: &'m (dyn FontMetrics + Sync),
+    images: &'m (dyn ImageSizes + Sync),
+}
+
+// The natural sizes of the images of replaced elements, which embedders
+// provide once they have decoded them.
+pub trait ImageSizes {
+    // Get the natural width and height of the image of an element, if it
+    // has been decoded.
+    fn natural_size(&self, node: &Node) -> Option<(f32, f32)>;
+
+    // Get the natural width and height of the image at a URL, such as a
+    // background image, if it has been decoded.
+    fn url_size(&self, _url: &str) -> Option<(f32, f32)> {
+        None
     }
+}
 
-    fn calc_block_position(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
-        let zero = Value::Length(0.0, Unit::Px);
+impl ImageSizes for HashMap<NodeId, (f32, f32)> {
+    fn natural_size(&self, node: &Node) -> Option<(f32, f32)> {
+        self.get(&node.node_id).copied()
+    }
+}
 
-        let d = &mut self.dimensions;
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom =
-            style.lookup("margin-bottom", "margin", &zero).to_px();
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom =
-            style.lookup("padding-bottom", "padding", &zero).to_px();
-        d.content.x = containing_block.content.x
-            + d.margin.left
-            + d.border.left
-            + d.padding.left;
-        d.content.y = containing_block.content.height
-            + containing_block.content.y
-            + d.margin.top
-            + d.border.top
-            + d.padding.top;
+// The image sizes of a document whose images are not loaded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoImages;
+
+impl ImageSizes for NoImages {
+    fn natural_size(&self, _node: &Node) -> Option<(f32, f32)> {
+        None
     }
+}
 
-    fn layout_block_children(&mut self) {
-        for child in &mut self.children {
-            child.layout(self.dimensions);
-            // Increment the height so each child is laid out below the previous one.
-            self.dimensions.content.height +=
-                child.dimensions.margin_box().height;
+// The floats placed so far in a block formatting context, which later
+// line boxes flow around.
+// SEE: https://www.w3.org/TR/CSS22/visuren.html#float-position
+#[derive(Debug, Default)]
+struct FloatContext {
+    // The margin boxes of the floats, with the side they float to.
+    floats: Vec<(Float, Rectangle)>,
+}
+
+impl FloatContext {
+    // Get the horizontal space between `left` and `right` that is not taken
+    // by floats, in the band from `y` to `y + height`.
+    fn available(
+        &self,
+        y: f32,
+        height: f32,
+        left: f32,
+        right: f32,
+    ) -> (f32, f32) {
+        self.overlapping(y, height).fold(
+            (left, right),
+            |(left, right), (side, rect)| match side {
+                Float::Left => (left.max(rect.x + rect.width), right),
+                _ => (left, right.min(rect.x)),
+            },
+        )
+    }
+
+    // Find the position of a new float's margin box, which is as high as
+    // possible, but not higher than `y` or earlier floats.
+    fn place(
+        &self,
+        side: Float,
+        width: f32,
+        height: f32,
+        y: f32,
+        left: f32,
+        right: f32,
+    ) -> (f32, f32) {
+        let mut y =
+            self.floats.iter().map(|(_, rect)| rect.y).fold(y, f32::max);
+        loop {
+            let (l, r) = self.available(y, height, left, right);
+            // Move below the highest float in the way, unless the float
+            // fits or nothing is in the way.
+            let next = self
+                .overlapping(y, height)
+                .map(|(_, rect)| rect.y + rect.height)
+                .fold(f32::INFINITY, f32::min);
+            if width <= r - l || next == f32::INFINITY {
+                let x = match side {
+                    Float::Right => r - width,
+                    _ => l,
+                };
+                return (x, y);
+            }
+            y = next;
         }
     }
 
-    fn calc_block_height(&mut self) {
-        if let Some(Value::Length(h, Unit::Px)) =
-            self.get_style_node().value("height")
-        {
-            self.dimensions.content.height = h;
+    fn add(&mut self, side: Float, rect: Rectangle) {
+        self.floats.push((side, rect));
+    }
+
+    // Get the bottom edge of the floats that a box with `clear` has to be
+    // placed below.
+    fn clearance(&self, clear: Clear) -> Option<f32> {
+        self.floats
+            .iter()
+            .filter(|(side, _)| clear.clears(*side))
+            .map(|(_, rect)| rect.y + rect.height)
+            .reduce(f32::max)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.floats.is_empty()
+    }
+
+    // Get the bottom edge of all floats.
+    fn bottom(&self) -> Option<f32> {
+        self.floats
+            .iter()
+            .map(|(_, rect)| rect.y + rect.height)
+            .reduce(f32::max)
+    }
+
+    fn overlapping(
+        &self,
+        y: f32,
+        height: f32,
+    ) -> impl Iterator<Item = &(Float, Rectangle)> {
+        self.floats.iter().filter(move |(_, rect)| {
+            rect.y < y + height && y < rect.y + rect.height
+        })
+    }
+}
+
+// Build the layout tree of a styled document, and lay it out in the
+// viewport, which is the initial containing block. Text is measured with
+// the fallback monospace metrics.
+pub fn layout_tree<'a>(
+    node: &'a StyledNode<'a>,
+    viewport: Dimensions,
+) -> LayoutBox<'a> {
+    layout_tree_with_metrics(node, viewport, &MonospaceMetrics, &NoImages)
+}
+
+// Like `layout_tree`, but measures text with the metrics of the fonts that
+// it will be drawn in, and images with their decoded sizes.
+pub fn layout_tree_with_metrics<'a>(
+    node: &'a StyledNode<'a>,
+    viewport: Dimensions,
+    metrics: &dyn FontMetrics,
+    images: &dyn ImageSizes,
+) -> LayoutBox<'a> {
+    let mut root_box = build_layout_tree(node);
+    root_box.relayout_with_metrics(viewport, metrics, images);
+    root_box
+}
+
+// Like `layout_tree_with_metrics`, but lays out the children of blocks on
+// several threads where they don't depend on each other.
+#[cfg(feature = "parallel")]
+pub fn layout_tree_parallel<'a>(
+    node: &'a StyledNode<'a>,
+    viewport: Dimensions,
+    metrics: &(dyn FontMetrics + Sync),
+    images: &(dyn ImageSizes + Sync),
+) -> LayoutBox<'a> {
+    let mut root_box = build_layout_tree(node);
+    let mut context = LayoutContext::new(metrics);
+    context.images = images;
+    context.parallel = Some(Parallel { metrics, images });
+    root_box.layout_root(viewport.content, &mut context);
+    root_box
+}
+
+// Get the box type generated by a styled node.
+// Flex and list-item layouts are not implemented yet, so their boxes are
+// laid out as blocks. Grid containers and tables are blocks whose children
+// are laid out in a grid, and so are the parts of a table. An inline
+// replaced element is an atomic box on a line, like an inline-block.
+fn box_type<'a>(style_node: &'a StyledNode<'a>) -> BoxType<'a> {
+    match style_node.display() {
+        Display::Block
+        | Display::Flex
+        | Display::Grid
+        | Display::ListItem
+        | Display::Table
+        | Display::TableRowGroup
+        | Display::TableHeaderGroup
+        | Display::TableFooterGroup
+        | Display::TableRow
+        | Display::TableCell
+        | Display::TableColumnGroup
+        | Display::TableColumn
+        | Display::TableCaption => BoxType::BlockNode(style_node),
+        Display::InlineBlock
+        | Display::InlineFlex
+        | Display::InlineGrid
+        | Display::InlineTable => BoxType::InlineBlockNode(style_node),
+        Display::Inline if is_replaced_element(style_node) => {
+            BoxType::InlineBlockNode(style_node)
         }
+        Display::Inline => BoxType::InlineNode(style_node),
+        Display::None => panic!("Node with display: none has no box."),
     }
 }
 
-fn sum<I>(iter: I) -> f32
-where
-    I: Iterator<Item = f32>,
-{
-    iter.fold(0., |acc, x| acc + x)
+// Check if a node is a replaced element, whose content is an image rather
+// than its children. The image of an `svg` element is drawn from its
+// descendants.
+// SEE: https://html.spec.whatwg.org/multipage/rendering.html#replaced-elements
+// TODO: Support `video`, `canvas`, and other replaced elements.
+fn is_replaced_element(style_node: &StyledNode) -> bool {
+    style_node.pseudo_element.is_none()
+        && matches!(
+            &style_node.node.node_type,
+            NodeType::Element(elem)
+                if elem.tag_name.eq_ignore_ascii_case("img")
+                    || elem.tag_name.eq_ignore_ascii_case("svg")
+        )
+}
+
+// Get the style of an anonymous box in a box, which inherits the inherited
+// properties of the box and takes the initial values of the others.
+fn anonymous_style(parent: &BoxType, display: Display) -> Arc<ComputedStyle> {
+    Arc::new(ComputedStyle::anonymous(parent.style(), display))
+}
+
+// Build a layout tree from the style tree.
+// Nodes with `display: none` generate no boxes, while hidden ones do,
+// because they still take up space. Painting skips them.
+fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+    if style_node.display() == Display::None {
+        panic!("Root node has display: none.");
+    }
+    let mut root = LayoutBox::new(box_type(style_node));
+    // Most nodes generate a single box, so the children are allocated once.
+    root.children.reserve_exact(style_node.children.len());
+
+    // The children of a replaced element are not rendered as boxes.
+    let children = if is_replaced_element(style_node) {
+        &[][..]
+    } else {
+        &style_node.children[..]
+    };
+    for child in children {
+        if child.display() != Display::None {
+            build_layout_tree(child).split_around_blocks(&mut root.children);
+        }
+    }
+    root.fix_table_structure();
+    root.fix_block_structure();
+
+    root
+}
+
+impl<'a> LayoutBox<'a> {
+    // Get the box that inline-level children of a block container are
+    // added to, once it has block-level children. That is an anonymous
+    // block box, which is the last child if the previous child was inline
+    // too.
+    fn get_inline_container(&mut self) -> &mut Self {
+        match self.box_type {
+            BoxType::InlineNode(_) => self,
+            BoxType::BlockNode(_)
+            | BoxType::InlineBlockNode(_)
+            | BoxType::Anonymous(_) => {
+                match self.children.last() {
+                    Some(LayoutBox {
+                        box_type: BoxType::Anonymous(style),
+                        ..
+                    }) if style.display == Display::Block => {}
+                    _ => {
+                        let style =
+                            anonymous_style(&self.box_type, Display::Block);
+                        self.children
+                            .push(LayoutBox::new(BoxType::Anonymous(style)))
+                    }
+                }
+                self.children.last_mut().unwrap()
+            }
+        }
+    }
+
+    // Check if the box is floated, and so taken out of normal flow.
+    #[inline]
+    fn is_float(&self) -> bool {
+        self.box_type.style().float != Float::None
+    }
+
+    #[inline]
+    fn position(&self) -> Position {
+        self.box_type.style().position
+    }
+
+    // Check if the box is the containing block of its descendants with a
+    // position of `absolute` or `fixed`, which are placed against it and
+    // only clipped by it and its own containing blocks.
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#containing-block-details
+    pub fn contains_positioned(&self, position: Position) -> bool {
+        match position {
+            Position::Fixed => self.is_transformed(),
+            _ => self.position() != Position::Static || self.is_transformed(),
+        }
+    }
+
+    // Check if the box is a replaced element, such as an image, whose
+    // content comes from outside the document.
+    pub fn is_replaced(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(node) | BoxType::InlineBlockNode(node) => {
+                is_replaced_element(node)
+            }
+            _ => false,
+        }
+    }
+
+    // Check if the box is laid out on the lines of its block container.
+    #[inline]
+    // Check if the box is an inline box, whose content is laid out in
+    // fragments on the lines of its block container rather than in its own
+    // border box.
+    pub fn is_inline_box(&self) -> bool {
+        matches!(self.box_type, BoxType::InlineNode(_)) && !self.is_replaced()
+    }
+
+    fn is_inline_level(&self) -> bool {
+        matches!(
+            self.box_type,
+            BoxType::InlineNode(_) | BoxType::InlineBlockNode(_)
+        )
+    }
+
+    fn is_grid_container(&self) -> bool {
+        matches!(self.display(), Display::Grid | Display::InlineGrid)
+    }
+
+    #[inline]
+    fn is_table(&self) -> bool {
+        matches!(self.display(), Display::Table | Display::InlineTable)
+    }
+
+    // Check if the box is a text node that contains only white space.
+    fn is_white_space_text(&self) -> bool {
+        match self.box_type {
+            BoxType::InlineNode(StyledNode {
+                node,
+                generated_text: None,
+                ..
+            }) => matches!(
+                &node.node_type,
+                NodeType::Text(text) if text.chars().all(text::is_white_space)
+            ),
+            _ => false,
+        }
+    }
+
+    // Generate the anonymous boxes that complete the structure of tables,
+    // so that every cell is in a row, and every row is in a table.
+    // White space between the parts of a table is dropped.
+    // SEE: https://www.w3.org/TR/CSS22/tables.html#anonymous-boxes
+    // TODO: Generate inline tables around table parts in inline content.
+    fn fix_table_structure(&mut self) {
+        // Children that are not allowed in this box are wrapped in an
+        // anonymous box of the `wrapper` display type.
+        let (allowed, wrapper): (fn(Display) -> bool, Display) = match self
+            .display()
+        {
+            Display::Table | Display::InlineTable => (
+                |display| {
+                    display.is_table_row_group()
+                        || matches!(
+                            display,
+                            Display::TableRow
+                                | Display::TableCaption
+                                | Display::TableColumnGroup
+                                | Display::TableColumn
+                        )
+                },
+                Display::TableRow,
+            ),
+            display if display.is_table_row_group() => {
+                (|display| display == Display::TableRow, Display::TableRow)
+            }
+            Display::TableRow => {
+                (|display| display == Display::TableCell, Display::TableCell)
+            }
+            Display::TableColumnGroup | Display::TableColumn => return,
+            _ => (|display| !display.is_table_part(), Display::Table),
+        };
+        if wrapper != Display::Table {
+            self.children.retain(|child| !child.is_white_space_text());
+        }
+        if self.children.iter().all(|child| allowed(child.display())) {
+            return;
+        }
+
+        let mut wrapping = false;
+        for child in std::mem::take(&mut self.children) {
+            if allowed(child.display()) {
+                self.children.push(child);
+                wrapping = false;
+                continue;
+            }
+            // Consecutive children share a wrapper.
+            if !wrapping {
+                let style = anonymous_style(&self.box_type, wrapper);
+                self.children
+                    .push(LayoutBox::new(BoxType::Anonymous(style)));
+                wrapping = true;
+            }
+            self.children.last_mut().unwrap().children.push(child);
+        }
+        for child in &mut self.children {
+            if let BoxType::Anonymous(_) = child.box_type {
+                child.fix_table_structure();
+            }
+        }
+    }
+
+    // Split an inline box that contains block-level boxes around them, so
+    // that the parts before and after each block are inline boxes of the
+    // same node, and the blocks become their siblings. The parts are added
+    // to `parts`, which are the children of the parent.
+    // SEE: https://www.w3.org/TR/CSS22/visuren.html#anonymous-block-level
+    fn split_around_blocks(mut self, parts: &mut Vec<Self>) {
+        let BoxType::InlineNode(node) = self.box_type else {
+            parts.push(self);
+            return;
+        };
+        if !self.children.iter().any(LayoutBox::is_in_flow_block_level) {
+            parts.push(self);
+            return;
+        }
+        let mut part = LayoutBox::new(BoxType::InlineNode(node));
+        for child in std::mem::take(&mut self.children) {
+            if !child.is_in_flow_block_level() {
+                part.children.push(child);
+                continue;
+            }
+            let next = LayoutBox::new(BoxType::InlineNode(node));
+            let before = std::mem::replace(&mut part, next);
+            if !before.children.is_empty() {
+                parts.push(before);
+            }
+            parts.push(child);
+        }
+        if !part.children.is_empty() {
+            parts.push(part);
+        }
+    }
+
+    // Wrap the runs of inline-level children of a block container that
+    // also has block-level children in anonymous block boxes, so that it
+    // only contains block-level boxes. Runs of collapsible white space
+    // generate no boxes, and floats and absolutely positioned boxes
+    // between blocks are not wrapped on their own.
+    // SEE: https://www.w3.org/TR/CSS22/visuren.html#anonymous-block-level
+    fn fix_block_structure(&mut self) {
+        for child in &mut self.children {
+            if let BoxType::Anonymous(_) = child.box_type {
+                child.fix_block_structure();
+            }
+        }
+        if !self.is_block_container()
+            || !self.children.iter().any(LayoutBox::is_in_flow_block_level)
+            || !self.children.iter().any(LayoutBox::is_inline_level)
+        {
+            return;
+        }
+
+        let mut run = Vec::new();
+        for child in std::mem::take(&mut self.children) {
+            if child.is_in_flow_block_level() {
+                self.wrap_inline_run(std::mem::take(&mut run));
+                self.children.push(child);
+            } else {
+                run.push(child);
+            }
+        }
+        self.wrap_inline_run(run);
+    }
+
+    fn wrap_inline_run(&mut self, run: Vec<Self>) {
+        let has_content = run.iter().any(|child| {
+            child.is_inline_level()
+                && !child.is_collapsible_white_space()
+                && !child.is_outside_marker()
+        });
+        for child in run {
+            // An outside marker stays with its list item.
+            if has_content && !child.is_outside_marker() {
+                self.get_inline_container().children.push(child);
+            } else if !child.is_collapsible_white_space() {
+                self.children.push(child);
+            }
+        }
+    }
+
+    // Check if the box is a block container, whose children are laid out
+    // as blocks or lines, rather than in a grid or a table.
+    fn is_block_container(&self) -> bool {
+        let display = self.display();
+        !matches!(self.box_type, BoxType::InlineNode(_))
+            && !self.is_replaced()
+            && !self.is_grid_container()
+            && !self.is_table()
+            && (!display.is_table_part()
+                || matches!(
+                    display,
+                    Display::TableCell | Display::TableCaption
+                ))
+    }
+
+    // Check if the box is the marker of a list item that is placed outside
+    // of it, rather than on its first line.
+    fn is_outside_marker(&self) -> bool {
+        match self.box_type {
+            BoxType::InlineNode(node) => {
+                node.pseudo_element == Some(PseudoElement::Marker)
+                    && node.style.list_style_position
+                        == ListStylePosition::Outside
+            }
+            _ => false,
+        }
+    }
+
+    // Check if the box is laid out as a block in normal flow.
+    fn is_in_flow_block_level(&self) -> bool {
+        !self.is_inline_level()
+            && !self.is_float()
+            && !self.position().is_out_of_flow()
+    }
+
+    // Check if the box is white space text that collapses away at the
+    // start of a line.
+    fn is_collapsible_white_space(&self) -> bool {
+        self.is_white_space_text()
+            && !self.box_type.style().white_space.preserves_newlines()
+    }
+
+    // Check if the box establishes a stacking context, which its
+    // descendants are painted in.
+    // SEE: https://www.w3.org/TR/CSS22/visuren.html#z-index
+    fn establishes_stacking_context(&self) -> bool {
+        let style = self.box_type.style();
+        (style.position != Position::Static && style.z_index.is_some())
+            || style.position == Position::Fixed
+            || style.opacity < 1.0
+            || self.is_transformed()
+    }
+
+    // Check if the box has a transform. Transforms don't apply to inline
+    // boxes, which are laid out in fragments.
+    // SEE: https://drafts.csswg.org/css-transforms/#transformable-element
+    fn is_transformed(&self) -> bool {
+        !matches!(self.box_type, BoxType::InlineNode(_))
+            && !self.box_type.style().transform.is_empty()
+    }
+
+    // Get the transformation of the box itself, which applies its transform
+    // functions around its transform origin.
+    // SEE: https://drafts.csswg.org/css-transforms/#transformation-matrix-computation
+    fn local_transform(&self) -> Transform {
+        let style = self.box_type.style();
+        let border_box = self.dimensions.border_box();
+        let (x, y) = style.transform_origin;
+        let origin_x = border_box.x + x.to_px(border_box.width);
+        let origin_y = border_box.y + y.to_px(border_box.height);
+        style
+            .transform
+            .iter()
+            .fold(
+                Transform::translate(origin_x, origin_y),
+                |matrix, function| {
+                    matrix.multiply(Transform::from_function(
+                        *function, border_box,
+                    ))
+                },
+            )
+            .multiply(Transform::translate(-origin_x, -origin_y))
+    }
+
+    // Combine the transforms of the box and its ancestors, and the scroll
+    // offsets of the scroll containers that contain it, after the tree has
+    // been laid out. `inherited` holds the matrices of the in-flow,
+    // absolutely positioned, and fixed positioned descendants of the parent,
+    // which differ when the parent or its ancestors don't contain them.
+    fn compute_transforms(&mut self, inherited: [Option<Transform>; 3]) {
+        let combine =
+            |outer: Option<Transform>, inner: Option<Transform>| match (
+                outer, inner,
+            ) {
+                (Some(outer), Some(inner)) => Some(outer.multiply(inner)),
+                (outer, inner) => outer.or(inner),
+            };
+        let [in_flow, absolute, fixed] = inherited;
+        let parent = match self.position() {
+            Position::Absolute => absolute,
+            Position::Fixed => fixed,
+            _ => in_flow,
+        };
+        self.transform = combine(parent, self.own_transform());
+        let (x, y) = self.scroll_offset;
+        let scroll =
+            (x != 0.0 || y != 0.0).then(|| Transform::translate(-x, -y));
+        let content = combine(self.transform, scroll);
+        let positioned = |position, inherited| {
+            if self.contains_positioned(position) {
+                content
+            } else {
+                inherited
+            }
+        };
+        let descendants = [
+            content,
+            positioned(Position::Absolute, absolute),
+            positioned(Position::Fixed, fixed),
+        ];
+        for child in &mut self.children {
+            child.compute_transforms(descendants);
+        }
+    }
+
+    // Check if the box establishes a new formatting context, which floats
+    // outside of it don't affect.
+    fn establishes_formatting_context(&self) -> bool {
+        match self.box_type {
+            BoxType::InlineBlockNode(_) => true,
+            BoxType::InlineNode(_) => false,
+            _ => {
+                self.is_float()
+                    || self.position().is_out_of_flow()
+                    || self.box_type.style().is_scroll_container()
+                    || self.is_grid_container()
+                    || self.is_multicol_container()
+                    || matches!(
+                        self.display(),
+                        Display::Table
+                            | Display::TableCell
+                            | Display::TableCaption
+                    )
+            }
+        }
+    }
+
+    // Lay out the tree again after some of its boxes were invalidated.
+    // Boxes that are clean and whose containing block keeps its size are
+    // only moved to their new position, without visiting their content.
+    pub fn relayout(&mut self, viewport: Dimensions) {
+        self.relayout_with_metrics(viewport, &MonospaceMetrics, &NoImages);
+    }
+
+    // Like `relayout`, but measures text with the metrics of the fonts that
+    // it will be drawn in, and images with their decoded sizes.
+    pub fn relayout_with_metrics(
+        &mut self,
+        viewport: Dimensions,
+        metrics: &dyn FontMetrics,
+        images: &dyn ImageSizes,
+    ) {
+        let mut context = LayoutContext::new(metrics);
+        context.images = images;
+        self.layout_root(viewport.content, &mut context);
+    }
+
+    // Mark the boxes of a node as changed, and their ancestors as having
+    // changed content. Returns whether the node has any boxes.
+    pub fn invalidate(
+        &mut self,
+        node: NodeId,
+        invalidation: Invalidation,
+    ) -> bool {
+        let mut found = match self.box_type {
+            BoxType::Anonymous(_) => false,
+            _ => self.get_style_node().node.node_id == node,
+        };
+        if found {
+            match invalidation {
+                Invalidation::Layout => self.dirty.layout = true,
+                Invalidation::Position => self.dirty.position = true,
+            }
+        }
+        for child in &mut self.children {
+            if child.invalidate(node, invalidation) {
+                self.dirty.children = true;
+                found = true;
+            }
+        }
+        // The intrinsic widths of the box and its ancestors include the
+        // content that changed.
+        if found && invalidation == Invalidation::Layout {
+            self.measured_widths.take();
+        }
+        found
+    }
+
+    // Replace the styles of the tree with the restyled tree of the same
+    // document, invalidating the boxes whose styles changed in a way that
+    // affects layout. Boxes whose children changed are built again.
+    // Changes to the document itself need a new layout tree, because the
+    // boxes borrow its nodes.
+    pub fn restyle(&mut self, style_node: &'a StyledNode<'a>) {
+        let old = std::mem::replace(self, build_layout_tree(style_node));
+        let mut nodes = HashMap::new();
+        self.adopt(old, &mut nodes);
+        self.replace_fragment_nodes(&nodes);
+    }
+
+    // Take over the layout of the box that the same node generated in the
+    // old tree, if it still generates the same kind of box with the same
+    // number of children. Otherwise, the new box is laid out from scratch.
+    // Returns whether the size of the box or its content may have changed.
+    fn adopt(
+        &mut self,
+        old: LayoutBox<'a>,
+        nodes: &mut HashMap<*const StyledNode<'a>, &'a StyledNode<'a>>,
+    ) -> bool {
+        let same_node = |new: &StyledNode, old: &StyledNode| {
+            new.node.node_id == old.node.node_id
+                && new.pseudo_element == old.pseudo_element
+        };
+        let matches = self.children.len() == old.children.len()
+            && match (&self.box_type, &old.box_type) {
+                (BoxType::BlockNode(new), BoxType::BlockNode(old))
+                | (BoxType::InlineNode(new), BoxType::InlineNode(old))
+                | (
+                    BoxType::InlineBlockNode(new),
+                    BoxType::InlineBlockNode(old),
+                ) => same_node(new, old),
+                (BoxType::Anonymous(new), BoxType::Anonymous(old)) => {
+                    new.display == old.display
+                }
+                _ => false,
+            };
+        if !matches {
+            return true;
+        }
+
+        let mut invalidation = Invalidation::from_style_change(
+            old.box_type.style(),
+            self.box_type.style(),
+        );
+        if !matches!(self.box_type, BoxType::Anonymous(_)) {
+            let (new, old) = (self.get_style_node(), old.get_style_node());
+            nodes.insert(old, new);
+            // Generated content, such as counters, is part of the style.
+            if new.generated_text != old.generated_text {
+                invalidation = Some(Invalidation::Layout);
+            }
+        }
+        self.dirty = old.dirty;
+        match invalidation {
+            Some(Invalidation::Layout) => self.dirty.layout = true,
+            Some(Invalidation::Position) => self.dirty.position = true,
+            None => {}
+        }
+        self.dimensions = old.dimensions;
+        self.lines = old.lines;
+        self.static_position = old.static_position;
+        self.cache = old.cache;
+        let mut changed = self.dirty.layout;
+        for (child, old) in self.children.iter_mut().zip(old.children) {
+            changed |= child.adopt(old, nodes);
+            if !child.dirty.is_clean() {
+                self.dirty.children = true;
+            }
+        }
+        // Moving boxes doesn't change the intrinsic widths.
+        if !changed {
+            self.measured_widths = old.measured_widths;
+        }
+        changed
+    }
+
+    // Point the text fragments of the lines that were taken over from the
+    // old tree at the nodes of the new tree.
+    fn replace_fragment_nodes(
+        &mut self,
+        nodes: &HashMap<*const StyledNode<'a>, &'a StyledNode<'a>>,
+    ) {
+        for line in &mut self.lines {
+            for fragment in &mut line.fragments {
+                if let Some(node) = nodes.get(&(fragment.node as *const _)) {
+                    fragment.node = node;
+                }
+            }
+        }
+        for child in &mut self.children {
+            child.replace_fragment_nodes(nodes);
+        }
+    }
+
+    // Lay out the tree in the viewport, which is also the containing block
+    // of fixed boxes and of absolutely positioned boxes without a
+    // positioned ancestor.
+    fn layout_root(
+        &mut self,
+        viewport: Rectangle,
+        context: &mut LayoutContext,
+    ) {
+        let containing_block = Dimensions {
+            content: Rectangle {
+                height: 0.0,
+                ..viewport
+            },
+            ..Default::default()
+        };
+        // The initial containing block has the direction of the root.
+        context.containing_height = Some(viewport.height);
+        context.direction = self.box_type.style().direction;
+        self.measure_intrinsic_widths(context);
+        self.layout(containing_block, context);
+        if !self.is_transformed() {
+            if self.position() == Position::Static {
+                self.layout_positioned_descendants(viewport, false, context);
+            }
+            self.layout_positioned_descendants(viewport, true, context);
+        }
+        self.compute_overflow();
+        self.compute_transforms([None; 3]);
+    }
+
+    // Get the rectangle that the content of the box is clipped to, which
+    // is its padding box if it is a scroll container.
+    pub fn clip_rect(&self) -> Option<Rectangle> {
+        match self.box_type {
+            BoxType::InlineNode(_) => None,
+            _ => self
+                .box_type
+                .style()
+                .is_scroll_container()
+                .then(|| self.dimensions.padding_box()),
+        }
+    }
+
+    // Break the laid out tree into pages at most `page_height` tall, and get
+    // the area of the margin box of the root on each page. Pages end
+    // between lines or block-level boxes, preferring the last place that
+    // fits, unless breaking there is avoided by `break-*` or would leave
+    // fewer lines than `orphans` or `widows` on either side. Content taller
+    // than a page is sliced at the page height.
+    // SEE: https://drafts.csswg.org/css-break/#breaking-rules
+    // TODO: Lay out content again on each page, so that margins after a
+    // break are truncated and boxes are split into fragments of their own.
+    pub fn paginate(&self, page_height: f32) -> Vec<Rectangle> {
+        assert!(page_height > 0.0, "Page height must be positive.");
+        let area = self.dimensions.margin_box();
+        let bottom = area.y + area.height;
+        let mut points = Vec::new();
+        self.collect_break_points(false, &mut points);
+        points.sort_by(|a, b| a.y.total_cmp(&b.y));
+
+        let mut pages = Vec::new();
+        let mut start = area.y;
+        while start < bottom {
+            let limit = start + page_height;
+            let fitting: Vec<_> = points
+                .iter()
+                .filter(|point| {
+                    point.y > start && point.y <= limit && point.y < bottom
+                })
+                .collect();
+            let end = if let Some(forced) = fitting.iter().find(|p| p.forced) {
+                forced.y
+            } else if bottom <= limit {
+                bottom
+            } else {
+                fitting
+                    .iter()
+                    .rfind(|point| !point.avoid)
+                    .or(fitting.last())
+                    .map_or(limit, |point| point.y)
+            };
+            pages.push(Rectangle {
+                y: start,
+                height: end - start,
+                ..area
+            });
+            start = end;
+        }
+        pages
+    }
+
+    // Collect the places where the content of the box may break onto the
+    // next page: between its lines, and between its block-level children
+    // and inside them. A forced break before the first child or after the
+    // last one applies to the box itself.
+    // SEE: https://drafts.csswg.org/css-break/#possible-breaks
+    fn collect_break_points(&self, avoid: bool, points: &mut Vec<BreakPoint>) {
+        let style = self.box_type.style();
+        let avoid = avoid || style.break_inside != Break::Auto;
+        let count = self.lines.len();
+        for (i, line) in self.lines.iter().enumerate().skip(1) {
+            let orphaned = i < style.orphans as usize;
+            let widowed = count - i < style.widows as usize;
+            points.push(BreakPoint {
+                y: line.rect.y,
+                forced: false,
+                avoid: avoid || orphaned || widowed,
+            });
+        }
+
+        let mut previous: Option<&LayoutBox> = None;
+        for child in &self.children {
+            if !child.is_in_flow_block_level() {
+                continue;
+            }
+            if let Some(previous) = previous {
+                let between = previous.break_after().max(child.break_before());
+                points.push(BreakPoint {
+                    y: child.dimensions.border_box().y,
+                    forced: between == Break::Page,
+                    avoid: avoid || between == Break::Avoid,
+                });
+            }
+            if child.is_fragmentable() {
+                child.collect_break_points(avoid, points);
+            }
+            previous = Some(child);
+        }
+    }
+
+    // Get the break before the box, which the first of its children passes
+    // on to it.
+    fn break_before(&self) -> Break {
+        let own = self.box_type.style().break_before;
+        let first = self
+            .children
+            .iter()
+            .find(|child| child.is_in_flow_block_level())
+            .filter(|_| self.is_fragmentable());
+        first.map_or(own, |first| own.max(first.break_before()))
+    }
+
+    // Get the break after the box, which the last of its children passes on
+    // to it.
+    fn break_after(&self) -> Break {
+        let own = self.box_type.style().break_after;
+        let last = self
+            .children
+            .iter()
+            .rfind(|child| child.is_in_flow_block_level())
+            .filter(|_| self.is_fragmentable());
+        last.map_or(own, |last| own.max(last.break_after()))
+    }
+
+    // Check if the content of the box is stacked vertically, so that it
+    // can break between pages. Other boxes are kept whole, and are only
+    // sliced if they are taller than a page.
+    fn is_fragmentable(&self) -> bool {
+        let stacked = match self.display() {
+            Display::Table
+            | Display::TableRowGroup
+            | Display::TableHeaderGroup
+            | Display::TableFooterGroup => true,
+            _ => self.is_block_container() && !self.is_multicol_container(),
+        };
+        stacked && !self.box_type.style().is_scroll_container()
+    }
+
+    // Dump the layout tree as text, one box per line, with its content
+    // rectangle and the edges that are not zero, followed by its lines and
+    // their text fragments. Inline boxes have no rectangle of their own.
+    // Lengths are rounded to hundredths, so that the output is stable and
+    // tests can compare it with golden files.
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.dump_into(&mut output, 0);
+        output
+    }
+
+    fn dump_into(&self, output: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let _ = match self.box_type {
+            BoxType::BlockNode(node) => {
+                write!(output, "{indent}block {}", node.label())
+            }
+            BoxType::InlineNode(node) => {
+                write!(output, "{indent}inline {}", node.label())
+            }
+            BoxType::InlineBlockNode(node) => {
+                write!(output, "{indent}inline-block {}", node.label())
+            }
+            BoxType::Anonymous(_) => write!(output, "{indent}anonymous"),
+        };
+        if !matches!(self.box_type, BoxType::InlineNode(_)) {
+            let d = &self.dimensions;
+            let _ = write!(output, " {}", dump_rect(d.content));
+            for (name, edge) in [
+                ("padding", d.padding),
+                ("border", d.border),
+                ("margin", d.margin),
+            ] {
+                if [edge.top, edge.right, edge.bottom, edge.left]
+                    .iter()
+                    .any(|&length| length != 0.0)
+                {
+                    let _ = write!(
+                        output,
+                        " {name} {} {} {} {}",
+                        round(edge.top),
+                        round(edge.right),
+                        round(edge.bottom),
+                        round(edge.left),
+                    );
+                }
+            }
+        }
+        output.push('\n');
+        for line in &self.lines {
+            let _ = writeln!(output, "{indent}  line {}", dump_rect(line.rect));
+            for fragment in &line.fragments {
+                let _ = writeln!(
+                    output,
+                    "{indent}    {:?} {}",
+                    fragment.text,
+                    dump_rect(fragment.rect),
+                );
+            }
+        }
+        for child in &self.children {
+            child.dump_into(output, depth + 1);
+        }
+    }
+
+    // Record the scrollable overflow of the box and its descendants, once
+    // they have been laid out. Returns the area that the box covers in the
+    // scrollable overflow of its parent, which is only its border box if
+    // it clips its content.
+    // TODO: Reserve space for the scrollbars of scroll containers.
+    fn compute_overflow(&mut self) -> Option<Rectangle> {
+        // Boxes without an area, such as table columns, don't extend it.
+        let mut overflow = None;
+        let mut add = |rect: Rectangle| {
+            if rect.width == 0.0 && rect.height == 0.0 {
+                return;
+            }
+            overflow =
+                Some(overflow.map_or(rect, |area: Rectangle| area.union(rect)));
+        };
+        // Text that doesn't wrap can overflow its line box.
+        for line in &self.lines {
+            add(line.rect);
+            for fragment in &line.fragments {
+                add(fragment.rect);
+            }
+        }
+        for child in &mut self.children {
+            if let Some(rect) = child.compute_overflow() {
+                add(rect);
+            }
+        }
+        // Inline boxes are not laid out themselves, so they only pass on
+        // the overflow of their content.
+        if let BoxType::InlineNode(_) = self.box_type {
+            return overflow;
+        }
+
+        let d = self.dimensions;
+        let scrollable = overflow.map_or(d.padding_box(), |overflow| {
+            d.padding_box().union(overflow)
+        });
+        self.dimensions.scrollable_overflow = scrollable;
+        let (max_x, max_y) = self.scroll_range();
+        self.scroll_offset = (
+            self.scroll_offset.0.min(max_x),
+            self.scroll_offset.1.min(max_y),
+        );
+        if self.clip_rect().is_some() {
+            Some(d.border_box())
+        } else {
+            Some(d.border_box().union(scrollable))
+        }
+    }
+
+    // Lay out a box whose margins don't collapse with the margins of any
+    // other box, such as a float or the root.
+    fn layout(
+        &mut self,
+        containing_block: Dimensions,
+        context: &mut LayoutContext,
+    ) {
+        self.layout_with_margin(containing_block, None, context);
+    }
+
+    // Lay out a box in normal flow, unless it is clean and would be laid
+    // out the same as the last time, in which case it is only moved to its
+    // new position.
+    fn layout_or_reuse(
+        &mut self,
+        containing_block: Dimensions,
+        margin: Option<CollapsedMargin>,
+        context: &mut LayoutContext,
+    ) -> CollapsedMargin {
+        match self.reuse_layout(containing_block, margin, context) {
+            Some(below) => below,
+            None => self.layout_with_margin(containing_block, margin, context),
+        }
+    }
+
+    // Move a clean box to the position that laying it out would give it,
+    // and return the margin below it, if its last layout is still valid.
+    // The layout of a box depends on the floats around it, unless it
+    // establishes a formatting context, and on the margin above it if that
+    // passes through to the margin below it.
+    fn reuse_layout(
+        &mut self,
+        containing_block: Dimensions,
+        margin: Option<CollapsedMargin>,
+        context: &LayoutContext,
+    ) -> Option<CollapsedMargin> {
+        let cache = self.cache?;
+        let valid = !self.needs_layout(containing_block.content.width, context)
+            && (cache.above == margin
+                || (cache.above.is_some() == margin.is_some()
+                    && !self.margins_pass_through()))
+            && (self.establishes_formatting_context()
+                || (!cache.floats && context.floats.is_empty()));
+        if !valid {
+            return None;
+        }
+
+        let current = self.dimensions.content;
+        let width = containing_block.content.width;
+        let margin = margin.map(|above| above.collapse(self.top_margin(width)));
+        self.calc_block_position(containing_block, margin);
+        let target = self.dimensions.content;
+        self.dimensions.content = current;
+        let (dx, dy) = self.relative_offset(width, context.containing_height);
+        self.translate(target.x + dx - current.x, target.y + dy - current.y);
+        self.dirty = Dirty::default();
+        Some(cache.below)
+    }
+
+    // Check if the box has to be laid out in a containing block of the
+    // given size, because it changed or was laid out in another size.
+    fn needs_layout(
+        &self,
+        containing_width: f32,
+        context: &LayoutContext,
+    ) -> bool {
+        self.dirty.layout
+            || self.dirty.children
+            || self.cache.is_none_or(|cache| {
+                cache.containing_width != containing_width
+                    || cache.containing_height != context.containing_height
+                    || cache.direction != context.direction
+            })
+    }
+
+    // Lay out a box. A block in normal flow is given the margin above it,
+    // which its top margin collapses with, and returns the margin below it,
+    // which the top margin of the next block collapses with.
+    fn layout_with_margin(
+        &mut self,
+        containing_block: Dimensions,
+        margin: Option<CollapsedMargin>,
+        context: &mut LayoutContext,
+    ) -> CollapsedMargin {
+        match self.box_type {
+            BoxType::BlockNode(_)
+            | BoxType::InlineBlockNode(_)
+            | BoxType::Anonymous(_) => {
+                let floats = !context.floats.is_empty();
+                let below = if self.establishes_formatting_context() {
+                    let floats = std::mem::take(&mut context.floats);
+                    let below =
+                        self.layout_block(containing_block, margin, context);
+                    context.floats = floats;
+                    below
+                } else {
+                    self.layout_block(containing_block, margin, context)
+                };
+                self.cache = Some(LayoutCache {
+                    containing_width: containing_block.content.width,
+                    containing_height: context.containing_height,
+                    direction: context.direction,
+                    above: margin,
+                    below,
+                    floats: floats || !context.floats.is_empty(),
+                });
+                // A relatively positioned box is moved after it has taken
+                // up its space in normal flow.
+                let (dx, dy) = self.relative_offset(
+                    containing_block.content.width,
+                    context.containing_height,
+                );
+                if (dx, dy) != (0.0, 0.0) {
+                    self.translate(dx, dy);
+                }
+                below
+            }
+            // Inline boxes are laid out by the line builder of their block
+            // container.
+            BoxType::InlineNode(_) => margin.unwrap_or_default(),
+        }
+    }
+
+    // Get the distance that a relatively positioned box is moved from its
+    // position in normal flow, in a containing block of the given size.
+    fn relative_offset(
+        &self,
+        containing_width: f32,
+        containing_height: Option<f32>,
+    ) -> (f32, f32) {
+        let style = self.box_type.style();
+        if style.position != Position::Relative {
+            return (0.0, 0.0);
+        }
+        let offset = style.offset;
+        let horizontal =
+            |length: LengthOrAuto| length.resolve(Some(containing_width));
+        let vertical = |length: LengthOrAuto| length.resolve(containing_height);
+        let dx = match horizontal(offset.left) {
+            LengthOrAuto::Px(left) => left,
+            _ => -horizontal(offset.right).to_px(),
+        };
+        let dy = match vertical(offset.top) {
+            LengthOrAuto::Px(top) => top,
+            _ => -vertical(offset.bottom).to_px(),
+        };
+        (dx, dy)
+    }
+
+    // Check if the top margin of the box adjoins the top margin of its
+    // first child in normal flow, because no border, padding, or new
+    // formatting context separates them.
+    fn top_margin_adjoins_children(&self) -> bool {
+        let style = self.box_type.style();
+        !matches!(self.box_type, BoxType::InlineNode(_))
+            && !self.is_replaced()
+            && !self.establishes_formatting_context()
+            && style.border_width.top == 0.0
+            && style.padding.top.is_zero()
+    }
+
+    // Check if the bottom margin of the box adjoins the bottom margin of
+    // its last child in normal flow, which also needs the box to be as
+    // tall as its content.
+    fn bottom_margin_adjoins_children(&self) -> bool {
+        let style = self.box_type.style();
+        !matches!(self.box_type, BoxType::InlineNode(_))
+            && !self.is_replaced()
+            && !self.establishes_formatting_context()
+            && style.border_width.bottom == 0.0
+            && style.padding.bottom.is_zero()
+            && style.height == LengthOrAuto::Auto
+    }
+
+    // Check if the top and bottom margins of the box adjoin, because it
+    // has no content between them.
+    fn collapses_through(&self) -> bool {
+        self.margins_pass_through()
+            && self.box_type.style().min_height.is_zero()
+    }
+
+    // Check if the margin above the box reaches the margin below it
+    // through the margins of its children, because none of them has
+    // content. Only then does the layout of the box depend on the margin
+    // above it for more than its position.
+    fn margins_pass_through(&self) -> bool {
+        self.top_margin_adjoins_children()
+            && self.bottom_margin_adjoins_children()
+            && self.children.iter().all(|child| {
+                child.is_float()
+                    || child.position().is_out_of_flow()
+                    || (!child.is_inline_level() && child.collapses_through())
+            })
+    }
+
+    // Get the top margin of the box, collapsed with the margins of its
+    // descendants that adjoin it. Percentages are resolved against the
+    // width of the containing block, which is assumed for the descendants
+    // too.
+    fn top_margin(&self, containing_width: f32) -> CollapsedMargin {
+        let style = self.box_type.style();
+        let mut margin = CollapsedMargin::new(
+            style.margin.top.resolve(Some(containing_width)).to_px(),
+        );
+        if !self.top_margin_adjoins_children() {
+            return margin;
+        }
+        for child in &self.children {
+            if child.is_float() || child.position().is_out_of_flow() {
+                continue;
+            }
+            if child.is_inline_level() {
+                break;
+            }
+            margin = margin.collapse(child.top_margin(containing_width));
+            if !child.collapses_through() {
+                break;
+            }
+            // The margins of an empty child adjoin the margins after it.
+            let bottom = child.box_type.style().margin.bottom;
+            margin =
+                margin.adjoin(bottom.resolve(Some(containing_width)).to_px());
+        }
+        margin
+    }
+
+    fn layout_block(
+        &mut self,
+        containing_block: Dimensions,
+        margin: Option<CollapsedMargin>,
+        context: &mut LayoutContext,
+    ) -> CollapsedMargin {
+        // Child width can depend on parent width,
+        // so we need to calculate the box's width before laying out its
+        // children.
+        // The width of a table fits its columns, and the width of a cell is
+        // the width of the columns it spans. The size of a replaced element
+        // is known from its image.
+        let replaced = self.is_replaced().then(|| {
+            self.replaced_size(
+                Some(containing_block.content.width),
+                context.containing_height,
+                context,
+            )
+        });
+        let width = match (self.display(), replaced) {
+            (_, Some((width, _))) => LengthOrAuto::Px(width),
+            (Display::Table | Display::InlineTable, _) => {
+                LengthOrAuto::Px(self.table_width(containing_block, context))
+            }
+            (Display::TableCell, _) => LengthOrAuto::Auto,
+            _ => self
+                .box_type
+                .style()
+                .width
+                .resolve(Some(containing_block.content.width)),
+        };
+        self.calc_block_width(containing_block, width, context);
+
+        // Determine where the box is located within the containing block.
+        // The top margin of a block in normal flow collapses with the margin
+        // above it.
+        let margin = margin.map(|above| {
+            above.collapse(self.top_margin(containing_block.content.width))
+        });
+        self.calc_block_position(containing_block, margin);
+
+        // A definite height is known before the children are laid out, and
+        // their percentage heights are resolved against it.
+        let height = match replaced {
+            Some((_, height)) => Some(height),
+            None => self.definite_height(context.containing_height),
+        };
+        let containing_height =
+            std::mem::replace(&mut context.containing_height, height);
+        let direction = std::mem::replace(
+            &mut context.direction,
+            self.box_type.style().direction,
+        );
+
+        // Recursively lay out the children of the box, replacing the lines
+        // of an earlier layout.
+        self.lines.clear();
+        let escaped = if self.is_replaced() {
+            None
+        } else if self.is_grid_container() {
+            self.layout_grid_children(context);
+            None
+        } else if self.is_table() {
+            self.layout_table_children(context);
+            None
+        } else if self.is_multicol_container() {
+            self.layout_columns(context);
+            None
+        } else {
+            let escaped = self.layout_block_children(margin, context);
+            self.layout_marker(context);
+            escaped
+        };
+
+        context.containing_height = containing_height;
+
+        // The root of a block formatting context grows to contain its
+        // floats.
+        if self.establishes_formatting_context() {
+            if let Some(bottom) = context.floats.bottom() {
+                let content = &mut self.dimensions.content;
+                content.height = content.height.max(bottom - content.y);
+            }
+        }
+
+        // Parent height can depend on child height,
+        // so we need to calculate the box's height after laying out its
+        // children.
+        self.calc_block_height(height, containing_height);
+
+        // A positioned box is the containing block of its absolutely
+        // positioned descendants, and a transformed box of its fixed ones
+        // too.
+        // SEE: https://drafts.csswg.org/css-transforms/#containing-block-for-all-descendants
+        if self.position() != Position::Static || self.is_transformed() {
+            let padding_box = self.dimensions.padding_box();
+            self.layout_positioned_descendants(padding_box, false, context);
+        }
+        if self.is_transformed() {
+            let padding_box = self.dimensions.padding_box();
+            self.layout_positioned_descendants(padding_box, true, context);
+        }
+        context.direction = direction;
+        self.dirty = Dirty::default();
+
+        // The margin below the last child collapses with the bottom margin
+        // of the box, if they adjoin.
+        let bottom = self
+            .box_type
+            .style()
+            .margin
+            .bottom
+            .resolve(Some(containing_block.content.width))
+            .to_px();
+        match escaped {
+            Some(margin) => margin.adjoin(bottom),
+            None => CollapsedMargin::new(bottom),
+        }
+    }
+
+    fn calc_block_width(
+        &mut self,
+        containing_block: Dimensions,
+        width: LengthOrAuto,
+        context: &LayoutContext,
+    ) {
+        // A width out of the bounds of `min-width` and `max-width` is
+        // resolved again as if the bound were specified, so that auto
+        // margins take up the difference.
+        // SEE: https://www.w3.org/TR/CSS22/visudet.html#min-max-widths
+        let style = self.box_type.style();
+        let basis = containing_block.content.width;
+        let min_width = style.min_width.to_px(basis);
+        let max_width = style.max_width.map(|max_width| max_width.to_px(basis));
+        let mut used = self.used_width(containing_block, width, context);
+        if let Some(max_width) = max_width {
+            if used.0 > max_width {
+                used = self.used_width(
+                    containing_block,
+                    LengthOrAuto::Px(max_width),
+                    context,
+                );
+            }
+        }
+        if used.0 < min_width {
+            used = self.used_width(
+                containing_block,
+                LengthOrAuto::Px(min_width),
+                context,
+            );
+        }
+
+        let style = self.box_type.style();
+        let (width, margin_left, margin_right) = used;
+        let d = &mut self.dimensions;
+        d.content.width = width;
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
+        d.padding.left = style.padding.left.to_px(basis);
+        d.padding.right = style.padding.right.to_px(basis);
+        d.border.left = style.border_width.left;
+        d.border.right = style.border_width.right;
+    }
+
+    // Resolve the width and the horizontal margins of a block from the
+    // width of its containing block.
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#blockwidth
+    fn used_width(
+        &self,
+        containing_block: Dimensions,
+        mut width: LengthOrAuto,
+        context: &LayoutContext,
+    ) -> (f32, f32, f32) {
+        let style = self.box_type.style();
+
+        // `width` has initial value `auto`.
+        let auto = LengthOrAuto::Auto;
+
+        // Percentages are resolved against the width of the containing
+        // block.
+        let basis = containing_block.content.width;
+        let mut margin_left = style.margin.left.resolve(Some(basis));
+        let mut margin_right = style.margin.right.resolve(Some(basis));
+
+        let padding_left = style.padding.left.to_px(basis);
+        let padding_right = style.padding.right.to_px(basis);
+
+        let border_left = style.border_width.left;
+        let border_right = style.border_width.right;
+
+        // Widths that fit the content are resolved from its intrinsic
+        // widths, and `fit-content` shrinks to fit the space left by the
+        // margins, borders, and padding.
+        width = match width {
+            LengthOrAuto::MinContent => {
+                LengthOrAuto::Px(self.content_widths(context).0)
+            }
+            LengthOrAuto::MaxContent => {
+                LengthOrAuto::Px(self.content_widths(context).1)
+            }
+            LengthOrAuto::FitContent => {
+                let available = basis
+                    - margin_left.to_px()
+                    - margin_right.to_px()
+                    - padding_left
+                    - padding_right
+                    - border_left
+                    - border_right;
+                LengthOrAuto::Px(self.shrink_to_fit(available, context))
+            }
+            width => width,
+        };
+
+        let total = sum([
+            margin_left.to_px(),
+            margin_right.to_px(),
+            padding_left,
+            padding_right,
+            border_left,
+            border_right,
+            width.to_px(),
+        ]
+        .into_iter());
+
+        if width != auto && total > containing_block.content.width {
+            if margin_left == auto {
+                margin_left = LengthOrAuto::Px(0.0);
+            }
+            if margin_right == auto {
+                margin_right = LengthOrAuto::Px(0.0);
+            }
+        }
+
+        let underflow = containing_block.content.width - total;
+
+        // Floats and inline-blocks are not stretched to fill the containing
+        // block by their margins, and their auto margins are zero. Tables
+        // can still be centered by their margins.
+        // SEE: https://www.w3.org/TR/CSS22/visudet.html#float-width
+        let shrinks = self.establishes_formatting_context() && !self.is_table();
+        if shrinks {
+            if margin_left == auto {
+                margin_left = LengthOrAuto::Px(0.0);
+            }
+            if margin_right == auto {
+                margin_right = LengthOrAuto::Px(0.0);
+            }
+        }
+
+        match (width == auto, margin_left == auto, margin_right == auto) {
+            (false, _, _) if shrinks => {}
+            // If values are defined, adjust the margin at the end of the
+            // containing block's direction by the underflow.
+            (false, false, false) => match context.direction {
+                Direction::Ltr => {
+                    margin_right =
+                        LengthOrAuto::Px(margin_right.to_px() + underflow);
+                }
+                Direction::Rtl => {
+                    margin_left =
+                        LengthOrAuto::Px(margin_left.to_px() + underflow);
+                }
+            },
+            // If only margin_right is auto, set underflow to it.
+            (false, false, true) => {
+                margin_right = LengthOrAuto::Px(underflow);
+            }
+            // If only margin_left is auto, set underflow to it.
+            (false, true, false) => {
+                margin_left = LengthOrAuto::Px(underflow);
+            }
+            // If both margins are auto, set them to half of the underflow.
+            (false, true, true) => {
+                margin_left = LengthOrAuto::Px(underflow / 2.0);
+                margin_right = LengthOrAuto::Px(underflow / 2.0);
+            }
+            // If width is auto, any other auto values become 0.
+            (true, _, _) => {
+                if margin_left == auto {
+                    margin_left = LengthOrAuto::Px(0.0);
+                }
+                if margin_right == auto {
+                    margin_right = LengthOrAuto::Px(0.0);
+                }
+
+                if self.is_float()
+                    || matches!(self.box_type, BoxType::InlineBlockNode(_))
+                {
+                    // Floats and inline-blocks shrink to fit their content.
+                    width = LengthOrAuto::Px(
+                        self.shrink_to_fit(underflow, context),
+                    );
+                } else if underflow >= 0.0 {
+                    // Expand width to fill the underflow.
+                    width = LengthOrAuto::Px(underflow);
+                } else {
+                    // Width can't be negative, so adjust the margin_right
+                    // instead.
+                    width = LengthOrAuto::Px(0.0);
+                    margin_right =
+                        LengthOrAuto::Px(margin_right.to_px() + underflow);
+                }
+            }
+        }
+
+        (width.to_px(), margin_left.to_px(), margin_right.to_px())
+    }
+
+    // Get the used width and height of a replaced element. A size that is
+    // not specified follows the natural size of the image, scaled to keep
+    // its aspect ratio, and an image without a natural size is 300 by 150
+    // pixels. Bounds that change one dimension scale an `auto` other one.
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#inline-replaced-width
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#inline-replaced-height
+    // TODO: Follow the full table of constraint violations in
+    // https://www.w3.org/TR/CSS22/visudet.html#min-max-widths
+    fn replaced_size(
+        &self,
+        containing_width: Option<f32>,
+        containing_height: Option<f32>,
+        context: &LayoutContext,
+    ) -> (f32, f32) {
+        let style = self.box_type.style();
+        let node = self.get_style_node().node;
+        let natural = context
+            .images
+            .natural_size(node)
+            .or_else(|| Svg::from_node(node)?.natural_size());
+        let ratio = natural
+            .filter(|&(width, height)| width > 0.0 && height > 0.0)
+            .map(|(width, height)| width / height);
+        let (natural_width, natural_height) = natural.unwrap_or((300.0, 150.0));
+
+        let specified_width = style.width.resolve(containing_width);
+        let specified_height = style.height.resolve(containing_height);
+        let (mut width, mut height) = match (specified_width, specified_height)
+        {
+            (LengthOrAuto::Px(width), LengthOrAuto::Px(height)) => {
+                (width, height)
+            }
+            (LengthOrAuto::Px(width), _) => {
+                (width, ratio.map_or(natural_height, |ratio| width / ratio))
+            }
+            (_, LengthOrAuto::Px(height)) => {
+                (ratio.map_or(natural_width, |ratio| height * ratio), height)
+            }
+            _ => (natural_width, natural_height),
+        };
+
+        let min_width =
+            style.min_width.resolve(containing_width).unwrap_or(0.0);
+        let max_width = style
+            .max_width
+            .and_then(|max_width| max_width.resolve(containing_width));
+        let bounded = max_width
+            .map_or(width, |max_width| width.min(max_width))
+            .max(min_width);
+        if bounded != width {
+            width = bounded;
+            if let (LengthOrAuto::Auto, Some(ratio)) = (specified_height, ratio)
+            {
+                height = width / ratio;
+            }
+        }
+        let bounded = self.clamp_height(height, containing_height);
+        if bounded != height {
+            height = bounded;
+            if let (LengthOrAuto::Auto, Some(ratio)) = (specified_width, ratio)
+            {
+                width = height * ratio;
+            }
+        }
+        (width, height)
+    }
+
+    // Place the box below the content of its containing block laid out so
+    // far, separated by its top margin, or by the collapsed margin above
+    // it in normal flow.
+    fn calc_block_position(
+        &mut self,
+        containing_block: Dimensions,
+        margin: Option<CollapsedMargin>,
+    ) {
+        let style = self.box_type.style();
+        // Vertical margins and paddings are also percentages of the width.
+        let basis = containing_block.content.width;
+        let top = match margin {
+            Some(margin) => margin.resolve(),
+            None => style.margin.top.resolve(Some(basis)).to_px(),
+        };
+
+        let d = &mut self.dimensions;
+        d.margin.top = style.margin.top.resolve(Some(basis)).to_px();
+        d.margin.bottom = style.margin.bottom.resolve(Some(basis)).to_px();
+        d.border.top = style.border_width.top;
+        d.border.bottom = style.border_width.bottom;
+        d.padding.top = style.padding.top.to_px(basis);
+        d.padding.bottom = style.padding.bottom.to_px(basis);
+        d.content.x = containing_block.content.x
+            + d.margin.left
+            + d.border.left
+            + d.padding.left;
+        d.content.y = containing_block.content.height
+            + containing_block.content.y
+            + top
+            + d.border.top
+            + d.padding.top;
+    }
+
+    // Lay out the children of a block container below each other, given
+    // the margin above the container if it is in normal flow.
+    // Returns the margin below the last child if it collapses with the
+    // bottom margin of the container.
+    // SEE: https://www.w3.org/TR/CSS22/box.html#collapsing-margins
+    fn layout_block_children(
+        &mut self,
+        above: Option<CollapsedMargin>,
+        context: &mut LayoutContext,
+    ) -> Option<CollapsedMargin> {
+        let content = self.dimensions.content;
+        // The margin above the next child, and the bottom edge of the
+        // content laid out so far. The top margin of the first child that
+        // adjoins the container's has already moved the container down.
+        let (mut margin, mut y) = match above {
+            Some(above) if self.top_margin_adjoins_children() => {
+                (above, content.y - above.resolve())
+            }
+            _ => (CollapsedMargin::default(), content.y),
+        };
+
+        #[cfg(feature = "parallel")]
+        self.layout_children_in_parallel(context);
+
+        // Inline children and the floats and absolutely positioned boxes
+        // among them are laid out by the line builder.
+        let in_line = |child: &LayoutBox| {
+            child.is_inline_level()
+                || child.is_float()
+                || child.position().is_out_of_flow()
+        };
+        let mut index = 0;
+        while index < self.children.len() {
+            if in_line(&self.children[index]) {
+                // Lay out the run of inline children as lines, which
+                // separate the margins around them.
+                let end = self.children[index..]
+                    .iter()
+                    .position(|child| !in_line(child))
+                    .map_or(self.children.len(), |n| index + n);
+                self.dimensions.content.height =
+                    y + margin.resolve() - content.y;
+                let lines = self.layout_inline_children(index..end, context);
+                if let Some(last) = lines.last() {
+                    y = last.rect.y + last.rect.height;
+                    margin = CollapsedMargin::default();
+                }
+                self.lines.extend(lines);
+                index = end;
+                continue;
+            }
+
+            let child = &mut self.children[index];
+            // A box that clears floats is moved below them, and its top
+            // margin no longer collapses with the margin above it.
+            let mut above = margin;
+            let style = child.box_type.style();
+            if let Some(bottom) = context.floats.clearance(style.clear) {
+                let top_margin = child.top_margin(content.width);
+                if y + margin.collapse(top_margin).resolve() < bottom {
+                    y = bottom - top_margin.resolve();
+                    above = CollapsedMargin::default();
+                }
+            }
+            self.dimensions.content.height = y - content.y;
+            margin =
+                child.layout_or_reuse(self.dimensions, Some(above), context);
+            // The next child is laid out below this one, unless its margins
+            // collapse through it.
+            if !child.collapses_through() {
+                let border_box = child.dimensions.border_box();
+                let (_, dy) = child
+                    .relative_offset(content.width, context.containing_height);
+                y = border_box.y + border_box.height - dy;
+            }
+            index += 1;
+        }
+
+        if above.is_some() && self.bottom_margin_adjoins_children() {
+            self.dimensions.content.height = (y - content.y).max(0.0);
+            Some(margin)
+        } else {
+            self.dimensions.content.height =
+                (y + margin.resolve() - content.y).max(0.0);
+            None
+        }
+    }
+
+    // Check if the content of the box is split into columns.
+    fn is_multicol_container(&self) -> bool {
+        let style = self.box_type.style();
+        (style.column_count.is_some() || style.column_width.is_some())
+            && self.is_block_container()
+    }
+
+    // Lay out the content of a multi-column container in a single column
+    // as wide as its columns, and then split it into columns of about the
+    // same height, between its lines and block-level children.
+    // TODO: Break inside block-level children, and support `column-span`
+    // and `column-fill`.
+    // SEE: https://drafts.csswg.org/css-multicol/#pseudo-algorithm
+    fn layout_columns(&mut self, context: &mut LayoutContext) {
+        let style = self.box_type.style();
+        let content = self.dimensions.content;
+        let gap = style.column_gap.unwrap_or(style.font_size);
+        let (count, width) = match (style.column_count, style.column_width) {
+            (Some(count), None) => (count, None),
+            (count, Some(width)) => {
+                let fit = ((content.width + gap) / (width + gap)).floor();
+                let fit = (fit as u32).max(1);
+                (count.map_or(fit, |count| count.min(fit)), Some(width))
+            }
+            (None, None) => (1, None),
+        };
+        let column_width = match width {
+            // A single column is narrowed to the width of the container.
+            Some(width) if count == 1 => width.min(content.width),
+            _ => ((content.width + gap) / count as f32 - gap).max(0.0),
+        };
+
+        self.dimensions.content.width = column_width;
+        self.layout_block_children(None, context);
+        self.dimensions.content.width = content.width;
+        if count > 1 {
+            self.balance_columns(count as usize, column_width + gap);
+            // The floats were moved into the columns with the content
+            // around them, and have been taken into account.
+            context.floats = FloatContext::default();
+        }
+    }
+
+    // Move the lines and the block-level children of a box laid out in a
+    // single column into columns, which are `pitch` apart. The shortest
+    // column height that fits the content into `count` columns is found by
+    // trying the heights at which the content would break otherwise.
+    fn balance_columns(&mut self, count: usize, pitch: f32) {
+        enum Item {
+            Line(usize),
+            Child(usize),
+        }
+        // Each item with where a column that starts with it begins, and its
+        // bottom edge. The margins of a block at the top of a column are
+        // truncated.
+        let top = self.dimensions.content.y;
+        let mut items: Vec<_> = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                (Item::Line(i), line.rect.y, line.rect.y + line.rect.height)
+            })
+            .chain(
+                self.children
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, child)| child.is_in_flow_block_level())
+                    .map(|(i, child)| {
+                        let margin_box = child.dimensions.margin_box();
+                        (
+                            Item::Child(i),
+                            child.dimensions.border_box().y,
+                            margin_box.y + margin_box.height,
+                        )
+                    }),
+            )
+            .collect();
+        items.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let Some(&(_, _, bottom)) = items.last() else {
+            return;
+        };
+
+        // Get the index of the first item of each column, when columns are
+        // at most `height` tall, and the next taller height that would
+        // break the content less.
+        let columns = |height: f32| {
+            let mut firsts = vec![0];
+            let mut start = top;
+            let mut next = f32::INFINITY;
+            for (i, &(_, item_start, end)) in items.iter().enumerate() {
+                if end - start > height && firsts.last() != Some(&i) {
+                    next = next.min(end - start);
+                    firsts.push(i);
+                    start = item_start;
+                }
+            }
+            (firsts, next)
+        };
+        let mut height = (bottom - top) / count as f32;
+        let firsts = loop {
+            let (firsts, next) = columns(height);
+            if firsts.len() <= count || next.is_infinite() {
+                break firsts;
+            }
+            height = next;
+        };
+
+        // The offset of the content from each item on, by where the item
+        // started.
+        let mut shifts = Vec::new();
+        for (column, window) in firsts.iter().enumerate() {
+            let first = &items[*window];
+            let dy = if column == 0 { 0.0 } else { top - first.1 };
+            shifts.push((first.1, column as f32 * pitch, dy));
+        }
+        let shift_at = |y: f32| {
+            shifts
+                .iter()
+                .rev()
+                .find(|&&(start, _, _)| start <= y)
+                .map_or((0.0, 0.0), |&(_, dx, dy)| (dx, dy))
+        };
+
+        let mut bottom = top;
+        for &(ref item, start, end) in &items {
+            let (dx, dy) = shift_at(start);
+            match *item {
+                Item::Line(i) => self.lines[i].translate(dx, dy),
+                Item::Child(i) => self.children[i].translate(dx, dy),
+            }
+            bottom = bottom.max(end + dy);
+        }
+        // Floats and atomic inlines on the lines move with them.
+        for child in &mut self.children {
+            if !child.is_in_flow_block_level() {
+                bottom = bottom.max(child.translate_into_columns(&shift_at));
+            }
+        }
+        self.dimensions.content.height = bottom - top;
+    }
+
+    // Move an inline-level box, a float, or an absolutely positioned box
+    // into the column of the content it was placed with, returning the
+    // bottom edge of the floats and atomic inlines moved.
+    fn translate_into_columns(
+        &mut self,
+        shift_at: &impl Fn(f32) -> (f32, f32),
+    ) -> f32 {
+        match self.box_type {
+            BoxType::InlineNode(_) => self
+                .children
+                .iter_mut()
+                .map(|child| child.translate_into_columns(shift_at))
+                .fold(f32::MIN, f32::max),
+            _ if self.position().is_out_of_flow() => {
+                let (dx, dy) = shift_at(self.static_position.1);
+                self.translate(dx, dy);
+                f32::MIN
+            }
+            _ => {
+                let margin_box = self.dimensions.margin_box();
+                let (dx, dy) = shift_at(margin_box.y);
+                self.translate(dx, dy);
+                margin_box.y + margin_box.height + dy
+            }
+        }
+    }
+
+    // Lay out the block-level children that need it on several threads,
+    // each at the top of the box and below a zero margin, once the width
+    // of the box is known. They are moved into place in order afterwards,
+    // and a child that turns out to depend on its siblings, through floats
+    // or the margins around it, is laid out again then.
+    #[cfg(feature = "parallel")]
+    fn layout_children_in_parallel(&mut self, context: &LayoutContext) {
+        use rayon::prelude::*;
+
+        let Some(parallel) = context.parallel else {
+            return;
+        };
+        if !context.floats.is_empty() {
+            return;
+        }
+        let containing_block = Dimensions {
+            content: Rectangle {
+                height: 0.0,
+                ..self.dimensions.content
+            },
+            ..self.dimensions
+        };
+        let (containing_height, direction) =
+            (context.containing_height, context.direction);
+        let mut children: Vec<_> = self
+            .children
+            .iter_mut()
+            .filter(|child| {
+                !child.is_inline_level()
+                    && !child.is_float()
+                    && !child.position().is_out_of_flow()
+                    && child
+                        .needs_layout(containing_block.content.width, context)
+            })
+            .collect();
+        if children.len() < 2 {
+            return;
+        }
+        children.par_iter_mut().for_each(|child| {
+            let mut context = LayoutContext::new(parallel.metrics);
+            context.images = parallel.images;
+            context.parallel = Some(parallel);
+            context.containing_height = containing_height;
+            context.direction = direction;
+            child.layout_with_margin(
+                containing_block,
+                Some(CollapsedMargin::default()),
+                &mut context,
+            );
+        });
+    }
+
+    // Lay out the children of a grid container in the areas of its grid.
+    // Items are placed by their lines, or else automatically in row-major
+    // order, and `auto` rows grow to fit their items.
+    // SEE: https://www.w3.org/TR/css-grid-1/#layout-algorithm
+    // TODO: Support `grid-auto-flow`, alignment, and named lines.
+    fn layout_grid_children(&mut self, context: &mut LayoutContext) {
+        let style = self.box_type.style();
+        let content = self.dimensions.content;
+        let column_gap = style.column_gap.unwrap_or(0.0);
+
+        // Text between the items is not laid out, and boxes out of normal
+        // flow are laid out later.
+        // TODO: Wrap text in anonymous grid items.
+        let mut items = Vec::new();
+        for (index, child) in self.children.iter_mut().enumerate() {
+            let is_text = matches!(
+                child.box_type,
+                BoxType::InlineNode(node) if matches!(node.node.node_type, NodeType::Text(_))
+            );
+            if child.position().is_out_of_flow() {
+                child.static_position = (content.x, content.y);
+            } else if !is_text {
+                items.push(index);
+            }
+        }
+        let placements = place_grid_items(
+            style,
+            items
+                .iter()
+                .map(|&index| self.children[index].box_type.style()),
+        );
+
+        // Size the columns, and lay out each item in the width of its
+        // columns.
+        let column_count = placements
+            .iter()
+            .map(|area| area.column + area.column_span)
+            .fold(style.grid_template_columns.len().max(1), usize::max);
+        let columns = size_tracks(
+            &style.grid_template_columns,
+            column_count,
+            content.width,
+            column_gap,
+        );
+        let column_x = track_offsets(&columns, content.x, column_gap);
+        for (&index, area) in items.iter().zip(&placements) {
+            let containing_block = Dimensions {
+                content: Rectangle {
+                    x: column_x[area.column],
+                    y: content.y,
+                    width: span_size(
+                        &columns,
+                        area.column,
+                        area.column_span,
+                        column_gap,
+                    ),
+                    height: 0.0,
+                },
+                ..Default::default()
+            };
+            // Grid items establish their own formatting context.
+            let floats = std::mem::take(&mut context.floats);
+            self.children[index].layout_block(containing_block, None, context);
+            context.floats = floats;
+        }
+
+        // Size the rows. A definite height of the container is shared by
+        // `fr` rows, which otherwise grow to fit their items like `auto`
+        // rows.
+        let row_count = placements
+            .iter()
+            .map(|area| area.row + area.row_span)
+            .fold(style.grid_template_rows.len(), usize::max);
+        // The definite height of the container, if any, is the height that
+        // its children are laid out in.
+        let height = context.containing_height;
+        let templates: Vec<TrackSize> = style
+            .grid_template_rows
+            .iter()
+            .map(|&track| match (track, height) {
+                (TrackSize::Fr(_) | TrackSize::Percent(_), None) => {
+                    TrackSize::Auto
+                }
+                (track, _) => track,
+            })
+            .collect();
+        let mut rows = size_tracks(
+            &templates,
+            row_count,
+            height.unwrap_or(0.0),
+            style.row_gap,
+        );
+        let is_auto = |row: usize| {
+            templates
+                .get(row)
+                .is_none_or(|&track| track == TrackSize::Auto)
+        };
+        // Items spanning several rows grow the last `auto` row they span,
+        // once the rows of the other items are sized.
+        for spanning in [false, true] {
+            for (&index, area) in items.iter().zip(&placements) {
+                if (area.row_span > 1) != spanning {
+                    continue;
+                }
+                let needed =
+                    self.children[index].dimensions.margin_box().height;
+                let available =
+                    span_size(&rows, area.row, area.row_span, style.row_gap);
+                let row = (area.row..area.row + area.row_span)
+                    .rev()
+                    .find(|&row| is_auto(row));
+                if let (Some(row), true) = (row, needed > available) {
+                    rows[row] += needed - available;
+                }
+            }
+        }
+        let row_y = track_offsets(&rows, content.y, style.row_gap);
+
+        // Move each item to its row, and stretch items without a height to
+        // fill their area.
+        for (&index, area) in items.iter().zip(&placements) {
+            let item = &mut self.children[index];
+            let area_height =
+                span_size(&rows, area.row, area.row_span, style.row_gap);
+            let margin_box = item.dimensions.margin_box();
+            if item.box_type.style().height == LengthOrAuto::Auto {
+                let edges = margin_box.height - item.dimensions.content.height;
+                item.dimensions.content.height = item.clamp_height(
+                    (area_height - edges).max(item.dimensions.content.height),
+                    Some(area_height),
+                );
+            }
+            item.translate(0.0, row_y[area.row] - margin_box.y);
+        }
+
+        self.dimensions.content.height =
+            span_size(&rows, 0, rows.len(), style.row_gap);
+    }
+
+    // Get the rows of a table, as the index of each row or its row group
+    // among the children of the table, and the index of the row in its
+    // group. Header rows come first, and footer rows last.
+    fn table_rows(&self) -> Vec<(usize, Option<usize>)> {
+        let mut rows = Vec::new();
+        for (index, child) in self.children.iter().enumerate() {
+            match child.display() {
+                Display::TableRow => rows.push((index, None)),
+                display if display.is_table_row_group() => rows.extend(
+                    (0..child.children.len()).map(|row| (index, Some(row))),
+                ),
+                _ => {}
+            }
+        }
+        rows.sort_by_key(|&(index, _)| match self.children[index].display() {
+            Display::TableHeaderGroup => 0,
+            Display::TableFooterGroup => 2,
+            _ => 1,
+        });
+        rows
+    }
+
+    fn table_row(&self, (index, row): (usize, Option<usize>)) -> &Self {
+        match row {
+            Some(row) => &self.children[index].children[row],
+            None => &self.children[index],
+        }
+    }
+
+    fn table_row_mut(
+        &mut self,
+        (index, row): (usize, Option<usize>),
+    ) -> &mut Self {
+        match row {
+            Some(row) => &mut self.children[index].children[row],
+            None => &mut self.children[index],
+        }
+    }
+
+    // Get the number of columns that a table cell spans, from its `colspan`
+    // attribute.
+    fn column_span(&self) -> usize {
+        let BoxType::BlockNode(node) = self.box_type else {
+            return 1;
+        };
+        match &node.node.node_type {
+            NodeType::Element(element) => element
+                .attributes
+                .get("colspan")
+                .and_then(|span| span.trim().parse::<usize>().ok())
+                .map_or(1, |span| span.clamp(1, 1000)),
+            _ => 1,
+        }
+    }
+
+    // Measure the minimum and maximum widths of the columns of a table,
+    // from the cells in them. Cells that span several columns widen them
+    // evenly, if they don't fit already.
+    // SEE: https://www.w3.org/TR/CSS22/tables.html#auto-table-layout
+    fn column_widths(&self, context: &LayoutContext) -> Vec<(f32, f32)> {
+        let spacing = self.box_type.style().border_spacing.0;
+        let mut columns: Vec<(f32, f32)> = Vec::new();
+        let mut spanning = Vec::new();
+        for path in self.table_rows() {
+            let mut column = 0;
+            for cell in &self.table_row(path).children {
+                let span = cell.column_span();
+                let widths = cell.intrinsic_widths(context);
+                if columns.len() < column + span {
+                    columns.resize(column + span, (0.0, 0.0));
+                }
+                if span == 1 {
+                    let (min, max) = &mut columns[column];
+                    *min = min.max(widths.0);
+                    *max = max.max(widths.1);
+                } else {
+                    spanning.push((column, span, widths));
+                }
+                column += span;
+            }
+        }
+
+        for (column, span, (min, max)) in spanning {
+            let columns = &mut columns[column..column + span];
+            let gaps = spacing * (span - 1) as f32;
+            let extra_min =
+                min - gaps - columns.iter().map(|(min, _)| min).sum::<f32>();
+            let extra_max =
+                max - gaps - columns.iter().map(|(_, max)| max).sum::<f32>();
+            for (column_min, column_max) in columns {
+                *column_min += extra_min.max(0.0) / span as f32;
+                *column_max += extra_max.max(0.0) / span as f32;
+                *column_max = column_max.max(*column_min);
+            }
+        }
+        columns
+    }
+
+    // Check if the columns of the table are sized by its first row, which
+    // needs a table with a definite width.
+    // SEE: https://www.w3.org/TR/CSS22/tables.html#fixed-table-layout
+    fn uses_fixed_table_layout(&self) -> bool {
+        let style = self.box_type.style();
+        style.table_layout == TableLayout::Fixed
+            && style.width != LengthOrAuto::Auto
+    }
+
+    // Get the minimum and maximum widths of the content of a table: its
+    // columns and the spacing around them.
+    fn table_content_widths(&self, context: &LayoutContext) -> (f32, f32) {
+        let columns = self.column_widths(context);
+        let spacing = self.box_type.style().border_spacing.0;
+        let gaps = spacing * (columns.len() + 1) as f32;
+        (
+            columns.iter().map(|(min, _)| min).sum::<f32>() + gaps,
+            columns.iter().map(|(_, max)| max).sum::<f32>() + gaps,
+        )
+    }
+
+    // Get the used width of the content box of a table. A table without a
+    // width is as wide as its content, but no wider than its containing
+    // block, and no table is narrower than its content.
+    fn table_width(
+        &self,
+        containing_block: Dimensions,
+        context: &LayoutContext,
+    ) -> f32 {
+        let style = self.box_type.style();
+        let basis = containing_block.content.width;
+        let width = style.width.resolve(Some(basis));
+        if let (true, LengthOrAuto::Px(width)) =
+            (self.uses_fixed_table_layout(), width)
+        {
+            return width;
+        }
+        let (min, max) = self.table_content_widths(context);
+        match width {
+            LengthOrAuto::Px(width) => width.max(min),
+            _ => {
+                let edges = style.margin.left.resolve(Some(basis)).to_px()
+                    + style.margin.right.resolve(Some(basis)).to_px()
+                    + style.padding.left.to_px(basis)
+                    + style.padding.right.to_px(basis)
+                    + style.border_width.left
+                    + style.border_width.right;
+                (basis - edges).min(max).max(min)
+            }
+        }
+    }
+
+    // Size the columns of a table to share its content width.
+    fn size_table_columns(&self, context: &LayoutContext) -> Vec<f32> {
+        let spacing = self.box_type.style().border_spacing.0;
+        let column_count = self
+            .table_rows()
+            .into_iter()
+            .map(|path| {
+                let row = self.table_row(path);
+                row.children.iter().map(LayoutBox::column_span).sum()
+            })
+            .max()
+            .unwrap_or(0);
+        let width = self.dimensions.content.width;
+        let space = width - spacing * (column_count + 1) as f32;
+
+        if self.uses_fixed_table_layout() {
+            // The cells of the first row with a width give the width of
+            // their columns, and the other columns share the rest.
+            let mut columns = vec![None; column_count];
+            if let Some(&path) = self.table_rows().first() {
+                let mut column = 0;
+                for cell in &self.table_row(path).children {
+                    let span = cell.column_span();
+                    let style = cell.box_type.style();
+                    if let LengthOrAuto::Px(cell_width) =
+                        style.width.resolve(Some(width))
+                    {
+                        let width = cell_width
+                            + style.padding.left.to_px(width)
+                            + style.padding.right.to_px(width)
+                            + style.border_width.left
+                            + style.border_width.right
+                            - spacing * (span - 1) as f32;
+                        columns[column..column + span]
+                            .fill(Some(width / span as f32));
+                    }
+                    column += span;
+                }
+            }
+            let fixed: f32 = columns.iter().flatten().sum();
+            let auto = columns.iter().filter(|width| width.is_none()).count();
+            let share = (space - fixed).max(0.0) / auto.max(1) as f32;
+            return columns
+                .into_iter()
+                .map(|width| width.unwrap_or(share))
+                .collect();
+        }
+
+        // Columns grow from their minimum towards their maximum width in
+        // proportion to the difference, and beyond it in proportion to the
+        // maximum width.
+        let columns = self.column_widths(context);
+        let min: f32 = columns.iter().map(|(min, _)| min).sum();
+        let max: f32 = columns.iter().map(|(_, max)| max).sum();
+        columns
+            .iter()
+            .map(|&(column_min, column_max)| {
+                if space <= min {
+                    column_min
+                } else if space <= max {
+                    column_min
+                        + (column_max - column_min) * (space - min)
+                            / (max - min)
+                } else if max > 0.0 {
+                    column_max * space / max
+                } else {
+                    space / columns.len() as f32
+                }
+            })
+            .collect()
+    }
+
+    // Lay out the rows of a table, whose cells are placed in its columns
+    // and separated by its border spacing. Captions are placed above the
+    // rows.
+    // SEE: https://www.w3.org/TR/CSS22/tables.html
+    // TODO: Support `rowspan`, collapsing borders, and `vertical-align` in
+    // cells.
+    fn layout_table_children(&mut self, context: &mut LayoutContext) {
+        let (horizontal, vertical) = self.box_type.style().border_spacing;
+        let columns = self.size_table_columns(context);
+
+        for child in &mut self.children {
+            if child.display() == Display::TableCaption {
+                child.layout(self.dimensions, context);
+                self.dimensions.content.height +=
+                    child.dimensions.margin_box().height;
+            }
+        }
+
+        let content = self.dimensions.content;
+        let column_x =
+            track_offsets(&columns, content.x + horizontal, horizontal);
+        let rows = self.table_rows();
+        let mut y = content.y + content.height + vertical;
+        for &path in &rows {
+            let row = self.table_row_mut(path);
+            let mut height = row
+                .box_type
+                .style()
+                .height
+                .resolve(context.containing_height)
+                .to_px();
+            let mut column = 0;
+            for cell in &mut row.children {
+                let span = cell.column_span();
+                let containing_block = Dimensions {
+                    content: Rectangle {
+                        x: column_x[column],
+                        y,
+                        width: span_size(&columns, column, span, horizontal),
+                        height: 0.0,
+                    },
+                    ..Default::default()
+                };
+                cell.layout(containing_block, context);
+                height = height.max(cell.dimensions.margin_box().height);
+                column += span;
+            }
+            // Cells are stretched to the height of their row.
+            for cell in &mut row.children {
+                cell.dimensions.content.height +=
+                    height - cell.dimensions.margin_box().height;
+            }
+            row.dimensions.content = Rectangle {
+                x: content.x + horizontal,
+                y,
+                width: (content.width - 2.0 * horizontal).max(0.0),
+                height,
+            };
+            y += height + vertical;
+        }
+
+        // Row groups enclose their rows.
+        for group in &mut self.children {
+            if !group.display().is_table_row_group() {
+                continue;
+            }
+            let (Some(first), Some(last)) =
+                (group.children.first(), group.children.last())
+            else {
+                continue;
+            };
+            let (first, last) =
+                (first.dimensions.content, last.dimensions.content);
+            group.dimensions.content = Rectangle {
+                height: last.y + last.height - first.y,
+                ..first
+            };
+        }
+
+        if !rows.is_empty() {
+            self.dimensions.content.height = y - content.y;
+        }
+    }
+
+    // Get the min-content and max-content widths of the margin box: the
+    // narrowest it can be without its content overflowing, and the width
+    // it takes up without breaking lines.
+    // Percentages of the unknown containing block width count as `auto`,
+    // or as zero.
+    // SEE: https://www.w3.org/TR/css-sizing-3/#intrinsic-sizes
+    fn intrinsic_widths(&self, context: &LayoutContext) -> (f32, f32) {
+        let style = self.box_type.style();
+        let edges = style.margin.left.to_px()
+            + style.margin.right.to_px()
+            + style.padding.left.resolve(None).unwrap_or(0.0)
+            + style.padding.right.resolve(None).unwrap_or(0.0)
+            + style.border_width.left
+            + style.border_width.right;
+        let (min, max) = match (self.display(), style.width) {
+            _ if self.is_replaced() => {
+                let (width, _) = self.replaced_size(None, None, context);
+                (width, width)
+            }
+            // The width of a cell is only the least width of its column.
+            (Display::TableCell, LengthOrAuto::Px(width)) => {
+                let (min, max) = self.content_widths(context);
+                (min.max(width), max.max(width))
+            }
+            (_, LengthOrAuto::Px(width)) => (width, width),
+            (_, LengthOrAuto::MinContent) => {
+                let (min, _) = self.content_widths(context);
+                (min, min)
+            }
+            (_, LengthOrAuto::MaxContent) => {
+                let (_, max) = self.content_widths(context);
+                (max, max)
+            }
+            _ => self.content_widths(context),
+        };
+        let clamp = |width: f32| {
+            style
+                .max_width
+                .and_then(|max_width| max_width.resolve(None))
+                .map_or(width, |max_width| width.min(max_width))
+                .max(style.min_width.resolve(None).unwrap_or(0.0))
+        };
+        (clamp(min) + edges, clamp(max) + edges)
+    }
+
+    // Get the width that the content of the box shrinks to fit in the
+    // available width: no wider than it needs, but breaking its lines
+    // rather than overflowing.
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#shrink-to-fit-float
+    fn shrink_to_fit(&self, available: f32, context: &LayoutContext) -> f32 {
+        let (min, max) = self.content_widths(context);
+        available.max(min).min(max)
+    }
+
+    // Measure the intrinsic widths of the content of every box before
+    // layout, from the leaves up, so that sizing a box uses the widths of
+    // its descendants instead of measuring them again.
+    // The content of an inline box is measured as part of its block
+    // container. A box that is already measured has measured descendants
+    // too, or measures them when it needs them.
+    fn measure_intrinsic_widths(&self, context: &LayoutContext) {
+        if self.measured_widths.get().is_some() {
+            return;
+        }
+        for child in &self.children {
+            child.measure_intrinsic_widths(context);
+        }
+        if !matches!(self.box_type, BoxType::InlineNode(_)) {
+            self.content_widths(context);
+        }
+    }
+
+    // Get the intrinsic widths of the content of the box, which are only
+    // measured the first time.
+    fn content_widths(&self, context: &LayoutContext) -> (f32, f32) {
+        *self
+            .measured_widths
+            .get_or_init(|| self.measure_content_widths(context))
+    }
+
+    fn measure_content_widths(&self, context: &LayoutContext) -> (f32, f32) {
+        if self.is_table() {
+            return self.table_content_widths(context);
+        }
+        let mut widths = InlineWidths::default();
+        for child in &self.children {
+            widths.add_box(child, context);
+        }
+        widths.finish()
+    }
+
+    // Break the text of inline children into lines, which start below the
+    // content laid out so far.
+    // TODO: Lay out the padding, borders, and margins of inline boxes.
+    fn layout_inline_children(
+        &mut self,
+        children: std::ops::Range<usize>,
+        context: &mut LayoutContext,
+    ) -> Vec<LineBox<'a>> {
+        let content = self.dimensions.content;
+        let style = self.box_type.style();
+        let mut builder = LineBuilder {
+            strut: inline_extents(context.metrics, style),
+            x_height: context.metrics.x_height(style),
+            context,
+            containing_block: self.dimensions,
+            x: content.x,
+            y: content.y + content.height,
+            width: content.width,
+            vertical_align: VerticalAlign::Baseline,
+            direction: style.direction,
+            text_align: style.text_align,
+            lines: Vec::new(),
+            fragments: Vec::new(),
+            alignments: Vec::new(),
+            boxes: Vec::new(),
+            line_width: 0.0,
+            line_height: 0.0,
+            pending_space: None,
+            can_break: false,
+        };
+        builder.start_line();
+        for child in &mut self.children[children] {
+            builder.add_inline_box(child);
+        }
+        builder.finish()
+    }
+
+    // Get the baseline that an atomic inline-level box is aligned on in
+    // its line. That is the first baseline of inline tables, grids, and
+    // flex containers, and the last one of inline blocks, unless they clip
+    // their content. Without one, it is the bottom of the margin box.
+    // SEE: https://drafts.csswg.org/css2/#propdef-vertical-align
+    // SEE: https://drafts.csswg.org/css-align/#baseline-export
+    fn inline_baseline(&self) -> f32 {
+        let baseline = match self.display() {
+            Display::InlineTable
+            | Display::InlineGrid
+            | Display::InlineFlex => self.first_baseline(),
+            _ if self.box_type.style().is_scroll_container() => None,
+            _ => self.last_baseline(),
+        };
+        baseline.unwrap_or_else(|| {
+            let margin_box = self.dimensions.margin_box();
+            margin_box.y + margin_box.height
+        })
+    }
+
+    // Place the outside marker of a list item on the start side of its
+    // content box, on the baseline of its first line. The marker doesn't
+    // take up space in the list item.
+    // SEE: https://drafts.csswg.org/css-lists/#list-style-position-property
+    fn layout_marker(&mut self, context: &LayoutContext) {
+        let content = self.dimensions.content;
+        let first_baseline = self.first_baseline();
+        let Some(marker) = self
+            .children
+            .iter_mut()
+            .find(|child| child.is_outside_marker())
+        else {
+            return;
+        };
+        let BoxType::InlineNode(node) = marker.box_type else {
+            return;
+        };
+        let text = node.generated_text.clone().unwrap_or_default();
+        let style = &node.style;
+        let metrics = context.metrics;
+        let width = metrics.measure(&text, style);
+        let (above, _) = inline_extents(metrics, style);
+        let baseline = first_baseline.unwrap_or(content.y + above);
+        let x = match style.direction {
+            Direction::Ltr => content.x - width,
+            Direction::Rtl => content.x + content.width,
+        };
+        let ascent = metrics.ascent(style);
+        marker.lines = vec![LineBox {
+            rect: Rectangle {
+                x,
+                y: baseline - above,
+                width,
+                height: style.line_height,
+            },
+            baseline,
+            fragments: vec![TextFragment {
+                node,
+                text,
+                rect: Rectangle {
+                    x,
+                    y: baseline - ascent,
+                    width,
+                    height: ascent + metrics.descent(style),
+                },
+                word_spacing: 0.0,
+                pieces: Vec::new(),
+                decoration_metrics: None,
+            }],
+        }];
+        marker.dirty = Dirty::default();
+    }
+
+    // Get the first baseline of the box, which is that of its first line
+    // box, or else of its first in-flow block-level child that has one.
+    // Replaced elements have none, and layouts that align boxes on their
+    // baselines, such as an inline formatting context, fall back to an
+    // edge of the box for them.
+    pub fn first_baseline(&self) -> Option<f32> {
+        if let Some(line) = self.lines.first() {
+            return Some(line.baseline);
+        }
+        if self.is_replaced() {
+            return None;
+        }
+        self.children
+            .iter()
+            .filter(|child| child.is_in_flow_block_level())
+            .find_map(|child| child.first_baseline())
+    }
+
+    // Get the last baseline of the box, which is that of its last line box,
+    // or else of its last in-flow block-level child that has one.
+    pub fn last_baseline(&self) -> Option<f32> {
+        if let Some(line) = self.lines.last() {
+            return Some(line.baseline);
+        }
+        if self.is_replaced() {
+            return None;
+        }
+        self.children
+            .iter()
+            .rev()
+            .filter(|child| child.is_in_flow_block_level())
+            .find_map(|child| child.last_baseline())
+    }
+
+    // Move the box and its content.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        // Inline boxes are not laid out, so they have no position to move.
+        if !matches!(self.box_type, BoxType::InlineNode(_)) {
+            self.dimensions.content.x += dx;
+            self.dimensions.content.y += dy;
+        }
+        self.static_position.0 += dx;
+        self.static_position.1 += dy;
+        for line in &mut self.lines {
+            line.translate(dx, dy);
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    // Get the height of the box if it doesn't depend on the content: a
+    // length, or a percentage of a definite containing block height.
+    fn definite_height(&self, containing_height: Option<f32>) -> Option<f32> {
+        match self.box_type.style().height.resolve(containing_height) {
+            LengthOrAuto::Px(height) => {
+                Some(self.clamp_height(height, containing_height))
+            }
+            _ => None,
+        }
+    }
+
+    fn calc_block_height(
+        &mut self,
+        height: Option<f32>,
+        containing_height: Option<f32>,
+    ) {
+        if let Some(h) = height {
+            self.dimensions.content.height = h;
+        }
+        self.dimensions.content.height = self
+            .clamp_height(self.dimensions.content.height, containing_height);
+    }
+
+    // Clamp a height to the bounds of `min-height` and `max-height`. The
+    // minimum wins over the maximum, and percentages of an unknown
+    // containing block height don't apply.
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#min-max-heights
+    fn clamp_height(&self, height: f32, containing_height: Option<f32>) -> f32 {
+        let style = self.box_type.style();
+        style
+            .max_height
+            .and_then(|max_height| max_height.resolve(containing_height))
+            .map_or(height, |max_height| height.min(max_height))
+            .max(style.min_height.resolve(containing_height).unwrap_or(0.0))
+    }
+
+    // Lay out the absolutely positioned descendants that have this box as
+    // their containing block, or the fixed ones if `fixed` is set.
+    // They are laid out after their containing block, whose size they may
+    // depend on.
+    fn layout_positioned_descendants(
+        &mut self,
+        containing_block: Rectangle,
+        fixed: bool,
+        context: &mut LayoutContext,
+    ) {
+        for child in &mut self.children {
+            match (child.position(), fixed) {
+                (Position::Absolute, false) => {
+                    child.layout_absolute(containing_block, context);
+                }
+                (Position::Fixed, true) => {
+                    child.layout_absolute(containing_block, context);
+                    if !child.is_transformed() {
+                        child.layout_positioned_descendants(
+                            containing_block,
+                            fixed,
+                            context,
+                        );
+                    }
+                }
+                // Other positioned boxes are the containing block of their
+                // own absolutely positioned descendants, and transformed
+                // boxes of all their positioned descendants.
+                (Position::Relative | Position::Fixed, false) => {}
+                _ if child.is_transformed() => {}
+                _ => child.layout_positioned_descendants(
+                    containing_block,
+                    fixed,
+                    context,
+                ),
+            }
+        }
+    }
+
+    // Lay out an absolutely positioned box in its containing block, which
+    // is a padding box.
+    // SEE: https://www.w3.org/TR/CSS22/visudet.html#abs-non-replaced-width
+    // TODO: Shrink the auto width to fit the content, unless both `left`
+    // and `right` are given.
+    fn layout_absolute(
+        &mut self,
+        containing_block: Rectangle,
+        context: &mut LayoutContext,
+    ) {
+        let style = self.box_type.style();
+        let horizontal =
+            |length: LengthOrAuto| length.resolve(Some(containing_block.width));
+        let vertical = |length: LengthOrAuto| {
+            length.resolve(Some(containing_block.height))
+        };
+        let offset = Sides {
+            top: vertical(style.offset.top),
+            right: horizontal(style.offset.right),
+            bottom: vertical(style.offset.bottom),
+            left: horizontal(style.offset.left),
+        };
+        let height = vertical(style.height);
+        let (left, right) = (offset.left.to_px(), offset.right.to_px());
+        let block = Dimensions {
+            content: Rectangle {
+                x: containing_block.x + left,
+                y: containing_block.y,
+                width: (containing_block.width - left - right).max(0.0),
+                height: 0.0,
+            },
+            ..Default::default()
+        };
+        let containing_height =
+            context.containing_height.replace(containing_block.height);
+        self.layout(block, context);
+        context.containing_height = containing_height;
+
+        // With both `top` and `bottom`, an auto height fills the space
+        // between them.
+        if let (LengthOrAuto::Px(top), LengthOrAuto::Px(bottom)) =
+            (offset.top, offset.bottom)
+        {
+            if height == LengthOrAuto::Auto {
+                let d = self.dimensions;
+                let edges = d.margin_box().height - d.content.height;
+                self.dimensions.content.height = self.clamp_height(
+                    (containing_block.height - top - bottom - edges).max(0.0),
+                    Some(containing_block.height),
+                );
+            }
+        }
+
+        let margin_box = self.dimensions.margin_box();
+        let x = match (offset.left, offset.right) {
+            (LengthOrAuto::Px(left), _) => containing_block.x + left,
+            (LengthOrAuto::Auto, LengthOrAuto::Px(right)) => {
+                containing_block.x + containing_block.width
+                    - right
+                    - margin_box.width
+            }
+            _ => self.static_position.0,
+        };
+        let y = match (offset.top, offset.bottom) {
+            (LengthOrAuto::Px(top), _) => containing_block.y + top,
+            (LengthOrAuto::Auto, LengthOrAuto::Px(bottom)) => {
+                containing_block.y + containing_block.height
+                    - bottom
+                    - margin_box.height
+            }
+            _ => self.static_position.1,
+        };
+        self.translate(x - margin_box.x, y - margin_box.y);
+    }
+}
+
+// The area of a grid item, in tracks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GridArea {
+    column: usize,
+    column_span: usize,
+    row: usize,
+    row_span: usize,
+}
+
+// Place grid items in the grid of a container. Items with a definite row
+// are placed first, and the others fill the remaining cells in order.
+// SEE: https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+fn place_grid_items<'s>(
+    container: &ComputedStyle,
+    items: impl Iterator<Item = &'s ComputedStyle>,
+) -> Vec<GridArea> {
+    let explicit_columns = container.grid_template_columns.len().max(1);
+    let explicit_rows = container.grid_template_rows.len();
+    let lines: Vec<_> = items
+        .map(|style| {
+            (
+                resolve_grid_lines(style.grid_column, explicit_columns),
+                resolve_grid_lines(style.grid_row, explicit_rows),
+            )
+        })
+        .collect();
+    let column_count = lines
+        .iter()
+        .map(|((start, span), _)| start.unwrap_or(0) + span)
+        .fold(explicit_columns, usize::max);
+
+    let mut occupied: Vec<Vec<bool>> = Vec::new();
+    let is_free = |occupied: &mut Vec<Vec<bool>>, area: GridArea| {
+        // Rows are added to the implicit grid as they are needed.
+        if occupied.len() < area.row + area.row_span {
+            occupied
+                .resize(area.row + area.row_span, vec![false; column_count]);
+        }
+        (area.row..area.row + area.row_span).all(|row| {
+            (area.column..area.column + area.column_span)
+                .all(|column| !occupied[row][column])
+        })
+    };
+    let mut areas = vec![None; lines.len()];
+    let mut cursor = (0, 0);
+    for definite_rows in [true, false] {
+        for (i, &((column, column_span), (row, row_span))) in
+            lines.iter().enumerate()
+        {
+            if row.is_some() != definite_rows {
+                continue;
+            }
+            let mut area = GridArea {
+                column: column.unwrap_or(0),
+                column_span,
+                row: row.unwrap_or(0),
+                row_span,
+            };
+            match (row, column) {
+                (Some(_), Some(_)) => {}
+                // Find the first column of the row with room for the item.
+                (Some(_), None) => {
+                    while area.column + column_span < column_count
+                        && !is_free(&mut occupied, area)
+                    {
+                        area.column += 1;
+                    }
+                }
+                // Search from the cursor, in the item's column if it has
+                // one.
+                (None, _) => {
+                    (area.row, area.column) = cursor;
+                    if let Some(column) = column {
+                        if column < area.column {
+                            area.row += 1;
+                        }
+                        area.column = column;
+                    }
+                    loop {
+                        if area.column + column_span > column_count {
+                            area.row += 1;
+                            area.column = column.unwrap_or(0);
+                        } else if is_free(&mut occupied, area) {
+                            break;
+                        } else if column.is_some() {
+                            area.row += 1;
+                        } else {
+                            area.column += 1;
+                        }
+                    }
+                    cursor = (area.row, area.column + column_span);
+                }
+            }
+            // Checking the area also adds its rows to the grid.
+            is_free(&mut occupied, area);
+            for row in &mut occupied[area.row..area.row + area.row_span] {
+                row[area.column..area.column + area.column_span].fill(true);
+            }
+            areas[i] = Some(area);
+        }
+    }
+    areas.into_iter().flatten().collect()
+}
+
+// The number of tracks that a grid can have, past which items are clamped
+// into it, so that huge line numbers don't make huge grids.
+// SEE: https://www.w3.org/TR/css-grid-1/#overlarge-grids
+const GRID_TRACK_LIMIT: usize = 10000;
+
+// Resolve the start and end lines of an item to its first track, unless
+// it is placed automatically, and the number of tracks it spans. Both are
+// clamped to the limit of the grid.
+fn resolve_grid_lines(
+    lines: (GridLine, GridLine),
+    explicit: usize,
+) -> (Option<usize>, usize) {
+    let (start, span) = resolve_unclamped_grid_lines(lines, explicit);
+    let span = span.clamp(1, GRID_TRACK_LIMIT);
+    match start {
+        Some(start) => {
+            let start = start.min(GRID_TRACK_LIMIT - 1);
+            (Some(start), span.min(GRID_TRACK_LIMIT - start))
+        }
+        None => (None, span),
+    }
+}
+
+fn resolve_unclamped_grid_lines(
+    (start, end): (GridLine, GridLine),
+    explicit: usize,
+) -> (Option<usize>, usize) {
+    // Lines are numbered from 1, and negative numbers count back from the
+    // last line of the explicit grid.
+    let line = |line: i32| {
+        if line > 0 {
+            line as usize - 1
+        } else {
+            (explicit as i32 + 1 + line).max(0) as usize
+        }
+    };
+    match (start, end) {
+        (GridLine::Line(start), GridLine::Line(end)) => {
+            let (start, end) = (line(start), line(end));
+            (Some(start.min(end)), start.abs_diff(end).max(1))
+        }
+        (GridLine::Line(start), GridLine::Span(span)) => {
+            (Some(line(start)), span as usize)
+        }
+        (GridLine::Line(start), GridLine::Auto) => (Some(line(start)), 1),
+        (GridLine::Span(span), GridLine::Line(end)) => {
+            (Some(line(end).saturating_sub(span as usize)), span as usize)
+        }
+        (GridLine::Auto, GridLine::Line(end)) => {
+            (Some(line(end).saturating_sub(1)), 1)
+        }
+        (GridLine::Span(span), _) | (GridLine::Auto, GridLine::Span(span)) => {
+            (None, span as usize)
+        }
+        (GridLine::Auto, GridLine::Auto) => (None, 1),
+    }
+}
+
+// Size `count` grid tracks in the given space. Tracks beyond the templates
+// are `auto`, and `auto` tracks share the free space like `1fr`.
+// TODO: Size `auto` tracks to fit their content.
+fn size_tracks(
+    templates: &[TrackSize],
+    count: usize,
+    space: f32,
+    gap: f32,
+) -> Vec<f32> {
+    let tracks: Vec<TrackSize> = (0..count)
+        .map(|i| templates.get(i).copied().unwrap_or(TrackSize::Auto))
+        .collect();
+    let fixed = |track: &TrackSize| match *track {
+        TrackSize::Px(px) => px,
+        TrackSize::Percent(percent) => percent / 100.0 * space,
+        _ => 0.0,
+    };
+    let flex = |track: &TrackSize| match *track {
+        TrackSize::Fr(fr) => fr,
+        TrackSize::Auto => 1.0,
+        _ => 0.0,
+    };
+    let gaps = gap * count.saturating_sub(1) as f32;
+    let free = (space - gaps - tracks.iter().map(fixed).sum::<f32>()).max(0.0);
+    let fractions: f32 = tracks.iter().map(flex).sum();
+    tracks
+        .iter()
+        .map(|track| {
+            if fractions > 0.0 {
+                fixed(track) + free * flex(track) / fractions
+            } else {
+                fixed(track)
+            }
+        })
+        .collect()
+}
+
+// Get the start position of each track.
+fn track_offsets(tracks: &[f32], start: f32, gap: f32) -> Vec<f32> {
+    tracks
+        .iter()
+        .scan(start, |position, size| {
+            let offset = *position;
+            *position += size + gap;
+            Some(offset)
+        })
+        .collect()
+}
+
+// Get the size of a span of tracks, including the gaps between them.
+fn span_size(tracks: &[f32], start: usize, span: usize, gap: f32) -> f32 {
+    let end = (start + span).min(tracks.len());
+    let tracks = &tracks[start.min(end)..end];
+    tracks.iter().sum::<f32>() + gap * tracks.len().saturating_sub(1) as f32
+}
+
+// Measures the intrinsic widths of content, by breaking its lines at
+// every opportunity for the minimum width, and at none for the maximum.
+#[derive(Debug, Default)]
+struct InlineWidths {
+    min: f32,
+    max: f32,
+    // The width of the current line, and of its last unbreakable piece.
+    line: f32,
+    word: f32,
+    // A collapsible space that is only counted if more content follows.
+    pending_space: f32,
+}
+
+impl InlineWidths {
+    fn add_box(&mut self, layout_box: &LayoutBox, context: &LayoutContext) {
+        if layout_box.position().is_out_of_flow()
+            || layout_box.is_outside_marker()
+        {
+            return;
+        }
+        match layout_box.box_type {
+            BoxType::InlineNode(node) if !layout_box.is_float() => {
+                match (&node.generated_text, &node.node.node_type) {
+                    (Some(text), _) => {
+                        self.add_text(&node.style, text, context.metrics)
+                    }
+                    (None, NodeType::Text(text)) => {
+                        self.add_text(&node.style, text, context.metrics)
+                    }
+                    _ => {
+                        for child in &layout_box.children {
+                            self.add_box(child, context);
+                        }
+                    }
+                }
+            }
+            // Floats and inline-blocks are unbreakable, and lines may break
+            // around them.
+            // TODO: Place floats beside the lines instead of on them.
+            _ if layout_box.is_float()
+                || matches!(
+                    layout_box.box_type,
+                    BoxType::InlineBlockNode(_)
+                ) =>
+            {
+                let (min, max) = layout_box.intrinsic_widths(context);
+                self.line += self.pending_space + max;
+                self.pending_space = 0.0;
+                self.word = 0.0;
+                self.min = self.min.max(min);
+            }
+            // Block-level boxes are on lines of their own.
+            _ => {
+                self.finish_line();
+                let (min, max) = layout_box.intrinsic_widths(context);
+                self.min = self.min.max(min);
+                self.max = self.max.max(max);
+            }
+        }
+    }
+
+    fn add_text(
+        &mut self,
+        style: &ComputedStyle,
+        text: &str,
+        metrics: &dyn FontMetrics,
+    ) {
+        let wraps = style.white_space.wraps();
+        for segment in text::segments(text, style.white_space) {
+            match segment {
+                TextSegment::Word(word) => {
+                    let width = metrics.measure(word, style);
+                    self.line += self.pending_space + width;
+                    if wraps {
+                        self.word += width;
+                    } else {
+                        self.word += self.pending_space + width;
+                    }
+                    self.pending_space = 0.0;
+                    self.min = self.min.max(self.word);
+                }
+                TextSegment::Space(_)
+                    if style.white_space.collapses_spaces() =>
+                {
+                    if self.line > 0.0 && self.pending_space == 0.0 {
+                        self.pending_space = metrics.measure(" ", style);
+                    }
+                    if wraps {
+                        self.word = 0.0;
+                    }
+                }
+                TextSegment::Space(space) => {
+                    let width = metrics.measure(space, style);
+                    self.line += width;
+                    if wraps {
+                        self.word = 0.0;
+                    } else {
+                        self.word += width;
+                        self.min = self.min.max(self.word);
+                    }
+                }
+                TextSegment::Newline => self.finish_line(),
+            }
+        }
+    }
+
+    fn finish_line(&mut self) {
+        self.max = self.max.max(self.line);
+        self.line = 0.0;
+        self.word = 0.0;
+        self.pending_space = 0.0;
+    }
+
+    fn finish(mut self) -> (f32, f32) {
+        self.finish_line();
+        (self.min, self.max)
+    }
+}
+
+// Breaks inline content into line boxes, which are shortened by floats.
+struct LineBuilder<'a, 'b, 'c, 'm> {
+    context: &'c mut LayoutContext<'m>,
+    // The dimensions of the block container.
+    containing_block: Dimensions,
+    // The left edge and the width of the current line.
+    x: f32,
+    width: f32,
+    // The top edge of the current line.
+    y: f32,
+    // The extents above and below the baseline of the line height of the
+    // block container, which every line is at least as tall as.
+    // SEE: https://drafts.csswg.org/css2/#strut
+    strut: (f32, f32),
+    // The x-height of the font of the block container, which `middle`
+    // aligns on.
+    x_height: f32,
+    // The alignment of the inline box that content is being added to.
+    vertical_align: VerticalAlign,
+    // The direction of the block container. Content is added from the left
+    // edge of each line, and lines of right-to-left content are mirrored
+    // when they are finished.
+    direction: Direction,
+    text_align: TextAlign,
+    lines: Vec<LineBox<'a>>,
+    // The content of the current line, and the alignment of each fragment.
+    fragments: Vec<TextFragment<'a>>,
+    alignments: Vec<VerticalAlign>,
+    // The atomic inline-level boxes on the current line, and their
+    // alignment.
+    boxes: Vec<(&'b mut LayoutBox<'a>, VerticalAlign)>,
+    line_width: f32,
+    line_height: f32,
+    // A collapsible space that is only placed if more content follows on
+    // the same line, with the node it belongs to, its width, and its offset
+    // in the text of the node.
+    pending_space: Option<(&'a StyledNode<'a>, f32, usize)>,
+    // Whether the line may break before the next word.
+    can_break: bool,
+}
+
+impl<'a, 'b> LineBuilder<'a, 'b, '_, '_> {
+    // Add the text of an inline box and its descendants.
+    fn add_inline_box(&mut self, layout_box: &'b mut LayoutBox<'a>) {
+        // Boxes out of normal flow are laid out later, and only remember
+        // where they would have been.
+        if layout_box.position().is_out_of_flow() {
+            layout_box.static_position = (self.x + self.line_width, self.y);
+            return;
+        }
+        if layout_box.is_float() {
+            self.add_float(layout_box);
+            return;
+        }
+        if let BoxType::InlineBlockNode(_) = layout_box.box_type {
+            self.add_atomic_inline(layout_box);
+            return;
+        }
+        // Outside markers are placed once the lines of their list item are
+        // laid out.
+        if layout_box.is_outside_marker() {
+            return;
+        }
+        // Inline boxes contain no blocks, which are split out of them when
+        // the tree is built.
+        let BoxType::InlineNode(node) = layout_box.box_type else {
+            return;
+        };
+        layout_box.dirty = Dirty::default();
+        match (&node.generated_text, &node.node.node_type) {
+            (Some(text), _) => self.add_text(node, text),
+            (None, NodeType::Text(text)) => self.add_text(node, text),
+            _ => {
+                // The content of an inline box is aligned with it, unless
+                // it has an alignment of its own.
+                let vertical_align = self.vertical_align;
+                if node.style.vertical_align != VerticalAlign::Baseline {
+                    self.vertical_align = node.style.vertical_align;
+                }
+                for child in &mut layout_box.children {
+                    self.add_inline_box(child);
+                }
+                self.vertical_align = vertical_align;
+            }
+        }
+    }
+
+    // Lay out a float, and place it on the current line if it fits next to
+    // the content so far, or below the line otherwise.
+    fn add_float(&mut self, float: &mut LayoutBox<'a>) {
+        float.layout_or_reuse(self.containing_block, None, self.context);
+        let style = float.box_type.style();
+        let (side, clear) = (style.float, style.clear);
+        let margin_box = float.dimensions.margin_box();
+        let fits = self.is_line_empty()
+            || self.line_width + margin_box.width <= self.width;
+        let mut y = if fits {
+            self.y
+        } else {
+            self.y + self.line_height.max(self.strut.0 + self.strut.1)
+        };
+        if let Some(bottom) = self.context.floats.clearance(clear) {
+            y = y.max(bottom);
+        }
+        let content = self.containing_block.content;
+        let (x, y) = self.context.floats.place(
+            side,
+            margin_box.width,
+            margin_box.height,
+            y,
+            content.x,
+            content.x + content.width,
+        );
+        float.translate(x - margin_box.x, y - margin_box.y);
+        self.context
+            .floats
+            .add(side, Rectangle { x, y, ..margin_box });
+
+        // Move the content of the current line out of the way.
+        let x = self.x;
+        self.start_line();
+        for fragment in &mut self.fragments {
+            fragment.rect.x += self.x - x;
+        }
+        for (layout_box, _) in &mut self.boxes {
+            layout_box.translate(self.x - x, 0.0);
+        }
+    }
+
+    // Lay out an atomic inline-level box, such as an inline-block or an
+    // image, and place it on the current line if it fits, or on the next
+    // one otherwise. Lines may break on both sides of it. The box is moved
+    // to its vertical position when the line is finished.
+    fn add_atomic_inline(&mut self, layout_box: &'b mut LayoutBox<'a>) {
+        let content = self.containing_block.content;
+        let containing_block = Dimensions {
+            content: Rectangle {
+                height: self.y - content.y,
+                ..content
+            },
+            ..self.containing_block
+        };
+        layout_box.layout_or_reuse(containing_block, None, self.context);
+        let margin_box = layout_box.dimensions.margin_box();
+        let space = self.pending_space.map_or(0.0, |(_, width, _)| width);
+        if !self.is_line_empty()
+            && self.line_width + space + margin_box.width > self.width
+        {
+            self.finish_line(false);
+        }
+        if let Some((space_node, space, offset)) = self.pending_space.take() {
+            self.push(space_node, " ", space, offset);
+        }
+
+        // Keep the offset of a relatively positioned box.
+        let (dx, dy) = layout_box
+            .relative_offset(content.width, self.context.containing_height);
+        layout_box.translate(
+            self.x + self.line_width + dx - margin_box.x,
+            self.y + dy - margin_box.y,
+        );
+        self.line_width += margin_box.width;
+        self.line_height = self.line_height.max(margin_box.height);
+        let vertical_align = match layout_box.box_type.style().vertical_align {
+            VerticalAlign::Baseline => self.vertical_align,
+            vertical_align => vertical_align,
+        };
+        self.boxes.push((layout_box, vertical_align));
+        self.can_break = true;
+    }
+
+    fn add_text(&mut self, node: &'a StyledNode<'a>, text: &str) {
+        let style = &node.style;
+        // Words and preserved spaces are slices of the text, so their
+        // offsets in it are known. A collapsed space is not, and is placed
+        // right after the previous segment.
+        let bytes = text.as_bytes().as_ptr_range();
+        let mut end = 0;
+        let mut offset = |segment: &str| {
+            let start = if bytes.contains(&segment.as_ptr()) {
+                segment.as_ptr() as usize - bytes.start as usize
+            } else {
+                end
+            };
+            end = start + segment.len();
+            start
+        };
+        for segment in text::segments(text, style.white_space) {
+            match segment {
+                TextSegment::Word(word) => {
+                    let width = self.context.metrics.measure(word, style);
+                    let space =
+                        self.pending_space.map_or(0.0, |(_, width, _)| width);
+                    if self.can_break
+                        && style.white_space.wraps()
+                        && self.line_width + space + width > self.width
+                    {
+                        self.finish_line(false);
+                    }
+                    if let Some((space_node, space, space_offset)) =
+                        self.pending_space.take()
+                    {
+                        self.push(space_node, " ", space, space_offset);
+                    }
+                    self.push(node, word, width, offset(word));
+                    self.can_break = false;
+                }
+                // Collapsible spaces are dropped at the start of a line and
+                // after another space.
+                TextSegment::Space(space)
+                    if style.white_space.collapses_spaces() =>
+                {
+                    let space_offset = offset(space);
+                    if !self.is_line_empty() && self.pending_space.is_none() {
+                        let width = self.context.metrics.measure(" ", style);
+                        self.pending_space = Some((node, width, space_offset));
+                        self.can_break = true;
+                    }
+                }
+                TextSegment::Space(space) => {
+                    let width = self.context.metrics.measure(space, style);
+                    self.push(node, space, width, offset(space));
+                    self.can_break = true;
+                }
+                TextSegment::Newline => {
+                    // An empty line still takes up the height of the line.
+                    self.line_height = self.line_height.max(style.line_height);
+                    self.finish_line(true);
+                }
+            }
+        }
+    }
+
+    // Append text to the current line, merging it into the last fragment
+    // if that belongs to the same node. `offset` is where the text starts
+    // in the text of the node.
+    fn push(
+        &mut self,
+        node: &'a StyledNode<'a>,
+        text: &str,
+        width: f32,
+        offset: usize,
+    ) {
+        match self.fragments.last_mut() {
+            Some(fragment) if std::ptr::eq(fragment.node, node) => {
+                fragment.pieces.push((offset, fragment.text.len()));
+                fragment.text.push_str(text);
+                fragment.rect.width += width;
+            }
+            _ => {
+                let decoration_metrics =
+                    (!node.style.text_decorations.is_empty()).then(|| {
+                        self.context.metrics.decoration_metrics(&node.style)
+                    });
+                self.fragments.push(TextFragment {
+                    node,
+                    text: text.to_string(),
+                    rect: Rectangle {
+                        x: self.x + self.line_width,
+                        y: 0.0,
+                        width,
+                        height: 0.0,
+                    },
+                    word_spacing: 0.0,
+                    pieces: vec![(offset, 0)],
+                    decoration_metrics,
+                });
+                self.alignments.push(self.vertical_align);
+            }
+        }
+        self.line_width += width;
+        self.line_height = self.line_height.max(node.style.line_height);
+    }
+
+    // Close the current line, and start the next one below it.
+    // Content is aligned on a shared baseline, which is placed so that the
+    // line is as short as possible, and content aligned with the top or the
+    // bottom of the line is placed last. The last line of a paragraph ends
+    // at a forced break or at the end of the content.
+    // SEE: https://drafts.csswg.org/css2/#line-height
+    fn finish_line(&mut self, last: bool) {
+        let metrics = self.context.metrics;
+        let mut fragments = std::mem::take(&mut self.fragments);
+        let alignments = std::mem::take(&mut self.alignments);
+        let mut boxes = std::mem::take(&mut self.boxes);
+
+        // The extents of each item above and below the baseline, or its
+        // height if it is aligned with the top or the bottom.
+        let x_height = self.x_height;
+        let extents = |(above, below): (f32, f32), align| match align {
+            VerticalAlign::Middle => {
+                let height = above + below;
+                ((height + x_height) / 2.0, (height - x_height) / 2.0)
+            }
+            _ => (above, below),
+        };
+        let fragment_extents: Vec<_> = fragments
+            .iter()
+            .zip(&alignments)
+            .map(|(fragment, &align)| {
+                extents(inline_extents(metrics, &fragment.node.style), align)
+            })
+            .collect();
+        let box_extents: Vec<_> = boxes
+            .iter()
+            .map(|(layout_box, align)| {
+                let margin_box = layout_box.dimensions.margin_box();
+                let above = layout_box.inline_baseline() - margin_box.y;
+                extents((above, margin_box.height - above), *align)
+            })
+            .collect();
+
+        let (mut above, mut below) = self.strut;
+        let mut aligned_height = 0.0_f32;
+        let mut aligned_bottom_height = 0.0_f32;
+        let items = alignments
+            .iter()
+            .zip(&fragment_extents)
+            .chain(boxes.iter().map(|(_, align)| align).zip(&box_extents));
+        for (align, &(item_above, item_below)) in items {
+            match align {
+                VerticalAlign::Baseline | VerticalAlign::Middle => {
+                    above = above.max(item_above);
+                    below = below.max(item_below);
+                }
+                VerticalAlign::Top => {
+                    aligned_height =
+                        aligned_height.max(item_above + item_below);
+                }
+                VerticalAlign::Bottom => {
+                    aligned_bottom_height =
+                        aligned_bottom_height.max(item_above + item_below);
+                }
+            }
+        }
+        // Content aligned with the bottom of the line pushes the baseline
+        // down, while content aligned with the top only extends the line.
+        let baseline =
+            self.y + above + (aligned_bottom_height - above - below).max(0.0);
+        let height = (baseline - self.y + below)
+            .max(aligned_height)
+            .max(self.line_height);
+        let top = |align, (item_above, item_below): (f32, f32)| match align {
+            VerticalAlign::Top => self.y,
+            VerticalAlign::Bottom => self.y + height - item_above - item_below,
+            VerticalAlign::Baseline | VerticalAlign::Middle => {
+                baseline - item_above
+            }
+        };
+
+        for ((fragment, &align), &extents) in
+            fragments.iter_mut().zip(&alignments).zip(&fragment_extents)
+        {
+            // The glyphs are centered within the line height of their node.
+            let style = &fragment.node.style;
+            let glyph_height = metrics.ascent(style) + metrics.descent(style);
+            let half_leading = (style.line_height - glyph_height) / 2.0;
+            fragment.rect.y = top(align, extents) + half_leading;
+            fragment.rect.height = glyph_height;
+        }
+
+        // Content is moved along the line by its alignment, and each space
+        // of justified text moves the content after it further.
+        let (offset, word_spacing, spaces) =
+            self.text_alignment(&fragments, &boxes, last);
+        let box_shifts: Vec<_> = boxes
+            .iter()
+            .map(|(layout_box, _)| {
+                let x = layout_box.dimensions.margin_box().x;
+                let spaces_before: usize = fragments
+                    .iter()
+                    .zip(&spaces)
+                    .filter(|(fragment, _)| fragment.rect.x < x)
+                    .map(|(_, &spaces)| spaces)
+                    .sum();
+                offset + word_spacing * spaces_before as f32
+            })
+            .collect();
+
+        // Right-to-left lines start at the right edge, and their content
+        // is ordered from right to left.
+        // TODO: Reorder the characters of mixed-direction text with the
+        // bidirectional algorithm.
+        // SEE: https://www.w3.org/TR/CSS22/visuren.html#direction
+        let (line_x, line_width) = (self.x, self.width);
+        let mirror = |x: f32, width: f32| match self.direction {
+            Direction::Ltr => x,
+            Direction::Rtl => 2.0 * line_x + line_width - x - width,
+        };
+        let mut shift = offset;
+        for (fragment, &spaces) in fragments.iter_mut().zip(&spaces) {
+            let stretch = word_spacing * spaces as f32;
+            fragment.word_spacing = word_spacing;
+            fragment.rect.width += stretch;
+            fragment.rect.x =
+                mirror(fragment.rect.x + shift, fragment.rect.width);
+            shift += stretch;
+        }
+        for (((layout_box, align), &extents), shift) in
+            boxes.iter_mut().zip(&box_extents).zip(box_shifts)
+        {
+            let margin_box = layout_box.dimensions.margin_box();
+            layout_box.translate(
+                mirror(margin_box.x + shift, margin_box.width) - margin_box.x,
+                top(*align, extents) - margin_box.y,
+            );
+        }
+        boxes.clear();
+        self.boxes = boxes;
+
+        self.lines.push(LineBox {
+            rect: Rectangle {
+                x: self.x,
+                y: self.y,
+                width: self.width,
+                height,
+            },
+            baseline,
+            fragments,
+        });
+        self.y += height;
+        self.line_width = 0.0;
+        self.line_height = 0.0;
+        self.pending_space = None;
+        self.can_break = false;
+        self.start_line();
+    }
+
+    // Resolve `text-align` for the content of the current line into an
+    // offset from the start of the line, and the extra width of each space
+    // of justified text, with the number of those spaces in each fragment.
+    // Collapsible spaces at the end of the line hang, and take no part in
+    // the alignment. Content that overflows the line is aligned with its
+    // start.
+    // SEE: https://drafts.csswg.org/css-text/#text-align-property
+    fn text_alignment(
+        &self,
+        fragments: &[TextFragment],
+        boxes: &[(&mut LayoutBox, VerticalAlign)],
+        last: bool,
+    ) -> (f32, f32, Vec<usize>) {
+        let metrics = self.context.metrics;
+        let mut spaces: Vec<_> = fragments
+            .iter()
+            .map(|fragment| fragment.text.matches(' ').count())
+            .collect();
+        let mut hanging = 0.0;
+        if let (Some(fragment), Some(spaces)) =
+            (fragments.last(), spaces.last_mut())
+        {
+            let style = &fragment.node.style;
+            let ends_line = boxes.iter().all(|(layout_box, _)| {
+                layout_box.dimensions.margin_box().x < fragment.rect.x
+            });
+            let trailing =
+                fragment.text.len() - fragment.text.trim_end_matches(' ').len();
+            if ends_line && trailing > 0 && style.white_space.collapses_spaces()
+            {
+                *spaces -= trailing;
+                hanging = trailing as f32 * metrics.measure(" ", style);
+            }
+        }
+        let free = (self.width - self.line_width + hanging).max(0.0);
+
+        // Offsets are from the start of the line, which is the right edge
+        // in right-to-left lines.
+        let ltr = self.direction == Direction::Ltr;
+        let offset = match self.text_align {
+            TextAlign::Start | TextAlign::Justify => 0.0,
+            TextAlign::End => free,
+            TextAlign::Center => free / 2.0,
+            TextAlign::Left if ltr => 0.0,
+            TextAlign::Right if !ltr => 0.0,
+            TextAlign::Left | TextAlign::Right => free,
+        };
+        let total: usize = spaces.iter().sum();
+        if self.text_align != TextAlign::Justify || last || total == 0 {
+            return (offset, 0.0, vec![0; fragments.len()]);
+        }
+        (offset, free / total as f32, spaces)
+    }
+
+    // Fit the current line between the floats next to it.
+    // TODO: Move lines whose content doesn't fit below the floats.
+    fn start_line(&mut self) {
+        let content = self.containing_block.content;
+        let (left, right) = self.context.floats.available(
+            self.y,
+            self.strut.0 + self.strut.1,
+            content.x,
+            content.x + content.width,
+        );
+        self.x = left;
+        self.width = right - left;
+    }
+
+    #[inline]
+    fn is_line_empty(&self) -> bool {
+        self.fragments.is_empty() && self.boxes.is_empty()
+    }
+
+    // Close the last line, unless it is empty.
+    fn finish(mut self) -> Vec<LineBox<'a>> {
+        if !self.is_line_empty() {
+            self.finish_line(true);
+        }
+        self.lines
+    }
+}
+
+// Format a rectangle for dumps, as its position and size.
+pub fn dump_rect(rect: Rectangle) -> String {
+    format!(
+        "({}, {}) {}x{}",
+        round(rect.x),
+        round(rect.y),
+        round(rect.width),
+        round(rect.height)
+    )
+}
+
+// Round a length to hundredths, without a negative zero.
+pub fn round(length: f32) -> f32 {
+    (length * 100.0).round() / 100.0 + 0.0
+}
+
+// Get the extents of the line height of a style above and below the
+// baseline. The leading, which is the difference between the line height
+// and the height of the glyphs, is split evenly above and below them.
+// SEE: https://drafts.csswg.org/css2/#leading
+fn inline_extents(
+    metrics: &dyn FontMetrics,
+    style: &ComputedStyle,
+) -> (f32, f32) {
+    let (ascent, descent) = (metrics.ascent(style), metrics.descent(style));
+    let half_leading = (style.line_height - ascent - descent) / 2.0;
+    (ascent + half_leading, descent + half_leading)
+}
+
+// A box whose descendants are painted together, in front of or behind the
+// other boxes of its parent context by its `z-index`.
+// Floats and positioned boxes with `z-index: auto` are painted as if they
+// were stacking contexts with a `z-index` of zero, except that their
+// positioned descendants and stacking contexts belong to the parent context.
+// SEE: https://www.w3.org/TR/CSS22/zindex.html
+#[derive(Debug)]
+pub struct StackingContext<'b, 'a> {
+    pub root: &'b LayoutBox<'a>,
+    pub z_index: i32,
+    // The descendants in normal flow, in tree order.
+    pub in_flow: Vec<&'b LayoutBox<'a>>,
+    // The floating descendants, in tree order.
+    pub floats: Vec<StackingContext<'b, 'a>>,
+    // The nested contexts and positioned descendants, from back to front.
+    // Those with the same `z-index` are in tree order.
+    pub children: Vec<StackingContext<'b, 'a>>,
+}
+
+impl<'b, 'a> StackingContext<'b, 'a> {
+    // Build the stacking context of a box and its descendants, such as the
+    // root of the layout tree.
+    pub fn new(root: &'b LayoutBox<'a>) -> Self {
+        let mut context = Self::empty(root);
+        let mut children = Vec::new();
+        context.collect(root, &mut children);
+        children.sort_by_key(|child| child.z_index);
+        context.children = children;
+        context
+    }
+
+    fn empty(root: &'b LayoutBox<'a>) -> Self {
+        Self {
+            root,
+            z_index: root.box_type.style().z_index.unwrap_or(0),
+            in_flow: Vec::new(),
+            floats: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    // Add the descendants of a box to this context, and the nested
+    // contexts and positioned boxes among them to `children`, which are
+    // those of the nearest actual stacking context.
+    fn collect(
+        &mut self,
+        parent: &'b LayoutBox<'a>,
+        children: &mut Vec<StackingContext<'b, 'a>>,
+    ) {
+        for child in &parent.children {
+            if child.establishes_stacking_context() {
+                children.push(Self::new(child));
+            } else if child.position() != Position::Static {
+                let index = children.len();
+                let mut positioned = Self::empty(child);
+                positioned.collect(child, children);
+                children.insert(index, positioned);
+            } else if child.is_float() {
+                let mut float = Self::empty(child);
+                float.collect(child, children);
+                self.floats.push(float);
+            } else {
+                self.in_flow.push(child);
+                self.collect(child, children);
+            }
+        }
+    }
+
+    // Get the boxes of the context in painting order, from back to front.
+    // Each box paints its background, borders, and lines, and hit testing
+    // visits the boxes in reverse.
+    // TODO: Paint the lines of boxes in normal flow after the floats.
+    pub fn paint_order(&self) -> Vec<&'b LayoutBox<'a>> {
+        let mut boxes = Vec::new();
+        self.push_paint_order(&mut boxes);
+        boxes
+    }
+
+    fn push_paint_order(&self, boxes: &mut Vec<&'b LayoutBox<'a>>) {
+        let behind = self.children.partition_point(|child| child.z_index < 0);
+        boxes.push(self.root);
+        for child in &self.children[..behind] {
+            child.push_paint_order(boxes);
+        }
+        boxes.extend(&self.in_flow);
+        for float in &self.floats {
+            float.push_paint_order(boxes);
+        }
+        for child in &self.children[behind..] {
+            child.push_paint_order(boxes);
+        }
+    }
+}
+
+// The boxes of each DOM node in a laid out tree, for embedders to look up
+// the geometry of a node, such as for hit testing and inspection.
+#[derive(Debug)]
+pub struct BoxMap<'b, 'a> {
+    // The boxes that each node generated in tree order, with the block
+    // container whose lines hold their content if they are inline boxes.
+    boxes: HashMap<NodeId, Vec<(&'b LayoutBox<'a>, &'b LayoutBox<'a>)>>,
+}
+
+impl<'b, 'a> BoxMap<'b, 'a> {
+    pub fn new(root: &'b LayoutBox<'a>) -> Self {
+        let mut map = Self {
+            boxes: HashMap::new(),
+        };
+        map.insert(root, root);
+        map
+    }
+
+    fn insert(
+        &mut self,
+        layout_box: &'b LayoutBox<'a>,
+        container: &'b LayoutBox<'a>,
+    ) {
+        if let Some(node_id) = layout_box.node_id() {
+            self.boxes
+                .entry(node_id)
+                .or_default()
+                .push((layout_box, container));
+        }
+        // The inline children of a block container are laid out on its
+        // lines, and those of an inline box on the lines of its container.
+        let container = match layout_box.box_type {
+            BoxType::InlineNode(_) => container,
+            _ => layout_box,
+        };
+        for child in &layout_box.children {
+            self.insert(child, container);
+        }
+    }
+
+    // Get the principal box of a node, which is the first one it
+    // generated, rather than a box of its pseudo-elements.
+    pub fn get(&self, node_id: NodeId) -> Option<&'b LayoutBox<'a>> {
+        self.boxes.get(&node_id).map(|boxes| boxes[0].0)
+    }
+
+    // Get all the boxes of a node in tree order, including those of its
+    // pseudo-elements and the parts of an inline box split by blocks.
+    pub fn get_all(
+        &self,
+        node_id: NodeId,
+    ) -> impl Iterator<Item = &'b LayoutBox<'a>> + '_ {
+        self.boxes
+            .get(&node_id)
+            .into_iter()
+            .flatten()
+            .map(|(layout_box, _)| *layout_box)
+    }
+
+    // Get the border boxes that a node covers. An inline box covers a
+    // rectangle per fragment of its text and per atomic inline in it.
+    pub fn rects(&self, node_id: NodeId) -> Vec<Rectangle> {
+        let mut rects = Vec::new();
+        for (layout_box, container) in
+            self.boxes.get(&node_id).into_iter().flatten()
+        {
+            box_rects(layout_box, container, &mut rects);
+        }
+        rects
+    }
+
+    // Get the rectangles that a node covers as they are drawn on the page,
+    // after transforms, such as to hit test them.
+    pub fn quads(&self, node_id: NodeId) -> Vec<Quad> {
+        let mut quads = Vec::new();
+        for (layout_box, container) in
+            self.boxes.get(&node_id).into_iter().flatten()
+        {
+            let mut rects = Vec::new();
+            box_rects(layout_box, container, &mut rects);
+            quads.extend(
+                rects
+                    .into_iter()
+                    .map(|rect| Quad::new(rect, layout_box.transform)),
+            );
+        }
+        quads
+    }
+}
+
+// Add the rectangles that a box covers.
+fn box_rects(
+    layout_box: &LayoutBox,
+    container: &LayoutBox,
+    rects: &mut Vec<Rectangle>,
+) {
+    match layout_box.box_type {
+        BoxType::InlineNode(_) => inline_rects(layout_box, container, rects),
+        _ => rects.push(layout_box.dimensions.border_box()),
+    }
+}
+
+// Add the rectangles of the fragments and atomic inlines in an inline box,
+// which are found on the lines of its block container, or on its own line
+// if it is an outside list marker.
+fn inline_rects(
+    layout_box: &LayoutBox,
+    container: &LayoutBox,
+    rects: &mut Vec<Rectangle>,
+) {
+    let node = layout_box.get_style_node();
+    rects.extend(
+        container
+            .lines
+            .iter()
+            .chain(&layout_box.lines)
+            .flat_map(|line| &line.fragments)
+            .filter(|fragment| std::ptr::eq(fragment.node, node))
+            .map(|fragment| fragment.rect),
+    );
+    for child in &layout_box.children {
+        match child.box_type {
+            BoxType::InlineNode(_) => inline_rects(child, container, rects),
+            _ => rects.push(child.dimensions.border_box()),
+        }
+    }
+}
+
+fn sum<I>(iter: I) -> f32
+where
+    I: Iterator<Item = f32>,
+{
+    iter.fold(0., |acc, x| acc + x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        html_parser::HTMLParser,
+        style::{MediaContext, apply},
+    };
+
+    // Get the text of each line.
+    fn line_texts(layout_box: &LayoutBox) -> Vec<String> {
+        layout_box
+            .lines
+            .iter()
+            .map(|line| {
+                line.fragments
+                    .iter()
+                    .map(|fragment| fragment.text.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_text_wraps_at_available_width() {
+        let root = HTMLParser::parse(
+            "<div>hello \t world,\n <b>foo</b><p>a  b\nc</p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { white-space: pre; margin: 0; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        // Ten characters of the monospace metrics fit in a line.
+        viewport.content.width = 60.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        // The text before the paragraph is wrapped in an anonymous block.
+        let anonymous = &layout_box.children[0];
+        assert_eq!(line_texts(anonymous), ["hello", "world, foo"]);
+        let second = &anonymous.lines[1];
+        assert_eq!(second.rect.y, 12.0);
+        assert_eq!(second.fragments[0].text, "world, ");
+        assert_eq!(second.fragments[1].rect.x, 42.0);
+
+        // Preformatted text keeps its spaces and newlines.
+        let pre = &layout_box.children[1];
+        assert_eq!(line_texts(pre), ["a  b", "c"]);
+        assert_eq!(pre.dimensions.content.y, 24.0);
+        assert_eq!(layout_box.dimensions.content.height, 48.0);
+    }
+
+    #[test]
+    fn test_lines_flow_around_floats() {
+        let root = HTMLParser::parse(
+            "<div><b class=\"l\"></b>aaaa bbbb cccc dddd eeee ffff gggg\
+             <b class=\"r\"></b><p></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } \
+             .l { float: left; width: 40px; height: 30px; } \
+             .r { float: right; width: 20px; height: 100px; } \
+             p { clear: both; margin: 0; height: 10px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        // Lines next to the left float are shortened.
+        assert_eq!(
+            line_texts(&layout_box.children[0]),
+            ["aaaa bbbb", "cccc dddd", "eeee ffff", "gggg"]
+        );
+        assert_eq!(layout_box.children[0].lines[2].rect.x, 40.0);
+        assert_eq!(layout_box.children[0].lines[3].rect.x, 0.0);
+        // The right float fits on the last line.
+        let right = &layout_box.children[0].children[2].dimensions.content;
+        assert_eq!((right.x, right.y), (80.0, 36.0));
+        // The paragraph is moved below both floats, which the div doesn't
+        // grow to contain.
+        assert_eq!(layout_box.children[1].dimensions.content.y, 136.0);
+        assert_eq!(layout_box.dimensions.content.height, 146.0);
+    }
+
+    #[test]
+    fn test_positioned_boxes() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"rel\"></p><div class=\"box\">\
+             <span class=\"abs\"></span><span class=\"static\"></span></div>\
+             <b class=\"fixed\"></b></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            ".rel { position: relative; top: 5px; left: 3px; height: 10px; \
+             margin: 0; } \
+             .box { position: relative; margin-left: 10px; width: 50px; \
+             height: 40px; padding: 5px; } \
+             .abs { position: absolute; right: 0; bottom: 0; width: 10px; \
+             height: 10px; } \
+             .static { position: absolute; width: 5px; height: 5px; } \
+             .fixed { position: fixed; left: 0; top: 0; right: 0; height: 7px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let viewport = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 200.0,
+            height: 100.0,
+        };
+        layout_box
+            .layout_root(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        let position = |layout_box: &LayoutBox| {
+            let content = layout_box.dimensions.content;
+            (content.x, content.y)
+        };
+        // The relative offset doesn't affect the following boxes.
+        assert_eq!(position(&layout_box.children[0]), (3.0, 5.0));
+        let container = &layout_box.children[1];
+        assert_eq!(position(container), (15.0, 15.0));
+        // Absolutely positioned boxes are placed in the padding box of the
+        // container, or where they would have been in normal flow.
+        assert_eq!(position(&container.children[0]), (60.0, 50.0));
+        assert_eq!(position(&container.children[1]), (15.0, 15.0));
+        // Fixed boxes are placed in the viewport.
+        let fixed = &layout_box.children[2].dimensions.content;
+        assert_eq!((fixed.x, fixed.y, fixed.width), (0.0, 0.0, 200.0));
+        assert_eq!(layout_box.dimensions.content.height, 60.0);
+    }
+
+    #[test]
+    fn test_grid_placement_and_track_sizing() {
+        let root = HTMLParser::parse(
+            "<div><div class=\"a\"></div><div></div><div class=\"c\"></div>\
+             <div class=\"d\"></div><div></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { display: grid; grid-template-columns: 100px 1fr 2fr; \
+             grid-template-rows: 50px; gap: 10px; } \
+             div div { display: block; } \
+             .a { grid-column: 1 / 3; } \
+             .c { grid-row: 2 / span 2; grid-column: 3; height: 100px; } \
+             .d { height: 30px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 300.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        let rect = |index: usize| {
+            let content = layout_box.children[index].dimensions.content;
+            (content.x, content.y, content.width, content.height)
+        };
+        // The `fr` columns share the 180px left by the fixed column and the
+        // gaps.
+        assert_eq!(rect(0), (0.0, 0.0, 170.0, 50.0));
+        assert_eq!(rect(1), (180.0, 0.0, 120.0, 50.0));
+        // The spanning item grows the last implicit row.
+        assert_eq!(rect(2), (180.0, 60.0, 120.0, 100.0));
+        assert_eq!(rect(3), (0.0, 60.0, 100.0, 30.0));
+        assert_eq!(rect(4), (110.0, 60.0, 60.0, 30.0));
+        assert_eq!(layout_box.dimensions.content.height, 160.0);
+    }
+
+    #[test]
+    fn test_grid_lines_are_clamped() {
+        // Lines and spans past the limit of the grid are clamped into it.
+        assert_eq!(
+            resolve_grid_lines(
+                (GridLine::Line(1_000_000_000), GridLine::Auto),
+                1
+            ),
+            (Some(9999), 1)
+        );
+        assert_eq!(
+            resolve_grid_lines(
+                (GridLine::Line(2), GridLine::Span(u32::MAX)),
+                1
+            ),
+            (Some(1), 9999)
+        );
+        assert_eq!(
+            resolve_grid_lines(
+                (
+                    GridLine::Line(-1_000_000_000),
+                    GridLine::Line(1_000_000_000)
+                ),
+                3
+            ),
+            (Some(0), 10000)
+        );
+        assert_eq!(
+            resolve_grid_lines((GridLine::Span(u32::MAX), GridLine::Auto), 3),
+            (None, 10000)
+        );
+
+        // Huge lines are laid out without making a huge grid.
+        let root = HTMLParser::parse(
+            "<div><div class=\"g\"><p class=\"row\"></p></div>\
+             <div class=\"g\"><p class=\"column\"></p><p></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            ".g { display: grid; grid-auto-rows: 10px; } \
+             p { margin: 0; height: 10px; } \
+             .row { grid-row: 1000000000; } \
+             .column { grid-column: 1000000000 / span 5; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        let areas = |index: usize| {
+            let styled = layout_box.children[index].style_node().unwrap();
+            place_grid_items(
+                &styled.style,
+                styled.children.iter().map(|child| child.style.as_ref()),
+            )
+        };
+        assert_eq!(
+            areas(0),
+            [GridArea {
+                column: 0,
+                column_span: 1,
+                row: 9999,
+                row_span: 1,
+            }]
+        );
+        assert_eq!(
+            areas(1)[0],
+            GridArea {
+                column: 9999,
+                column_span: 1,
+                row: 0,
+                row_span: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_multicol_columns() {
+        let root = HTMLParser::parse(
+            "<div><div class=\"count\"><p></p><p></p><p></p><p></p><p></p>\
+             <p></p></div><div class=\"width\"><p></p><p></p><p></p><p></p>\
+             </div><div class=\"both\"><p></p><p></p><p></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; height: 10px; } \
+             .count, .width, .both { column-gap: 10px; } \
+             .count { column-count: 3; } .width { column-width: 150px; } \
+             .both { column-count: 3; column-width: 150px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 320.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        // The height of each container, and the position of each child in
+        // it with its width.
+        let columns = |index: usize| {
+            let container: &LayoutBox = &layout_box.children[index];
+            let content = container.dimensions.content;
+            let children: Vec<_> = container
+                .children
+                .iter()
+                .map(|child| {
+                    let child = child.dimensions.content;
+                    (child.x - content.x, child.y - content.y, child.width)
+                })
+                .collect();
+            (content.height, children)
+        };
+        // Three columns share the width, and the content is balanced
+        // between them.
+        assert_eq!(
+            columns(0),
+            (
+                20.0,
+                vec![
+                    (0.0, 0.0, 100.0),
+                    (0.0, 10.0, 100.0),
+                    (110.0, 0.0, 100.0),
+                    (110.0, 10.0, 100.0),
+                    (220.0, 0.0, 100.0),
+                    (220.0, 10.0, 100.0),
+                ]
+            )
+        );
+        // As many columns as are at least as wide as `column-width` fit,
+        // and they are widened to share the width.
+        assert_eq!(
+            columns(1),
+            (
+                20.0,
+                vec![
+                    (0.0, 0.0, 155.0),
+                    (0.0, 10.0, 155.0),
+                    (165.0, 0.0, 155.0),
+                    (165.0, 10.0, 155.0),
+                ]
+            )
+        );
+        // `column-count` is the most columns with both, and the columns
+        // grow until the content fits into that many.
+        assert_eq!(
+            columns(2),
+            (
+                20.0,
+                vec![
+                    (0.0, 0.0, 155.0),
+                    (0.0, 10.0, 155.0),
+                    (165.0, 0.0, 155.0)
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_table_columns_fit_cells() {
+        let root = HTMLParser::parse(
+            "<div><table><tr><td>aaaa</td><td>b</td></tr>\
+             <tr><td colspan=\"2\">cccc dddd eeee</td></tr></table>\
+             <div class=\"t\"><p class=\"w\">x</p><p>y</p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } \
+             table { border-spacing: 4px; } \
+             td { padding: 0; } \
+             .t { display: table; table-layout: fixed; width: 100px; \
+             border-spacing: 4px; } \
+             p { display: table-cell; margin: 0; } \
+             .w { width: 20px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        let rect = |layout_box: &LayoutBox| {
+            let content = layout_box.dimensions.content;
+            (content.x, content.y, content.width, content.height)
+        };
+        // The spanning cell widens both columns evenly, and the table is as
+        // wide as its columns and the spacing around them.
+        let table = &layout_box.children[0];
+        assert_eq!(rect(table), (0.0, 0.0, 92.0, 36.0));
+        let rows = &table.children;
+        assert_eq!(rect(&rows[0].children[0]), (4.0, 4.0, 49.0, 12.0));
+        assert_eq!(rect(&rows[0].children[1]), (57.0, 4.0, 31.0, 12.0));
+        assert_eq!(rect(&rows[1].children[0]), (4.0, 20.0, 84.0, 12.0));
+        assert_eq!(line_texts(&rows[1].children[0]), ["cccc dddd eeee"]);
+
+        // Cells outside a row are wrapped in an anonymous one, and with
+        // fixed layout, the columns are sized by the first row.
+        let table = &layout_box.children[1];
+        assert!(matches!(table.children[0].box_type, BoxType::Anonymous(_)));
+        let cells = &table.children[0].children;
+        assert_eq!(rect(&cells[0]), (4.0, 40.0, 20.0, 12.0));
+        assert_eq!(rect(&cells[1]), (28.0, 40.0, 68.0, 12.0));
+        assert_eq!(layout_box.dimensions.content.height, 56.0);
+    }
+
+    #[test]
+    fn test_margins_collapse() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"empty\"></p><p class=\"b\"></p>\
+             <div class=\"wrap\"><p class=\"c\"></p></div><p class=\"neg\"></p>\
+             <div class=\"padded\"><p class=\"c\"></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { height: 10px; margin: 0; } \
+             .a { margin-bottom: 20px; } \
+             .empty { height: auto; margin: 30px 0; } \
+             .b { margin: 10px 0 5px; } \
+             .wrap { margin-top: 15px; } \
+             .c { margin: 25px 0 10px; } \
+             .neg { margin-top: -4px; } \
+             .padded { padding: 1px 0; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        let y = |layout_box: &LayoutBox| layout_box.dimensions.content.y;
+        let children = &layout_box.children;
+        // Sibling margins collapse to the largest one, also through an
+        // empty block.
+        assert_eq!(y(&children[1]), 40.0);
+        assert_eq!(y(&children[2]), 40.0);
+        // The margins of the first and last child collapse with the
+        // margins of their parent.
+        let wrap = &children[3];
+        assert_eq!((y(wrap), y(&wrap.children[0])), (75.0, 75.0));
+        assert_eq!(wrap.dimensions.content.height, 10.0);
+        // A negative margin is subtracted from the positive one.
+        assert_eq!(y(&children[4]), 91.0);
+        // Padding separates the margins of a parent and its children.
+        let padded = &children[5];
+        assert_eq!((y(padded), y(&padded.children[0])), (102.0, 127.0));
+        assert_eq!(padded.dimensions.content.height, 45.0);
+        assert_eq!(layout_box.dimensions.content.height, 148.0);
+    }
+
+    #[test]
+    fn test_min_and_max_sizes() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"max\"></p><p class=\"min\"></p>\
+             <p class=\"short\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; } \
+             .max { max-width: 50px; margin: 0 auto; min-height: 20px; } \
+             .min { width: 10px; min-width: 30px; max-width: 20px; } \
+             .short { height: 40px; max-height: 10px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        let children = &layout_box.children;
+        // The auto margins are resolved again for the clamped width.
+        let max = children[0].dimensions;
+        assert_eq!((max.content.width, max.margin.left), (50.0, 75.0));
+        assert_eq!(max.content.height, 20.0);
+        // The minimum wins over the maximum.
+        let min = children[1].dimensions;
+        assert_eq!((min.content.width, min.margin.right), (30.0, 170.0));
+        assert_eq!(children[2].dimensions.content.height, 10.0);
+        assert_eq!(layout_box.dimensions.content.height, 30.0);
+    }
+
+    #[test]
+    fn test_percentages_resolve_against_containing_block() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"half\"></p><div class=\"fixed\"><p></p></div>\
+             <div><p></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; height: 50%; } \
+             .half { width: 50%; padding: 10%; height: auto; } \
+             .fixed { height: 100px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut layout_box = build_layout_tree(&styled);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        layout_box.layout(viewport, &mut LayoutContext::new(&MonospaceMetrics));
+
+        // Vertical paddings are also percentages of the width.
+        let half = layout_box.children[0].dimensions;
+        assert_eq!((half.content.x, half.content.y), (20.0, 20.0));
+        assert_eq!((half.content.width, half.padding.bottom), (100.0, 20.0));
+        // Percentage heights need a containing block with a definite
+        // height, and are `auto` otherwise.
+        let height = |index: usize| {
+            layout_box.children[index].children[0]
+                .dimensions
+                .content
+                .height
+        };
+        assert_eq!(height(1), 50.0);
+        assert_eq!(height(2), 0.0);
+        assert_eq!(layout_box.dimensions.content.height, 140.0);
+    }
+
+    #[test]
+    fn test_layout_tree_seeds_viewport() {
+        let root = HTMLParser::parse(
+            "<div><p>a</p><p class=\"fixed\">b</p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { height: 50%; } p { margin: 0; font-size: 10px; } \
+             .fixed { position: fixed; bottom: 0; right: 0; width: 20px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        viewport.content.height = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        // The root is as wide as the viewport, and its percentage height
+        // resolves against the viewport height.
+        let root = layout_box.dimensions.content;
+        assert_eq!((root.width, root.height), (200.0, 50.0));
+        assert_eq!(line_texts(&layout_box.children[0]), ["a"]);
+        // Fixed boxes are positioned in the viewport.
+        let fixed = layout_box.children[1].dimensions.content;
+        assert_eq!((fixed.x, fixed.y), (180.0, 88.0));
+    }
+
+    #[test]
+    fn test_replaced_elements() {
+        let root = HTMLParser::parse(
+            "<div>ab<img width=\"20\" height=\"10\"></img>cd<img></img>\
+             <div><img class=\"block\" height=\"25\"></img></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } img { max-width: 100%; } \
+             .block { display: block; margin: 0 auto; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let images = HashMap::from([
+            (root.children[3].node_id, (400.0, 200.0)),
+            (root.children[4].children[0].node_id, (400.0, 200.0)),
+        ]);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree_with_metrics(
+            &styled,
+            viewport,
+            &MonospaceMetrics,
+            &images,
+        );
+
+        let rect = |layout_box: &LayoutBox| {
+            let content = layout_box.dimensions.content;
+            (content.x, content.y, content.width, content.height)
+        };
+        // Images are sized by their attributes, and placed on lines with
+        // their bottom on the baseline, which makes the line taller.
+        let anonymous = &layout_box.children[0];
+        assert_eq!(rect(&anonymous.children[1]), (12.0, 0.0, 20.0, 10.0));
+        assert_eq!(line_texts(anonymous), ["abcd", ""]);
+        assert_eq!(anonymous.lines[0].rect.height, 13.0);
+        // A large image shrinks to fit, keeping its aspect ratio, and
+        // moves to the next line.
+        assert_eq!(rect(&anonymous.children[3]), (0.0, 13.0, 100.0, 50.0));
+        // A block image is centered by its margins.
+        let block = &layout_box.children[1].children[0];
+        assert_eq!(rect(block), (25.0, 66.0, 50.0, 25.0));
+        assert_eq!(layout_box.dimensions.content.height, 91.0);
+    }
+
+    #[test]
+    fn test_inline_blocks_shrink_to_fit() {
+        let root = HTMLParser::parse(
+            "<div>ab<span>cd ef</span>gh<span>aaaa bbbb cccc dddd eeee</span>\
+             <div><p>xy</p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } span { display: inline-block; } \
+             p { float: left; margin: 0; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        let rect = |layout_box: &LayoutBox| {
+            let content = layout_box.dimensions.content;
+            (content.x, content.y, content.width, content.height)
+        };
+        // An inline-block is as wide as its content, and text flows on
+        // both sides of it.
+        let anonymous = &layout_box.children[0];
+        assert_eq!(rect(&anonymous.children[1]), (12.0, 0.0, 30.0, 12.0));
+        assert_eq!(line_texts(&anonymous.children[1]), ["cd ef"]);
+        // One that doesn't fit moves to the next line, and breaks its own
+        // lines to fit the containing block.
+        let wide = &anonymous.children[3];
+        assert_eq!(rect(wide), (0.0, 12.0, 100.0, 24.0));
+        assert_eq!(line_texts(wide), ["aaaa bbbb cccc", "dddd eeee"]);
+        assert_eq!(line_texts(anonymous), ["abgh", ""]);
+        // Floats shrink to fit too.
+        let float = &layout_box.children[1].children[0];
+        assert_eq!(rect(float), (0.0, 36.0, 12.0, 12.0));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let root = HTMLParser::parse(
+            "<div><div class=\"scroll\">a b c d e f</div><div class=\"wide\">\
+             </div><div class=\"bfc\"><p></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } \
+             .scroll { width: 20px; height: 20px; overflow: hidden; } \
+             .wide { width: 300px; height: 10px; } .bfc { overflow: auto; } \
+             p { float: left; width: 10px; height: 30px; margin: 0; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        let rect = |rect: Rectangle| (rect.x, rect.y, rect.width, rect.height);
+        // A scroll container records the overflow of its content, and clips
+        // it to its padding box.
+        let scroll = &layout_box.children[0];
+        assert_eq!(line_texts(scroll), ["a b", "c d", "e f"]);
+        assert_eq!(
+            rect(scroll.dimensions.scrollable_overflow),
+            (0.0, 0.0, 20.0, 36.0)
+        );
+        assert_eq!(scroll.clip_rect().map(rect), Some((0.0, 0.0, 20.0, 20.0)));
+        assert!(layout_box.clip_rect().is_none());
+        // A scroll container establishes a block formatting context, which
+        // contains its floats.
+        assert_eq!(layout_box.children[2].dimensions.content.height, 30.0);
+        // Only the border box of a scroll container overflows its parent.
+        assert_eq!(
+            rect(layout_box.dimensions.scrollable_overflow),
+            (0.0, 0.0, 300.0, 60.0)
+        );
+    }
+
+    #[test]
+    fn test_stacking_contexts() {
+        let root = HTMLParser::parse(
+            "<div id=\"root\"><p id=\"flow\"></p><p id=\"behind\"></p>\
+             <p id=\"float\"></p><p id=\"top\"></p><div id=\"positioned\">\
+             <p id=\"inside\"></p><p id=\"nested\"></p></div>\
+             <div id=\"faded\"><p id=\"group\"></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "#behind { position: relative; z-index: -1; } \
+             #float { float: left; } #top { position: absolute; z-index: 2; } \
+             #positioned { position: relative; } \
+             #nested { position: absolute; z-index: 1; } \
+             #faded { opacity: 0.5; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        let context = StackingContext::new(&layout_box);
+        let ids: Vec<_> = context
+            .paint_order()
+            .into_iter()
+            .map(|layout_box| {
+                match &layout_box.get_style_node().node.node_type {
+                    NodeType::Element(elem) => elem.id().unwrap().as_str(),
+                    _ => panic!("Expected an element."),
+                }
+            })
+            .collect();
+        // Negative contexts are painted behind the boxes in normal flow,
+        // positioned boxes in front of the floats, and the descendants of
+        // a positioned box in front of it, unless they are positioned
+        // themselves.
+        assert_eq!(
+            ids,
+            [
+                "root",
+                "behind",
+                "flow",
+                "float",
+                "positioned",
+                "inside",
+                "faded",
+                "group",
+                "nested",
+                "top"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_intrinsic_widths() {
+        // Counts the characters that are measured.
+        struct CountingMetrics(std::cell::Cell<usize>);
+
+        impl FontMetrics for CountingMetrics {
+            fn advance(&self, c: char, style: &ComputedStyle) -> f32 {
+                self.0.set(self.0.get() + 1);
+                MonospaceMetrics.advance(c, style)
+            }
+        }
+
+        let root = HTMLParser::parse(
+            "<div><p class=\"fit\">ab cd</p><p class=\"min\">ab cd</p>\
+             <span><span><span>abc</span></span></span></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 0; } \
+             .fit { width: fit-content; margin: 0 auto; } \
+             .min { width: min-content; } span { display: inline-block; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let metrics = CountingMetrics(Default::default());
+        let layout_box =
+            layout_tree_with_metrics(&styled, viewport, &metrics, &NoImages);
+
+        let rect = |layout_box: &LayoutBox| {
+            let content = layout_box.dimensions.content;
+            (content.x, content.width)
+        };
+        // A box that fits its content can be centered by its margins.
+        assert_eq!(rect(&layout_box.children[0]), (35.0, 30.0));
+        assert_eq!(rect(&layout_box.children[1]), (0.0, 12.0));
+        assert_eq!(line_texts(&layout_box.children[1]), ["ab", "cd"]);
+        let inner = &layout_box.children[2].children[0].children[0];
+        assert_eq!(rect(inner), (0.0, 18.0));
+        // Each character is measured once for the intrinsic widths, however
+        // deeply the boxes that shrink to fit it are nested, and once more
+        // when it is laid out in a line.
+        assert_eq!(metrics.0.get(), 2 * (5 + 5 + 3));
+    }
+
+    #[test]
+    fn test_vertical_align() {
+        let root = HTMLParser::parse(
+            "<div><div>ab<span>cd</span></div>\
+             <div>ab<img width=\"30\" height=\"30\"></img></div>\
+             <div>ab<img class=\"top\" width=\"40\" height=\"40\"></img></div>\
+             <div>ab<img class=\"bottom\" width=\"40\" height=\"40\"></img>\
+             </div>\
+             <div>ab<img class=\"middle\" width=\"20\" height=\"20\"></img>\
+             </div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { display: block; font-size: 10px; } span { font-size: 20px; } \
+             .top { vertical-align: top; } .bottom { vertical-align: bottom; } \
+             .middle { vertical-align: middle; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        // The line height of each piece of content is split around a shared
+        // baseline.
+        let line = &layout_box.children[0].lines[0];
+        assert_eq!((line.rect.height, line.baseline), (24.0, 18.0));
+        let glyphs: Vec<_> = line
+            .fragments
+            .iter()
+            .map(|fragment| fragment.rect.y)
+            .collect();
+        assert_eq!(glyphs, [10.0, 2.0]);
+
+        // Each case is the position of the line, the image, and the glyphs.
+        let positions = |layout_box: &LayoutBox| {
+            let line = &layout_box.lines[0];
+            (
+                line.rect.y,
+                line.rect.height,
+                layout_box.children[1].dimensions.content.y,
+                line.fragments[0].rect.y,
+            )
+        };
+        // An image sits on the baseline.
+        assert_eq!(
+            positions(&layout_box.children[1]),
+            (24.0, 33.0, 24.0, 46.0)
+        );
+        // Images aligned with the top or the bottom of the line leave the
+        // text at the other end.
+        assert_eq!(
+            positions(&layout_box.children[2]),
+            (57.0, 40.0, 57.0, 58.0)
+        );
+        assert_eq!(
+            positions(&layout_box.children[3]),
+            (97.0, 40.0, 97.0, 126.0)
+        );
+        // A middle aligned image is centered on half the x-height above the
+        // baseline.
+        assert_eq!(
+            positions(&layout_box.children[4]),
+            (137.0, 20.0, 137.0, 141.5)
+        );
+    }
+
+    #[test]
+    fn test_relayout() {
+        // Records the words that are measured, which shows the boxes whose
+        // content was laid out.
+        #[derive(Default)]
+        struct RecordingMetrics(std::cell::RefCell<Vec<String>>);
+
+        impl RecordingMetrics {
+            fn words(&self) -> Vec<String> {
+                let mut words = self.0.take();
+                words.sort();
+                words.dedup();
+                words
+            }
+        }
+
+        impl FontMetrics for RecordingMetrics {
+            fn advance(&self, c: char, style: &ComputedStyle) -> f32 {
+                MonospaceMetrics.advance(c, style)
+            }
+
+            fn measure(&self, text: &str, style: &ComputedStyle) -> f32 {
+                if !text.trim().is_empty() {
+                    self.0.borrow_mut().push(text.to_string());
+                }
+                MonospaceMetrics.measure(text, style)
+            }
+        }
+
+        // The content rectangles of all boxes and lines in tree order.
+        fn geometry(layout_box: &LayoutBox) -> Vec<(f32, f32, f32, f32)> {
+            let rect = |r: Rectangle| (r.x, r.y, r.width, r.height);
+            let mut rects = vec![rect(layout_box.dimensions.content)];
+            rects.extend(layout_box.lines.iter().map(|line| rect(line.rect)));
+            for child in &layout_box.children {
+                rects.extend(geometry(child));
+            }
+            rects
+        }
+
+        let root = HTMLParser::parse(
+            "<div><p>one</p><p>two<img></img></p><p class=\"rel\">three</p>\
+             </div>"
+                .to_string(),
+        );
+        let css = "div { font-size: 10px; } p { margin: 0; } \
+                   .rel { position: relative; }";
+        let restyle = |extra: &str| {
+            let stylesheets = [CSSParser::parse(format!("{css} {extra}"))];
+            apply(&root, &stylesheets, &MediaContext::default())
+        };
+        let styled = restyle("");
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        let metrics = RecordingMetrics::default();
+        let mut images = HashMap::new();
+        let mut layout_box =
+            layout_tree_with_metrics(&styled, viewport, &metrics, &images);
+        assert_eq!(metrics.words(), ["one", "three", "two"]);
+
+        // Nothing is laid out again while nothing changed.
+        layout_box.relayout_with_metrics(viewport, &metrics, &images);
+        assert!(metrics.words().is_empty());
+
+        // When an image loads, only its paragraph is laid out again, and
+        // the one after it moves up.
+        let img = root.children[1].children[1].node_id;
+        images.insert(img, (20.0, 10.0));
+        assert!(layout_box.invalidate(img, Invalidation::Layout));
+        assert!(
+            layout_box.dirty.children && layout_box.children[1].dirty.children
+        );
+        layout_box.relayout_with_metrics(viewport, &metrics, &images);
+        assert_eq!(metrics.words(), ["two"]);
+        assert!(layout_box.dirty.is_clean());
+        assert_eq!(layout_box.children[2].dimensions.content.y, 25.0);
+        let fresh = layout_tree_with_metrics(
+            &styled,
+            viewport,
+            &MonospaceMetrics,
+            &images,
+        );
+        assert_eq!(geometry(&layout_box), geometry(&fresh));
+
+        // Painted properties don't affect layout, and a relative offset
+        // only moves the box.
+        let moved = restyle(".rel { left: 5px; color: red; }");
+        layout_box.restyle(&moved);
+        let dirty = Dirty {
+            position: true,
+            ..Default::default()
+        };
+        assert_eq!(layout_box.children[2].dirty, dirty);
+        assert!(layout_box.children[0].dirty.is_clean());
+        layout_box.relayout_with_metrics(viewport, &metrics, &images);
+        assert!(metrics.words().is_empty());
+        assert_eq!(layout_box.children[2].dimensions.content.x, 5.0);
+        let fresh = layout_tree_with_metrics(
+            &moved,
+            viewport,
+            &MonospaceMetrics,
+            &images,
+        );
+        assert_eq!(geometry(&layout_box), geometry(&fresh));
+
+        // Other properties lay the box out again.
+        let resized = restyle(".rel { font-size: 20px; }");
+        layout_box.restyle(&resized);
+        layout_box.relayout_with_metrics(viewport, &metrics, &images);
+        assert_eq!(metrics.words(), ["three"]);
+        let fresh = layout_tree_with_metrics(
+            &resized,
+            viewport,
+            &MonospaceMetrics,
+            &images,
+        );
+        assert_eq!(geometry(&layout_box), geometry(&fresh));
+
+        // A different viewport width lays out everything again.
+        viewport.content.width = 100.0;
+        layout_box.relayout_with_metrics(viewport, &metrics, &images);
+        assert_eq!(metrics.words(), ["one", "three", "two"]);
+    }
+
+    // Lay out each HTML file in `testdata/layout`, with the stylesheet of
+    // the same name, and compare the dump of its layout tree with the text
+    // file of the same name. Run with `UPDATE_GOLDEN=1` to write the dumps
+    // to the text files instead, and review the changes as diffs.
+    #[test]
+    fn test_golden_layouts() {
+        use std::{fs, path::Path};
+
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/layout");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        let mut failures = Vec::new();
+        for path in paths {
+            let html = fs::read_to_string(&path).unwrap();
+            let css = fs::read_to_string(path.with_extension("css"))
+                .unwrap_or_default();
+            let root = HTMLParser::parse(html);
+            let stylesheets = [CSSParser::parse(css)];
+            let styled = apply(&root, &stylesheets, &MediaContext::default());
+            let mut viewport = Dimensions::default();
+            viewport.content.width = 400.0;
+            viewport.content.height = 300.0;
+            let dump = layout_tree(&styled, viewport).dump();
+
+            let expected_path = path.with_extension("txt");
+            if update {
+                fs::write(&expected_path, &dump).unwrap();
+            } else if fs::read_to_string(&expected_path).ok() != Some(dump) {
+                failures.push(expected_path.display().to_string());
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "Layouts differ from the golden files, which `UPDATE_GOLDEN=1` \
+             updates: {failures:?}"
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_layout() {
+        // Children depend on each other through margins that collapse
+        // through empty boxes, floats, and clearance.
+        let root = HTMLParser::parse(
+            "<div><p>one</p><div class=\"empty\"></div>\
+             <div><p>two</p><p class=\"float\">float</p></div>\
+             <p>three wraps around the float</p><p class=\"clear\">four</p>\
+             <div class=\"bfc\"><p>five</p><p>six</p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 10px 0; } \
+             .empty { margin: 25px 0; } .float { float: left; width: 30px; } \
+             .clear { clear: left; } .bfc { overflow: hidden; margin: 5px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+
+        let parallel = layout_tree_parallel(
+            &styled,
+            viewport,
+            &MonospaceMetrics,
+            &NoImages,
+        );
+        assert_eq!(parallel.dump(), layout_tree(&styled, viewport).dump());
+    }
+
+    #[test]
+    fn test_anonymous_blocks() {
+        let root = HTMLParser::parse(
+            "<div>ab<span>cd<p>ef</p>gh</span><p>ij</p><b class=\"f\"></b>\
+             <p>kl</p>mn</div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { display: block; } .f { float: left; }".to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let layout_box = build_layout_tree(&styled);
+
+        let kinds = |layout_box: &LayoutBox| -> Vec<String> {
+            layout_box
+                .children
+                .iter()
+                .map(|child| match child.box_type {
+                    BoxType::BlockNode(node) => {
+                        format!("block {}", node.label())
+                    }
+                    BoxType::InlineNode(node) => {
+                        format!("inline {}", node.label())
+                    }
+                    BoxType::InlineBlockNode(node) => {
+                        format!("inline-block {}", node.label())
+                    }
+                    BoxType::Anonymous(_) => "anonymous".to_string(),
+                })
+                .collect()
+        };
+        // The span is split around the paragraph in it, and the runs of
+        // inline content are wrapped in anonymous blocks. The float between
+        // paragraphs isn't.
+        assert_eq!(
+            kinds(&layout_box),
+            [
+                "anonymous",
+                "block p",
+                "anonymous",
+                "block p",
+                "block b",
+                "block p",
+                "anonymous"
+            ]
+        );
+        assert_eq!(
+            kinds(&layout_box.children[0]),
+            ["inline \"ab\"", "inline span"]
+        );
+        assert_eq!(kinds(&layout_box.children[2]), ["inline span"]);
+        assert_eq!(kinds(&layout_box.children[6]), ["inline \"mn\""]);
+    }
+
+    #[test]
+    fn test_right_to_left() {
+        let root = HTMLParser::parse(
+            "<div dir=\"rtl\"><p class=\"narrow\"></p>\
+             <p>ab <span class=\"box\"></span> cd efgh ijkl mn</p>\
+             <div class=\"narrow\" dir=\"ltr\"></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 0; } \
+             .narrow { width: 40px; height: 10px; margin: 0 10px; } \
+             .box { display: inline-block; width: 10px; height: 10px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        // The margins of an over-constrained block are resolved against
+        // its left margin in a right-to-left containing block.
+        let narrow = &layout_box.children[0].dimensions;
+        assert_eq!((narrow.margin.left, narrow.content.x), (50.0, 50.0));
+        // Lines start at the right edge, and content is placed from right
+        // to left.
+        let paragraph = &layout_box.children[1];
+        assert_eq!(line_texts(paragraph), ["ab cd efgh ijkl", "mn"]);
+        let xs: Vec<_> = paragraph.lines[0]
+            .fragments
+            .iter()
+            .map(|fragment| fragment.rect.x)
+            .collect();
+        assert_eq!(xs, [82.0, 0.0]);
+        assert_eq!(paragraph.children[1].dimensions.content.x, 72.0);
+        assert_eq!(paragraph.lines[1].fragments[0].rect.x, 88.0);
+        // The direction of a box itself doesn't change how its own margins
+        // are resolved.
+        let ltr = &layout_box.children[2].dimensions;
+        assert_eq!((ltr.margin.left, ltr.content.x), (50.0, 50.0));
+    }
+
+    #[test]
+    fn test_text_align() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"center\">ab cd</p><p align=\"right\">ab</p>\
+             <p class=\"justify\">aaaa <b>bbbb</b>, cccc dddd</p>\
+             <p class=\"end\" dir=\"rtl\">ab</p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 0; } \
+             .center { text-align: center; } .justify { text-align: justify; } \
+             .end { text-align: end; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        let rects = |layout_box: &LayoutBox, line: usize| -> Vec<(f32, f32)> {
+            layout_box.lines[line]
+                .fragments
+                .iter()
+                .map(|fragment| (fragment.rect.x, fragment.rect.width))
+                .collect()
+        };
+        assert_eq!(rects(&layout_box.children[0], 0), [(35.0, 30.0)]);
+        assert_eq!(rects(&layout_box.children[1], 0), [(88.0, 12.0)]);
+        // The spaces of a justified line are stretched to fill it, and the
+        // last line is aligned with the start.
+        let justified = &layout_box.children[2];
+        assert_eq!(line_texts(justified), ["aaaa bbbb, cccc", "dddd"]);
+        assert_eq!(
+            rects(justified, 0),
+            [(0.0, 35.0), (35.0, 24.0), (59.0, 41.0)]
+        );
+        assert_eq!(justified.lines[0].fragments[0].word_spacing, 5.0);
+        assert_eq!(rects(justified, 1), [(0.0, 24.0)]);
+        // The end of a right-to-left line is its left edge.
+        assert_eq!(rects(&layout_box.children[3], 0), [(0.0, 12.0)]);
+    }
+
+    #[test]
+    fn test_paginate() {
+        let root = HTMLParser::parse(
+            "<div><p>aa bb cc dd ee</p><p class=\"page\">ff gg</p>\
+             <p class=\"avoid\">hh ii jj</p><div class=\"tall\"></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 0; width: 12px; } \
+             .page { page-break-before: always; } \
+             .avoid { break-before: avoid; } .tall { height: 80px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        let pages: Vec<_> = layout_box
+            .paginate(50.0)
+            .iter()
+            .map(|page| (page.y, page.height))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                // Breaking after one line or before the last one would
+                // leave too few lines on a page.
+                (0.0, 36.0),
+                // A forced break ends the page early.
+                (36.0, 24.0),
+                // When every break that fits is avoided, the last one is
+                // taken.
+                (60.0, 48.0),
+                (108.0, 12.0),
+                // A box taller than a page is sliced.
+                (120.0, 50.0),
+                (170.0, 30.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_box_map() {
+        let root = HTMLParser::parse(
+            "<div><p>ab <span>cd ef</span></p><img></img></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 0; } \
+             img { display: block; width: 20px; height: 10px; padding: 1px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        // Five characters of the monospace metrics fit in a line.
+        viewport.content.width = 30.0;
+        let layout_root = layout_tree(&styled, viewport);
+        let map = BoxMap::new(&layout_root);
+
+        let (p, img) = (&root.children[0], &root.children[1]);
+        let (text, span) = (&p.children[0], &p.children[1]);
+        let p_box = map.get(p.node_id).unwrap();
+        assert!(std::ptr::eq(p_box, &layout_root.children()[0]));
+        assert_eq!(p_box.node_id(), Some(p.node_id));
+        assert_eq!(p_box.dimensions().content.height, 24.0);
+        assert_eq!(map.get_all(p.node_id).count(), 1);
+
+        let rects = |node_id| {
+            map.rects(node_id)
+                .iter()
+                .map(|rect| dump_rect(*rect))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rects(text.node_id), ["(0, 1) 18x10"]);
+        // The span wraps onto the second line.
+        assert_eq!(rects(span.node_id), ["(18, 1) 12x10", "(0, 13) 12x10"]);
+        assert_eq!(rects(img.node_id), ["(0, 24) 22x12"]);
+        assert!(map.get(root.node_id).is_some());
+    }
+
+    #[test]
+    fn test_viewport_relative_heights() {
+        let root = HTMLParser::parse(
+            "<div><p>a</p><p class=\"half\"></p><p class=\"fill\"></p>\
+             <div class=\"tall\"><p class=\"half\"></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { position: relative; font-size: 10px; } p { margin: 0; } \
+             .half { height: 50%; } \
+             .fill { position: absolute; top: 0; bottom: 0; width: 5vw; } \
+             .tall { height: 20vh; } \
+             div > .half { position: absolute; width: 10px; }"
+                .to_string(),
+        )];
+        let media = MediaContext {
+            viewport_width: 200.0,
+            viewport_height: 100.0,
+            ..Default::default()
+        };
+        let styled = apply(&root, &stylesheets, &media);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        viewport.content.height = 100.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        // The absolutely positioned children don't add to the auto height
+        // of their containing block, but resolve percentages and offsets
+        // against the height it ends up with.
+        let heights: Vec<_> = layout_box
+            .children
+            .iter()
+            .map(|child| child.dimensions.content.height)
+            .collect();
+        assert_eq!(heights, [12.0, 16.0, 32.0, 20.0]);
+        assert_eq!(layout_box.children[2].dimensions.content.width, 10.0);
+        assert_eq!(
+            layout_box.children[3].children[0].dimensions.content.height,
+            10.0
+        );
+    }
+
+    #[test]
+    fn test_transforms() {
+        let root = HTMLParser::parse(
+            "<div><div class=\"t\"><p class=\"fixed\"></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } p { margin: 0; } \
+             .t { margin: 10px 20px; width: 100px; height: 50px; \
+             transform: translate(10px, 50%) scale(2); \
+             transform-origin: left top; } \
+             .fixed { position: fixed; top: 5px; left: 5px; width: 10px; \
+             height: 10px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        viewport.content.height = 100.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // Transforms don't affect layout, but are applied around the
+        // origin afterwards, and their percentages are of the border box.
+        let transformed = &layout_root.children[0];
+        let border_box = transformed.dimensions.border_box();
+        assert_eq!((border_box.x, border_box.y), (20.0, 10.0));
+        let quad = transformed.border_quad();
+        assert_eq!(
+            quad.points,
+            [(30.0, 35.0), (230.0, 35.0), (230.0, 135.0), (30.0, 135.0)]
+        );
+        assert!(quad.contains((100.0, 100.0)));
+        assert!(!quad.contains((25.0, 20.0)));
+        let transform = transformed.transform().unwrap();
+        let inverse = transform.inverse().unwrap();
+        assert_eq!(inverse.apply(transform.apply((3.0, 4.0))), (3.0, 4.0));
+
+        // The transformed box is the containing block of fixed descendants,
+        // which are transformed with it.
+        let fixed = &transformed.children[0];
+        let content = fixed.dimensions.content;
+        assert_eq!((content.x, content.y), (25.0, 15.0));
+        let map = BoxMap::new(&layout_root);
+        let node_id = root.children[0].children[0].node_id;
+        assert_eq!(map.quads(node_id)[0].points[0], (40.0, 45.0));
+        // It also establishes a stacking context.
+        assert_eq!(StackingContext::new(&layout_root).children.len(), 1);
+    }
+
+    #[test]
+    fn test_scroll_by() {
+        let root = HTMLParser::parse(
+            "<div class=\"s\"><p></p><p></p><p></p><p></p><p class=\"a\"></p>\
+             <p class=\"r\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } p { margin: 0; height: 10px; } \
+             .s { overflow: hidden; width: 50px; height: 20px; } \
+             .a { position: absolute; top: 0; left: 0; width: 5px; } \
+             .r { position: relative; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let mut layout_root = layout_tree(&styled, viewport);
+
+        let s = root.node_id;
+        let p = root.children[0].node_id;
+        assert_eq!(layout_root.scroll_range(), (0.0, 30.0));
+        assert!(layout_root.scroll_by(s, 0.0, 15.0));
+        assert_eq!(layout_root.scroll_offset(), (0.0, 15.0));
+        // The content moves with the offset, but not the absolute box,
+        // whose containing block is outside of the scroll container.
+        let children = &layout_root.children;
+        assert_eq!(
+            children[0].transform(),
+            Some(Transform::translate(0.0, -15.0))
+        );
+        assert_eq!(children[4].transform(), None);
+        assert_eq!(
+            children[5].transform(),
+            Some(Transform::translate(0.0, -15.0))
+        );
+
+        // The offset stays within the scrollable overflow, and only scroll
+        // containers scroll.
+        assert!(layout_root.scroll_by(s, 10.0, 100.0));
+        assert_eq!(layout_root.scroll_offset(), (0.0, 30.0));
+        assert!(!layout_root.scroll_by(s, 0.0, 1.0));
+        assert!(!layout_root.scroll_by(p, 0.0, -1.0));
+
+        // The offset is kept when the box is laid out again.
+        layout_root.relayout_with_metrics(
+            viewport,
+            &MonospaceMetrics,
+            &HashMap::new(),
+        );
+        assert_eq!(layout_root.scroll_offset(), (0.0, 30.0));
+        assert_eq!(
+            layout_root.children[0].transform(),
+            Some(Transform::translate(0.0, -30.0))
+        );
+    }
+
+    #[test]
+    fn test_baselines() {
+        let root = HTMLParser::parse(
+            "<div>a<span class=\"block\"><p>b</p><p>c</p></span>\
+             <span class=\"grid\"><p>d</p><p>e</p></span>\
+             <span class=\"scroll\"><p>f</p></span></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; } p { margin: 0; } \
+             .block { display: inline-block; } \
+             .grid { display: inline-grid; } \
+             .scroll { display: inline-block; overflow: hidden; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        let layout_box = layout_tree(&styled, viewport);
+
+        // Inline blocks export the baseline of their last line, and inline
+        // grids that of their first one, which the line aligns them on.
+        let line = &layout_box.lines[0];
+        let [_, block, grid, scroll] = &layout_box.children[..] else {
+            panic!("Expected four children");
+        };
+        assert_eq!(block.first_baseline(), Some(9.0));
+        assert_eq!(block.last_baseline(), Some(line.baseline));
+        assert_eq!(grid.first_baseline(), Some(line.baseline));
+        assert_eq!(grid.last_baseline(), Some(line.baseline + 12.0));
+        // A box that clips its content sits on its bottom margin edge.
+        let border_box = scroll.dimensions.border_box();
+        assert_eq!(border_box.y + border_box.height, line.baseline);
+        assert_eq!(layout_box.first_baseline(), Some(line.baseline));
+    }
 }
@@ -0,0 +1,40 @@
+pub mod bloom;
+#[cfg(feature = "net")]
+pub mod browser;
+pub mod computed;
+#[cfg(feature = "net")]
+pub mod cookie;
+pub mod css_parser;
+pub mod document;
+pub mod dom;
+pub mod event;
+#[cfg(feature = "fonts")]
+pub mod font;
+pub mod form;
+#[cfg(feature = "wgpu")]
+pub mod gpu;
+pub mod html_parser;
+#[cfg(feature = "net")]
+pub mod http_cache;
+pub mod image_cache;
+pub mod layout;
+pub mod loader;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod painting;
+#[cfg(feature = "net")]
+pub mod pipeline;
+pub mod presentational;
+pub mod sanitize;
+pub mod screenshot;
+#[cfg(feature = "tiny-skia")]
+pub mod skia;
+pub mod style;
+pub mod svg;
+pub mod terminal;
+pub mod text;
+pub mod url;
+pub mod visitor;
+pub mod xpath;
+
+pub use layout::{BoxMap, Dimensions, LayoutBox, Rectangle, layout_tree};
@@ -0,0 +1,413 @@
+// Resource loaders get the bytes of documents, stylesheets, and images from
+// URLs. Each scheme has its own loader, so that tests can serve resources
+// from memory and embedders can add schemes of their own, such as one for
+// assets bundled with an application.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use crate::url::{Url, percent_decode};
+
+// A resource that was loaded from a URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resource {
+    // The URL that the resource was loaded from, which differs from the
+    // requested one after redirects.
+    pub url: Url,
+    // The HTTP status of the response, which is 200 for resources that
+    // don't come from HTTP.
+    pub status: u16,
+    // The MIME type of the resource, such as `text/css; charset=utf-8`, if
+    // it is known.
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Resource {
+    // Create a resource that was loaded successfully.
+    pub fn new(url: Url, content_type: Option<&str>, data: Vec<u8>) -> Self {
+        Self {
+            url,
+            status: 200,
+            content_type: content_type.map(str::to_string),
+            data,
+        }
+    }
+
+    // Check if the resource was found, which it always was unless it came
+    // from HTTP.
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    // Get the resource of a response to a request for a URL.
+    #[cfg(feature = "net")]
+    pub fn from_response(url: &Url, response: crate::net::Response) -> Self {
+        Self {
+            url: Url::parse(&response.url).unwrap_or_else(|| url.clone()),
+            status: response.status,
+            content_type: response.header("content-type").map(str::to_string),
+            data: response.body,
+        }
+    }
+}
+
+// Loads the resources of URLs. Failures to load a resource, such as a file
+// that doesn't exist or a server that can't be reached, are errors, but
+// responses with HTTP error statuses are not.
+pub trait ResourceLoader {
+    fn load(&self, url: &Url) -> io::Result<Resource>;
+}
+
+// Loads each URL with the loader of its scheme. By default, `file:` and
+// `data:` URLs can be loaded, and `http:` and `https:` URLs too with the
+// `net` feature.
+pub struct SchemeLoader {
+    loaders: HashMap<String, Box<dyn ResourceLoader + Send + Sync>>,
+}
+
+impl SchemeLoader {
+    // Create a loader without any schemes.
+    pub fn empty() -> Self {
+        Self {
+            loaders: HashMap::new(),
+        }
+    }
+
+    // Load the URLs of a scheme with a loader, in place of the loader that
+    // the scheme had.
+    pub fn register(
+        &mut self,
+        scheme: &str,
+        loader: impl ResourceLoader + Send + Sync + 'static,
+    ) {
+        self.loaders
+            .insert(scheme.to_ascii_lowercase(), Box::new(loader));
+    }
+}
+
+impl Default for SchemeLoader {
+    fn default() -> Self {
+        let mut loader = Self::empty();
+        loader.register("file", FileLoader);
+        loader.register("data", DataLoader);
+        #[cfg(feature = "net")]
+        {
+            // Both schemes share their cookies.
+            let http = HttpLoader::default();
+            loader.register("http", http.clone());
+            loader.register("https", http);
+        }
+        loader
+    }
+}
+
+impl ResourceLoader for SchemeLoader {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        let loader = self.loaders.get(url.scheme()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported scheme of {}", url),
+            )
+        })?;
+        loader.load(url)
+    }
+}
+
+// Loads resources from memory, keyed by their URLs without fragments.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLoader {
+    resources: HashMap<Url, (Option<String>, Vec<u8>)>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a resource, replacing the one at the same URL. The URL must be
+    // absolute.
+    pub fn insert(
+        &mut self,
+        url: &str,
+        content_type: Option<&str>,
+        data: &[u8],
+    ) {
+        let url = Url::parse(url)
+            .unwrap_or_else(|| panic!("Invalid URL {}", url))
+            .without_fragment();
+        self.resources
+            .insert(url, (content_type.map(str::to_string), data.to_vec()));
+    }
+}
+
+impl ResourceLoader for MemoryLoader {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        let (content_type, data) =
+            self.resources.get(&url.without_fragment()).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No resource at {}", url),
+                )
+            })?;
+        Ok(Resource::new(
+            url.clone(),
+            content_type.as_deref(),
+            data.clone(),
+        ))
+    }
+}
+
+// The size of the largest file that `FileLoader` reads, in bytes, which
+// keeps a page from making the browser read a device or a huge file.
+pub const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+// Loads `file:` URLs from the local file system. The MIME type is guessed
+// from the extension of the file. Files larger than `MAX_FILE_SIZE` fail
+// to load.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileLoader;
+
+impl ResourceLoader for FileLoader {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        // Only files on this host can be loaded.
+        if !matches!(url.host(), Some("" | "localhost")) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unsupported host of {}", url),
+            ));
+        }
+        let path = String::from_utf8(percent_decode(url.path()))
+            .map(PathBuf::from)
+            .map_err(|error| {
+                io::Error::new(io::ErrorKind::InvalidInput, error)
+            })?;
+        let content_type = match path.extension().and_then(|e| e.to_str()) {
+            Some("html" | "htm") => Some("text/html"),
+            Some("css") => Some("text/css"),
+            Some("png") => Some("image/png"),
+            Some("jpg" | "jpeg") => Some("image/jpeg"),
+            Some("gif") => Some("image/gif"),
+            Some("svg") => Some("image/svg+xml"),
+            _ => None,
+        };
+        // The file may grow while it is read, or have no size, like a
+        // device, so the size of what is read is checked rather than the
+        // size of the file.
+        let mut data = Vec::new();
+        File::open(path)?
+            .take(MAX_FILE_SIZE + 1)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > MAX_FILE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("{} is larger than {} bytes", url, MAX_FILE_SIZE),
+            ));
+        }
+        Ok(Resource::new(url.clone(), content_type, data))
+    }
+}
+
+// Loads `data:` URLs, whose resource is in the URL itself, such as
+// `data:text/css,p%20{}` or `data:image/png;base64,iVBORw0...`.
+// SEE: https://fetch.spec.whatwg.org/#data-urls
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataLoader;
+
+impl ResourceLoader for DataLoader {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid data URL {}", url),
+            )
+        };
+        // The data may contain `?`, which starts the query of the URL.
+        let serialized = url.without_fragment().to_string();
+        let rest = &serialized[url.scheme().len() + 1..];
+        let (mime_type, data) = rest.split_once(',').ok_or_else(invalid)?;
+        let mime_type = String::from_utf8_lossy(&percent_decode(mime_type))
+            .trim()
+            .to_string();
+        let data = percent_decode(data);
+        let (mime_type, data) = match strip_base64(&mime_type) {
+            Some(mime_type) => (
+                mime_type.to_string(),
+                decode_base64(&data).ok_or_else(invalid)?,
+            ),
+            None => (mime_type, data),
+        };
+        // Without a type, the data is plain text.
+        let mime_type = if mime_type.is_empty() {
+            "text/plain;charset=US-ASCII".to_string()
+        } else if mime_type.starts_with(';') {
+            format!("text/plain{}", mime_type)
+        } else {
+            mime_type
+        };
+        Ok(Resource::new(url.clone(), Some(&mime_type), data))
+    }
+}
+
+// Remove the `;base64` parameter that ends the MIME type of a `data:` URL,
+// if it has one.
+fn strip_base64(mime_type: &str) -> Option<&str> {
+    let (rest, parameter) = mime_type.rsplit_once(';')?;
+    parameter
+        .trim()
+        .eq_ignore_ascii_case("base64")
+        .then_some(rest.trim_end())
+}
+
+// Decode base64, ignoring whitespace, with or without padding.
+// SEE: https://infra.spec.whatwg.org/#forgiving-base64-decode
+pub fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut digits: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+    if digits.len() % 4 == 0 {
+        for _ in 0..2 {
+            if digits.last() == Some(&b'=') {
+                digits.pop();
+            }
+        }
+    }
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(digits.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for digit in digits {
+        let value = match digit {
+            b'A'..=b'Z' => digit - b'A',
+            b'a'..=b'z' => digit - b'a' + 26,
+            b'0'..=b'9' => digit - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+// Loads `http:` and `https:` URLs with a client, which the clones of the
+// loader share.
+#[cfg(feature = "net")]
+#[derive(Debug, Clone, Default)]
+pub struct HttpLoader {
+    pub client: std::sync::Arc<crate::net::Client>,
+}
+
+#[cfg(feature = "net")]
+impl ResourceLoader for HttpLoader {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        let response = self.client.fetch(&url.to_string())?;
+        Ok(Resource::from_response(url, response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_data_urls() {
+        let load = |url: &str| {
+            let resource = DataLoader.load(&Url::parse(url).unwrap()).unwrap();
+            (resource.content_type.unwrap(), resource.data)
+        };
+        assert_eq!(
+            load("data:text/css,p%20{ color: red }"),
+            ("text/css".to_string(), b"p { color: red }".to_vec())
+        );
+        assert_eq!(
+            load("data:,a?b#c"),
+            ("text/plain;charset=US-ASCII".to_string(), b"a?b".to_vec())
+        );
+        assert_eq!(
+            load("data:;charset=utf-8,a"),
+            ("text/plain;charset=utf-8".to_string(), b"a".to_vec())
+        );
+        assert_eq!(
+            load("data:image/png;base64,iVBO Rw0K"),
+            ("image/png".to_string(), b"\x89PNG\r\n".to_vec())
+        );
+        assert!(
+            DataLoader
+                .load(&Url::parse("data:text/css").unwrap())
+                .is_err()
+        );
+        assert!(
+            DataLoader
+                .load(&Url::parse("data:;base64,a===").unwrap())
+                .is_err()
+        );
+
+        assert_eq!(decode_base64(b"YQ=="), Some(b"a".to_vec()));
+        assert_eq!(decode_base64(b"YWI"), Some(b"ab".to_vec()));
+        assert_eq!(decode_base64(b"YWJj"), Some(b"abc".to_vec()));
+        assert_eq!(decode_base64(b"Y"), None);
+    }
+
+    #[test]
+    fn test_scheme_loader() {
+        let path = std::env::temp_dir().join("simple browser loader.css");
+        fs::write(&path, "p {}").unwrap();
+        let file_url = format!(
+            "file://{}",
+            crate::url::percent_encode(&path.to_string_lossy())
+        );
+
+        let mut memory = MemoryLoader::new();
+        memory.insert("app://assets/a.css", Some("text/css"), b"a {}");
+        let mut loader = SchemeLoader::default();
+        loader.register("APP", memory);
+        let load = |url: &str| loader.load(&Url::parse(url).unwrap());
+
+        let resource = load(&file_url).unwrap();
+        assert_eq!(resource.content_type.as_deref(), Some("text/css"));
+        assert_eq!(resource.data, b"p {}");
+        assert!(resource.is_success());
+        assert_eq!(load("data:,a").unwrap().data, b"a");
+        let resource = load("app://assets/a.css#x").unwrap();
+        assert_eq!(resource.url.to_string(), "app://assets/a.css#x");
+        assert_eq!(resource.data, b"a {}");
+
+        assert_eq!(
+            load("app://assets/b.css").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(
+            load("gopher://example.com/").unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+        assert!(load("file://example.com/a.css").is_err());
+
+        // Files are read up to the largest size.
+        let file = File::create(&path).unwrap();
+        file.set_len(MAX_FILE_SIZE).unwrap();
+        assert_eq!(load(&file_url).unwrap().data.len() as u64, MAX_FILE_SIZE);
+        file.set_len(MAX_FILE_SIZE + 1).unwrap();
+        assert_eq!(
+            load(&file_url).unwrap_err().kind(),
+            io::ErrorKind::FileTooLarge
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,11 +1,60 @@
-use html_parser::HTMLParser;
+// Lay out an HTML document with stylesheets, and print its layout tree.
+// Run with `cargo run -- page.html [style.css ...] [--term]`. With `--term`,
+// the page is drawn in the terminal with ANSI colors instead, as wide as
+// the terminal if `COLUMNS` is set.
 
-mod css_parser;
-mod dom;
-mod html_parser;
-mod layout;
-mod style;
+use std::{env, fs, process};
+
+use simple_browser_rs::{
+    css_parser::CSSParser,
+    html_parser::HTMLParser,
+    layout::{Dimensions, layout_tree},
+    painting::Images,
+    style::{MediaContext, apply},
+    terminal::{CELL_WIDTH, render_to_terminal},
+};
 
 fn main() {
-    HTMLParser::parse("<html></html>".to_string());
+    let args: Vec<String> = env::args().skip(1).collect();
+    let term = args.iter().any(|arg| arg == "--term");
+    let mut paths = args.iter().filter(|arg| *arg != "--term");
+    let Some(html) = paths.next() else {
+        eprintln!("Usage: simple-browser-rs <html> [css ...] [--term]");
+        process::exit(2);
+    };
+    let read = |path: &str| {
+        fs::read_to_string(path).unwrap_or_else(|error| {
+            eprintln!("Failed to read {}: {}", path, error);
+            process::exit(2);
+        })
+    };
+
+    let root = HTMLParser::parse(read(html));
+    let stylesheets: Vec<_> =
+        paths.map(|path| CSSParser::parse(read(path))).collect();
+    let columns = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .unwrap_or(80);
+    let media = MediaContext {
+        viewport_width: columns as f32 * CELL_WIDTH,
+        ..MediaContext::default()
+    };
+    let styled = apply(&root, &stylesheets, &media);
+
+    let mut viewport = Dimensions::default();
+    viewport.content.width = media.viewport_width;
+    viewport.content.height = media.viewport_height;
+    let layout_root = layout_tree(&styled, viewport);
+    if !term {
+        print!("{}", layout_root.dump());
+        return;
+    }
+    // The whole page is drawn, however far it scrolls.
+    let mut bounds = viewport.content;
+    bounds.height = layout_root.dimensions().margin_box().height;
+    print!(
+        "{}",
+        render_to_terminal(&layout_root, bounds, &Images::new())
+    );
 }
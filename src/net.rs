@@ -0,0 +1,595 @@
+// Fetch documents and their resources over HTTP and HTTPS, and decode the
+// bytes of HTML documents into text in the encoding that they declare. A
+// client keeps the cookies that servers set, and follows redirects itself,
+// so that the cookies set by a redirect are sent to where it leads. Bodies
+// that are compressed with a content coding are decompressed, and chunked
+// ones are put together, before they are returned.
+// SEE: https://fetch.spec.whatwg.org/
+// SEE: https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding
+
+use std::io::{self, Read};
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+
+use crate::{cookie::CookieJar, url::Url};
+
+// The content codings that bodies can be compressed with, in the order
+// that they are preferred.
+// SEE: https://httpwg.org/specs/rfc9110.html#field.accept-encoding
+pub const ACCEPT_ENCODING: &str = "br, gzip, deflate";
+
+// The most redirects that are followed for a request by default.
+// SEE: https://fetch.spec.whatwg.org/#http-redirect-fetch
+pub const MAX_REDIRECTS: usize = 20;
+
+// A response to a request, with its body read whole.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    // The URL that the response is for, which differs from the requested
+    // one after redirects.
+    pub url: String,
+    pub status: u16,
+    // The headers in the order that they were received, with lowercase
+    // names.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    // Get the value of the first header of a name, ignoring its case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Check if the status is in the 2xx range.
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    // Decode the body as an HTML document. See `decode_html`.
+    pub fn text(&self) -> String {
+        decode_html(&self.body, self.header("content-type"))
+    }
+}
+
+// Sends requests with the cookies of the responses that it received, and
+// follows their redirects.
+#[derive(Debug)]
+pub struct Client {
+    pub cookies: CookieJar,
+    // The most redirects that are followed for a request, after which it
+    // fails.
+    pub max_redirects: usize,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self {
+            cookies: CookieJar::new(),
+            max_redirects: MAX_REDIRECTS,
+        }
+    }
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Send a GET request for a URL. Redirects are followed, and responses
+    // with error statuses are returned like others. Failures to connect,
+    // too many redirects, and URLs that are not HTTP or HTTPS, are errors.
+    pub fn fetch(&self, url: &str) -> io::Result<Response> {
+        self.fetch_with_headers(url, &[])
+    }
+
+    // Like `fetch`, but sends headers with the request, such as
+    // `If-None-Match`. They are not sent with the requests that its redirects
+    // lead to, which may be for other origins.
+    pub fn fetch_with_headers(
+        &self,
+        url: &str,
+        mut headers: &[(&str, &str)],
+    ) -> io::Result<Response> {
+        let mut url = Url::parse(url)
+            .filter(Url::is_http)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid HTTP URL {}", url),
+                )
+            })?
+            .without_fragment();
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .max_redirects(0)
+            .build()
+            .into();
+        for _ in 0..=self.max_redirects {
+            let cookie = self.cookies.header(&url);
+            let request = [("Accept-Encoding", ACCEPT_ENCODING)]
+                .into_iter()
+                .chain(headers.iter().copied())
+                .chain(cookie.as_deref().map(|cookie| ("Cookie", cookie)))
+                .fold(agent.get(url.to_string()), |request, (name, value)| {
+                    request.header(name, value)
+                });
+            let response = request.call().map_err(ureq::Error::into_io)?;
+            let response = read_response(&url, response)?;
+            for (name, value) in &response.headers {
+                if name == "set-cookie" {
+                    self.cookies.set(&url, value);
+                }
+            }
+            // A redirect without a valid `Location` is a response like any
+            // other.
+            let location = response
+                .header("location")
+                .filter(|_| {
+                    matches!(response.status, 301 | 302 | 303 | 307 | 308)
+                })
+                .and_then(|location| url.join(location));
+            match location {
+                Some(location) => {
+                    url = location.without_fragment();
+                    headers = &[];
+                }
+                None => return Ok(response),
+            }
+        }
+        Err(io::Error::other(format!("Too many redirects to {}", url)))
+    }
+}
+
+// Read a response whole, and decompress its body. The headers of the body
+// as it was sent are removed.
+fn read_response(
+    url: &Url,
+    response: ureq::http::Response<ureq::Body>,
+) -> io::Result<Response> {
+    let status = response.status().as_u16();
+    let mut headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            (name.as_str().to_ascii_lowercase(), value.into_owned())
+        })
+        .collect();
+    let mut body = Vec::new();
+    response.into_body().into_reader().read_to_end(&mut body)?;
+    let content_encoding = headers
+        .iter()
+        .find(|(name, _)| name == "content-encoding")
+        .map(|(_, value)| value.clone());
+    if let Some(content_encoding) = content_encoding {
+        body = decode_content(body, &content_encoding)?;
+        headers.retain(|(name, _)| {
+            name != "content-encoding" && name != "content-length"
+        });
+    }
+    Ok(Response {
+        url: url.to_string(),
+        status,
+        headers,
+        body,
+    })
+}
+
+// Decompress a body with the content codings of a `Content-Encoding`
+// header, such as `gzip`, which were applied in the order that they are
+// listed. Bodies with unknown codings are errors, and empty bodies, such as
+// those of `304 Not Modified` responses, are left as they are.
+// SEE: https://httpwg.org/specs/rfc9110.html#field.content-encoding
+pub fn decode_content(
+    body: Vec<u8>,
+    content_encoding: &str,
+) -> io::Result<Vec<u8>> {
+    if body.is_empty() {
+        return Ok(body);
+    }
+    let mut codings: Vec<String> = content_encoding
+        .split(',')
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect();
+    codings.reverse();
+    codings.into_iter().try_fold(body, |body, coding| {
+        let mut decoded = Vec::new();
+        match coding.as_str() {
+            "gzip" | "x-gzip" => {
+                MultiGzDecoder::new(body.as_slice())
+                    .read_to_end(&mut decoded)?;
+            }
+            // Some servers send raw deflate data instead of the zlib format
+            // that `deflate` means.
+            "deflate" => {
+                if ZlibDecoder::new(body.as_slice())
+                    .read_to_end(&mut decoded)
+                    .is_err()
+                {
+                    decoded.clear();
+                    DeflateDecoder::new(body.as_slice())
+                        .read_to_end(&mut decoded)?;
+                }
+            }
+            "br" => {
+                brotli_decompressor::Decompressor::new(body.as_slice(), 4096)
+                    .read_to_end(&mut decoded)?;
+            }
+            coding => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unsupported content coding {}", coding),
+                ));
+            }
+        }
+        Ok(decoded)
+    })
+}
+
+// Send a GET request for a URL with a client of its own. See
+// `Client::fetch`.
+pub fn fetch(url: &str) -> io::Result<Response> {
+    Client::new().fetch(url)
+}
+
+// Decode the bytes of an HTML document. The encoding is taken from a byte
+// order mark, the `charset` of the `Content-Type` header, or a `<meta>`
+// element near the start of the document, in that order. Documents that
+// declare none are decoded as UTF-8 if they are valid UTF-8, and as
+// windows-1252 otherwise.
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset).and_then(for_label))
+        .or_else(|| prescan(bytes))
+        .unwrap_or_else(|| match std::str::from_utf8(bytes) {
+            Ok(_) => UTF_8,
+            Err(_) => WINDOWS_1252,
+        });
+    // The byte order mark is removed if there is one.
+    encoding.decode(bytes).0.into_owned()
+}
+
+// Decode the bytes of a stylesheet. The encoding is taken from a byte order
+// mark, the `charset` of the `Content-Type` header, or an `@charset` rule
+// at the very start of the stylesheet, in that order, and is UTF-8
+// otherwise.
+// SEE: https://drafts.csswg.org/css-syntax/#input-byte-stream
+pub fn decode_css(bytes: &[u8], content_type: Option<&str>) -> String {
+    let at_charset = || {
+        let rest = bytes.strip_prefix(b"@charset \"")?;
+        let end = rest.iter().position(|&byte| byte == b'"')?;
+        if !rest[end..].starts_with(b"\";") {
+            return None;
+        }
+        let encoding = Encoding::for_label(&rest[..end])?;
+        Some(if encoding.name().starts_with("UTF-16") {
+            UTF_8
+        } else {
+            encoding
+        })
+    };
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset).and_then(for_label))
+        .or_else(at_charset)
+        .unwrap_or(UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+// Get the encoding of a label, such as `utf-8` or `latin1`.
+// SEE: https://encoding.spec.whatwg.org/#concept-encoding-get
+fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+// Get the value of the `charset` parameter of a MIME type, such as
+// `text/html; charset="utf-8"`.
+// SEE: https://html.spec.whatwg.org/multipage/urls-and-fetching.html#algorithm-for-extracting-a-character-encoding-from-a-meta-element
+fn charset(mime_type: &str) -> Option<&str> {
+    let lower = mime_type.to_ascii_lowercase();
+    let start = lower.find("charset")? + "charset".len();
+    let rest = mime_type[start..].trim_start().strip_prefix('=')?;
+    let rest = rest.trim_start();
+    let value = match rest.chars().next()? {
+        quote @ ('"' | '\'') => rest[1..].split(quote).next()?,
+        _ => rest
+            .split(|c: char| c == ';' || c.is_ascii_whitespace())
+            .next()?,
+    };
+    (!value.is_empty()).then_some(value)
+}
+
+// Find the encoding that a `<meta charset>` or a `<meta http-equiv
+// content>` element declares in the first 1024 bytes of a document. A
+// declared UTF-16 encoding means UTF-8, because the document would have had
+// a byte order mark otherwise.
+// SEE: https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    let lower = head.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    while let Some(start) = rest.find("<meta") {
+        let tag = &rest[start..];
+        let end = tag.find('>').unwrap_or(tag.len());
+        if let Some(encoding) = charset(&tag[..end]).and_then(for_label) {
+            return Some(if encoding.name().starts_with("UTF-16") {
+                UTF_8
+            } else {
+                encoding
+            });
+        }
+        rest = &tag[end..];
+    }
+    None
+}
+
+// Serve canned responses on a local port, one for each connection in turn,
+// and send the requests that were received on a channel. Returns the URL
+// of the server, which stops after the last response.
+#[cfg(test)]
+pub fn serve(
+    responses: Vec<Vec<u8>>,
+) -> (String, std::sync::mpsc::Receiver<String>) {
+    let count = responses.len();
+    let mut responses = responses.into_iter();
+    serve_with(count, move |_| responses.next().unwrap())
+}
+
+// Like `serve`, but with the response for each path, for requests that are
+// sent at the same time and can arrive in any order.
+#[cfg(test)]
+pub fn serve_paths(
+    responses: Vec<(&str, Vec<u8>)>,
+) -> (String, std::sync::mpsc::Receiver<String>) {
+    let count = responses.len();
+    let mut responses: std::collections::HashMap<String, Vec<u8>> = responses
+        .into_iter()
+        .map(|(path, response)| (path.to_string(), response))
+        .collect();
+    serve_with(count, move |request| {
+        let path = request.split(' ').nth(1).unwrap_or_default();
+        responses
+            .remove(path)
+            .unwrap_or_else(|| panic!("No response for {}", path))
+    })
+}
+
+#[cfg(test)]
+fn serve_with(
+    count: usize,
+    mut respond: impl FnMut(&str) -> Vec<u8> + Send + 'static,
+) -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::{io::Write, net::TcpListener, sync::mpsc, thread};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for _ in 0..count {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Read the head of the request, which ends with an empty line.
+            let mut request = Vec::new();
+            let mut byte = [0];
+            while !request.ends_with(b"\r\n\r\n") {
+                if stream.read(&mut byte).unwrap() == 0 {
+                    break;
+                }
+                request.push(byte[0]);
+            }
+            let request = String::from_utf8_lossy(&request).into_owned();
+            let response = respond(&request);
+            let _ = sender.send(request);
+            stream.write_all(&response).unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch() {
+        let (url, requests) = serve(vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\
+              X-Test: a\r\nContent-Length: 5\r\n\r\nhello"
+                .to_vec(),
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+        ]);
+
+        let response = fetch(&format!("{}/page.html", url)).unwrap();
+        assert!(requests.recv().unwrap().starts_with("GET /page.html "));
+        assert_eq!(response.url, format!("{}/page.html", url));
+        assert_eq!(response.status, 200);
+        assert!(response.is_success());
+        assert_eq!(response.header("X-TEST"), Some("a"));
+        assert_eq!(response.body, b"hello");
+
+        // Error statuses are responses too.
+        let response = fetch(&format!("{}/missing", url)).unwrap();
+        assert_eq!(response.status, 404);
+        assert!(!response.is_success());
+
+        assert!(fetch("ftp://example.com/").is_err());
+    }
+
+    #[test]
+    fn test_redirects() {
+        let (url, requests) = serve(vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next#a\r\n\
+              Set-Cookie: a=1\r\nContent-Length: 0\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 301 Moved Permanently\r\nLocation: final/page\r\n\
+              Set-Cookie: b=2; Path=/final\r\nContent-Length: 0\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone".to_vec(),
+            b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /loop\r\n\
+              Content-Length: 0\r\n\r\n"
+                .to_vec(),
+            b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /loop\r\n\
+              Content-Length: 0\r\n\r\n"
+                .to_vec(),
+        ]);
+        let client = Client::new();
+        let response = client
+            .fetch_with_headers(
+                &format!("{}/start", url),
+                &[("Authorization", "secret")],
+            )
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.url, format!("{}/final/page", url));
+        assert_eq!(response.body, b"done");
+
+        // The cookies that the redirects set are sent to where they lead,
+        // and the headers of the request are not.
+        let requests: Vec<String> = requests.iter().take(3).collect();
+        assert!(requests[0].starts_with("GET /start "));
+        assert!(requests[0].contains("authorization: secret\r\n"));
+        assert!(!requests[0].contains("cookie"));
+        assert!(!requests[1].contains("authorization"));
+        assert!(!requests[2].contains("authorization"));
+        assert!(requests[1].starts_with("GET /next "));
+        assert!(requests[1].contains("cookie: a=1\r\n"));
+        assert!(requests[2].starts_with("GET /final/page "));
+        assert!(requests[2].contains("cookie: b=2; a=1\r\n"));
+
+        let client = Client {
+            max_redirects: 1,
+            ..Client::new()
+        };
+        assert!(client.fetch(&format!("{}/loop", url)).is_err());
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        use std::io::Write;
+
+        use flate2::{Compression, write::GzEncoder};
+
+        let body = b"<p>compressed</p>".repeat(10);
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&body).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut brotli = Vec::new();
+        brotli::BrotliCompress(
+            &mut body.as_slice(),
+            &mut brotli,
+            &Default::default(),
+        )
+        .unwrap();
+
+        // The gzip body is sent in two chunks.
+        let (head, tail) = gzip.split_at(gzip.len() / 2);
+        let chunked = [
+            b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\
+              Transfer-Encoding: chunked\r\n\r\n"
+                .as_slice(),
+            format!("{:x}\r\n", head.len()).as_bytes(),
+            head,
+            format!("\r\n{:x}\r\n", tail.len()).as_bytes(),
+            tail,
+            b"\r\n0\r\n\r\n",
+        ]
+        .concat();
+        let brotli = [
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: br\r\n\
+                 Content-Length: {}\r\n\r\n",
+                brotli.len()
+            )
+            .as_bytes(),
+            &brotli,
+        ]
+        .concat();
+        let (url, requests) = serve(vec![chunked, brotli]);
+
+        let response = fetch(&url).unwrap();
+        assert!(
+            requests
+                .recv()
+                .unwrap()
+                .contains("accept-encoding: br, gzip, deflate\r\n")
+        );
+        assert_eq!(response.body, body);
+        assert_eq!(response.header("content-encoding"), None);
+        assert_eq!(fetch(&url).unwrap().body, body);
+
+        // Codings are removed in the reverse order that they were applied.
+        let mut zlib =
+            flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&gzip).unwrap();
+        let zlib = zlib.finish().unwrap();
+        assert_eq!(decode_content(zlib, "gzip, Deflate").unwrap(), body);
+        let mut deflate = flate2::write::DeflateEncoder::new(
+            Vec::new(),
+            Compression::default(),
+        );
+        deflate.write_all(&body).unwrap();
+        let deflate = deflate.finish().unwrap();
+        assert_eq!(decode_content(deflate, "deflate").unwrap(), body);
+        assert_eq!(decode_content(Vec::new(), "gzip").unwrap(), b"");
+        assert_eq!(decode_content(body.clone(), "identity").unwrap(), body);
+        assert!(decode_content(body.clone(), "compress").is_err());
+        assert!(decode_content(body, "gzip").is_err());
+    }
+
+    #[test]
+    fn test_decode_css() {
+        let css = b"@charset \"latin1\"; p::before { content: \"\xe9\"; }";
+        assert!(decode_css(css, None).ends_with("\"\u{e9}\"; }"));
+        assert!(
+            decode_css(css, Some("text/css; charset=utf-8"))
+                .ends_with("\"\u{fffd}\"; }")
+        );
+        assert_eq!(decode_css("\u{e9}".as_bytes(), None), "\u{e9}");
+    }
+
+    #[test]
+    fn test_decode_html() {
+        // "é" in UTF-8 and in windows-1252.
+        let utf8 = "<p>\u{e9}</p>".as_bytes();
+        let latin1 = b"<p>\xe9</p>";
+        assert_eq!(decode_html(utf8, None), "<p>\u{e9}</p>");
+        assert_eq!(decode_html(latin1, None), "<p>\u{e9}</p>");
+        assert_eq!(
+            decode_html(latin1, Some("text/html; charset=ISO-8859-1")),
+            "<p>\u{e9}</p>"
+        );
+        assert_eq!(
+            decode_html(utf8, Some("text/html;charset=\"windows-1252\"")),
+            "<p>\u{c3}\u{a9}</p>"
+        );
+
+        // A byte order mark wins over the header, and is removed.
+        let bom = [b"\xef\xbb\xbf".as_slice(), utf8].concat();
+        assert_eq!(
+            decode_html(&bom, Some("text/html; charset=latin1")),
+            "<p>\u{e9}</p>"
+        );
+
+        // The header wins over `<meta>`, which is found near the start.
+        let meta = b"<head><meta charset=\"latin1\"></head><p>\xc3\xa9</p>";
+        assert_eq!(
+            decode_html(meta, None),
+            "<head><meta charset=\"latin1\"></head><p>\u{c3}\u{a9}</p>"
+        );
+        assert!(
+            decode_html(meta, Some("text/html; charset=utf-8"))
+                .ends_with("<p>\u{e9}</p>")
+        );
+        let http_equiv = b"<meta http-equiv=\"Content-Type\" \
+                           content=\"text/html; charset=utf-16\"><p>\xc3\xa9</p>";
+        assert!(decode_html(http_equiv, None).ends_with("<p>\u{e9}</p>"));
+    }
+}
@@ -0,0 +1,4645 @@
+// Painting turns a laid out box tree into a list of drawing commands, which
+// backends rasterize or draw on a screen. The commands are in the order that
+// they are painted, from back to front, so that later ones cover earlier
+// ones.
+// SEE: https://www.w3.org/TR/CSS22/zindex.html
+
+use std::{collections::HashMap, fmt::Write, ops, sync::Arc};
+
+use crate::{
+    computed::{
+        BackgroundImage,
+        BackgroundRepeat,
+        BackgroundSize,
+        BorderStyle,
+        BoxShadow,
+        ColorStop,
+        Corners,
+        FontStyle,
+        GradientDirection,
+        OutlineStyle,
+        Overflow,
+        Position,
+        RadialShape,
+        RadialSize,
+        Sides,
+        Visibility,
+    },
+    css_parser::{Color, Value},
+    dom::{Node, NodeId, NodeType, Range},
+    layout::{
+        EdgeSizes,
+        ImageSizes,
+        LayoutBox,
+        Quad,
+        Rectangle,
+        RoundedRect,
+        StackingContext,
+        TextFragment,
+        Transform,
+        dump_rect,
+        round,
+    },
+    style::StyledNode,
+    svg::{FillRule, Path, Svg},
+    text::FontMetrics,
+};
+
+// TODO: The following features are not implemented:
+// - Backgrounds and borders of inline boxes, which are laid out as fragments
+
+// A drawing command of a display list.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DisplayCommand {
+    // Fill a rectangle with a color, such as a background or a border side.
+    SolidRect {
+        color: Color,
+        rect: Rectangle,
+    },
+    Text(TextRun),
+    // Paint a shadow shaped like `shadow` and blurred by the blur radius,
+    // outside of the border box `rect` that casts it.
+    BoxShadow {
+        color: Color,
+        rect: RoundedRect,
+        shadow: RoundedRect,
+        blur: f32,
+    },
+    // Draw the image of a replaced element, scaled to fill the rectangle.
+    // The decoded image is looked up by the URL of its `src` attribute.
+    Image {
+        node: NodeId,
+        url: String,
+        rect: Rectangle,
+    },
+    // Draw an SVG image that is in the document, with its view box fit into
+    // the rectangle.
+    Svg {
+        svg: Svg,
+        rect: Rectangle,
+    },
+    // Draw an image scaled to `tile`, and repeat it within the rectangle
+    // along the axes that it repeats on.
+    BackgroundImage {
+        image: ImageSource,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat_x: bool,
+        repeat_y: bool,
+    },
+    // Fill a rectangle with rounded corners, such as the background of a
+    // box with `border-radius`.
+    RoundedRect {
+        color: Color,
+        rect: RoundedRect,
+    },
+    // Fill the area between a rounded rectangle and that rectangle shrunk
+    // by `widths`, such as a rounded border. Each point of the area takes
+    // the color of the side that it is nearest to.
+    RoundedBorder {
+        colors: Sides<Color>,
+        rect: RoundedRect,
+        widths: EdgeSizes,
+    },
+    // Clip the commands up to the matching `PopClip` to a rounded
+    // rectangle, within the clips that are already in effect.
+    PushClip(RoundedRect),
+    PopClip,
+    // Paint the commands up to the matching `PopLayer` into a transparent
+    // layer, and then blend the layer with an opacity over what is painted
+    // below it. Overlapping content in the layer covers each other before
+    // it is made translucent as a whole.
+    // SEE: https://drafts.csswg.org/css-color/#transparency
+    PushLayer {
+        opacity: f32,
+    },
+    PopLayer,
+    // Map the geometry of the commands up to the matching `PopTransform` by
+    // a transform, after the transforms that are already in effect.
+    // SEE: https://drafts.csswg.org/css-transforms/#transform-rendering
+    PushTransform(Transform),
+    PopTransform,
+}
+
+// The image of a background, with the geometry of gradients relative to the
+// top left corner of a tile.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImageSource {
+    // An image that the embedder decodes, by its URL.
+    Url(String),
+    // A gradient whose color changes along the line from `start` to `end`.
+    LinearGradient {
+        start: (f32, f32),
+        end: (f32, f32),
+        stops: Vec<GradientStop>,
+    },
+    // A gradient whose color changes from the center outward, and is at
+    // the offset of 1 on the ellipse with `radii`.
+    RadialGradient {
+        center: (f32, f32),
+        radii: (f32, f32),
+        stops: Vec<GradientStop>,
+    },
+}
+
+// A color of a gradient, at an offset on the gradient line where 0 is the
+// start and 1 is the end. The offsets never decrease.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Color,
+}
+
+impl ImageSource {
+    // Get the color of a gradient at a point of a tile.
+    pub fn gradient_color(&self, (x, y): (f32, f32)) -> Option<Color> {
+        let (offset, stops) = match self {
+            ImageSource::Url(_) => return None,
+            ImageSource::LinearGradient { start, end, stops } => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let offset = ((x - start.0) * dx + (y - start.1) * dy)
+                    / (dx * dx + dy * dy);
+                (offset, stops)
+            }
+            ImageSource::RadialGradient {
+                center,
+                radii,
+                stops,
+            } => {
+                let offset =
+                    ((x - center.0) / radii.0).hypot((y - center.1) / radii.1);
+                (offset, stops)
+            }
+        };
+        let first = stops.first()?;
+        if offset <= first.offset {
+            return Some(first.color);
+        }
+        for pair in stops.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if offset < to.offset {
+                let t = (offset - from.offset) / (to.offset - from.offset);
+                return Some(interpolate(from.color, to.color, t));
+            }
+        }
+        stops.last().map(|stop| stop.color)
+    }
+}
+
+// Interpolate between two colors in sRGB with premultiplied alpha, so that
+// a transparent color doesn't darken the other one.
+// SEE: https://drafts.csswg.org/css-images/#coloring-gradient-line
+fn interpolate(from: Color, to: Color, t: f32) -> Color {
+    let (from_alpha, to_alpha) = (from.a as f32 * (1.0 - t), to.a as f32 * t);
+    let alpha = from_alpha + to_alpha;
+    if alpha == 0.0 {
+        return TRANSPARENT;
+    }
+    let channel = |from: u8, to: u8| {
+        ((from as f32 * from_alpha + to as f32 * to_alpha) / alpha).round()
+            as u8
+    };
+    Color {
+        r: channel(from.r, to.r),
+        g: channel(from.g, to.g),
+        b: channel(from.b, to.b),
+        a: alpha.round() as u8,
+    }
+}
+
+// A piece of text on one line, with the font that it is drawn in.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextRun {
+    pub text: String,
+    // The area of the text on its line, whose height is the line height.
+    pub rect: Rectangle,
+    // The position of the baseline that the glyphs are drawn on.
+    pub baseline: f32,
+    pub color: Color,
+    pub font_size: f32,
+    pub font_family: String,
+    pub font_weight: u16,
+    pub font_style: FontStyle,
+    // The extra width of each space, by which justified text is stretched.
+    pub word_spacing: f32,
+}
+
+impl TextRun {
+    // Map a run of text through a transform that only scales it by the same
+    // amount along both axes and moves it, such as from CSS pixels to
+    // device pixels, so that its glyphs are rasterized at the size that
+    // they are drawn rather than scaled afterwards.
+    pub fn transformed(&self, transform: Transform) -> Option<Self> {
+        let Transform { a, b, c, d, e, f } = transform;
+        if b != 0.0 || c != 0.0 || a != d || a <= 0.0 {
+            return None;
+        }
+        Some(Self {
+            rect: Rectangle {
+                x: self.rect.x * a + e,
+                y: self.rect.y * a + f,
+                width: self.rect.width * a,
+                height: self.rect.height * a,
+            },
+            baseline: self.baseline * a + f,
+            font_size: self.font_size * a,
+            word_spacing: self.word_spacing * a,
+            ..self.clone()
+        })
+    }
+}
+
+pub type DisplayList = Vec<DisplayCommand>;
+
+// Build the display list of a laid out tree. Each box paints its
+// background, then its borders, then the text of its lines or its image,
+// in the painting order of the stacking contexts. The boxes inside scroll
+// containers are clipped to their rounded padding boxes, translucent boxes
+// are painted with their descendants into layers, and transformed boxes are
+// drawn with their descendants through their transforms. The content of a
+// scrolled box is moved by its scroll offset, and its scrollbars are painted
+// over what it contains. Background images are left out unless `images`
+// knows their sizes.
+pub fn build_display_list(
+    layout_root: &LayoutBox,
+    images: &dyn ImageSizes,
+) -> DisplayList {
+    build_display_list_with_selection(layout_root, images, None)
+}
+
+// The text that the user selected, which is highlighted behind the text
+// that it covers.
+// SEE: https://drafts.csswg.org/css-pseudo/#highlight-painting
+pub struct Selection<'a> {
+    // The selected bytes of the text of each text node.
+    ranges: HashMap<NodeId, ops::Range<usize>>,
+    color: Color,
+    // The metrics that the text was laid out with, which tell where each
+    // character of a fragment is.
+    metrics: &'a dyn FontMetrics,
+}
+
+impl<'a> Selection<'a> {
+    pub fn new(
+        root: &Node,
+        range: &Range,
+        color: Color,
+        metrics: &'a dyn FontMetrics,
+    ) -> Self {
+        Self {
+            ranges: range.text_ranges(root),
+            color,
+            metrics,
+        }
+    }
+
+    // Get the part of a fragment that is selected, if any. Generated text
+    // is not in the tree, and can't be selected.
+    fn fragment_rect(&self, fragment: &TextFragment) -> Option<Rectangle> {
+        let node = fragment.node();
+        if node.generated_text.is_some() {
+            return None;
+        }
+        let range = self.ranges.get(&node.node.node_id)?;
+        fragment.text_range_rect(range.start, range.end, self.metrics)
+    }
+
+    // Get the rectangles that the selection highlights, before the
+    // transforms and scroll offsets of their boxes.
+    pub fn rects(&self, layout_root: &LayoutBox) -> Vec<Rectangle> {
+        let mut rects = Vec::new();
+        self.collect_rects(layout_root, &mut rects);
+        rects
+    }
+
+    fn collect_rects(
+        &self,
+        layout_box: &LayoutBox,
+        rects: &mut Vec<Rectangle>,
+    ) {
+        for line in layout_box.lines() {
+            rects.extend(
+                line.fragments()
+                    .iter()
+                    .filter_map(|fragment| self.fragment_rect(fragment)),
+            );
+        }
+        for child in layout_box.children() {
+            self.collect_rects(child, rects);
+        }
+    }
+}
+
+// Build the display list with the text of a selection highlighted.
+pub fn build_display_list_with_selection(
+    layout_root: &LayoutBox,
+    images: &dyn ImageSizes,
+    selection: Option<&Selection>,
+) -> DisplayList {
+    let mut effects = HashMap::new();
+    collect_effects(layout_root, &InheritedEffects::default(), &mut effects);
+
+    let effects_of = |layout_box: &LayoutBox<'_>| -> &[Effect] {
+        effects
+            .get(&(layout_box as *const LayoutBox as *const _))
+            .map_or(&[][..], Vec::as_slice)
+    };
+    let boxes = StackingContext::new(layout_root).paint_order();
+    // The scrollbars of each scroll container are painted right after the
+    // last box that it clips.
+    let mut scrollbars: HashMap<usize, Vec<&LayoutBox>> = HashMap::new();
+    for (i, layout_box) in boxes.iter().enumerate() {
+        if scrollbar_axes(layout_box) == (false, false) {
+            continue;
+        }
+        let id = *layout_box as *const LayoutBox as *const ();
+        let last = boxes.iter().rposition(|other| {
+            effects_of(other)
+                .iter()
+                .any(|effect| matches!(effect, Effect::Clip(clip_id, ..) if *clip_id == id))
+        });
+        scrollbars
+            .entry(last.map_or(i, |last| last.max(i)))
+            .or_default()
+            .push(layout_box);
+    }
+
+    let mut list = Vec::new();
+    let mut current: &[Effect] = &[];
+    for (i, layout_box) in boxes.iter().enumerate() {
+        // Boxes that paint nothing, such as inline boxes, don't switch the
+        // effects, so that no empty clips or layers are pushed for them.
+        let mut commands = Vec::new();
+        render_layout_box(&mut commands, layout_box, images, selection);
+        if !commands.is_empty() {
+            let box_effects = effects_of(layout_box);
+            switch_effects(&mut list, current, box_effects);
+            current = box_effects;
+            list.append(&mut commands);
+        }
+        for scroller in scrollbars.remove(&i).unwrap_or_default() {
+            let box_effects = effects_of(scroller);
+            switch_effects(&mut list, current, box_effects);
+            current = box_effects;
+            render_scrollbars(&mut list, scroller);
+        }
+    }
+    // Outlines are painted over everything else, in the painting order of
+    // their boxes, so that they are not covered by their neighbors.
+    // SEE: https://www.w3.org/TR/CSS22/zindex.html#painting-order
+    for layout_box in &boxes {
+        let mut commands = Vec::new();
+        render_outline(&mut commands, layout_box);
+        if !commands.is_empty() {
+            let box_effects = effects_of(layout_box);
+            switch_effects(&mut list, current, box_effects);
+            current = box_effects;
+            list.append(&mut commands);
+        }
+    }
+    switch_effects(&mut list, current, &[]);
+    list
+}
+
+// A clip, a layer, or a transform that the painting of a box is nested in.
+// Each effect is told apart from those of other boxes by its box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Effect {
+    // The clip of a scroll container, with the transform of the box, which
+    // hit testing maps points through.
+    Clip(*const (), RoundedRect, Option<Transform>),
+    // The layer of a box with an opacity.
+    Layer(*const (), f32),
+    // The transform of a box relative to its parent.
+    Transform(*const (), Transform),
+    // The scroll offset of a scroll container, which moves its content.
+    Scroll(*const (), (f32, f32)),
+}
+
+// The effects that apply to the descendants of a box, which depend on
+// whether the box contains them, from the outermost one.
+#[derive(Debug, Clone, Default)]
+struct InheritedEffects {
+    in_flow: Vec<Effect>,
+    absolute: Vec<Effect>,
+    fixed: Vec<Effect>,
+}
+
+// Record the effects that apply to each box, from the outermost one. The
+// clip of a box applies to the descendants that it contains, so positioned
+// boxes escape the clips of the boxes between them and their containing
+// blocks. The layer of a box applies to itself and all its descendants,
+// which are in its stacking context, so they are painted right after it
+// into the same layer, and so does its transform.
+fn collect_effects<'a>(
+    layout_box: &LayoutBox<'a>,
+    inherited: &InheritedEffects,
+    boxes: &mut HashMap<*const LayoutBox<'a>, Vec<Effect>>,
+) {
+    let style = layout_box.style();
+    let id = layout_box as *const LayoutBox as *const ();
+    let layer =
+        (style.opacity < 1.0).then_some(Effect::Layer(id, style.opacity));
+    let transform = layout_box
+        .own_transform()
+        .map(|transform| Effect::Transform(id, transform));
+    let mut effects = match style.position {
+        Position::Absolute => inherited.absolute.clone(),
+        Position::Fixed => inherited.fixed.clone(),
+        _ => inherited.in_flow.clone(),
+    };
+    effects.extend(layer);
+    effects.extend(transform);
+    if !effects.is_empty() {
+        boxes.insert(layout_box, effects.clone());
+    }
+
+    let mut in_flow = effects;
+    if layout_box.clip_rect().is_some() {
+        in_flow.push(Effect::Clip(
+            id,
+            layout_box.rounded_padding_box(),
+            layout_box.transform(),
+        ));
+        let (x, y) = layout_box.scroll_offset();
+        if x != 0.0 || y != 0.0 {
+            in_flow.push(Effect::Scroll(id, (x, y)));
+        }
+    }
+    let positioned = |position, effects: &[Effect]| {
+        if layout_box.contains_positioned(position) {
+            in_flow.clone()
+        } else {
+            effects
+                .iter()
+                .copied()
+                .chain(layer)
+                .chain(transform)
+                .collect()
+        }
+    };
+    let descendants = InheritedEffects {
+        absolute: positioned(Position::Absolute, &inherited.absolute),
+        fixed: positioned(Position::Fixed, &inherited.fixed),
+        in_flow,
+    };
+    for child in layout_box.children() {
+        collect_effects(child, &descendants, boxes);
+    }
+}
+
+// Pop the effects that don't apply to the next box, and push those of the
+// next box that aren't in effect yet.
+fn switch_effects(list: &mut DisplayList, current: &[Effect], next: &[Effect]) {
+    let common = current
+        .iter()
+        .zip(next)
+        .take_while(|(current, next)| current == next)
+        .count();
+    list.extend(current[common..].iter().rev().map(|effect| match effect {
+        Effect::Clip(..) => DisplayCommand::PopClip,
+        Effect::Layer(..) => DisplayCommand::PopLayer,
+        Effect::Transform(..) | Effect::Scroll(..) => {
+            DisplayCommand::PopTransform
+        }
+    }));
+    list.extend(next[common..].iter().map(|effect| match *effect {
+        Effect::Clip(_, clip, _) => DisplayCommand::PushClip(clip),
+        Effect::Layer(_, opacity) => DisplayCommand::PushLayer { opacity },
+        Effect::Transform(_, transform) => {
+            DisplayCommand::PushTransform(transform)
+        }
+        Effect::Scroll(_, (x, y)) => {
+            DisplayCommand::PushTransform(Transform::translate(-x, -y))
+        }
+    }));
+}
+
+// Find the node under a point of the page, which is the frontmost one that
+// is visible there and not clipped away, and get it with its ancestors, from
+// the innermost one. Text is hit on its fragments, and boxes within their
+// rounded border boxes, as they are drawn after transforms.
+// SEE: https://drafts.csswg.org/cssom-view/#hit-testing
+pub fn hit_test(layout_root: &LayoutBox, point: (f32, f32)) -> Vec<NodeId> {
+    let mut effects = HashMap::new();
+    collect_effects(layout_root, &InheritedEffects::default(), &mut effects);
+    let mut ancestors = HashMap::new();
+    collect_ancestors(layout_root, &mut Vec::new(), &mut ancestors);
+
+    let boxes = StackingContext::new(layout_root).paint_order();
+    for layout_box in boxes.into_iter().rev() {
+        let clipped = effects
+            .get(&(layout_box as *const LayoutBox))
+            .into_iter()
+            .flatten()
+            .any(|effect| match *effect {
+                Effect::Clip(_, clip, transform) => {
+                    !local_point(point, transform)
+                        .is_some_and(|point| clip.contains(point))
+                }
+                Effect::Layer(..)
+                | Effect::Transform(..)
+                | Effect::Scroll(..) => false,
+            });
+        if clipped {
+            continue;
+        }
+        if let Some(node) = hit_box(layout_box, point) {
+            return ancestors
+                .get(&(node as *const StyledNode))
+                .cloned()
+                .unwrap_or_default();
+        }
+    }
+    Vec::new()
+}
+
+// Find what a box paints at a point, which is a fragment of its text in
+// front of the box itself.
+fn hit_box<'a>(
+    layout_box: &LayoutBox<'a>,
+    point: (f32, f32),
+) -> Option<&'a StyledNode<'a>> {
+    let point = local_point(point, layout_box.transform())?;
+    // The content of a scroll container is also clipped by it, and moved
+    // by its scroll offset.
+    let clip = layout_box.rounded_padding_box();
+    if layout_box.clip_rect().is_none() || clip.contains(point) {
+        let (x, y) = layout_box.scroll_offset();
+        let point = (point.0 + x, point.1 + y);
+        let fragment = layout_box
+            .lines()
+            .iter()
+            .flat_map(|line| line.fragments())
+            .rev()
+            .find(|fragment| {
+                let rect = fragment.rect();
+                fragment.node().style.visibility == Visibility::Visible
+                    && (rect.x..rect.x + rect.width).contains(&point.0)
+                    && (rect.y..rect.y + rect.height).contains(&point.1)
+            });
+        if let Some(fragment) = fragment {
+            return Some(fragment.node());
+        }
+    }
+    if layout_box.is_inline_box()
+        || layout_box.style().visibility != Visibility::Visible
+        || !layout_box.rounded_border_box().contains(point)
+    {
+        return None;
+    }
+    // Anonymous boxes are hit as the boxes that they are in.
+    layout_box.style_node()
+}
+
+// Map a point of the page to the coordinates that a box is laid out in,
+// unless its transform flattens it.
+fn local_point(
+    point: (f32, f32),
+    transform: Option<Transform>,
+) -> Option<(f32, f32)> {
+    match transform {
+        Some(transform) => Some(transform.inverse()?.apply(point)),
+        None => Some(point),
+    }
+}
+
+// Record the nodes of each style node and its ancestors, from the innermost
+// one. The boxes of pseudo-elements belong to their elements.
+fn collect_ancestors<'a>(
+    layout_box: &LayoutBox<'a>,
+    nodes: &mut Vec<NodeId>,
+    ancestors: &mut HashMap<*const StyledNode<'a>, Vec<NodeId>>,
+) {
+    let pushed = match layout_box.node_id() {
+        Some(node_id) if nodes.last() != Some(&node_id) => {
+            nodes.push(node_id);
+            true
+        }
+        _ => false,
+    };
+    if let Some(node) = layout_box.style_node() {
+        ancestors.insert(node, nodes.iter().rev().copied().collect());
+    }
+    for child in layout_box.children() {
+        collect_ancestors(child, nodes, ancestors);
+    }
+    if pushed {
+        nodes.pop();
+    }
+}
+
+fn render_layout_box(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    images: &dyn ImageSizes,
+    selection: Option<&Selection>,
+) {
+    let style = layout_box.style();
+    let mut content = Vec::new();
+    // The descendants of a hidden box may still be visible, so only the box
+    // itself is skipped.
+    if style.visibility == Visibility::Visible {
+        render_box_shadows(list, layout_box, &style.box_shadow);
+        render_background(list, layout_box);
+        render_background_image(list, layout_box, images);
+        render_borders(list, layout_box);
+        if layout_box.is_replaced() {
+            render_replaced(&mut content, layout_box);
+        }
+    }
+    // The text of a line belongs to its text nodes, whose visibility may
+    // differ from that of the block container.
+    for line in layout_box.lines() {
+        for fragment in line.fragments() {
+            if let Some(selection) = selection {
+                if let Some(rect) = selection.fragment_rect(fragment) {
+                    push_rect(&mut content, selection.color, rect);
+                }
+            }
+            render_text(&mut content, fragment, line.baseline());
+        }
+    }
+
+    // The content of a scroll container is clipped like its descendants,
+    // and the image of a replaced element to its rounded corners.
+    // SEE: https://drafts.csswg.org/css-backgrounds/#corner-clipping
+    let clip = layout_box.rounded_padding_box();
+    if !content.is_empty()
+        && (layout_box.clip_rect().is_some()
+            || layout_box.is_replaced() && clip.is_rounded())
+    {
+        let (x, y) = layout_box.scroll_offset();
+        let scrolled = x != 0.0 || y != 0.0;
+        list.push(DisplayCommand::PushClip(clip));
+        if scrolled {
+            list.push(DisplayCommand::PushTransform(Transform::translate(
+                -x, -y,
+            )));
+        }
+        list.append(&mut content);
+        if scrolled {
+            list.push(DisplayCommand::PopTransform);
+        }
+        list.push(DisplayCommand::PopClip);
+    } else {
+        list.append(&mut content);
+    }
+}
+
+// Paint the image of a replaced element in its content box, which is an
+// SVG image for an `svg` element.
+fn render_replaced(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let rect = layout_box.dimensions().content;
+    let Some(node) = layout_box.style_node().map(|node| node.node) else {
+        return;
+    };
+    let NodeType::Element(element) = &node.node_type else {
+        return;
+    };
+    if is_empty(rect) {
+        return;
+    }
+    if let Some(svg) = Svg::from_node(node) {
+        list.push(DisplayCommand::Svg { svg, rect });
+    } else {
+        list.push(DisplayCommand::Image {
+            node: node.node_id,
+            url: element.attributes.get("src").cloned().unwrap_or_default(),
+            rect,
+        });
+    }
+}
+
+// The thickness of scrollbars, which are painted over the content of scroll
+// containers.
+const SCROLLBAR_SIZE: f32 = 8.0;
+const SCROLLBAR_TRACK: Color = Color {
+    r: 0xf0,
+    g: 0xf0,
+    b: 0xf0,
+    a: 255,
+};
+const SCROLLBAR_THUMB: Color = Color {
+    r: 0xa0,
+    g: 0xa0,
+    b: 0xa0,
+    a: 255,
+};
+
+// Check along which axes a box shows scrollbars, which are always shown
+// with `overflow: scroll` and only if the content overflows with `auto`.
+fn scrollbar_axes(layout_box: &LayoutBox) -> (bool, bool) {
+    if layout_box.clip_rect().is_none()
+        || layout_box.style().visibility != Visibility::Visible
+    {
+        return (false, false);
+    }
+    let style = layout_box.style();
+    let (max_x, max_y) = layout_box.scroll_range();
+    let shows = |overflow, max: f32| {
+        overflow == Overflow::Scroll || overflow == Overflow::Auto && max > 0.0
+    };
+    (
+        shows(style.overflow_x, max_x),
+        shows(style.overflow_y, max_y),
+    )
+}
+
+// Paint the scrollbars of a scroll container along the right and bottom
+// edges of its padding box, as a track with a thumb that shows which part
+// of the content is in view.
+fn render_scrollbars(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let (horizontal, vertical) = scrollbar_axes(layout_box);
+    let padding = layout_box.dimensions().padding_box();
+    let (max_x, max_y) = layout_box.scroll_range();
+    let (x, y) = layout_box.scroll_offset();
+    // Two scrollbars leave the corner between them empty.
+    let corner = if horizontal && vertical {
+        SCROLLBAR_SIZE
+    } else {
+        0.0
+    };
+    // Get where the thumb starts along a track and how long it is.
+    let thumb = |track: f32, visible: f32, offset: f32, max: f32| {
+        let length = (track * visible / (visible + max))
+            .max(SCROLLBAR_SIZE)
+            .min(track);
+        let start = if max > 0.0 {
+            (track - length) * offset / max
+        } else {
+            0.0
+        };
+        (start, length)
+    };
+    if vertical {
+        let track = Rectangle {
+            x: padding.x + padding.width - SCROLLBAR_SIZE,
+            y: padding.y,
+            width: SCROLLBAR_SIZE,
+            height: (padding.height - corner).max(0.0),
+        };
+        let (start, length) = thumb(track.height, padding.height, y, max_y);
+        push_rect(list, SCROLLBAR_TRACK, track);
+        push_rect(
+            list,
+            SCROLLBAR_THUMB,
+            Rectangle {
+                y: track.y + start,
+                height: length,
+                ..track
+            },
+        );
+    }
+    if horizontal {
+        let track = Rectangle {
+            x: padding.x,
+            y: padding.y + padding.height - SCROLLBAR_SIZE,
+            width: (padding.width - corner).max(0.0),
+            height: SCROLLBAR_SIZE,
+        };
+        let (start, length) = thumb(track.width, padding.width, x, max_x);
+        push_rect(list, SCROLLBAR_TRACK, track);
+        push_rect(
+            list,
+            SCROLLBAR_THUMB,
+            Rectangle {
+                x: track.x + start,
+                width: length,
+                ..track
+            },
+        );
+    }
+}
+
+// Paint the shadows that a box casts outside of its border box, below its
+// background. The first shadow is painted on top.
+// TODO: Paint inset shadows.
+fn render_box_shadows(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    shadows: &[BoxShadow],
+) {
+    let rect = layout_box.rounded_border_box();
+    for shadow in shadows.iter().rev() {
+        if shadow.inset || shadow.color.a == 0 {
+            continue;
+        }
+        let mut shape = spread(rect, shadow.spread);
+        shape.rect.x += shadow.offset.0;
+        shape.rect.y += shadow.offset.1;
+        if is_empty(shape.rect) {
+            continue;
+        }
+        list.push(DisplayCommand::BoxShadow {
+            color: shadow.color,
+            rect,
+            shadow: shape,
+            blur: shadow.blur,
+        });
+    }
+}
+
+// Grow a rounded rectangle by a distance on every side, or shrink it by a
+// negative one. Rounded corners grow with it, and sharp ones stay sharp.
+// SEE: https://drafts.csswg.org/css-backgrounds/#shadow-shape
+fn spread(rect: RoundedRect, distance: f32) -> RoundedRect {
+    let radius = |(x, y): (f32, f32)| {
+        if x > 0.0 && y > 0.0 {
+            ((x + distance).max(0.0), (y + distance).max(0.0))
+        } else {
+            (0.0, 0.0)
+        }
+    };
+    let Rectangle {
+        x,
+        y,
+        width,
+        height,
+    } = rect.rect;
+    RoundedRect::new(
+        Rectangle {
+            x: x - distance,
+            y: y - distance,
+            width: (width + 2.0 * distance).max(0.0),
+            height: (height + 2.0 * distance).max(0.0),
+        },
+        Corners {
+            top_left: radius(rect.radii.top_left),
+            top_right: radius(rect.radii.top_right),
+            bottom_right: radius(rect.radii.bottom_right),
+            bottom_left: radius(rect.radii.bottom_left),
+        },
+    )
+}
+
+fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let color = layout_box.style().background_color;
+    let rect = layout_box.rounded_border_box();
+    if !rect.is_rounded() {
+        push_rect(list, color, rect.rect);
+    } else if color.a != 0 && !is_empty(rect.rect) {
+        list.push(DisplayCommand::RoundedRect { color, rect });
+    }
+}
+
+// Paint the background image of a box, which is positioned in its padding
+// box and repeated over its border box.
+// SEE: https://drafts.csswg.org/css-backgrounds/#backgrounds
+fn render_background_image(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    images: &dyn ImageSizes,
+) {
+    let style = layout_box.style();
+    let Some(image) = &style.background_image else {
+        return;
+    };
+    // Gradients have no natural size, and fill the area unless sized.
+    let natural_size = match image {
+        BackgroundImage::Url(url) => match images.url_size(url) {
+            Some(size) => Some(size),
+            None => return,
+        },
+        _ => None,
+    };
+    if natural_size.is_some_and(|(width, height)| width <= 0.0 || height <= 0.0)
+    {
+        return;
+    }
+
+    // A size with one `auto` keeps the aspect ratio of the image.
+    // SEE: https://drafts.csswg.org/css-backgrounds/#background-size
+    let area = layout_box.dimensions().padding_box();
+    let (tile_width, tile_height) = match (style.background_size, natural_size)
+    {
+        (BackgroundSize::Contain, Some((width, height))) => {
+            let scale = (area.width / width).min(area.height / height);
+            (width * scale, height * scale)
+        }
+        (BackgroundSize::Cover, Some((width, height))) => {
+            let scale = (area.width / width).max(area.height / height);
+            (width * scale, height * scale)
+        }
+        (BackgroundSize::Size(x, y), natural_size) => {
+            let x = x.map(|x| x.to_px(area.width));
+            let y = y.map(|y| y.to_px(area.height));
+            match (x, y, natural_size) {
+                (Some(x), Some(y), _) => (x, y),
+                (Some(x), None, Some((width, height))) => {
+                    (x, x * height / width)
+                }
+                (None, Some(y), Some((width, height))) => {
+                    (y * width / height, y)
+                }
+                (None, None, Some(size)) => size,
+                (x, y, None) => {
+                    (x.unwrap_or(area.width), y.unwrap_or(area.height))
+                }
+            }
+        }
+        (_, None) => (area.width, area.height),
+    };
+    if tile_width <= 0.0 || tile_height <= 0.0 {
+        return;
+    }
+    // A percentage lines up the point at that percentage of the image with
+    // the point at that percentage of the area.
+    // SEE: https://drafts.csswg.org/css-backgrounds/#background-position
+    let (x, y) = style.background_position;
+    let tile = Rectangle {
+        x: area.x + x.to_px(area.width - tile_width),
+        y: area.y + y.to_px(area.height - tile_height),
+        width: tile_width,
+        height: tile_height,
+    };
+
+    let rect = layout_box.rounded_border_box();
+    if is_empty(rect.rect) {
+        return;
+    }
+    let (repeat_x, repeat_y) = style.background_repeat;
+    let command = DisplayCommand::BackgroundImage {
+        image: image_source(image, tile),
+        rect: rect.rect,
+        tile,
+        repeat_x: repeat_x == BackgroundRepeat::Repeat,
+        repeat_y: repeat_y == BackgroundRepeat::Repeat,
+    };
+    if rect.is_rounded() {
+        list.push(DisplayCommand::PushClip(rect));
+        list.push(command);
+        list.push(DisplayCommand::PopClip);
+    } else {
+        list.push(command);
+    }
+}
+
+// Lay out a background image in a tile, such as the gradient line of a
+// linear gradient.
+fn image_source(image: &BackgroundImage, tile: Rectangle) -> ImageSource {
+    let (width, height) = (tile.width, tile.height);
+    match image {
+        BackgroundImage::Url(url) => ImageSource::Url(url.clone()),
+        // The gradient line runs through the center of the tile, and is
+        // long enough that the lines perpendicular to it at its ends touch
+        // the corners.
+        // SEE: https://drafts.csswg.org/css-images/#linear-gradients
+        BackgroundImage::LinearGradient { direction, stops } => {
+            let angle = match *direction {
+                GradientDirection::Angle(angle) => angle,
+                // The line is perpendicular to the diagonal between the
+                // corners next to the one that it points to.
+                GradientDirection::Corner { right, bottom } => {
+                    let x = if right { height } else { -height };
+                    let y = if bottom { -width } else { width };
+                    x.atan2(y)
+                }
+            };
+            let (sin, cos) = angle.sin_cos();
+            let length = (width * sin).abs() + (height * cos).abs();
+            let (dx, dy) = (sin * length / 2.0, -cos * length / 2.0);
+            let center = (width / 2.0, height / 2.0);
+            ImageSource::LinearGradient {
+                start: (center.0 - dx, center.1 - dy),
+                end: (center.0 + dx, center.1 + dy),
+                stops: gradient_stops(stops, length),
+            }
+        }
+        // SEE: https://drafts.csswg.org/css-images/#radial-gradients
+        BackgroundImage::RadialGradient {
+            shape,
+            size,
+            position,
+            stops,
+        } => {
+            let center = (position.0.to_px(width), position.1.to_px(height));
+            // The distances from the center to the nearest and farthest
+            // sides along each axis.
+            let (left, right) = (center.0.abs(), (width - center.0).abs());
+            let (top, bottom) = (center.1.abs(), (height - center.1).abs());
+            let closest = (left.min(right), top.min(bottom));
+            let farthest = (left.max(right), top.max(bottom));
+            let circle = *shape == RadialShape::Circle;
+            let (x, y) = match *size {
+                RadialSize::Radii(x, y) => (x.to_px(width), y.to_px(height)),
+                RadialSize::ClosestSide if circle => {
+                    let radius = closest.0.min(closest.1);
+                    (radius, radius)
+                }
+                RadialSize::FarthestSide if circle => {
+                    let radius = farthest.0.max(farthest.1);
+                    (radius, radius)
+                }
+                RadialSize::ClosestSide => closest,
+                RadialSize::FarthestSide => farthest,
+                // The ellipse through a corner has the same aspect ratio as
+                // the one that touches the sides next to it.
+                RadialSize::ClosestCorner | RadialSize::FarthestCorner => {
+                    let (x, y) = match size {
+                        RadialSize::ClosestCorner => closest,
+                        _ => farthest,
+                    };
+                    if circle {
+                        let radius = x.hypot(y);
+                        (radius, radius)
+                    } else {
+                        (
+                            x * std::f32::consts::SQRT_2,
+                            y * std::f32::consts::SQRT_2,
+                        )
+                    }
+                }
+            };
+            // A degenerate shape is drawn as a tiny one.
+            let radii = (x.max(f32::EPSILON), y.max(f32::EPSILON));
+            ImageSource::RadialGradient {
+                center,
+                radii,
+                stops: gradient_stops(stops, radii.0),
+            }
+        }
+    }
+}
+
+// Place the color stops of a gradient on a gradient line of a length. The
+// first and last stops default to the ends of the line, stops that would
+// go backward are moved up to the stops before them, and the stops in
+// between are spread evenly.
+// SEE: https://drafts.csswg.org/css-images/#color-stop-fixup
+fn gradient_stops(stops: &[ColorStop], length: f32) -> Vec<GradientStop> {
+    let last = stops.len() - 1;
+    let mut offsets: Vec<Option<f32>> = stops
+        .iter()
+        .enumerate()
+        .map(|(i, stop)| match stop.position {
+            Some(position) => Some(position.to_px(length) / length),
+            None if i == 0 => Some(0.0),
+            None if i == last => Some(1.0),
+            None => None,
+        })
+        .collect();
+    let mut max = f32::MIN;
+    for offset in offsets.iter_mut().flatten() {
+        max = max.max(*offset);
+        *offset = max;
+    }
+    let mut i = 0;
+    while i < last {
+        let start = i;
+        i += 1;
+        while offsets[i].is_none() {
+            i += 1;
+        }
+        let (from, to) = (offsets[start].unwrap(), offsets[i].unwrap());
+        let steps = (i - start) as f32;
+        for (step, offset) in offsets[start + 1..i].iter_mut().enumerate() {
+            *offset = Some(from + (to - from) * (step + 1) as f32 / steps);
+        }
+    }
+    stops
+        .iter()
+        .zip(offsets)
+        .map(|(stop, offset)| GradientStop {
+            offset: offset.unwrap(),
+            color: stop.color,
+        })
+        .collect()
+}
+
+// Paint the border sides. The top and bottom sides span the full width of
+// the border box, and the left and right sides the height between them.
+// TODO: Draw the styles of rounded borders, which are drawn solid.
+fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let style = layout_box.style();
+    let dimensions = layout_box.dimensions();
+    let border = dimensions.border;
+    let border_box = dimensions.border_box();
+    let rect = layout_box.rounded_border_box();
+    if rect.is_rounded() {
+        if border != EdgeSizes::default() {
+            list.push(DisplayCommand::RoundedBorder {
+                colors: style.border_color,
+                rect,
+                widths: border,
+            });
+        }
+        return;
+    }
+    let inner_height = border_box.height - border.top - border.bottom;
+
+    let sides = [
+        (
+            Rectangle {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: border.top,
+            },
+            style.border_style.top,
+            style.border_color.top,
+            true,
+        ),
+        (
+            Rectangle {
+                x: border_box.x + border_box.width - border.right,
+                y: border_box.y + border.top,
+                width: border.right,
+                height: inner_height,
+            },
+            style.border_style.right,
+            style.border_color.right,
+            false,
+        ),
+        (
+            Rectangle {
+                x: border_box.x,
+                y: border_box.y + border_box.height - border.bottom,
+                width: border_box.width,
+                height: border.bottom,
+            },
+            style.border_style.bottom,
+            style.border_color.bottom,
+            true,
+        ),
+        (
+            Rectangle {
+                x: border_box.x,
+                y: border_box.y + border.top,
+                width: border.left,
+                height: inner_height,
+            },
+            style.border_style.left,
+            style.border_color.left,
+            false,
+        ),
+    ];
+    for (rect, border_style, color, horizontal) in sides {
+        render_border_side(list, rect, border_style, color, horizontal);
+    }
+}
+
+// The color and the width of the focus ring, which is drawn for outlines
+// whose style is `auto`.
+const FOCUS_RING_COLOR: Color = Color {
+    r: 0x00,
+    g: 0x5f,
+    b: 0xcc,
+    a: 255,
+};
+const FOCUS_RING_WIDTH: f32 = 2.0;
+
+// Paint the outline of a box outside of its border box, at its offset. An
+// outline follows the rounded corners of the border box like a spread
+// shadow does, and is drawn solid around them. A focus ring is a rounded
+// outline in the color of focus rings, at least as wide as them.
+// SEE: https://drafts.csswg.org/css-ui/#outline
+fn render_outline(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let style = layout_box.style();
+    if style.visibility != Visibility::Visible || style.outline_width <= 0.0 {
+        return;
+    }
+    let (width, color, rounded) = match style.outline_style {
+        OutlineStyle::Auto => (
+            style.outline_width.max(FOCUS_RING_WIDTH),
+            FOCUS_RING_COLOR,
+            true,
+        ),
+        OutlineStyle::Border(_) => {
+            (style.outline_width, style.outline_color, false)
+        }
+    };
+    if color.a == 0 {
+        return;
+    }
+    let mut rect = spread(
+        layout_box.rounded_border_box(),
+        style.outline_offset + width,
+    );
+    if rounded && !rect.is_rounded() {
+        // The square corners of a focus ring are rounded by its width.
+        rect.radii = Corners {
+            top_left: (width, width),
+            top_right: (width, width),
+            bottom_right: (width, width),
+            bottom_left: (width, width),
+        };
+    }
+    if is_empty(rect.rect) {
+        return;
+    }
+    let widths = EdgeSizes {
+        left: width,
+        right: width,
+        top: width,
+        bottom: width,
+    };
+    if rect.is_rounded() {
+        list.push(DisplayCommand::RoundedBorder {
+            colors: Sides {
+                top: color,
+                right: color,
+                bottom: color,
+                left: color,
+            },
+            rect,
+            widths,
+        });
+        return;
+    }
+    let OutlineStyle::Border(outline_style) = style.outline_style else {
+        return;
+    };
+    let Rectangle {
+        x,
+        y,
+        width: outer_width,
+        height: outer_height,
+    } = rect.rect;
+    let inner_height = outer_height - 2.0 * width;
+    let sides = [
+        (
+            Rectangle {
+                x,
+                y,
+                width: outer_width,
+                height: width,
+            },
+            true,
+        ),
+        (
+            Rectangle {
+                x: x + outer_width - width,
+                y: y + width,
+                width,
+                height: inner_height,
+            },
+            false,
+        ),
+        (
+            Rectangle {
+                x,
+                y: y + outer_height - width,
+                width: outer_width,
+                height: width,
+            },
+            true,
+        ),
+        (
+            Rectangle {
+                x,
+                y: y + width,
+                width,
+                height: inner_height,
+            },
+            false,
+        ),
+    ];
+    for (side, horizontal) in sides {
+        render_border_side(list, side, outline_style, color, horizontal);
+    }
+}
+
+// Paint one border side in its style. Dots and dashes are separated by
+// gaps about as long as them, which are stretched so that the side starts
+// and ends with one.
+// SEE: https://drafts.csswg.org/css-backgrounds/#border-style
+// TODO: Shade the sides of `groove`, `ridge`, `inset`, and `outset`
+// borders, which are drawn solid.
+fn render_border_side(
+    list: &mut DisplayList,
+    rect: Rectangle,
+    style: BorderStyle,
+    color: Color,
+    horizontal: bool,
+) {
+    let (length, thickness) = if horizontal {
+        (rect.width, rect.height)
+    } else {
+        (rect.height, rect.width)
+    };
+    // Get a piece of the side, from `start` along it and `offset` across
+    // it.
+    let piece = |start: f32, length: f32, offset: f32, size: f32| {
+        if horizontal {
+            Rectangle {
+                x: rect.x + start,
+                y: rect.y + offset,
+                width: length,
+                height: size,
+            }
+        } else {
+            Rectangle {
+                x: rect.x + offset,
+                y: rect.y + start,
+                width: size,
+                height: length,
+            }
+        }
+    };
+
+    match style {
+        BorderStyle::None | BorderStyle::Hidden => {}
+        // Two lines, with a space between them as wide as each of them.
+        BorderStyle::Double if thickness >= 3.0 => {
+            let line = (thickness / 3.0).round();
+            push_rect(list, color, piece(0.0, length, 0.0, line));
+            push_rect(list, color, piece(0.0, length, thickness - line, line));
+        }
+        BorderStyle::Dotted | BorderStyle::Dashed => {
+            let dash = match style {
+                BorderStyle::Dotted => thickness,
+                _ => thickness * 3.0,
+            };
+            let count = ((length + dash) / (dash * 2.0)).round();
+            if count < 2.0 {
+                push_rect(list, color, rect);
+                return;
+            }
+            let gap = (length - count * dash) / (count - 1.0);
+            for i in 0..count as usize {
+                let start = i as f32 * (dash + gap);
+                push_rect(list, color, piece(start, dash, 0.0, thickness));
+            }
+        }
+        _ => push_rect(list, color, rect),
+    }
+}
+
+// Paint the text of a fragment with its decorations. Underlines and
+// overlines are drawn below the text, and lines through it above it.
+// SEE: https://drafts.csswg.org/css-text-decor/#painting-order
+fn render_text(list: &mut DisplayList, fragment: &TextFragment, baseline: f32) {
+    let style = &fragment.node().style;
+    if style.visibility != Visibility::Visible || fragment.text().is_empty() {
+        return;
+    }
+    render_decorations(list, fragment, baseline, false);
+    if style.color.a != 0 {
+        list.push(DisplayCommand::Text(TextRun {
+            text: fragment.text().to_string(),
+            rect: fragment.rect(),
+            baseline,
+            color: style.color,
+            font_size: style.font_size,
+            font_family: style.font_family.clone(),
+            font_weight: style.font_weight,
+            font_style: style.font_style,
+            word_spacing: fragment.word_spacing(),
+        }));
+    }
+    render_decorations(list, fragment, baseline, true);
+}
+
+// Paint either the lines through the text of a fragment, or its underlines
+// and overlines, in the color of each box that decorates it.
+fn render_decorations(
+    list: &mut DisplayList,
+    fragment: &TextFragment,
+    baseline: f32,
+    line_through: bool,
+) {
+    let Some(metrics) = fragment.decoration_metrics() else {
+        return;
+    };
+    let line = |offset: f32, thickness: f32| Rectangle {
+        y: baseline + offset,
+        height: thickness,
+        ..fragment.rect()
+    };
+    for decoration in &fragment.node().style.text_decorations {
+        let lines = decoration.line;
+        if line_through && lines.line_through {
+            let rect = line(
+                metrics.line_through_offset,
+                metrics.line_through_thickness,
+            );
+            push_rect(list, decoration.color, rect);
+        }
+        if !line_through && lines.underline {
+            let rect =
+                line(metrics.underline_offset, metrics.underline_thickness);
+            push_rect(list, decoration.color, rect);
+        }
+        if !line_through && lines.overline {
+            let rect =
+                line(metrics.overline_offset, metrics.underline_thickness);
+            push_rect(list, decoration.color, rect);
+        }
+    }
+}
+
+// Add a rectangle, unless nothing of it would be seen.
+fn push_rect(list: &mut DisplayList, color: Color, rect: Rectangle) {
+    if color.a != 0 && !is_empty(rect) {
+        list.push(DisplayCommand::SolidRect { color, rect });
+    }
+}
+
+#[inline]
+fn is_empty(rect: Rectangle) -> bool {
+    rect.width <= 0.0 || rect.height <= 0.0
+}
+
+// An image decoded into pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    // The pixels row by row, from the top left corner.
+    pub pixels: Vec<Color>,
+}
+
+impl Bitmap {
+    // Decode an image in one of the formats that are enabled, which is told
+    // by its signature, unless it is broken.
+    // SEE: https://mimesniff.spec.whatwg.org/#matching-an-image-type-pattern
+    #[cfg_attr(
+        not(any(feature = "png", feature = "jpeg", feature = "gif")),
+        allow(unused_variables)
+    )]
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        #[cfg(feature = "png")]
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Self::decode_png(data);
+        }
+        #[cfg(feature = "jpeg")]
+        if data.starts_with(b"\xff\xd8\xff") {
+            return Self::decode_jpeg(data);
+        }
+        #[cfg(feature = "gif")]
+        if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            return Self::decode_gif(data);
+        }
+        None
+    }
+
+    // Decode a PNG image, unless it is broken.
+    #[cfg(feature = "png")]
+    pub fn decode_png(data: &[u8]) -> Option<Self> {
+        let mut decoder = png::Decoder::new(data);
+        // Expand palettes and bit depths other than eight.
+        decoder
+            .set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().ok()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).ok()?;
+        let buffer = &buffer[..info.buffer_size()];
+        let color = |r, g, b, a| Color { r, g, b, a };
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer
+                .chunks_exact(4)
+                .map(|p| color(p[0], p[1], p[2], p[3]))
+                .collect(),
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .map(|p| color(p[0], p[1], p[2], 255))
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .map(|p| color(p[0], p[0], p[0], p[1]))
+                .collect(),
+            png::ColorType::Grayscale => {
+                buffer.iter().map(|&p| color(p, p, p, 255)).collect()
+            }
+            png::ColorType::Indexed => return None,
+        };
+        Some(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        })
+    }
+
+    // Decode a JPEG image, unless it is broken.
+    #[cfg(feature = "jpeg")]
+    pub fn decode_jpeg(data: &[u8]) -> Option<Self> {
+        use jpeg_decoder::PixelFormat;
+
+        let mut decoder = jpeg_decoder::Decoder::new(data);
+        let buffer = decoder.decode().ok()?;
+        let info = decoder.info()?;
+        let color = |r, g, b| Color { r, g, b, a: 255 };
+        let pixels = match info.pixel_format {
+            PixelFormat::L8 => buffer.iter().map(|&l| color(l, l, l)).collect(),
+            // The samples are in native byte order, of which the high byte
+            // is kept.
+            PixelFormat::L16 => buffer
+                .chunks_exact(2)
+                .map(|p| {
+                    let l = (u16::from_ne_bytes([p[0], p[1]]) >> 8) as u8;
+                    color(l, l, l)
+                })
+                .collect(),
+            PixelFormat::RGB24 => buffer
+                .chunks_exact(3)
+                .map(|p| color(p[0], p[1], p[2]))
+                .collect(),
+            PixelFormat::CMYK32 => buffer
+                .chunks_exact(4)
+                .map(|p| {
+                    let ink = |c: u8| {
+                        ((255 - c as u32) * (255 - p[3] as u32) / 255) as u8
+                    };
+                    color(ink(p[0]), ink(p[1]), ink(p[2]))
+                })
+                .collect(),
+        };
+        Some(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels,
+        })
+    }
+
+    // Decode the first frame of a GIF image, unless it is broken. The frame
+    // is placed on the logical screen of the image, which is transparent
+    // around it.
+    #[cfg(feature = "gif")]
+    pub fn decode_gif(data: &[u8]) -> Option<Self> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(data).ok()?;
+        let width = decoder.width() as usize;
+        let height = decoder.height() as usize;
+        let frame = decoder.read_next_frame().ok()??;
+        let mut pixels = vec![TRANSPARENT; width * height];
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        for (y, row) in frame
+            .buffer
+            .chunks_exact(frame.width as usize * 4)
+            .enumerate()
+        {
+            for (x, p) in row.chunks_exact(4).enumerate() {
+                if left + x < width && top + y < height {
+                    pixels[(top + y) * width + left + x] = Color {
+                        r: p[0],
+                        g: p[1],
+                        b: p[2],
+                        a: p[3],
+                    };
+                }
+            }
+        }
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+// The decoded images of a document, by their URLs as they are written in
+// `url()` values and `src` attributes. Bitmaps are shared with the other
+// sets of images that have them, and SVG images are kept as their shapes,
+// which are drawn at whatever size the images are.
+#[derive(Debug, Clone, Default)]
+pub struct Images {
+    bitmaps: HashMap<String, Arc<Bitmap>>,
+    svgs: HashMap<String, Svg>,
+}
+
+impl Images {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, url: &str, bitmap: Bitmap) {
+        self.insert_shared(url, Arc::new(bitmap));
+    }
+
+    pub fn insert_shared(&mut self, url: &str, bitmap: Arc<Bitmap>) {
+        self.bitmaps.insert(url.to_string(), bitmap);
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Bitmap> {
+        self.bitmaps.get(url).map(Arc::as_ref)
+    }
+
+    // Get a bitmap to share, such as with another set of images.
+    pub fn get_shared(&self, url: &str) -> Option<Arc<Bitmap>> {
+        self.bitmaps.get(url).cloned()
+    }
+
+    pub fn insert_svg(&mut self, url: &str, svg: Svg) {
+        self.svgs.insert(url.to_string(), svg);
+    }
+
+    pub fn get_svg(&self, url: &str) -> Option<&Svg> {
+        self.svgs.get(url)
+    }
+}
+
+impl ImageSizes for Images {
+    fn natural_size(&self, node: &Node) -> Option<(f32, f32)> {
+        match &node.node_type {
+            NodeType::Element(element) => {
+                self.url_size(element.attributes.get("src")?)
+            }
+            _ => None,
+        }
+    }
+
+    fn url_size(&self, url: &str) -> Option<(f32, f32)> {
+        match self.get_svg(url) {
+            Some(svg) => svg.natural_size(),
+            None => self
+                .get(url)
+                .map(|bitmap| (bitmap.width as f32, bitmap.height as f32)),
+        }
+    }
+}
+
+// A surface that display lists are drawn on, such as a grid of pixels, the
+// canvas of a graphics library, or a recorder of the calls that tests what
+// is drawn. Geometry is in the coordinates of the transforms in effect, and
+// each push is undone by the matching pop.
+pub trait PaintBackend {
+    // Fill a rectangle with a color, blended over what is already drawn.
+    fn fill_rect(&mut self, color: Color, rect: Rectangle);
+    fn fill_rounded_rect(&mut self, color: Color, rect: &RoundedRect);
+    // Fill the area between a rounded rectangle and that rectangle shrunk
+    // by `widths`, with each point in the color of the side nearest to it.
+    fn fill_border(
+        &mut self,
+        colors: &Sides<Color>,
+        rect: &RoundedRect,
+        widths: EdgeSizes,
+    );
+    // Draw a shadow shaped like `shadow` and blurred by the blur radius,
+    // outside of the rectangle `rect` that casts it.
+    fn draw_shadow(
+        &mut self,
+        color: Color,
+        rect: &RoundedRect,
+        shadow: &RoundedRect,
+        blur: f32,
+    );
+    fn draw_text(&mut self, run: &TextRun);
+    // Fill the inside of a path by a fill rule, with its open subpaths
+    // closed.
+    fn fill_path(&mut self, color: Color, path: &Path, fill_rule: FillRule);
+    // Stroke the lines of a path, with round joins and butt caps.
+    fn stroke_path(&mut self, color: Color, path: &Path, width: f32);
+    // Draw a bitmap scaled to `tile`, and repeated within `rect` along the
+    // axes that `repeat` asks for.
+    fn draw_image(
+        &mut self,
+        bitmap: &Bitmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    );
+    // Like `draw_image`, but with a gradient, whose geometry is relative to
+    // the tile.
+    fn draw_gradient(
+        &mut self,
+        gradient: &ImageSource,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    );
+    fn push_clip(&mut self, clip: &RoundedRect);
+    fn push_transform(&mut self, transform: Transform);
+    // Draw into a transparent layer until the matching pop, which blends
+    // the layer with an opacity over what is below it.
+    fn push_layer(&mut self, opacity: f32);
+    fn pop(&mut self);
+}
+
+// Dump a display list as text, one command per line, with the commands
+// between a push and its pop indented under the push. Lengths are rounded
+// to hundredths and colors are written like in CSS, so that the output is
+// stable and tests can compare it with golden files. Nodes are left out,
+// since their ids depend on the order that documents were parsed in.
+pub fn dump_display_list(list: &[DisplayCommand]) -> String {
+    let mut output = String::new();
+    let mut depth = 0;
+    for command in list {
+        if matches!(
+            command,
+            DisplayCommand::PopClip
+                | DisplayCommand::PopLayer
+                | DisplayCommand::PopTransform
+        ) {
+            depth -= 1;
+        }
+        let indent = "  ".repeat(depth);
+        let _ = writeln!(output, "{indent}{}", dump_command(command));
+        if matches!(
+            command,
+            DisplayCommand::PushClip(_)
+                | DisplayCommand::PushLayer { .. }
+                | DisplayCommand::PushTransform(_)
+        ) {
+            depth += 1;
+        }
+    }
+    output
+}
+
+fn dump_command(command: &DisplayCommand) -> String {
+    let color = |color: &Color| Value::ColorValue(*color).to_string();
+    let point = |(x, y): (f32, f32)| format!("({}, {})", round(x), round(y));
+    match command {
+        DisplayCommand::SolidRect { color: c, rect } => {
+            format!("rect {} {}", color(c), dump_rect(*rect))
+        }
+        DisplayCommand::Text(run) => {
+            let mut text = format!(
+                "text {:?} {} baseline {} {} {}px {:?} {} {:?}",
+                run.text,
+                dump_rect(run.rect),
+                round(run.baseline),
+                color(&run.color),
+                round(run.font_size),
+                run.font_family,
+                run.font_weight,
+                run.font_style,
+            );
+            if run.word_spacing != 0.0 {
+                let _ =
+                    write!(text, " word-spacing {}", round(run.word_spacing));
+            }
+            text
+        }
+        DisplayCommand::BoxShadow {
+            color: c,
+            rect,
+            shadow,
+            blur,
+        } => format!(
+            "shadow {} {} of {} blur {}",
+            color(c),
+            dump_rounded(shadow),
+            dump_rounded(rect),
+            round(*blur)
+        ),
+        DisplayCommand::Image { url, rect, .. } => {
+            format!("image {:?} {}", url, dump_rect(*rect))
+        }
+        DisplayCommand::Svg { svg, rect } => {
+            format!("svg {} with {} shapes", dump_rect(*rect), svg.shapes.len())
+        }
+        DisplayCommand::BackgroundImage {
+            image,
+            rect,
+            tile,
+            repeat_x,
+            repeat_y,
+        } => {
+            let stops = |stops: &[GradientStop]| {
+                stops
+                    .iter()
+                    .map(|stop| {
+                        format!("{} {}", color(&stop.color), round(stop.offset))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let image = match image {
+                ImageSource::Url(url) => format!("{:?}", url),
+                ImageSource::LinearGradient {
+                    start,
+                    end,
+                    stops: s,
+                } => {
+                    format!(
+                        "linear {} to {} [{}]",
+                        point(*start),
+                        point(*end),
+                        stops(s)
+                    )
+                }
+                ImageSource::RadialGradient {
+                    center,
+                    radii,
+                    stops: s,
+                } => format!(
+                    "radial {} radii {} [{}]",
+                    point(*center),
+                    point(*radii),
+                    stops(s)
+                ),
+            };
+            let repeat = match (repeat_x, repeat_y) {
+                (true, true) => "repeat",
+                (true, false) => "repeat-x",
+                (false, true) => "repeat-y",
+                (false, false) => "no-repeat",
+            };
+            format!(
+                "background {} {} tile {} {}",
+                image,
+                dump_rect(*rect),
+                dump_rect(*tile),
+                repeat
+            )
+        }
+        DisplayCommand::RoundedRect { color: c, rect } => {
+            format!("rounded rect {} {}", color(c), dump_rounded(rect))
+        }
+        DisplayCommand::RoundedBorder {
+            colors,
+            rect,
+            widths,
+        } => format!(
+            "rounded border {} {} {} {} {} widths {} {} {} {}",
+            color(&colors.top),
+            color(&colors.right),
+            color(&colors.bottom),
+            color(&colors.left),
+            dump_rounded(rect),
+            round(widths.top),
+            round(widths.right),
+            round(widths.bottom),
+            round(widths.left)
+        ),
+        DisplayCommand::PushClip(rect) => {
+            format!("clip {}", dump_rounded(rect))
+        }
+        DisplayCommand::PopClip => "pop clip".to_string(),
+        DisplayCommand::PushLayer { opacity } => {
+            format!("layer {}", round(*opacity))
+        }
+        DisplayCommand::PopLayer => "pop layer".to_string(),
+        DisplayCommand::PushTransform(transform) => {
+            let Transform { a, b, c, d, e, f } = *transform;
+            let [a, b, c, d, e, f] = [a, b, c, d, e, f].map(round);
+            format!("transform [{a} {b} {c} {d} {e} {f}]")
+        }
+        DisplayCommand::PopTransform => "pop transform".to_string(),
+    }
+}
+
+// Format a rounded rectangle for dumps, with its radii unless they are all
+// zero.
+fn dump_rounded(rect: &RoundedRect) -> String {
+    let RoundedRect { rect, radii } = rect;
+    let corners = [
+        radii.top_left,
+        radii.top_right,
+        radii.bottom_right,
+        radii.bottom_left,
+    ];
+    if corners.iter().all(|&(x, y)| x == 0.0 && y == 0.0) {
+        return dump_rect(*rect);
+    }
+    let corners = corners
+        .map(|(x, y)| format!("{}/{}", round(x), round(y)))
+        .join(" ");
+    format!("{} radii {}", dump_rect(*rect), corners)
+}
+
+// Draw a display list on a backend from back to front, with the images that
+// have been decoded. Images that aren't decoded are left out.
+pub fn paint_display_list(
+    list: &[DisplayCommand],
+    images: &Images,
+    backend: &mut dyn PaintBackend,
+) {
+    for command in list {
+        match command {
+            DisplayCommand::SolidRect { color, rect } => {
+                backend.fill_rect(*color, *rect);
+            }
+            DisplayCommand::Text(run) => backend.draw_text(run),
+            DisplayCommand::BoxShadow {
+                color,
+                rect,
+                shadow,
+                blur,
+            } => backend.draw_shadow(*color, rect, shadow, *blur),
+            DisplayCommand::Image { url, rect, .. } => {
+                if let Some(svg) = images.get_svg(url) {
+                    draw_svg(backend, svg, *rect);
+                } else if let Some(bitmap) = images.get(url) {
+                    backend.draw_image(bitmap, *rect, *rect, (false, false));
+                }
+            }
+            DisplayCommand::Svg { svg, rect } => draw_svg(backend, svg, *rect),
+            DisplayCommand::BackgroundImage {
+                image,
+                rect,
+                tile,
+                repeat_x,
+                repeat_y,
+            } => {
+                let repeat = (*repeat_x, *repeat_y);
+                match image {
+                    ImageSource::Url(url) => {
+                        if let Some(bitmap) = images.get(url) {
+                            backend.draw_image(bitmap, *rect, *tile, repeat);
+                        }
+                    }
+                    gradient => {
+                        backend.draw_gradient(gradient, *rect, *tile, repeat);
+                    }
+                }
+            }
+            DisplayCommand::RoundedRect { color, rect } => {
+                backend.fill_rounded_rect(*color, rect);
+            }
+            DisplayCommand::RoundedBorder {
+                colors,
+                rect,
+                widths,
+            } => backend.fill_border(colors, rect, *widths),
+            DisplayCommand::PushClip(clip) => backend.push_clip(clip),
+            DisplayCommand::PushLayer { opacity } => {
+                backend.push_layer(*opacity);
+            }
+            DisplayCommand::PushTransform(transform) => {
+                backend.push_transform(*transform);
+            }
+            DisplayCommand::PopClip
+            | DisplayCommand::PopLayer
+            | DisplayCommand::PopTransform => backend.pop(),
+        }
+    }
+}
+
+// Draw the shapes of an SVG image with its view box fit into a rectangle,
+// which clips them.
+fn draw_svg(backend: &mut dyn PaintBackend, svg: &Svg, rect: Rectangle) {
+    backend.push_clip(&RoundedRect::new(rect, Default::default()));
+    backend.push_transform(svg.transform_to(rect));
+    for shape in &svg.shapes {
+        if let Some(color) = shape.fill {
+            backend.fill_path(color, &shape.path, shape.fill_rule);
+        }
+        if let Some(color) = shape.stroke.filter(|_| shape.stroke_width > 0.0) {
+            backend.stroke_path(color, &shape.path, shape.stroke_width);
+        }
+    }
+    backend.pop();
+    backend.pop();
+}
+
+// A grid of pixels that display lists are rasterized into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    // The pixels row by row, from the top left corner.
+    pub pixels: Vec<Color>,
+    pub width: usize,
+    pub height: usize,
+    // The clips in effect, from the outermost one, with the transforms
+    // from the canvas to the coordinates that they are in.
+    clips: Vec<(RoundedRect, Transform)>,
+    // The transform in effect, and the one that undoes it unless it
+    // flattens the plane, in which case nothing is drawn.
+    transform: Transform,
+    inverse: Option<Transform>,
+    // What the pops restore, from the outermost push.
+    saved: Vec<Saved>,
+}
+
+// How much two canvases differ, as the largest difference of a channel of
+// a pixel, and the number of pixels that differ at all. Reftests allow
+// small differences, such as from rounding colors differently.
+// SEE: https://web-platform-tests.org/writing-tests/reftests.html#fuzzy-matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PixelDifference {
+    pub max_difference: u8,
+    pub total_pixels: usize,
+}
+
+// The state before a push, which the matching pop restores.
+#[derive(Debug, Clone, PartialEq)]
+enum Saved {
+    Clip,
+    Transform(Transform),
+    // The pixels below a layer, and the opacity of the layer.
+    Layer(Vec<Color>, f32),
+}
+
+impl Canvas {
+    // Create a canvas filled with opaque white, which is what the page is
+    // drawn over when the root has no background.
+    pub fn new(width: usize, height: usize) -> Self {
+        let white = Color {
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        };
+        Self {
+            pixels: vec![white; width * height],
+            width,
+            height,
+            clips: Vec::new(),
+            transform: Transform::IDENTITY,
+            inverse: Some(Transform::IDENTITY),
+            saved: Vec::new(),
+        }
+    }
+
+    fn push_clip(&mut self, clip: &RoundedRect) {
+        let inverse = self.inverse.unwrap_or(Transform::IDENTITY);
+        self.clips.push((*clip, inverse));
+        self.saved.push(Saved::Clip);
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.saved.push(Saved::Transform(self.transform));
+        self.transform = self.transform.multiply(transform);
+        self.inverse = self.transform.inverse();
+    }
+
+    fn push_layer(&mut self, opacity: f32) {
+        let backdrop = std::mem::replace(
+            &mut self.pixels,
+            vec![TRANSPARENT; self.width * self.height],
+        );
+        self.saved.push(Saved::Layer(backdrop, opacity));
+    }
+
+    fn pop(&mut self) {
+        match self.saved.pop().expect("Unbalanced pops") {
+            Saved::Clip => {
+                self.clips.pop();
+            }
+            Saved::Transform(transform) => {
+                self.transform = transform;
+                self.inverse = transform.inverse();
+            }
+            Saved::Layer(backdrop, opacity) => {
+                let layer = std::mem::replace(&mut self.pixels, backdrop);
+                for (pixel, color) in self.pixels.iter_mut().zip(layer) {
+                    let alpha = (color.a as f32 * opacity).round() as u8;
+                    *pixel = blend(Color { a: alpha, ..color }, *pixel);
+                }
+            }
+        }
+    }
+
+    // Get the transform in effect, if it keeps rectangles upright, such as
+    // one that scales CSS pixels to device pixels.
+    fn axis_aligned(&self) -> Option<Transform> {
+        let Transform { a, b, c, d, .. } = self.transform;
+        (b == 0.0 && c == 0.0 && a > 0.0 && d > 0.0).then_some(self.transform)
+    }
+
+    // Get the pixels that a rectangle covers after the transform in effect,
+    // as the columns and rows from `x0` and `y0` up to `x1` and `y1`.
+    fn pixel_bounds(&self, rect: Rectangle) -> (usize, usize, usize, usize) {
+        let points = Quad::new(rect, Some(self.transform)).points;
+        let min = |axis: fn(&(f32, f32)) -> f32| {
+            points.iter().map(axis).fold(f32::INFINITY, f32::min)
+        };
+        let max = |axis: fn(&(f32, f32)) -> f32| {
+            points.iter().map(axis).fold(f32::NEG_INFINITY, f32::max)
+        };
+        let clamp =
+            |value: f32, max: usize| value.clamp(0.0, max as f32) as usize;
+        (
+            clamp(min(|point| point.0).floor(), self.width),
+            clamp(min(|point| point.1).floor(), self.height),
+            clamp(max(|point| point.0).ceil(), self.width),
+            clamp(max(|point| point.1).ceil(), self.height),
+        )
+    }
+
+    // Fill the pixels whose centers are in a rectangle, blending the color
+    // over them by its alpha. Under a transform that keeps it upright, its
+    // edges are snapped to the nearest pixel edges.
+    pub fn fill_rect(&mut self, color: Color, rect: Rectangle) {
+        let transform = self.axis_aligned();
+        let Some(transform) = transform.filter(|_| self.clips.is_empty())
+        else {
+            let shape = RoundedRect::new(rect, Default::default());
+            self.fill_shape(rect, |point| {
+                shape.contains(point).then_some(color)
+            });
+            return;
+        };
+        let clamp = |value: f32, max: usize| {
+            value.round().clamp(0.0, max as f32) as usize
+        };
+        let (left, top) = transform.apply((rect.x, rect.y));
+        let (right, bottom) =
+            transform.apply((rect.x + rect.width, rect.y + rect.height));
+        let x0 = clamp(left, self.width);
+        let y0 = clamp(top, self.height);
+        let x1 = clamp(right, self.width);
+        let y1 = clamp(bottom, self.height);
+        for y in y0..y1 {
+            for pixel in
+                &mut self.pixels[y * self.width + x0..y * self.width + x1]
+            {
+                *pixel = blend(color, *pixel);
+            }
+        }
+    }
+
+    // Draw a shadow outside of the rectangle that casts it. A blurred
+    // shadow is approximated by blurring its shape three times with a box
+    // blur, which is close to a Gaussian blur with half the blur radius as
+    // its standard deviation.
+    // SEE: https://drafts.csswg.org/css-backgrounds/#shadow-blur
+    fn draw_shadow(
+        &mut self,
+        color: Color,
+        rect: &RoundedRect,
+        shadow: &RoundedRect,
+        blur: f32,
+    ) {
+        if blur <= 0.0 {
+            self.fill_shape(shadow.rect, |point| {
+                (shadow.contains(point) && !rect.contains(point))
+                    .then_some(color)
+            });
+            return;
+        }
+        let Some(inverse) = self.inverse else {
+            return;
+        };
+        let radius = blur_radius(blur);
+        // Three blurs spread the shadow by three times the radius, and the
+        // parts of it off the canvas are only kept as far as they blur into
+        // it.
+        let extent = 3 * radius as i32;
+        let canvas = Quad::new(
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: self.width as f32,
+                height: self.height as f32,
+            },
+            Some(inverse),
+        )
+        .points;
+        let edge = |axis: fn(&(f32, f32)) -> f32, fold: fn(f32, f32) -> f32| {
+            canvas.iter().map(axis).reduce(fold).unwrap_or_default()
+        };
+        let bounds = shadow.rect;
+        let x0 = (bounds.x.floor() as i32)
+            .max(edge(|point| point.0, f32::min).floor() as i32)
+            - extent;
+        let y0 = (bounds.y.floor() as i32)
+            .max(edge(|point| point.1, f32::min).floor() as i32)
+            - extent;
+        let x1 = ((bounds.x + bounds.width).ceil() as i32)
+            .min(edge(|point| point.0, f32::max).ceil() as i32)
+            + extent;
+        let y1 = ((bounds.y + bounds.height).ceil() as i32)
+            .min(edge(|point| point.1, f32::max).ceil() as i32)
+            + extent;
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let (width, height) = ((x1 - x0) as usize, (y1 - y0) as usize);
+        let mut mask: Vec<f32> = (y0..y1)
+            .flat_map(|y| {
+                (x0..x1).map(move |x| {
+                    let center = (x as f32 + 0.5, y as f32 + 0.5);
+                    if shadow.contains(center) { 1.0 } else { 0.0 }
+                })
+            })
+            .collect();
+        for _ in 0..3 {
+            box_blur(&mut mask, width, height, radius);
+        }
+        self.draw_coverage(color, (x0, y0), (width, height), &mask, |point| {
+            rect.contains(point)
+        });
+    }
+
+    // Draw the glyphs of a run of text. Under a transform that does more
+    // than move and evenly scale them, the glyphs are rasterized before the
+    // transform and then mapped through it.
+    #[cfg(feature = "fonts")]
+    fn draw_text(&mut self, fonts: &crate::font::Fonts, run: &TextRun) {
+        if let Some(run) = run.transformed(self.transform) {
+            fonts.draw_glyphs(&run, |x, y, coverage| {
+                let alpha = (run.color.a as f32 * coverage).round() as u8;
+                self.blend_pixel(
+                    x,
+                    y,
+                    Color {
+                        a: alpha,
+                        ..run.color
+                    },
+                );
+            });
+            return;
+        }
+        let mut glyphs = Vec::new();
+        fonts.draw_glyphs(run, |x, y, coverage| glyphs.push((x, y, coverage)));
+        let (Some(x0), Some(y0)) = (
+            glyphs.iter().map(|glyph| glyph.0).min(),
+            glyphs.iter().map(|glyph| glyph.1).min(),
+        ) else {
+            return;
+        };
+        let width = glyphs.iter().map(|glyph| glyph.0 - x0 + 1).max();
+        let height = glyphs.iter().map(|glyph| glyph.1 - y0 + 1).max();
+        let (width, height) =
+            (width.unwrap_or(0) as usize, height.unwrap_or(0) as usize);
+        let mut mask = vec![0.0; width * height];
+        for (x, y, coverage) in glyphs {
+            let cell = &mut mask[(y - y0) as usize * width + (x - x0) as usize];
+            *cell = (*cell + coverage).min(1.0);
+        }
+        self.draw_coverage(run.color, (x0, y0), (width, height), &mask, |_| {
+            false
+        });
+    }
+
+    // Blend a color over the pixels by the coverage of a grid of cells,
+    // which are a unit wide in the coordinates of the transform in effect
+    // and start at `origin`. Each pixel is covered as much as the cell that
+    // its center is in, unless `excluded` contains the center.
+    fn draw_coverage(
+        &mut self,
+        color: Color,
+        (x0, y0): (i32, i32),
+        (width, height): (usize, usize),
+        coverage: &[f32],
+        excluded: impl Fn((f32, f32)) -> bool,
+    ) {
+        let Some(inverse) = self.inverse else {
+            return;
+        };
+        let (px0, py0, px1, py1) = self.pixel_bounds(Rectangle {
+            x: x0 as f32,
+            y: y0 as f32,
+            width: width as f32,
+            height: height as f32,
+        });
+        for y in py0..py1 {
+            for x in px0..px1 {
+                let center = (x as f32 + 0.5, y as f32 + 0.5);
+                let point = inverse.apply(center);
+                let column = point.0.floor() as i32 - x0;
+                let row = point.1.floor() as i32 - y0;
+                if column < 0
+                    || row < 0
+                    || column as usize >= width
+                    || row as usize >= height
+                    || excluded(point)
+                    || !self.is_visible(center)
+                {
+                    continue;
+                }
+                let cell = coverage[row as usize * width + column as usize];
+                let alpha = (color.a as f32 * cell).round() as u8;
+                if alpha == 0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[y * self.width + x];
+                *pixel = blend(Color { a: alpha, ..color }, *pixel);
+            }
+        }
+    }
+
+    // Fill the pixels that a shape covers, given the color of the shape at
+    // each point that it covers. Each pixel is sampled at several points,
+    // so that curved edges are smooth.
+    fn fill_shape(
+        &mut self,
+        bounds: Rectangle,
+        color_at: impl Fn((f32, f32)) -> Option<Color>,
+    ) {
+        const SAMPLES: usize = 4;
+        let Some(inverse) = self.inverse else {
+            return;
+        };
+        let (x0, y0, x1, y1) = self.pixel_bounds(bounds);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // The sums of the premultiplied channels of the samples.
+                let mut sums = [0.0; 4];
+                for i in 0..SAMPLES * SAMPLES {
+                    let point = (
+                        x as f32
+                            + ((i % SAMPLES) as f32 + 0.5) / SAMPLES as f32,
+                        y as f32
+                            + ((i / SAMPLES) as f32 + 0.5) / SAMPLES as f32,
+                    );
+                    if !self.is_visible(point) {
+                        continue;
+                    }
+                    if let Some(color) = color_at(inverse.apply(point)) {
+                        let alpha = color.a as f32;
+                        sums[0] += color.r as f32 * alpha;
+                        sums[1] += color.g as f32 * alpha;
+                        sums[2] += color.b as f32 * alpha;
+                        sums[3] += alpha;
+                    }
+                }
+                if sums[3] == 0.0 {
+                    continue;
+                }
+                let color = Color {
+                    r: (sums[0] / sums[3]).round() as u8,
+                    g: (sums[1] / sums[3]).round() as u8,
+                    b: (sums[2] / sums[3]).round() as u8,
+                    a: (sums[3] / (SAMPLES * SAMPLES) as f32).round() as u8,
+                };
+                let pixel = &mut self.pixels[y * self.width + x];
+                *pixel = blend(color, *pixel);
+            }
+        }
+    }
+
+    fn fill_path(&mut self, color: Color, path: &Path, fill_rule: FillRule) {
+        self.fill_shape(path.bounding_rect(), |point| {
+            path.contains(point, fill_rule).then_some(color)
+        });
+    }
+
+    fn stroke_path(&mut self, color: Color, path: &Path, width: f32) {
+        let half = width / 2.0;
+        let bounds = path.bounding_rect().expanded_by(EdgeSizes {
+            left: half,
+            right: half,
+            top: half,
+            bottom: half,
+        });
+        self.fill_shape(bounds, |point| {
+            path.stroke_contains(point, width).then_some(color)
+        });
+    }
+
+    // Draw a bitmap scaled to `tile`, and repeated within `rect` along the
+    // axes that `repeat` asks for. Each pixel takes the color of the
+    // nearest pixel of the bitmap.
+    pub fn draw_bitmap(
+        &mut self,
+        bitmap: &Bitmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        if bitmap.pixels.is_empty() {
+            return;
+        }
+        let source = |point: f32, size: f32, pixels: usize| {
+            ((point / size * pixels as f32) as usize).min(pixels - 1)
+        };
+        self.fill_tiles(rect, tile, repeat, |(x, y)| {
+            let row = source(y, tile.height, bitmap.height);
+            let column = source(x, tile.width, bitmap.width);
+            Some(bitmap.pixels[row * bitmap.width + column])
+        });
+    }
+
+    // Fill the pixels of `rect` with tiles, repeated along the axes that
+    // `repeat` asks for. Each pixel is colored by `sample` at its center,
+    // relative to the top left corner of the tile that contains it.
+    fn fill_tiles(
+        &mut self,
+        rect: Rectangle,
+        tile: Rectangle,
+        (repeat_x, repeat_y): (bool, bool),
+        sample: impl Fn((f32, f32)) -> Option<Color>,
+    ) {
+        let Some(inverse) = self.inverse else {
+            return;
+        };
+        if is_empty(tile) {
+            return;
+        }
+        let (mut x0, mut x1) = (rect.x, rect.x + rect.width);
+        let (mut y0, mut y1) = (rect.y, rect.y + rect.height);
+        if !repeat_x {
+            x0 = x0.max(tile.x);
+            x1 = x1.min(tile.x + tile.width);
+        }
+        if !repeat_y {
+            y0 = y0.max(tile.y);
+            y1 = y1.min(tile.y + tile.height);
+        }
+        let area = Rectangle {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        };
+        if is_empty(area) {
+            return;
+        }
+        let (px0, py0, px1, py1) = self.pixel_bounds(area);
+        for y in py0..py1 {
+            for x in px0..px1 {
+                let center = (x as f32 + 0.5, y as f32 + 0.5);
+                let (local_x, local_y) = inverse.apply(center);
+                if !(x0..x1).contains(&local_x)
+                    || !(y0..y1).contains(&local_y)
+                    || !self.is_visible(center)
+                {
+                    continue;
+                }
+                let tile_x = (local_x - tile.x).rem_euclid(tile.width);
+                let tile_y = (local_y - tile.y).rem_euclid(tile.height);
+                if let Some(color) = sample((tile_x, tile_y)) {
+                    let pixel = &mut self.pixels[y * self.width + x];
+                    *pixel = blend(color, *pixel);
+                }
+            }
+        }
+    }
+
+    // Check if a point of the canvas is inside all the clips in effect.
+    fn is_visible(&self, point: (f32, f32)) -> bool {
+        self.clips
+            .iter()
+            .all(|(clip, inverse)| clip.contains(inverse.apply(point)))
+    }
+
+    // Blend a color over a pixel, unless it is outside the canvas or
+    // clipped.
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0
+            || y < 0
+            || x as usize >= self.width
+            || y as usize >= self.height
+            || !self.is_visible((x as f32 + 0.5, y as f32 + 0.5))
+        {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        *pixel = blend(color, *pixel);
+    }
+
+    // Compare the pixels with those of a canvas of the same size.
+    pub fn difference(&self, other: &Canvas) -> PixelDifference {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "Canvases of different sizes"
+        );
+        let mut difference = PixelDifference::default();
+        for (a, b) in self.pixels.iter().zip(&other.pixels) {
+            let channels = [(a.r, b.r), (a.g, b.g), (a.b, b.b), (a.a, b.a)];
+            let max = channels.iter().map(|&(a, b)| a.abs_diff(b)).max();
+            let max = max.unwrap_or_default();
+            if max > 0 {
+                difference.max_difference = difference.max_difference.max(max);
+                difference.total_pixels += 1;
+            }
+        }
+        difference
+    }
+
+    // Get the pixels as bytes of red, green, blue, and alpha.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| [color.r, color.g, color.b, color.a])
+            .collect()
+    }
+
+    // Encode the pixels as a PNG image.
+    #[cfg(feature = "png")]
+    pub fn write_png(
+        &self,
+        writer: impl std::io::Write,
+    ) -> std::io::Result<()> {
+        let mut encoder =
+            png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba())?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+// The backend that rasterizes display lists into a canvas on the CPU. Text
+// is only drawn with fonts.
+#[derive(Debug)]
+pub struct CanvasBackend<'a> {
+    pub canvas: &'a mut Canvas,
+    #[cfg(feature = "fonts")]
+    pub fonts: Option<&'a crate::font::Fonts>,
+}
+
+impl<'a> CanvasBackend<'a> {
+    pub fn new(canvas: &'a mut Canvas) -> Self {
+        Self {
+            canvas,
+            #[cfg(feature = "fonts")]
+            fonts: None,
+        }
+    }
+
+    #[cfg(feature = "fonts")]
+    pub fn with_fonts(
+        canvas: &'a mut Canvas,
+        fonts: &'a crate::font::Fonts,
+    ) -> Self {
+        Self {
+            canvas,
+            fonts: Some(fonts),
+        }
+    }
+}
+
+impl PaintBackend for CanvasBackend<'_> {
+    fn fill_rect(&mut self, color: Color, rect: Rectangle) {
+        self.canvas.fill_rect(color, rect);
+    }
+
+    fn fill_rounded_rect(&mut self, color: Color, rect: &RoundedRect) {
+        self.canvas.fill_shape(rect.rect, |point| {
+            rect.contains(point).then_some(color)
+        });
+    }
+
+    fn fill_border(
+        &mut self,
+        colors: &Sides<Color>,
+        rect: &RoundedRect,
+        widths: EdgeSizes,
+    ) {
+        let inner = rect.shrunk_by(widths);
+        self.canvas.fill_shape(rect.rect, |point| {
+            (rect.contains(point) && !inner.contains(point))
+                .then(|| nearest_side(rect.rect, widths, point))
+                .map(|side| match side {
+                    Side::Top => colors.top,
+                    Side::Right => colors.right,
+                    Side::Bottom => colors.bottom,
+                    Side::Left => colors.left,
+                })
+        });
+    }
+
+    fn draw_shadow(
+        &mut self,
+        color: Color,
+        rect: &RoundedRect,
+        shadow: &RoundedRect,
+        blur: f32,
+    ) {
+        self.canvas.draw_shadow(color, rect, shadow, blur);
+    }
+
+    #[cfg_attr(not(feature = "fonts"), allow(unused_variables))]
+    fn draw_text(&mut self, run: &TextRun) {
+        #[cfg(feature = "fonts")]
+        if let Some(fonts) = self.fonts {
+            self.canvas.draw_text(fonts, run);
+        }
+    }
+
+    fn fill_path(&mut self, color: Color, path: &Path, fill_rule: FillRule) {
+        self.canvas.fill_path(color, path, fill_rule);
+    }
+
+    fn stroke_path(&mut self, color: Color, path: &Path, width: f32) {
+        self.canvas.stroke_path(color, path, width);
+    }
+
+    fn draw_image(
+        &mut self,
+        bitmap: &Bitmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        self.canvas.draw_bitmap(bitmap, rect, tile, repeat);
+    }
+
+    fn draw_gradient(
+        &mut self,
+        gradient: &ImageSource,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        self.canvas.fill_tiles(rect, tile, repeat, |point| {
+            gradient.gradient_color(point)
+        });
+    }
+
+    fn push_clip(&mut self, clip: &RoundedRect) {
+        self.canvas.push_clip(clip);
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.canvas.push_transform(transform);
+    }
+
+    fn push_layer(&mut self, opacity: f32) {
+        self.canvas.push_layer(opacity);
+    }
+
+    fn pop(&mut self) {
+        self.canvas.pop();
+    }
+}
+
+// Get the transform from the page, which is laid out in CSS pixels, to the
+// device pixels of a canvas that covers `bounds` of it.
+pub fn device_transform(
+    bounds: Rectangle,
+    device_pixel_ratio: f32,
+) -> Transform {
+    Transform::scale(device_pixel_ratio, device_pixel_ratio)
+        .multiply(Transform::translate(-bounds.x, -bounds.y))
+}
+
+// Get the size in device pixels of a canvas that covers `bounds` of the
+// page.
+pub fn device_size(
+    bounds: Rectangle,
+    device_pixel_ratio: f32,
+) -> (usize, usize) {
+    (
+        (bounds.width * device_pixel_ratio).round() as usize,
+        (bounds.height * device_pixel_ratio).round() as usize,
+    )
+}
+
+// Draw a laid out tree on a backend, with the top left corner of `bounds`
+// of the page at its origin, and each CSS pixel covering
+// `device_pixel_ratio` pixels of the backend along each axis.
+pub fn paint_with_backend(
+    layout_root: &LayoutBox,
+    bounds: Rectangle,
+    images: &Images,
+    device_pixel_ratio: f32,
+    backend: &mut dyn PaintBackend,
+) {
+    let list = build_display_list(layout_root, images);
+    backend.push_transform(device_transform(bounds, device_pixel_ratio));
+    paint_display_list(&list, images, backend);
+    backend.pop();
+}
+
+// Rasterize a laid out tree into a canvas that covers `bounds` of the page,
+// with the images that have been decoded, at a device pixel ratio.
+pub fn paint(
+    layout_root: &LayoutBox,
+    bounds: Rectangle,
+    images: &Images,
+    device_pixel_ratio: f32,
+) -> Canvas {
+    let (width, height) = device_size(bounds, device_pixel_ratio);
+    let mut canvas = Canvas::new(width, height);
+    let mut backend = CanvasBackend::new(&mut canvas);
+    paint_with_backend(
+        layout_root,
+        bounds,
+        images,
+        device_pixel_ratio,
+        &mut backend,
+    );
+    canvas
+}
+
+// Like `paint`, but also draws text with the fonts that it was laid out
+// with, which are rasterized at the size of the text in device pixels.
+#[cfg(feature = "fonts")]
+pub fn paint_with_fonts(
+    layout_root: &LayoutBox,
+    bounds: Rectangle,
+    images: &Images,
+    device_pixel_ratio: f32,
+    fonts: &crate::font::Fonts,
+) -> Canvas {
+    let (width, height) = device_size(bounds, device_pixel_ratio);
+    let mut canvas = Canvas::new(width, height);
+    let mut backend = CanvasBackend::with_fonts(&mut canvas, fonts);
+    paint_with_backend(
+        layout_root,
+        bounds,
+        images,
+        device_pixel_ratio,
+        &mut backend,
+    );
+    canvas
+}
+
+// The size of the square tiles that a canvas is painted again in when part
+// of it is damaged.
+pub const TILE_SIZE: usize = 64;
+
+// A drawing command of a display list, with the pushes that it is nested in
+// and the bounds of what it can draw on the page.
+#[derive(Debug, PartialEq)]
+struct DrawnCommand<'a> {
+    command: &'a DisplayCommand,
+    context: Vec<&'a DisplayCommand>,
+    bounds: Rectangle,
+}
+
+// Find the areas of the page that look different between two display lists
+// of it, such as before and after a style change, which are all that has to
+// be painted again. The commands are matched from both ends of the lists,
+// and the ones in between that differ, or are drawn in different clips,
+// layers, or transforms, damage where they draw in both lists.
+pub fn damage(
+    old: &[DisplayCommand],
+    new: &[DisplayCommand],
+) -> Vec<Rectangle> {
+    let old = drawn_commands(old);
+    let new = drawn_commands(new);
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    let mut damage = Vec::new();
+    let mut add = |command: &DrawnCommand| {
+        if !is_empty(command.bounds) && !damage.contains(&command.bounds) {
+            damage.push(command.bounds);
+        }
+    };
+    // When as many commands changed as there were, they are taken to be the
+    // same ones changed in place.
+    if old.len() == new.len() {
+        for (a, b) in old.iter().zip(new).filter(|(a, b)| a != b) {
+            add(a);
+            add(b);
+        }
+    } else {
+        old.iter().chain(new).for_each(add);
+    }
+    damage
+}
+
+// Get the drawing commands of a display list, with where they draw through
+// the transforms and within the clips that they are nested in.
+fn drawn_commands(list: &[DisplayCommand]) -> Vec<DrawnCommand<'_>> {
+    let mut commands = Vec::new();
+    let mut context = Vec::new();
+    let mut transform = Transform::IDENTITY;
+    let mut clip: Option<Rectangle> = None;
+    // What the pops restore, from the outermost push.
+    let mut saved = Vec::new();
+    for command in list {
+        let bounds = match command {
+            DisplayCommand::SolidRect { rect, .. }
+            | DisplayCommand::Image { rect, .. }
+            | DisplayCommand::Svg { rect, .. }
+            | DisplayCommand::BackgroundImage { rect, .. } => *rect,
+            DisplayCommand::RoundedRect { rect, .. }
+            | DisplayCommand::RoundedBorder { rect, .. } => rect.rect,
+            // Glyphs may reach out of their line, such as the tails of
+            // italics or tall glyphs in a small line height.
+            DisplayCommand::Text(run) => {
+                let overhang = run.font_size / 2.0;
+                run.rect.expanded_by(EdgeSizes {
+                    left: overhang,
+                    right: overhang,
+                    top: overhang,
+                    bottom: overhang,
+                })
+            }
+            DisplayCommand::BoxShadow { shadow, blur, .. } => {
+                // A blurred shadow spreads by three times the blur radius,
+                // as it is drawn with three box blurs.
+                let extent = if *blur > 0.0 {
+                    3.0 * blur_radius(*blur) as f32 + 1.0
+                } else {
+                    0.0
+                };
+                shadow.rect.expanded_by(EdgeSizes {
+                    left: extent,
+                    right: extent,
+                    top: extent,
+                    bottom: extent,
+                })
+            }
+            DisplayCommand::PushClip(rect) => {
+                saved.push((transform, clip));
+                let bounds =
+                    Quad::new(rect.rect, Some(transform)).bounding_rect();
+                clip =
+                    Some(clip.map_or(bounds, |clip| clip.intersection(bounds)));
+                context.push(command);
+                continue;
+            }
+            DisplayCommand::PushLayer { .. } => {
+                saved.push((transform, clip));
+                context.push(command);
+                continue;
+            }
+            DisplayCommand::PushTransform(other) => {
+                saved.push((transform, clip));
+                transform = transform.multiply(*other);
+                context.push(command);
+                continue;
+            }
+            DisplayCommand::PopClip
+            | DisplayCommand::PopLayer
+            | DisplayCommand::PopTransform => {
+                (transform, clip) = saved.pop().expect("Unbalanced pops");
+                context.pop();
+                continue;
+            }
+        };
+        let bounds = Quad::new(bounds, Some(transform)).bounding_rect();
+        commands.push(DrawnCommand {
+            command,
+            context: context.clone(),
+            bounds: clip.map_or(bounds, |clip| clip.intersection(bounds)),
+        });
+    }
+    commands
+}
+
+// Paint the tiles of a canvas that covers `bounds` of the page again where
+// they overlap the damaged areas of the page, and leave the rest of it as
+// it was. The canvas is in device pixels, like one that `paint` made at the
+// device pixel ratio. Returns the areas of the canvas that were painted
+// again, in device pixels.
+pub fn repaint(
+    canvas: &mut Canvas,
+    bounds: Rectangle,
+    list: &[DisplayCommand],
+    images: &Images,
+    device_pixel_ratio: f32,
+    damage: &[Rectangle],
+) -> Vec<Rectangle> {
+    let transform = device_transform(bounds, device_pixel_ratio);
+    repaint_tiles(canvas, transform, damage, |tile, transform| {
+        let mut backend = CanvasBackend::new(tile);
+        backend.push_transform(transform);
+        paint_display_list(list, images, &mut backend);
+        backend.pop();
+    })
+}
+
+// Like `repaint`, but also draws text with the fonts that it was laid out
+// with.
+#[cfg(feature = "fonts")]
+pub fn repaint_with_fonts(
+    canvas: &mut Canvas,
+    bounds: Rectangle,
+    list: &[DisplayCommand],
+    images: &Images,
+    device_pixel_ratio: f32,
+    fonts: &crate::font::Fonts,
+    damage: &[Rectangle],
+) -> Vec<Rectangle> {
+    let transform = device_transform(bounds, device_pixel_ratio);
+    repaint_tiles(canvas, transform, damage, |tile, transform| {
+        let mut backend = CanvasBackend::with_fonts(tile, fonts);
+        backend.push_transform(transform);
+        paint_display_list(list, images, &mut backend);
+        backend.pop();
+    })
+}
+
+// Paint each damaged tile of a canvas on its own canvas, through the
+// transform from the page to the tile, and copy it over the tile. Painting
+// a tile alone gives the same pixels as painting the whole canvas, since
+// nothing is drawn differently by where the canvas ends.
+fn repaint_tiles(
+    canvas: &mut Canvas,
+    transform: Transform,
+    damage: &[Rectangle],
+    paint: impl Fn(&mut Canvas, Transform),
+) -> Vec<Rectangle> {
+    let columns = canvas.width.div_ceil(TILE_SIZE);
+    let rows = canvas.height.div_ceil(TILE_SIZE);
+    let mut damaged = vec![false; columns * rows];
+    let damage = damage
+        .iter()
+        .map(|rect| Quad::new(*rect, Some(transform)).bounding_rect());
+    for rect in damage.filter(|rect| !is_empty(*rect)) {
+        // The tiles from the one with the first pixel that the rectangle
+        // touches to the one with the last.
+        let tiles = |start: f32, length: f32, size: usize| {
+            let first = start.floor().clamp(0.0, size as f32) as usize;
+            let end = (start + length).ceil().clamp(0.0, size as f32) as usize;
+            if first < end {
+                first / TILE_SIZE..end.div_ceil(TILE_SIZE)
+            } else {
+                0..0
+            }
+        };
+        for row in tiles(rect.y, rect.height, canvas.height) {
+            for column in tiles(rect.x, rect.width, canvas.width) {
+                damaged[row * columns + column] = true;
+            }
+        }
+    }
+
+    let mut painted = Vec::new();
+    for (i, _) in damaged.iter().enumerate().filter(|(_, damaged)| **damaged) {
+        let (x0, y0) = ((i % columns) * TILE_SIZE, (i / columns) * TILE_SIZE);
+        let width = TILE_SIZE.min(canvas.width - x0);
+        let height = TILE_SIZE.min(canvas.height - y0);
+        let mut tile = Canvas::new(width, height);
+        paint(
+            &mut tile,
+            Transform::translate(-(x0 as f32), -(y0 as f32))
+                .multiply(transform),
+        );
+        for (y, row) in tile.pixels.chunks(width).enumerate() {
+            let start = (y0 + y) * canvas.width + x0;
+            canvas.pixels[start..start + width].copy_from_slice(row);
+        }
+        painted.push(Rectangle {
+            x: x0 as f32,
+            y: y0 as f32,
+            width: width as f32,
+            height: height as f32,
+        });
+    }
+    painted
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+// Get the border side that a point of a border is nearest to, relative to
+// the widths of the sides, which divides the corners from the outer corner
+// toward the inner one.
+fn nearest_side(
+    rect: Rectangle,
+    widths: EdgeSizes,
+    (x, y): (f32, f32),
+) -> Side {
+    [
+        (Side::Top, y - rect.y, widths.top),
+        (Side::Right, rect.x + rect.width - x, widths.right),
+        (Side::Bottom, rect.y + rect.height - y, widths.bottom),
+        (Side::Left, x - rect.x, widths.left),
+    ]
+    .into_iter()
+    .filter(|(_, _, width)| *width > 0.0)
+    .min_by(|(_, a, a_width), (_, b, b_width)| {
+        (a / a_width).total_cmp(&(b / b_width))
+    })
+    .map_or(Side::Top, |(side, _, _)| side)
+}
+
+// Get the radius of the box blur that is applied three times to blur a
+// shadow by a blur radius. Its variance is a third of that of a Gaussian
+// blur with half the blur radius as its standard deviation.
+pub fn blur_radius(blur: f32) -> usize {
+    let sigma = blur / 2.0;
+    (((4.0 * sigma * sigma + 1.0).sqrt() - 1.0) / 2.0)
+        .round()
+        .max(1.0) as usize
+}
+
+// Blur a grid of values by averaging each one with the values up to a
+// radius away, first along the rows and then along the columns. The values
+// past the edges are zero.
+pub fn box_blur(
+    values: &mut [f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+) {
+    let size = (2 * radius + 1) as f32;
+    let mut line = Vec::new();
+    let mut blur_line = |values: &mut [f32], start: usize, step: usize, len| {
+        line.clear();
+        line.extend((0..len).map(|i| values[start + i * step]));
+        let mut sum: f32 = line.iter().take(radius).sum();
+        for i in 0..len {
+            if i + radius < len {
+                sum += line[i + radius];
+            }
+            values[start + i * step] = sum / size;
+            if i >= radius {
+                sum -= line[i - radius];
+            }
+        }
+    };
+    for y in 0..height {
+        blur_line(values, y * width, 1, width);
+    }
+    for x in 0..width {
+        blur_line(values, x, width, height);
+    }
+}
+
+const TRANSPARENT: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+};
+
+// Composite a color over another with the source-over operator.
+// SEE: https://drafts.fxtf.org/compositing/#porterduffcompositingoperators_srcover
+fn blend(source: Color, backdrop: Color) -> Color {
+    if source.a == 255 {
+        return source;
+    }
+    let source_alpha = source.a as f32 / 255.0;
+    let backdrop_alpha = backdrop.a as f32 / 255.0 * (1.0 - source_alpha);
+    let alpha = source_alpha + backdrop_alpha;
+    if alpha == 0.0 {
+        return TRANSPARENT;
+    }
+    let channel = |s: u8, b: u8| {
+        ((s as f32 * source_alpha + b as f32 * backdrop_alpha) / alpha).round()
+            as u8
+    };
+    Color {
+        r: channel(source.r, backdrop.r),
+        g: channel(source.g, backdrop.g),
+        b: channel(source.b, backdrop.b),
+        a: (alpha * 255.0).round() as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        document::Document,
+        dom::BoundaryPoint,
+        html_parser::HTMLParser,
+        layout::{Dimensions, NoImages, layout_tree, layout_tree_with_metrics},
+        style::{
+            ElementStateProvider,
+            MediaContext,
+            StyleCache,
+            apply,
+            restyle,
+        },
+        text::MonospaceMetrics,
+    };
+
+    // Describe the commands of the display list of a tree.
+    fn describe(layout_root: &LayoutBox) -> Vec<String> {
+        describe_with_images(layout_root, &NoImages)
+    }
+
+    fn describe_with_images(
+        layout_root: &LayoutBox,
+        images: &dyn ImageSizes,
+    ) -> Vec<String> {
+        build_display_list(layout_root, images)
+            .iter()
+            .map(|command| match command {
+                DisplayCommand::SolidRect { color, rect } => format!(
+                    "rect #{:02x}{:02x}{:02x} ({}, {}) {}x{}",
+                    color.r,
+                    color.g,
+                    color.b,
+                    rect.x,
+                    rect.y,
+                    rect.width,
+                    rect.height
+                ),
+                DisplayCommand::Text(run) => format!(
+                    "text {:?} ({}, {}) baseline {}",
+                    run.text, run.rect.x, run.rect.y, run.baseline
+                ),
+                DisplayCommand::Image { rect, .. } => format!(
+                    "image ({}, {}) {}x{}",
+                    rect.x, rect.y, rect.width, rect.height
+                ),
+                DisplayCommand::Svg { svg, rect } => format!(
+                    "svg ({}, {}) {}x{} with {} shapes",
+                    rect.x,
+                    rect.y,
+                    rect.width,
+                    rect.height,
+                    svg.shapes.len()
+                ),
+                DisplayCommand::BackgroundImage {
+                    image,
+                    rect,
+                    tile,
+                    repeat_x,
+                    repeat_y,
+                } => format!(
+                    "background {} ({}, {}) {}x{} tile ({}, {}) {}x{} {} {}",
+                    match image {
+                        ImageSource::Url(url) => url,
+                        ImageSource::LinearGradient { .. } => "linear",
+                        ImageSource::RadialGradient { .. } => "radial",
+                    },
+                    rect.x,
+                    rect.y,
+                    rect.width,
+                    rect.height,
+                    tile.x,
+                    tile.y,
+                    tile.width,
+                    tile.height,
+                    repeat_x,
+                    repeat_y
+                ),
+                DisplayCommand::RoundedRect { rect, .. } => {
+                    format!("rounded rect {}", describe_rounded(rect))
+                }
+                DisplayCommand::RoundedBorder { rect, .. } => {
+                    format!("rounded border {}", describe_rounded(rect))
+                }
+                DisplayCommand::PushClip(rect) => {
+                    format!("clip {}", describe_rounded(rect))
+                }
+                DisplayCommand::PopClip => "pop clip".to_string(),
+                DisplayCommand::PushLayer { opacity } => {
+                    format!("layer {}", opacity)
+                }
+                DisplayCommand::PopLayer => "pop layer".to_string(),
+                DisplayCommand::PushTransform(transform) => {
+                    let Transform { a, b, c, d, e, f } = transform;
+                    format!("transform [{} {} {} {} {} {}]", a, b, c, d, e, f)
+                }
+                DisplayCommand::PopTransform => "pop transform".to_string(),
+                DisplayCommand::BoxShadow { shadow, blur, .. } => {
+                    format!("shadow {} blur {}", describe_rounded(shadow), blur)
+                }
+            })
+            .collect()
+    }
+
+    fn describe_rounded(rect: &RoundedRect) -> String {
+        let RoundedRect { rect, radii } = rect;
+        let corners = [
+            radii.top_left,
+            radii.top_right,
+            radii.bottom_right,
+            radii.bottom_left,
+        ]
+        .map(|(x, y)| format!("({}, {})", x, y));
+        format!(
+            "({}, {}) {}x{} {}",
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height,
+            corners.join(" ")
+        )
+    }
+
+    #[test]
+    fn test_display_list() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"box\">hi</p><p class=\"hidden\">no<b>yes</b></p>\
+             <img></img><p class=\"front\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; line-height: 12px; } p { margin: 0; } \
+             .box { background-color: #ff0000; border: 2px solid #0000ff; } \
+             .hidden { visibility: hidden; background-color: #00ff00; } \
+             b { visibility: visible; } \
+             img { display: block; width: 4px; height: 3px; } \
+             .front { position: relative; z-index: 1; top: -40px; \
+             height: 5px; background-color: #000000; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        let commands = describe(&layout_root);
+        // Backgrounds are painted under borders, the text of a hidden box
+        // is skipped unless a descendant is visible again, and positioned
+        // boxes are painted last.
+        assert_eq!(
+            commands,
+            [
+                "rect #ff0000 (0, 0) 100x16",
+                "rect #0000ff (0, 0) 100x2",
+                "rect #0000ff (98, 2) 2x12",
+                "rect #0000ff (0, 14) 100x2",
+                "rect #0000ff (0, 2) 2x12",
+                "text \"hi\" (2, 3) baseline 11",
+                "text \"yes\" (12, 17) baseline 25",
+                "image (0, 28) 4x3",
+                "rect #000000 (0, -9) 100x5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_border_styles() {
+        let root = HTMLParser::parse("<div></div>".to_string());
+        let stylesheets = [CSSParser::parse(
+            "div { width: 14px; height: 10px; border-width: 2px 3px; \
+             border-style: dashed double dotted none; \
+             border-color: #ff0000 #00ff00 #0000ff; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // Each side has its own color. Dashes and dots are spread along the
+        // side, a double border is two lines, and the left border has no
+        // style, and so no width either.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "rect #ff0000 (0, 0) 6x2",
+                "rect #ff0000 (11, 0) 6x2",
+                "rect #00ff00 (14, 2) 1x10",
+                "rect #00ff00 (16, 2) 1x10",
+                "rect #0000ff (0, 12) 2x2",
+                "rect #0000ff (3.75, 12) 2x2",
+                "rect #0000ff (7.5, 12) 2x2",
+                "rect #0000ff (11.25, 12) 2x2",
+                "rect #0000ff (15, 12) 2x2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outlines() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0 8px 8px; width: 8px; height: 4px; \
+             background-color: #ff0000; } \
+             .a { outline: 2px solid #0000ff; outline-offset: 1px; } \
+             .b { outline: 1px dotted #00ff00; border-radius: 2px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 24.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // Outlines don't take up space, and are painted after all boxes,
+        // over the ones that they overlap. A rounded outline is solid.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "rect #ff0000 (8, 0) 8x4",
+                "rounded rect (8, 12) 8x4 (2, 2) (2, 2) (2, 2) (2, 2)",
+                "rect #0000ff (5, -3) 14x2",
+                "rect #0000ff (17, -1) 2x6",
+                "rect #0000ff (5, 5) 14x2",
+                "rect #0000ff (5, -1) 2x6",
+                "rounded border (7, 11) 10x6 (3, 3) (3, 3) (3, 3) (3, 3)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_focus_ring() {
+        struct Focused(NodeId);
+
+        impl ElementStateProvider for Focused {
+            fn is_focused(&self, node: &Node) -> bool {
+                node.node_id == self.0
+            }
+        }
+
+        let document = Document::new(HTMLParser::parse(
+            "<div><a href=\"/\">a</a><button>b</button></div>".to_string(),
+        ));
+        let stylesheets = [CSSParser::parse(
+            "a, button { display: block; width: 8px; height: 4px; \
+             margin: 4px; } \
+             button:focus { outline: 3px dashed #0000ff; }"
+                .to_string(),
+        )];
+        let focusable = document.root.focusable_elements();
+        assert_eq!(focusable.len(), 2);
+        let describe_focused = |node: NodeId| {
+            let styled = restyle(
+                &document,
+                &stylesheets,
+                &MediaContext::default(),
+                &mut StyleCache::default(),
+                &[],
+                &Focused(node),
+            );
+            let mut viewport = Dimensions::default();
+            viewport.content.width = 24.0;
+            describe(&layout_tree(&styled, viewport))
+                .into_iter()
+                .filter(|command| !command.starts_with("text"))
+                .collect::<Vec<_>>()
+        };
+
+        // The focused element is drawn with a focus ring, which is rounded
+        // even around square corners.
+        assert_eq!(
+            describe_focused(focusable[0]),
+            ["rounded border (2, 2) 12x8 (2, 2) (2, 2) (2, 2) (2, 2)"]
+        );
+        // Unless the author gave it an outline of their own.
+        assert_eq!(
+            describe_focused(focusable[1]),
+            [
+                "rect #0000ff (1, 9) 14x3",
+                "rect #0000ff (12, 12) 3x4",
+                "rect #0000ff (1, 16) 14x3",
+                "rect #0000ff (1, 12) 3x4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rounded_corners() {
+        let root = HTMLParser::parse(
+            "<div class=\"outer\"><p>a</p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; line-height: 12px; } \
+             .outer { width: 16px; height: 12px; border: 2px solid #0000ff; \
+             border-radius: 30px 50% 0 0; overflow: hidden; \
+             background-color: #ff0000; } \
+             p { margin: 0; height: 20px; background-color: #00ff00; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // Radii that don't fit are scaled down, and the content of a scroll
+        // container is clipped to its padding box, whose corners follow the
+        // inside of the border.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "rounded rect (0, 0) 20x16 (15, 15) (5, 4) (0, 0) (0, 0)",
+                "rounded border (0, 0) 20x16 (15, 15) (5, 4) (0, 0) (0, 0)",
+                "clip (2, 2) 16x12 (13, 13) (3, 2) (0, 0) (0, 0)",
+                "rect #00ff00 (2, 2) 16x20",
+                "text \"a\" (2, 3) baseline 11",
+                "pop clip",
+            ]
+        );
+
+        let canvas = paint(
+            &layout_root,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 20.0,
+                height: 16.0,
+            },
+            &Images::new(),
+            1.0,
+        );
+        let pixel = |x: usize, y: usize| {
+            let color = canvas.pixels[y * 20 + x];
+            (color.r, color.g, color.b)
+        };
+        // The corners outside the curves are left unpainted.
+        assert_eq!(pixel(0, 0), (255, 255, 255));
+        assert_eq!(pixel(19, 0), (255, 255, 255));
+        assert_eq!(pixel(0, 15), (0, 0, 255));
+        // The border, and the content clipped inside of it.
+        assert_eq!(pixel(16, 1), (0, 0, 255));
+        assert_eq!(pixel(2, 2), (255, 255, 255));
+        assert_eq!(pixel(10, 8), (0, 255, 0));
+        assert_eq!(pixel(10, 14), (0, 0, 255));
+    }
+
+    #[test]
+    fn test_paint() {
+        let root = HTMLParser::parse("<div><p></p></div>".to_string());
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; width: 3px; height: 2px; \
+             background-color: #ff0000; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 4.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // The canvas starts at the second column of the page.
+        let mut canvas = paint(
+            &layout_root,
+            Rectangle {
+                x: 1.0,
+                y: 0.0,
+                width: 3.0,
+                height: 4.0,
+            },
+            &Images::new(),
+            1.0,
+        );
+        // Translucent colors are blended over what is painted.
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 128,
+        };
+        canvas.fill_rect(
+            blue,
+            Rectangle {
+                x: 0.0,
+                y: 1.0,
+                width: 1.0,
+                height: 2.0,
+            },
+        );
+        let hex: Vec<_> = canvas
+            .pixels
+            .iter()
+            .map(|c| format!("{:02x}{:02x}{:02x}{:02x}", c.r, c.g, c.b, c.a))
+            .collect();
+        assert_eq!(
+            hex.chunks(3).collect::<Vec<_>>(),
+            [
+                ["ff0000ff", "ff0000ff", "ffffffff"],
+                ["7f0080ff", "ff0000ff", "ffffffff"],
+                ["7f7fffff", "ffffffff", "ffffffff"],
+                ["ffffffff", "ffffffff", "ffffffff"],
+            ]
+        );
+        assert_eq!(&canvas.to_rgba()[..4], [255, 0, 0, 255]);
+
+        #[cfg(feature = "png")]
+        {
+            let mut png = Vec::new();
+            canvas.write_png(&mut png).unwrap();
+            assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+            let bitmap = Bitmap::decode(&png).unwrap();
+            assert_eq!((bitmap.width, bitmap.height), (3, 4));
+            assert_eq!(bitmap.pixels, canvas.pixels);
+            assert_eq!(Bitmap::decode_png(b"not a png"), None);
+        }
+    }
+
+    #[test]
+    fn test_decode_images() {
+        assert_eq!(Bitmap::decode(b"not an image"), None);
+
+        #[cfg(feature = "jpeg")]
+        {
+            // A red image on the left and a blue one on the right, which
+            // come out a little off after lossy compression.
+            let mut data = Vec::new();
+            for _ in 0..16 {
+                for x in 0..16 {
+                    data.extend(if x < 8 { [255, 0, 0] } else { [0, 0, 255] });
+                }
+            }
+            let mut jpeg = Vec::new();
+            jpeg_encoder::Encoder::new(&mut jpeg, 100)
+                .encode(&data, 16, 16, jpeg_encoder::ColorType::Rgb)
+                .unwrap();
+            let bitmap = Bitmap::decode(&jpeg).unwrap();
+            assert_eq!((bitmap.width, bitmap.height), (16, 16));
+            let close = |a: Color, b: Color| {
+                [(a.r, b.r), (a.g, b.g), (a.b, b.b), (a.a, b.a)]
+                    .iter()
+                    .all(|&(a, b)| a.abs_diff(b) <= 8)
+            };
+            let red = Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            };
+            let blue = Color {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255,
+            };
+            assert!(close(bitmap.pixels[16 * 8 + 2], red));
+            assert!(close(bitmap.pixels[16 * 8 + 13], blue));
+            assert_eq!(Bitmap::decode(&jpeg[..jpeg.len() / 2]), None);
+        }
+
+        #[cfg(feature = "gif")]
+        {
+            // The first frame covers the right half of the logical screen,
+            // and the second one is not decoded.
+            let mut gif = Vec::new();
+            {
+                let palette = [255, 0, 0, 0, 0, 255];
+                let mut encoder =
+                    gif::Encoder::new(&mut gif, 4, 2, &palette).unwrap();
+                for index in [0, 1] {
+                    let mut frame = gif::Frame {
+                        left: 2,
+                        width: 2,
+                        height: 2,
+                        ..gif::Frame::default()
+                    };
+                    frame.buffer = vec![index; 4].into();
+                    encoder.write_frame(&frame).unwrap();
+                }
+            }
+            let bitmap = Bitmap::decode(&gif).unwrap();
+            let red = Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            };
+            assert_eq!((bitmap.width, bitmap.height), (4, 2));
+            assert_eq!(
+                bitmap.pixels,
+                [TRANSPARENT, TRANSPARENT, red, red].repeat(2)
+            );
+            assert_eq!(Bitmap::decode(b"GIF89a"), None);
+        }
+    }
+
+    #[test]
+    fn test_background_image() {
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        // A 2x1 image, red on the left and blue on the right.
+        let mut images = Images::new();
+        images.insert(
+            "a.png",
+            Bitmap {
+                width: 2,
+                height: 1,
+                pixels: vec![red, blue],
+            },
+        );
+
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"></p><p class=\"c\"></p>\
+             <p class=\"d\"></p><p class=\"e\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; width: 8px; height: 4px; padding: 1px; \
+             background-image: url(\"a.png\"); } \
+             .a { background-repeat: no-repeat; \
+             background-position: right bottom; } \
+             .b { background-size: 4px; background-repeat: repeat-x; } \
+             .c { background-size: contain; background-repeat: repeat-y; } \
+             .d { background-size: cover; background-position: 50% 0; } \
+             .e { background-image: url(missing.png); }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 10.0;
+        let layout_root = layout_tree_with_metrics(
+            &styled,
+            viewport,
+            &MonospaceMetrics,
+            &images,
+        );
+
+        // Images are positioned and sized in the padding box, and images
+        // that haven't been decoded are left out.
+        assert_eq!(
+            describe_with_images(&layout_root, &images),
+            [
+                "background a.png (0, 0) 10x6 tile (8, 5) 2x1 false false",
+                "background a.png (0, 6) 10x6 tile (0, 6) 4x2 true false",
+                "background a.png (0, 12) 10x6 tile (0, 12) 10x5 false true",
+                "background a.png (0, 18) 10x6 tile (-1, 18) 12x6 true true",
+            ]
+        );
+
+        let canvas = paint(
+            &layout_root,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 10.0,
+                height: 24.0,
+            },
+            &images,
+            1.0,
+        );
+        let pixel = |x: usize, y: usize| {
+            let color = canvas.pixels[y * 10 + x];
+            (color.r, color.g, color.b)
+        };
+        let (red, blue, white) = ((255, 0, 0), (0, 0, 255), (255, 255, 255));
+        // Without repeating, only the tile in the bottom right corner.
+        assert_eq!(pixel(8, 5), red);
+        assert_eq!(pixel(9, 5), blue);
+        assert_eq!(pixel(7, 5), white);
+        assert_eq!(pixel(9, 4), white);
+        // Repeated along the row, but not below it.
+        assert_eq!(
+            (0..10).map(|x| pixel(x, 6)).collect::<Vec<_>>(),
+            [red, red, blue, blue, red, red, blue, blue, red, red]
+        );
+        assert_eq!(pixel(0, 8), white);
+        // Repeated down the column.
+        assert_eq!(pixel(4, 17), red);
+        assert_eq!(pixel(5, 17), blue);
+        // Covering the box, and centered horizontally.
+        assert_eq!(pixel(0, 18), red);
+        assert_eq!(pixel(4, 18), red);
+        assert_eq!(pixel(5, 18), blue);
+        assert_eq!(pixel(9, 23), blue);
+    }
+
+    #[test]
+    fn test_svg_images() {
+        let mut images = Images::new();
+        images.insert_svg(
+            "a.svg",
+            Svg::parse(
+                "<svg width=\"4\" height=\"4\" viewBox=\"0 0 2 2\">\
+                 <path d=\"M0 0 H2 V2 Z\" fill=\"lime\"/></svg>",
+            )
+            .unwrap(),
+        );
+        let root = HTMLParser::parse(
+            "<div><svg width=\"20\" viewBox=\"0 0 10 5\">\
+             <rect width=\"10\" height=\"5\" fill=\"#ff0000\"/>\
+             <circle cx=\"5\" cy=\"2.5\" r=\"2\" fill=\"#0000ff\"/></svg>\
+             <img src=\"a.svg\"></img></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } svg { display: block; } \
+             img { display: block; width: 20px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 20.0;
+        let layout_root = layout_tree_with_metrics(
+            &styled,
+            viewport,
+            &MonospaceMetrics,
+            &images,
+        );
+
+        // An inline image is sized by its attributes, and its shapes are
+        // not boxes. An image from a file is sized by its own.
+        assert_eq!(
+            describe_with_images(&layout_root, &images),
+            ["svg (0, 0) 20x10 with 2 shapes", "image (0, 10) 20x20"]
+        );
+        let rect = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 20.0,
+            height: 30.0,
+        };
+        let canvas = paint(&layout_root, rect, &images, 1.0);
+        let pixel = |x: usize, y: usize| {
+            let color = canvas.pixels[y * 20 + x];
+            (color.r, color.g, color.b)
+        };
+        // The view boxes are scaled to fit.
+        assert_eq!(pixel(1, 1), (255, 0, 0));
+        assert_eq!(pixel(10, 5), (0, 0, 255));
+        assert_eq!(pixel(18, 12), (0, 255, 0));
+        assert_eq!(pixel(2, 28), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_gradients() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"></p><p class=\"c\"></p>\
+             </div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; width: 20px; height: 4px; } \
+             .a { background-image: linear-gradient(90deg, #ff0000 20%, \
+             #00ff00 #0000ff 80%); } \
+             .b { background-image: linear-gradient(to bottom right, \
+             #000000 50%, #ffffff 50%); } \
+             .c { background-image: radial-gradient(closest-side, \
+             #ffffff, #000000); background-size: 4px 4px; \
+             background-repeat: repeat-x; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 20.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // Gradients fill the area unless they are sized.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "background linear (0, 0) 20x4 tile (0, 0) 20x4 true true",
+                "background linear (0, 4) 20x4 tile (0, 4) 20x4 true true",
+                "background radial (0, 8) 20x4 tile (0, 8) 4x4 true false",
+            ]
+        );
+        let display_list = build_display_list(&layout_root, &NoImages);
+        let DisplayCommand::BackgroundImage {
+            image: ImageSource::LinearGradient { start, end, stops },
+            ..
+        } = &display_list[0]
+        else {
+            panic!("{:?}", display_list[0]);
+        };
+        let round = |(x, y): (f32, f32)| (x.round(), y.round());
+        assert_eq!((round(*start), round(*end)), ((0.0, 2.0), (20.0, 2.0)));
+        // The stop without a position is halfway between its neighbors.
+        assert_eq!(
+            stops.iter().map(|stop| stop.offset).collect::<Vec<_>>(),
+            [0.2, 0.5, 0.8]
+        );
+
+        let canvas = paint(
+            &layout_root,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 20.0,
+                height: 12.0,
+            },
+            &Images::new(),
+            1.0,
+        );
+        let pixel = |x: usize, y: usize| {
+            let color = canvas.pixels[y * 20 + x];
+            (color.r, color.g, color.b)
+        };
+        // The colors before the first stop and after the last one are
+        // those of the stops, and the ones between them are interpolated.
+        assert_eq!(pixel(1, 0), (255, 0, 0));
+        assert_eq!(pixel(7, 0), (106, 149, 0));
+        assert_eq!(pixel(12, 0), (0, 149, 106));
+        assert_eq!(pixel(19, 0), (0, 0, 255));
+        // The gradient line toward a corner is perpendicular to the other
+        // diagonal, so stops at the same offset make a sharp diagonal.
+        assert_eq!(pixel(0, 7), (0, 0, 0));
+        assert_eq!(pixel(9, 5), (0, 0, 0));
+        assert_eq!(pixel(12, 6), (255, 255, 255));
+        assert_eq!(pixel(19, 7), (255, 255, 255));
+        // A radial gradient fades from its center to the closest side, and
+        // is repeated.
+        assert_eq!(pixel(1, 9), (165, 165, 165));
+        assert_eq!(pixel(0, 10), (53, 53, 53));
+        assert_eq!(pixel(16, 10), (53, 53, 53));
+        assert_eq!(pixel(0, 8), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_interpolate_premultiplied() {
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let transparent = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 0,
+        };
+        // The color of a transparent stop doesn't show.
+        assert_eq!(
+            interpolate(red, transparent, 0.5),
+            Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 128,
+            }
+        );
+    }
+
+    #[test]
+    fn test_opacity_layers() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"><span></span></p><p class=\"b\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0; height: 2px; background-color: #ff0000; } \
+             span { display: block; height: 1px; \
+             background-color: #0000ff; } \
+             .a { opacity: 0.5; } \
+             .b { opacity: 0.5; position: relative; top: -1px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 2.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // Each translucent box has its own layer, even right after another
+        // one.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "layer 0.5",
+                "rect #ff0000 (0, 0) 2x2",
+                "rect #0000ff (0, 0) 2x1",
+                "pop layer",
+                "layer 0.5",
+                "rect #ff0000 (0, 1) 2x2",
+                "pop layer",
+            ]
+        );
+
+        let canvas = paint(
+            &layout_root,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 2.0,
+                height: 4.0,
+            },
+            &Images::new(),
+            1.0,
+        );
+        let column: Vec<_> = (0..4)
+            .map(|y| {
+                let color = canvas.pixels[y * 2];
+                (color.r, color.g, color.b)
+            })
+            .collect();
+        // The blue child covers the red parent before the layer is made
+        // translucent, so no red shows through it.
+        assert_eq!(
+            column,
+            [
+                (127, 127, 255),
+                (255, 63, 63),
+                (255, 127, 127),
+                (255, 255, 255)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_box_shadow() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { margin: 0 4px 8px; width: 8px; height: 8px; \
+             background-color: #ff0000; } \
+             .a { box-shadow: 2px 10px 0 1px #0000ff; } \
+             .b { box-shadow: 0 0 4px #000000; border-radius: 2px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 16.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // A shadow is painted right below the background of its box, and
+        // over the boxes before it.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "shadow (5, 9) 10x10 (0, 0) (0, 0) (0, 0) (0, 0) blur 0",
+                "rect #ff0000 (4, 0) 8x8",
+                "shadow (4, 16) 8x8 (2, 2) (2, 2) (2, 2) (2, 2) blur 4",
+                "rounded rect (4, 16) 8x8 (2, 2) (2, 2) (2, 2) (2, 2)",
+            ]
+        );
+
+        let canvas = paint(
+            &layout_root,
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 16.0,
+                height: 32.0,
+            },
+            &Images::new(),
+            1.0,
+        );
+        let pixel = |x: usize, y: usize| {
+            let color = canvas.pixels[y * 16 + x];
+            (color.r, color.g, color.b)
+        };
+        // The offset shadow is grown by its spread.
+        assert_eq!(pixel(4, 9), (255, 255, 255));
+        assert_eq!(pixel(5, 9), (0, 0, 255));
+        assert_eq!(pixel(14, 9), (0, 0, 255));
+        assert_eq!(pixel(15, 9), (255, 255, 255));
+        // The blurred shadow fades away from the box on every side.
+        let gray = |x: usize, y: usize| {
+            let (r, g, b) = pixel(x, y);
+            assert!(r == g && g == b, "{:?}", (r, g, b));
+            r
+        };
+        assert!(gray(3, 20) < gray(2, 20));
+        assert!(gray(2, 20) < gray(1, 20));
+        assert!(gray(0, 20) < 255);
+        assert_eq!(gray(3, 20), gray(12, 20));
+        assert_eq!(gray(3, 14), gray(3, 25));
+        assert_eq!(gray(0, 9), 255);
+        assert_eq!(pixel(8, 20), (255, 0, 0));
+        // It covers the shadow of the box before it.
+        let (r, g, b) = pixel(8, 15);
+        assert!(r == g && r < b && b < 255, "{:?}", (r, g, b));
+    }
+
+    #[test]
+    fn test_positioned_boxes_escape_clips() {
+        let root = HTMLParser::parse(
+            "<div class=\"s\"><p class=\"a\"></p><p class=\"f\"></p>\
+             <div class=\"r\"><p class=\"b\"></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } p { margin: 0; width: 2px; height: 8px; \
+             background-color: #ff0000; } \
+             .s { overflow: hidden; height: 4px; } \
+             .a { position: absolute; top: 0; left: 0; } \
+             .f { position: fixed; top: 0; left: 2px; } \
+             .r { position: relative; } \
+             .b { position: absolute; top: 0; left: 4px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 10.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // The containing blocks of the absolute and fixed boxes are outside
+        // of the scroll container, but that of the last one is inside.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "rect #ff0000 (0, 0) 2x8",
+                "rect #ff0000 (2, 0) 2x8",
+                "clip (0, 0) 10x4 (0, 0) (0, 0) (0, 0) (0, 0)",
+                "rect #ff0000 (4, 0) 2x8",
+                "pop clip",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_decorations() {
+        let root = HTMLParser::parse(
+            "<div><p>ab <b>cd</b> <span>ef</span></p><p><s>gh</s></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; line-height: 12px; } p { margin: 0; } \
+             p { text-decoration: underline line-through #ff0000; } \
+             b { text-decoration: overline; color: #0000ff; } \
+             span { display: inline-block; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // The decorations of a box are drawn across the text of its in-flow
+        // descendants in its color, from the font metrics of each text.
+        // Lines through the text are drawn over it, and the others under
+        // it. Atomic inlines are not decorated by their ancestors.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "rect #ff0000 (0, 10) 18x1",
+                "text \"ab \" (0, 1) baseline 9",
+                "rect #ff0000 (0, 6) 18x1",
+                "rect #ff0000 (18, 10) 12x1",
+                "rect #0000ff (18, 1) 12x1",
+                "text \"cd\" (18, 1) baseline 9",
+                "rect #ff0000 (18, 6) 12x1",
+                "text \"ef\" (30, 1) baseline 9",
+                "rect #ff0000 (0, 22) 12x1",
+                "text \"gh\" (0, 13) baseline 21",
+                "rect #ff0000 (0, 18) 12x1",
+                "rect #000000 (0, 18) 12x1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection() {
+        let root = HTMLParser::parse(
+            "<div><p>hello world</p><p>foo</p><p>a   b</p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 10px; line-height: 12px; } p { margin: 0; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 100.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        let [hello, foo, a] =
+            [0, 1, 2].map(|i| root.children[i].children[0].node_id);
+        let color = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        let point = |node, offset| BoundaryPoint { node, offset };
+        let rects = |start, end| {
+            let range = Range { start, end };
+            let selection =
+                Selection::new(&root, &range, color, &MonospaceMetrics);
+            selection
+                .rects(&layout_root)
+                .iter()
+                .map(|rect| (rect.x, rect.y, rect.width))
+                .collect::<Vec<_>>()
+        };
+        // A range over text nodes selects their bytes between its points.
+        assert_eq!(
+            rects(point(hello, 3), point(foo, 1)),
+            [(18.0, 1.0, 48.0), (0.0, 13.0, 6.0)]
+        );
+        // A point in an element is before its child at the offset.
+        assert_eq!(
+            rects(point(root.node_id, 1), point(root.children[2].node_id, 1)),
+            [(0.0, 13.0, 18.0), (0.0, 25.0, 18.0)]
+        );
+        // Offsets are in the text before its spaces are collapsed.
+        assert_eq!(rects(point(a, 4), point(a, 5)), [(12.0, 25.0, 6.0)]);
+        assert!(rects(point(foo, 1), point(hello, 3)).is_empty());
+
+        // Highlights are painted behind the text that they cover.
+        let range = Range {
+            start: point(foo, 0),
+            end: point(foo, 3),
+        };
+        let selection = Selection::new(&root, &range, color, &MonospaceMetrics);
+        let list = build_display_list_with_selection(
+            &layout_root,
+            &NoImages,
+            Some(&selection),
+        );
+        let highlight = DisplayCommand::SolidRect {
+            color,
+            rect: Rectangle {
+                x: 0.0,
+                y: 13.0,
+                width: 18.0,
+                height: 10.0,
+            },
+        };
+        let position = list.iter().position(|command| *command == highlight);
+        assert!(matches!(
+            list.get(position.unwrap() + 1),
+            Some(DisplayCommand::Text(run)) if run.text == "foo"
+        ));
+    }
+
+    #[test]
+    fn test_hit_test() {
+        let root = HTMLParser::parse(
+            "<div class=\"o\"><div class=\"s\"><p class=\"a\">ab</p>\
+             <p class=\"t\"></p><p class=\"z\"></p></div></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } .o { height: 50px; } \
+             p { margin: 0; height: 10px; font-size: 5px; } \
+             .s { position: relative; overflow: hidden; width: 40px; \
+             height: 20px; } \
+             .t { width: 10px; transform: translate(20px, 0); } \
+             .z { position: absolute; top: 5px; left: 30px; width: 10px; \
+             height: 30px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 50.0;
+        viewport.content.height = 50.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        let outer = root.node_id;
+        let s = root.children[0].node_id;
+        let [a, t, z] = [0, 1, 2].map(|i| root.children[0].children[i].node_id);
+        let text = root.children[0].children[0].children[0].node_id;
+        // Text is in front of its box, and the chain goes out to the root.
+        assert_eq!(hit_test(&layout_root, (2.0, 5.0)), [text, a, s, outer]);
+        assert_eq!(hit_test(&layout_root, (20.0, 5.0)), [a, s, outer]);
+        // Transformed boxes are hit where they are drawn.
+        assert_eq!(hit_test(&layout_root, (25.0, 15.0)), [t, s, outer]);
+        assert_eq!(hit_test(&layout_root, (5.0, 15.0)), [s, outer]);
+        // Positioned boxes are in front, but not outside of their clips.
+        assert_eq!(hit_test(&layout_root, (35.0, 8.0)), [z, s, outer]);
+        assert_eq!(hit_test(&layout_root, (35.0, 25.0)), [outer]);
+        assert!(hit_test(&layout_root, (60.0, 25.0)).is_empty());
+    }
+
+    #[test]
+    fn test_damage() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"><span></span></p>\
+             <p class=\"c\"></p></div>"
+                .to_string(),
+        );
+        let style = |b: &str| {
+            let stylesheets = [CSSParser::parse(format!(
+                "div {{ margin: 0; }} p {{ margin: 0; height: 60px; }} \
+                 span {{ display: block; width: 20px; height: 20px; \
+                 background-color: #0000ff; \
+                 box-shadow: 4px 4px 6px #000000; }} \
+                 .a {{ background-color: #ff0000; }} \
+                 .b {{ {b} }} .c {{ background-color: #00ff00; }}"
+            ))];
+            apply(&root, &stylesheets, &MediaContext::default())
+        };
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 200.0;
+        let bounds = Rectangle {
+            x: 0.0,
+            y: 10.0,
+            width: 200.0,
+            height: 170.0,
+        };
+        let before = style("background-color: #ffff00;");
+        let old =
+            build_display_list(&layout_tree(&before, viewport), &NoImages);
+        let mut canvas = Canvas::new(200, 170);
+        repaint(&mut canvas, bounds, &old, &Images::new(), 1.0, &[bounds]);
+        assert_eq!(
+            canvas,
+            paint(&layout_tree(&before, viewport), bounds, &Images::new(), 1.0)
+        );
+
+        // Only the box that changed is damaged, and painting the tiles that
+        // it touches again looks the same as painting everything.
+        let after = style("background-color: #00ffff;");
+        let new = build_display_list(&layout_tree(&after, viewport), &NoImages);
+        let rect = |rect: &Rectangle| (rect.x, rect.y, rect.width, rect.height);
+        let damaged = damage(&old, &new);
+        assert_eq!(
+            damaged.iter().map(rect).collect::<Vec<_>>(),
+            [(0.0, 60.0, 200.0, 60.0)]
+        );
+        let painted =
+            repaint(&mut canvas, bounds, &new, &Images::new(), 1.0, &damaged);
+        assert_eq!(painted.len(), 8);
+        assert_eq!(rect(&painted[7]), (192.0, 64.0, 8.0, 64.0));
+        assert_eq!(
+            canvas,
+            paint(&layout_tree(&after, viewport), bounds, &Images::new(), 1.0)
+        );
+
+        // A box that moves in the painting order damages the boxes that it
+        // moves past too.
+        let layered = style("background-color: #00ffff; opacity: 0.5;");
+        let old = new;
+        let new =
+            build_display_list(&layout_tree(&layered, viewport), &NoImages);
+        let damaged = damage(&old, &new);
+        assert_eq!(damaged.len(), 4);
+        repaint(&mut canvas, bounds, &new, &Images::new(), 1.0, &damaged);
+        assert_eq!(
+            canvas,
+            paint(
+                &layout_tree(&layered, viewport),
+                bounds,
+                &Images::new(),
+                1.0
+            )
+        );
+
+        // Nothing is damaged when nothing changed.
+        assert!(damage(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_dump_display_list() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\">hi</p><p class=\"b\"></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } p { margin: 0; height: 10px; } \
+             .a { background-color: #ff0000; overflow: hidden; \
+             border-radius: 2px; } \
+             .b { opacity: 0.5; transform: translate(1px, 2px); \
+             background-image: linear-gradient(#00ff00, #0000ff); }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 20.0;
+        let list =
+            build_display_list(&layout_tree(&styled, viewport), &NoImages);
+        assert_eq!(
+            dump_display_list(&list),
+            "rounded rect #ff0000 (0, 0) 20x10 radii 2/2 2/2 2/2 2/2\n\
+             clip (0, 0) 20x10 radii 2/2 2/2 2/2 2/2\n\
+             \x20 text \"hi\" (0, 1.6) 19.2x16 baseline 14.4 #000000 16px \
+             \"serif\" 400 Normal\n\
+             pop clip\n\
+             layer 0.5\n\
+             \x20 transform [1 0 0 1 1 2]\n\
+             \x20   background linear (10, 0) to (10, 10) \
+             [#00ff00 0, #0000ff 1] (0, 10) 20x10 tile (0, 10) 20x10 repeat\n\
+             \x20 pop transform\n\
+             pop layer\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_display_list() {
+        let root = HTMLParser::parse(
+            "<div><p>hi</p><svg width=\"4\" height=\"4\">\
+             <circle cx=\"2\" cy=\"2\" r=\"2\"/></svg></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { background-color: #ff0000; opacity: 0.5; }".to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 20.0;
+        let list =
+            build_display_list(&layout_tree(&styled, viewport), &NoImages);
+        let json = serde_json::to_string(&list).unwrap();
+        let parsed: DisplayList = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, list);
+    }
+
+    // Paint each HTML file in `testdata/reftests` and the reference file of
+    // the same name ending in `-ref`, each with the stylesheet of the same
+    // name, and check that they look the same. A test can allow a range of
+    // differences with a `fuzzy` meta element, such as
+    // `<meta name="fuzzy" content="maxDifference=0-1;totalPixels=0-100"/>`.
+    #[test]
+    fn test_reftests() {
+        use std::{fs, ops::RangeInclusive, path::Path};
+
+        // Get the allowed differences of a test, which are none by default.
+        fn fuzzy(
+            node: &Node,
+        ) -> Option<(RangeInclusive<u8>, RangeInclusive<usize>)> {
+            let NodeType::Element(element) = &node.node_type else {
+                return None;
+            };
+            if element.tag_name != "meta"
+                || element.attributes.get("name").map(String::as_str)
+                    != Some("fuzzy")
+            {
+                return node.children.iter().find_map(fuzzy);
+            }
+            let content = element.attributes.get("content")?;
+            let ranges = content
+                .split(';')
+                .map(|part| {
+                    let range =
+                        part.split_once('=').map_or(part, |(_, range)| range);
+                    let (start, end) =
+                        range.split_once('-').unwrap_or((range, range));
+                    (start.trim().parse().unwrap(), end.trim().parse().unwrap())
+                })
+                .collect::<Vec<(usize, usize)>>();
+            let [(max_start, max_end), (total_start, total_end)] = ranges[..]
+            else {
+                panic!("Invalid fuzzy content {:?}", content);
+            };
+            Some((max_start as u8..=max_end as u8, total_start..=total_end))
+        }
+
+        let dir =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/reftests");
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+            .filter(|path| {
+                !path
+                    .file_stem()
+                    .unwrap()
+                    .to_string_lossy()
+                    .ends_with("-ref")
+            })
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        let render = |path: &Path| {
+            let html = fs::read_to_string(path).unwrap();
+            let css = fs::read_to_string(path.with_extension("css"))
+                .unwrap_or_default();
+            let root = HTMLParser::parse(html);
+            let stylesheets = [CSSParser::parse(css)];
+            let styled = apply(&root, &stylesheets, &MediaContext::default());
+            let mut viewport = Dimensions::default();
+            viewport.content.width = 100.0;
+            viewport.content.height = 100.0;
+            let layout_root = layout_tree(&styled, viewport);
+            let canvas =
+                paint(&layout_root, viewport.content, &Images::new(), 1.0);
+            (canvas, fuzzy(&root))
+        };
+        let mut failures = Vec::new();
+        for path in paths {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            let reference = path.with_file_name(format!("{}-ref.html", stem));
+            let (canvas, fuzzy) = render(&path);
+            let (expected, _) = render(&reference);
+            let difference = canvas.difference(&expected);
+            let (max_difference, total_pixels) =
+                fuzzy.unwrap_or((0..=0, 0..=0));
+            if !max_difference.contains(&difference.max_difference)
+                || !total_pixels.contains(&difference.total_pixels)
+            {
+                failures.push(format!("{}: {:?}", stem, difference));
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "Pages differ from their references: {failures:?}"
+        );
+    }
+
+    #[test]
+    fn test_device_pixel_ratio() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } p { margin: 0; height: 3px; } \
+             .a { background-color: #ff0000; } \
+             .b { width: 2.5px; background-color: #0000ff; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 4.0;
+        let layout_root = layout_tree(&styled, viewport);
+        let bounds = Rectangle {
+            x: 0.0,
+            y: 1.0,
+            width: 4.0,
+            height: 4.0,
+        };
+        let rows = |canvas: &Canvas| {
+            canvas
+                .pixels
+                .chunks(canvas.width)
+                .map(|row| {
+                    row.iter()
+                        .map(|pixel| match (pixel.r, pixel.g, pixel.b) {
+                            (255, 0, 0) => 'r',
+                            (0, 0, 255) => 'b',
+                            _ => '.',
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The page is painted at twice the size, and the edges that fall
+        // between pixels of the page are sharp in device pixels.
+        let canvas = paint(&layout_root, bounds, &Images::new(), 1.0);
+        assert_eq!(rows(&canvas), ["rrrr", "rrrr", "bbb.", "bbb."]);
+        let canvas = paint(&layout_root, bounds, &Images::new(), 2.0);
+        assert_eq!((canvas.width, canvas.height), (8, 8));
+        assert_eq!(
+            rows(&canvas),
+            [
+                "rrrrrrrr", "rrrrrrrr", "rrrrrrrr", "rrrrrrrr", "bbbbb...",
+                "bbbbb...", "bbbbb...", "bbbbb...",
+            ]
+        );
+
+        // Repainting a canvas in device pixels looks like painting it.
+        let list = build_display_list(&layout_root, &NoImages);
+        let mut repainted = Canvas::new(8, 8);
+        let painted = repaint(
+            &mut repainted,
+            bounds,
+            &list,
+            &Images::new(),
+            2.0,
+            &[bounds],
+        );
+        assert_eq!(painted.len(), 1);
+        assert_eq!((painted[0].width, painted[0].height), (8.0, 8.0));
+        assert_eq!(repainted, canvas);
+
+        // Text is scaled before it is rasterized, unless it would be
+        // stretched or skewed.
+        let run = TextRun {
+            text: "a b".to_string(),
+            rect: Rectangle {
+                x: 1.0,
+                y: 2.0,
+                width: 30.0,
+                height: 20.0,
+            },
+            baseline: 16.0,
+            color: Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            font_size: 16.0,
+            font_family: "serif".to_string(),
+            font_weight: 400,
+            font_style: FontStyle::Normal,
+            word_spacing: 1.0,
+        };
+        let scaled = run.transformed(device_transform(bounds, 2.0)).unwrap();
+        assert_eq!(
+            (scaled.rect.x, scaled.rect.y, scaled.rect.width),
+            (2.0, 2.0, 60.0)
+        );
+        assert_eq!(
+            (scaled.baseline, scaled.font_size, scaled.word_spacing),
+            (30.0, 32.0, 2.0)
+        );
+        assert_eq!(run.transformed(Transform::scale(2.0, 1.0)), None);
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let root = HTMLParser::parse(
+            "<div><div class=\"s\"><p class=\"a\"></p><p></p><p></p></div>\
+             </div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 0; } p { margin: 0; height: 10px; } \
+             .s { overflow: auto; width: 40px; height: 20px; } \
+             .a { background-color: #ff0000; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 50.0;
+        let mut layout_root = layout_tree(&styled, viewport);
+        let s = root.children[0].node_id;
+        assert!(layout_root.scroll_by(s, 0.0, 5.0));
+
+        // The content is moved up by the offset inside the clip, and the
+        // scrollbar is painted over it, with a thumb as long as the part
+        // of the content that is in view.
+        assert_eq!(
+            describe(&layout_root),
+            [
+                "clip (0, 0) 40x20 (0, 0) (0, 0) (0, 0) (0, 0)",
+                "transform [1 0 0 1 -0 -5]",
+                "rect #ff0000 (0, 0) 40x10",
+                "pop transform",
+                "pop clip",
+                "rect #f0f0f0 (32, 0) 8x20",
+                "rect #a0a0a0 (32, 3.3333335) 8x13.333333",
+            ]
+        );
+
+        // Hit testing finds what is drawn at a point after scrolling.
+        let [a, b] = [0, 1].map(|i| root.children[0].children[i].node_id);
+        assert_eq!(hit_test(&layout_root, (5.0, 3.0))[0], a);
+        assert_eq!(hit_test(&layout_root, (5.0, 8.0))[0], b);
+    }
+
+    #[test]
+    fn test_paint_backend() {
+        // A backend that records the calls, with the geometry that they
+        // are made with.
+        #[derive(Default)]
+        struct Recorder {
+            calls: Vec<String>,
+        }
+        impl PaintBackend for Recorder {
+            fn fill_rect(&mut self, _: Color, rect: Rectangle) {
+                self.calls.push(format!(
+                    "rect ({}, {}) {}x{}",
+                    rect.x, rect.y, rect.width, rect.height
+                ));
+            }
+            fn fill_rounded_rect(&mut self, _: Color, _: &RoundedRect) {}
+            fn fill_border(
+                &mut self,
+                _: &Sides<Color>,
+                _: &RoundedRect,
+                _: EdgeSizes,
+            ) {
+            }
+            fn draw_shadow(
+                &mut self,
+                _: Color,
+                _: &RoundedRect,
+                _: &RoundedRect,
+                _: f32,
+            ) {
+            }
+            fn draw_text(&mut self, run: &TextRun) {
+                self.calls.push(format!("text {:?}", run.text));
+            }
+            fn fill_path(&mut self, _: Color, _: &Path, _: FillRule) {}
+            fn stroke_path(&mut self, _: Color, _: &Path, _: f32) {}
+            fn draw_image(
+                &mut self,
+                _: &Bitmap,
+                _: Rectangle,
+                _: Rectangle,
+                _: (bool, bool),
+            ) {
+            }
+            fn draw_gradient(
+                &mut self,
+                _: &ImageSource,
+                _: Rectangle,
+                _: Rectangle,
+                _: (bool, bool),
+            ) {
+            }
+            fn push_clip(&mut self, _: &RoundedRect) {
+                self.calls.push("clip".to_string());
+            }
+            fn push_transform(&mut self, transform: Transform) {
+                let Transform { a, b, c, d, e, f } = transform;
+                self.calls.push(format!(
+                    "transform [{} {} {} {} {} {}]",
+                    a, b, c, d, e, f
+                ));
+            }
+            fn push_layer(&mut self, opacity: f32) {
+                self.calls.push(format!("layer {}", opacity));
+            }
+            fn pop(&mut self) {
+                self.calls.push("pop".to_string());
+            }
+        }
+
+        let root = HTMLParser::parse(
+            "<div class=\"t\"><p>a</p><p class=\"o\"></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { width: 2px; } p { margin: 0; height: 1px; \
+             background-color: #ff0000; font-size: 1px; } \
+             .t { transform: scale(2); transform-origin: 0 0; } \
+             .o { opacity: 0.5; overflow: hidden; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 4.0;
+        let layout_root = layout_tree(&styled, viewport);
+
+        // The page is moved to the origin of the backend, and a transformed
+        // box is drawn with its descendants through its transform.
+        let mut recorder = Recorder::default();
+        let bounds = Rectangle {
+            x: 1.0,
+            y: 1.0,
+            width: 4.0,
+            height: 4.0,
+        };
+        paint_with_backend(
+            &layout_root,
+            bounds,
+            &Images::new(),
+            1.0,
+            &mut recorder,
+        );
+        assert_eq!(
+            recorder.calls,
+            [
+                "transform [1 0 0 1 -1 -1]",
+                "transform [2 0 0 2 0 0]",
+                "rect (0, 0) 2x1",
+                "text \"a\"",
+                "layer 0.5",
+                "rect (0, 1) 2x1",
+                "pop",
+                "pop",
+                "pop",
+            ]
+        );
+
+        // The canvas maps the pixels through the transforms, so each row of
+        // the boxes covers two rows of pixels, from the top one at -1.
+        let canvas = paint(&layout_root, bounds, &Images::new(), 1.0);
+        let pixels: String = canvas
+            .pixels
+            .iter()
+            .map(|pixel| match (pixel.g, pixel.b) {
+                (0, 0) => 'r',
+                (127, 127) => 'o',
+                _ => ' ',
+            })
+            .collect();
+        assert_eq!(pixels, "rrr ooo ooo     ");
+    }
+}
@@ -0,0 +1,808 @@
+// The page-load pipeline loads a document on another thread, discovers its
+// subresources, and loads them concurrently, reporting its progress on a
+// channel as it goes:
+//
+// 1. The document is loaded and parsed: `LoadEvent::DocumentParsed`.
+// 2. Its stylesheets and images are loaded at the same time, by a fixed number
+//    of threads that each take the next resource once they are done with one.
+//    Once the stylesheets are: `LoadEvent::StylesheetsReady`.
+// 3. The page is laid out without the images that are still loading, and can be
+//    painted: `LoadEvent::FirstPaintReady`. The background images of the
+//    elements that are displayed are known now, and start loading.
+// 4. Once the images are loaded and decoded, the page is laid out again, and
+//    the load completes.
+//
+// Resources are loaded with the loader of each scheme, so pages whose
+// stylesheets and images are `data:` URLs load without a network.
+//
+// A load is a future that completes with the page, which works with any
+// executor because it doesn't depend on a runtime, and can be waited for
+// without one too.
+
+use std::{
+    any::Any,
+    collections::HashSet,
+    future::Future,
+    io,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        Arc,
+        Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use crate::{
+    browser::Page,
+    computed::BackgroundImage,
+    css_parser::{CSSParser, StyleSheet, Value},
+    document::{Document, StylesheetSource},
+    dom::{Node, NodeType},
+    html_parser::HTMLParser,
+    image_cache::ImageCache,
+    loader::{Resource, ResourceLoader},
+    net,
+    painting::{DisplayList, build_display_list},
+    style::{Display, StyledNode},
+    url::{self, Url},
+};
+
+// The number of threads that load the subresources of a page, which is as
+// many connections as browsers open to a host.
+const LOAD_THREADS: usize = 6;
+
+// The progress of a page load.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadEvent {
+    // The document was loaded and parsed, and refers to a number of
+    // stylesheets and `<img>` images.
+    DocumentParsed {
+        url: Url,
+        stylesheets: usize,
+        images: usize,
+    },
+    // All stylesheets were loaded, except for a number that failed to.
+    StylesheetsReady {
+        failed: usize,
+    },
+    // The page was styled and laid out, and this is what it shows before
+    // its images are loaded.
+    FirstPaintReady(DisplayList),
+}
+
+// A page that is loading. Poll it as a future, or wait for it, to get the
+// page once it is loaded.
+pub struct PageLoad {
+    events: Receiver<LoadEvent>,
+    state: Arc<Mutex<LoadState>>,
+}
+
+#[derive(Default)]
+struct LoadState {
+    // The page, or the error that it failed to load with.
+    page: Option<io::Result<Page>>,
+    waker: Option<Waker>,
+}
+
+impl PageLoad {
+    // Start loading the document at a URL with a loader, for a viewport of
+    // a width and a height in CSS pixels.
+    pub fn start(
+        loader: Arc<dyn ResourceLoader + Send + Sync>,
+        url: Url,
+        viewport: (f32, f32),
+    ) -> Self {
+        let (sender, events) = mpsc::channel();
+        let state = Arc::new(Mutex::new(LoadState::default()));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            // A page that the parsers can't handle fails to load, instead of
+            // panicking on the thread that waits for it.
+            let page = panic::catch_unwind(AssertUnwindSafe(|| {
+                load(&*loader, &url, viewport, &sender)
+            }))
+            .unwrap_or_else(|payload| Err(panic_error(payload)));
+            let mut state = shared.lock().unwrap();
+            state.page = Some(page);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            // The channel closes after the page is stored, so that waiting
+            // for the last event is waiting for the page.
+            drop(sender);
+        });
+        Self { events, state }
+    }
+
+    // The events of the load, which end when it completes.
+    #[inline]
+    pub fn events(&self) -> &Receiver<LoadEvent> {
+        &self.events
+    }
+
+    // Block the thread until the page is loaded.
+    pub fn wait(self) -> io::Result<Page> {
+        while self.events.recv().is_ok() {}
+        let page = self.state.lock().unwrap().page.take();
+        page.expect("The page is stored before the events end")
+    }
+}
+
+impl Future for PageLoad {
+    type Output = io::Result<Page>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.page.take() {
+            Some(page) => Poll::Ready(page),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Turn the panic of a load into an error, with its message if it has one.
+fn panic_error(payload: Box<dyn Any + Send>) -> io::Error {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "The page failed to load".to_string(),
+        },
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Load a page and its subresources, sending the events of its progress.
+fn load(
+    loader: &(dyn ResourceLoader + Send + Sync),
+    url: &Url,
+    viewport: (f32, f32),
+    events: &Sender<LoadEvent>,
+) -> io::Result<Page> {
+    let resource = loader.load(url)?;
+    let mut document = HTMLParser::parse_document(net::decode_html(
+        &resource.data,
+        resource.content_type.as_deref(),
+    ));
+    document.url = Some(resource.url.to_string());
+    let sources = document.stylesheet_sources();
+    let images = image_urls(&document);
+    // Nobody may be listening to the events.
+    let _ = events.send(LoadEvent::DocumentParsed {
+        url: resource.url.clone(),
+        stylesheets: sources.len(),
+        images: images.len(),
+    });
+
+    let mut page = Page {
+        url: resource.url,
+        status: resource.status,
+        document,
+        stylesheets: Vec::new(),
+        images: ImageCache::new(),
+        display_list: Vec::new(),
+    };
+    let mut seen: HashSet<String> =
+        images.iter().map(|(src, _)| src.clone()).collect();
+    let subresources = SubresourceLoader {
+        loader,
+        document: page.url.clone(),
+    };
+    let (jobs, queue) = mpsc::channel();
+    let queue = Mutex::new(queue);
+    let (done, loaded) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..LOAD_THREADS {
+            let (queue, done) = (&queue, done.clone());
+            let loader = &subresources;
+            scope.spawn(move || {
+                loop {
+                    // The queue is only locked while waiting for a job.
+                    let job = queue.lock().unwrap().recv();
+                    let Ok(job) = job else {
+                        break;
+                    };
+                    // A panic is resumed by the thread that receives the
+                    // results, which would otherwise wait for this one, and
+                    // fails the load.
+                    let loaded = panic::catch_unwind(AssertUnwindSafe(|| {
+                        run_job(loader, job)
+                    }));
+                    if done.send(loaded).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // The results end once the threads do.
+        drop(done);
+        let mut loaded = loaded.into_iter().map(|loaded| {
+            loaded.unwrap_or_else(|payload| panic::resume_unwind(payload))
+        });
+
+        let stylesheet_count = sources.len();
+        for (index, source) in sources.into_iter().enumerate() {
+            jobs.send(Job::Stylesheet(index, source)).unwrap();
+        }
+        for (src, url) in images {
+            jobs.send(Job::Image(src, url)).unwrap();
+        }
+        let mut stylesheets = Vec::new();
+        let mut images = Vec::new();
+        while stylesheets.len() < stylesheet_count {
+            match loaded.next().unwrap() {
+                Loaded::Stylesheet(index, stylesheet) => {
+                    stylesheets.push((index, stylesheet));
+                }
+                Loaded::Image(src, image) => images.push((src, image)),
+            }
+        }
+        stylesheets.sort_by_key(|(index, _)| *index);
+        let failed = stylesheets
+            .iter()
+            .filter(|(_, stylesheet)| stylesheet.is_none())
+            .count();
+        page.stylesheets = stylesheets
+            .into_iter()
+            .filter_map(|(_, stylesheet)| stylesheet)
+            .collect();
+        let _ = events.send(LoadEvent::StylesheetsReady { failed });
+
+        let base = page.document.base_url().and_then(|base| Url::parse(&base));
+        let (display_list, backgrounds) =
+            page.with_layout(viewport, |layout_root| {
+                let mut urls = Vec::new();
+                if let Some(styled) = layout_root.style_node() {
+                    collect_background_urls(
+                        base.as_ref(),
+                        styled,
+                        &mut seen,
+                        &mut urls,
+                    );
+                }
+                (build_display_list(layout_root, &page.images), urls)
+            });
+        page.display_list = display_list;
+        let _ =
+            events.send(LoadEvent::FirstPaintReady(page.display_list.clone()));
+        for (src, url) in backgrounds {
+            jobs.send(Job::Image(src, url)).unwrap();
+        }
+        // The threads end once the queue is empty.
+        drop(jobs);
+
+        for loaded in loaded {
+            if let Loaded::Image(src, image) = loaded {
+                images.push((src, image));
+            }
+        }
+        for (src, image) in images {
+            if let Some(image) = image {
+                page.images.load(&src, image.data);
+            }
+        }
+    });
+    if !page.images.wait().is_empty() {
+        page.layout(viewport);
+    }
+    Ok(page)
+}
+
+// Loads the subresources of a document. Files may only be loaded by
+// documents that are files too, so that pages from elsewhere can't read
+// the file system.
+struct SubresourceLoader<'a> {
+    loader: &'a (dyn ResourceLoader + Send + Sync),
+    document: Url,
+}
+
+impl ResourceLoader for SubresourceLoader<'_> {
+    fn load(&self, url: &Url) -> io::Result<Resource> {
+        if url.scheme() == "file" && self.document.scheme() != "file" {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} may not load {}", self.document, url),
+            ));
+        }
+        self.loader.load(url)
+    }
+}
+
+// A subresource of a page to load: a stylesheet by its index in the
+// document, or an image by its URL as it is written.
+enum Job {
+    Stylesheet(usize, StylesheetSource),
+    Image(String, Url),
+}
+
+// A loaded subresource, or `None` if it failed to load.
+enum Loaded {
+    Stylesheet(usize, Option<StyleSheet>),
+    Image(String, Option<Resource>),
+}
+
+fn run_job(loader: &(dyn ResourceLoader + Send + Sync), job: Job) -> Loaded {
+    match job {
+        Job::Stylesheet(index, source) => {
+            Loaded::Stylesheet(index, load_stylesheet(loader, source))
+        }
+        Job::Image(src, url) => Loaded::Image(
+            src,
+            loader.load(&url).ok().filter(Resource::is_success),
+        ),
+    }
+}
+
+// Load and parse a stylesheet, unless it fails to load. The URLs of a
+// linked stylesheet are resolved against its own URL.
+fn load_stylesheet(
+    loader: &(dyn ResourceLoader + Send + Sync),
+    source: StylesheetSource,
+) -> Option<StyleSheet> {
+    match source {
+        StylesheetSource::Inline(css) => Some(CSSParser::parse(css)),
+        StylesheetSource::Link(url) => {
+            let resource = loader.load(&Url::parse(&url)?).ok()?;
+            let css = net::decode_css(
+                &resource.data,
+                resource.content_type.as_deref(),
+            );
+            resource.is_success().then(|| {
+                let mut stylesheet = CSSParser::parse(css);
+                resolve_urls(&mut stylesheet, &resource.url);
+                stylesheet
+            })
+        }
+    }
+}
+
+// Resolve the relative URLs of the values of a stylesheet against the URL
+// that it was loaded from, which they are relative to instead of the
+// document.
+// SEE: https://drafts.csswg.org/css-values/#relative-urls
+fn resolve_urls(stylesheet: &mut StyleSheet, base: &Url) {
+    for rule in &mut stylesheet.rules {
+        for declaration in &mut rule.declarations {
+            resolve_value_urls(&mut declaration.value, base);
+        }
+    }
+}
+
+fn resolve_value_urls(value: &mut Value, base: &Url) {
+    match value {
+        Value::Url(reference) if !url::is_absolute(reference) => {
+            if let Some(resolved) = base.join(reference) {
+                *reference = resolved.to_string();
+            }
+        }
+        Value::List(values) | Value::Function(_, values) => {
+            for value in values {
+                resolve_value_urls(value, base);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Get the images of the `<img>` elements of a document, each once, by
+// their `src` as it is written, which painting looks them up by, and the
+// URL that it resolves to.
+fn image_urls(document: &Document) -> Vec<(String, Url)> {
+    let mut urls = Vec::new();
+    collect_image_urls(
+        document,
+        &document.root,
+        &mut HashSet::new(),
+        &mut urls,
+    );
+    urls
+}
+
+fn collect_image_urls(
+    document: &Document,
+    node: &Node,
+    seen: &mut HashSet<String>,
+    urls: &mut Vec<(String, Url)>,
+) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        let src = elem
+            .attributes
+            .get("src")
+            .filter(|_| elem.tag_name == "img");
+        let url = document
+            .resolved_url(node.node_id, "src")
+            .and_then(|url| Url::parse(&url));
+        if let (Some(src), Some(url)) = (src, url) {
+            if seen.insert(src.clone()) {
+                urls.push((src.clone(), url));
+            }
+        }
+    }
+    for child in &node.children {
+        collect_image_urls(document, child, seen, urls);
+    }
+}
+
+// Get the background images of the elements that are displayed, by the
+// URLs of their styles, which painting looks them up by, and the URL that
+// they resolve to against the base URL of the document.
+fn collect_background_urls(
+    base: Option<&Url>,
+    styled: &StyledNode,
+    seen: &mut HashSet<String>,
+    urls: &mut Vec<(String, Url)>,
+) {
+    if styled.style.display == Display::None {
+        return;
+    }
+    if let Some(BackgroundImage::Url(src)) = &styled.style.background_image {
+        let url = match base {
+            Some(base) => base.join(src),
+            None => Url::parse(src),
+        };
+        if let Some(url) = url {
+            if seen.insert(src.clone()) {
+                urls.push((src.clone(), url));
+            }
+        }
+    }
+    for child in &styled.children {
+        collect_background_urls(base, child, seen, urls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        task::Wake,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        loader::{MemoryLoader, Resource},
+        painting::{DisplayCommand, ImageSource},
+    };
+
+    // Run a future to completion on this thread.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    // Loads resources from memory after a delay, counting how many are
+    // loading at the same time.
+    struct SlowLoader {
+        memory: MemoryLoader,
+        loading: AtomicUsize,
+        most_loading: AtomicUsize,
+    }
+
+    impl ResourceLoader for SlowLoader {
+        fn load(&self, url: &Url) -> io::Result<Resource> {
+            let loading = self.loading.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_loading.fetch_max(loading, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(100));
+            self.loading.fetch_sub(1, Ordering::SeqCst);
+            self.memory.load(url)
+        }
+    }
+
+    // Loads resources from memory, and panics for the ones that it doesn't
+    // have.
+    struct PanickingLoader(MemoryLoader);
+
+    impl ResourceLoader for PanickingLoader {
+        fn load(&self, url: &Url) -> io::Result<Resource> {
+            match self.0.load(url) {
+                Ok(resource) => Ok(resource),
+                Err(_) => panic!("The loader panicked"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_events() {
+        let mut memory = MemoryLoader::new();
+        memory.insert(
+            "app://site/index.html",
+            Some("text/html"),
+            b"<html><head>\
+              <link rel=\"stylesheet\" href=\"style.css\"></link>\
+              <link rel=\"stylesheet\" href=\"missing.css\"></link>\
+              </head><body>\
+              <img src=\"a.svg\"></img><img src=\"a.svg\"></img>\
+              <img src=\"missing.png\"></img>\
+              </body></html>",
+        );
+        memory.insert(
+            "app://site/style.css",
+            Some("text/css"),
+            b"img { display: block; }",
+        );
+        memory.insert(
+            "app://site/a.svg",
+            Some("image/svg+xml"),
+            b"<svg width=\"20\" height=\"10\"></svg>",
+        );
+        let url = Url::parse("app://site/index.html").unwrap();
+        let load =
+            PageLoad::start(Arc::new(memory), url.clone(), (100.0, 50.0));
+
+        let events: Vec<LoadEvent> = load.events().iter().collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            LoadEvent::DocumentParsed {
+                url,
+                stylesheets: 2,
+                images: 2,
+            }
+        );
+        assert_eq!(events[1], LoadEvent::StylesheetsReady { failed: 1 });
+        let LoadEvent::FirstPaintReady(ref first_paint) = events[2] else {
+            panic!("Expected the first paint, got {:?}", events[2]);
+        };
+        // Images that aren't decoded yet are laid out at the default size
+        // of replaced elements.
+        let image_heights = |display_list: &DisplayList| {
+            display_list
+                .iter()
+                .filter_map(|command| match command {
+                    DisplayCommand::Image { url, rect, .. } => {
+                        Some((url.clone(), rect.height))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let a = "a.svg".to_string();
+        let missing = "missing.png".to_string();
+        assert_eq!(
+            image_heights(first_paint),
+            [
+                (a.clone(), 150.0),
+                (a.clone(), 150.0),
+                (missing.clone(), 150.0)
+            ]
+        );
+
+        // The page is laid out again once its images are decoded.
+        let page = load.wait().unwrap();
+        assert_eq!(page.stylesheets.len(), 1);
+        assert!(page.images.images().get_svg("a.svg").is_some());
+        assert_eq!(
+            image_heights(&page.display_list),
+            [(a.clone(), 10.0), (a, 10.0), (missing, 150.0)]
+        );
+    }
+
+    #[test]
+    fn test_background_image_urls() {
+        // The URLs of linked stylesheets are relative to the stylesheets,
+        // and the others to the document.
+        let mut memory = MemoryLoader::new();
+        memory.insert(
+            "app://site/pages/index.html",
+            None,
+            b"<html><head>\
+              <link rel=\"stylesheet\" href=\"/css/site.css\"></link>\
+              <style>.b { background-image: url(b.svg); }</style>\
+              </head><body><div class=\"a\"></div><div class=\"b\"></div>\
+              <div style=\"background-image: url(c.svg)\"></div>\
+              </body></html>",
+        );
+        memory.insert(
+            "app://site/css/site.css",
+            None,
+            b"div { height: 10px; } \
+              .a { background-image: url(\"images/a.svg\"); }",
+        );
+        for path in ["css/images/a", "pages/b", "pages/c"] {
+            memory.insert(
+                &format!("app://site/{}.svg", path),
+                Some("image/svg+xml"),
+                b"<svg width=\"10\" height=\"10\"></svg>",
+            );
+        }
+        let url = Url::parse("app://site/pages/index.html").unwrap();
+        let page = PageLoad::start(Arc::new(memory), url, (100.0, 50.0))
+            .wait()
+            .unwrap();
+
+        let backgrounds: Vec<&str> = page
+            .display_list
+            .iter()
+            .filter_map(|command| match command {
+                DisplayCommand::BackgroundImage {
+                    image: ImageSource::Url(url),
+                    ..
+                } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            backgrounds,
+            ["app://site/css/images/a.svg", "b.svg", "c.svg"]
+        );
+        for url in backgrounds {
+            assert!(page.images.images().get_svg(url).is_some(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_load_panics_are_errors() {
+        // The panic of a load fails it, instead of leaving whatever waits
+        // for its page never woken.
+        let mut memory = MemoryLoader::new();
+        memory.insert(
+            "app://site/index.html",
+            None,
+            b"<html><head>\
+              <link rel=\"stylesheet\" href=\"missing.css\"></link>\
+              </head></html>",
+        );
+        let loader = Arc::new(PanickingLoader(memory));
+        let start = |url| {
+            let url = Url::parse(url).unwrap();
+            PageLoad::start(loader.clone(), url, (0.0, 0.0))
+        };
+        let errors = [
+            start("app://site/missing.html").wait(),
+            block_on(start("app://site/missing.html")),
+            // The panics of subresources fail the load too.
+            start("app://site/index.html").wait(),
+        ];
+        for error in errors {
+            let error = error.err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), "The loader panicked");
+        }
+    }
+
+    #[test]
+    fn test_malformed_stylesheets() {
+        // The invalid rules of a stylesheet are skipped, and the other
+        // stylesheets that load at the same time are unaffected.
+        let mut memory = MemoryLoader::new();
+        memory.insert(
+            "app://site/index.html",
+            None,
+            b"<html><head>\
+              <link rel=\"stylesheet\" href=\"a.css\"></link>\
+              <link rel=\"stylesheet\" href=\"b.css\"></link>\
+              <link rel=\"stylesheet\" href=\"c.css\"></link>\
+              </head><body><p>a</p></body></html>",
+        );
+        memory.insert("app://site/a.css", None, b"p { margin: 0; }");
+        memory.insert(
+            "app://site/b.css",
+            None,
+            b"@import url(\"d.css\");\
+              @font-face { font-family: a; src: url(a.woff); }\
+              a[href] { color: red; }\
+              li:nth-child(2n + 1) { color: red; }\
+              p { color: red !default; height: 10px; }",
+        );
+        memory.insert("app://site/c.css", None, b"p { padding: 0; }");
+        let url = Url::parse("app://site/index.html").unwrap();
+        let load = PageLoad::start(Arc::new(memory), url, (100.0, 50.0));
+        let events: Vec<LoadEvent> = load.events().iter().collect();
+        let page = load.wait().unwrap();
+
+        assert!(events.contains(&LoadEvent::StylesheetsReady { failed: 0 }));
+        assert_eq!(page.stylesheets.len(), 3);
+        let rules = &page.stylesheets[1].rules;
+        assert_eq!(rules.len(), 1);
+        let names: Vec<&str> = rules[0]
+            .declarations
+            .iter()
+            .map(|declaration| declaration.name.as_str())
+            .collect();
+        assert_eq!(names, ["height"]);
+    }
+
+    #[test]
+    fn test_file_subresources() {
+        // Only documents that are files load files.
+        let html = b"<html><head>\
+                     <link rel=\"stylesheet\" href=\"file:///a.css\"></link>\
+                     </head><body><img src=\"file:///b.svg\"></img>\
+                     </body></html>";
+        let mut memory = MemoryLoader::new();
+        memory.insert("app://site/index.html", None, html);
+        memory.insert("file:///index.html", None, html);
+        memory.insert("file:///a.css", None, b"p { margin: 0; }");
+        memory.insert("file:///b.svg", None, b"<svg></svg>");
+        let loader = Arc::new(memory);
+        let load = |url| {
+            let url = Url::parse(url).unwrap();
+            let load = PageLoad::start(loader.clone(), url, (100.0, 50.0));
+            let events: Vec<LoadEvent> = load.events().iter().collect();
+            (load.wait().unwrap(), events)
+        };
+
+        let (page, events) = load("app://site/index.html");
+        assert!(events.contains(&LoadEvent::StylesheetsReady { failed: 1 }));
+        assert!(page.stylesheets.is_empty());
+        assert!(page.images.images().get_svg("file:///b.svg").is_none());
+
+        let (page, events) = load("file:///index.html");
+        assert!(events.contains(&LoadEvent::StylesheetsReady { failed: 0 }));
+        assert_eq!(page.stylesheets.len(), 1);
+        assert!(page.images.images().get_svg("file:///b.svg").is_some());
+    }
+
+    #[test]
+    fn test_concurrent_loads() {
+        let mut memory = MemoryLoader::new();
+        memory.insert(
+            "app://site/index.html",
+            None,
+            b"<html><head>\
+              <link rel=\"stylesheet\" href=\"a.css\"></link>\
+              <link rel=\"stylesheet\" href=\"b.css\"></link>\
+              </head><body><img src=\"c.svg\"></img></body></html>",
+        );
+        memory.insert("app://site/a.css", None, b"p { margin: 0; }");
+        memory.insert("app://site/b.css", None, b"p { padding: 0; }");
+        memory.insert("app://site/c.svg", None, b"<svg></svg>");
+        let loader = Arc::new(SlowLoader {
+            memory,
+            loading: AtomicUsize::new(0),
+            most_loading: AtomicUsize::new(0),
+        });
+
+        let url = Url::parse("app://site/index.html").unwrap();
+        let page =
+            block_on(PageLoad::start(loader.clone(), url, (100.0, 50.0)))
+                .unwrap();
+        assert_eq!(page.stylesheets.len(), 2);
+        assert_eq!(loader.most_loading.load(Ordering::SeqCst), 3);
+
+        // No more resources than there are threads load at the same time.
+        let mut memory = MemoryLoader::new();
+        let images: String = (0..LOAD_THREADS * 2)
+            .map(|i| format!("<img src=\"{}.svg\"></img>", i))
+            .collect();
+        memory.insert("app://site/images.html", None, images.as_bytes());
+        let loader = Arc::new(SlowLoader {
+            memory,
+            loading: AtomicUsize::new(0),
+            most_loading: AtomicUsize::new(0),
+        });
+        let url = Url::parse("app://site/images.html").unwrap();
+        PageLoad::start(loader.clone(), url, (100.0, 50.0))
+            .wait()
+            .unwrap();
+        assert_eq!(loader.most_loading.load(Ordering::SeqCst), LOAD_THREADS);
+
+        let url = Url::parse("app://site/missing.html").unwrap();
+        let error = block_on(PageLoad::start(loader, url, (100.0, 50.0)))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{
+    css_parser::{Color, Declaration, Unit, Value},
+    dom::ElementData,
+};
+
+// Map the legacy presentational attributes of an element, such as
+// `<body bgcolor>`, to declarations.
+// The cascade places them below all author rules, so that any stylesheet
+// overrides them.
+// SEE: https://html.spec.whatwg.org/multipage/rendering.html#presentational-hints
+pub fn presentational_hints(elem: &ElementData) -> Vec<Declaration> {
+    let tag_name = elem.tag_name.to_ascii_lowercase();
+    let attribute = |name: &str| elem.attributes.get(name).map(|s| s.trim());
+    let mut hints = Vec::new();
+    let mut hint = |name: &str, value: Value| {
+        hints.push(Declaration {
+            name: name.to_string(),
+            value,
+            important: false,
+        })
+    };
+
+    if matches!(tag_name.as_str(), "body" | "table" | "tr" | "td" | "th") {
+        if let Some(color) = attribute("bgcolor").and_then(parse_legacy_color) {
+            hint("background-color", Value::ColorValue(color));
+        }
+    }
+    if tag_name == "body" {
+        if let Some(color) = attribute("text").and_then(parse_legacy_color) {
+            hint("color", Value::ColorValue(color));
+        }
+    }
+
+    if tag_name == "table" {
+        if let Some(spacing) =
+            attribute("cellspacing").and_then(parse_dimension)
+        {
+            hint("border-spacing", spacing);
+        }
+    }
+
+    if matches!(tag_name.as_str(), "table" | "td" | "th" | "img" | "col") {
+        if let Some(width) = attribute("width").and_then(parse_dimension) {
+            hint("width", width);
+        }
+    }
+    if matches!(tag_name.as_str(), "td" | "th" | "tr" | "img") {
+        if let Some(height) = attribute("height").and_then(parse_dimension) {
+            hint("height", height);
+        }
+    }
+
+    if let Some(align) = attribute("align") {
+        let align = align.to_ascii_lowercase();
+        match (tag_name.as_str(), align.as_str()) {
+            // Images float to the side.
+            ("img", "left" | "right") => {
+                hint("float", Value::Keyword(align));
+            }
+            // Tables are centered by their margins.
+            ("table", "center") => {
+                hint("margin-left", Value::Keyword("auto".to_string()));
+                hint("margin-right", Value::Keyword("auto".to_string()));
+            }
+            (
+                "div" | "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "td"
+                | "th" | "tr" | "caption",
+                "left" | "right" | "center" | "justify",
+            ) => {
+                hint("text-align", Value::Keyword(align));
+            }
+            _ => {}
+        }
+    }
+
+    // The `dir` attribute sets the direction of any element.
+    if let Some(dir) = attribute("dir") {
+        let dir = dir.to_ascii_lowercase();
+        if dir == "ltr" || dir == "rtl" {
+            hint("direction", Value::Keyword(dir));
+        }
+    }
+
+    hints
+}
+
+// Parse a dimension attribute, such as `100` or `50%`.
+fn parse_dimension(value: &str) -> Option<Value> {
+    let (number, unit) = match value.strip_suffix('%') {
+        Some(number) => (number, Unit::Percent),
+        None => (value.strip_suffix("px").unwrap_or(value), Unit::Px),
+    };
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+        .map(|n| Value::Length(n, unit))
+}
+
+// Parse a legacy color value, which is a hex color with or without `#`, or
+// one of the basic color names.
+// TODO: Support the full legacy color parsing algorithm.
+pub fn parse_legacy_color(value: &str) -> Option<Color> {
+    let color = |r, g, b| Some(Color { r, g, b, a: 255 });
+    match value.to_ascii_lowercase().as_str() {
+        "black" => return color(0, 0, 0),
+        "silver" => return color(192, 192, 192),
+        "gray" | "grey" => return color(128, 128, 128),
+        "white" => return color(255, 255, 255),
+        "maroon" => return color(128, 0, 0),
+        "red" => return color(255, 0, 0),
+        "purple" => return color(128, 0, 128),
+        "fuchsia" => return color(255, 0, 255),
+        "green" => return color(0, 128, 0),
+        "lime" => return color(0, 255, 0),
+        "olive" => return color(128, 128, 0),
+        "yellow" => return color(255, 255, 0),
+        "navy" => return color(0, 0, 128),
+        "blue" => return color(0, 0, 255),
+        "teal" => return color(0, 128, 128),
+        "aqua" => return color(0, 255, 255),
+        _ => {}
+    }
+
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let digit = |i: usize| channel(&hex[i..i + 1]).map(|d| d * 17);
+            color(digit(0)?, digit(1)?, digit(2)?)
+        }
+        6 => color(
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        computed::{LengthOrAuto, TextAlign},
+        css_parser::CSSParser,
+        dom::AttributeMap,
+        html_parser::HTMLParser,
+        style::{MediaContext, apply},
+    };
+
+    fn hints(
+        tag_name: &str,
+        attributes: &[(&str, &str)],
+    ) -> Vec<(String, Value)> {
+        let elem = ElementData {
+            tag_name: tag_name.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<AttributeMap>(),
+        };
+        presentational_hints(&elem)
+            .into_iter()
+            .map(|declaration| (declaration.name, declaration.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_presentational_hints() {
+        let hint = |name: &str, value| (name.to_string(), value);
+        let keyword = |keyword: &str| Value::Keyword(keyword.to_string());
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(
+            hints(
+                "td",
+                &[("bgcolor", "#f00"), ("width", " 50% "), ("align", "Center")]
+            ),
+            [
+                hint("background-color", Value::ColorValue(red)),
+                hint("width", Value::Length(50.0, Unit::Percent)),
+                hint("text-align", keyword("center")),
+            ]
+        );
+        assert_eq!(
+            hints("img", &[("width", "100"), ("align", "left")]),
+            [
+                hint("width", Value::Length(100.0, Unit::Px)),
+                hint("float", keyword("left")),
+            ]
+        );
+        assert_eq!(
+            hints("table", &[("align", "center")]),
+            [
+                hint("margin-left", keyword("auto")),
+                hint("margin-right", keyword("auto"))
+            ]
+        );
+        // Attributes that the element doesn't have hints for, and invalid
+        // values, are ignored.
+        assert_eq!(hints("div", &[("bgcolor", "red"), ("width", "10")]), []);
+        assert_eq!(
+            hints(
+                "td",
+                &[("bgcolor", "#ff00"), ("width", "-1"), ("align", "middle")]
+            ),
+            []
+        );
+    }
+
+    #[test]
+    fn test_author_styles_override_hints() {
+        let root = HTMLParser::parse(
+            "<body bgcolor=\"red\" text=\"blue\">\
+             <div align=\"center\"></div><p align=\"right\"></p>\
+             <img width=\"100\"></img></body>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "body { background-color: #00ff00; } p { text-align: left; } \
+             img { width: 10px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let color = |r, g, b| Color { r, g, b, a: 255 };
+        assert_eq!(styled.style.background_color, color(0, 255, 0));
+        assert_eq!(styled.style.color, color(0, 0, 255));
+        assert_eq!(styled.children[0].style.text_align, TextAlign::Center);
+        assert_eq!(styled.children[1].style.text_align, TextAlign::Left);
+        assert_eq!(styled.children[2].style.width, LengthOrAuto::Px(10.0));
+    }
+}
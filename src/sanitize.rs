@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+
+use crate::{
+    dom::{ElementData, Node, NodeType},
+    visitor::{self, NodeVisitor, VisitAction},
+};
+
+// Elements removed together with their content, whatever the policy says.
+const DANGEROUS_TAGS: [&str; 8] = [
+    "script", "style", "iframe", "object", "embed", "frame", "frameset",
+    "template",
+];
+
+// Attributes holding URLs, whose scheme must be allowed.
+const URL_ATTRIBUTES: [&str; 6] =
+    ["href", "src", "action", "formaction", "cite", "poster"];
+
+// An allowlist describing which content survives sanitization.
+#[derive(Debug, Clone)]
+pub struct SanitizePolicy {
+    // Elements that are kept. Other elements are unwrapped, keeping their
+    // children.
+    pub allowed_tags: HashSet<String>,
+    // Attributes that are kept on allowed elements.
+    pub allowed_attributes: HashSet<String>,
+    // Schemes allowed in URL attributes. Relative URLs are always allowed.
+    pub allowed_url_schemes: HashSet<String>,
+}
+
+impl Default for SanitizePolicy {
+    // A policy suitable for user-generated content such as comments.
+    fn default() -> Self {
+        let to_set =
+            |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+        Self {
+            allowed_tags: to_set(&[
+                "a",
+                "b",
+                "blockquote",
+                "br",
+                "code",
+                "div",
+                "em",
+                "h1",
+                "h2",
+                "h3",
+                "h4",
+                "h5",
+                "h6",
+                "hr",
+                "i",
+                "img",
+                "li",
+                "ol",
+                "p",
+                "pre",
+                "span",
+                "strong",
+                "table",
+                "tbody",
+                "td",
+                "th",
+                "thead",
+                "tr",
+                "u",
+                "ul",
+            ]),
+            allowed_attributes: to_set(&[
+                "alt", "class", "colspan", "height", "href", "id", "rowspan",
+                "src", "title", "width",
+            ]),
+            allowed_url_schemes: to_set(&["http", "https", "mailto"]),
+        }
+    }
+}
+
+// Strip everything the policy does not allow from the tree.
+// Comments are always removed, and so are event handler attributes (`on*`)
+// even if the policy lists them. The root can't be unwrapped, so a root
+// element that isn't allowed is kept, but its attributes are still
+// filtered. A root that would be removed, such as a `<script>`, is
+// replaced by an empty text node instead.
+pub fn sanitize(root: &mut Node, policy: &SanitizePolicy) {
+    let removed = match root.node_type {
+        NodeType::Element(ref elem) => is_dangerous(&elem.tag_name),
+        NodeType::Text(_) => false,
+        NodeType::Comment(_) => true,
+    };
+    if removed {
+        root.children.clear();
+        root.node_type = NodeType::Text(String::new());
+        return;
+    }
+    visitor::walk(root, &mut Sanitizer { policy });
+}
+
+// Check if an element is removed together with its content.
+fn is_dangerous(tag_name: &str) -> bool {
+    DANGEROUS_TAGS.contains(&tag_name.to_ascii_lowercase().as_str())
+}
+
+struct Sanitizer<'a> {
+    policy: &'a SanitizePolicy,
+}
+
+impl Sanitizer<'_> {
+    // Check if an attribute may be kept on an element.
+    fn is_allowed_attribute(&self, name: &str, value: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("on")
+            || !self.policy.allowed_attributes.contains(&name)
+        {
+            return false;
+        }
+        if URL_ATTRIBUTES.contains(&name.as_str()) {
+            return match url_scheme(value) {
+                Some(scheme) => {
+                    self.policy.allowed_url_schemes.contains(&scheme)
+                }
+                None => true,
+            };
+        }
+        true
+    }
+}
+
+impl NodeVisitor for Sanitizer<'_> {
+    fn visit_element(&mut self, elem: &mut ElementData) -> VisitAction {
+        if is_dangerous(&elem.tag_name) {
+            return VisitAction::Remove;
+        }
+        let tag_name = elem.tag_name.to_ascii_lowercase();
+        elem.attributes
+            .retain(|name, value| self.is_allowed_attribute(name, value));
+        if self.policy.allowed_tags.contains(&tag_name) {
+            VisitAction::Continue
+        } else {
+            VisitAction::Unwrap
+        }
+    }
+
+    fn visit_comment(&mut self, _comment: &mut String) -> VisitAction {
+        VisitAction::Remove
+    }
+}
+
+// Extract the lowercase scheme of a URL, or `None` for a relative URL.
+// Whitespace and control characters are ignored like browsers do, so
+// `java\tscript:` is still recognized as `javascript`.
+fn url_scheme(url: &str) -> Option<String> {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let end = url.find([':', '/', '?', '#'])?;
+    if !url[end..].starts_with(':') {
+        return None;
+    }
+    Some(url[..end].to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::HTMLParser;
+
+    // Sanitize a fragment, and serialize what is left of it, with the
+    // attributes of each element in order of their names.
+    fn sanitized(html: &str, policy: &SanitizePolicy) -> String {
+        fn serialize(node: &Node, output: &mut String) {
+            match node.node_type {
+                NodeType::Element(ref elem) => {
+                    let mut attributes: Vec<_> =
+                        elem.attributes.iter().collect();
+                    attributes.sort();
+                    output.push_str(&format!("<{}", elem.tag_name));
+                    for (name, value) in attributes {
+                        output.push_str(&format!(" {}=\"{}\"", name, value));
+                    }
+                    output.push('>');
+                    for child in &node.children {
+                        serialize(child, output);
+                    }
+                    output.push_str(&format!("</{}>", elem.tag_name));
+                }
+                NodeType::Text(ref text) => output.push_str(text),
+                NodeType::Comment(ref comment) => {
+                    output.push_str(&format!("<!--{}-->", comment))
+                }
+            }
+        }
+
+        let mut root = HTMLParser::parse(html.to_string());
+        sanitize(&mut root, policy);
+        let mut output = String::new();
+        serialize(&root, &mut output);
+        output
+    }
+
+    #[test]
+    fn test_dangerous_elements_are_removed() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(
+            sanitized(
+                "<div><p>a</p><script>alert(1)</script>\
+                 <style>p { color: red }</style>\
+                 <iframe src=\"https://example.com\"><p>b</p></iframe>\
+                 <SCRIPT>alert(2)</SCRIPT><p>c</p></div>",
+                &policy
+            ),
+            "<div><p>a</p><p>c</p></div>"
+        );
+        // Even if the policy allows them.
+        let mut policy = SanitizePolicy::default();
+        policy.allowed_tags.insert("script".to_string());
+        assert_eq!(
+            sanitized("<div><script>alert(1)</script></div>", &policy),
+            "<div></div>"
+        );
+    }
+
+    #[test]
+    fn test_disallowed_roots() {
+        let policy = SanitizePolicy::default();
+        // A root that would be removed is replaced, content and all.
+        assert_eq!(sanitized("<script>alert(1)</script>", &policy), "");
+        assert_eq!(sanitized("<iframe><p>a</p></iframe>", &policy), "");
+        assert_eq!(sanitized("<!--a-->", &policy), "");
+        // A root that would be unwrapped is kept, with its attributes
+        // filtered.
+        assert_eq!(
+            sanitized(
+                "<section onclick=\"alert(1)\" id=\"a\"><p>b</p></section>",
+                &policy
+            ),
+            "<section id=\"a\"><p>b</p></section>"
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut policy = SanitizePolicy::default();
+        // Event handlers are stripped even if the policy allows them.
+        policy.allowed_attributes.insert("onclick".to_string());
+        policy.allowed_attributes.insert("onerror".to_string());
+        assert_eq!(
+            sanitized(
+                "<div><p onclick=\"alert(1)\" ONERROR=\"alert(2)\" \
+                 class=\"a\" style=\"color: red\">b</p></div>",
+                &policy
+            ),
+            "<div><p class=\"a\">b</p></div>"
+        );
+    }
+
+    #[test]
+    fn test_url_attributes() {
+        let policy = SanitizePolicy::default();
+        let link = |href: &str| {
+            sanitized(&format!("<p><a href=\"{}\">a</a></p>", href), &policy)
+        };
+        for href in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "java\tscript:alert(1)",
+            " jav\u{1}ascript:alert(1)",
+            "data:text/html,a",
+        ] {
+            assert_eq!(link(href), "<p><a>a</a></p>", "{:?}", href);
+        }
+        for href in [
+            "https://example.com/",
+            "HTTP://example.com/",
+            "mailto:a@example.com",
+            "/a:b",
+            "?a:b",
+            "#a:b",
+            "page.html",
+        ] {
+            assert_eq!(
+                link(href),
+                format!("<p><a href=\"{}\">a</a></p>", href),
+                "{:?}",
+                href
+            );
+        }
+        // The scheme of `src` is checked too.
+        assert_eq!(
+            sanitized(
+                "<p><img src=\"javascript:alert(1)\" alt=\"a\"></img></p>",
+                &policy
+            ),
+            "<p><img alt=\"a\"></img></p>"
+        );
+    }
+
+    #[test]
+    fn test_disallowed_elements_are_unwrapped() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(
+            sanitized(
+                "<div><section><p>a<font color=\"red\">b</font></p>\
+                 </section><form><b>c</b></form></div>",
+                &policy
+            ),
+            "<div><p>ab</p><b>c</b></div>"
+        );
+    }
+
+    #[test]
+    fn test_comments_are_removed() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(
+            sanitized("<div>a<!-- b --><p>c<!--d--></p></div>", &policy),
+            "<div>a<p>c</p></div>"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+// Render an HTML document with a stylesheet to an image in one call, such
+// as a thumbnail of a page or of one element on it. The page is laid out in
+// a viewport of a given size in CSS pixels, and painted at one pixel per
+// CSS pixel.
+
+use std::{error::Error, fmt};
+
+use crate::{
+    css_parser::CSSParser,
+    html_parser::HTMLParser,
+    layout::{
+        BoxMap,
+        Dimensions,
+        LayoutBox,
+        NoImages,
+        Rectangle,
+        layout_tree_with_metrics,
+    },
+    painting::{Canvas, Images, device_size, paint},
+    style::{MediaContext, apply, query_selector},
+    text::{FontMetrics, MonospaceMetrics},
+};
+
+// The largest width and height of a rendered image in pixels, which bounds
+// the memory that rendering takes: the canvas of an image this large takes
+// 256 MiB, and its bytes as much again.
+pub const MAX_IMAGE_SIZE: usize = 8192;
+
+// Why a page or an element couldn't be rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderError {
+    // No element matches the selector, or the element generates no box.
+    NotFound,
+    // The image would be wider or taller than `MAX_IMAGE_SIZE`, with the
+    // width and the height that it would have.
+    TooLarge(usize, usize),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::NotFound => write!(f, "No element to render"),
+            RenderError::TooLarge(width, height) => write!(
+                f,
+                "An image of {}x{} pixels is larger than {}x{}",
+                width, height, MAX_IMAGE_SIZE, MAX_IMAGE_SIZE
+            ),
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+// An image as bytes of red, green, blue, and alpha, row by row from the
+// top left corner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl RgbaImage {
+    // Get the color of a pixel.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        assert!(x < self.width && y < self.height, "Pixel out of bounds");
+        let i = (y * self.width + x) * 4;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+}
+
+impl From<Canvas> for RgbaImage {
+    fn from(canvas: Canvas) -> Self {
+        Self {
+            width: canvas.width,
+            height: canvas.height,
+            data: canvas.to_rgba(),
+        }
+    }
+}
+
+// Render what a page shows in a viewport of a width and a height. Text is
+// only drawn with fonts; see `render_to_image_with_fonts`.
+pub fn render_to_image(
+    html: &str,
+    css: &str,
+    viewport: (f32, f32),
+) -> Result<RgbaImage, RenderError> {
+    render(
+        html,
+        css,
+        viewport,
+        None,
+        &MonospaceMetrics,
+        &|layout_root, bounds| paint(layout_root, bounds, &Images::new(), 1.0),
+    )
+}
+
+// Render the border box of the first element that a selector matches, on
+// the page laid out in a viewport. The element is rendered whole even if
+// it is outside of the viewport. Fails if no element matches, the element
+// generates no box, or its border box is larger than `MAX_IMAGE_SIZE`.
+pub fn render_element_to_image(
+    html: &str,
+    css: &str,
+    viewport: (f32, f32),
+    selector: &str,
+) -> Result<RgbaImage, RenderError> {
+    render(
+        html,
+        css,
+        viewport,
+        Some(selector),
+        &MonospaceMetrics,
+        &|layout_root, bounds| paint(layout_root, bounds, &Images::new(), 1.0),
+    )
+}
+
+// Like `render_to_image`, but lays out text with fonts and draws it.
+#[cfg(feature = "fonts")]
+pub fn render_to_image_with_fonts(
+    html: &str,
+    css: &str,
+    viewport: (f32, f32),
+    fonts: &crate::font::Fonts,
+) -> Result<RgbaImage, RenderError> {
+    render(html, css, viewport, None, fonts, &|layout_root, bounds| {
+        crate::painting::paint_with_fonts(
+            layout_root,
+            bounds,
+            &Images::new(),
+            1.0,
+            fonts,
+        )
+    })
+}
+
+// Like `render_element_to_image`, but lays out text with fonts and draws
+// it.
+#[cfg(feature = "fonts")]
+pub fn render_element_to_image_with_fonts(
+    html: &str,
+    css: &str,
+    viewport: (f32, f32),
+    selector: &str,
+    fonts: &crate::font::Fonts,
+) -> Result<RgbaImage, RenderError> {
+    render(
+        html,
+        css,
+        viewport,
+        Some(selector),
+        fonts,
+        &|layout_root, bounds| {
+            crate::painting::paint_with_fonts(
+                layout_root,
+                bounds,
+                &Images::new(),
+                1.0,
+                fonts,
+            )
+        },
+    )
+}
+
+// Lay out a page and paint the viewport, or the border box of the element
+// that a selector matches, unless the image would be too large.
+fn render(
+    html: &str,
+    css: &str,
+    (width, height): (f32, f32),
+    selector: Option<&str>,
+    metrics: &dyn FontMetrics,
+    paint: &dyn Fn(&LayoutBox, Rectangle) -> Canvas,
+) -> Result<RgbaImage, RenderError> {
+    let root = HTMLParser::parse(html.to_string());
+    let stylesheets = [CSSParser::parse(css.to_string())];
+    let media = MediaContext {
+        viewport_width: width,
+        viewport_height: height,
+        ..MediaContext::default()
+    };
+    let styled = apply(&root, &stylesheets, &media);
+    let mut viewport = Dimensions::default();
+    viewport.content.width = width;
+    viewport.content.height = height;
+    let layout_root =
+        layout_tree_with_metrics(&styled, viewport, metrics, &NoImages);
+
+    let bounds = match selector {
+        Some(selector) => {
+            let dimensions = query_selector(&root, selector).and_then(|node| {
+                Some(BoxMap::new(&layout_root).get(node.node_id)?.dimensions())
+            });
+            dimensions.ok_or(RenderError::NotFound)?.border_box()
+        }
+        None => viewport.content,
+    };
+    let (width, height) = device_size(bounds, 1.0);
+    if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+        return Err(RenderError::TooLarge(width, height));
+    }
+    Ok(paint(&layout_root, bounds).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_to_image() {
+        let html = "<div><p class=\"a\"></p><p id=\"b\"></p></div>";
+        let css = "div { background-color: #0000ff; } \
+                   p { margin: 4px; height: 8px; } \
+                   .a { background-color: #ff0000; } \
+                   #b { width: 6px; border: 1px solid #00ff00; \
+                   background-color: #ffffff; }";
+
+        let image = render_to_image(html, css, (20.0, 40.0)).unwrap();
+        assert_eq!((image.width, image.height), (20, 40));
+        assert_eq!(image.data.len(), 20 * 40 * 4);
+        assert_eq!(image.pixel(0, 0), [0, 0, 255, 255]);
+        assert_eq!(image.pixel(4, 4), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(19, 39), [255, 255, 255, 255]);
+
+        // An element is cropped to its border box.
+        let image =
+            render_element_to_image(html, css, (20.0, 40.0), "div > #b")
+                .unwrap();
+        assert_eq!((image.width, image.height), (8, 10));
+        assert_eq!(image.pixel(0, 0), [0, 255, 0, 255]);
+        assert_eq!(image.pixel(1, 1), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(7, 9), [0, 255, 0, 255]);
+
+        assert_eq!(
+            render_element_to_image(html, css, (20.0, 40.0), "span"),
+            Err(RenderError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_render_too_large() {
+        let html = "<div><p></p></div>";
+        let css = "p { width: 10px; height: 100000000px; }";
+
+        // An image as large as the maximum is rendered, but no larger.
+        let size = MAX_IMAGE_SIZE as f32;
+        let image = render_to_image(html, "", (1.0, size)).unwrap();
+        assert_eq!((image.width, image.height), (1, MAX_IMAGE_SIZE));
+        assert_eq!(
+            render_to_image(html, "", (size + 1.0, 1.0)),
+            Err(RenderError::TooLarge(MAX_IMAGE_SIZE + 1, 1))
+        );
+
+        assert_eq!(
+            render_element_to_image(html, css, (20.0, 40.0), "p"),
+            Err(RenderError::TooLarge(10, 100000000))
+        );
+        // The page is still rendered in the viewport.
+        let image = render_to_image(html, css, (20.0, 40.0)).unwrap();
+        assert_eq!((image.width, image.height), (20, 40));
+    }
+}
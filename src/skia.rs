@@ -0,0 +1,704 @@
+// A paint backend that draws display lists with tiny-skia, which fills
+// paths with analytic antialiasing and draws images through its shaders.
+// SEE: https://github.com/linebender/tiny-skia
+
+use tiny_skia::{
+    ColorU8,
+    FillRule,
+    FilterQuality,
+    LineCap,
+    LineJoin,
+    Mask,
+    Paint,
+    Path,
+    PathBuilder,
+    Pattern,
+    Pixmap,
+    PixmapPaint,
+    Rect,
+    SpreadMode,
+    Stroke,
+};
+
+use crate::{
+    computed::Sides,
+    css_parser::Color,
+    layout::{EdgeSizes, Rectangle, RoundedRect, Transform},
+    painting::{
+        Bitmap,
+        ImageSource,
+        PaintBackend,
+        TextRun,
+        blur_radius,
+        box_blur,
+    },
+    svg,
+};
+
+// A backend that draws on a pixmap, which starts out opaque white like a
+// canvas. Text is only drawn with fonts.
+#[derive(Debug, Clone)]
+pub struct SkiaBackend {
+    pub pixmap: Pixmap,
+    #[cfg(feature = "fonts")]
+    pub fonts: Option<crate::font::Fonts>,
+    transform: tiny_skia::Transform,
+    // The coverage of the pixels that the clips in effect leave visible,
+    // unless nothing is clipped.
+    clip: Option<Mask>,
+    // What the pops restore, from the outermost push.
+    saved: Vec<Saved>,
+}
+
+// The state before a push, which the matching pop restores.
+#[derive(Debug, Clone)]
+enum Saved {
+    Clip(Option<Mask>),
+    Transform(tiny_skia::Transform),
+    // The pixmap below a layer, and the opacity of the layer.
+    Layer(Pixmap, f32),
+}
+
+impl SkiaBackend {
+    // Create a backend with a pixmap of a size. Panics if it is empty.
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut pixmap = Pixmap::new(width, height)
+            .unwrap_or_else(|| panic!("Invalid size: {}x{}", width, height));
+        pixmap.fill(tiny_skia::Color::WHITE);
+        Self {
+            pixmap,
+            #[cfg(feature = "fonts")]
+            fonts: None,
+            transform: tiny_skia::Transform::identity(),
+            clip: None,
+            saved: Vec::new(),
+        }
+    }
+
+    // Get the pixels row by row, from the top left corner, without
+    // premultiplied alpha.
+    pub fn pixels(&self) -> Vec<Color> {
+        self.pixmap
+            .pixels()
+            .iter()
+            .map(|pixel| {
+                let color = pixel.demultiply();
+                Color {
+                    r: color.red(),
+                    g: color.green(),
+                    b: color.blue(),
+                    a: color.alpha(),
+                }
+            })
+            .collect()
+    }
+
+    // Get a mask that covers what the clips in effect leave visible.
+    fn clip_mask(&self) -> Mask {
+        self.clip.clone().unwrap_or_else(|| {
+            let mut mask =
+                Mask::new(self.pixmap.width(), self.pixmap.height()).unwrap();
+            mask.data_mut().fill(255);
+            mask
+        })
+    }
+
+    // Fill a path with a solid color.
+    fn fill(&mut self, color: Color, path: &Path, fill_rule: FillRule) {
+        self.pixmap.fill_path(
+            path,
+            &solid(color),
+            fill_rule,
+            self.transform,
+            self.clip.as_ref(),
+        );
+    }
+
+    // Fill `rect` with a pattern whose pixmap is scaled to `tile`, and
+    // repeated along the axes that `repeat` asks for.
+    fn fill_pattern(
+        &mut self,
+        pattern: &Pixmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        (repeat_x, repeat_y): (bool, bool),
+    ) {
+        let (mut x0, mut x1) = (rect.x, rect.x + rect.width);
+        let (mut y0, mut y1) = (rect.y, rect.y + rect.height);
+        if !repeat_x {
+            x0 = x0.max(tile.x);
+            x1 = x1.min(tile.x + tile.width);
+        }
+        if !repeat_y {
+            y0 = y0.max(tile.y);
+            y1 = y1.min(tile.y + tile.height);
+        }
+        let Some(area) = Rect::from_ltrb(x0, y0, x1, y1) else {
+            return;
+        };
+        let paint = Paint {
+            shader: Pattern::new(
+                pattern.as_ref(),
+                SpreadMode::Repeat,
+                FilterQuality::Nearest,
+                1.0,
+                tiny_skia::Transform::from_row(
+                    tile.width / pattern.width() as f32,
+                    0.0,
+                    0.0,
+                    tile.height / pattern.height() as f32,
+                    tile.x,
+                    tile.y,
+                ),
+            ),
+            ..Paint::default()
+        };
+        self.pixmap
+            .fill_rect(area, &paint, self.transform, self.clip.as_ref());
+    }
+}
+
+impl PaintBackend for SkiaBackend {
+    fn fill_rect(&mut self, color: Color, rect: Rectangle) {
+        if let Some(rect) = to_rect(rect) {
+            self.pixmap.fill_rect(
+                rect,
+                &solid(color),
+                self.transform,
+                self.clip.as_ref(),
+            );
+        }
+    }
+
+    fn fill_rounded_rect(&mut self, color: Color, rect: &RoundedRect) {
+        if let Some(path) = rounded_path(&[rect]) {
+            self.fill(color, &path, FillRule::Winding);
+        }
+    }
+
+    // Each side is filled within the border, up to the lines from the outer
+    // corners through the inner ones, which divide the sides like
+    // `nearest_side` of the CPU backend does.
+    fn fill_border(
+        &mut self,
+        colors: &Sides<Color>,
+        rect: &RoundedRect,
+        widths: EdgeSizes,
+    ) {
+        let inner = rect.shrunk_by(widths);
+        let Some(ring) = rounded_path(&[rect, &inner]) else {
+            return;
+        };
+        let sides = [colors.top, colors.right, colors.bottom, colors.left];
+        if sides.iter().all(|color| *color == colors.top) {
+            self.fill(colors.top, &ring, FillRule::EvenOdd);
+            return;
+        }
+        let mut mask = self.clip_mask();
+        mask.intersect_path(&ring, FillRule::EvenOdd, true, self.transform);
+
+        let Rectangle {
+            x,
+            y,
+            width,
+            height,
+        } = rect.rect;
+        let (right, bottom) = (x + width, y + height);
+        let outer = [(x, y), (right, y), (right, bottom), (x, bottom)];
+        let inner = [
+            (x + widths.left, y + widths.top),
+            (right - widths.right, y + widths.top),
+            (right - widths.right, bottom - widths.bottom),
+            (x + widths.left, bottom - widths.bottom),
+        ];
+        let side_widths =
+            [widths.top, widths.right, widths.bottom, widths.left];
+        for (i, color) in sides.into_iter().enumerate() {
+            if side_widths[i] <= 0.0 || color.a == 0 {
+                continue;
+            }
+            let j = (i + 1) % 4;
+            let points = side_area(
+                (outer[i], inner[i]),
+                (outer[j], inner[j]),
+                width + height,
+            );
+            let mut builder = PathBuilder::new();
+            builder.move_to(points[0].0, points[0].1);
+            for point in &points[1..] {
+                builder.line_to(point.0, point.1);
+            }
+            builder.close();
+            if let Some(path) = builder.finish() {
+                self.pixmap.fill_path(
+                    &path,
+                    &solid(color),
+                    FillRule::Winding,
+                    self.transform,
+                    Some(&mask),
+                );
+            }
+        }
+    }
+
+    // A blurred shadow is blurred in the pixels of the pixmap, with the
+    // same box blurs as the CPU backend.
+    fn draw_shadow(
+        &mut self,
+        color: Color,
+        rect: &RoundedRect,
+        shadow: &RoundedRect,
+        blur: f32,
+    ) {
+        // The mask of what is outside of the rectangle that casts the
+        // shadow, which is cut out of a rectangle around both of them.
+        let around = RoundedRect::new(
+            Rectangle {
+                x: rect.rect.x.min(shadow.rect.x) - 3.0 * blur - 1.0,
+                y: rect.rect.y.min(shadow.rect.y) - 3.0 * blur - 1.0,
+                width: rect.rect.width + shadow.rect.width + 6.0 * blur + 2.0,
+                height: rect.rect.height
+                    + shadow.rect.height
+                    + 6.0 * blur
+                    + 2.0,
+            },
+            Default::default(),
+        );
+        let (Some(outside), Some(shape)) =
+            (rounded_path(&[&around, rect]), rounded_path(&[shadow]))
+        else {
+            return;
+        };
+        let mut mask = self.clip_mask();
+        mask.intersect_path(&outside, FillRule::EvenOdd, true, self.transform);
+        if blur <= 0.0 {
+            self.pixmap.fill_path(
+                &shape,
+                &solid(color),
+                FillRule::Winding,
+                self.transform,
+                Some(&mask),
+            );
+            return;
+        }
+
+        // The shadow is rasterized with a margin, so that the parts of it
+        // off the pixmap blur into it.
+        let radius = blur_radius(blur);
+        let extent = 3 * radius as u32;
+        let (width, height) = (
+            self.pixmap.width() + 2 * extent,
+            self.pixmap.height() + 2 * extent,
+        );
+        let mut coverage = Mask::new(width, height).unwrap();
+        coverage.fill_path(
+            &shape,
+            FillRule::Winding,
+            true,
+            self.transform.post_translate(extent as f32, extent as f32),
+        );
+        let mut values: Vec<f32> = coverage
+            .data()
+            .iter()
+            .map(|value| *value as f32 / 255.0)
+            .collect();
+        for _ in 0..3 {
+            box_blur(&mut values, width as usize, height as usize, radius);
+        }
+        let mut blurred = Pixmap::new(width, height).unwrap();
+        for (pixel, value) in blurred.pixels_mut().iter_mut().zip(values) {
+            let alpha = (color.a as f32 * value).round() as u8;
+            *pixel = ColorU8::from_rgba(color.r, color.g, color.b, alpha)
+                .premultiply();
+        }
+        self.pixmap.draw_pixmap(
+            -(extent as i32),
+            -(extent as i32),
+            blurred.as_ref(),
+            &PixmapPaint::default(),
+            tiny_skia::Transform::identity(),
+            Some(&mask),
+        );
+    }
+
+    // The glyphs are rasterized like on a canvas, and drawn as a pixmap
+    // through the transform in effect, unless it only moves and evenly
+    // scales them, in which case they are rasterized at that size.
+    #[cfg_attr(not(feature = "fonts"), allow(unused_variables))]
+    fn draw_text(&mut self, run: &TextRun) {
+        #[cfg(feature = "fonts")]
+        if let Some(fonts) = &self.fonts {
+            let tiny_skia::Transform {
+                sx,
+                ky,
+                kx,
+                sy,
+                tx,
+                ty,
+            } = self.transform;
+            let scaled =
+                run.transformed(Transform::new([sx, ky, kx, sy, tx, ty]));
+            let (run, transform) = match &scaled {
+                Some(scaled) => (scaled, tiny_skia::Transform::identity()),
+                None => (run, self.transform),
+            };
+            let mut glyphs = Vec::new();
+            fonts.draw_glyphs(run, |x, y, coverage| {
+                glyphs.push((x, y, coverage));
+            });
+            let (Some(x0), Some(y0)) = (
+                glyphs.iter().map(|glyph| glyph.0).min(),
+                glyphs.iter().map(|glyph| glyph.1).min(),
+            ) else {
+                return;
+            };
+            let width = glyphs.iter().map(|glyph| glyph.0 - x0 + 1).max();
+            let height = glyphs.iter().map(|glyph| glyph.1 - y0 + 1).max();
+            let (Some(width), Some(height)) = (width, height) else {
+                return;
+            };
+            let mut mask = vec![0.0f32; width as usize * height as usize];
+            for (x, y, coverage) in glyphs {
+                let cell = &mut mask
+                    [(y - y0) as usize * width as usize + (x - x0) as usize];
+                *cell = (*cell + coverage).min(1.0);
+            }
+            let mut pixmap = Pixmap::new(width as u32, height as u32).unwrap();
+            let color = run.color;
+            for (pixel, value) in pixmap.pixels_mut().iter_mut().zip(mask) {
+                let alpha = (color.a as f32 * value).round() as u8;
+                *pixel = ColorU8::from_rgba(color.r, color.g, color.b, alpha)
+                    .premultiply();
+            }
+            let quality = if transform.is_translate() {
+                FilterQuality::Nearest
+            } else {
+                FilterQuality::Bilinear
+            };
+            self.pixmap.draw_pixmap(
+                x0,
+                y0,
+                pixmap.as_ref(),
+                &PixmapPaint {
+                    quality,
+                    ..PixmapPaint::default()
+                },
+                transform,
+                self.clip.as_ref(),
+            );
+        }
+    }
+
+    fn fill_path(
+        &mut self,
+        color: Color,
+        path: &svg::Path,
+        fill_rule: svg::FillRule,
+    ) {
+        let fill_rule = match fill_rule {
+            svg::FillRule::NonZero => FillRule::Winding,
+            svg::FillRule::EvenOdd => FillRule::EvenOdd,
+        };
+        if let Some(path) = to_path(path) {
+            self.fill(color, &path, fill_rule);
+        }
+    }
+
+    fn stroke_path(&mut self, color: Color, path: &svg::Path, width: f32) {
+        let Some(path) = to_path(path) else {
+            return;
+        };
+        let stroke = Stroke {
+            width,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Round,
+            ..Stroke::default()
+        };
+        self.pixmap.stroke_path(
+            &path,
+            &solid(color),
+            &stroke,
+            self.transform,
+            self.clip.as_ref(),
+        );
+    }
+
+    fn draw_image(
+        &mut self,
+        bitmap: &Bitmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        let Some(mut pixmap) =
+            Pixmap::new(bitmap.width as u32, bitmap.height as u32)
+        else {
+            return;
+        };
+        for (pixel, color) in pixmap.pixels_mut().iter_mut().zip(&bitmap.pixels)
+        {
+            *pixel = ColorU8::from_rgba(color.r, color.g, color.b, color.a)
+                .premultiply();
+        }
+        self.fill_pattern(&pixmap, rect, tile, repeat);
+    }
+
+    // Gradients are colored like on a canvas, since tiny-skia interpolates
+    // colors without premultiplying them, and drawn as a pattern.
+    fn draw_gradient(
+        &mut self,
+        gradient: &ImageSource,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        let (width, height) = (tile.width.ceil(), tile.height.ceil());
+        let Some(mut pixmap) = Pixmap::new(width as u32, height as u32) else {
+            return;
+        };
+        let pixels = pixmap.pixels_mut();
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let (x, y) = (i % width as usize, i / width as usize);
+            let point = (
+                (x as f32 + 0.5) * tile.width / width,
+                (y as f32 + 0.5) * tile.height / height,
+            );
+            if let Some(color) = gradient.gradient_color(point) {
+                *pixel = ColorU8::from_rgba(color.r, color.g, color.b, color.a)
+                    .premultiply();
+            }
+        }
+        self.fill_pattern(&pixmap, rect, tile, repeat);
+    }
+
+    fn push_clip(&mut self, clip: &RoundedRect) {
+        let mut mask = self.clip_mask();
+        match rounded_path(&[clip]) {
+            Some(path) => mask.intersect_path(
+                &path,
+                FillRule::Winding,
+                true,
+                self.transform,
+            ),
+            None => mask.clear(),
+        }
+        let previous = self.clip.replace(mask);
+        self.saved.push(Saved::Clip(previous));
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.saved.push(Saved::Transform(self.transform));
+        let Transform { a, b, c, d, e, f } = transform;
+        self.transform = self
+            .transform
+            .pre_concat(tiny_skia::Transform::from_row(a, b, c, d, e, f));
+    }
+
+    fn push_layer(&mut self, opacity: f32) {
+        let layer =
+            Pixmap::new(self.pixmap.width(), self.pixmap.height()).unwrap();
+        let backdrop = std::mem::replace(&mut self.pixmap, layer);
+        self.saved.push(Saved::Layer(backdrop, opacity));
+    }
+
+    fn pop(&mut self) {
+        match self.saved.pop().expect("Unbalanced pops") {
+            Saved::Clip(clip) => self.clip = clip,
+            Saved::Transform(transform) => self.transform = transform,
+            Saved::Layer(backdrop, opacity) => {
+                let layer = std::mem::replace(&mut self.pixmap, backdrop);
+                self.pixmap.draw_pixmap(
+                    0,
+                    0,
+                    layer.as_ref(),
+                    &PixmapPaint {
+                        opacity,
+                        ..PixmapPaint::default()
+                    },
+                    tiny_skia::Transform::identity(),
+                    None,
+                );
+            }
+        }
+    }
+}
+
+fn solid(color: Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(color.r, color.g, color.b, color.a);
+    paint
+}
+
+fn to_rect(rect: Rectangle) -> Option<Rect> {
+    Rect::from_xywh(rect.x, rect.y, rect.width, rect.height)
+}
+
+// Convert a flattened path into one of tiny-skia, unless it is empty.
+fn to_path(path: &svg::Path) -> Option<Path> {
+    let mut builder = PathBuilder::new();
+    for subpath in &path.subpaths {
+        let Some((&(x, y), rest)) = subpath.points.split_first() else {
+            continue;
+        };
+        builder.move_to(x, y);
+        for &(x, y) in rest {
+            builder.line_to(x, y);
+        }
+        if subpath.closed {
+            builder.close();
+        }
+    }
+    builder.finish()
+}
+
+// Build a path of rounded rectangles, whose corners are quarters of
+// ellipses approximated by cubic curves.
+fn rounded_path(rects: &[&RoundedRect]) -> Option<Path> {
+    // The distance of the control points from the ends of a curve, relative
+    // to the radius.
+    const KAPPA: f32 = 0.552_284_8;
+    let mut builder = PathBuilder::new();
+    for rect in rects {
+        let Rectangle {
+            x,
+            y,
+            width,
+            height,
+        } = rect.rect;
+        let (right, bottom) = (x + width, y + height);
+        let radii = rect.radii;
+        builder.move_to(x + radii.top_left.0, y);
+        let mut corner = |from: (f32, f32),
+                          corner: (f32, f32),
+                          to: (f32, f32)| {
+            let control = |(x, y): (f32, f32)| {
+                (x + KAPPA * (corner.0 - x), y + KAPPA * (corner.1 - y))
+            };
+            let (first, second) = (control(from), control(to));
+            builder.line_to(from.0, from.1);
+            builder.cubic_to(first.0, first.1, second.0, second.1, to.0, to.1);
+        };
+        corner(
+            (right - radii.top_right.0, y),
+            (right, y),
+            (right, y + radii.top_right.1),
+        );
+        corner(
+            (right, bottom - radii.bottom_right.1),
+            (right, bottom),
+            (right - radii.bottom_right.0, bottom),
+        );
+        corner(
+            (x + radii.bottom_left.0, bottom),
+            (x, bottom),
+            (x, bottom - radii.bottom_left.1),
+        );
+        corner((x, y + radii.top_left.1), (x, y), (x + radii.top_left.0, y));
+        builder.close();
+    }
+    builder.finish()
+}
+
+// Get the polygon of a border side, between its outer corners and the rays
+// from them through the inner corners, which is a triangle where the rays
+// meet and reaches `length` along them otherwise.
+fn side_area(
+    (start, start_inner): ((f32, f32), (f32, f32)),
+    (end, end_inner): ((f32, f32), (f32, f32)),
+    length: f32,
+) -> Vec<(f32, f32)> {
+    let start_ray = (start_inner.0 - start.0, start_inner.1 - start.1);
+    let end_ray = (end_inner.0 - end.0, end_inner.1 - end.1);
+    let cross = |a: (f32, f32), b: (f32, f32)| a.0 * b.1 - a.1 * b.0;
+    let denominator = cross(start_ray, end_ray);
+    if denominator.abs() > f32::EPSILON {
+        let offset = (end.0 - start.0, end.1 - start.1);
+        let t = cross(offset, end_ray) / denominator;
+        if t > 0.0 {
+            let meet = (start.0 + t * start_ray.0, start.1 + t * start_ray.1);
+            return vec![start, end, meet];
+        }
+    }
+    let far = |point: (f32, f32), ray: (f32, f32)| {
+        let scale = length / ray.0.hypot(ray.1).max(f32::EPSILON);
+        (point.0 + ray.0 * scale, point.1 + ray.1 * scale)
+    };
+    vec![start, end, far(end, end_ray), far(start, start_ray)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        html_parser::HTMLParser,
+        layout::{Dimensions, layout_tree},
+        painting::{Images, paint, paint_with_backend},
+        style::{MediaContext, apply},
+    };
+
+    #[test]
+    fn test_skia_backend() {
+        let root = HTMLParser::parse(
+            "<div class=\"a\"><p class=\"b\"></p><p class=\"c\"></p>\
+             <svg viewBox=\"0 0 10 10\"><circle cx=\"5\" cy=\"5\" r=\"4\" \
+             fill=\"#ff00ff\" stroke=\"#000000\"/></svg></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 8px; width: 40px; height: 40px; \
+             background-color: #ffee88; border: 4px solid #3366cc; \
+             border-left-color: #cc3333; border-radius: 10px; \
+             box-shadow: 4px 4px 6px #000000; overflow: hidden; \
+             transform: rotate(10deg); } \
+             p { margin: 0; height: 10px; } \
+             .b { background-image: linear-gradient(#ff0000, #0000ff); } \
+             .c { background-color: #00ff00; opacity: 0.5; } \
+             svg { display: block; width: 10px; height: 10px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 64.0;
+        let layout_root = layout_tree(&styled, viewport);
+        let bounds = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 64.0,
+            height: 64.0,
+        };
+
+        let mut backend = SkiaBackend::new(64, 64);
+        paint_with_backend(
+            &layout_root,
+            bounds,
+            &Images::new(),
+            1.0,
+            &mut backend,
+        );
+        let canvas = paint(&layout_root, bounds, &Images::new(), 1.0);
+
+        // The backends only differ in how they antialias edges, so few
+        // pixels are far apart.
+        let distance = |a: Color, b: Color| {
+            [(a.r, b.r), (a.g, b.g), (a.b, b.b), (a.a, b.a)]
+                .iter()
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+                .unwrap_or(0)
+        };
+        let pixels = backend.pixels();
+        let far = pixels
+            .iter()
+            .zip(&canvas.pixels)
+            .filter(|(a, b)| distance(**a, **b) > 48)
+            .count();
+        assert!(far < 64, "{}", far);
+        // Inside the shapes they agree, except that gradients are sampled
+        // at the nearest pixel of their tiles.
+        for (x, y) in [(30, 20), (20, 30), (30, 40), (11, 30), (50, 50)] {
+            let (a, b) = (pixels[y * 64 + x], canvas.pixels[y * 64 + x]);
+            assert!(distance(a, b) <= 16, "({}, {}) {:?} {:?}", x, y, a, b);
+        }
+    }
+}
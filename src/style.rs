@@ -1,30 +1,162 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    sync::{Arc, OnceLock},
+};
 
 use crate::{
+    bloom::{self, BloomFilter},
+    computed::{
+        ComputedStyle,
+        DEFAULT_FONT_SIZE,
+        WhiteSpace,
+        css_wide_keyword,
+    },
     css_parser::{
-        Rule, Selector, SimpleSelector, Specificity, StyleSheet, Value,
+        CSSParser,
+        Combinator,
+        Declaration,
+        MediaFeature,
+        MediaQuery,
+        MediaQueryList,
+        Origin,
+        PseudoClass,
+        PseudoElement,
+        Rule,
+        Selector,
+        SimpleSelector,
+        Specificity,
+        StyleSheet,
+        Unit,
+        Value,
     },
-    dom::{ElementData, Node, NodeType},
+    document::{Document, QuirksMode},
+    dom::{ElementData, Node, NodeId, NodeType},
+    presentational::presentational_hints,
 };
 
 // The map from CSS property names to values.
-type PropertyMap = HashMap<String, Value>;
+// It is ordered by name, so that iterating over it is deterministic.
+pub type PropertyMap = BTreeMap<String, Value>;
 
 // A node with associated styles.
+// Content generated by the `::before` and `::after` pseudo-elements has no
+// DOM node, so its styled nodes refer to the originating element and are
+// marked with `pseudo_element`.
 #[derive(Debug)]
 pub struct StyledNode<'a> {
     pub node: &'a Node,
-    pub specified_values: PropertyMap,
+    pub pseudo_element: Option<PseudoElement>,
+    // The text of a generated text node.
+    pub generated_text: Option<String>,
+    pub specified_values: Arc<PropertyMap>,
+    pub style: Arc<ComputedStyle>,
     pub children: Vec<StyledNode<'a>>,
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Display {
     Inline,
     Block,
+    InlineBlock,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    ListItem,
+    Table,
+    InlineTable,
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableColumnGroup,
+    TableColumn,
+    TableCaption,
     None,
 }
 
+impl Display {
+    // Parse a display keyword. Unknown keywords yield `None`, so that the
+    // caller can fall back to the initial value.
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Some(match keyword {
+            "inline" => Display::Inline,
+            "block" => Display::Block,
+            "inline-block" => Display::InlineBlock,
+            "flex" => Display::Flex,
+            "inline-flex" => Display::InlineFlex,
+            "grid" => Display::Grid,
+            "inline-grid" => Display::InlineGrid,
+            "list-item" => Display::ListItem,
+            "table" => Display::Table,
+            "inline-table" => Display::InlineTable,
+            "table-row-group" => Display::TableRowGroup,
+            "table-header-group" => Display::TableHeaderGroup,
+            "table-footer-group" => Display::TableFooterGroup,
+            "table-row" => Display::TableRow,
+            "table-cell" => Display::TableCell,
+            "table-column-group" => Display::TableColumnGroup,
+            "table-column" => Display::TableColumn,
+            "table-caption" => Display::TableCaption,
+            "none" => Display::None,
+            _ => return None,
+        })
+    }
+
+    // Get the block-level equivalent of this display type, which floated
+    // boxes are laid out with.
+    // SEE: https://drafts.csswg.org/css-display/#transformations
+    pub fn blockified(self) -> Self {
+        match self {
+            Display::Inline | Display::InlineBlock => Display::Block,
+            Display::InlineFlex => Display::Flex,
+            Display::InlineGrid => Display::Grid,
+            Display::InlineTable => Display::Table,
+            display => display,
+        }
+    }
+
+    // Check if boxes of this display type are parts of a table, which are
+    // laid out by the table algorithm.
+    pub fn is_table_part(self) -> bool {
+        matches!(
+            self,
+            Display::TableRowGroup
+                | Display::TableHeaderGroup
+                | Display::TableFooterGroup
+                | Display::TableRow
+                | Display::TableCell
+                | Display::TableColumnGroup
+                | Display::TableColumn
+                | Display::TableCaption
+        )
+    }
+
+    // Check if boxes of this display type group table rows.
+    pub fn is_table_row_group(self) -> bool {
+        matches!(
+            self,
+            Display::TableRowGroup
+                | Display::TableHeaderGroup
+                | Display::TableFooterGroup
+        )
+    }
+
+    // Check if boxes of this display type participate in inline layout.
+    pub fn is_inline_level(self) -> bool {
+        matches!(
+            self,
+            Display::Inline
+                | Display::InlineBlock
+                | Display::InlineFlex
+                | Display::InlineGrid
+                | Display::InlineTable
+        )
+    }
+}
+
 impl<'a> StyledNode<'a> {
     // Get the value by given property name.
     #[inline]
@@ -46,115 +178,2736 @@ impl<'a> StyledNode<'a> {
     }
 
     // Get the display value.
+    #[inline]
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match s.as_str() {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
+        self.style.display
+    }
+
+    // Dump the styled tree as text, one node per line, with the specified
+    // values of each node in name order.
+    // The output is stable, so that tests can compare it with a golden
+    // string.
+    pub fn dump(&self) -> String {
+        let mut output = String::new();
+        self.dump_into(&mut output, 0);
+        output
+    }
+
+    // Get a short name of the node for dumps: the tag name of an element,
+    // the pseudo-element, or the quoted text.
+    pub fn label(&self) -> String {
+        match (
+            &self.generated_text,
+            self.pseudo_element,
+            &self.node.node_type,
+        ) {
+            (Some(text), _, _) => format!("{:?}", text),
+            (None, Some(PseudoElement::Before), _) => "::before".to_string(),
+            (None, Some(PseudoElement::After), _) => "::after".to_string(),
+            (None, Some(PseudoElement::Marker), _) => "::marker".to_string(),
+            (None, None, NodeType::Element(elem)) => elem.tag_name.clone(),
+            (None, None, NodeType::Text(text)) => format!("{:?}", text),
+            (None, None, NodeType::Comment(_)) => "#comment".to_string(),
+        }
+    }
+
+    fn dump_into(&self, output: &mut String, depth: usize) {
+        let _ = write!(output, "{}{}", "  ".repeat(depth), self.label());
+        if !self.specified_values.is_empty() {
+            let declarations: Vec<String> = self
+                .specified_values
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            let _ = write!(output, " {{ {}; }}", declarations.join("; "));
+        }
+        output.push('\n');
+        for child in &self.children {
+            child.dump_into(output, depth + 1);
+        }
+    }
+}
+
+// Get the default stylesheet of the browser.
+// It is applied below author styles, so that unstyled documents still get
+// sensible display types and spacing.
+pub fn user_agent_stylesheet() -> &'static StyleSheet {
+    static STYLESHEET: OnceLock<StyleSheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        CSSParser::parse_with_origin(
+            include_str!("user_agent.css").to_string(),
+            Origin::UserAgent,
+        )
+    })
+}
+
+// Get the stylesheet with the rules that only apply in quirks mode.
+// SEE: https://html.spec.whatwg.org/multipage/rendering.html#the-css-user-agent-style-sheet-and-presentational-hints
+fn quirks_mode_stylesheet() -> &'static StyleSheet {
+    static STYLESHEET: OnceLock<StyleSheet> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        CSSParser::parse_with_origin(
+            include_str!("quirks.css").to_string(),
+            Origin::UserAgent,
+        )
+    })
+}
+
+// The environment that stylesheets are applied in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaContext {
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+    // The initial font size, which `rem` lengths of the root element are
+    // relative to.
+    pub root_font_size: f32,
+    // How many device pixels a CSS pixel covers along each axis, such as 2
+    // on a high-resolution display. Layout is in CSS pixels, and painting
+    // scales it to device pixels.
+    pub device_pixel_ratio: f32,
+}
+
+impl MediaContext {
+    // Check if a media query list matches, which is the case if any of its
+    // queries matches.
+    pub fn matches(&self, queries: &MediaQueryList) -> bool {
+        queries.iter().any(|query| self.matches_query(query))
+    }
+
+    // Check if a media query matches. Only the screen media type is
+    // supported.
+    fn matches_query(&self, query: &MediaQuery) -> bool {
+        let type_matches = query
+            .media_type
+            .as_deref()
+            .is_none_or(|media_type| matches!(media_type, "all" | "screen"));
+        let matches = type_matches
+            && query
+                .features
+                .iter()
+                .all(|feature| self.matches_feature(feature));
+        matches != query.negated
+    }
+
+    // Check if a media feature matches. Unknown features never match.
+    fn matches_feature(&self, feature: &MediaFeature) -> bool {
+        let (width, height) = (self.viewport_width, self.viewport_height);
+        // Relative lengths in media queries are relative to the initial
+        // font size.
+        let length = match feature.value {
+            Some(Value::Length(px, Unit::Px)) => Some(px),
+            Some(Value::Length(em, Unit::Em | Unit::Rem)) => {
+                Some(em * self.root_font_size)
+            }
+            Some(Value::Length(vw, Unit::Vw)) => Some(vw / 100.0 * width),
+            Some(Value::Length(vh, Unit::Vh)) => Some(vh / 100.0 * height),
+            Some(Value::Length(vmin, Unit::Vmin)) => {
+                Some(vmin / 100.0 * width.min(height))
+            }
+            Some(Value::Length(vmax, Unit::Vmax)) => {
+                Some(vmax / 100.0 * width.max(height))
+            }
+            Some(Value::Number(0.0)) => Some(0.0),
+            _ => None,
+        };
+        let ratio = self.device_pixel_ratio;
+        let resolution = match feature.value {
+            Some(Value::Length(dppx, Unit::Dppx)) => Some(dppx),
+            Some(Value::Length(dpi, Unit::Dpi)) => Some(dpi / 96.0),
+            _ => None,
+        };
+        match feature.name.as_str() {
+            "width" => length.map_or(width > 0.0, |px| width == px),
+            "min-width" => length.is_some_and(|px| width >= px),
+            "max-width" => length.is_some_and(|px| width <= px),
+            "height" => length.map_or(height > 0.0, |px| height == px),
+            "min-height" => length.is_some_and(|px| height >= px),
+            "max-height" => length.is_some_and(|px| height <= px),
+            "orientation" => match feature.value {
+                Some(Value::Keyword(ref k)) if k == "portrait" => {
+                    height >= width
+                }
+                Some(Value::Keyword(ref k)) if k == "landscape" => {
+                    width > height
+                }
+                None => true,
+                _ => false,
+            },
+            // SEE: https://drafts.csswg.org/mediaqueries/#resolution
+            "resolution" => {
+                resolution.map_or(ratio > 0.0, |dppx| ratio == dppx)
+            }
+            "min-resolution" => resolution.is_some_and(|dppx| ratio >= dppx),
+            "max-resolution" => resolution.is_some_and(|dppx| ratio <= dppx),
+            _ => false,
+        }
+    }
+}
+
+impl Default for MediaContext {
+    fn default() -> Self {
+        Self {
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+            root_font_size: DEFAULT_FONT_SIZE,
+            device_pixel_ratio: 1.0,
+        }
+    }
+}
+
+// The dynamic states of an element that pseudo-classes match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ElementState {
+    pub hover: bool,
+    pub focus: bool,
+    pub active: bool,
+    pub visited: bool,
+    pub checked: bool,
+}
+
+impl ElementState {
+    // Check if an element in this state matches a pseudo-class.
+    #[inline]
+    pub fn matches(self, pseudo_class: PseudoClass) -> bool {
+        match pseudo_class {
+            PseudoClass::Hover => self.hover,
+            PseudoClass::Focus => self.focus,
+            PseudoClass::Active => self.active,
+            PseudoClass::Visited => self.visited,
+            PseudoClass::Checked => self.checked,
+        }
+    }
+}
+
+// Provides the states that pseudo-classes such as `:hover` match, so that
+// the style module doesn't need to know about input devices or history.
+// After a state changes, the embedder invalidates the style of the
+// element with `Document::invalidate_style` and restyles.
+pub trait ElementStateProvider {
+    fn is_hovered(&self, _node: &Node) -> bool {
+        false
+    }
+
+    fn is_focused(&self, _node: &Node) -> bool {
+        false
+    }
+
+    fn is_active(&self, _node: &Node) -> bool {
+        false
+    }
+
+    fn is_visited(&self, _node: &Node) -> bool {
+        false
+    }
+
+    // By default, checkboxes and radio buttons are checked if they have the
+    // `checked` attribute, and options if they have `selected`.
+    fn is_checked(&self, node: &Node) -> bool {
+        match node.node_type {
+            NodeType::Element(ref elem) => match elem.tag_name.as_str() {
+                "input" => elem.attributes.contains_key("checked"),
+                "option" => elem.attributes.contains_key("selected"),
+                _ => false,
             },
-            _ => Display::Inline,
+            _ => false,
+        }
+    }
+
+    // Get all states of an element.
+    fn state(&self, node: &Node) -> ElementState {
+        ElementState {
+            hover: self.is_hovered(node),
+            focus: self.is_focused(node),
+            active: self.is_active(node),
+            visited: self.is_visited(node),
+            checked: self.is_checked(node),
         }
     }
 }
 
-// Apply a stylesheet to an entire DOM tree.
-fn style_tree<'a>(
+// The states of a document that nobody interacts with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StaticElementState;
+
+impl ElementStateProvider for StaticElementState {}
+
+// Apply stylesheets to an entire DOM tree, producing the styled tree that
+// layout consumes. This is the second stage of the pipeline:
+//
+// HTMLParser::parse -> style::apply -> layout
+//
+// The stylesheets are ordered by origin (user agent, user, author) and
+// then by their order in the slice. The user-agent stylesheet is always
+// applied, and rules inside `@media` only if their condition matches the
+// media context.
+pub fn apply<'a>(
     root: &'a Node,
-    stylesheet: &'a StyleSheet,
+    stylesheets: &[StyleSheet],
+    media: &MediaContext,
 ) -> StyledNode<'a> {
-    StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
+    let mut cache = StyleCache::default();
+    Restyler::new(
+        stylesheets,
+        media,
+        QuirksMode::NoQuirks,
+        &mut cache,
+        &[],
+        &StaticElementState,
+    )
+    .style_root(root)
+}
+
+// Restyle a document after mutations, like `apply`, but only match rules
+// for the subtrees under `dirty_roots` and nodes that are new to the cache.
+// Other nodes reuse their cached styles, and are only recomputed if their
+// parent's style changed. Pseudo-classes match the states from `state`.
+// Unlike `apply`, the quirks mode of the document is honored.
+// The cache must be cleared whenever the stylesheets change.
+pub fn restyle<'a>(
+    document: &'a Document,
+    stylesheets: &[StyleSheet],
+    media: &MediaContext,
+    cache: &mut StyleCache,
+    dirty_roots: &[NodeId],
+    state: &dyn ElementStateProvider,
+) -> StyledNode<'a> {
+    Restyler::new(
+        stylesheets,
+        media,
+        document.quirks_mode,
+        cache,
+        dirty_roots,
+        state,
+    )
+    .style_root(&document.root)
+}
+
+// Find the first element in tree order that a list of selectors matches,
+// such as `#main > p.note`, like `querySelector` does. Pseudo-classes match
+// the states of a document that nobody interacts with, and selectors of
+// pseudo-elements match nothing.
+// SEE: https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+pub fn query_selector<'a>(root: &'a Node, selectors: &str) -> Option<&'a Node> {
+    let stylesheet = CSSParser::parse(format!("{} {{}}", selectors));
+    let rule = stylesheet.rules.first()?;
+    find_matching(root, 1, &rule.selectors, &mut Vec::new())
+}
+
+fn find_matching<'a>(
+    node: &'a Node,
+    index: usize,
+    selectors: &[Selector],
+    ancestors: &mut Vec<MatchingElement<'a>>,
+) -> Option<&'a Node> {
+    let NodeType::Element(ref data) = node.node_type else {
+        return None;
+    };
+    let elem = MatchingElement {
+        data,
+        index,
+        state: StaticElementState.state(node),
+    };
+    if selectors.iter().any(|selector| {
+        selector.subject().pseudo_element.is_none()
+            && matches(elem, ancestors, selector)
+    }) {
+        return Some(node);
+    }
+    ancestors.push(elem);
+    let found = element_indices(node).find_map(|(child, index)| {
+        find_matching(child, index, selectors, ancestors)
+    });
+    ancestors.pop();
+    found
+}
+
+// Get the children of a node, with the position of each among its sibling
+// elements, counting from 1, which `:nth-child` matches.
+fn element_indices(node: &Node) -> impl Iterator<Item = (&Node, usize)> {
+    node.children.iter().scan(0, |index, child| {
+        if let NodeType::Element(_) = child.node_type {
+            *index += 1;
+        }
+        Some((child, *index))
+    })
+}
+
+// The stylesheets of a document together with the media they are applied
+// in and the styles computed so far.
+#[derive(Debug, Default)]
+pub struct StyleEngine {
+    stylesheets: Vec<StyleSheet>,
+    media: MediaContext,
+    cache: StyleCache,
+}
+
+impl StyleEngine {
+    pub fn new(stylesheets: Vec<StyleSheet>, media: MediaContext) -> Self {
+        Self {
+            stylesheets,
+            media,
+            cache: StyleCache::default(),
+        }
+    }
+
+    #[inline]
+    pub fn stylesheets(&self) -> &[StyleSheet] {
+        &self.stylesheets
+    }
+
+    #[inline]
+    pub fn media(&self) -> &MediaContext {
+        &self.media
+    }
+
+    // Add a stylesheet after the others. All cached styles are discarded.
+    pub fn add_stylesheet(&mut self, stylesheet: StyleSheet) {
+        self.stylesheets.push(stylesheet);
+        self.cache.clear();
+    }
+
+    // Style the document, reusing the styles computed for nodes outside
+    // `dirty_roots`. See `restyle`.
+    pub fn restyle<'a>(
+        &mut self,
+        document: &'a Document,
+        dirty_roots: &[NodeId],
+        state: &dyn ElementStateProvider,
+    ) -> StyledNode<'a> {
+        restyle(
+            document,
+            &self.stylesheets,
+            &self.media,
+            &mut self.cache,
+            dirty_roots,
+            state,
+        )
+    }
+
+    // Resize the viewport and re-evaluate the `@media` conditions.
+    // Return the roots of the subtrees whose styles must be recomputed,
+    // which are the elements matched by rules whose condition started or
+    // stopped matching, and, if the size changed, the elements with
+    // lengths relative to the viewport. Pass them to the next `restyle`.
+    pub fn set_viewport(
+        &mut self,
+        document: &Document,
+        width: f32,
+        height: f32,
+    ) -> Vec<NodeId> {
+        let media = MediaContext {
+            viewport_width: width,
+            viewport_height: height,
+            ..self.media
+        };
+        let toggled: Vec<&Rule> = std::iter::once(user_agent_stylesheet())
+            .chain(&self.stylesheets)
+            .flat_map(|stylesheet| &stylesheet.rules)
+            .filter(|rule| {
+                rule.media.as_ref().is_some_and(|queries| {
+                    self.media.matches(queries) != media.matches(queries)
+                })
+            })
+            .collect();
+
+        let mut dirty_roots = Vec::new();
+        if !toggled.is_empty() {
+            collect_matching_elements(
+                &document.root,
+                1,
+                &toggled,
+                &mut AncestorStack::default(),
+                &mut dirty_roots,
+            );
+        }
+
+        // Elements with viewport units have cached styles for the old
+        // size. Their descendants only inherit computed lengths, so they
+        // are recomputed if the style of the element changes.
+        if (width, height)
+            != (self.media.viewport_width, self.media.viewport_height)
+        {
+            let mut sized: Vec<NodeId> =
+                self.cache
+                    .entries
+                    .iter()
+                    .filter(|(_, cached)| {
+                        std::iter::once(&cached.specified_values)
+                            .chain(
+                                cached.generated.iter().map(|generated| {
+                                    &generated.specified_values
+                                }),
+                            )
+                            .flat_map(|values| values.values())
+                            .any(uses_viewport_units)
+                    })
+                    .map(|(&id, _)| id)
+                    .filter(|id| !dirty_roots.contains(id))
+                    .collect();
+            sized.sort();
+            dirty_roots.extend(sized);
+        }
+
+        // Styles outside the dirty roots don't depend on the viewport, so
+        // they stay valid.
+        self.media = media;
+        self.cache.media = Some(media);
+        dirty_roots
+    }
+}
+
+// Check if a value has a length relative to the size of the viewport.
+fn uses_viewport_units(value: &Value) -> bool {
+    match value {
+        Value::Length(_, Unit::Vw | Unit::Vh | Unit::Vmin | Unit::Vmax) => true,
+        Value::List(values) | Value::Function(_, values) => {
+            values.iter().any(uses_viewport_units)
+        }
+        _ => false,
+    }
+}
+
+// Collect the topmost elements that any selector of the rules matches.
+fn collect_matching_elements<'a>(
+    node: &'a Node,
+    index: usize,
+    rules: &[&Rule],
+    ancestors: &mut AncestorStack<'a>,
+    matched: &mut Vec<NodeId>,
+) {
+    let NodeType::Element(ref data) = node.node_type else {
+        return;
+    };
+    // The states of elements may change without another restyle, so
+    // pseudo-classes are assumed to match.
+    let elem = MatchingElement {
+        data,
+        index,
+        state: ElementState {
+            hover: true,
+            focus: true,
+            active: true,
+            visited: true,
+            checked: true,
         },
-        children: root
-            .children
+    };
+    // Pseudo-element selectors are matched against their originating
+    // element, which styles them.
+    if rules
+        .iter()
+        .flat_map(|rule| &rule.selectors)
+        .any(|selector| matches(elem, &ancestors.elements, selector))
+    {
+        // The whole subtree is restyled, so there is no need to look
+        // further down.
+        matched.push(node.node_id);
+        return;
+    }
+    ancestors.push(elem);
+    for (child, index) in element_indices(node) {
+        collect_matching_elements(child, index, rules, ancestors, matched);
+    }
+    ancestors.pop();
+}
+
+// Styles computed by a previous restyle, keyed by node.
+#[derive(Debug, Default)]
+pub struct StyleCache {
+    entries: HashMap<NodeId, CachedStyle>,
+    media: Option<MediaContext>,
+}
+
+impl StyleCache {
+    // Forget all cached styles.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.media = None;
+    }
+
+    // The number of cached nodes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Check if no node is cached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedStyle {
+    specified_values: Arc<PropertyMap>,
+    style: Arc<ComputedStyle>,
+    // The styles of the pseudo-elements that generate content.
+    generated: Vec<GeneratedStyle>,
+}
+
+// The style of a `::before` or `::after` pseudo-element.
+#[derive(Debug, Clone)]
+struct GeneratedStyle {
+    pseudo_element: PseudoElement,
+    content: String,
+    specified_values: Arc<PropertyMap>,
+    style: Arc<ComputedStyle>,
+    // The style of the generated text, which inherits everything.
+    text_style: Arc<ComputedStyle>,
+}
+
+// The state of a single styling pass.
+struct Restyler<'s, 'a, 'c> {
+    rule_maps: Vec<RuleMap<'s>>,
+    media: MediaContext,
+    quirks_mode: QuirksMode,
+    cache: &'c mut StyleCache,
+    dirty_roots: HashSet<NodeId>,
+    state: &'c dyn ElementStateProvider,
+    // The ancestors of the node being styled.
+    ancestors: AncestorStack<'a>,
+    // Whether any selector matches elements by their position among their
+    // siblings, which keeps siblings from sharing their styles.
+    structural: bool,
+    // The computed font size of the root element.
+    root_font_size: f32,
+    // The nodes styled so far, whose cached styles are kept.
+    visited: HashSet<NodeId>,
+}
+
+impl<'s, 'a, 'c> Restyler<'s, 'a, 'c> {
+    fn new(
+        stylesheets: &'s [StyleSheet],
+        media: &MediaContext,
+        quirks_mode: QuirksMode,
+        cache: &'c mut StyleCache,
+        dirty_roots: &[NodeId],
+        state: &'c dyn ElementStateProvider,
+    ) -> Self {
+        let quirks_stylesheet = match quirks_mode {
+            QuirksMode::Quirks => Some(quirks_mode_stylesheet()),
+            QuirksMode::NoQuirks | QuirksMode::LimitedQuirks => None,
+        };
+        let mut rule_maps: Vec<RuleMap> =
+            std::iter::once(user_agent_stylesheet())
+                .chain(quirks_stylesheet)
+                .chain(stylesheets)
+                .map(|stylesheet| RuleMap::new(stylesheet, media))
+                .collect();
+        rule_maps.sort_by_key(|rule_map| rule_map.origin);
+        let structural = rule_maps
             .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+            .flat_map(|rule_map| rule_map.rules)
+            .flat_map(|rule| &rule.selectors)
+            .any(|selector| selector.subject().nth_child.is_some());
+
+        // Cached styles are only valid for the same media.
+        if cache.media != Some(*media) {
+            cache.clear();
+            cache.media = Some(*media);
+        }
+
+        Self {
+            rule_maps,
+            media: *media,
+            quirks_mode,
+            cache,
+            dirty_roots: dirty_roots.iter().copied().collect(),
+            state,
+            ancestors: AncestorStack::default(),
+            structural,
+            root_font_size: media.root_font_size,
+            visited: HashSet::new(),
+        }
+    }
+
+    // Style the root element and its descendants.
+    // The root is computed first, because `rem` lengths of all descendants
+    // are relative to its font size.
+    fn style_root(mut self, root: &'a Node) -> StyledNode<'a> {
+        let styled =
+            self.style_node(root, 1, None, false, false, &mut HashMap::new());
+        let visited = &self.visited;
+        self.cache.entries.retain(|id, _| visited.contains(id));
+        styled
+    }
+
+    // Style a node and its descendants.
+    // `dirty` is set inside a dirty subtree, and `parent_changed` is set
+    // when the computed style of the parent differs from the cached one.
+    // `sharing` holds the styles computed for the previous siblings.
+    fn style_node(
+        &mut self,
+        node: &'a Node,
+        index: usize,
+        parent_style: Option<&ComputedStyle>,
+        dirty: bool,
+        parent_changed: bool,
+        sharing: &mut HashMap<SharingKey<'a>, CachedStyle>,
+    ) -> StyledNode<'a> {
+        self.visited.insert(node.node_id);
+        let dirty = dirty || self.dirty_roots.contains(&node.node_id);
+        let cached = self.cache.entries.get(&node.node_id).cloned();
+        let root_font_size = match parent_style {
+            Some(_) => self.root_font_size,
+            None => self.media.root_font_size,
+        };
+
+        let elem = match node.node_type {
+            NodeType::Element(ref data) => Some(MatchingElement {
+                data,
+                index,
+                state: self.state.state(node),
+            }),
+            NodeType::Text(_) | NodeType::Comment(_) => None,
+        };
+
+        let styles = match cached {
+            Some(ref cached) if !dirty && !parent_changed => cached.clone(),
+            _ => {
+                let key = SharingKey::new(node, elem, self.structural);
+                match sharing.get(&key) {
+                    // A sibling with the same matching inputs and the same
+                    // parent has the same style.
+                    Some(shared) => shared.clone(),
+                    None => {
+                        let styles = self.compute_style(
+                            elem,
+                            cached.as_ref(),
+                            dirty,
+                            parent_style,
+                            root_font_size,
+                        );
+                        sharing.insert(key, styles.clone());
+                        styles
+                    }
+                }
+            }
+        };
+        let changed = cached.as_ref().is_none_or(|cached| {
+            !Arc::ptr_eq(&cached.style, &styles.style)
+                && *cached.style != *styles.style
+        });
+
+        if parent_style.is_none() {
+            self.root_font_size = styles.style.font_size;
+        }
+        self.cache.entries.insert(node.node_id, styles.clone());
+
+        if let Some(elem) = elem {
+            self.ancestors.push(elem);
+        }
+        // Comments are not rendered, so they have no styled node.
+        let mut child_sharing = HashMap::new();
+        let mut children: Vec<StyledNode> = element_indices(node)
+            .filter(|(child, _)| {
+                !matches!(child.node_type, NodeType::Comment(_))
+            })
+            .map(|(child, index)| {
+                self.style_node(
+                    child,
+                    index,
+                    Some(&styles.style),
+                    dirty,
+                    changed,
+                    &mut child_sharing,
+                )
+            })
+            .collect();
+        if elem.is_some() {
+            self.ancestors.pop();
+        }
+
+        // Generated content becomes the first or last child.
+        for generated in &styles.generated {
+            let styled = generated_node(node, generated);
+            match generated.pseudo_element {
+                PseudoElement::After => children.push(styled),
+                _ => children.insert(0, styled),
+            }
+        }
+        number_list_items(node, &mut children);
+
+        StyledNode {
+            node,
+            pseudo_element: None,
+            generated_text: None,
+            specified_values: styles.specified_values,
+            style: styles.style,
+            children,
+        }
+    }
+
+    // Compute the style of a node that can't reuse its cached style.
+    // Rules are only matched again inside dirty subtrees, and the cached
+    // allocations are kept if the styles didn't change.
+    fn compute_style(
+        &self,
+        elem: Option<MatchingElement>,
+        cached: Option<&CachedStyle>,
+        dirty: bool,
+        parent_style: Option<&ComputedStyle>,
+        root_font_size: f32,
+    ) -> CachedStyle {
+        let Some(elem) = elem else {
+            // Text inherits everything from its parent.
+            let specified_values = match cached {
+                Some(cached) => cached.specified_values.clone(),
+                None => Arc::new(PropertyMap::new()),
+            };
+            let style = self.compute_or_reuse(
+                &specified_values,
+                cached.map(|cached| &cached.style),
+                parent_style,
+                root_font_size,
+            );
+            return CachedStyle {
+                specified_values,
+                style,
+                generated: Vec::new(),
+            };
+        };
+
+        let specified = |pseudo_element| {
+            Arc::new(specified_values(
+                elem,
+                pseudo_element,
+                &self.ancestors,
+                &self.rule_maps,
+                self.quirks_mode,
+            ))
+        };
+        // Rules only need to be matched again if the node is new or its
+        // subtree is dirty.
+        let rematch = dirty || cached.is_none();
+        let specified_values = match cached {
+            Some(cached) if !rematch => cached.specified_values.clone(),
+            _ => specified(None),
+        };
+        let style = self.compute_or_reuse(
+            &specified_values,
+            cached.map(|cached| &cached.style),
+            parent_style,
+            root_font_size,
+        );
+
+        let mut generated = Vec::new();
+        for pseudo_element in [PseudoElement::Before, PseudoElement::After] {
+            let cached = cached.and_then(|cached| {
+                cached
+                    .generated
+                    .iter()
+                    .find(|g| g.pseudo_element == pseudo_element)
+            });
+            let specified_values = match cached {
+                Some(cached) if !rematch => cached.specified_values.clone(),
+                // It generated nothing before, and nothing changed since.
+                None if !rematch => continue,
+                // Most stylesheets have no rules for a pseudo-element, so
+                // don't match them at all in that case.
+                _ if !self.rule_maps.iter().any(|rule_map| {
+                    rule_map.pseudo_elements.contains(&pseudo_element)
+                }) =>
+                {
+                    continue;
+                }
+                _ => specified(Some(pseudo_element)),
+            };
+            // `content: normal` and `content: none` generate nothing.
+            let Some(Value::String(content)) = specified_values.get("content")
+            else {
+                continue;
+            };
+            let generated_style = self.compute_or_reuse(
+                &specified_values,
+                cached.map(|cached| &cached.style),
+                Some(&style),
+                root_font_size,
+            );
+            if generated_style.display == Display::None {
+                continue;
+            }
+            let text_style = self.compute_or_reuse(
+                &PropertyMap::new(),
+                cached.map(|cached| &cached.text_style),
+                Some(&generated_style),
+                root_font_size,
+            );
+            generated.push(GeneratedStyle {
+                pseudo_element,
+                content: content.clone(),
+                specified_values: specified_values.clone(),
+                style: generated_style,
+                text_style,
+            });
+        }
+
+        CachedStyle {
+            specified_values,
+            style,
+            generated,
+        }
+    }
+
+    // Compute a style, keeping the cached allocation if it didn't change.
+    fn compute_or_reuse(
+        &self,
+        specified_values: &PropertyMap,
+        cached: Option<&Arc<ComputedStyle>>,
+        parent_style: Option<&ComputedStyle>,
+        root_font_size: f32,
+    ) -> Arc<ComputedStyle> {
+        let mut style = ComputedStyle::compute(
+            specified_values,
+            parent_style,
+            root_font_size,
+            (self.media.viewport_width, self.media.viewport_height),
+        );
+        style.snap_border_widths(self.media.device_pixel_ratio);
+        match cached {
+            Some(cached) if **cached == style => cached.clone(),
+            _ => Arc::new(style),
+        }
+    }
+}
+
+// Number the list items among the children of a node, and give each of
+// them a marker as its first child. Items count up from the `start` of an
+// ordered list, or from the `value` of an item.
+// SEE: https://html.spec.whatwg.org/multipage/grouping-content.html#ordinal-value
+// TODO: Support `counter-reset` and `counter-increment`, and count the
+// items of nested lists in the scope of their own list.
+fn number_list_items<'a>(node: &'a Node, children: &mut [StyledNode<'a>]) {
+    let attribute = |node: &Node, name: &str| match node.node_type {
+        NodeType::Element(ref elem) => elem
+            .attributes
+            .get(name)
+            .and_then(|value| value.trim().parse::<i32>().ok()),
+        _ => None,
+    };
+    let mut ordinal = attribute(node, "start").unwrap_or(1);
+    for child in children {
+        if child.style.display != Display::ListItem {
+            continue;
+        }
+        if let Some(value) = attribute(child.node, "value") {
+            ordinal = value;
+        }
+        if let Some(text) = child.style.list_style_type.marker(ordinal) {
+            let mut style =
+                ComputedStyle::anonymous(&child.style, Display::Inline);
+            style.white_space = WhiteSpace::Pre;
+            child.children.insert(
+                0,
+                StyledNode {
+                    node: child.node,
+                    pseudo_element: Some(PseudoElement::Marker),
+                    generated_text: Some(text),
+                    specified_values: Arc::new(PropertyMap::new()),
+                    style: Arc::new(style),
+                    children: Vec::new(),
+                },
+            );
+        }
+        ordinal += 1;
+    }
+}
+
+// Build the styled node of a pseudo-element with its generated text.
+fn generated_node<'a>(
+    node: &'a Node,
+    generated: &GeneratedStyle,
+) -> StyledNode<'a> {
+    let text = StyledNode {
+        node,
+        pseudo_element: Some(generated.pseudo_element),
+        generated_text: Some(generated.content.clone()),
+        specified_values: Arc::new(PropertyMap::new()),
+        style: generated.text_style.clone(),
+        children: Vec::new(),
+    };
+    StyledNode {
+        node,
+        pseudo_element: Some(generated.pseudo_element),
+        generated_text: None,
+        specified_values: generated.specified_values.clone(),
+        style: generated.style.clone(),
+        children: vec![text],
+    }
+}
+
+// The inputs of selector matching for a node. Siblings with equal keys
+// match the same rules, because they also share their ancestors.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum SharingKey<'a> {
+    Text,
+    Element {
+        tag_name: &'a str,
+        attributes: Vec<(&'a str, &'a str)>,
+        state: ElementState,
+        // The position among the sibling elements, if selectors match it.
+        index: Option<usize>,
+    },
+}
+
+impl<'a> SharingKey<'a> {
+    fn new(
+        node: &'a Node,
+        elem: Option<MatchingElement>,
+        structural: bool,
+    ) -> Self {
+        match (&node.node_type, elem) {
+            (NodeType::Element(data), Some(elem)) => {
+                let mut attributes: Vec<(&str, &str)> = data
+                    .attributes
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .collect();
+                attributes.sort_unstable();
+                SharingKey::Element {
+                    tag_name: &data.tag_name,
+                    attributes,
+                    state: elem.state,
+                    index: structural.then_some(elem.index),
+                }
+            }
+            _ => SharingKey::Text,
+        }
     }
 }
 
 // Apply styles to a single element.
+// Declarations are applied from the lowest to the highest precedence, so
+// that later ones overwrite earlier ones.
 fn specified_values(
-    elem: &ElementData,
-    stylesheet: &StyleSheet,
+    elem: MatchingElement,
+    pseudo_element: Option<PseudoElement>,
+    ancestors: &AncestorStack,
+    rule_maps: &[RuleMap],
+    quirks_mode: QuirksMode,
 ) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    // Pseudo-elements have no attributes of their own.
+    let (hints, inline_style) = match pseudo_element {
+        Some(_) => (Vec::new(), Vec::new()),
+        None => (
+            presentational_hints(elem.data),
+            elem.data
+                .attributes
+                .get("style")
+                .map(|style| CSSParser::parse_declaration_list(style.clone()))
+                .unwrap_or_default(),
+        ),
+    };
+    let attribute_declarations = hints
+        .iter()
+        .map(|declaration| (declaration, CascadeLevel::PresentationalHint))
+        .chain(inline_style.iter().map(|declaration| {
+            let level = if declaration.important {
+                CascadeLevel::ImportantStyleAttribute
+            } else {
+                CascadeLevel::StyleAttribute
+            };
+            (declaration, level)
+        }));
+    let mut declarations: Vec<(CascadeKey, &Declaration)> =
+        attribute_declarations
+            .enumerate()
+            .map(|(declaration_index, (declaration, level))| {
+                let key = CascadeKey {
+                    level,
+                    specificity: (0, 0, 0),
+                    sheet_index: 0,
+                    rule_index: 0,
+                    declaration_index,
+                };
+                (key, declaration)
+            })
+            .collect();
+    for (sheet_index, rule_map) in rule_maps.iter().enumerate() {
+        for matched in matching_rules(elem, pseudo_element, ancestors, rule_map)
+        {
+            for (declaration_index, declaration) in
+                matched.rule.declarations.iter().enumerate()
+            {
+                let key = CascadeKey {
+                    level: CascadeLevel::new(
+                        rule_map.origin,
+                        declaration.important,
+                    ),
+                    specificity: matched.specificity,
+                    sheet_index,
+                    rule_index: matched.rule_index,
+                    declaration_index,
+                };
+                declarations.push((key, declaration));
+            }
+        }
+    }
 
-    // Go through the rules in order of specificity.
-    rules.sort_by(|a, b| a.0.cmp(&b.0));
+    // Go through the declarations in order of precedence.
+    declarations.sort_by_key(|(key, _)| *key);
 
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+    // Remember the candidate declarations of each property rather than only
+    // the winning one, because `revert` rolls back to the candidates of
+    // lower origins.
+    let mut candidates: HashMap<String, Vec<(CascadeLevel, Value)>> =
+        HashMap::new();
+    for (key, declaration) in declarations {
+        // In quirks mode, author styles may omit the unit of lengths.
+        let unitless_lengths = quirks_mode == QuirksMode::Quirks
+            && key.level.origin() == Origin::Author;
+        for (name, value) in expand_declaration(declaration, unitless_lengths) {
+            candidates.entry(name).or_default().push((key.level, value));
         }
     }
+    candidates
+        .into_iter()
+        .filter_map(|(name, candidates)| {
+            cascaded_value(candidates).map(|value| (name, value))
+        })
+        .collect()
+}
 
-    return values;
+// Find the winning value among the candidate declarations of a property,
+// given from the lowest to the highest precedence.
+// `revert` discards the declarations of its own origin and all higher ones.
+// If no declaration is left, the property has no cascaded value, which is
+// what reverting the user-agent origin means.
+fn cascaded_value(candidates: Vec<(CascadeLevel, Value)>) -> Option<Value> {
+    let mut reverted_origin = None;
+    for (level, value) in candidates.into_iter().rev() {
+        let origin = level.origin();
+        if reverted_origin.is_some_and(|reverted| origin >= reverted) {
+            continue;
+        }
+        match value {
+            Value::Keyword(ref k) if k == "revert" => {
+                reverted_origin = Some(origin)
+            }
+            value => return Some(value),
+        }
+    }
+    None
 }
 
-// A rule with its specificity.
-type MatchedRule<'a> = (Specificity, &'a Rule);
+// The properties whose lengths may omit the unit in quirks mode.
+// SEE: https://quirks.spec.whatwg.org/#the-unitless-length-quirk
+const UNITLESS_LENGTH_QUIRK: &[&str] = &[
+    "border-width",
+    "border-top-width",
+    "border-right-width",
+    "border-bottom-width",
+    "border-left-width",
+    "bottom",
+    "font-size",
+    "height",
+    "left",
+    "margin",
+    "margin-top",
+    "margin-right",
+    "margin-bottom",
+    "margin-left",
+    "max-height",
+    "max-width",
+    "min-height",
+    "min-width",
+    "padding",
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+    "right",
+    "text-indent",
+    "top",
+    "width",
+];
+
+// Expand a declaration into the longhand properties it sets.
+// Shorthands are expanded here rather than during computation, so that a
+// longhand only overrides a shorthand if it wins the cascade.
+// Property names and keywords are case-insensitive, so they are lowercased,
+// except for font family names.
+// With `unitless_lengths`, numbers are taken as pixels for the properties
+// that the unitless length quirk applies to.
+fn expand_declaration(
+    declaration: &Declaration,
+    unitless_lengths: bool,
+) -> Vec<(String, Value)> {
+    let name = declaration.name.to_ascii_lowercase();
+    let unitless_lengths =
+        unitless_lengths && UNITLESS_LENGTH_QUIRK.contains(&name.as_str());
+    let normalize = |value: &Value| match *value {
+        Value::Keyword(ref keyword) if name != "font-family" => {
+            Value::Keyword(keyword.to_ascii_lowercase())
+        }
+        Value::Number(number) if unitless_lengths => {
+            Value::Length(number, Unit::Px)
+        }
+        Value::Function(ref function, ref arguments) => {
+            Value::Function(function.to_ascii_lowercase(), arguments.clone())
+        }
+        ref value => value.clone(),
+    };
+    let values = match declaration.value {
+        Value::List(ref values) => values.iter().map(normalize).collect(),
+        ref value => vec![normalize(value)],
+    };
+    let value = match values.as_slice() {
+        [value] => value.clone(),
+        _ => Value::List(values.clone()),
+    };
+
+    let (prefix, suffix) = match name.as_str() {
+        "margin" => ("margin", ""),
+        "padding" => ("padding", ""),
+        "border-width" => ("border", "-width"),
+        "border-style" => ("border", "-style"),
+        "border-color" => ("border", "-color"),
+        "border" | "border-top" | "border-right" | "border-bottom"
+        | "border-left" => return expand_border(&name, &values),
+        "border-radius" => return expand_border_radius(&values),
+        // `gap` sets the row gap and then the column gap, which defaults
+        // to the row gap.
+        "gap" => {
+            let column_gap = values.get(1).unwrap_or(&values[0]);
+            return vec![
+                ("row-gap".to_string(), values[0].clone()),
+                ("column-gap".to_string(), column_gap.clone()),
+            ];
+        }
+        // `columns` sets the column width and the column count in any
+        // order, and either of them may be left out or `auto`.
+        "columns" => {
+            if let Some(keyword) = css_wide_keyword_of(&values) {
+                return vec![
+                    ("column-width".to_string(), keyword.clone()),
+                    ("column-count".to_string(), keyword.clone()),
+                ];
+            }
+            let auto = Value::Keyword("auto".to_string());
+            let count = values
+                .iter()
+                .find(|value| matches!(value, Value::Number(n) if *n != 0.0));
+            let width = values.iter().find(|value| {
+                !matches!(value, Value::Keyword(_)) && Some(*value) != count
+            });
+            return vec![
+                ("column-width".to_string(), width.unwrap_or(&auto).clone()),
+                ("column-count".to_string(), count.unwrap_or(&auto).clone()),
+            ];
+        }
+        // `list-style` sets the marker position and type in any order.
+        // Omitted values are reset to their initial values.
+        // TODO: Support `list-style-image`.
+        "list-style" => {
+            if let Some(keyword) = css_wide_keyword_of(&values) {
+                return vec![
+                    ("list-style-position".to_string(), keyword.clone()),
+                    ("list-style-type".to_string(), keyword.clone()),
+                ];
+            }
+            let keyword = |value: &Value| match value {
+                Value::Keyword(k) => Some(k.clone()),
+                _ => None,
+            };
+            let is_position = |k: &String| k == "inside" || k == "outside";
+            let keywords: Vec<_> = values.iter().filter_map(keyword).collect();
+            let position = keywords.iter().find(|k| is_position(k));
+            let style_type = keywords.iter().find(|k| !is_position(k));
+            return vec![
+                (
+                    "list-style-position".to_string(),
+                    Value::Keyword(
+                        position.map_or("outside", |k| k.as_str()).to_string(),
+                    ),
+                ),
+                (
+                    "list-style-type".to_string(),
+                    Value::Keyword(
+                        style_type.map_or("disc", |k| k.as_str()).to_string(),
+                    ),
+                ),
+            ];
+        }
+        // The legacy `page-break-*` properties are aliases of `break-*`,
+        // where `always` is `page`.
+        // SEE: https://drafts.csswg.org/css-break/#page-break-properties
+        "page-break-before" | "page-break-after" | "page-break-inside" => {
+            let value = match value {
+                Value::Keyword(k) if k == "always" => {
+                    Value::Keyword("page".to_string())
+                }
+                value => value,
+            };
+            return vec![(name["page-".len()..].to_string(), value)];
+        }
+        // `overflow` sets the horizontal and then the vertical overflow,
+        // which defaults to the horizontal one.
+        // `text-decoration: underline #ff0000` sets the lines and their
+        // color, which is `currentColor` unless it is given. The style of
+        // the lines is accepted, but they are always solid.
+        "text-decoration" => {
+            if let Some(keyword) = css_wide_keyword_of(&values) {
+                return vec![
+                    ("text-decoration-line".to_string(), keyword.clone()),
+                    ("text-decoration-color".to_string(), keyword.clone()),
+                ];
+            }
+            let is_line = |value: &&Value| {
+                matches!(value, Value::Keyword(k) if matches!(
+                    k.as_str(),
+                    "none" | "underline" | "overline" | "line-through"
+                ))
+            };
+            let is_style = |value: &&Value| {
+                matches!(value, Value::Keyword(k) if matches!(
+                    k.as_str(),
+                    "solid" | "double" | "dotted" | "dashed" | "wavy"
+                ))
+            };
+            let lines: Vec<_> =
+                values.iter().filter(is_line).cloned().collect();
+            let color = values
+                .iter()
+                .find(|value| !is_line(value) && !is_style(value))
+                .cloned()
+                .unwrap_or_else(|| Value::Keyword("currentcolor".to_string()));
+            let line = match lines.as_slice() {
+                [] => Value::Keyword("none".to_string()),
+                [line] => line.clone(),
+                _ => Value::List(lines),
+            };
+            return vec![
+                ("text-decoration-line".to_string(), line),
+                ("text-decoration-color".to_string(), color),
+            ];
+        }
+        // `outline` sets the width, style, and color of the outline in any
+        // order, like `border` does for a side, and `auto` is a style.
+        "outline" => {
+            let (width, style, color) = line_values(&values, &["auto"]);
+            return vec![
+                ("outline-width".to_string(), width),
+                ("outline-style".to_string(), style),
+                ("outline-color".to_string(), color),
+            ];
+        }
+        "overflow" => {
+            let overflow_y = values.get(1).unwrap_or(&values[0]);
+            return vec![
+                ("overflow-x".to_string(), values[0].clone()),
+                ("overflow-y".to_string(), overflow_y.clone()),
+            ];
+        }
+        // `grid-column: 1 / 3` sets the start and end lines.
+        "grid-column" | "grid-row" => {
+            let mut lines = values
+                .split(|value| matches!(value, Value::Keyword(k) if k == "/"));
+            let line = |values: Option<&[Value]>| match values {
+                Some([value]) => value.clone(),
+                Some(values) if !values.is_empty() => {
+                    Value::List(values.to_vec())
+                }
+                _ => Value::Keyword("auto".to_string()),
+            };
+            let (start, end) = (line(lines.next()), line(lines.next()));
+            return vec![
+                (format!("{}-start", name), start),
+                (format!("{}-end", name), end),
+            ];
+        }
+        _ => return vec![(name, value)],
+    };
+    // One to four values set the top, right, bottom, and left sides, where
+    // missing sides copy the opposite one.
+    let (top, right, bottom, left) = match values.as_slice() {
+        [all] => (all, all, all, all),
+        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+        [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+        [top, right, bottom, left, ..] => (top, right, bottom, left),
+        [] => return Vec::new(),
+    };
+    [
+        ("top", top),
+        ("right", right),
+        ("bottom", bottom),
+        ("left", left),
+    ]
+    .into_iter()
+    .map(|(side, value)| {
+        (format!("{}-{}{}", prefix, side, suffix), value.clone())
+    })
+    .collect()
+}
+
+// The keywords of `border-style`.
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge",
+    "inset", "outset",
+];
+
+// Expand `border`, which sets the width, style, and color of all sides, or
+// `border-top` and the like, which set those of one side. They are given in
+// any order, and omitted values are reset to their initial values.
+// SEE: https://www.w3.org/TR/CSS22/box.html#border-shorthand-properties
+fn expand_border(name: &str, values: &[Value]) -> Vec<(String, Value)> {
+    let sides: &[&str] = match name.strip_prefix("border-") {
+        Some(side) => &[side][..],
+        None => &["top", "right", "bottom", "left"],
+    };
+    let (width, style, color) = line_values(values, &[]);
+    let mut longhands = Vec::new();
+    for side in sides {
+        longhands.push((format!("border-{}-width", side), width.clone()));
+        longhands.push((format!("border-{}-style", side), style.clone()));
+        longhands.push((format!("border-{}-color", side), color.clone()));
+    }
+    longhands
+}
+
+// Sort the values of a shorthand of a line, like `border`, into its width,
+// its style, which is a border style or one of `styles`, and its color.
+fn line_values(values: &[Value], styles: &[&str]) -> (Value, Value, Value) {
+    let keyword = |k: &str| Value::Keyword(k.to_string());
+    match css_wide_keyword_of(values) {
+        Some(value) => (value.clone(), value.clone(), value.clone()),
+        None => {
+            let is_color = |value: &&Value| match value {
+                Value::ColorValue(_) => true,
+                Value::Keyword(k) => k == "currentcolor",
+                _ => false,
+            };
+            let is_style = |value: &&Value| matches!(value, Value::Keyword(k) if BORDER_STYLES.contains(&k.as_str()) || styles.contains(&k.as_str()));
+            let color = values.iter().find(is_color);
+            let style = values.iter().find(is_style);
+            let width = values
+                .iter()
+                .find(|value| !is_color(value) && !is_style(value));
+            (
+                width.cloned().unwrap_or_else(|| keyword("medium")),
+                style.cloned().unwrap_or_else(|| keyword("none")),
+                color.cloned().unwrap_or_else(|| keyword("currentcolor")),
+            )
+        }
+    }
+}
+
+// Expand `border-radius`, whose horizontal radii may be followed by a slash
+// and the vertical radii. Each list of one to four radii sets the corners
+// clockwise from the top left one, where missing corners copy the opposite
+// one.
+// SEE: https://drafts.csswg.org/css-backgrounds/#border-radius
+fn expand_border_radius(values: &[Value]) -> Vec<(String, Value)> {
+    let names = [
+        "border-top-left-radius",
+        "border-top-right-radius",
+        "border-bottom-right-radius",
+        "border-bottom-left-radius",
+    ];
+    if let Some(keyword) = css_wide_keyword_of(values) {
+        return names
+            .iter()
+            .map(|name| (name.to_string(), keyword.clone()))
+            .collect();
+    }
+    let corners = |radii: &[Value]| match radii {
+        [all] => Some([all, all, all, all].map(Value::clone)),
+        [diagonal, other] => {
+            Some([diagonal, other, diagonal, other].map(Value::clone))
+        }
+        [top_left, other, bottom_right] => {
+            Some([top_left, other, bottom_right, other].map(Value::clone))
+        }
+        [top_left, top_right, bottom_right, bottom_left, ..] => Some(
+            [top_left, top_right, bottom_right, bottom_left].map(Value::clone),
+        ),
+        [] => None,
+    };
+    let mut lists =
+        values.split(|value| matches!(value, Value::Keyword(k) if k == "/"));
+    let Some(horizontal) = lists.next().and_then(corners) else {
+        return Vec::new();
+    };
+    let vertical = lists.next().and_then(corners);
+    names
+        .iter()
+        .zip(horizontal)
+        .enumerate()
+        .map(|(i, (name, horizontal))| {
+            let value = match &vertical {
+                Some(vertical) => {
+                    Value::List(vec![horizontal, vertical[i].clone()])
+                }
+                None => horizontal,
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+// Get the value of a shorthand that is a single CSS-wide keyword, which
+// all of its longhands take.
+fn css_wide_keyword_of(values: &[Value]) -> Option<&Value> {
+    match values {
+        [value] if css_wide_keyword(Some(value)).is_some() => Some(value),
+        _ => None,
+    }
+}
+
+// The precedence of a declaration given by its origin and importance,
+// from lowest to highest. Important declarations reverse the order of
+// origins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CascadeLevel {
+    UserAgent,
+    User,
+    // Declarations mapped from presentational attributes, which precede all
+    // author rules.
+    PresentationalHint,
+    Author,
+    // Declarations of the `style` attribute, which take precedence over
+    // author rules of any specificity.
+    StyleAttribute,
+    ImportantAuthor,
+    ImportantStyleAttribute,
+    ImportantUser,
+    ImportantUserAgent,
+}
+
+impl CascadeLevel {
+    fn new(origin: Origin, important: bool) -> Self {
+        match (origin, important) {
+            (Origin::UserAgent, false) => CascadeLevel::UserAgent,
+            (Origin::User, false) => CascadeLevel::User,
+            (Origin::Author, false) => CascadeLevel::Author,
+            (Origin::Author, true) => CascadeLevel::ImportantAuthor,
+            (Origin::User, true) => CascadeLevel::ImportantUser,
+            (Origin::UserAgent, true) => CascadeLevel::ImportantUserAgent,
+        }
+    }
+
+    // Get the origin of the declarations at this level. Presentational
+    // hints and the style attribute belong to the author origin.
+    fn origin(self) -> Origin {
+        match self {
+            CascadeLevel::UserAgent | CascadeLevel::ImportantUserAgent => {
+                Origin::UserAgent
+            }
+            CascadeLevel::User | CascadeLevel::ImportantUser => Origin::User,
+            CascadeLevel::PresentationalHint
+            | CascadeLevel::Author
+            | CascadeLevel::StyleAttribute
+            | CascadeLevel::ImportantAuthor
+            | CascadeLevel::ImportantStyleAttribute => Origin::Author,
+        }
+    }
+}
+
+// The precedence of a declaration in the cascade. Fields are compared in
+// order, so source order only breaks ties between equal levels and
+// specificities, with later sheets, rules, and declarations winning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct CascadeKey {
+    level: CascadeLevel,
+    specificity: Specificity,
+    sheet_index: usize,
+    rule_index: usize,
+    declaration_index: usize,
+}
+
+// A rule that matched an element.
+struct MatchedRule<'a> {
+    // The specificity of the most specific selector that matched.
+    specificity: Specificity,
+    // The position of the rule in its stylesheet.
+    rule_index: usize,
+    rule: &'a Rule,
+}
+
+// Rules of a stylesheet bucketed by the most selective component of each
+// selector (id, then class, then tag), so that matching an element only
+// tests the rules that could possibly apply to it.
+struct RuleMap<'a> {
+    origin: Origin,
+    rules: &'a [Rule],
+    by_id: HashMap<&'a str, Vec<usize>>,
+    by_class: HashMap<&'a str, Vec<usize>>,
+    // Keyed by lowercase tag name, because tag names are case-insensitive.
+    by_tag: HashMap<String, Vec<usize>>,
+    universal: Vec<usize>,
+    // The hashes of the ancestor components of each selector of each rule,
+    // checked against the ancestor Bloom filter before matching.
+    ancestor_hashes: Vec<Vec<Vec<u32>>>,
+    // The pseudo-elements that any selector targets.
+    pseudo_elements: HashSet<PseudoElement>,
+}
+
+impl<'a> RuleMap<'a> {
+    // Index the rules of a stylesheet, leaving out the rules whose media
+    // condition doesn't match.
+    fn new(stylesheet: &'a StyleSheet, media: &MediaContext) -> Self {
+        let mut rule_map = Self {
+            origin: stylesheet.origin,
+            rules: &stylesheet.rules,
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: Vec::new(),
+            ancestor_hashes: Vec::with_capacity(stylesheet.rules.len()),
+            pseudo_elements: HashSet::new(),
+        };
+        for (i, rule) in stylesheet.rules.iter().enumerate() {
+            rule_map
+                .ancestor_hashes
+                .push(rule.selectors.iter().map(ancestor_hashes).collect());
+            if rule
+                .media
+                .as_ref()
+                .is_some_and(|queries| !media.matches(queries))
+            {
+                continue;
+            }
+            for selector in &rule.selectors {
+                let simple = selector.subject();
+                rule_map.pseudo_elements.extend(simple.pseudo_element);
+                let bucket = if let Some(ref id) = simple.id {
+                    rule_map.by_id.entry(id.as_str()).or_default()
+                } else if let Some(class) = simple.class.first() {
+                    rule_map.by_class.entry(class.as_str()).or_default()
+                } else if let Some(ref tag_name) = simple.tag_name {
+                    rule_map
+                        .by_tag
+                        .entry(tag_name.to_ascii_lowercase())
+                        .or_default()
+                } else {
+                    &mut rule_map.universal
+                };
+                bucket.push(i);
+            }
+        }
+        rule_map
+    }
+
+    // Get the rules that could match an element with their indices, in
+    // stylesheet order.
+    fn candidates(&self, elem: &ElementData) -> Vec<(usize, &'a Rule)> {
+        let mut indices = self.universal.clone();
+        if let Some(bucket) =
+            elem.id().and_then(|id| self.by_id.get(id.as_str()))
+        {
+            indices.extend(bucket);
+        }
+        for class in elem.classes() {
+            if let Some(bucket) = self.by_class.get(class) {
+                indices.extend(bucket);
+            }
+        }
+        if let Some(bucket) =
+            self.by_tag.get(&elem.tag_name.to_ascii_lowercase())
+        {
+            indices.extend(bucket);
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        indices.into_iter().map(|i| (i, &self.rules[i])).collect()
+    }
+}
 
 // Find all CSS rules that match the given element.
+// With `pseudo_element`, find the rules that match that pseudo-element of
+// the element instead.
 fn matching_rules<'a>(
-    elem: &ElementData,
-    stylesheet: &'a StyleSheet,
+    elem: MatchingElement,
+    pseudo_element: Option<PseudoElement>,
+    ancestors: &AncestorStack,
+    rule_map: &RuleMap<'a>,
 ) -> Vec<MatchedRule<'a>> {
-    stylesheet
-        .rules
-        .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+    rule_map
+        .candidates(elem.data)
+        .into_iter()
+        .filter_map(|(rule_index, rule)| {
+            match_rule(
+                elem,
+                pseudo_element,
+                ancestors,
+                rule_index,
+                rule,
+                &rule_map.ancestor_hashes[rule_index],
+            )
+        })
         .collect()
 }
 
 // Match a CSS rule to an element.
-// Find the first selector that matches the element, because CSS parser stores the selectors from most- to least-specific.
+// Find the first selector that matches the element, because CSS parser stores
+// the selectors from most- to least-specific.
 fn match_rule<'a>(
-    elem: &ElementData,
+    elem: MatchingElement,
+    pseudo_element: Option<PseudoElement>,
+    ancestors: &AncestorStack,
+    rule_index: usize,
     rule: &'a Rule,
+    ancestor_hashes: &[Vec<u32>],
 ) -> Option<MatchedRule<'a>> {
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, selector))
-        .map(|selector| (selector.specificity(), rule))
+        .zip(ancestor_hashes)
+        .find(|(selector, hashes)| {
+            // Skip the walk up the ancestors if the filter proves that one
+            // of the required ancestors is missing.
+            selector.subject().pseudo_element == pseudo_element
+                && hashes
+                    .iter()
+                    .all(|&hash| ancestors.filter.might_contain_hash(hash))
+                && matches(elem, &ancestors.elements, selector)
+        })
+        .map(|(selector, _)| MatchedRule {
+            specificity: selector.specificity(),
+            rule_index,
+            rule,
+        })
 }
 
-// Check if a selector matches an element.
-#[inline]
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+// Check if a selector matches an element, given its ancestor elements from
+// the root down to its parent.
+fn matches(
+    elem: MatchingElement,
+    ancestors: &[MatchingElement],
+    selector: &Selector,
+) -> bool {
     match selector {
         Selector::Simple(simple) => matches_simple_selector(elem, simple),
+        Selector::Complex(complex) => {
+            matches_simple_selector(elem, &complex.subject)
+                && matches_ancestors(ancestors, &complex.ancestors)
+        }
+    }
+}
+
+// Check if the ancestors match the ancestor components of a complex
+// selector, from right to left.
+fn matches_ancestors(
+    ancestors: &[MatchingElement],
+    components: &[(Combinator, SimpleSelector)],
+) -> bool {
+    let Some(((combinator, simple), rest)) = components.split_first() else {
+        return true;
+    };
+    match combinator {
+        Combinator::Child => match ancestors.split_last() {
+            Some((&parent, ancestors)) => {
+                matches_simple_selector(parent, simple)
+                    && matches_ancestors(ancestors, rest)
+            }
+            None => false,
+        },
+        // Try the nearest matching ancestor first, and backtrack to farther
+        // ones if the rest of the selector doesn't match.
+        Combinator::Descendant => (0..ancestors.len()).rev().any(|i| {
+            matches_simple_selector(ancestors[i], simple)
+                && matches_ancestors(&ancestors[..i], rest)
+        }),
+    }
+}
+
+// An element as seen by selector matching.
+#[derive(Debug, Clone, Copy)]
+struct MatchingElement<'a> {
+    data: &'a ElementData,
+    // The position among the sibling elements, counting from 1.
+    index: usize,
+    state: ElementState,
+}
+
+// The ancestor elements of the element being styled, with a Bloom filter
+// of their tag names, ids, and classes. The filter rejects most selectors
+// whose ancestors are missing without walking up the tree.
+#[derive(Debug, Default)]
+struct AncestorStack<'a> {
+    elements: Vec<MatchingElement<'a>>,
+    filter: BloomFilter,
+}
+
+impl<'a> AncestorStack<'a> {
+    fn push(&mut self, elem: MatchingElement<'a>) {
+        for hash in element_hashes(elem.data) {
+            self.filter.insert_hash(hash);
+        }
+        self.elements.push(elem);
+    }
+
+    fn pop(&mut self) {
+        if let Some(elem) = self.elements.pop() {
+            for hash in element_hashes(elem.data) {
+                self.filter.remove_hash(hash);
+            }
+        }
+    }
+}
+
+// Hash the components of an element that selectors can match.
+// Tag names are lowercased, because they are case-insensitive.
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+    let mut hashes = vec![bloom::hash(&elem.tag_name.to_ascii_lowercase())];
+    hashes.extend(elem.id().map(|id| bloom::hash(&('#', id.as_str()))));
+    hashes.extend(elem.classes().into_iter().map(|c| bloom::hash(&('.', c))));
+    hashes
+}
+
+// Hash the components that ancestors must have to match a selector.
+fn ancestor_hashes(selector: &Selector) -> Vec<u32> {
+    let Selector::Complex(ref complex) = *selector else {
+        return Vec::new();
+    };
+    let mut hashes = Vec::new();
+    for (_, simple) in &complex.ancestors {
+        if let Some(ref tag_name) = simple.tag_name {
+            hashes.push(bloom::hash(&tag_name.to_ascii_lowercase()));
+        }
+        if let Some(ref id) = simple.id {
+            hashes.push(bloom::hash(&('#', id.as_str())));
+        }
+        for class in &simple.class {
+            hashes.push(bloom::hash(&('.', class.as_str())));
+        }
     }
+    hashes
 }
 
 // Check if a simple selector matches an element.
 fn matches_simple_selector(
-    elem: &ElementData,
+    elem: MatchingElement,
     selector: &SimpleSelector,
 ) -> bool {
-    // Check type selector.
-    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
-        return false;
-    }
+    // Every component of the selector must match. Missing components match
+    // any element.
+    let MatchingElement {
+        data: elem,
+        index,
+        state,
+    } = elem;
+
+    // Check type selector. HTML tag names are case-insensitive.
+    let tag_matches = selector
+        .tag_name
+        .as_ref()
+        .is_none_or(|name| elem.tag_name.eq_ignore_ascii_case(name));
 
     // Check id selector.
-    if selector.id.iter().any(|id| elem.id() != Some(id)) {
-        return false;
-    }
+    let id_matches =
+        selector.id.as_ref().is_none_or(|id| elem.id() == Some(id));
 
-    // Check class selector.
-    if selector
+    // Check class selectors.
+    let classes = elem.classes();
+    let classes_match = selector
         .class
         .iter()
-        .any(|class| !elem.classes().contains(class.as_str()))
-    {
-        return false;
+        .all(|class| classes.contains(class.as_str()));
+
+    // Check pseudo-classes.
+    let pseudo_classes_match = selector
+        .pseudo_classes
+        .iter()
+        .all(|&pseudo_class| state.matches(pseudo_class));
+
+    // Check the position among the sibling elements.
+    let nth_child_matches = selector.nth_child.is_none_or(|n| n == index);
+
+    tag_matches
+        && id_matches
+        && classes_match
+        && pseudo_classes_match
+        && nth_child_matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        computed::{
+            BorderStyle,
+            GridLine,
+            LengthOrAuto,
+            LengthPercentage,
+            OutlineStyle,
+            Overflow,
+        },
+        dom::AttributeMap,
+        html_parser::HTMLParser,
+    };
+
+    fn element(tag_name: &str, attributes: &[(&str, &str)]) -> ElementData {
+        ElementData {
+            tag_name: tag_name.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<AttributeMap>(),
+        }
+    }
+
+    fn matching(data: &ElementData) -> MatchingElement<'_> {
+        MatchingElement {
+            data,
+            index: 1,
+            state: ElementState::default(),
+        }
+    }
+
+    fn selector(source: &str) -> Selector {
+        let mut stylesheet = CSSParser::parse(format!("{} {{}}", source));
+        stylesheet.rules.remove(0).selectors.remove(0)
+    }
+
+    #[test]
+    fn test_compound_class_requires_all_classes() {
+        let both = element("div", &[("class", "a b")]);
+        let only_a = element("div", &[("class", "a")]);
+        assert!(matches(matching(&both), &[], &selector(".a.b")));
+        assert!(!matches(matching(&only_a), &[], &selector(".a.b")));
+    }
+
+    #[test]
+    fn test_class_attribute_with_extra_whitespace() {
+        let elem = element("div", &[("class", "  a\tb\n")]);
+        assert!(matches(matching(&elem), &[], &selector(".a.b")));
+        assert!(!elem.classes().contains(""));
+    }
+
+    #[test]
+    fn test_tag_class_and_id_combinations() {
+        let elem = element("p", &[("id", "main"), ("class", "note")]);
+        assert!(matches(matching(&elem), &[], &selector("p")));
+        assert!(matches(matching(&elem), &[], &selector("P")));
+        assert!(matches(matching(&elem), &[], &selector("p.note")));
+        assert!(matches(matching(&elem), &[], &selector("p#main")));
+        assert!(matches(matching(&elem), &[], &selector("p#main.note")));
+        assert!(matches(matching(&elem), &[], &selector("#main.note")));
+        assert!(!matches(matching(&elem), &[], &selector("div.note")));
+        assert!(!matches(matching(&elem), &[], &selector("p#other.note")));
+        assert!(!matches(matching(&elem), &[], &selector("p#main.other")));
+    }
+
+    #[test]
+    fn test_query_selector() {
+        let root = HTMLParser::parse(
+            "<div id=\"a\"><p>1</p><section><p class=\"x\">2</p></section>\
+             </div>"
+                .to_string(),
+        );
+        let text = |selectors: &str| {
+            query_selector(&root, selectors).map(Node::text_content)
+        };
+        // The first match in tree order, which may be the root itself.
+        assert_eq!(text("p"), Some("1".to_string()));
+        assert_eq!(text("#a > p"), Some("1".to_string()));
+        assert_eq!(text("section p, .y"), Some("2".to_string()));
+        assert_eq!(text("div"), Some("12".to_string()));
+        assert_eq!(text("#a > .x"), None);
+        assert_eq!(text("p::before"), None);
+    }
+
+    #[test]
+    fn test_selector_without_classes() {
+        let plain = element("span", &[]);
+        let classed = element("span", &[("class", "x")]);
+        assert!(matches(matching(&plain), &[], &selector("span")));
+        assert!(matches(matching(&classed), &[], &selector("span")));
+        assert!(matches(matching(&plain), &[], &selector("*")));
+        assert!(!matches(matching(&plain), &[], &selector(".x")));
+    }
+
+    #[test]
+    fn test_descendant_and_child_combinators() {
+        let html = element("html", &[]);
+        let list = element("ul", &[("class", "menu")]);
+        let item = element("li", &[]);
+        let link = element("a", &[]);
+        let ancestors = [matching(&html), matching(&list), matching(&item)];
+        assert!(matches(matching(&link), &ancestors, &selector("ul a")));
+        assert!(matches(
+            matching(&link),
+            &ancestors,
+            &selector("html .menu li a")
+        ));
+        assert!(matches(matching(&link), &ancestors, &selector("li > a")));
+        assert!(matches(
+            matching(&link),
+            &ancestors,
+            &selector("ul > li > a")
+        ));
+        assert!(!matches(matching(&link), &ancestors, &selector("ul > a")));
+        assert!(!matches(matching(&link), &ancestors, &selector("ol a")));
+        assert!(!matches(matching(&link), &[], &selector("li a")));
+    }
+
+    #[test]
+    fn test_descendant_combinator_backtracks() {
+        // The nearest `div` has no `p` parent, but a farther one does.
+        let p = element("p", &[]);
+        let outer = element("div", &[]);
+        let inner = element("div", &[]);
+        let span = element("span", &[]);
+        let ancestors = [matching(&p), matching(&outer), matching(&inner)];
+        assert!(matches(
+            matching(&span),
+            &ancestors,
+            &selector("p > div span")
+        ));
+    }
+
+    #[test]
+    fn test_complex_selector_specificity() {
+        assert_eq!(selector("ul li.item > a").specificity(), (0, 1, 3));
+        assert_eq!(selector("#nav a").specificity(), (1, 0, 1));
+    }
+
+    #[test]
+    fn test_ancestor_filter() {
+        let list = element("UL", &[("id", "nav"), ("class", "menu")]);
+        let mut ancestors = AncestorStack::default();
+        ancestors.push(matching(&list));
+        let has_all = |ancestors: &AncestorStack, source: &str| {
+            ancestor_hashes(&selector(source))
+                .iter()
+                .all(|&hash| ancestors.filter.might_contain_hash(hash))
+        };
+        assert!(has_all(&ancestors, "ul#nav.menu a"));
+        assert!(has_all(&ancestors, "a"));
+        ancestors.pop();
+        assert!(!has_all(&ancestors, "ul a"));
+    }
+
+    #[test]
+    fn test_shorthands_follow_cascade_order() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div id=\"a\" class=\"b\"></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "#a { margin: 10px; } .b { margin-top: 5px; padding-left: 3px; } \
+             div { PADDING: 1px; DISPLAY: BLOCK; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        // The `margin` shorthand wins over `margin-top` by specificity, and
+        // `padding-left` wins over the `padding` shorthand.
+        assert_eq!(styled.style.margin.top, LengthOrAuto::Px(10.0));
+        assert_eq!(styled.style.padding.left, LengthPercentage::Px(3.0));
+        assert_eq!(styled.style.padding.top, LengthPercentage::Px(1.0));
+        assert_eq!(styled.display(), Display::Block);
+    }
+
+    #[test]
+    fn test_shorthands_with_several_values() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p></p><span></span></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { margin: 1px 2px 3px; padding: 1px 2px; gap: 4px 5px; } \
+             p { overflow: hidden scroll; columns: 3 10em; } \
+             span { grid-column: 2 / span 3; columns: auto 40px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let margin = styled.style.margin;
+        assert_eq!(margin.right, LengthOrAuto::Px(2.0));
+        assert_eq!(margin.bottom, LengthOrAuto::Px(3.0));
+        assert_eq!(margin.left, LengthOrAuto::Px(2.0));
+        assert_eq!(styled.style.padding.bottom, LengthPercentage::Px(1.0));
+        assert_eq!(styled.style.padding.left, LengthPercentage::Px(2.0));
+        assert_eq!(
+            (styled.style.row_gap, styled.style.column_gap),
+            (4.0, Some(5.0))
+        );
+        let p = &styled.children[0].style;
+        assert_eq!(
+            (p.overflow_x, p.overflow_y),
+            (Overflow::Hidden, Overflow::Scroll)
+        );
+        assert_eq!((p.column_count, p.column_width), (Some(3), Some(160.0)));
+        let span = &styled.children[1].style;
+        assert_eq!(span.grid_column, (GridLine::Line(2), GridLine::Span(3)));
+        assert_eq!((span.column_count, span.column_width), (None, Some(40.0)));
+    }
+
+    #[test]
+    fn test_border_shorthands() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { border: #ff0000 dashed thin; border-left: 4px solid; } \
+             p { border-width: 2px; border-style: solid none; \
+             border-color: #00ff00 #0000ff; border-bottom-color: #ff0000; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let style = &styled.style;
+        assert_eq!(style.border_width.top, 1.0);
+        assert_eq!(style.border_width.left, 4.0);
+        assert_eq!(style.border_style.top, BorderStyle::Dashed);
+        assert_eq!(style.border_style.left, BorderStyle::Solid);
+        assert_eq!(style.border_color.top.r, 255);
+        assert_eq!(style.border_color.left, style.color);
+        let p = &styled.children[0].style;
+        assert_eq!((p.border_width.top, p.border_width.right), (2.0, 0.0));
+        assert_eq!(p.border_color.top.g, 255);
+        assert_eq!(p.border_color.left.b, 255);
+        assert_eq!(p.border_color.bottom.r, 255);
+    }
+
+    #[test]
+    fn test_outline_shorthand() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { outline: #ff0000 dotted 2px; } p { outline: auto; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let style = &styled.style;
+        assert_eq!(style.outline_width, 2.0);
+        assert_eq!(
+            style.outline_style,
+            OutlineStyle::Border(BorderStyle::Dotted)
+        );
+        assert_eq!(style.outline_color.r, 255);
+        let p = &styled.children[0].style;
+        assert_eq!(p.outline_style, OutlineStyle::Auto);
+        assert_eq!(p.outline_color, p.color);
+    }
+
+    #[test]
+    fn test_border_radius_shorthand() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p></p></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { border-radius: 4px 10%; border-top-right-radius: 1px 2px; } \
+             p { border-radius: 1px 2px 3px / 5px; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let radius = &styled.style.border_radius;
+        let (px, percent) = (LengthPercentage::Px, LengthPercentage::Percent);
+        assert_eq!(radius.top_left, (px(4.0), px(4.0)));
+        assert_eq!(radius.top_right, (px(1.0), px(2.0)));
+        assert_eq!(radius.bottom_left, (percent(10.0), percent(10.0)));
+        let radius = &styled.children[0].style.border_radius;
+        assert_eq!(radius.top_left, (px(1.0), px(5.0)));
+        assert_eq!(radius.bottom_right, (px(3.0), px(5.0)));
+        assert_eq!(radius.bottom_left, (px(2.0), px(5.0)));
+    }
+
+    #[test]
+    fn test_pseudo_classes_match_element_state() {
+        let link = element("a", &[]);
+        let hovered = MatchingElement {
+            data: &link,
+            index: 1,
+            state: ElementState {
+                hover: true,
+                ..ElementState::default()
+            },
+        };
+        assert!(matches(hovered, &[], &selector("a:hover")));
+        assert!(!matches(hovered, &[], &selector("a:hover:focus")));
+        assert!(!matches(matching(&link), &[], &selector("a:hover")));
+        assert!(matches(
+            matching(&link),
+            &[hovered],
+            &selector(":hover > a")
+        ));
+        assert_eq!(selector("a:hover").specificity(), (0, 1, 1));
+    }
+
+    #[test]
+    fn test_state_provider_drives_restyle() {
+        struct Hovered(NodeId);
+
+        impl ElementStateProvider for Hovered {
+            fn is_hovered(&self, node: &Node) -> bool {
+                node.node_id == self.0
+            }
+        }
+
+        let mut document =
+            Document::new(crate::html_parser::HTMLParser::parse(
+                "<div><p id=\"a\"></p><p id=\"b\"></p></div>".to_string(),
+            ));
+        let a = document.get_element_by_id("a").unwrap().node_id;
+        let b = document.get_element_by_id("b").unwrap().node_id;
+        let mut engine = StyleEngine::new(
+            vec![CSSParser::parse(
+                "p:hover { font-weight: bold; }".to_string(),
+            )],
+            MediaContext::default(),
+        );
+        let dirty_roots = document.take_style_dirty_roots();
+        let styled = engine.restyle(&document, &dirty_roots, &Hovered(a));
+        assert_eq!(styled.children[0].style.font_weight, 700);
+        assert_eq!(styled.children[1].style.font_weight, 400);
+
+        // Move the pointer from `a` to `b`.
+        document.invalidate_style(a);
+        document.invalidate_style(b);
+        let dirty_roots = document.take_style_dirty_roots();
+        let styled = engine.restyle(&document, &dirty_roots, &Hovered(b));
+        assert_eq!(styled.children[0].style.font_weight, 400);
+        assert_eq!(styled.children[1].style.font_weight, 700);
     }
 
-    true
+    #[test]
+    fn test_restyle_keeps_clean_subtrees() {
+        let mut document =
+            Document::new(crate::html_parser::HTMLParser::parse(
+                "<div><section id=\"a\"><p></p></section>\
+                 <section id=\"b\"><p></p></section></div>"
+                    .to_string(),
+            ));
+        let a = document.get_element_by_id("a").unwrap().node_id;
+        let mut engine = StyleEngine::new(
+            vec![CSSParser::parse(
+                ".big p { font-weight: bold; }".to_string(),
+            )],
+            MediaContext::default(),
+        );
+        // The styles of the root, `a`, its paragraph, `b`, and its
+        // paragraph.
+        let arcs = |styled: &StyledNode| {
+            let div = styled;
+            let (a, b) = (&div.children[0], &div.children[1]);
+            [div, a, &a.children[0], b, &b.children[0]].map(|styled| {
+                (styled.specified_values.clone(), styled.style.clone())
+            })
+        };
+        let dirty_roots = document.take_style_dirty_roots();
+        let before =
+            arcs(&engine.restyle(&document, &dirty_roots, &StaticElementState));
+
+        document.set_attribute(a, "class", "big".to_string());
+        let dirty_roots = document.take_style_dirty_roots();
+        assert_eq!(dirty_roots, [a]);
+        let styled =
+            engine.restyle(&document, &dirty_roots, &StaticElementState);
+        assert_eq!(styled.children[0].children[0].style.font_weight, 700);
+        assert_eq!(styled.children[1].children[0].style.font_weight, 400);
+
+        // The dirty subtree is matched again, and the rest keeps the
+        // cached styles.
+        let after = arcs(&styled);
+        let kept: Vec<bool> = before
+            .iter()
+            .zip(&after)
+            .map(|((specified_before, before), (specified_after, after))| {
+                Arc::ptr_eq(specified_before, specified_after)
+                    && Arc::ptr_eq(before, after)
+            })
+            .collect();
+        assert_eq!(kept, [true, false, false, true, true]);
+    }
+
+    #[test]
+    fn test_set_viewport_dirties_elements_of_toggled_rules() {
+        let document = Document::new(crate::html_parser::HTMLParser::parse(
+            "<div><section><p></p></section><span></span></div>".to_string(),
+        ));
+        let section = document.root.children[0].node_id;
+        let mut engine = StyleEngine::new(
+            vec![CSSParser::parse(
+                "@media (min-width: 600px) { section { font-weight: bold; } } \
+                 span { color: #ff0000; }"
+                    .to_string(),
+            )],
+            MediaContext {
+                viewport_width: 500.0,
+                viewport_height: 400.0,
+                ..MediaContext::default()
+            },
+        );
+        engine.restyle(&document, &[], &StaticElementState);
+
+        // Crossing the breakpoint, either way, dirties the elements that
+        // the rules inside it match.
+        let dirty_roots = engine.set_viewport(&document, 800.0, 400.0);
+        assert_eq!(dirty_roots, [section]);
+        let styled =
+            engine.restyle(&document, &dirty_roots, &StaticElementState);
+        assert_eq!(styled.children[0].style.font_weight, 700);
+        assert_eq!(styled.children[0].children[0].style.font_weight, 700);
+        let dirty_roots = engine.set_viewport(&document, 599.0, 400.0);
+        assert_eq!(dirty_roots, [section]);
+        engine.restyle(&document, &dirty_roots, &StaticElementState);
+
+        // A resize that crosses no breakpoint dirties nothing, and keeps
+        // the cached styles.
+        assert_eq!(engine.set_viewport(&document, 300.0, 200.0), []);
+        let styled = engine.restyle(&document, &[], &StaticElementState);
+        assert_eq!(styled.children[0].style.font_weight, 400);
+    }
+
+    #[test]
+    fn test_siblings_share_styles() {
+        struct Hovered(NodeId);
+
+        impl ElementStateProvider for Hovered {
+            fn is_hovered(&self, node: &Node) -> bool {
+                node.node_id == self.0
+            }
+        }
+
+        let document = Document::new(crate::html_parser::HTMLParser::parse(
+            "<div><p class=\"x\"></p><p class=\"x\"></p><p class=\"y\"></p>\
+             <p class=\"x\" title=\"t\"></p><p class=\"x\"></p></div>"
+                .to_string(),
+        ));
+        let hovered = document.root.children[4].node_id;
+        let mut engine = StyleEngine::new(
+            vec![CSSParser::parse(".x { color: #ff0000; }".to_string())],
+            MediaContext::default(),
+        );
+        let styled = engine.restyle(&document, &[], &Hovered(hovered));
+
+        // Siblings with the same tag name, attributes, and state share the
+        // styles of the first of them.
+        let first = &styled.children[0];
+        let shared: Vec<bool> = styled.children[1..]
+            .iter()
+            .map(|sibling| {
+                Arc::ptr_eq(&sibling.specified_values, &first.specified_values)
+                    && Arc::ptr_eq(&sibling.style, &first.style)
+            })
+            .collect();
+        assert_eq!(shared, [true, false, false, false]);
+        // Styles that are equal are still computed for each element that
+        // doesn't share them.
+        assert_eq!(styled.children[3].style, first.style);
+    }
+
+    #[test]
+    fn test_nth_child() {
+        // Text and comments don't count as siblings.
+        let document = Document::new(crate::html_parser::HTMLParser::parse(
+            "<ul><li>a</li> <!-- b --><li>c</li><li>d</li></ul>".to_string(),
+        ));
+        let mut engine = StyleEngine::new(
+            vec![CSSParser::parse(
+                "li:nth-child(2) { color: #ff0000; }".to_string(),
+            )],
+            MediaContext::default(),
+        );
+        // Siblings that differ only in their position don't share styles.
+        let styled = engine.restyle(&document, &[], &StaticElementState);
+        let red = crate::css_parser::Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let colors: Vec<bool> = styled
+            .children
+            .iter()
+            .filter(|child| {
+                matches!(child.node.node_type, NodeType::Element(_))
+            })
+            .map(|child| child.style.color == red)
+            .collect();
+        assert_eq!(colors, [false, true, false]);
+        assert_eq!(
+            query_selector(&document.root, "li:nth-child(3)")
+                .map(Node::text_content),
+            Some("d".to_string())
+        );
+        assert!(query_selector(&document.root, "li:nth-child(4)").is_none());
+        assert!(query_selector(&document.root, "ul:nth-child(1)").is_some());
+    }
+
+    #[test]
+    fn test_style_attribute_precedence() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div id=\"a\" style=\"margin-top: 1px; margin-left: 2px; \
+             padding-top: 3px !important; font-weight: bold\"></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "#a { margin-top: 10px; margin-left: 20px !important; \
+             padding-top: 30px !important; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        assert_eq!(styled.style.margin.top, LengthOrAuto::Px(1.0));
+        assert_eq!(styled.style.margin.left, LengthOrAuto::Px(20.0));
+        assert_eq!(styled.style.padding.top, LengthPercentage::Px(3.0));
+        assert_eq!(styled.style.font_weight, 700);
+    }
+
+    #[test]
+    fn test_invalid_style_attribute_declarations_are_skipped() {
+        let parse = |source: &str| {
+            CSSParser::parse_declaration_list(source.to_string())
+                .into_iter()
+                .map(|declaration| {
+                    (declaration.name, declaration.value, declaration.important)
+                })
+                .collect::<Vec<_>>()
+        };
+        let width = |important| {
+            (
+                "width".to_string(),
+                Value::Length(10.0, Unit::Px),
+                important,
+            )
+        };
+        for source in [";;;", "width", "color: ", "width:10px !importantx"] {
+            assert_eq!(parse(source), []);
+        }
+        assert_eq!(
+            parse(
+                ";width: 10px;; color; margin: 1qq; \
+                 content: \"a;b\" !x; top: #zz; padding: calc(1px; 2px)"
+            ),
+            [width(false)]
+        );
+        assert_eq!(parse("height: ; width:10px; color: \"a"), [width(false)]);
+        assert_eq!(parse("width: 10px !important"), [width(true)]);
+    }
+
+    #[test]
+    fn test_revert_rolls_back_to_lower_origins() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><h1 style=\"margin-top: revert\"></h1>\
+             <p style=\"display: revert\"></p><p></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [
+            CSSParser::parse_with_origin(
+                "h1 { margin-top: 5px; } p { font-weight: bold; }".to_string(),
+                Origin::User,
+            ),
+            CSSParser::parse(
+                "h1 { margin-top: 10px; } p { display: inline; } \
+                 div { font-weight: revert; display: revert; }"
+                    .to_string(),
+            ),
+        ];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let (h1, p) = (&styled.children[0], &styled.children[1]);
+        // The user rule wins once the author rules are reverted.
+        assert_eq!(h1.style.margin.top, LengthOrAuto::Px(5.0));
+        // The user-agent rule wins once the author rules are reverted.
+        assert_eq!(p.display(), Display::Block);
+        assert_eq!(p.style.font_weight, 700);
+        assert_eq!(styled.children[2].display(), Display::Inline);
+        // Without any lower declaration, the property is unset.
+        assert_eq!(styled.style.font_weight, 400);
+        assert_eq!(styled.display(), Display::Block);
+    }
+
+    #[test]
+    fn test_quirks_mode_from_doctype() {
+        let source = |doctype: &str| {
+            format!("{}<html><div><table><p></p></table></div></html>", doctype)
+        };
+        let modes = [
+            ("<!DOCTYPE html>", QuirksMode::NoQuirks),
+            ("", QuirksMode::Quirks),
+            (
+                "<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">",
+                QuirksMode::Quirks,
+            ),
+            (
+                "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \
+                 \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">",
+                QuirksMode::LimitedQuirks,
+            ),
+        ];
+        for (doctype, quirks_mode) in modes {
+            let document =
+                crate::html_parser::HTMLParser::parse_document(source(doctype));
+            assert_eq!(document.quirks_mode, quirks_mode, "{}", doctype);
+        }
+
+        let stylesheets = [CSSParser::parse(
+            "div { width: 100; font-size: 20px; }".to_string(),
+        )];
+        let styled = |doctype: &str| {
+            let document =
+                crate::html_parser::HTMLParser::parse_document(source(doctype));
+            let styled = restyle(
+                &document,
+                &stylesheets,
+                &MediaContext::default(),
+                &mut StyleCache::default(),
+                &[],
+                &StaticElementState,
+            );
+            let div = &styled.children[0];
+            (div.style.width, div.children[0].style.font_size)
+        };
+        // Unitless lengths are ignored, and tables inherit the font size.
+        assert_eq!(styled("<!DOCTYPE html>"), (LengthOrAuto::Auto, 20.0));
+        // Unitless lengths are pixels, and tables reset the font size.
+        assert_eq!(styled(""), (LengthOrAuto::Px(100.0), DEFAULT_FONT_SIZE));
+    }
+
+    #[test]
+    fn test_device_pixel_ratio() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p></p><p class=\"thin\"></p><p class=\"none\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p { border: 1.5px solid; } .thin { border-width: 0.25px; } \
+             .none { border-width: 0; } \
+             @media (min-resolution: 2dppx) { div { width: 10px; } } \
+             @media (max-resolution: 96dpi) { div { width: 20px; } }"
+                .to_string(),
+        )];
+        let styled = |device_pixel_ratio: f32| {
+            let media = MediaContext {
+                device_pixel_ratio,
+                ..MediaContext::default()
+            };
+            let styled = apply(&root, &stylesheets, &media);
+            let widths = styled
+                .children
+                .iter()
+                .map(|child| child.style.border_width.top)
+                .collect::<Vec<_>>();
+            (styled.style.width, widths)
+        };
+        // Borders are rounded down to whole device pixels, but are at least
+        // one device pixel wide.
+        assert_eq!(styled(1.0), (LengthOrAuto::Px(20.0), vec![1.0, 1.0, 0.0]));
+        assert_eq!(styled(2.0), (LengthOrAuto::Px(10.0), vec![1.5, 0.5, 0.0]));
+        assert_eq!(
+            styled(1.5),
+            (LengthOrAuto::Auto, vec![4.0 / 3.0, 2.0 / 3.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn test_generated_content() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p>text</p><span></span><em></em></div>".to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "p::before { content: \"a\"; color: #ff0000; } \
+             p::after { content: 'b'; } \
+             span::before { content: none; } span::after { content: normal; } \
+             em::before { content: \"c\"; display: none; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+
+        // The content strings become text in the pseudo-elements, which
+        // are the first and the last children, and inherit their styles.
+        let p = &styled.children[0];
+        let generated: Vec<_> = p
+            .children
+            .iter()
+            .map(|child| {
+                let text = child.children.first();
+                (
+                    child.pseudo_element,
+                    text.and_then(|text| text.generated_text.as_deref()),
+                    text.map(|text| text.style.color),
+                )
+            })
+            .collect();
+        let red = crate::css_parser::Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(
+            generated,
+            [
+                (Some(PseudoElement::Before), Some("a"), Some(red)),
+                (None, None, None),
+                (Some(PseudoElement::After), Some("b"), Some(p.style.color)),
+            ]
+        );
+        assert_eq!(p.children[0].style.color, red);
+
+        // `content: none` and `content: normal` generate nothing, and
+        // neither do pseudo-elements that are not displayed.
+        assert!(styled.children[1].children.is_empty());
+        assert!(styled.children[2].children.is_empty());
+    }
+
+    #[test]
+    fn test_dump_styled_tree() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div class=\"note\" style=\"color: #ff0000\"><p>Hi</p>\
+             <span></span></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            ".note { padding: 1em; } span { z-index: 2; } \
+             span::before { content: \"> \"; width: 50%; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let expected = "\
+div { color: #ff0000; display: block; padding-bottom: 1em; padding-left: 1em; \
+padding-right: 1em; padding-top: 1em; }
+  p { display: block; margin-bottom: 16px; margin-top: 16px; }
+    \"Hi\"
+  span { z-index: 2; }
+    ::before { content: \"> \"; width: 50%; }
+      \"> \"
+";
+        assert_eq!(styled.dump(), expected);
+    }
+
+    #[test]
+    fn test_cascade_origins_and_importance() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div id=\"a\"></div>".to_string(),
+        );
+        // The sheets are given in the reverse order of their origins, which
+        // must not matter.
+        let stylesheets = [
+            CSSParser::parse(
+                "div { margin-top: 3px; margin-bottom: 3px !important; \
+                 margin-left: 3px !important; padding-top: 3px; \
+                 padding-left: 3px; }"
+                    .to_string(),
+            ),
+            CSSParser::parse_with_origin(
+                "#a { margin-top: 2px; margin-right: 2px; \
+                 margin-bottom: 2px !important; margin-left: 2px !important; \
+                 padding-left: 2px; }"
+                    .to_string(),
+                Origin::User,
+            ),
+            CSSParser::parse_with_origin(
+                "#a { margin-top: 1px; margin-right: 1px; \
+                 margin-bottom: 1px !important; padding-top: 1px !important; }"
+                    .to_string(),
+                Origin::UserAgent,
+            ),
+        ];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let style = &styled.style;
+        // Normal declarations: author over user over user agent.
+        assert_eq!(style.margin.top, LengthOrAuto::Px(3.0));
+        assert_eq!(style.margin.right, LengthOrAuto::Px(2.0));
+        // Important declarations: user agent over user over author.
+        assert_eq!(style.margin.bottom, LengthOrAuto::Px(1.0));
+        assert_eq!(style.margin.left, LengthOrAuto::Px(2.0));
+        // An important declaration wins over a normal one of any origin.
+        assert_eq!(style.padding.top, LengthPercentage::Px(1.0));
+        // Specificity only decides between declarations of the same origin.
+        assert_eq!(style.padding.left, LengthPercentage::Px(3.0));
+    }
+
+    #[test]
+    fn test_source_order_breaks_specificity_ties() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div id=\"b\" class=\"a b\"></div>".to_string(),
+        );
+        // Enough rules of equal specificity that an unstable sort would
+        // reorder them.
+        let widths: String = (0..40)
+            .map(|width| format!(".a {{ width: {}px; }} ", width))
+            .collect();
+        let stylesheets = [
+            CSSParser::parse(format!(
+                "{} .a {{ margin-top: 1px; margin-left: 1px; }} \
+                 #b {{ margin-right: 1px; }} \
+                 .a {{ margin-bottom: 1px !important; }} \
+                 .a {{ padding-top: 1px; }} .b {{ padding-top: 2px; }} \
+                 .b {{ padding-left: 2px; }} .a {{ padding-left: 1px; }}",
+                widths
+            )),
+            CSSParser::parse(
+                ".a { margin-top: 2px; } .b { margin-right: 2px; } \
+                 .b { margin-bottom: 2px !important; }"
+                    .to_string(),
+            ),
+        ];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let style = &styled.style;
+        assert_eq!(style.width, LengthOrAuto::Px(39.0));
+        // A later sheet wins over an earlier one, for important declarations
+        // too, but not over a more specific selector.
+        assert_eq!(style.margin.top, LengthOrAuto::Px(2.0));
+        assert_eq!(style.margin.bottom, LengthOrAuto::Px(2.0));
+        assert_eq!(style.margin.right, LengthOrAuto::Px(1.0));
+        assert_eq!(style.margin.left, LengthOrAuto::Px(1.0));
+        // A later rule wins over an earlier one in the same sheet.
+        assert_eq!(style.padding.top, LengthPercentage::Px(2.0));
+        assert_eq!(style.padding.left, LengthPercentage::Px(1.0));
+    }
+
+    #[test]
+    fn test_relative_font_sizes() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p><span class=\"a\"></span><span class=\"b\"></span></p>\
+             <span class=\"c\"></span></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 1.25rem; line-height: 1.5; } \
+             p { font-size: 2em; line-height: 150%; } \
+             .a { font-size: 50%; } \
+             .b { font-size: 0.5rem; margin-left: 1em; padding-left: 1rem; } \
+             .c { font-size: 1rem; }"
+                .to_string(),
+        )];
+        let media = MediaContext {
+            root_font_size: 16.0,
+            ..MediaContext::default()
+        };
+        let styled = apply(&root, &stylesheets, &media);
+        let p = &styled.children[0];
+        let (a, b, c) = (&p.children[0], &p.children[1], &styled.children[1]);
+        // `rem` lengths of the root element are relative to the initial font
+        // size, and those of other elements to the root element's.
+        assert_eq!(styled.style.font_size, 20.0);
+        assert_eq!(styled.style.line_height, 30.0);
+        assert_eq!(p.style.font_size, 40.0);
+        assert_eq!(p.style.line_height, 60.0);
+        assert_eq!(a.style.font_size, 20.0);
+        assert_eq!(b.style.font_size, 10.0);
+        assert_eq!(b.style.margin.left, LengthOrAuto::Px(10.0));
+        assert_eq!(b.style.padding.left, LengthPercentage::Px(20.0));
+        assert_eq!(c.style.font_size, 20.0);
+        // A line height given as a number is inherited as a factor, and one
+        // given as a length or a percentage as the absolute length.
+        assert_eq!(a.style.line_height, 60.0);
+        assert_eq!(b.style.line_height, 60.0);
+        assert_eq!(c.style.line_height, 30.0);
+    }
+
+    #[test]
+    fn test_current_color() {
+        let root = crate::html_parser::HTMLParser::parse(
+            "<div><p class=\"a\"></p><p class=\"b\"></p></div>".to_string(),
+        );
+        // The color is declared after the properties that refer to it.
+        let stylesheets = [CSSParser::parse(
+            "div { border-top-color: currentColor; \
+             background-color: currentcolor; color: #ff0000; } \
+             .a { color: #0000ff; } \
+             .b { color: #00ff00; border-color: CURRENTCOLOR; \
+             background-color: currentColor; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let (a, b) = (&styled.children[0], &styled.children[1]);
+        let color = |r, g, b| crate::css_parser::Color { r, g, b, a: 255 };
+        assert_eq!(styled.style.border_color.top, color(255, 0, 0));
+        assert_eq!(styled.style.background_color, color(255, 0, 0));
+        // The initial border color is the element's own color, not the
+        // parent's, and the background isn't inherited.
+        assert_eq!(a.style.border_color.left, color(0, 0, 255));
+        assert_ne!(a.style.background_color, color(255, 0, 0));
+        assert_eq!(b.style.border_color.right, color(0, 255, 0));
+        assert_eq!(b.style.background_color, color(0, 255, 0));
+    }
 }
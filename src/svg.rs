@@ -0,0 +1,828 @@
+// SVG images are drawn from a subset of SVG: paths, rectangles, circles,
+// ellipses, lines, polylines, and polygons, filled and stroked with solid
+// colors, in groups that pass their fills and strokes on to their shapes.
+// The view box of an image is scaled to fit where it is drawn. Shapes are
+// flattened into polygons, which backends fill and stroke.
+// SEE: https://www.w3.org/TR/SVG2/
+
+// TODO: The following features are not implemented:
+// - Transforms, opacity, gradients, patterns, text, and other elements
+// - Arcs in path data
+// - The `style` attribute, stylesheets, and units other than pixels
+// - Stroke joins other than round, caps other than butt, and dashes
+// - Values of `preserveAspectRatio` other than `xMidYMid meet`
+
+use crate::{
+    css_parser::Color,
+    dom::{Node, NodeType},
+    html_parser::HTMLParser,
+    layout::{Rectangle, Transform},
+    presentational::parse_legacy_color,
+};
+
+// The number of lines that a Bézier curve is flattened into.
+const CURVE_SEGMENTS: usize = 16;
+
+// The distance of the control points of a cubic Bézier curve that draws a
+// quarter of a circle with a radius of one from its ends.
+const KAPPA: f32 = 0.552_284_8;
+
+// An SVG image, with the shapes that it draws in the order that they are
+// painted.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Svg {
+    // The size of the image from the `width` and `height` attributes of its
+    // root, if they are in pixels.
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    // The area of the coordinates of the shapes that is fit into where the
+    // image is drawn.
+    pub view_box: Option<Rectangle>,
+    pub shapes: Vec<Shape>,
+}
+
+// A shape of an image, with how it is filled and stroked.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Shape {
+    pub path: Path,
+    pub fill: Option<Color>,
+    pub fill_rule: FillRule,
+    pub stroke: Option<Color>,
+    pub stroke_width: f32,
+}
+
+// How the inside of a path that crosses itself is decided.
+// SEE: https://www.w3.org/TR/SVG2/painting.html#FillRuleProperty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FillRule {
+    // Inside is where the path winds around a point at least once, counting
+    // the opposite way negatively.
+    NonZero,
+    // Inside is where a ray from a point crosses the path an odd number of
+    // times.
+    EvenOdd,
+}
+
+// A path whose curves are flattened into lines.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Path {
+    pub subpaths: Vec<Subpath>,
+}
+
+// The points of a part of a path that is drawn without lifting the pen.
+// Open subpaths are closed when they are filled, but not when they are
+// stroked.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Subpath {
+    pub points: Vec<(f32, f32)>,
+    pub closed: bool,
+}
+
+impl Svg {
+    // Parse the source of an SVG file, unless it has no `svg` element.
+    pub fn parse(source: &str) -> Option<Self> {
+        // The XML declaration is skipped, which the HTML parser doesn't
+        // know.
+        let mut source = source.trim_start();
+        if source.starts_with("<?") {
+            let end = source.find("?>")?;
+            source = &source[end + 2..];
+        }
+        let root = HTMLParser::parse(source.to_string());
+        if is_element(&root, "svg") {
+            return Self::from_node(&root);
+        }
+        root.children
+            .iter()
+            .find(|child| is_element(child, "svg"))
+            .and_then(Self::from_node)
+    }
+
+    // Read an image from an `svg` element and its descendants.
+    pub fn from_node(node: &Node) -> Option<Self> {
+        let NodeType::Element(element) = &node.node_type else {
+            return None;
+        };
+        if !element.tag_name.eq_ignore_ascii_case("svg") {
+            return None;
+        }
+        let attribute = |name| element.attributes.get(name);
+        let view_box = attribute("viewBox")
+            .map(|value| numbers(value))
+            .and_then(|numbers| match numbers[..] {
+                [x, y, width, height] if width > 0.0 && height > 0.0 => {
+                    Some(Rectangle {
+                        x,
+                        y,
+                        width,
+                        height,
+                    })
+                }
+                _ => None,
+            });
+        let mut shapes = Vec::new();
+        collect_shapes(node, &Paint::default(), &mut shapes);
+        Some(Self {
+            width: attribute("width").and_then(|value| length(value)),
+            height: attribute("height").and_then(|value| length(value)),
+            view_box,
+            shapes,
+        })
+    }
+
+    // Get the size that the image is drawn at unless it is sized otherwise,
+    // with a missing dimension from the aspect ratio of the view box.
+    // SEE: https://www.w3.org/TR/SVG2/coords.html#SizingSVGInCSS
+    pub fn natural_size(&self) -> Option<(f32, f32)> {
+        let ratio = self.view_box.map(|rect| rect.width / rect.height);
+        match (self.width, self.height, ratio) {
+            (Some(width), Some(height), _) => Some((width, height)),
+            (Some(width), None, Some(ratio)) => Some((width, width / ratio)),
+            (None, Some(height), Some(ratio)) => Some((height * ratio, height)),
+            _ => None,
+        }
+    }
+
+    // Get the transform from the coordinates of the shapes to a rectangle
+    // that the image is drawn in, which scales the view box uniformly to
+    // fit, and centers it.
+    // SEE: https://www.w3.org/TR/SVG2/coords.html#ComputingAViewportsTransform
+    pub fn transform_to(&self, rect: Rectangle) -> Transform {
+        let Some(view_box) = self.view_box else {
+            return Transform::translate(rect.x, rect.y);
+        };
+        let scale =
+            (rect.width / view_box.width).min(rect.height / view_box.height);
+        Transform {
+            a: scale,
+            b: 0.0,
+            c: 0.0,
+            d: scale,
+            e: rect.x + (rect.width - view_box.width * scale) / 2.0
+                - view_box.x * scale,
+            f: rect.y + (rect.height - view_box.height * scale) / 2.0
+                - view_box.y * scale,
+        }
+    }
+}
+
+impl Path {
+    // Get the smallest rectangle that contains the points of the path.
+    pub fn bounding_rect(&self) -> Rectangle {
+        let points = self.subpaths.iter().flat_map(|subpath| &subpath.points);
+        let (mut x0, mut y0) = (f32::INFINITY, f32::INFINITY);
+        let (mut x1, mut y1) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for &(x, y) in points {
+            (x0, y0) = (x0.min(x), y0.min(y));
+            (x1, y1) = (x1.max(x), y1.max(y));
+        }
+        if x0 > x1 {
+            return Rectangle::default();
+        }
+        Rectangle {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+
+    // Check if a point is inside the path by a fill rule, with each subpath
+    // closed.
+    // SEE: https://www.w3.org/TR/SVG2/painting.html#WindingRule
+    pub fn contains(&self, (x, y): (f32, f32), fill_rule: FillRule) -> bool {
+        let mut winding = 0;
+        for subpath in &self.subpaths {
+            let points = &subpath.points;
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                // The edge crosses the ray to the right of the point when
+                // its ends are on different sides of it.
+                let side = (x1 - x0) * (y - y0) - (y1 - y0) * (x - x0);
+                if y0 <= y && y1 > y && side > 0.0 {
+                    winding += 1;
+                } else if y1 <= y && y0 > y && side < 0.0 {
+                    winding -= 1;
+                }
+            }
+        }
+        match fill_rule {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+
+    // Check if a point is within half of a stroke width from the path.
+    // Strokes are joined round, and open subpaths end where they do.
+    pub fn stroke_contains(&self, point: (f32, f32), width: f32) -> bool {
+        let half = width / 2.0;
+        self.subpaths.iter().any(|subpath| {
+            let points = &subpath.points;
+            let segments = if subpath.closed {
+                points.len()
+            } else {
+                points.len().saturating_sub(1)
+            };
+            (0..segments).any(|i| {
+                let (x0, y0) = points[i];
+                let (x1, y1) = points[(i + 1) % points.len()];
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length = dx * dx + dy * dy;
+                let t = if length > 0.0 {
+                    ((point.0 - x0) * dx + (point.1 - y0) * dy) / length
+                } else {
+                    0.0
+                };
+                // Past the ends of an open subpath is outside of the
+                // stroke.
+                if !subpath.closed
+                    && (i == 0 && t < 0.0 || i == segments - 1 && t > 1.0)
+                {
+                    return false;
+                }
+                let t = t.clamp(0.0, 1.0);
+                let (x, y) = (x0 + dx * t - point.0, y0 + dy * t - point.1);
+                x * x + y * y <= half * half
+            })
+        })
+    }
+}
+
+// The fills and strokes that groups pass on to their descendants.
+#[derive(Debug, Clone, Copy)]
+struct Paint {
+    fill: Option<Color>,
+    fill_rule: FillRule,
+    stroke: Option<Color>,
+    stroke_width: f32,
+}
+
+impl Default for Paint {
+    fn default() -> Self {
+        Self {
+            fill: Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            }),
+            fill_rule: FillRule::NonZero,
+            stroke: None,
+            stroke_width: 1.0,
+        }
+    }
+}
+
+// Collect the shapes of the children of an element, with the paint that
+// they inherit from it.
+fn collect_shapes(node: &Node, inherited: &Paint, shapes: &mut Vec<Shape>) {
+    for child in &node.children {
+        let NodeType::Element(element) = &child.node_type else {
+            continue;
+        };
+        let attribute = |name| element.attributes.get(name).map(String::as_str);
+        let number = |name| attribute(name).and_then(length).unwrap_or(0.0);
+        let paint_value = |name, inherited| match attribute(name) {
+            Some("none") => None,
+            Some(value) => parse_legacy_color(value).or(inherited),
+            None => inherited,
+        };
+        let paint = Paint {
+            fill: paint_value("fill", inherited.fill),
+            fill_rule: match attribute("fill-rule") {
+                Some("evenodd") => FillRule::EvenOdd,
+                Some("nonzero") => FillRule::NonZero,
+                _ => inherited.fill_rule,
+            },
+            stroke: paint_value("stroke", inherited.stroke),
+            stroke_width: attribute("stroke-width")
+                .and_then(length)
+                .unwrap_or(inherited.stroke_width),
+        };
+
+        let path = match element.tag_name.to_ascii_lowercase().as_str() {
+            "g" => {
+                collect_shapes(child, &paint, shapes);
+                continue;
+            }
+            "path" => parse_path_data(attribute("d").unwrap_or_default()),
+            "rect" => {
+                let (width, height) = (number("width"), number("height"));
+                // A missing radius is the other one.
+                let rx = attribute("rx").and_then(length);
+                let ry = attribute("ry").and_then(length);
+                let (rx, ry) = match (rx, ry) {
+                    (Some(rx), Some(ry)) => (rx, ry),
+                    (Some(radius), None) | (None, Some(radius)) => {
+                        (radius, radius)
+                    }
+                    (None, None) => (0.0, 0.0),
+                };
+                rect_path(
+                    Rectangle {
+                        x: number("x"),
+                        y: number("y"),
+                        width,
+                        height,
+                    },
+                    rx.min(width / 2.0),
+                    ry.min(height / 2.0),
+                )
+            }
+            "circle" => {
+                let r = number("r");
+                ellipse_path((number("cx"), number("cy")), r, r)
+            }
+            "ellipse" => ellipse_path(
+                (number("cx"), number("cy")),
+                number("rx"),
+                number("ry"),
+            ),
+            "line" => Path {
+                subpaths: vec![Subpath {
+                    points: vec![
+                        (number("x1"), number("y1")),
+                        (number("x2"), number("y2")),
+                    ],
+                    closed: false,
+                }],
+            },
+            tag @ ("polyline" | "polygon") => {
+                let numbers = numbers(attribute("points").unwrap_or_default());
+                Path {
+                    subpaths: vec![Subpath {
+                        points: numbers
+                            .chunks_exact(2)
+                            .map(|pair| (pair[0], pair[1]))
+                            .collect(),
+                        closed: tag == "polygon",
+                    }],
+                }
+            }
+            _ => continue,
+        };
+        if !path.subpaths.is_empty() {
+            shapes.push(Shape {
+                path,
+                fill: paint.fill,
+                fill_rule: paint.fill_rule,
+                stroke: paint.stroke,
+                stroke_width: paint.stroke_width,
+            });
+        }
+    }
+}
+
+fn is_element(node: &Node, tag_name: &str) -> bool {
+    matches!(
+        &node.node_type,
+        NodeType::Element(element) if element.tag_name.eq_ignore_ascii_case(tag_name)
+    )
+}
+
+// Parse a length in pixels, with or without the unit.
+fn length(value: &str) -> Option<f32> {
+    let value = value.trim();
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .parse()
+        .ok()
+        .filter(|n: &f32| n.is_finite())
+}
+
+// Parse a list of numbers separated by whitespace or commas.
+fn numbers(value: &str) -> Vec<f32> {
+    let mut parser = PathParser::new(value);
+    std::iter::from_fn(|| parser.number()).collect()
+}
+
+// Get the path of a rectangle, with its corners rounded by radii.
+fn rect_path(rect: Rectangle, rx: f32, ry: f32) -> Path {
+    let Rectangle {
+        x,
+        y,
+        width,
+        height,
+    } = rect;
+    if width <= 0.0 || height <= 0.0 {
+        return Path::default();
+    }
+    let mut builder = PathBuilder::default();
+    builder.move_to((x + rx, y));
+    builder.line_to((x + width - rx, y));
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    // The corners are drawn clockwise from the top right one, each from
+    // the end of one side to the start of the next.
+    let corners = [
+        ((x + width - rx, y), (x + width, y + ry)),
+        ((x + width, y + height - ry), (x + width - rx, y + height)),
+        ((x + rx, y + height), (x, y + height - ry)),
+        ((x, y + ry), (x + rx, y)),
+    ];
+    for (i, (start, end)) in corners.into_iter().enumerate() {
+        if i > 0 {
+            builder.line_to(start);
+        }
+        if rx > 0.0 && ry > 0.0 {
+            // The control points are on the tangents at the ends, which
+            // are along the sides.
+            let (c1, c2) = match i {
+                0 => ((start.0 + kx, start.1), (end.0, end.1 - ky)),
+                1 => ((start.0, start.1 + ky), (end.0 + kx, end.1)),
+                2 => ((start.0 - kx, start.1), (end.0, end.1 + ky)),
+                _ => ((start.0, start.1 - ky), (end.0 - kx, end.1)),
+            };
+            builder.cubic_to(c1, c2, end);
+        }
+    }
+    builder.close();
+    builder.finish()
+}
+
+// Get the path of an ellipse, as four cubic Bézier curves.
+fn ellipse_path((cx, cy): (f32, f32), rx: f32, ry: f32) -> Path {
+    if rx <= 0.0 || ry <= 0.0 {
+        return Path::default();
+    }
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let mut builder = PathBuilder::default();
+    builder.move_to((cx + rx, cy));
+    builder.cubic_to((cx + rx, cy + ky), (cx + kx, cy + ry), (cx, cy + ry));
+    builder.cubic_to((cx - kx, cy + ry), (cx - rx, cy + ky), (cx - rx, cy));
+    builder.cubic_to((cx - rx, cy - ky), (cx - kx, cy - ry), (cx, cy - ry));
+    builder.cubic_to((cx + kx, cy - ry), (cx + rx, cy - ky), (cx + rx, cy));
+    builder.close();
+    builder.finish()
+}
+
+// Builds a path from pen movements, flattening curves as they are added.
+#[derive(Debug, Default)]
+struct PathBuilder {
+    subpaths: Vec<Subpath>,
+    current: (f32, f32),
+}
+
+impl PathBuilder {
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.subpaths.push(Subpath {
+            points: vec![point],
+            closed: false,
+        });
+        self.current = point;
+    }
+
+    fn line_to(&mut self, point: (f32, f32)) {
+        if self.subpaths.last().is_none_or(|subpath| subpath.closed) {
+            self.move_to(self.current);
+        }
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.points.push(point);
+        }
+        self.current = point;
+    }
+
+    fn cubic_to(&mut self, c1: (f32, f32), c2: (f32, f32), end: (f32, f32)) {
+        let start = self.current;
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            let point = |axis: fn((f32, f32)) -> f32| {
+                u * u * u * axis(start)
+                    + 3.0 * u * u * t * axis(c1)
+                    + 3.0 * u * t * t * axis(c2)
+                    + t * t * t * axis(end)
+            };
+            self.line_to((point(|p| p.0), point(|p| p.1)));
+        }
+    }
+
+    fn quad_to(&mut self, control: (f32, f32), end: (f32, f32)) {
+        // A quadratic curve is the cubic one with its control points two
+        // thirds of the way to the control point.
+        let start = self.current;
+        let toward = |from: (f32, f32)| {
+            (
+                from.0 + (control.0 - from.0) * 2.0 / 3.0,
+                from.1 + (control.1 - from.1) * 2.0 / 3.0,
+            )
+        };
+        self.cubic_to(toward(start), toward(end), end);
+    }
+
+    // Close the current subpath, after which the pen is back at its start.
+    fn close(&mut self) {
+        if let Some(subpath) = self.subpaths.last_mut() {
+            subpath.closed = true;
+            self.current = subpath.points[0];
+        }
+    }
+
+    fn finish(self) -> Path {
+        Path {
+            subpaths: self
+                .subpaths
+                .into_iter()
+                .filter(|subpath| subpath.points.len() > 1)
+                .collect(),
+        }
+    }
+}
+
+// Parse the `d` attribute of a path into its path. The path ends at the
+// first error, as far as it was drawn.
+// SEE: https://www.w3.org/TR/SVG2/paths.html#PathDataBNF
+fn parse_path_data(data: &str) -> Path {
+    let mut parser = PathParser::new(data);
+    let mut builder = PathBuilder::default();
+    // The second control point of the previous curve, which the smooth
+    // curves reflect.
+    let mut last_cubic = None;
+    let mut last_quad = None;
+    let mut command = None;
+    loop {
+        parser.skip_separators();
+        let Some(next) = parser.peek() else {
+            break;
+        };
+        if next.is_ascii_alphabetic() {
+            parser.pos += 1;
+            command = Some(next);
+        } else if command.is_none() {
+            break;
+        }
+        let Some(current) = command else {
+            break;
+        };
+        let relative = current.is_ascii_lowercase();
+        let (x0, y0) = builder.current;
+        let offset = |(x, y): (f32, f32)| {
+            if relative { (x + x0, y + y0) } else { (x, y) }
+        };
+        let (cubic, quad) = (last_cubic.take(), last_quad.take());
+        // Reflect a control point of the previous curve through the
+        // current point, or take the current point without one.
+        let reflect = |control: Option<(f32, f32)>| {
+            control.map_or((x0, y0), |(x, y)| (2.0 * x0 - x, 2.0 * y0 - y))
+        };
+        match current.to_ascii_uppercase() {
+            'M' => {
+                let Some(point) = parser.point() else { break };
+                builder.move_to(offset(point));
+                // Pairs after the first one draw lines.
+                command = Some(if relative { 'l' } else { 'L' });
+            }
+            'L' => {
+                let Some(point) = parser.point() else { break };
+                builder.line_to(offset(point));
+            }
+            'H' => {
+                let Some(x) = parser.number() else { break };
+                builder.line_to((if relative { x + x0 } else { x }, y0));
+            }
+            'V' => {
+                let Some(y) = parser.number() else { break };
+                builder.line_to((x0, if relative { y + y0 } else { y }));
+            }
+            'C' => {
+                let (Some(c1), Some(c2), Some(end)) =
+                    (parser.point(), parser.point(), parser.point())
+                else {
+                    break;
+                };
+                let c2 = offset(c2);
+                builder.cubic_to(offset(c1), c2, offset(end));
+                last_cubic = Some(c2);
+            }
+            'S' => {
+                let (Some(c2), Some(end)) = (parser.point(), parser.point())
+                else {
+                    break;
+                };
+                let c2 = offset(c2);
+                builder.cubic_to(reflect(cubic), c2, offset(end));
+                last_cubic = Some(c2);
+            }
+            'Q' => {
+                let (Some(control), Some(end)) =
+                    (parser.point(), parser.point())
+                else {
+                    break;
+                };
+                let control = offset(control);
+                builder.quad_to(control, offset(end));
+                last_quad = Some(control);
+            }
+            'T' => {
+                let Some(end) = parser.point() else { break };
+                let control = reflect(quad);
+                builder.quad_to(control, offset(end));
+                last_quad = Some(control);
+            }
+            'Z' => {
+                builder.close();
+                command = None;
+            }
+            _ => break,
+        }
+    }
+    builder.finish()
+}
+
+// Reads the numbers of path data and lists of points, which may be
+// separated by whitespace, a comma, or only the sign of the next number.
+struct PathParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PathParser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.pos).map(|&byte| byte as char)
+    }
+
+    fn skip_separators(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_whitespace() || c == ',')
+        {
+            self.pos += 1;
+        }
+    }
+
+    // Parse a number, such as `-1.5e3` or `.5`.
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let start = parser.pos;
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+        if matches!(self.peek(), Some('+' | '-')) {
+            self.pos += 1;
+        }
+        let mut has_digits = digits(self);
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            has_digits |= digits(self);
+        }
+        if !has_digits {
+            self.pos = start;
+            return None;
+        }
+        // An exponent only belongs to the number if it has digits.
+        let mantissa_end = self.pos;
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                self.pos = mantissa_end;
+            }
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn point(&mut self) -> Option<(f32, f32)> {
+        Some((self.number()?, self.number()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_data() {
+        let path = parse_path_data("M10,10 l20-0 v.5e1 H10z m5 5 30 0");
+        assert_eq!(
+            path.subpaths,
+            [
+                Subpath {
+                    points: vec![
+                        (10.0, 10.0),
+                        (30.0, 10.0),
+                        (30.0, 15.0),
+                        (10.0, 15.0)
+                    ],
+                    closed: true,
+                },
+                Subpath {
+                    points: vec![(15.0, 15.0), (45.0, 15.0)],
+                    closed: false,
+                },
+            ]
+        );
+        // Curves are flattened, and end where they are asked to.
+        let path =
+            parse_path_data("M0 0 Q 10 10 20 0 T 40 0 C 40 10 50 10 50 0");
+        let points = &path.subpaths[0].points;
+        assert_eq!(points.len(), 1 + 3 * CURVE_SEGMENTS);
+        assert_eq!(points[CURVE_SEGMENTS], (20.0, 0.0));
+        assert_eq!(points.last(), Some(&(50.0, 0.0)));
+        // The smooth curve bends the other way.
+        assert!(points[CURVE_SEGMENTS / 2].1 > 4.0);
+        assert!(points[CURVE_SEGMENTS * 3 / 2].1 < -4.0);
+        // The path ends at the first error.
+        let path = parse_path_data("M 0 0 L 10 10 A 1 1 0 0 0 5 5 L 0 10");
+        assert_eq!(path.subpaths[0].points, [(0.0, 0.0), (10.0, 10.0)]);
+    }
+
+    #[test]
+    fn test_fill_and_stroke() {
+        // A square with a square hole, drawn the same way around.
+        let path = parse_path_data("M0 0 H30 V30 H0 Z M10 10 H20 V20 H10 Z");
+        assert!(path.contains((15.0, 15.0), FillRule::NonZero));
+        assert!(!path.contains((15.0, 15.0), FillRule::EvenOdd));
+        assert!(path.contains((5.0, 15.0), FillRule::EvenOdd));
+        assert!(!path.contains((35.0, 15.0), FillRule::NonZero));
+
+        // Open ends are cut off square, and joins are round.
+        let path = parse_path_data("M0 0 H10 V10");
+        assert!(path.stroke_contains((5.0, 1.0), 2.0));
+        assert!(!path.stroke_contains((-0.5, 0.0), 2.0));
+        assert!(path.stroke_contains((10.5, -0.5), 2.0));
+        assert!(!path.stroke_contains((10.0, 10.5), 2.0));
+        assert!(!path.stroke_contains((5.0, 5.0), 2.0));
+    }
+
+    #[test]
+    fn test_parse_svg() {
+        let svg = Svg::parse(
+            "<?xml version=\"1.0\"?>\n\
+             <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" \
+             viewBox=\"0 0 20 10\">\
+             <g fill=\"red\" stroke=\"#00f\" stroke-width=\"2\">\
+             <rect x=\"1\" y=\"2\" width=\"4\" height=\"3\"/>\
+             <circle cx=\"10\" cy=\"5\" r=\"2\" fill=\"none\"></circle>\
+             </g><polygon points=\"0,0 1,0 1,1\"/><text>ignored</text></svg>",
+        )
+        .unwrap();
+        // The height is from the aspect ratio of the view box, which is
+        // fit into where the image is drawn.
+        assert_eq!(svg.natural_size(), Some((100.0, 50.0)));
+        let rect = Rectangle {
+            x: 10.0,
+            y: 0.0,
+            width: 100.0,
+            height: 100.0,
+        };
+        assert_eq!(svg.transform_to(rect).apply((20.0, 10.0)), (110.0, 75.0));
+
+        let red = Color {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let blue = Color {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        let paints: Vec<_> = svg
+            .shapes
+            .iter()
+            .map(|shape| (shape.fill, shape.stroke, shape.stroke_width))
+            .collect();
+        assert_eq!(
+            paints,
+            [
+                (Some(red), Some(blue), 2.0),
+                (None, Some(blue), 2.0),
+                (Some(Paint::default().fill.unwrap()), None, 1.0),
+            ]
+        );
+        assert_eq!(
+            svg.shapes[0].path.bounding_rect(),
+            Rectangle {
+                x: 1.0,
+                y: 2.0,
+                width: 4.0,
+                height: 3.0
+            }
+        );
+        let circle = svg.shapes[1].path.bounding_rect();
+        assert_eq!((circle.x, circle.width), (8.0, 4.0));
+    }
+}
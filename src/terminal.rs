@@ -0,0 +1,321 @@
+// The terminal renderer draws a page with the characters of a terminal,
+// so that it can be looked at without a window or an image viewer. Each
+// cell shows two pixels of the page, one above the other, as an upper half
+// block in the color of the top pixel over the color of the bottom one.
+// Text is drawn as characters, one per cell, over the colors of its cells.
+// SEE: https://en.wikipedia.org/wiki/ANSI_escape_code#24-bit
+
+use std::fmt::Write;
+
+use crate::{
+    computed::Sides,
+    css_parser::Color,
+    layout::{EdgeSizes, LayoutBox, Rectangle, RoundedRect, Transform},
+    painting::{
+        Bitmap,
+        Canvas,
+        CanvasBackend,
+        ImageSource,
+        Images,
+        PaintBackend,
+        TextRun,
+        device_size,
+        paint_with_backend,
+    },
+    svg::{FillRule, Path},
+};
+
+// The width of a cell in CSS pixels, which is the advance of a character
+// of 16px monospace text, and cells are twice as tall, which is the normal
+// line height of that text. Each pixel of the terminal is a square half of
+// a cell.
+pub const CELL_WIDTH: f32 = 9.6;
+
+// A grid of cells that display lists are drawn into.
+#[derive(Debug, Clone)]
+pub struct TerminalCanvas {
+    // The pixels of the cells, two rows of them for each row of cells.
+    pub canvas: Canvas,
+    // The character drawn in each cell row by row, with its color.
+    pub text: Vec<Option<(char, Color)>>,
+    pub columns: usize,
+    pub rows: usize,
+    // The transforms in effect after each push, from the outermost one.
+    transforms: Vec<Transform>,
+}
+
+impl TerminalCanvas {
+    pub fn new(columns: usize, rows: usize) -> Self {
+        Self {
+            canvas: Canvas::new(columns, rows * 2),
+            text: vec![None; columns * rows],
+            columns,
+            rows,
+            transforms: Vec::new(),
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        self.transforms
+            .last()
+            .copied()
+            .unwrap_or(Transform::IDENTITY)
+    }
+
+    fn backend(&mut self) -> CanvasBackend<'_> {
+        CanvasBackend::new(&mut self.canvas)
+    }
+
+    // Widen a length that is thinner than a pixel to a pixel, so that
+    // borders and decoration lines don't vanish at the size of cells.
+    // `scale` is how many pixels a CSS pixel covers.
+    fn at_least_a_pixel(length: f32, scale: f32) -> f32 {
+        if length > 0.0 && length * scale < 1.0 {
+            1.0 / scale
+        } else {
+            length
+        }
+    }
+
+    // Write the cells as lines of text with ANSI escape codes, which set
+    // the 24-bit colors of each cell when they change.
+    pub fn to_ansi(&self) -> String {
+        let mut output = String::new();
+        let width = self.canvas.width;
+        for row in 0..self.rows {
+            let mut colors = None;
+            for column in 0..self.columns {
+                let top = self.canvas.pixels[row * 2 * width + column];
+                let bottom = self.canvas.pixels[(row * 2 + 1) * width + column];
+                let (c, foreground, background) =
+                    match self.text[row * self.columns + column] {
+                        Some((c, color)) => (c, color, average(top, bottom)),
+                        None => ('\u{2580}', top, bottom),
+                    };
+                if colors != Some((foreground, background)) {
+                    colors = Some((foreground, background));
+                    let Color { r, g, b, .. } = foreground;
+                    write!(output, "\x1b[38;2;{};{};{}m", r, g, b).unwrap();
+                    let Color { r, g, b, .. } = background;
+                    write!(output, "\x1b[48;2;{};{};{}m", r, g, b).unwrap();
+                }
+                output.push(c);
+            }
+            output.push_str("\x1b[0m\n");
+        }
+        output
+    }
+}
+
+// Mix the colors of the two pixels of a cell, which text is drawn over.
+fn average(top: Color, bottom: Color) -> Color {
+    let mix = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    Color {
+        r: mix(top.r, bottom.r),
+        g: mix(top.g, bottom.g),
+        b: mix(top.b, bottom.b),
+        a: 255,
+    }
+}
+
+impl PaintBackend for TerminalCanvas {
+    // An opaque rectangle also covers the text drawn below it in the cells
+    // that it covers whole.
+    fn fill_rect(&mut self, color: Color, mut rect: Rectangle) {
+        let transform = self.transform();
+        rect.width = Self::at_least_a_pixel(rect.width, transform.a);
+        rect.height = Self::at_least_a_pixel(rect.height, transform.d);
+        self.backend().fill_rect(color, rect);
+        if color.a < 255 || transform.b != 0.0 || transform.c != 0.0 {
+            return;
+        }
+        let (left, top) = transform.apply((rect.x, rect.y));
+        let (right, bottom) =
+            transform.apply((rect.x + rect.width, rect.y + rect.height));
+        let cell =
+            |value: f32, max: usize| value.clamp(0.0, max as f32) as usize;
+        let x0 = cell(left.round(), self.columns);
+        let x1 = cell(right.round(), self.columns);
+        let y0 = cell((top / 2.0).ceil(), self.rows);
+        let y1 = cell((bottom / 2.0).floor(), self.rows);
+        for y in y0..y1 {
+            self.text[y * self.columns + x0..y * self.columns + x1].fill(None);
+        }
+    }
+
+    fn fill_rounded_rect(&mut self, color: Color, rect: &RoundedRect) {
+        self.backend().fill_rounded_rect(color, rect);
+    }
+
+    fn fill_border(
+        &mut self,
+        colors: &Sides<Color>,
+        rect: &RoundedRect,
+        widths: EdgeSizes,
+    ) {
+        let Transform { a, d, .. } = self.transform();
+        let widths = EdgeSizes {
+            left: Self::at_least_a_pixel(widths.left, a),
+            right: Self::at_least_a_pixel(widths.right, a),
+            top: Self::at_least_a_pixel(widths.top, d),
+            bottom: Self::at_least_a_pixel(widths.bottom, d),
+        };
+        self.backend().fill_border(colors, rect, widths);
+    }
+
+    fn draw_shadow(
+        &mut self,
+        color: Color,
+        rect: &RoundedRect,
+        shadow: &RoundedRect,
+        blur: f32,
+    ) {
+        self.backend().draw_shadow(color, rect, shadow, blur);
+    }
+
+    // Each character takes up a cell, from the cell where the text starts,
+    // in the row of the middle of the glyphs, so that text of any size
+    // stays readable. Text is not clipped, and transforms that don't keep
+    // it upright leave it out.
+    fn draw_text(&mut self, run: &TextRun) {
+        let Some(run) = run.transformed(self.transform()) else {
+            return;
+        };
+        let row = (run.rect.y + run.rect.height / 2.0) / 2.0;
+        if row < 0.0 || row >= self.rows as f32 || run.color.a == 0 {
+            return;
+        }
+        let row = row as usize;
+        let start = run.rect.x.round();
+        for (i, c) in run.text.chars().enumerate() {
+            let column = start + i as f32;
+            if c.is_whitespace() || c.is_control() || column < 0.0 {
+                continue;
+            }
+            let column = column as usize;
+            if column < self.columns {
+                self.text[row * self.columns + column] = Some((c, run.color));
+            }
+        }
+    }
+
+    fn fill_path(&mut self, color: Color, path: &Path, fill_rule: FillRule) {
+        self.backend().fill_path(color, path, fill_rule);
+    }
+
+    fn stroke_path(&mut self, color: Color, path: &Path, width: f32) {
+        self.backend().stroke_path(color, path, width);
+    }
+
+    fn draw_image(
+        &mut self,
+        bitmap: &Bitmap,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        self.backend().draw_image(bitmap, rect, tile, repeat);
+    }
+
+    fn draw_gradient(
+        &mut self,
+        gradient: &ImageSource,
+        rect: Rectangle,
+        tile: Rectangle,
+        repeat: (bool, bool),
+    ) {
+        self.backend().draw_gradient(gradient, rect, tile, repeat);
+    }
+
+    fn push_clip(&mut self, clip: &RoundedRect) {
+        self.transforms.push(self.transform());
+        self.backend().push_clip(clip);
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.transforms.push(self.transform().multiply(transform));
+        self.backend().push_transform(transform);
+    }
+
+    fn push_layer(&mut self, opacity: f32) {
+        self.transforms.push(self.transform());
+        self.backend().push_layer(opacity);
+    }
+
+    fn pop(&mut self) {
+        self.transforms.pop();
+        self.backend().pop();
+    }
+}
+
+// Render `bounds` of a laid out tree as lines of text with ANSI colors,
+// with a cell for every `CELL_WIDTH` CSS pixels across.
+pub fn render_to_terminal(
+    layout_root: &LayoutBox,
+    bounds: Rectangle,
+    images: &Images,
+) -> String {
+    let scale = 1.0 / CELL_WIDTH;
+    let (columns, height) = device_size(bounds, scale);
+    let mut terminal = TerminalCanvas::new(columns, height.div_ceil(2));
+    paint_with_backend(layout_root, bounds, images, scale, &mut terminal);
+    terminal.to_ansi()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        css_parser::CSSParser,
+        html_parser::HTMLParser,
+        layout::{Dimensions, layout_tree},
+        style::{MediaContext, apply},
+    };
+
+    #[test]
+    fn test_render_to_terminal() {
+        let root = HTMLParser::parse(
+            "<div><p class=\"box\">hi</p><p class=\"line\"></p></div>"
+                .to_string(),
+        );
+        let stylesheets = [CSSParser::parse(
+            "div { font-size: 16px; } p { margin: 0; width: 48px; } \
+             .box { background-color: #ff0000; color: #0000ff; } \
+             .line { height: 9.6px; border-top: 1px solid #00ff00; }"
+                .to_string(),
+        )];
+        let styled = apply(&root, &stylesheets, &MediaContext::default());
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 96.0;
+        let layout_root = layout_tree(&styled, viewport);
+        let bounds = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 96.0,
+            height: 38.4,
+        };
+
+        let output = render_to_terminal(&layout_root, bounds, &Images::new());
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        // Text is drawn over the color of its cells, and the rest of the
+        // cells show two pixels each.
+        let red = "\x1b[48;2;255;0;0m";
+        assert_eq!(
+            lines[0],
+            format!(
+                "\x1b[38;2;0;0;255m{}hi\x1b[38;2;255;0;0m{}\u{2580}\u{2580}\u{2580}\
+                 \x1b[38;2;255;255;255m\x1b[48;2;255;255;255m{}\x1b[0m",
+                red,
+                red,
+                "\u{2580}".repeat(5)
+            )
+        );
+        // A border thinner than a pixel is drawn a pixel thick.
+        assert!(
+            lines[1].starts_with(
+                "\x1b[38;2;0;255;0m\x1b[48;2;255;255;255m\u{2580}"
+            )
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::computed::{ComputedStyle, WhiteSpace};
+
+// The metrics of the fonts that text is laid out in.
+// Embedders with a real font stack implement this, so that layout measures
+// text the same way it is later drawn.
+pub trait FontMetrics {
+    // Get the advance width of a character in the font of the style.
+    fn advance(&self, c: char, style: &ComputedStyle) -> f32;
+
+    // Get the distance from the baseline to the top of the glyphs.
+    fn ascent(&self, style: &ComputedStyle) -> f32 {
+        style.font_size * 0.8
+    }
+
+    // Get the distance from the baseline to the bottom of the glyphs.
+    fn descent(&self, style: &ComputedStyle) -> f32 {
+        style.font_size * 0.2
+    }
+
+    // Get the height of lowercase letters above the baseline, which
+    // `vertical-align: middle` aligns on.
+    fn x_height(&self, style: &ComputedStyle) -> f32 {
+        style.font_size * 0.5
+    }
+
+    // Get the width of a piece of text.
+    fn measure(&self, text: &str, style: &ComputedStyle) -> f32 {
+        text.chars().map(|c| self.advance(c, style)).sum()
+    }
+
+    // Get where the lines that decorate text are drawn. Fonts give the
+    // position and thickness of underlines and strikeouts, and otherwise
+    // they are placed like in common fonts, at least a pixel thick.
+    fn decoration_metrics(&self, style: &ComputedStyle) -> DecorationMetrics {
+        let thickness = (style.font_size / 16.0).max(1.0);
+        DecorationMetrics {
+            underline_offset: style.font_size * 0.1,
+            underline_thickness: thickness,
+            overline_offset: -self.ascent(style),
+            line_through_offset: -self.x_height(style) / 2.0 - thickness / 2.0,
+            line_through_thickness: thickness,
+        }
+    }
+}
+
+// The placement of the lines that decorate text. Offsets are from the
+// baseline to the top edge of each line, and grow downwards. Overlines are
+// as thick as underlines.
+// SEE: https://drafts.csswg.org/css-text-decor/#text-underline-position-property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecorationMetrics {
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    pub overline_offset: f32,
+    pub line_through_offset: f32,
+    pub line_through_thickness: f32,
+}
+
+// The fallback metrics, where every character is as wide as in a typical
+// monospace font.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MonospaceMetrics;
+
+impl FontMetrics for MonospaceMetrics {
+    fn advance(&self, c: char, style: &ComputedStyle) -> f32 {
+        match c {
+            // A tab advances to the next tab stop, which is eight spaces
+            // apart. Measuring it as eight spaces is a good approximation.
+            '\t' => style.font_size * 0.6 * 8.0,
+            _ => style.font_size * 0.6,
+        }
+    }
+}
+
+// A piece of text between break opportunities.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextSegment<'t> {
+    Word(&'t str),
+    // White space that lines may break after. Collapsible white space is a
+    // single space.
+    Space(&'t str),
+    // A forced line break.
+    Newline,
+}
+
+// Split text into segments, collapsing white space as `white_space`
+// requires. The segments are produced as they are consumed, so that
+// laying out text doesn't allocate them.
+// Collapsing across text nodes, such as dropping a space after another
+// one, is left to the line builder.
+// SEE: https://drafts.csswg.org/css-text/#white-space-phase-1
+pub fn segments(text: &str, white_space: WhiteSpace) -> Segments<'_> {
+    Segments {
+        rest: text,
+        white_space,
+        newlines: 0,
+        preserved: None,
+    }
+}
+
+// An iterator over the segments of a text.
+#[derive(Debug, Clone)]
+pub struct Segments<'t> {
+    rest: &'t str,
+    white_space: WhiteSpace,
+    // The number of newlines left of a run of white space whose spaces
+    // collapse around them.
+    newlines: usize,
+    // The rest of a run of preserved white space, which is split into
+    // lines.
+    preserved: Option<&'t str>,
+}
+
+impl<'t> Iterator for Segments<'t> {
+    type Item = TextSegment<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.newlines > 0 {
+                self.newlines -= 1;
+                return Some(TextSegment::Newline);
+            }
+            if let Some(run) = self.preserved.take() {
+                if let Some(tail) = run.strip_prefix('\n') {
+                    self.preserved = Some(tail).filter(|tail| !tail.is_empty());
+                    return Some(TextSegment::Newline);
+                }
+                let end = run.find('\n').unwrap_or(run.len());
+                let (line, tail) = run.split_at(end);
+                self.preserved = Some(tail).filter(|tail| !tail.is_empty());
+                let line = line.trim_matches('\r');
+                if !line.is_empty() {
+                    return Some(TextSegment::Space(line));
+                }
+                continue;
+            }
+
+            let c = self.rest.chars().next()?;
+            let end = if is_white_space(c) {
+                self.rest.find(|c| !is_white_space(c))
+            } else {
+                self.rest.find(is_white_space)
+            }
+            .unwrap_or(self.rest.len());
+            let (run, tail) = self.rest.split_at(end);
+            self.rest = tail;
+
+            if !is_white_space(c) {
+                return Some(TextSegment::Word(run));
+            } else if !self.white_space.preserves_newlines() {
+                return Some(TextSegment::Space(" "));
+            } else if self.white_space.collapses_spaces() {
+                // Spaces around a preserved newline are removed.
+                match run.matches('\n').count() {
+                    0 => return Some(TextSegment::Space(" ")),
+                    n => self.newlines = n,
+                }
+            } else {
+                self.preserved = Some(run);
+            }
+        }
+    }
+}
+
+// Check if a character is document white space. Unlike
+// `char::is_whitespace`, this excludes no-break spaces.
+#[inline]
+pub fn is_white_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments_collapse_white_space() {
+        use TextSegment::*;
+
+        let text = "  a \t b\n\nc ";
+        assert_eq!(
+            segments(text, WhiteSpace::Normal).collect::<Vec<_>>(),
+            [
+                Space(" "),
+                Word("a"),
+                Space(" "),
+                Word("b"),
+                Space(" "),
+                Word("c"),
+                Space(" ")
+            ]
+        );
+        assert_eq!(
+            segments(text, WhiteSpace::PreLine).collect::<Vec<_>>(),
+            [
+                Space(" "),
+                Word("a"),
+                Space(" "),
+                Word("b"),
+                Newline,
+                Newline,
+                Word("c"),
+                Space(" ")
+            ]
+        );
+        assert_eq!(
+            segments(text, WhiteSpace::Pre).collect::<Vec<_>>(),
+            [
+                Space("  "),
+                Word("a"),
+                Space(" \t "),
+                Word("b"),
+                Newline,
+                Newline,
+                Word("c"),
+                Space(" ")
+            ]
+        );
+        // No-break spaces are part of words.
+        assert_eq!(
+            segments("a\u{a0}b", WhiteSpace::Normal).collect::<Vec<_>>(),
+            [Word("a\u{a0}b")]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+// Reference resolution as defined by RFC 3986 section 5, and absolute URLs
+// normalized so that they can be compared and fetched.
+// SEE: https://datatracker.ietf.org/doc/html/rfc3986#section-5
+// SEE: https://url.spec.whatwg.org/
+
+use std::fmt;
+
+// The components of a URI reference.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Components<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+// Check if a URI reference is absolute, i.e. it has a scheme.
+pub fn is_absolute(reference: &str) -> bool {
+    split(reference.trim()).scheme.is_some()
+}
+
+// Resolve a URI reference against a base URI. The result is normalized
+// like `Url::parse` does if it is absolute.
+pub fn resolve(base: &str, reference: &str) -> String {
+    let resolved = resolve_reference(base, reference);
+    Url::parse(&resolved).map_or(resolved, |url| url.to_string())
+}
+
+// An absolute URL, such as `https://example.com/a/b?c#d`. Its scheme and
+// host are lowercased, the default port of its scheme is removed, dot
+// segments are removed from its path, and characters that can't appear in
+// URLs are percent-encoded, so that URLs of the same resource are equal.
+// URLs without an authority, such as `data:` URLs, keep their path as is.
+// SEE: https://url.spec.whatwg.org/#concept-url
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Url {
+    scheme: String,
+    // The user information before the host, such as `user:password`.
+    userinfo: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+impl Url {
+    // Parse an absolute URL. Returns `None` for relative references and
+    // URLs with an invalid port, or without a host where their scheme
+    // needs one.
+    pub fn parse(input: &str) -> Option<Self> {
+        let components = split(input.trim());
+        let scheme = components.scheme?.to_ascii_lowercase();
+        let (userinfo, host, port) = match components.authority {
+            Some(authority) => {
+                let (userinfo, host_port) = match authority.rsplit_once('@') {
+                    Some((userinfo, host_port)) => {
+                        (Some(percent_encode(userinfo)), host_port)
+                    }
+                    None => (None, authority),
+                };
+                // The colon of an IPv6 address is not that of the port.
+                let (host, port) = match host_port.rsplit_once(':') {
+                    Some((host, port)) if !port.contains(']') => {
+                        let port = match port {
+                            "" => None,
+                            port => Some(port.parse::<u16>().ok()?),
+                        };
+                        (host, port)
+                    }
+                    _ => (host_port, None),
+                };
+                let port =
+                    port.filter(|&port| default_port(&scheme) != Some(port));
+                (userinfo, Some(host.to_ascii_lowercase()), port)
+            }
+            None => (None, None, None),
+        };
+        if default_port(&scheme).is_some()
+            && host.as_deref().is_none_or(str::is_empty)
+        {
+            return None;
+        }
+        let path = match (host.is_some(), components.path) {
+            (true, "") => "/".to_string(),
+            (true, path) => percent_encode(&remove_dot_segments(path)),
+            (false, path) if path.starts_with('/') => {
+                percent_encode(&remove_dot_segments(path))
+            }
+            (false, path) => percent_encode(path),
+        };
+        Some(Self {
+            scheme,
+            userinfo,
+            host,
+            port,
+            path,
+            query: components.query.map(percent_encode),
+            fragment: components.fragment.map(percent_encode),
+        })
+    }
+
+    // Resolve a URL reference against this URL, such as `../style.css`.
+    pub fn join(&self, reference: &str) -> Option<Self> {
+        Self::parse(&resolve_reference(&self.to_string(), reference))
+    }
+
+    #[inline]
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    #[inline]
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    // Get the port if it is not the default one of the scheme.
+    #[inline]
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    // Get the port, or the default one of the scheme.
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or_else(|| default_port(&self.scheme))
+    }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    #[inline]
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    // Get the URL without its fragment, which is the same resource.
+    pub fn without_fragment(&self) -> Self {
+        Self {
+            fragment: None,
+            ..self.clone()
+        }
+    }
+
+    // Check if the URL is fetched over HTTP or HTTPS.
+    pub fn is_http(&self) -> bool {
+        matches!(self.scheme.as_str(), "http" | "https")
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.scheme)?;
+        if let Some(ref host) = self.host {
+            f.write_str("//")?;
+            if let Some(ref userinfo) = self.userinfo {
+                write!(f, "{}@", userinfo)?;
+            }
+            f.write_str(host)?;
+            if let Some(port) = self.port {
+                write!(f, ":{}", port)?;
+            }
+        }
+        f.write_str(&self.path)?;
+        if let Some(ref query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(ref fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+// Get the port that a scheme uses unless a URL says otherwise. Only these
+// schemes need a host.
+// SEE: https://url.spec.whatwg.org/#default-port
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+// Percent-encode the characters that can't appear in a URL: controls,
+// spaces, characters outside of ASCII, and `"`, `<`, `>`, `` ` ``, `{`,
+// and `}`. Characters that are already percent-encoded are kept as they
+// are.
+// SEE: https://url.spec.whatwg.org/#percent-encoded-bytes
+pub fn percent_encode(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for &byte in input.as_bytes() {
+        match byte {
+            0x21..=0x7e if !b"\"<>`{}".contains(&byte) => {
+                output.push(byte as char)
+            }
+            _ => output.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    output
+}
+
+// Decode the percent-encoded bytes of a string, such as `%20` for a space.
+// A `%` that isn't followed by two hexadecimal digits is kept as it is.
+// SEE: https://url.spec.whatwg.org/#percent-decode
+pub fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|hex| hex.bytes().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                output.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+// Resolve a URI reference against a base URI, without normalizing it.
+fn resolve_reference(base: &str, reference: &str) -> String {
+    let base = split(base.trim());
+    let reference = split(reference.trim());
+
+    let target = if reference.scheme.is_some() {
+        Target::new(reference, remove_dot_segments(reference.path))
+    } else if reference.authority.is_some() {
+        Target {
+            scheme: base.scheme,
+            ..Target::new(reference, remove_dot_segments(reference.path))
+        }
+    } else if reference.path.is_empty() {
+        Target {
+            scheme: base.scheme,
+            authority: base.authority,
+            path: base.path.to_string(),
+            query: reference.query.or(base.query),
+            fragment: reference.fragment,
+        }
+    } else {
+        let path = if reference.path.starts_with('/') {
+            remove_dot_segments(reference.path)
+        } else {
+            remove_dot_segments(&merge(&base, reference.path))
+        };
+        Target {
+            scheme: base.scheme,
+            authority: base.authority,
+            ..Target::new(reference, path)
+        }
+    };
+
+    target.to_string()
+}
+
+// A resolved URI, ready to be recomposed.
+struct Target<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: String,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> Target<'a> {
+    // Create a target from the components of a reference and a new path.
+    fn new(reference: Components<'a>, path: String) -> Self {
+        Self {
+            scheme: reference.scheme,
+            authority: reference.authority,
+            path,
+            query: reference.query,
+            fragment: reference.fragment,
+        }
+    }
+}
+
+impl std::fmt::Display for Target<'_> {
+    // Recompose the components (RFC 3986 section 5.3).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = self.scheme {
+            write!(f, "{}:", scheme)?;
+        }
+        if let Some(authority) = self.authority {
+            write!(f, "//{}", authority)?;
+        }
+        f.write_str(&self.path)?;
+        if let Some(query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+// Split a URI reference into its components (RFC 3986 appendix B).
+fn split(s: &str) -> Components<'_> {
+    let mut components = Components::default();
+    let mut rest = s;
+
+    if let Some(end) = rest.find([':', '/', '?', '#']) {
+        let scheme = &rest[..end];
+        if rest[end..].starts_with(':') && is_scheme(scheme) {
+            components.scheme = Some(scheme);
+            rest = &rest[end + 1..];
+        }
+    }
+
+    if let Some(after) = rest.strip_prefix("//") {
+        let end = after.find(['/', '?', '#']).unwrap_or(after.len());
+        components.authority = Some(&after[..end]);
+        rest = &after[end..];
+    }
+
+    if let Some(start) = rest.find('#') {
+        components.fragment = Some(&rest[start + 1..]);
+        rest = &rest[..start];
+    }
+    if let Some(start) = rest.find('?') {
+        components.query = Some(&rest[start + 1..]);
+        rest = &rest[..start];
+    }
+    components.path = rest;
+
+    components
+}
+
+// Check if a string is a valid scheme.
+fn is_scheme(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+// Merge a relative path with the path of the base (RFC 3986 section 5.2.3).
+fn merge(base: &Components, path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        return format!("/{}", path);
+    }
+    match base.path.rfind('/') {
+        Some(i) => format!("{}{}", &base.path[..=i], path),
+        None => path.to_string(),
+    }
+}
+
+// Remove `.` and `..` segments from a path (RFC 3986 section 5.2.4).
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output: Vec<&str> = Vec::new();
+
+    while !input.is_empty() {
+        if let Some(rest) = input
+            .strip_prefix("../")
+            .or_else(|| input.strip_prefix("./"))
+        {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            output.pop();
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            // Move the first segment, including its leading slash, to the
+            // output.
+            let start = usize::from(input.starts_with('/'));
+            let end =
+                input[start..].find('/').map_or(input.len(), |i| i + start);
+            output.push(&input[..end]);
+            input = &input[end..];
+        }
+    }
+
+    output.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let base = "http://a/b/c/d;p?q";
+        // The examples of RFC 3986 section 5.4.
+        assert_eq!(resolve(base, "g"), "http://a/b/c/g");
+        assert_eq!(resolve(base, "./g"), "http://a/b/c/g");
+        assert_eq!(resolve(base, "/g"), "http://a/g");
+        assert_eq!(resolve(base, "//g"), "http://g/");
+        assert_eq!(resolve(base, "?y"), "http://a/b/c/d;p?y");
+        assert_eq!(resolve(base, "#s"), "http://a/b/c/d;p?q#s");
+        assert_eq!(resolve(base, ""), "http://a/b/c/d;p?q");
+        assert_eq!(resolve(base, "../g"), "http://a/b/g");
+        assert_eq!(resolve(base, "../../../g"), "http://a/g");
+        assert_eq!(resolve(base, "g/./h/../i"), "http://a/b/c/g/i");
+        // Relative references stay relative against relative bases.
+        assert_eq!(resolve("css/a.css", "b.css"), "css/b.css");
+        assert!(is_absolute("data:,a"));
+        assert!(!is_absolute("/a:b"));
+    }
+
+    #[test]
+    fn test_url() {
+        let url =
+            Url::parse(" HTTPS://User@Example.COM:443/a/../b c/?q=\"1\"#top")
+                .unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert_eq!(url.host(), Some("example.com"));
+        assert_eq!(url.port(), None);
+        assert_eq!(url.port_or_default(), Some(443));
+        assert_eq!(url.path(), "/b%20c/");
+        assert_eq!(url.query(), Some("q=%221%22"));
+        assert_eq!(url.fragment(), Some("top"));
+        assert!(url.is_http());
+        assert_eq!(
+            url.to_string(),
+            "https://User@example.com/b%20c/?q=%221%22#top"
+        );
+        assert_eq!(
+            url.without_fragment().to_string(),
+            "https://User@example.com/b%20c/?q=%221%22"
+        );
+
+        let url = Url::parse("http://[::1]:8080").unwrap();
+        assert_eq!(url.host(), Some("[::1]"));
+        assert_eq!(url.port(), Some(8080));
+        assert_eq!(url.to_string(), "http://[::1]:8080/");
+
+        // Joining resolves against the URL, and keeps escapes as they are.
+        let page = Url::parse("https://example.com/docs/page.html").unwrap();
+        assert_eq!(
+            page.join("../style.css").unwrap().to_string(),
+            "https://example.com/style.css"
+        );
+        assert_eq!(
+            page.join("images/a%20b.png").unwrap().to_string(),
+            "https://example.com/docs/images/a%20b.png"
+        );
+        assert_eq!(
+            page.join("//cdn.example.com/x").unwrap().to_string(),
+            "https://cdn.example.com/x"
+        );
+
+        // Paths without an authority are opaque.
+        let url = Url::parse("data:text/css,p { color: red }").unwrap();
+        assert_eq!(url.host(), None);
+        assert_eq!(url.path(), "text/css,p%20%7B%20color:%20red%20%7D");
+        assert_eq!(
+            Url::parse("file:///tmp/../a.html").unwrap().to_string(),
+            "file:///a.html"
+        );
+
+        assert_eq!(Url::parse("style.css"), None);
+        assert_eq!(Url::parse("http://example.com:99999/"), None);
+        assert_eq!(Url::parse("http:///a"), None);
+    }
+
+    #[test]
+    fn test_percent_encoding() {
+        assert_eq!(percent_encode("a b\u{e9}%20"), "a%20b%C3%A9%20");
+        assert_eq!(percent_decode("a%20b%C3%A9%2"), b"a b\xc3\xa9%2");
+        assert_eq!(percent_decode("%zz%"), b"%zz%");
+    }
+}
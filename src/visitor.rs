@@ -0,0 +1,209 @@
+use crate::dom::{ElementData, Node, NodeType};
+
+// What the walker should do with a node after visiting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitAction {
+    // Keep the node and visit its children.
+    Continue,
+    // Keep the node but do not visit its children.
+    SkipChildren,
+    // Remove the node together with its children.
+    Remove,
+    // Remove the node but keep its (visited) children in its place.
+    Unwrap,
+}
+
+// Typed callbacks for processing a DOM tree.
+// Every callback defaults to `VisitAction::Continue`, so implementors only
+// override what they need.
+pub trait NodeVisitor {
+    // Called before the children of an element are visited.
+    fn visit_element(&mut self, _elem: &mut ElementData) -> VisitAction {
+        VisitAction::Continue
+    }
+
+    // Called after the children of an element have been visited.
+    fn leave_element(&mut self, _elem: &mut ElementData) {}
+
+    // Called for each text node.
+    fn visit_text(&mut self, _text: &mut String) -> VisitAction {
+        VisitAction::Continue
+    }
+
+    // Called for each comment node.
+    fn visit_comment(&mut self, _comment: &mut String) -> VisitAction {
+        VisitAction::Continue
+    }
+}
+
+// Walk the tree in document order, applying the visitor to every node.
+// The root itself cannot be removed, so `Remove` and `Unwrap` on the root
+// are treated like `SkipChildren` and `Continue` respectively.
+pub fn walk(root: &mut Node, visitor: &mut impl NodeVisitor) {
+    visit(root, visitor);
+}
+
+// Visit a node and its descendants, returning the action for the node.
+fn visit(node: &mut Node, visitor: &mut impl NodeVisitor) -> VisitAction {
+    let action = match node.node_type {
+        NodeType::Element(ref mut elem) => visitor.visit_element(elem),
+        NodeType::Text(ref mut text) => visitor.visit_text(text),
+        NodeType::Comment(ref mut comment) => visitor.visit_comment(comment),
+    };
+
+    if matches!(action, VisitAction::Continue | VisitAction::Unwrap) {
+        walk_children(node, visitor);
+        if let NodeType::Element(ref mut elem) = node.node_type {
+            visitor.leave_element(elem);
+        }
+    }
+
+    action
+}
+
+// Visit the children of a node, applying the requested removals.
+// The children are detached while being visited, so the visitor can never
+// observe a partially updated child list.
+fn walk_children(node: &mut Node, visitor: &mut impl NodeVisitor) {
+    let mut children = Vec::with_capacity(node.children.len());
+    for mut child in std::mem::take(&mut node.children) {
+        match visit(&mut child, visitor) {
+            VisitAction::Continue | VisitAction::SkipChildren => {
+                children.push(child)
+            }
+            VisitAction::Remove => {}
+            VisitAction::Unwrap => children.append(&mut child.children),
+        }
+    }
+    node.children = children;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::HTMLParser;
+
+    // A visitor that applies an action to the elements of each tag name,
+    // and records the nodes that it visits and leaves.
+    struct Recorder {
+        actions: Vec<(&'static str, VisitAction)>,
+        log: Vec<String>,
+    }
+
+    impl NodeVisitor for Recorder {
+        fn visit_element(&mut self, elem: &mut ElementData) -> VisitAction {
+            self.log.push(format!("<{}>", elem.tag_name));
+            self.actions
+                .iter()
+                .find(|(tag_name, _)| *tag_name == elem.tag_name)
+                .map_or(VisitAction::Continue, |&(_, action)| action)
+        }
+
+        fn leave_element(&mut self, elem: &mut ElementData) {
+            self.log.push(format!("</{}>", elem.tag_name));
+        }
+
+        fn visit_text(&mut self, text: &mut String) -> VisitAction {
+            self.log.push(text.clone());
+            VisitAction::Continue
+        }
+
+        fn visit_comment(&mut self, _comment: &mut String) -> VisitAction {
+            VisitAction::Remove
+        }
+    }
+
+    // Walk a tree with the actions, returning what is left of it and the
+    // log of the walk.
+    fn walk_with(
+        html: &str,
+        actions: Vec<(&'static str, VisitAction)>,
+    ) -> (String, Vec<String>) {
+        fn serialize(node: &Node) -> String {
+            match node.node_type {
+                NodeType::Element(ref elem) => {
+                    let children: String =
+                        node.children.iter().map(serialize).collect();
+                    format!("<{0}>{1}</{0}>", elem.tag_name, children)
+                }
+                NodeType::Text(ref text) => text.clone(),
+                NodeType::Comment(ref comment) => format!("<!--{}-->", comment),
+            }
+        }
+
+        let mut root = HTMLParser::parse(html.to_string());
+        let mut recorder = Recorder {
+            actions,
+            log: Vec::new(),
+        };
+        walk(&mut root, &mut recorder);
+        (serialize(&root), recorder.log)
+    }
+
+    const HTML: &str = "<div><p>a<b>b</b></p><!--c--><p>d</p></div>";
+
+    #[test]
+    fn test_continue() {
+        let (html, log) = walk_with(HTML, Vec::new());
+        assert_eq!(html, "<div><p>a<b>b</b></p><p>d</p></div>");
+        assert_eq!(
+            log,
+            [
+                "<div>", "<p>", "a", "<b>", "b", "</b>", "</p>", "<p>", "d",
+                "</p>", "</div>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        // The node is removed with its children, which aren't visited.
+        let (html, log) = walk_with(HTML, vec![("b", VisitAction::Remove)]);
+        assert_eq!(html, "<div><p>a</p><p>d</p></div>");
+        assert!(!log.contains(&"b".to_string()));
+        assert!(!log.contains(&"</b>".to_string()));
+        let (html, _) = walk_with(HTML, vec![("p", VisitAction::Remove)]);
+        assert_eq!(html, "<div></div>");
+    }
+
+    #[test]
+    fn test_unwrap() {
+        // The children are visited and take the place of the node.
+        let (html, log) = walk_with(HTML, vec![("p", VisitAction::Unwrap)]);
+        assert_eq!(html, "<div>a<b>b</b>d</div>");
+        assert_eq!(
+            log,
+            [
+                "<div>", "<p>", "a", "<b>", "b", "</b>", "</p>", "<p>", "d",
+                "</p>", "</div>",
+            ]
+        );
+        // The actions on the children apply before they are moved.
+        let (html, _) = walk_with(
+            HTML,
+            vec![("p", VisitAction::Unwrap), ("b", VisitAction::Unwrap)],
+        );
+        assert_eq!(html, "<div>abd</div>");
+    }
+
+    #[test]
+    fn test_skip_children() {
+        // The node and its children are kept, but the children aren't
+        // visited, and the node isn't left.
+        let (html, log) =
+            walk_with(HTML, vec![("p", VisitAction::SkipChildren)]);
+        assert_eq!(html, "<div><p>a<b>b</b></p><p>d</p></div>");
+        assert_eq!(log, ["<div>", "<p>", "<p>", "</div>"]);
+    }
+
+    #[test]
+    fn test_root() {
+        // The root can't be removed or unwrapped.
+        let (html, log) = walk_with(HTML, vec![("div", VisitAction::Remove)]);
+        assert_eq!(html, "<div><p>a<b>b</b></p><!--c--><p>d</p></div>");
+        assert_eq!(log, ["<div>"]);
+        let (html, log) = walk_with(HTML, vec![("div", VisitAction::Unwrap)]);
+        assert_eq!(html, "<div><p>a<b>b</b></p><p>d</p></div>");
+        assert_eq!(log.len(), 11);
+    }
+}
@@ -0,0 +1,486 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+
+use crate::dom::{Node, NodeId, NodeType};
+
+// A minimal subset of XPath 1.0 for querying the DOM:
+// - Absolute (`/html/body`) and relative (`body/p`) location paths
+// - The child (`/`) and descendant-or-self (`//`) axes
+// - Name tests (`div`), the wildcard (`*`), and `text()`
+// - Predicates: position (`[2]`), attribute existence (`[@id]`), and attribute
+//   equality (`[@id='main']`)
+// TODO: Support parent and sibling axes, functions, and boolean operators.
+
+#[derive(Debug)]
+pub struct XPath {
+    absolute: bool,
+    steps: Vec<Step>,
+}
+
+// An expression that couldn't be parsed, with the byte offset in it where
+// parsing stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XPathError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for XPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+impl Error for XPathError {}
+
+#[derive(Debug)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Axis {
+    Child,
+    DescendantOrSelf,
+}
+
+#[derive(Debug)]
+enum NodeTest {
+    Name(String),
+    AnyElement,
+    Text,
+}
+
+#[derive(Debug)]
+enum Predicate {
+    // 1-based position among the nodes selected from the same parent.
+    Position(usize),
+    HasAttribute(String),
+    AttributeEquals(String, String),
+}
+
+impl XPath {
+    // Parse an XPath expression, failing if it is malformed or uses syntax
+    // outside of the supported subset.
+    pub fn parse(source: &str) -> Result<Self, XPathError> {
+        XPathParser {
+            pos: 0,
+            input: source.to_string(),
+        }
+        .parse_path()
+    }
+
+    // Evaluate the expression against a tree, returning the matched nodes.
+    // Absolute paths start above the root, so `/html` matches an `html` root,
+    // while relative paths start at the root.
+    pub fn evaluate<'a>(&self, root: &'a Node) -> Vec<&'a Node> {
+        // Each context is represented by its child list, which is what the
+        // next step selects from.
+        let mut contexts: Vec<&'a [Node]> = if self.absolute {
+            vec![std::slice::from_ref(root)]
+        } else {
+            vec![&root.children]
+        };
+        let mut matched = Vec::new();
+
+        for step in &self.steps {
+            matched = Vec::new();
+            for siblings in contexts {
+                match step.axis {
+                    Axis::Child => matched.extend(step.select(siblings)),
+                    Axis::DescendantOrSelf => {
+                        let mut lists = Vec::new();
+                        collect_child_lists(siblings, &mut lists);
+                        for list in lists {
+                            matched.extend(step.select(list));
+                        }
+                    }
+                }
+            }
+            dedup_nodes(&mut matched);
+            contexts = matched.iter().map(|node| &node.children[..]).collect();
+        }
+
+        // The descendant axis selects from one child list after another,
+        // but the matched nodes are returned in document order.
+        if self
+            .steps
+            .iter()
+            .any(|step| step.axis == Axis::DescendantOrSelf)
+        {
+            let mut order = HashMap::new();
+            number_in_document_order(root, &mut order);
+            matched.sort_by_key(|node| order[&node.node_id]);
+        }
+        matched
+    }
+}
+
+impl Step {
+    // Select the nodes matching this step from a list of siblings.
+    fn select<'a>(&self, siblings: &'a [Node]) -> Vec<&'a Node> {
+        let mut nodes: Vec<&Node> = siblings
+            .iter()
+            .filter(|node| self.test.matches(node))
+            .collect();
+        for predicate in &self.predicates {
+            nodes = match predicate {
+                Predicate::Position(position) => position
+                    .checked_sub(1)
+                    .and_then(|i| nodes.get(i))
+                    .into_iter()
+                    .copied()
+                    .collect(),
+                Predicate::HasAttribute(name) => nodes
+                    .into_iter()
+                    .filter(|node| attribute(node, name).is_some())
+                    .collect(),
+                Predicate::AttributeEquals(name, value) => nodes
+                    .into_iter()
+                    .filter(|node| attribute(node, name) == Some(value))
+                    .collect(),
+            };
+        }
+        nodes
+    }
+}
+
+impl NodeTest {
+    // Check if a node passes this test.
+    fn matches(&self, node: &Node) -> bool {
+        match (self, &node.node_type) {
+            (NodeTest::Name(name), NodeType::Element(elem)) => {
+                elem.tag_name.eq_ignore_ascii_case(name)
+            }
+            (NodeTest::AnyElement, NodeType::Element(_)) => true,
+            (NodeTest::Text, NodeType::Text(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+// Get an attribute value of a node, if it is an element.
+fn attribute<'a>(node: &'a Node, name: &str) -> Option<&'a String> {
+    match node.node_type {
+        NodeType::Element(ref elem) => elem.attributes.get(name),
+        _ => None,
+    }
+}
+
+// Collect the given list and the child lists of all its descendants.
+fn collect_child_lists<'a>(siblings: &'a [Node], lists: &mut Vec<&'a [Node]>) {
+    lists.push(siblings);
+    for node in siblings {
+        collect_child_lists(&node.children, lists);
+    }
+}
+
+// Number the nodes of a tree in document order.
+fn number_in_document_order(node: &Node, order: &mut HashMap<NodeId, usize>) {
+    order.insert(node.node_id, order.len());
+    for child in &node.children {
+        number_in_document_order(child, order);
+    }
+}
+
+// Remove duplicated nodes, keeping the first occurrence.
+fn dedup_nodes(nodes: &mut Vec<&Node>) {
+    let mut seen = HashSet::new();
+    nodes.retain(|node| seen.insert(node.node_id));
+}
+
+#[derive(Debug)]
+struct XPathParser {
+    pos: usize,
+    input: String,
+}
+
+impl XPathParser {
+    // Parse a location path.
+    fn parse_path(&mut self) -> Result<XPath, XPathError> {
+        self.consume_whitespace();
+        let absolute = self.starts_with("/");
+        let mut steps = Vec::new();
+        let mut axis = if absolute {
+            self.parse_separator()?
+        } else {
+            Axis::Child
+        };
+        loop {
+            steps.push(self.parse_step(axis)?);
+            self.consume_whitespace();
+            if self.is_eof() {
+                break;
+            }
+            axis = self.parse_separator()?;
+        }
+        Ok(XPath { absolute, steps })
+    }
+
+    // Parse `/` or `//`.
+    fn parse_separator(&mut self) -> Result<Axis, XPathError> {
+        if self.starts_with("//") {
+            self.pos += 2;
+            Ok(Axis::DescendantOrSelf)
+        } else {
+            self.expect("/")?;
+            Ok(Axis::Child)
+        }
+    }
+
+    // Parse a single step with its predicates.
+    fn parse_step(&mut self, axis: Axis) -> Result<Step, XPathError> {
+        self.consume_whitespace();
+        let test = if self.starts_with("*") {
+            self.pos += 1;
+            NodeTest::AnyElement
+        } else {
+            let name = self.parse_name();
+            if name.is_empty() {
+                return Err(self.error("Expected a node test".to_string()));
+            }
+            if name == "text" && self.starts_with("()") {
+                self.pos += 2;
+                NodeTest::Text
+            } else {
+                NodeTest::Name(name)
+            }
+        };
+
+        let mut predicates = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if !self.starts_with("[") {
+                break;
+            }
+            predicates.push(self.parse_predicate()?);
+        }
+        Ok(Step {
+            axis,
+            test,
+            predicates,
+        })
+    }
+
+    // Parse a predicate in square brackets.
+    fn parse_predicate(&mut self) -> Result<Predicate, XPathError> {
+        self.expect("[")?;
+        self.consume_whitespace();
+        let predicate = if self.starts_with("@") {
+            self.pos += 1;
+            let name = self.parse_name();
+            if name.is_empty() {
+                return Err(
+                    self.error("Expected an attribute name".to_string())
+                );
+            }
+            self.consume_whitespace();
+            if self.starts_with("=") {
+                self.pos += 1;
+                self.consume_whitespace();
+                Predicate::AttributeEquals(name, self.parse_literal()?)
+            } else {
+                Predicate::HasAttribute(name)
+            }
+        } else {
+            let digits = self.consume_chars_while(|c| c.is_ascii_digit());
+            match digits.parse() {
+                Ok(position) => Predicate::Position(position),
+                Err(_) => {
+                    return Err(self.error("Unsupported predicate".to_string()));
+                }
+            }
+        };
+        self.consume_whitespace();
+        self.expect("]")?;
+        Ok(predicate)
+    }
+
+    // Parse a quoted string literal.
+    fn parse_literal(&mut self) -> Result<String, XPathError> {
+        if !self.starts_with("\"") && !self.starts_with("'") {
+            return Err(self.error("Expected a string literal".to_string()));
+        }
+        let open_quote = self.consume_char();
+        let value = self.consume_chars_while(|c| c != open_quote);
+        self.expect(&open_quote.to_string())?;
+        Ok(value)
+    }
+
+    // Parse an element or attribute name.
+    fn parse_name(&mut self) -> String {
+        self.consume_chars_while(|c| {
+            c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')
+        })
+    }
+
+    // Consume whitespace.
+    fn consume_whitespace(&mut self) {
+        self.consume_chars_while(char::is_whitespace);
+    }
+
+    // Consume characters while the condition is true.
+    fn consume_chars_while(
+        &mut self,
+        condition: impl Fn(char) -> bool,
+    ) -> String {
+        let mut result = String::new();
+        while !self.is_eof() && condition(self.next_char()) {
+            result.push(self.consume_char());
+        }
+        result
+    }
+
+    // Consume a character.
+    #[inline]
+    fn consume_char(&mut self) -> char {
+        let c = self.next_char();
+        self.pos += c.len_utf8();
+        c
+    }
+
+    // Read the next character from the input.
+    #[inline]
+    fn next_char(&self) -> char {
+        self.input[self.pos..].chars().next().unwrap()
+    }
+
+    // Check if the input starts with a given string.
+    #[inline]
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    // If the exact string is found, move the position forward.
+    // Otherwise, fail.
+    fn expect(&mut self, s: &str) -> Result<(), XPathError> {
+        if self.starts_with(s) {
+            self.pos += s.len();
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected {:?}", s)))
+        }
+    }
+
+    // Make an error at the current position.
+    fn error(&self, message: String) -> XPathError {
+        XPathError {
+            message,
+            position: self.pos,
+        }
+    }
+
+    // Check if the input is at the end.
+    #[inline]
+    fn is_eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_parser::HTMLParser;
+
+    // Evaluate an expression, and name each matched node by its `id`, its
+    // tag name, or its text.
+    fn evaluate(root: &Node, source: &str) -> Vec<String> {
+        XPath::parse(source)
+            .unwrap()
+            .evaluate(root)
+            .into_iter()
+            .map(|node| match node.node_type {
+                NodeType::Element(ref elem) => elem
+                    .attributes
+                    .get("id")
+                    .cloned()
+                    .unwrap_or_else(|| elem.tag_name.clone()),
+                NodeType::Text(ref text) => text.clone(),
+                NodeType::Comment(_) => "comment".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_axes() {
+        let root = HTMLParser::parse(
+            "<html><body><div id=\"a\"><p id=\"b\">one</p>\
+             <div id=\"c\"><p id=\"d\">two</p></div></div>\
+             <p id=\"e\"></p></body></html>"
+                .to_string(),
+        );
+        assert_eq!(evaluate(&root, "/html/body/p"), ["e"]);
+        assert_eq!(evaluate(&root, "body/div/p"), ["b"]);
+        assert_eq!(evaluate(&root, "/html/body/*"), ["a", "e"]);
+        // The descendants are in document order, each once.
+        assert_eq!(evaluate(&root, "//p"), ["b", "d", "e"]);
+        assert_eq!(evaluate(&root, "//div//p"), ["b", "d"]);
+        assert_eq!(evaluate(&root, "//div/p/text()"), ["one", "two"]);
+    }
+
+    #[test]
+    fn test_predicates() {
+        let root = HTMLParser::parse(
+            "<ul><li id=\"a\" class=\"x\"></li><li id=\"b\"></li>\
+             <li id=\"c\" class=\"y\"></li><li id=\"d\" class=\"x\"></li></ul>"
+                .to_string(),
+        );
+        assert_eq!(evaluate(&root, "li[2]"), ["b"]);
+        assert_eq!(evaluate(&root, "li[@class]"), ["a", "c", "d"]);
+        assert_eq!(evaluate(&root, "li[@class='x']"), ["a", "d"]);
+        assert_eq!(evaluate(&root, "li[@class=\"y\"]"), ["c"]);
+        // Predicates apply in turn, each to the nodes of the one before.
+        assert_eq!(evaluate(&root, "li[@class='x'][2]"), ["d"]);
+        assert_eq!(evaluate(&root, "li[2][@class]"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_no_matches() {
+        let root = HTMLParser::parse(
+            "<html><body><p>text</p></body></html>".to_string(),
+        );
+        for source in ["/body", "//span", "//p[2]", "//p[0]", "//p[@id]"] {
+            assert_eq!(
+                evaluate(&root, source),
+                Vec::<String>::new(),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| XPath::parse(source).unwrap_err();
+        let cases = [
+            ("", "Expected a node test", 0),
+            ("/", "Expected a node test", 1),
+            ("//p/", "Expected a node test", 4),
+            ("p q", "Expected \"/\"", 2),
+            ("p[", "Unsupported predicate", 2),
+            ("p[x]", "Unsupported predicate", 2),
+            ("p[1", "Expected \"]\"", 3),
+            ("p[@]", "Expected an attribute name", 3),
+            ("p[@id=]", "Expected a string literal", 6),
+            ("p[@id=", "Expected a string literal", 6),
+            ("p[@id='a]", "Expected \"'\"", 9),
+            ("p/@id", "Expected a node test", 2),
+        ];
+        for (source, message, position) in cases {
+            let expected = XPathError {
+                message: message.to_string(),
+                position,
+            };
+            assert_eq!(error(source), expected, "{}", source);
+        }
+        assert_eq!(
+            error("p[x]").to_string(),
+            "Unsupported predicate at byte 2"
+        );
+    }
+}